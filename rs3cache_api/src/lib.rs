@@ -0,0 +1,198 @@
+//! A stable interface to [`rs3cache`].
+//!
+//! `rs3cache` changes freely between releases as the game and its cache do.
+//! This crate only exposes what is needed to open a cache, dump its definitions and render its map,
+//! and follows semver: anything reachable from here only changes in a major release.
+//! New kinds of dumps and new options may be added in minor releases, which is why [`Dump`] and [`Render`]
+//! are `#[non_exhaustive]` and options are set through [`SessionBuilder`].
+//!
+//! Select the game with the `rs3`, `osrs` or `legacy` feature.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use rs3cache_api::{CacheSession, Dump};
+//!
+//! let session = CacheSession::builder("path/to/cache", "out").threads(4).build();
+//! session.dump(Dump::LocationConfigs)?;
+//! # Ok::<(), rs3cache_api::CacheError>(())
+//! ```
+#![forbid(unsafe_code)]
+#![warn(
+    unused_imports,
+    unused_qualifications,
+    unused_import_braces,
+    unused_extern_crates,
+    rustdoc::broken_intra_doc_links,
+    missing_docs,
+    rustdoc::missing_crate_level_docs,
+    unreachable_pub
+)]
+#![deny(keyword_idents, macro_use_extern_crate)]
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rs3cache::cli::{self, Config};
+pub use rs3cache_backend::{
+    buf::ReadError,
+    error::{CacheError, CacheResult},
+};
+
+/// What [`CacheSession::dump`] can dump. More may be added in minor releases.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dump {
+    /// Everything that the game's cache can be dumped as.
+    All,
+    /// Every kind of config, such as location and item configs.
+    Configs,
+    /// The configs of locations.
+    LocationConfigs,
+    /// The configs of npcs.
+    NpcConfigs,
+    /// The configs of items.
+    ItemConfigs,
+    /// The configs of map labels.
+    Maplabels,
+    /// The configs of varbits.
+    VarbitConfigs,
+    /// The structs.
+    Structs,
+    /// The enums.
+    Enums,
+    /// The locations of every mapsquare.
+    Locations,
+    /// Every frame of every sprite, as images.
+    Sprites,
+}
+
+impl Dump {
+    fn inner(self) -> cli::Dump {
+        match self {
+            Self::All => cli::Dump::All,
+            Self::Configs => cli::Dump::Configs,
+            Self::LocationConfigs => cli::Dump::LocationConfigs,
+            Self::NpcConfigs => cli::Dump::NpcConfig,
+            Self::ItemConfigs => cli::Dump::ItemConfigs,
+            Self::Maplabels => cli::Dump::Maplabels,
+            Self::VarbitConfigs => cli::Dump::VarbitConfigs,
+            Self::Structs => cli::Dump::Structs,
+            Self::Enums => cli::Dump::Enums,
+            Self::Locations => cli::Dump::Locations,
+            Self::Sprites => cli::Dump::Sprites,
+        }
+    }
+}
+
+/// What [`CacheSession::render`] can render. More may be added in minor releases.
+#[cfg(not(target_arch = "wasm32"))]
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Render {
+    /// The world map, as tiles of every zoom level.
+    Map,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Render {
+    fn inner(self) -> cli::Render {
+        match self {
+            Self::Map => cli::Render::Map,
+        }
+    }
+}
+
+/// Sets the options of a [`CacheSession`]. Options that are not set keep the defaults of the command line.
+#[derive(Debug)]
+pub struct SessionBuilder {
+    config: Config,
+}
+
+impl SessionBuilder {
+    /// Renders the map at `scale` pixels per tile at the highest zoom level.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.config.render_config.scale = scale;
+        self
+    }
+
+    /// Only renders the mapsquares `i, j` in `mapsquares`, such as `(50, 50)` for Lumbridge.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn mapsquares(mut self, mapsquares: impl IntoIterator<Item = (u8, u8)>) -> Self {
+        self.config.mapsquare = mapsquares.into_iter().collect();
+        self
+    }
+
+    /// Uses at most `threads` threads.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn threads(mut self, threads: u16) -> Self {
+        self.config.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Opens the session.
+    ///
+    /// # Panics
+    ///
+    /// If the threads set by [`threads`](Self::threads) cannot be started, as rayon panics for its own threads.
+    pub fn build(self) -> CacheSession {
+        let pool = self.config.threads.map(|threads| {
+            ThreadPoolBuilder::new()
+                .num_threads(threads as usize)
+                .build()
+                .expect("the threads of the session can be started")
+        });
+        CacheSession { config: self.config, pool }
+    }
+}
+
+/// A cache, and where to write what is read from it.
+#[derive(Debug)]
+pub struct CacheSession {
+    config: Config,
+    /// The threads that the session runs on, if it does not use rayon's global ones.
+    pool: Option<ThreadPool>,
+}
+
+impl CacheSession {
+    /// Opens the cache at `input`, writing output to `output`, with the defaults of the command line.
+    pub fn open(input: impl Into<PathBuf>, output: impl Into<PathBuf>) -> Self {
+        Self::builder(input, output).build()
+    }
+
+    /// Starts setting the options of a session of the cache at `input`, writing output to `output`.
+    pub fn builder(input: impl Into<PathBuf>, output: impl Into<PathBuf>) -> SessionBuilder {
+        // The defaults of the command line, which are not those of `Config::default`.
+        let mut config = Config::try_parse_from(["rs3cache"]).expect("the defaults of every argument are valid");
+        config.input = rs3cache_backend::path::CachePath::Argument(input.into().into());
+        config.output = output.into();
+        SessionBuilder { config }
+    }
+
+    /// Dumps `what`. [`Dump::All`] and [`Dump::Configs`] dump everything they stand for that can be dumped
+    /// with the options of the session, one after another.
+    pub fn dump(&self, what: Dump) -> CacheResult<()> {
+        self.install(|| {
+            for dump in what.inner().expand(&self.config) {
+                dump.call()(&self.config)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Runs `f` on the threads of the session.
+    fn install<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        match &self.pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+
+    /// Renders `what`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render(&self, what: Render) -> CacheResult<()> {
+        self.install(|| what.inner().call(&self.config))
+    }
+}
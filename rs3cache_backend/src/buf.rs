@@ -5,7 +5,8 @@ use core::ops::Deref;
 use std::{fmt::Debug, panic::Location};
 
 use ::error::Context;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Serialize, Serializer};
 
 #[derive(::error::Error)]
@@ -35,6 +36,19 @@ pub enum ReadError {
         #[location]
         location: &'static Location<'static>,
     },
+    #[error = "version {version} is not implemented"]
+    VersionNotImplemented {
+        version: u8,
+        #[location]
+        location: &'static Location<'static>,
+    },
+    #[error = "byte {byte} has a huffman code of {len} bits, which does not fit in the code"]
+    InvalidHuffmanCode {
+        byte: u8,
+        len: u8,
+        #[location]
+        location: &'static Location<'static>,
+    },
     #[error = "could not parse buffer"]
     #[cfg_attr(debug_assertions, help = "managed to read up to {thing}")]
     #[cfg_attr(debug_assertions, help = "managed to decode opcodes {opcodes:?}")]
@@ -250,7 +264,11 @@ pub trait BufExtra: Buf + Sized + Clone {
             unsafe { JString::new(self.clone(), nul_pos) }
         } else {
             // this string format is not utf8, of course :)
-            chunk.iter().map(|&i| i as char).collect::<String>().into()
+            chunk
+                .iter()
+                .map(|&i| crate::cp1252::decode(i).unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect::<String>()
+                .into()
         };
         self.advance(nul_pos + 1);
         Ok(s)
@@ -292,6 +310,49 @@ pub trait BufExtra: Buf + Sized + Clone {
 
 impl<T: Buf + Clone> BufExtra for T {}
 
+/// The writes that mirror the reads of [`BufExtra`], used to encode definitions.
+pub trait BufMutExtra: BufMut + Sized {
+    /// Writes an 32-bit unsigned integer as two or four bytes. See [`BufExtra::try_get_smart32`].
+    #[inline]
+    fn put_smart32(&mut self, value: Option<u32>) {
+        match value {
+            None => self.put_u16(0x7FFF),
+            Some(value) if value < 0x7FFF => self.put_u16(value as u16),
+            Some(value) => self.put_u32(value | 0x80000000),
+        }
+    }
+
+    /// Writes an 16-bit unsigned integer, which must be less than `0x8000`, as one or two bytes.
+    /// See [`BufExtra::try_get_unsigned_smart`].
+    #[inline]
+    fn put_unsigned_smart(&mut self, value: u16) {
+        debug_assert!(value < 0x8000, "{value} is too large for an unsigned smart");
+        if value < 0x80 {
+            self.put_u8(value as u8)
+        } else {
+            self.put_u16(value | 0x8000)
+        }
+    }
+
+    /// Writes a terminated String, encoded as Windows-1252. Characters that it does not have are written as `?`.
+    /// See [`BufExtra::try_get_string`].
+    #[inline]
+    fn put_string(&mut self, s: &str) {
+        let terminator: u8 = if cfg!(feature = "dat") { b'\n' } else { b'\0' };
+
+        if s.is_ascii() {
+            self.put_slice(s.as_bytes());
+        } else {
+            for c in s.chars() {
+                self.put_u8(crate::cp1252::encode(c).unwrap_or(b'?'));
+            }
+        }
+        self.put_u8(terminator);
+    }
+}
+
+impl<T: BufMut> BufMutExtra for T {}
+
 #[derive(Clone, Debug)]
 pub struct JString<R: Buf> {
     inner: JStringKind<R>,
@@ -307,6 +368,21 @@ impl<R: Buf> Serialize for JString<R> {
     }
 }
 
+/// Described as the string it serializes to.
+impl<R: Buf> JsonSchema for JString<R> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum JStringKind<R: Buf> {
     Refcounted { buf: R, len: usize },
@@ -369,3 +445,36 @@ impl<R: Buf> pyo3::IntoPy<pyo3::Py<pyo3::PyAny>> for JString<R> {
         pyo3::types::PyString::new(py, &self).into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = BytesMut::new();
+        for value in [None, Some(0), Some(0x7FFE), Some(0x7FFF), Some(0x12345678)] {
+            buffer.put_smart32(value);
+        }
+        for value in [0, 0x7F, 0x80, 0x7FFF] {
+            buffer.put_unsigned_smart(value);
+        }
+        buffer.put_string("Gnome Ball");
+        buffer.put_string("Café");
+        buffer.put_string("5€ – it’s 日");
+
+        let mut buffer = buffer.freeze();
+        for value in [None, Some(0), Some(0x7FFE), Some(0x7FFF), Some(0x12345678)] {
+            assert_eq!(buffer.try_get_smart32().unwrap(), value);
+        }
+        for value in [0, 0x7F, 0x80, 0x7FFF] {
+            assert_eq!(buffer.try_get_unsigned_smart().unwrap(), value);
+        }
+        assert_eq!(&*buffer.try_get_string().unwrap(), "Gnome Ball");
+        assert_eq!(&*buffer.try_get_string().unwrap(), "Café");
+        assert_eq!(&*buffer.try_get_string().unwrap(), "5€ – it’s ?");
+        assert!(!buffer.has_remaining());
+    }
+}
@@ -0,0 +1,77 @@
+//! Windows-1252, the encoding of the game's text.
+//!
+//! It is Latin-1, except for `0x80..=0x9F`, which are punctuation and a few letters instead of control characters.
+
+/// The characters of `0x80..=0x9F`. The five codes that Windows-1252 leaves undefined are `None`.
+const HIGH: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('‘'),
+    Some('’'),
+    Some('“'),
+    Some('”'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+/// The character of `code`, if it has one.
+pub fn decode(code: u8) -> Option<char> {
+    match code {
+        0x80..=0x9F => HIGH[code as usize - 0x80],
+        _ => Some(char::from(code)),
+    }
+}
+
+/// The code of `c`, if it is in Windows-1252.
+pub fn encode(c: char) -> Option<u8> {
+    match u8::try_from(c) {
+        Ok(0x80..=0x9F) => None,
+        Ok(code) => Some(code),
+        Err(_) => HIGH.iter().position(|&high| high == Some(c)).map(|i| 0x80 + i as u8),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for code in 0..=255 {
+            if let Some(c) = decode(code) {
+                assert_eq!(encode(c), Some(code), "{c}");
+            }
+        }
+        assert_eq!(encode('€'), Some(0x80));
+        assert_eq!(encode('é'), Some(0xE9));
+        assert_eq!(decode(0x92), Some('’'));
+        assert_eq!(decode(0x81), None);
+        // The control characters that Latin-1 has there are not in Windows-1252.
+        assert_eq!(encode('\u{80}'), None);
+        assert_eq!(encode('日'), None);
+    }
+}
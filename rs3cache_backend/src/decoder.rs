@@ -1,105 +1,307 @@
 //! Functions to decompress cache data.
 
-use std::io::Read;
+use std::io::{Read, Write};
 
 use ::error::Context;
 use bytes::Bytes;
 use libflate::{gzip, zlib};
 
+/// A compression format that archives in the cache can be stored in.
+///
+/// Every implementation is listed in [`CODECS`], which is consulted by both [`decompress`] and [`compress`].
+pub trait CompressionCodec: Sync {
+    /// A short name for the format, as in `"gzip"`.
+    fn name(&self) -> &'static str;
+
+    /// Whether `container` is stored in this format.
+    fn matches(&self, container: &[u8]) -> bool;
+
+    /// Decompresses `container`, which includes its header.
+    fn decode(&self, container: &[u8]) -> Result<Bytes, DecodeError>;
+
+    /// Compresses `data` into a container, including its header.
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, DecodeError>;
+}
+
+/// All known [`CompressionCodec`]s.
+pub static CODECS: &[&dyn CompressionCodec] = &[
+    &ZlibCodec,
+    &NoCompression,
+    &Bzip2Codec,
+    &GzipCodec,
+    &LzmaCodec,
+    #[cfg(feature = "dat")]
+    &LegacyGzipCodec,
+];
+
+/// Returns the [`CompressionCodec`] with the given [name](CompressionCodec::name), if any.
+pub fn codec(name: &str) -> Option<&'static dyn CompressionCodec> {
+    CODECS.iter().copied().find(|codec| codec.name() == name)
+}
+
 /// Decompresses index files.
 ///
 /// Used internally by [`CacheIndex`](crate::index::CacheIndex).
-pub fn decompress(mut encoded_data: Vec<u8>, #[cfg(feature = "dat2")] xtea: Option<crate::xtea::Xtea>) -> Result<Bytes, DecodeError> {
-    match &mut *encoded_data {
-        // The zlib format
-        [b'Z', b'L', b'B', b'\x01', x0, x1, x2, x3, data @ ..] => {
-            let length = u32::from_be_bytes([*x0, *x1, *x2, *x3]);
-            let decoder = zlib::Decoder::new(&*data).context(Zlib)?;
-            let ret = do_read(decoder, length)?;
-
-            Ok(ret)
-        }
+pub fn decompress(encoded_data: Vec<u8>, #[cfg(feature = "dat2")] xtea: Option<crate::xtea::Xtea>) -> Result<Bytes, DecodeError> {
+    // Some tools pack empty files
+    if encoded_data.len() < 4 {
+        return Err(Empty::new(encoded_data));
+    }
+
+    // A xtea-encrypted gzip
+    // TODO: see if a missing xtea can be handled more gently
+    #[cfg(feature = "dat2")]
+    if let Some(xtea) = xtea && let [2, x0, x1, x2, x3, data @ .., _, _] = &*encoded_data {
+        let mut decrypted = vec![2, *x0, *x1, *x2, *x3];
+        decrypted.extend(crate::xtea::Xtea::decrypt(data, xtea));
+        return GzipCodec.decode(&decrypted);
+    }
+
+    let codec = CODECS
+        .iter()
+        .find(|codec| codec.matches(&encoded_data))
+        .unwrap_or_else(|| unimplemented!("unknown format {:?}", &encoded_data[0..30.min(encoded_data.len())]));
+
+    match codec.decode(&encoded_data) {
+        // An undecodable gzip is usually one that is encrypted
+        #[cfg(feature = "dat2")]
+        Err(DecodeError::Gzip { .. }) if codec.name() == GzipCodec.name() => Err(Xtea::new()),
+        ret => ret,
+    }
+}
+
+/// Compresses `data` into a container using the given [`CompressionCodec`].
+///
+/// The inverse of [`decompress`].
+pub fn compress(data: &[u8], codec: &dyn CompressionCodec) -> Result<Vec<u8>, DecodeError> {
+    codec.encode(data)
+}
+
+/// Writes the header used by the numbered formats: the compression type,
+/// the compressed length and (if compressed) the decompressed length.
+fn header(compression: u8, compressed: &[u8], decompressed_len: Option<usize>) -> Vec<u8> {
+    let mut container = Vec::with_capacity(compressed.len() + 9);
+    container.push(compression);
+    container.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+    if let Some(len) = decompressed_len {
+        container.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    container.extend_from_slice(compressed);
+    container
+}
+
+/// The zlib format, with a `ZLB\x01` magic header.
+pub struct ZlibCodec;
+
+impl CompressionCodec for ZlibCodec {
+    fn name(&self) -> &'static str {
+        "zlib"
+    }
 
-        // No compression
-        [0, _, _, _, _, data @ .., _, _] => {
-            let ret = Bytes::copy_from_slice(data);
-            Ok(ret)
+    fn matches(&self, container: &[u8]) -> bool {
+        container.starts_with(b"ZLB\x01")
+    }
+
+    fn decode(&self, container: &[u8]) -> Result<Bytes, DecodeError> {
+        match container {
+            [b'Z', b'L', b'B', b'\x01', x0, x1, x2, x3, data @ ..] => {
+                let length = u32::from_be_bytes([*x0, *x1, *x2, *x3]);
+                let decoder = zlib::Decoder::new(data).context(Zlib)?;
+                do_read(decoder, length)
+            }
+            _ => Err(Unimplemented::new(container.to_vec())),
         }
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut encoder = zlib::Encoder::new(Vec::new()).context(Compress)?;
+        encoder.write_all(data).context(Compress)?;
+        let compressed = encoder.finish().into_result().context(Compress)?;
+
+        let mut container = b"ZLB\x01".to_vec();
+        container.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        container.extend(compressed);
+        Ok(container)
+    }
+}
+
+/// No compression.
+///
+/// The data is exactly as long as its header says. Containers in idx-based caches are followed by
+/// a two byte version, which is not part of the data; containers without one are read in full.
+pub struct NoCompression;
+
+impl CompressionCodec for NoCompression {
+    fn name(&self) -> &'static str {
+        "none"
+    }
 
-        // The bzip format
-        [1, _, _, _, _, data @ ..] => {
-            let mut header = *b"BZh1";
-            let length: &mut [u8; 4] = data.get_mut(0..4).unwrap().try_into().unwrap();
-            std::mem::swap(&mut header, length);
-            let length = u32::from_be_bytes(header);
+    fn matches(&self, container: &[u8]) -> bool {
+        container.first() == Some(&0)
+    }
 
-            let decoder = bzip2_rs::DecoderReader::new(&*data);
-            let ret = do_read(decoder, length)?;
-            Ok(ret)
+    fn decode(&self, container: &[u8]) -> Result<Bytes, DecodeError> {
+        match container {
+            [0, x0, x1, x2, x3, data @ ..] => {
+                let length = u32::from_be_bytes([*x0, *x1, *x2, *x3]) as usize;
+                let data = data.get(..length).ok_or_else(|| Truncated::new(length, data.len()))?;
+                Ok(Bytes::copy_from_slice(data))
+            }
+            _ => Err(Unimplemented::new(container.to_vec())),
         }
+    }
 
-        // A xtea-encrypted gzip
-        #[cfg(feature = "dat2")]
-        // TODO: see if a missing xtea can be handled more gently
-        [2, _, _, _, _, data @ .., _, _] if let Some(xtea) = xtea => {
-            let decrypted = crate::xtea::Xtea::decrypt(data, xtea);
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        Ok(header(0, data, None))
+    }
+}
+
+/// The bzip2 format, stored without its `BZh1` header.
+pub struct Bzip2Codec;
 
-            if let [x0, x1, x2, x3, decrypted @ ..] = &*decrypted {
-                let decoder = gzip::Decoder::new(decrypted).context(Gzip)?;
+impl CompressionCodec for Bzip2Codec {
+    fn name(&self) -> &'static str {
+        "bzip2"
+    }
+
+    fn matches(&self, container: &[u8]) -> bool {
+        container.first() == Some(&1)
+    }
+
+    fn decode(&self, container: &[u8]) -> Result<Bytes, DecodeError> {
+        match container {
+            [1, _, _, _, _, x0, x1, x2, x3, data @ ..] => {
                 let length = u32::from_be_bytes([*x0, *x1, *x2, *x3]);
-                let ret = do_read(decoder, length)?;
-                Ok(ret)
-            } else {
-                unreachable!()
+                let mut restored = b"BZh1".to_vec();
+                restored.extend_from_slice(data);
+
+                let decoder = bzip2_rs::DecoderReader::new(&*restored);
+                do_read(decoder, length)
             }
+            _ => Err(Unimplemented::new(container.to_vec())),
         }
+    }
 
-        // The gzip format
-        [2, _y0, _y1, _y2, _y3, x0, x1, x2, x3, data @ ..] => {
-            let length = u32::from_be_bytes([*x0, *x1, *x2, *x3]);
-            let ret = try {
-                let decoder = gzip::Decoder::new(&*data).context(Gzip)?;
-                let ret = do_read(decoder, length)?;
-                ret
-            };
-            match ret {
-                #[cfg(feature = "dat2")]
-                Err(_) => Err(Xtea::new()),
-                e => e,
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::new(1));
+        encoder.write_all(data).context(Compress)?;
+        let compressed = encoder.finish().context(Compress)?;
+        // The cache leaves out the `BZh1` header, which `decode` puts back.
+        Ok(header(1, &compressed[4..], Some(data.len())))
+    }
+}
+
+/// The gzip format.
+pub struct GzipCodec;
+
+impl CompressionCodec for GzipCodec {
+    fn name(&self) -> &'static str {
+        "gzip"
+    }
+
+    fn matches(&self, container: &[u8]) -> bool {
+        container.first() == Some(&2)
+    }
+
+    fn decode(&self, container: &[u8]) -> Result<Bytes, DecodeError> {
+        match container {
+            [2, _y0, _y1, _y2, _y3, x0, x1, x2, x3, data @ ..] => {
+                let length = u32::from_be_bytes([*x0, *x1, *x2, *x3]);
+                let decoder = gzip::Decoder::new(data).context(Gzip)?;
+                do_read(decoder, length)
             }
+            _ => Err(Unimplemented::new(container.to_vec())),
         }
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut encoder = gzip::Encoder::new(Vec::new()).context(Compress)?;
+        encoder.write_all(data).context(Compress)?;
+        let compressed = encoder.finish().into_result().context(Compress)?;
+        Ok(header(2, &compressed, Some(data.len())))
+    }
+}
+
+/// The lzma format, stored as its properties followed by the stream, without the decompressed length.
+pub struct LzmaCodec;
+
+impl CompressionCodec for LzmaCodec {
+    fn name(&self) -> &'static str {
+        "lzma"
+    }
+
+    fn matches(&self, container: &[u8]) -> bool {
+        container.first() == Some(&3)
+    }
 
-        // An older variant of the gzip format
-        #[cfg(feature = "dat")]
-        [b'\x1f', b'\x8b', b'\x08', data @ ..] => {
-            if let [data @ .., _version, _version_part2] = data {
-                let ret: Result<Bytes, DecodeError> = try {
-                    let mut decoder = gzip::Decoder::new(&*data).context(Gzip)?;
-                    let mut buf = Vec::new();
-                    decoder.read_to_end(&mut buf).unwrap();
-                    buf.into()
+    fn decode(&self, container: &[u8]) -> Result<Bytes, DecodeError> {
+        match container {
+            [3, _, _, _, _, x0, x1, x2, x3, data @ ..] => {
+                let length = u32::from_be_bytes([*x0, *x1, *x2, *x3]);
+                let options = lzma_rs::decompress::Options {
+                    unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(length as u64)),
+                    ..Default::default()
                 };
-                if ret.is_err() {
-                    // Sometimes tools generate caches where trailing versions are missing,
-                    // and the below code includes the last two bytes.
-                    let data = encoded_data.as_slice();
-                    let mut decoder = gzip::Decoder::new(data).context(Gzip)?;
-                    let mut buf = Vec::new();
-                    decoder.read_to_end(&mut buf).unwrap();
-                    Ok(buf.into())
-                } else {
-                    ret
-                }
-            } else {
-                unreachable!()
+                let mut decoded_data = Vec::with_capacity(length as usize);
+                lzma_rs::lzma_decompress_with_options(&mut &*data, &mut decoded_data, &options).context(Lzma)?;
+                Ok(decoded_data.into())
             }
+            _ => Err(Unimplemented::new(container.to_vec())),
         }
+    }
+
+    fn encode(&self, mut data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let options = lzma_rs::compress::Options {
+            unpacked_size: lzma_rs::compress::UnpackedSize::SkipWritingToHeader,
+        };
+        let len = data.len();
+        let mut compressed = Vec::new();
+        lzma_rs::lzma_compress_with_options(&mut data, &mut compressed, &options).context(Compress)?;
+        Ok(header(3, &compressed, Some(len)))
+    }
+}
+
+/// An older variant of the gzip format, which is a plain gzip stream followed by a version.
+#[cfg(feature = "dat")]
+pub struct LegacyGzipCodec;
+
+#[cfg(feature = "dat")]
+impl CompressionCodec for LegacyGzipCodec {
+    fn name(&self) -> &'static str {
+        "legacy_gzip"
+    }
 
-        // Some tools pack empty files
-        [] | [_] | [_, _] | [_, _, _] => Err(Empty::new(encoded_data)),
+    fn matches(&self, container: &[u8]) -> bool {
+        container.starts_with(b"\x1f\x8b\x08")
+    }
+
+    fn decode(&self, container: &[u8]) -> Result<Bytes, DecodeError> {
+        if let [data @ .., _version, _version_part2] = container {
+            let ret: Result<Bytes, DecodeError> = try {
+                let mut decoder = gzip::Decoder::new(data).context(Gzip)?;
+                let mut buf = Vec::new();
+                decoder.read_to_end(&mut buf).unwrap();
+                buf.into()
+            };
+            if ret.is_err() {
+                // Sometimes tools generate caches where trailing versions are missing,
+                // and the below code includes the last two bytes.
+                let mut decoder = gzip::Decoder::new(container).context(Gzip)?;
+                let mut buf = Vec::new();
+                decoder.read_to_end(&mut buf).unwrap();
+                Ok(buf.into())
+            } else {
+                ret
+            }
+        } else {
+            unreachable!()
+        }
+    }
 
-        // Oh no
-        _ => unimplemented!("unknown format {:?}", &encoded_data[0..30]),
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut encoder = gzip::Encoder::new(Vec::new()).context(Compress)?;
+        encoder.write_all(data).context(Compress)?;
+        encoder.finish().into_result().context(Compress)
     }
 }
 
@@ -129,8 +331,20 @@ pub enum DecodeError {
         #[source]
         source: bzip2_rs::decoder::DecoderError,
     },
+    #[error = "could not decompress lzma-compressed buffer"]
+    Lzma {
+        #[source]
+        source: lzma_rs::error::Error,
+    },
+    #[error = "buffer is {found} bytes long, but its header says it is {expected} bytes long"]
+    Truncated { expected: usize, found: usize },
     #[error = "passed empty buffer: {buf:?}"]
     Empty { buf: Vec<u8> },
+    #[error = "could not compress buffer"]
+    Compress {
+        #[source]
+        source: std::io::Error,
+    },
     #[error = "decoding format not implemented"]
     Unimplemented { buf: Vec<u8> },
     #[cfg(feature = "dat2")]
@@ -171,3 +385,62 @@ mod tests {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod codec_tests {
+    use super::*;
+
+    fn roundtrip(codec: &dyn CompressionCodec) -> Result<(), DecodeError> {
+        let data = b"The quick brown fox jumps over the lazy dog".repeat(10);
+        let container = compress(&data, codec)?;
+        assert!(codec.matches(&container));
+        assert_eq!(&*codec.decode(&container)?, &*data);
+        Ok(())
+    }
+
+    #[test]
+    fn none() -> Result<(), DecodeError> {
+        roundtrip(&NoCompression)
+    }
+
+    #[test]
+    fn none_version() -> Result<(), DecodeError> {
+        let mut container = compress(b"data", &NoCompression)?;
+        container.extend_from_slice(&[0, 7]);
+        assert_eq!(&*NoCompression.decode(&container)?, b"data");
+
+        container.truncate(7);
+        assert!(matches!(
+            NoCompression.decode(&container),
+            Err(DecodeError::Truncated { expected: 4, found: 2 })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn bzip2() -> Result<(), DecodeError> {
+        roundtrip(&Bzip2Codec)
+    }
+
+    #[test]
+    fn lzma() -> Result<(), DecodeError> {
+        roundtrip(&LzmaCodec)
+    }
+
+    #[test]
+    fn gzip() -> Result<(), DecodeError> {
+        roundtrip(&GzipCodec)
+    }
+
+    #[test]
+    fn zlib() -> Result<(), DecodeError> {
+        roundtrip(&ZlibCodec)
+    }
+
+    #[test]
+    fn registry() {
+        for codec in CODECS {
+            assert_eq!(super::codec(codec.name()).map(|c| c.name()), Some(codec.name()));
+        }
+    }
+}
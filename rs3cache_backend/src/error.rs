@@ -47,6 +47,14 @@ pub enum CacheError {
         #[location]
         location: &'static Location<'static>,
     },
+    #[error = "something went wrong when parsing {file:?}"]
+    JsonDecode {
+        #[source]
+        source: serde_json::Error,
+        file: PathBuf,
+        #[location]
+        location: &'static Location<'static>,
+    },
     #[error = "{msg}"]
     Decompression {
         msg: String,
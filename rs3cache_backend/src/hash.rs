@@ -7,6 +7,32 @@ pub fn hash_djb2(s: impl AsRef<[u8]>) -> i32 {
     n
 }
 
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// Computes the [CRC-32](https://en.wikipedia.org/wiki/Cyclic_redundancy_check) of `data`,
+/// as stored in the reference tables of the cache.
+pub fn crc32(data: impl AsRef<[u8]>) -> i32 {
+    let mut crc = !0u32;
+    for byte in data.as_ref() {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc as i32
+}
+
 #[cfg(feature = "dat")]
 pub fn hash_archive(s: &str) -> i32 {
     let mut n: i32 = 0;
@@ -46,3 +72,14 @@ mod legacy {
         assert_eq!(hash, -923525801);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789") as u32, 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
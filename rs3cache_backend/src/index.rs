@@ -269,6 +269,8 @@ pub enum IntegrityError {
     #[cfg(feature = "dat2")]
     #[error = "Mapsquare ({i}, {i}) has no xtea"]
     XteaMissing { i: u32, j: u32 },
+    #[error = "Index {index_id} Archive {archive_id}: sector {sector} belongs to a different archive"]
+    SectorMismatch { index_id: u32, archive_id: u32, sector: u32 },
     #[error = "Index {metadata.index_id} Archive {metadata.archive_id}: Crc does not match: {crc} !=  {metadata.crc}"]
     Crc { crc: i64, metadata: Metadata },
     #[error = "Index {metadata.index_id} Archive {metadata.archive_id}: Version does not match: {version} !=  {metadata.version}"]
@@ -15,6 +15,7 @@ use crate::{
     error::{self, CacheResult, CannotOpen},
     index::*,
     meta::{IndexMetadata, Metadata},
+    warning::{self, Warning},
     xtea::Xtea,
 };
 impl<S> CacheIndex<S>
@@ -33,78 +34,71 @@ where
     }
 
     fn read_index(&self, a: u32, b: u32) -> CacheResult<Vec<u8>> {
-        let mut buffer = BufReader::new(&self.file);
+        let (length, sector) = Self::get_entry(a, b, &self.input)?;
+        read_chain(BufReader::new(&self.file), a, b, length, sector)
+    }
 
-        let (length, mut sector) = Self::get_entry(a, b, &self.input)?;
+    /// Reads the container described by `metadata`.
+    ///
+    /// Some caches contain stale copies of a group, left behind by clients that did not clean up after an update.
+    /// If the chain in the idx file cannot be read, or does not match the crc and version of the reference table,
+    /// the data file is searched for a sector chain that does, and the conflict is reported.
+    ///
+    /// # Errors
+    ///
+    /// Raises the error of the chain in the idx file, such as [`IntegrityError::Crc`] or [`IntegrityError::Version`],
+    /// if no matching chain can be found.
+    fn read_verified(&self, metadata: &Metadata) -> CacheResult<Vec<u8>> {
+        let (a, b) = (metadata.index_id(), metadata.archive_id());
+        let (length, first) = Self::get_entry(a, b, &self.input)?;
+        let indexed = read_chain(BufReader::new(&self.file), a, b, length, first).and_then(|data| {
+            Self::check(metadata, &data).context(error::Integrity)?;
+            Ok(data)
+        });
+        let mismatch = match indexed {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        };
 
-        let mut read_count = 0;
-        let mut part = 0;
-        let mut data = Vec::with_capacity(length as _);
+        let len = self
+            .file
+            .metadata()
+            .context(FileSeek)
+            .context(error::Read { what: "data file size" })?
+            .len();
+        for sector in find_chains(BufReader::new(&self.file), len, a, b)? {
+            // The idx entry is the one that didn't match; don't bother reading it again
+            if sector == first {
+                continue;
+            }
+            let Ok(data) = read_candidate(BufReader::new(&self.file), a, b, sector) else { continue };
+            if Self::check(metadata, &data).is_ok() {
+                warning::warn(Warning::StaleArchive {
+                    index_id: a,
+                    archive_id: b,
+                    sector,
+                });
+                return Ok(data);
+            }
+        }
 
-        while sector != 0 {
-            buffer.seek(SeekFrom::Start((sector * 520) as _)).context(FileSeek).context(error::Read {
-                what: "buffer start position",
-            })?;
-            let (_header_size, current_archive, block_size) = if b >= 0xFFFF {
-                let mut buf = [0; 4];
-                buffer
-                    .read_exact(&mut buf)
-                    .context(FileSeek)
-                    .context(error::Read { what: "archive checksum" })?;
-                (10, i32::from_be_bytes(buf), 510.min(length - read_count))
-            } else {
-                let mut buf = [0; 2];
-                buffer
-                    .read_exact(&mut buf)
-                    .context(FileSeek)
-                    .context(error::Read { what: "archive checksum" })?;
-                (8, u16::from_be_bytes(buf) as _, 512.min(length - read_count))
-            };
-
-            let current_part = {
-                let mut buf = [0; 2];
-                buffer
-                    .read_exact(&mut buf)
-                    .context(FileSeek)
-                    .context(error::Read { what: "part checksum" })?;
-                u16::from_be_bytes(buf)
-            };
-            let new_sector = {
-                let mut buf = [0; 4];
-                buffer.read_exact(&mut buf[1..4]).context(FileSeek).context(error::Read {
-                    what: "next sector position",
-                })?;
-                u32::from_be_bytes(buf)
-            };
-            let current_index = {
-                let mut buf = [0; 1];
-                buffer.read_exact(&mut buf).context(FileSeek).context(error::Read {
-                    what: "current position checksum",
-                })?;
-                u8::from_be_bytes(buf)
-            };
-
-            assert_eq!(a, current_index as u32);
-            assert_eq!(b, current_archive as u32);
-            assert_eq!(part, current_part as u32);
-
-            part += 1;
-            read_count += block_size;
-            sector = new_sector;
-
-            let mut buf = [0u8; 512];
-            buffer
-                .read_exact(&mut buf[..(block_size as usize)])
-                .context(FileSeek)
-                .context(error::Read { what: "archive data" })?;
+        Err(mismatch)
+    }
 
-            data.extend_from_slice(&buf[..(block_size as usize)]);
+    fn check(metadata: &Metadata, data: &[u8]) -> Result<(), IntegrityError> {
+        let (container, trailer) = split_trailer(data);
+        metadata.verify(container)?;
+        match trailer {
+            Some(version) if version != metadata.version() as u16 => Err(IntegrityError::Version {
+                version: version as i64,
+                metadata: metadata.clone(),
+            }),
+            _ => Ok(()),
         }
-        Ok(data)
     }
 
     pub fn get_file(&self, metadata: &Metadata) -> CacheResult<Bytes> {
-        let data = self.read_index(metadata.index_id(), metadata.archive_id())?;
+        let data = self.read_verified(metadata)?;
         decoder::decompress(data, None).context(error::Decode)
     }
 
@@ -121,7 +115,7 @@ where
                 archive_id,
             })
             .context(error::Integrity)?;
-        let data = self.read_index(metadata.index_id(), metadata.archive_id())?;
+        let data = self.read_verified(metadata)?;
         let data = decoder::decompress(data, xtea).context(error::Decode)?;
         Ok(Archive::deserialize(metadata, data))
     }
@@ -191,3 +185,229 @@ impl CacheIndex<Initial> {
         Ok(s)
     }
 }
+
+/// Lists the ids of the indices present in the cache, in ascending order.
+///
+/// These are the entries of the reference index, `main_file_cache.idx255`, which are six bytes each.
+///
+/// # Errors
+///
+/// Raises [`CannotOpen`](CacheError::CannotOpen) if the reference index cannot be found.
+pub fn index_ids(input: &CachePath) -> CacheResult<Vec<u32>> {
+    let file = path!(input / "cache" / "main_file_cache.idx255");
+    let len = fs::metadata(&file).context(CannotOpen { file, input: input.clone() })?.len();
+    Ok((0..(len / 6) as u32).collect())
+}
+
+/// Reads the sector chain of archive `b` of index `a`, `length` bytes long and starting at `sector`, from `buffer`.
+fn read_chain(mut buffer: impl Read + Seek, a: u32, b: u32, length: u32, mut sector: u32) -> CacheResult<Vec<u8>> {
+    let mut read_count = 0;
+    let mut part = 0;
+    let mut data = Vec::with_capacity(length as _);
+
+    // Chains found by scanning may run on past the length in their header.
+    while sector != 0 && read_count < length {
+        buffer.seek(SeekFrom::Start((sector * 520) as _)).context(FileSeek).context(error::Read {
+            what: "buffer start position",
+        })?;
+        let (_header_size, current_archive, block_size) = if b >= 0xFFFF {
+            let mut buf = [0; 4];
+            buffer
+                .read_exact(&mut buf)
+                .context(FileSeek)
+                .context(error::Read { what: "archive checksum" })?;
+            (10, i32::from_be_bytes(buf), 510.min(length - read_count))
+        } else {
+            let mut buf = [0; 2];
+            buffer
+                .read_exact(&mut buf)
+                .context(FileSeek)
+                .context(error::Read { what: "archive checksum" })?;
+            (8, u16::from_be_bytes(buf) as _, 512.min(length - read_count))
+        };
+
+        let current_part = {
+            let mut buf = [0; 2];
+            buffer
+                .read_exact(&mut buf)
+                .context(FileSeek)
+                .context(error::Read { what: "part checksum" })?;
+            u16::from_be_bytes(buf)
+        };
+        let new_sector = {
+            let mut buf = [0; 4];
+            buffer.read_exact(&mut buf[1..4]).context(FileSeek).context(error::Read {
+                what: "next sector position",
+            })?;
+            u32::from_be_bytes(buf)
+        };
+        let current_index = {
+            let mut buf = [0; 1];
+            buffer.read_exact(&mut buf).context(FileSeek).context(error::Read {
+                what: "current position checksum",
+            })?;
+            u8::from_be_bytes(buf)
+        };
+
+        if a != current_index as u32 || b != current_archive as u32 || part != current_part as u32 {
+            return Err(IntegrityError::SectorMismatch {
+                index_id: a,
+                archive_id: b,
+                sector,
+            })
+            .context(error::Integrity);
+        }
+
+        part += 1;
+        read_count += block_size;
+        sector = new_sector;
+
+        let mut buf = [0u8; 512];
+        buffer
+            .read_exact(&mut buf[..(block_size as usize)])
+            .context(FileSeek)
+            .context(error::Read { what: "archive data" })?;
+
+        data.extend_from_slice(&buf[..(block_size as usize)]);
+    }
+    Ok(data)
+}
+
+/// Finds the starting sectors of all chains belonging to archive `b` of index `a` in `buffer`, which is `len` bytes long.
+///
+/// This scans every sector header in the data file, so it should only be used when something is wrong.
+fn find_chains(mut buffer: impl Read + Seek, len: u64, a: u32, b: u32) -> CacheResult<Vec<u32>> {
+    let header_size = if b >= 0xFFFF { 10 } else { 8 };
+
+    let mut sectors = Vec::new();
+    for sector in 1..(len / 520) as u32 {
+        let mut header = [0u8; 10];
+        buffer.seek(SeekFrom::Start(sector as u64 * 520)).context(FileSeek).context(error::Read {
+            what: "buffer start position",
+        })?;
+        buffer
+            .read_exact(&mut header[..header_size])
+            .context(FileSeek)
+            .context(error::Read { what: "sector header" })?;
+        let (archive, part, index) = match header_size {
+            10 => (
+                u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+                u16::from_be_bytes([header[4], header[5]]),
+                header[9],
+            ),
+            _ => (
+                u16::from_be_bytes([header[0], header[1]]) as u32,
+                u16::from_be_bytes([header[2], header[3]]),
+                header[7],
+            ),
+        };
+        if archive == b && part == 0 && index as u32 == a {
+            sectors.push(sector);
+        }
+    }
+    Ok(sectors)
+}
+
+/// Reads a chain that is not referenced by the idx file, deriving its length from the container header.
+fn read_candidate(mut buffer: impl Read + Seek, a: u32, b: u32, sector: u32) -> CacheResult<Vec<u8>> {
+    let header_size = if b >= 0xFFFF { 10 } else { 8 };
+    let mut header = [0u8; 5];
+    buffer
+        .seek(SeekFrom::Start(sector as u64 * 520 + header_size))
+        .context(FileSeek)
+        .context(error::Read {
+            what: "buffer start position",
+        })?;
+    buffer
+        .read_exact(&mut header)
+        .context(FileSeek)
+        .context(error::Read { what: "container header" })?;
+    let [compression, l0, l1, l2, l3] = header;
+    let length = u32::from_be_bytes([l0, l1, l2, l3]) + if compression == 0 { 5 } else { 9 } + 2;
+    read_chain(buffer, a, b, length, sector)
+}
+
+/// Splits a container into the part covered by the crc and its version trailer, if present.
+fn split_trailer(data: &[u8]) -> (&[u8], Option<u16>) {
+    let len = match data {
+        [0, l0, l1, l2, l3, ..] => u32::from_be_bytes([*l0, *l1, *l2, *l3]) as usize + 5,
+        [_, l0, l1, l2, l3, ..] => u32::from_be_bytes([*l0, *l1, *l2, *l3]) as usize + 9,
+        _ => return (data, None),
+    };
+    match data.get(len..) {
+        Some(&[v0, v1]) => (&data[..len], Some(u16::from_be_bytes([v0, v1]))),
+        _ => (data, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailer() {
+        let container = [0, 0, 0, 0, 2, 0xAB, 0xCD];
+        assert_eq!(split_trailer(&container), (&container[..], None));
+
+        let with_trailer = [0, 0, 0, 0, 2, 0xAB, 0xCD, 0x01, 0x02];
+        assert_eq!(split_trailer(&with_trailer), (&with_trailer[..7], Some(0x0102)));
+
+        let compressed = [2, 0, 0, 0, 1, 0, 0, 0, 9, 0xFF, 0x00, 0x07];
+        assert_eq!(split_trailer(&compressed), (&compressed[..10], Some(7)));
+    }
+
+    /// A data file of `count` empty sectors.
+    fn data_file(count: usize) -> Vec<u8> {
+        vec![0; count * 520]
+    }
+
+    /// Writes a sector of archive `b` of index `a` with a small archive id.
+    fn put_sector(file: &mut [u8], sector: usize, a: u8, b: u16, part: u16, next: u32, data: &[u8]) {
+        let start = sector * 520;
+        file[start..start + 2].copy_from_slice(&b.to_be_bytes());
+        file[start + 2..start + 4].copy_from_slice(&part.to_be_bytes());
+        file[start + 4..start + 7].copy_from_slice(&next.to_be_bytes()[1..]);
+        file[start + 7] = a;
+        file[start + 8..start + 8 + data.len()].copy_from_slice(data);
+    }
+
+    /// An uncompressed container of `len` bytes of `fill`, with a version trailer.
+    fn container(len: usize, fill: u8) -> Vec<u8> {
+        let mut container = vec![0];
+        container.extend_from_slice(&(len as u32).to_be_bytes());
+        container.extend(std::iter::repeat(fill).take(len));
+        container.extend_from_slice(&[0, 1]);
+        container
+    }
+
+    #[test]
+    fn chains() {
+        let mut file = data_file(6);
+        put_sector(&mut file, 1, 2, 5, 0, 0, &[]);
+        // The second part of a chain does not start one.
+        put_sector(&mut file, 2, 2, 5, 1, 0, &[]);
+        put_sector(&mut file, 3, 3, 5, 0, 0, &[]);
+        put_sector(&mut file, 4, 2, 6, 0, 0, &[]);
+        put_sector(&mut file, 5, 2, 5, 0, 0, &[]);
+        let len = file.len() as u64;
+        assert_eq!(find_chains(Cursor::new(file), len, 2, 5).unwrap(), vec![1, 5]);
+    }
+
+    #[test]
+    fn candidates() {
+        // A container that spans two sectors that are not next to each other.
+        let data = container(600, 0xAB);
+        let mut file = data_file(5);
+        put_sector(&mut file, 1, 2, 5, 0, 4, &data[..512]);
+        put_sector(&mut file, 4, 2, 5, 1, 0, &data[512..]);
+        // A stale chain that runs on past the end of its container.
+        let stale = container(10, 0xCD);
+        put_sector(&mut file, 2, 2, 5, 0, 3, &stale);
+        put_sector(&mut file, 3, 2, 5, 1, 0, &[]);
+
+        assert_eq!(read_candidate(Cursor::new(file.clone()), 2, 5, 1).unwrap(), data);
+        assert_eq!(read_candidate(Cursor::new(file.clone()), 2, 5, 2).unwrap(), stale);
+        // The chain belongs to another archive.
+        assert!(read_candidate(Cursor::new(file), 2, 6, 1).is_err());
+    }
+}
@@ -83,6 +83,17 @@ where
         }
     }
 
+    /// Reads the archive whose name hashes to the hash of `name`.
+    pub fn archive_by_name(&self, name: String) -> CacheResult<Bytes> {
+        let hash = crate::hash::hash_djb2(&name);
+        for (_, m) in self.metadatas.iter() {
+            if m.name() == Some(hash) {
+                return self.get_file(m);
+            }
+        }
+        Err(ArchiveMissingNamed::new(self.index_id, name)).context(error::Integrity)
+    }
+
     /// Assert whether the cache held by `self` is in a coherent state.
     ///
     /// # Errors
@@ -129,6 +140,30 @@ where
     }
 }
 
+/// Lists the ids of the indices present in the cache, in ascending order.
+///
+/// # Errors
+///
+/// Raises [`Io`](CacheError::Io) if the cache folder cannot be read.
+pub fn index_ids(input: &CachePath) -> CacheResult<Vec<u32>> {
+    let folder = input.as_ref();
+    let folder = if folder.as_os_str().is_empty() {
+        std::path::Path::new(".")
+    } else {
+        folder
+    };
+    let entries = std::fs::read_dir(folder).with_context(|| error::Io { path: folder.into() })?;
+    let mut ids = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            name.to_str()?.strip_prefix("js5-")?.strip_suffix(".jcache")?.parse().ok()
+        })
+        .filter(|&id| id != 255)
+        .collect::<Vec<u32>>();
+    ids.sort_unstable();
+    Ok(ids)
+}
+
 /// Asserts whether all indices' metadata match their contents.
 /// Indices 14, 40, 54, 55 are not necessarily complete.
 ///
@@ -28,11 +28,15 @@
 
 pub mod arc;
 pub mod buf;
+pub mod cp1252;
 pub mod decoder;
 pub mod error;
 pub mod hash;
 pub mod index;
 pub mod meta;
 pub mod path;
+pub mod recovery;
+pub mod unknown_opcodes;
+pub mod warning;
 #[cfg(feature = "dat2")]
 pub mod xtea;
@@ -22,6 +22,8 @@ use {
     std::hash::{Hash, Hasher},
 };
 
+use crate::index::IntegrityError;
+
 /// Metadata about [`Archive`](crate::arc::Archive)s.
 #[cfg_eval]
 #[cfg_attr(feature = "pyo3", pyclass(frozen))]
@@ -173,12 +175,60 @@ impl Metadata {
     pub fn child_indices(&self) -> &[u32] {
         &self.child_indices
     }
+
+    /// Checks a raw (still compressed) container against the crc recorded in `self`.
+    ///
+    /// Intended to be used on groups as they are received, so that mismatches can be requested again
+    /// rather than be written into the cache.
+    ///
+    /// # Errors
+    ///
+    /// Raises [`IntegrityError::Crc`] if the crc of `container` does not match.
+    pub fn verify(&self, container: &[u8]) -> Result<(), IntegrityError> {
+        let crc = crate::hash::crc32(container);
+        if crc == self.crc {
+            Ok(())
+        } else {
+            Err(IntegrityError::Crc {
+                crc: crc as i64,
+                metadata: self.clone(),
+            })
+        }
+    }
+
+    /// Requests the container described by `self` until it passes [`verify`](Self::verify), at most `attempts` times.
+    ///
+    /// This is what a downloader should receive groups with, so that one that arrives corrupted is requested again
+    /// rather than written into the cache.
+    ///
+    /// # Errors
+    ///
+    /// Raises the error of `request`, or [`IntegrityError::Crc`] if no attempt matched.
+    pub fn request_verified<E: From<IntegrityError>>(
+        &self,
+        attempts: usize,
+        mut request: impl FnMut(&Metadata) -> Result<Vec<u8>, E>,
+    ) -> Result<Vec<u8>, E> {
+        let mut mismatch = None;
+        for _ in 0..attempts.max(1) {
+            let container = request(self)?;
+            match self.verify(&container) {
+                Ok(()) => return Ok(container),
+                Err(e) => mismatch = Some(e),
+            }
+        }
+        Err(mismatch.expect("there is at least one attempt").into())
+    }
 }
 
 /// Contains the [`Metadata`] for every [`Archive`](crate::arc::Archive) in the index.
 
 #[derive(Serialize, Clone, Debug, Default, Hash, Eq, Ord, PartialOrd, PartialEq)]
 pub struct IndexMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<i8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<i32>,
     metadatas: BTreeMap<u32, Metadata>,
 }
 
@@ -186,6 +236,8 @@ impl IndexMetadata {
     #[cfg(any(feature = "dat2", feature = "dat"))]
     pub(crate) fn empty() -> Self {
         Self {
+            format: None,
+            version: None,
             metadatas: BTreeMap::default(),
         }
     }
@@ -200,7 +252,7 @@ impl IndexMetadata {
     pub(crate) fn deserialize(index_id: u32, mut buffer: Bytes) -> Result<Self, ReadError> {
         let format = buffer.try_get_i8()?;
 
-        let _index_utc_stamp = if format > 5 { Some(buffer.try_get_i32()?) } else { None };
+        let version = if format > 5 { Some(buffer.try_get_i32()?) } else { None };
 
         let [named, hashed, unk4, ..] = buffer.get_bitflags();
 
@@ -319,7 +371,30 @@ impl IndexMetadata {
 
         //assert!(!buffer.buf.has_remaining());
 
-        Ok(Self { metadatas })
+        Ok(Self {
+            format: Some(format),
+            version,
+            metadatas,
+        })
+    }
+
+    /// The format of the reference table this was read from, if any.
+    #[inline(always)]
+    pub const fn format(&self) -> Option<i8> {
+        self.format
+    }
+
+    /// The version of the reference table this was read from, if present.
+    ///
+    /// This is incremented (or set to a timestamp) whenever the index is updated.
+    #[inline(always)]
+    pub const fn version(&self) -> Option<i32> {
+        self.version
+    }
+
+    /// The highest [version](Metadata::version) of the archives in the index.
+    pub fn latest_archive_version(&self) -> Option<i32> {
+        self.metadatas.values().map(Metadata::version).max()
     }
 
     /// View a specific [`Metadata`] of `self`.
@@ -349,3 +424,44 @@ impl IntoIterator for IndexMetadata {
         self.metadatas.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(container: &[u8]) -> Metadata {
+        Metadata {
+            index_id: 2,
+            archive_id: 6,
+            crc: crate::hash::crc32(container),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn verify() {
+        let metadata = metadata(b"container");
+        assert!(metadata.verify(b"container").is_ok());
+        assert!(matches!(
+            metadata.verify(b"corrupted"),
+            Err(IntegrityError::Crc { crc, .. }) if crc == crate::hash::crc32(b"corrupted") as i64
+        ));
+    }
+
+    #[test]
+    fn request_verified() {
+        let metadata = metadata(b"container");
+
+        let mut responses = [b"corrupted".to_vec(), b"container".to_vec()].into_iter();
+        let received = metadata.request_verified::<IntegrityError>(2, |_| Ok(responses.next().unwrap()));
+        assert_eq!(received.unwrap(), b"container");
+
+        let mut requests = 0;
+        let received = metadata.request_verified::<IntegrityError>(3, |_| {
+            requests += 1;
+            Ok(b"corrupted".to_vec())
+        });
+        assert!(matches!(received, Err(IntegrityError::Crc { .. })));
+        assert_eq!(requests, 3);
+    }
+}
@@ -0,0 +1,94 @@
+//! Keeping what was decoded of definitions that fail to decode.
+//!
+//! Normally a definition that fails to decode fails the whole dump. When recovering is turned on,
+//! decoders instead return what they read up to that point, with a description of where they stopped
+//! in an `_error` field. This takes precedence over [capturing unknown opcodes](crate::unknown_opcodes).
+
+use std::fmt::Display;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+
+use crate::buf::{ReadError, WithInfo};
+
+/// Where a decoder stopped.
+pub struct Stopped<'a> {
+    /// The opcode that failed to decode.
+    pub opcode: u8,
+    /// The opcodes that were decoded before it.
+    #[cfg(debug_assertions)]
+    pub opcodes: Vec<u8>,
+    /// What is left of the buffer.
+    pub buffer: Bytes,
+    /// What was decoded up to this point.
+    pub thing: &'a dyn Display,
+}
+
+impl Stopped<'_> {
+    /// If `recovering`, returns the description for the `_error` field of the partially decoded definition.
+    /// Otherwise, returns `error` with what the decoder managed to read.
+    pub fn recover(self, recovering: bool, error: ReadError) -> Result<String, ReadError> {
+        if recovering {
+            Ok(describe(self.opcode, &error, self.buffer.remaining()))
+        } else {
+            Err(error).map_err(Box::new).context(WithInfo {
+                #[cfg(debug_assertions)]
+                opcodes: self.opcodes,
+                buffer: self.buffer,
+                #[cfg(debug_assertions)]
+                thing: self.thing.to_string(),
+            })
+        }
+    }
+}
+
+/// Describes where decoding stopped, for the `_error` field of a partially decoded definition.
+pub fn describe(opcode: u8, error: &ReadError, remaining: usize) -> String {
+    format!("stopped at opcode {opcode} with {remaining} bytes left: {error}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::Location;
+
+    use super::*;
+
+    fn unimplemented() -> ReadError {
+        ReadError::OpcodeNotImplemented {
+            opcode: 200,
+            location: Location::caller(),
+        }
+    }
+
+    fn stopped(thing: &dyn Display) -> Stopped<'_> {
+        Stopped {
+            opcode: 200,
+            #[cfg(debug_assertions)]
+            opcodes: vec![1, 2],
+            buffer: Bytes::from_static(&[1, 2, 3]),
+            thing,
+        }
+    }
+
+    #[test]
+    fn description() {
+        assert_eq!(
+            describe(200, &unimplemented(), 3),
+            "stopped at opcode 200 with 3 bytes left: opcode 200 is not implemented"
+        );
+    }
+
+    #[test]
+    fn recovering() {
+        assert_eq!(
+            stopped(&"thing").recover(true, unimplemented()).unwrap(),
+            "stopped at opcode 200 with 3 bytes left: opcode 200 is not implemented"
+        );
+    }
+
+    #[test]
+    fn not_recovering() {
+        let error = stopped(&"thing").recover(false, unimplemented()).unwrap_err();
+        assert!(matches!(error, ReadError::WithInfo { ref source, .. } if matches!(**source, ReadError::OpcodeNotImplemented { opcode: 200, .. })));
+    }
+}
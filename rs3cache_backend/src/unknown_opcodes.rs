@@ -0,0 +1,108 @@
+//! Opcodes that decoders do not know of yet.
+//!
+//! Normally these fail the whole decode. When capturing, definitions that run into one are left out
+//! instead, and the opcode is recorded in an [`UnknownOpcodes`] with a sample of the bytes that follow it,
+//! so that a game update can be turned into a list of opcodes to implement.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use serde::Serialize;
+
+use crate::{
+    buf::ReadError,
+    warning::{self, Warning},
+};
+
+/// How many bytes following an unknown opcode are kept as a sample.
+pub const SAMPLE_LEN: usize = 32;
+
+/// An unknown opcode of a kind of definition, and how often it was seen.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOpcode {
+    pub what: &'static str,
+    pub opcode: u8,
+    pub count: usize,
+    /// The bytes following the first occurence of the opcode, in hex.
+    pub sample: String,
+}
+
+/// The unknown opcodes captured so far, by kind of definition.
+#[derive(Debug, Default)]
+pub struct UnknownOpcodes {
+    seen: Mutex<BTreeMap<(&'static str, u8), UnknownOpcode>>,
+}
+
+impl UnknownOpcodes {
+    /// Passes `result` through, unless it failed on an unknown opcode.
+    /// Then the opcode is recorded under `what`, warned about, and `None` is returned so the definition can be left out.
+    pub fn capture<T>(&self, what: &'static str, result: Result<T, ReadError>) -> Option<Result<T, ReadError>> {
+        match &result {
+            Err(ReadError::WithInfo { source, buffer, .. }) if let ReadError::OpcodeNotImplemented { opcode, .. } = **source => {
+                self.seen
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .entry((what, opcode))
+                    .or_insert_with(|| UnknownOpcode {
+                        what,
+                        opcode,
+                        count: 0,
+                        sample: buffer.iter().take(SAMPLE_LEN).map(|byte| format!("{byte:02x}")).collect(),
+                    })
+                    .count += 1;
+                warning::warn(Warning::UnknownOpcode { what, opcode });
+                None
+            }
+            _ => Some(result),
+        }
+    }
+
+    /// Returns all unknown opcodes recorded so far, and clears them.
+    pub fn take(&self) -> Vec<UnknownOpcode> {
+        std::mem::take(&mut *self.seen.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+            .into_values()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::Location;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn unknown(opcode: u8) -> Result<(), ReadError> {
+        Err(ReadError::WithInfo {
+            source: Box::new(ReadError::OpcodeNotImplemented {
+                opcode,
+                location: Location::caller(),
+            }),
+            #[cfg(debug_assertions)]
+            opcodes: Vec::new(),
+            buffer: Bytes::from_static(&[1, 2, 0xff]),
+            #[cfg(debug_assertions)]
+            thing: String::new(),
+        })
+    }
+
+    #[test]
+    fn capturing() {
+        let unknown_opcodes = UnknownOpcodes::default();
+        assert!(unknown_opcodes.capture("things", unknown(200)).is_none());
+        assert!(unknown_opcodes.capture("things", unknown(200)).is_none());
+        assert!(unknown_opcodes.capture("things", Ok(())).unwrap().is_ok());
+
+        assert_eq!(
+            unknown_opcodes.take(),
+            vec![UnknownOpcode {
+                what: "things",
+                opcode: 200,
+                count: 2,
+                sample: "0102ff".to_string(),
+            }]
+        );
+        assert!(unknown_opcodes.take().is_empty());
+        assert!(warning::take().contains(&Warning::UnknownOpcode { what: "things", opcode: 200 }));
+    }
+}
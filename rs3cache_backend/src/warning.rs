@@ -0,0 +1,107 @@
+//! Problems that were worked around rather than treated as errors.
+//!
+//! These are collected while decoding and rendering, so they can be reported at the end of a run.
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+
+/// Something that was worked around. Serialized with a `kind` tag, which is stable.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// The idx entry of an archive was stale, and a copy elsewhere in the data file was used instead.
+    StaleArchive { index_id: u32, archive_id: u32, sector: u32 },
+    /// A sprite could not be found, and was left out.
+    MissingSprite { id: u32, frame: u32 },
+    /// Something was not present in the cache, and was skipped.
+    MissingKey { what: &'static str, key: String },
+    /// A music track is named, but not present in the cache.
+    MissingTrack { name: String },
+    /// Only the first `chunks` of the `expected` chunks of a music track are present, and only they were exported.
+    IncompleteTrack { name: String, chunks: usize, expected: usize },
+    /// A chunk of a music track could not be joined to the ones before it, and the track was left out.
+    UnjoinableTrack { name: String, chunk: usize, reason: &'static str },
+    /// A map tile could not be written to `path`, and was left out.
+    TileFailed { path: String, message: String },
+    /// A definition of `what` had an opcode that is not implemented, and was left out.
+    UnknownOpcode { what: &'static str, opcode: u8 },
+    /// A legacy sprite could not be decoded, and was left out.
+    UndecodableSprite { name: String },
+}
+
+impl Warning {
+    /// The `kind` tag this is serialized with.
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::StaleArchive { .. } => "stale_archive",
+            Self::MissingSprite { .. } => "missing_sprite",
+            Self::MissingKey { .. } => "missing_key",
+            Self::MissingTrack { .. } => "missing_track",
+            Self::IncompleteTrack { .. } => "incomplete_track",
+            Self::UnjoinableTrack { .. } => "unjoinable_track",
+            Self::TileFailed { .. } => "tile_failed",
+            Self::UnknownOpcode { .. } => "unknown_opcode",
+            Self::UndecodableSprite { .. } => "undecodable_sprite",
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StaleArchive {
+                index_id,
+                archive_id,
+                sector,
+            } => write!(
+                f,
+                "index {index_id} archive {archive_id} is stale, used the copy at sector {sector} instead"
+            ),
+            Self::MissingSprite { id, frame } => write!(f, "sprite {id} frame {frame} is missing"),
+            Self::MissingKey { what, key } => write!(f, "{what} {key} is missing"),
+            Self::MissingTrack { name } => write!(f, "music track `{name}` is missing"),
+            Self::IncompleteTrack { name, chunks, expected } => {
+                write!(
+                    f,
+                    "music track `{name}` is incomplete, only {chunks} of its {expected} chunks are present"
+                )
+            }
+            Self::UnjoinableTrack { name, chunk, reason } => write!(f, "chunk {chunk} of music track `{name}` {reason}"),
+            Self::TileFailed { path, message } => write!(f, "tile {path} could not be written: {message}"),
+            Self::UnknownOpcode { what, opcode } => write!(f, "{what} with unknown opcode {opcode} were left out"),
+            Self::UndecodableSprite { name } => write!(f, "sprite `{name}` could not be decoded and was left out"),
+        }
+    }
+}
+
+static WARNINGS: Mutex<BTreeSet<Warning>> = Mutex::new(BTreeSet::new());
+
+/// Records `warning`. Identical warnings are only recorded once.
+pub fn warn(warning: Warning) {
+    WARNINGS.lock().unwrap().insert(warning);
+}
+
+/// Returns all warnings recorded so far, and clears them.
+pub fn take() -> Vec<Warning> {
+    std::mem::take(&mut *WARNINGS.lock().unwrap()).into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize() {
+        let warning = Warning::MissingSprite { id: 317, frame: 5 };
+        assert_eq!(
+            serde_json::to_string(&warning).unwrap(),
+            r#"{"kind":"missing_sprite","id":317,"frame":5}"#
+        );
+        assert_eq!(warning.kind(), "missing_sprite");
+    }
+}
@@ -0,0 +1,174 @@
+//! Reports what is at a coordinate, as in `rs3 at 3200 3200 0`.
+//!
+//! Only the [`MapSquare`] containing the coordinate and its western and southern neighbours are decoded;
+//! the latter for large locations that extend into it.
+
+use std::{collections::BTreeMap, num::TryFromIntError};
+
+use clap::Args;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    cli::Config,
+    definitions::{
+        location_configs::LocationConfig,
+        locations::Location,
+        mapsquares::{MapSquare, MapSquares},
+        overlays::Overlay,
+        tiles::Tile,
+        underlays::Underlay,
+    },
+    show,
+};
+
+/// Arguments of the `at` subcommand.
+#[derive(Args, Clone, Debug)]
+pub struct At {
+    /// The horizontal game coordinate.
+    #[clap(value_parser = clap::value_parser!(u32).range(0..0x4000))]
+    pub x: u32,
+
+    /// The vertical game coordinate.
+    #[clap(value_parser = clap::value_parser!(u32).range(0..0x4000))]
+    pub y: u32,
+
+    /// The plane, a.k.a elevation.
+    #[clap(default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..4))]
+    pub plane: u8,
+}
+
+impl At {
+    /// The coordinates of the [`MapSquare`] this is in, and the coordinates inside it.
+    ///
+    /// # Errors
+    ///
+    /// Coordinates of `0x4000` and over are not in any mapsquare.
+    fn split(&self) -> Result<((u8, u8), (u8, u8)), TryFromIntError> {
+        let square = (u8::try_from(self.x >> 6)?, u8::try_from(self.y >> 6)?);
+        Ok((square, ((self.x & 0x3F) as u8, (self.y & 0x3F) as u8)))
+    }
+}
+
+/// Whether the footprint of `loc` covers the tile at the game coordinates `x, y`.
+fn covers(loc: &Location, loc_config: Option<&LocationConfig>, x: u32, y: u32) -> bool {
+    let (dim_x, dim_y) = loc_config.map_or((1, 1), |c| (c.dim_x.unwrap_or(1) as u32, c.dim_y.unwrap_or(1) as u32));
+    // Rotating by 90 or 270 degrees swaps the dimensions.
+    let (dim_x, dim_y) = if loc.rotation % 2 == 1 { (dim_y, dim_x) } else { (dim_x, dim_y) };
+
+    let loc_x = loc.i as u32 * 64 + loc.x as u32;
+    let loc_y = loc.j as u32 * 64 + loc.y as u32;
+    (loc_x..loc_x + dim_x).contains(&x) && (loc_y..loc_y + dim_y).contains(&y)
+}
+
+/// Entry point for `at`.
+pub fn run(config: &Config, at: &At) -> Result<(), Box<dyn std::error::Error>> {
+    let ((i, j), (x, y)) = at.split()?;
+
+    let mapsquares = MapSquares::new(config)?;
+    let square = mapsquares.get(i, j)?;
+    let neighbours: Vec<MapSquare> = [(i.wrapping_sub(1), j), (i, j.wrapping_sub(1)), (i.wrapping_sub(1), j.wrapping_sub(1))]
+        .into_iter()
+        .filter_map(|(i, j)| mapsquares.get(i, j).ok())
+        .collect();
+
+    let location_configs = LocationConfig::dump_all(config)?;
+    let overlays = Overlay::dump_all(config)?;
+    let underlays = Underlay::dump_all(config)?;
+
+    let tile = square.tiles().map(|tiles| tiles[[at.plane as usize, x as usize, y as usize]]);
+
+    let locations = std::iter::once(&square)
+        .chain(&neighbours)
+        .flat_map(|sq| sq.locations().unwrap_or_default())
+        .filter(|loc| loc.plane.matches(&at.plane))
+        .filter(|loc| covers(loc, location_configs.get(&loc.id), at.x, at.y))
+        .map(|loc| {
+            let mut value = to_value(loc);
+            let name = location_configs.get(&loc.id).and_then(|c| c.name.as_ref());
+            value["name"] = to_value(&name);
+            value
+        })
+        .collect::<Vec<_>>();
+
+    let value = json!({
+        "x": at.x,
+        "y": at.y,
+        "plane": at.plane,
+        "mapsquare": { "i": i, "j": j, "x": x, "y": y },
+        "region": (i as u32) << 8 | j as u32,
+        "tile": tile.map(|tile| describe(&tile, &overlays, &underlays)),
+        "locations": locations,
+    });
+
+    let mut text = String::new();
+    show::colour(&mut text, &value, 0);
+    show::page(&text);
+    Ok(())
+}
+
+/// Describes a [`Tile`] along with the overlay and underlay on it.
+fn describe(tile: &Tile, overlays: &BTreeMap<u32, Overlay>, underlays: &BTreeMap<u32, Underlay>) -> Value {
+    // Tiles refer to overlays and underlays by their id plus one.
+    let overlay = tile.overlay_id.and_then(|id| overlays.get(&(id.checked_sub(1)? as u32)));
+    let underlay = tile.underlay_id.and_then(|id| underlays.get(&(id.checked_sub(1)? as u32)));
+
+    let mut value = to_value(tile);
+    value["overlay"] = to_value(&overlay);
+    value["underlay"] = to_value(&underlay);
+    value
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::locations::Watery;
+
+    fn loc(x: u8, y: u8, rotation: u8) -> Location {
+        Location {
+            plane: Watery::False(0),
+            i: 50,
+            j: 50,
+            x,
+            y,
+            id: 0,
+            r#type: 10,
+            rotation,
+        }
+    }
+
+    #[test]
+    fn split() {
+        let at = At { x: 3222, y: 3218, plane: 0 };
+        assert_eq!(at.split(), Ok(((50, 50), (22, 18))));
+
+        let at = At {
+            x: 0x4000,
+            y: 3218,
+            plane: 0,
+        };
+        assert!(at.split().is_err());
+    }
+
+    #[test]
+    fn footprint() {
+        let config = LocationConfig {
+            dim_x: Some(3),
+            dim_y: Some(1),
+            ..Default::default()
+        };
+
+        assert!(covers(&loc(10, 10, 0), None, 3210, 3210));
+        assert!(!covers(&loc(10, 10, 0), None, 3211, 3210));
+
+        assert!(covers(&loc(10, 10, 0), Some(&config), 3212, 3210));
+        assert!(!covers(&loc(10, 10, 0), Some(&config), 3210, 3211));
+
+        assert!(covers(&loc(10, 10, 1), Some(&config), 3210, 3212));
+        assert!(!covers(&loc(10, 10, 1), Some(&config), 3212, 3210));
+    }
+}
@@ -0,0 +1,36 @@
+//! Stops long runs cleanly when ctrl-C is pressed.
+//!
+//! The first ctrl-C only sets a flag. Dumps that have not started yet are skipped, and the map renderer
+//! finishes the tiles it is working on before writing its manifest, so that the output is left in a known state.
+//! A second ctrl-C exits immediately.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use console::style;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the ctrl-C handler.
+///
+/// Does nothing if a handler was already installed, for example by an embedding application.
+pub fn install() {
+    let _ = ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!(
+            "\n    {} finishing what is in progress, press ctrl-C again to exit immediately",
+            style("Cancelling").yellow()
+        );
+    });
+}
+
+/// Requests that the run stops, as if ctrl-C was pressed.
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether the run should stop.
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
@@ -1,14 +1,23 @@
 use std::{
     fmt,
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
-use clap::{Parser, ValueEnum};
-use rs3cache_backend::{error::CacheResult, path::CachePath};
+use clap::{Parser, Subcommand, ValueEnum};
+use rs3cache_backend::{
+    buf::ReadError,
+    error::CacheResult,
+    path::CachePath,
+    unknown_opcodes::{UnknownOpcode, UnknownOpcodes},
+};
 
-use crate::definitions;
+use crate::{
+    definitions,
+    output::{filter::Filter, predicate::Predicate, sink::Sink},
+};
 #[cfg(not(target_arch = "wasm32"))]
-use crate::renderers::map;
+use crate::{output::naming, renderers::map};
 
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(ValueEnum, Clone, Debug)]
@@ -30,80 +39,509 @@ impl Render {
     }
 }
 
+/// How rendered tiles are stored.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[clap(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// A png file per tile, as `<layer>/<mapid>/<zoom>/<plane>_<x>_<y>.png`.
+    #[default]
+    Files,
+    /// An MBTiles file per plane, as `<layer>/<mapid>/<plane>.mbtiles`. See [`mbtiles`](crate::output::mbtiles).
+    #[cfg(feature = "mbtiles")]
+    Mbtiles,
+}
+
+/// The image format of rendered tiles.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[clap(rename_all = "snake_case")]
+pub enum TileFormat {
+    #[default]
+    Png,
+    /// Lossless, or lossy with `--tile-quality`. Much smaller than png, and supported by all browsers.
+    #[cfg(feature = "webp")]
+    Webp,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TileFormat {
+    /// The extension of tile files, without the dot.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            #[cfg(feature = "webp")]
+            Self::Webp => "webp",
+        }
+    }
+
+    pub const fn image_format(self) -> image::ImageFormat {
+        match self {
+            Self::Png => image::ImageFormat::Png,
+            #[cfg(feature = "webp")]
+            Self::Webp => image::ImageFormat::WebP,
+        }
+    }
+}
+
+/// Trades off the time spent encoding rendered tiles against their size.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[clap(rename_all = "snake_case")]
+pub enum PngEncoder {
+    /// Fast compression, with a cheap filter.
+    Fast,
+    /// Default compression, with adaptive filtering.
+    #[default]
+    Small,
+    /// Quantized to at most 256 colours, as an indexed png. Lossy, but about half the size of `small`.
+    ///
+    /// See [`quantize`](crate::renderers::quantize).
+    Indexed,
+}
+
+/// How the map renderer reports its progress.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[clap(rename_all = "snake_case")]
+pub enum Progress {
+    /// Only the progress bars, on stderr.
+    #[default]
+    Bar,
+    /// Also a line of json on stderr, or in `--progress-file`, for every mapsquare rendered and tile written,
+    /// see [`progress`](crate::renderers::progress).
+    Json,
+}
+
+/// The file format that dumps are written in.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[clap(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Json,
+    /// Binary glTF 2.0, for `--dump models`. This is what models are written as by default.
+    Gltf,
+    /// Wavefront OBJ with vertex colours, for `--dump models`.
+    Obj,
+    /// A GeoJSON feature collection, for `--dump locations`.
+    Geojson,
+    /// Comma separated values, one definition per row, for json dumps. See [`csv`](crate::output::csv).
+    Csv,
+    /// Apache Parquet, one file per definition type, for json dumps. See [`parquet`](crate::output::parquet).
+    #[cfg(feature = "arrow")]
+    Parquet,
+    /// MessagePack, with the same structure as json, for json dumps.
+    Msgpack,
+    /// CBOR, with the same structure as json, for json dumps.
+    Cbor,
+    /// One json object per line, written as they are decoded. For json dumps and `--dump locations`.
+    Jsonl,
+    /// NumPy arrays, for `--dump tiles`. See [`npy`](crate::output::npy).
+    Npy,
+}
+
+impl Format {
+    /// Whether definitions are flattened into rows in this format, rather than serialized as they are.
+    pub fn is_tabular(self) -> bool {
+        match self {
+            Format::Csv => true,
+            #[cfg(feature = "arrow")]
+            Format::Parquet => true,
+            _ => false,
+        }
+    }
+}
+
+/// The revision of the game a cache is from, as far as the format of its configs is concerned.
+///
+/// Each variant is the first revision of a format; older ones are less. See [`Config::revision`].
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[clap(rename_all = "snake_case")]
+pub enum Revision {
+    /// Old school caches, and caches from before 2008.
+    Base,
+    #[value(name = "2008_3")]
+    R2008_3,
+    #[value(name = "2009_1")]
+    R2009_1,
+    #[value(name = "2010_1")]
+    R2010_1,
+    #[value(name = "2010_3")]
+    R2010_3,
+    #[value(name = "2011_11")]
+    R2011_11,
+    #[value(name = "2013")]
+    R2013,
+    #[value(name = "2013_4")]
+    R2013_4,
+    /// Current caches, as read with the `rs3` feature.
+    Latest,
+}
+
+impl Revision {
+    /// The revision this build assumes when it cannot tell, as given by its game feature and shims.
+    ///
+    /// Shimmed builds decode every revision from [`Revision::R2008_3`] on, whichever shim they were built with.
+    pub const fn compiled() -> Self {
+        if cfg!(feature = "rs3") {
+            Revision::Latest
+        } else if cfg!(feature = "2013_4_shim") {
+            Revision::R2013_4
+        } else if cfg!(feature = "2013_shim") {
+            Revision::R2013
+        } else if cfg!(feature = "2011_11_shim") {
+            Revision::R2011_11
+        } else if cfg!(feature = "2010_3_shim") {
+            Revision::R2010_3
+        } else if cfg!(feature = "2010_1_shim") {
+            Revision::R2010_1
+        } else if cfg!(feature = "2009_1_shim") {
+            Revision::R2009_1
+        } else if cfg!(feature = "2008_3_shim") {
+            Revision::R2008_3
+        } else {
+            Revision::Base
+        }
+    }
+
+    /// The revision that was current in the given month, for caches that predate current ones.
+    pub fn at(year: i32, month: u32) -> Self {
+        match (year, month) {
+            (..=2007, _) | (2008, 1..=2) => Revision::Base,
+            (2008, _) => Revision::R2008_3,
+            (2009, _) => Revision::R2009_1,
+            (2010, 1..=2) => Revision::R2010_1,
+            (2010, _) | (2011, 1..=10) => Revision::R2010_3,
+            (2011, _) | (2012, _) => Revision::R2011_11,
+            (2013, 1..=3) => Revision::R2013,
+            _ => Revision::R2013_4,
+        }
+    }
+
+    /// Guesses the revision of the cache from the versions of its archives, which are usually the time they were built at.
+    ///
+    /// Builds without shims only read one format, so this is [`Revision::compiled`] for them,
+    /// as it is for caches whose archives have no timestamps.
+    #[allow(unused_variables)]
+    fn detect(config: &Config) -> Self {
+        #[cfg(feature = "2008_3_shim")]
+        {
+            let timestamp = definitions::revision::CacheRevision::dump(config)
+                .ok()
+                .and_then(|revision| revision.latest_archive_version)
+                .filter(|&version| version > 1_000_000_000);
+            if let Some(timestamp) = timestamp {
+                let (year, month) = year_month(timestamp as i64);
+                return Self::at(year, month);
+            }
+        }
+        Self::compiled()
+    }
+}
+
+#[cfg(feature = "2008_3_shim")]
+/// The year and month of a unix timestamp.
+fn year_month(timestamp: i64) -> (i32, u32) {
+    // From http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = timestamp.div_euclid(86400) + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year as i32, month as u32)
+}
+
 #[derive(ValueEnum, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 #[clap(rename_all = "snake_case")]
 pub enum Dump {
     All,
     Configs,
-    #[cfg(feature = "rs3")]
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
     Music,
     #[cfg(feature = "rs3")]
     Achievements,
+    #[cfg(feature = "rs3")]
+    Dbrows,
+    #[cfg(feature = "rs3")]
+    Dbtables,
+    #[cfg(feature = "osrs")]
+    Clientscripts,
+    #[cfg(feature = "osrs")]
+    Sounds,
     Sprites,
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    SpritesDiff,
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    Fonts,
     Locations,
     LocationsEach,
+    Tiles,
     TilesEach,
     LocationConfigs,
     LocationConfigsEach,
     NpcConfig,
     ItemConfigs,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Identkits,
+    #[cfg(feature = "osrs")]
+    Interfaces,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Inventories,
     Maplabels,
     #[cfg(feature = "rs3")]
+    Quickchat,
+    #[cfg(feature = "rs3")]
+    Quests,
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    MapIcons,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    MapLabelPlacements,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Xref,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    ParamsUsage,
+    #[cfg(feature = "rs3")]
     Worldmaps,
+    #[cfg(feature = "rs3")]
+    MapZones,
     VarbitConfigs,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Varps,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Varcs,
     Structs,
     Enums,
     #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Params,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Sequences,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Hitmarks,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Headbars,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Spotanims,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
     Underlays,
     #[cfg(any(feature = "rs3", feature = "osrs"))]
     Overlays,
     #[cfg(feature = "osrs")]
     Textures,
+    #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+    Models,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Revision,
+    #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+    SceneryShots,
+    #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+    NpcRenders,
+    #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+    Terrain3d,
+}
+
+/// Which `--filter`s a [`Dump`] honours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filtering {
+    /// Its definitions are saved with [`export`](crate::output::export), which applies any filter.
+    All,
+    /// Its files are named by id, and only ids can be filtered with [`Config::keeps`].
+    Ids,
+    /// It has nothing to filter by.
+    Nothing,
 }
 
 impl Dump {
+    /// Whether this dump can be written as `--format format`. Npy arrays are only for tiles, which are only tables,
+    /// glTF and OBJ only for meshes, and GeoJSON only for locations.
+    pub fn accepts(&self, format: Format) -> bool {
+        match self {
+            Dump::Tiles => matches!(format, Format::Csv | Format::Npy),
+            // Models are written as glTF unless they are asked for as OBJ.
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Models => matches!(format, Format::Json | Format::Gltf | Format::Obj),
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Terrain3d => matches!(format, Format::Json | Format::Gltf),
+            Dump::Locations => !matches!(format, Format::Npy | Format::Gltf | Format::Obj),
+            _ => !matches!(format, Format::Npy | Format::Gltf | Format::Obj | Format::Geojson),
+        }
+    }
+
+    /// The dumps that this dump stands for with `config`.
+    ///
+    /// `all` and `configs` stand for those of their dumps that can be written as `--format`, leaving out
+    /// `sprites_diff` when there is no `--previous` cache to compare against. Every other dump stands for itself.
+    pub fn expand(&self, config: &Config) -> Vec<Dump> {
+        let dumps = match self {
+            Dump::All => Dump::value_variants(),
+            Dump::Configs => Dump::configs(),
+            other => return vec![other.clone()],
+        };
+        dumps
+            .iter()
+            .cloned()
+            .filter(|dump| !matches!(dump, Dump::All | Dump::Configs) && dump.accepts(config.format))
+            .filter(|dump| {
+                #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+                if *dump == Dump::SpritesDiff {
+                    return config.previous.is_some();
+                }
+                true
+            })
+            .collect()
+    }
+
+    /// Which `--filter`s this dump honours.
+    pub fn filtering(&self) -> Filtering {
+        match self {
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Music => Filtering::Ids,
+            #[cfg(feature = "osrs")]
+            Dump::Sounds => Filtering::Ids,
+            #[cfg(feature = "osrs")]
+            Dump::Interfaces => Filtering::Ids,
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::MapIcons => Filtering::Ids,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Models | Dump::SceneryShots | Dump::NpcRenders => Filtering::Ids,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Sprites => Filtering::Ids,
+            // Legacy sprites are named rather than numbered.
+            #[cfg(feature = "legacy")]
+            Dump::Sprites => Filtering::Nothing,
+            Dump::Locations | Dump::LocationConfigsEach => Filtering::Ids,
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::SpritesDiff | Dump::Fonts => Filtering::Nothing,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Xref | Dump::ParamsUsage | Dump::Revision => Filtering::Nothing,
+            #[cfg(feature = "rs3")]
+            Dump::Quickchat | Dump::Worldmaps | Dump::MapZones => Filtering::Nothing,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Terrain3d => Filtering::Nothing,
+            Dump::Tiles | Dump::TilesEach | Dump::LocationsEach => Filtering::Nothing,
+            _ => Filtering::All,
+        }
+    }
+
     pub fn call(&self) -> fn(&Config) -> CacheResult<()> {
         match self {
             #[cfg(feature = "rs3")]
             Dump::Music => definitions::music::export_each,
+            #[cfg(feature = "osrs")]
+            Dump::Music => definitions::midi::export,
             #[cfg(feature = "rs3")]
             Dump::Achievements => definitions::achievements::export,
+            #[cfg(feature = "rs3")]
+            Dump::Dbrows => definitions::dbrows::export,
+            #[cfg(feature = "rs3")]
+            Dump::Dbtables => definitions::dbtables::export,
+            #[cfg(feature = "osrs")]
+            Dump::Clientscripts => definitions::clientscripts::export,
+            #[cfg(feature = "osrs")]
+            Dump::Sounds => definitions::sound_effects::export,
             Dump::Sprites => definitions::sprites::save_all,
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::SpritesDiff => definitions::sprites::export_diff,
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::Fonts => definitions::fonts::export,
+            Dump::Tiles => definitions::mapsquares::export_tiles_table,
             Dump::TilesEach => definitions::mapsquares::export_tiles_by_square,
-            Dump::Locations => definitions::mapsquares::export_locations_by_id,
+            Dump::Locations => |config| match config.format {
+                Format::Geojson => definitions::mapsquares::export_locations_geojson(config),
+                Format::Jsonl => definitions::mapsquares::export_locations_jsonl(config),
+                _ => definitions::mapsquares::export_locations_by_id(config),
+            },
             Dump::LocationsEach => definitions::mapsquares::export_locations_by_square,
             Dump::LocationConfigs => definitions::location_configs::export,
             Dump::LocationConfigsEach => definitions::location_configs::export_each,
             Dump::NpcConfig => definitions::npc_configs::export,
             Dump::ItemConfigs => definitions::item_configs::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Identkits => definitions::identkits::export,
+            #[cfg(feature = "osrs")]
+            Dump::Interfaces => definitions::interfaces::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Inventories => definitions::inventories::export,
             Dump::Maplabels => definitions::maplabel_configs::export,
             #[cfg(feature = "rs3")]
+            Dump::Quickchat => definitions::quickchat::export,
+            #[cfg(feature = "rs3")]
+            Dump::Quests => definitions::quests::export,
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::MapIcons => definitions::maplabel_configs::export_icons,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::MapLabelPlacements => definitions::maplabel_configs::export_placements,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Xref => crate::xref::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::ParamsUsage => crate::params_usage::export,
+            #[cfg(feature = "rs3")]
             Dump::Worldmaps => |config| try {
                 definitions::worldmaps::dump_big(config)?;
                 definitions::worldmaps::dump_small(config)?;
                 definitions::worldmaps::export_pastes(config)?;
-                definitions::worldmaps::export_zones(config)?;
             },
+            #[cfg(feature = "rs3")]
+            Dump::MapZones => definitions::worldmaps::export_zones,
             Dump::VarbitConfigs => definitions::varbit_configs::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Varps => definitions::var_configs::export_varps,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Varcs => definitions::var_configs::export_varcs,
             Dump::Structs => definitions::structs::export,
             Dump::Enums => definitions::enums::export,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Params => definitions::params::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Sequences => definitions::sequences::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Hitmarks => definitions::hitmarks::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Headbars => definitions::headbars::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Spotanims => definitions::spotanims::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
             Dump::Underlays => definitions::underlays::export,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
             Dump::Overlays => definitions::overlays::export,
             #[cfg(feature = "osrs")]
             Dump::Textures => definitions::textures::export,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Models => definitions::models::export,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Revision => definitions::revision::export,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::SceneryShots => crate::renderers::scenery::export,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::NpcRenders => crate::renderers::npcs::export,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Terrain3d => crate::renderers::terrain::export,
             Dump::All | Dump::Configs => |_| Ok(()),
         }
     }
 
     pub fn as_str(&self) -> &'static str {
         match self {
-            #[cfg(feature = "rs3")]
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
             Dump::Music => "music",
             #[cfg(feature = "rs3")]
             Dump::Achievements => "achievements",
+            #[cfg(feature = "rs3")]
+            Dump::Dbrows => "dbrows",
+            #[cfg(feature = "rs3")]
+            Dump::Dbtables => "dbtables",
+            #[cfg(feature = "osrs")]
+            Dump::Clientscripts => "clientscripts",
+            #[cfg(feature = "osrs")]
+            Dump::Sounds => "sounds",
             Dump::Sprites => "sprites",
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::SpritesDiff => "sprites_diff",
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::Fonts => "fonts",
+            Dump::Tiles => "tiles",
             Dump::TilesEach => "tiles_by_square",
             Dump::Locations => "locations_by_id",
             Dump::LocationsEach => "locations_by_square",
@@ -111,18 +549,62 @@ impl Dump {
             Dump::LocationConfigsEach => "location_configs_each",
             Dump::NpcConfig => "npc_configs",
             Dump::ItemConfigs => "item_configs",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Identkits => "identkits",
+            #[cfg(feature = "osrs")]
+            Dump::Interfaces => "interfaces",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Inventories => "inventories",
             Dump::Maplabels => "maplabel_configs",
             #[cfg(feature = "rs3")]
+            Dump::Quickchat => "quickchat",
+            #[cfg(feature = "rs3")]
+            Dump::Quests => "quests",
+            #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+            Dump::MapIcons => "map_icons",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::MapLabelPlacements => "map_label_placements",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Xref => "xref",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::ParamsUsage => "params_usage",
+            #[cfg(feature = "rs3")]
             Dump::Worldmaps => "world_maps",
+            #[cfg(feature = "rs3")]
+            Dump::MapZones => "map_zones",
             Dump::VarbitConfigs => "varbit_configs",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Varps => "varps",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Varcs => "varcs",
             Dump::Structs => "structs",
             Dump::Enums => "enums",
             #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Params => "params",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Sequences => "sequences",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Hitmarks => "hitmarks",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Headbars => "headbars",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Spotanims => "spotanims",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
             Dump::Underlays => "underlays",
             #[cfg(any(feature = "rs3", feature = "osrs"))]
             Dump::Overlays => "overlays",
             #[cfg(feature = "osrs")]
             Dump::Textures => "textures",
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Models => "models",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Revision => "revision",
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::SceneryShots => "scenery_shots",
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::NpcRenders => "npc_renders",
+            #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+            Dump::Terrain3d => "terrain3d",
             Dump::All => "all",
             Dump::Configs => "configs",
         }
@@ -132,14 +614,38 @@ impl Dump {
         &[
             #[cfg(feature = "rs3")]
             Dump::Achievements,
+            #[cfg(feature = "rs3")]
+            Dump::Quests,
+            #[cfg(feature = "rs3")]
+            Dump::Dbrows,
+            #[cfg(feature = "rs3")]
+            Dump::Dbtables,
             Dump::LocationConfigs,
             Dump::NpcConfig,
             Dump::ItemConfigs,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Identkits,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Inventories,
             Dump::Maplabels,
             Dump::VarbitConfigs,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Varps,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Varcs,
             Dump::Structs,
             Dump::Enums,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Params,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Sequences,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Hitmarks,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Headbars,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Dump::Spotanims,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
             Dump::Underlays,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
             Dump::Overlays,
@@ -165,17 +671,46 @@ const OUTPUT: &str = if cfg!(feature = "rs3") {
     unimplemented!()
 };
 
+/// Subcommands, for things that are not dumping or rendering.
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Decodes and prints a single definition, as in `show item 4151`.
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    Show(crate::show::Show),
+    /// Reports the mapsquare, tile and locations at a coordinate, as in `at 3200 3200 0`.
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    At(crate::at::At),
+    /// Writes JSON Schemas of dumped definitions, as in `schema item npc`.
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    Schema(crate::schema::Schema),
+    /// Writes a key to the map, with what its mapscenes, icons and line colours mean, as `legend.png` and `legend.json`.
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    Legend(crate::legend::Legend),
+    /// Serves rendered tiles over HTTP with a map viewer, as in `serve --port 8080`.
+    #[cfg(not(target_arch = "wasm32"))]
+    Serve(crate::serve::Serve),
+}
+
 #[derive(Debug, Default, Parser)]
 #[clap(author, about = "Tools and api for reading and interpreting the RuneScape game cache")]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The path where to look for the current cache.
     #[command(flatten)]
     pub input: CachePath,
 
     /// The path where to place output.
+    ///
+    /// With the `s3` feature, this can also be a bucket, as in `s3://bucket/prefix`.
+    /// Tiles and json dumps are then uploaded directly; see [`sink`](crate::output::sink).
     #[clap(long, env = OUTPUT, default_value_os = ".")]
     pub output: PathBuf,
 
+    #[clap(skip)]
+    sink: OnceLock<Sink>,
+
     /// This exports them as small tiles, formatted as `<layer>/<mapid>/<zoom>/<plane>_<x>_<y>.png`,
     /// suitable for use with interactive map libraries such as <https://leafletjs.com/>,
     /// as seen on <https://mejrs.github.io/>
@@ -183,17 +718,368 @@ pub struct Config {
     #[clap(value_enum, long, num_args(..))]
     pub render: Vec<Render>,
 
+    /// How rendered tiles are stored.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(value_enum, long, default_value_t)]
+    pub output_format: OutputFormat,
+
+    /// The image format of rendered tiles, which are named `<plane>_<x>_<y>.<format>`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(value_enum, long, default_value_t)]
+    pub tile_format: TileFormat,
+
+    /// How tiles are named in the folder of their layer, from the placeholders `{map_id}`, `{zoom}`, `{plane}`, `{x}`, `{y}` and `{ext}`.
+    /// Templates without `{map_id}` cannot be used with `--all-maps`.
+    ///
+    /// See [`naming`](crate::output::naming).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "TEMPLATE", default_value_t)]
+    pub tile_template: naming::Template,
+
+    /// Whether tile rows count from the south, as the renderer does, or from the north.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(value_enum, long, default_value_t)]
+    pub tile_scheme: naming::TileScheme,
+
+    /// Encodes webp tiles lossily, at this quality from 0 to 100. Without it they are lossless.
+    #[cfg(all(feature = "webp", not(target_arch = "wasm32")))]
+    #[clap(long, value_name = "QUALITY", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub tile_quality: Option<u8>,
+
+    /// The png encoder used for rendered tiles.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(value_enum, long, default_value_t)]
+    pub png_encoder: PngEncoder,
+
+    /// How the map is rendered.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[command(flatten)]
+    pub render_config: map::RenderConfig,
+
+    /// Also renders every world map that is pasted together from parts of the game map, such as those of dungeons,
+    /// each into its own tile tree, as with `--map-id`.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "rs3"))]
+    #[clap(long)]
+    pub all_maps: bool,
+
+    /// Also renders these layers, each into its own transparent tile tree next to `mapsquares`, as in `--layers base,walls,mapscenes`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(value_enum, long, value_delimiter = ',')]
+    pub layers: Vec<map::Layer>,
+
+    /// Only renders the mapsquares in this rectangle of game coordinates, as in `--render-region 3136,3328,3327,3519`,
+    /// or in a named region, as in `--render-region varrock`.
+    ///
+    /// Only the zoom level tiles that contain these mapsquares are remade; see [`Region`](map::Region).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "X1,Y1,X2,Y2|NAME")]
+    pub render_region: Option<map::Region>,
+
+    /// Only renders these mapsquares, as in `--mapsquare 50,50 --mapsquare 50,51`. Combines with `--render-region`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "I,J", value_parser = map::parse_mapsquare)]
+    pub mapsquare: Vec<(u8, u8)>,
+
+    /// Only renders the mapsquares that look different than in the cache at this path, and the zoom level tiles containing them.
+    ///
+    /// See [`changed`](crate::renderers::changed) for what is compared.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "PATH")]
+    pub render_changed: Option<PathBuf>,
+
+    /// Skips the mapsquares that an earlier, interrupted render already rendered from the same cache with the same settings.
+    ///
+    /// See [`resume`](crate::renderers::resume).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub resume: bool,
+
+    /// Overrides colours of the map with those of this toml file, see [`palette`](crate::renderers::map::palette).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "PATH", value_parser = map::palette::parse)]
+    pub palette: Option<map::palette::Palette>,
+
+    /// Draws the points, lines, polygons and labels of this GeoJSON file onto the map,
+    /// see [`markers`](crate::renderers::map::markers).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "PATH", value_parser = map::markers::parse)]
+    pub markers: Option<map::markers::Markers>,
+
+    /// Tints the tiles covered by locations with these ids, as in `--highlight-loc 1234,5678`, in the `highlights` layer.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_delimiter = ',')]
+    pub highlight_loc: Vec<u32>,
+
+    /// Also saves every placement of the locations of `--highlight-loc` to `highlights.json`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, requires = "highlight_loc")]
+    pub highlight_json: bool,
+
+    /// Renders the `grid` layer, with the boundaries, coordinates and region ids of mapsquares and the lines between tiles.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub debug_grid: bool,
+
+    /// Renders the `chunks` layer, with the boundaries of chunks, and stripes over the mapsquares
+    /// whose locations could not be decrypted, so that they do not look empty.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub debug_chunks: bool,
+
+    /// Keeps the map renderer roughly below this many MiB, at the cost of wall time. Also given as `--max-memory`.
+    ///
+    /// This is not a hard ceiling. It only caps how many mapsquares are rendered at once, never more than `--threads`,
+    /// by an estimate of what one takes at the `--tile-size`; `--layers` are rendered one after another, so they take longer rather than more memory.
+    /// While zoom levels are made, half of it caps how many tiles are kept in memory.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, alias = "max-memory", value_name = "MIB")]
+    pub memory_limit: Option<u64>,
+
+    /// Composites and downscales map tiles on the gpu, see [`gpu`](crate::renderers::gpu).
+    #[cfg(all(not(target_arch = "wasm32"), feature = "gpu"))]
+    #[clap(long)]
+    pub gpu: bool,
+
+    /// Uses at most this many threads, for rendering as well as dumping. Defaults to one per cpu.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub threads: Option<u16>,
+
+    /// How the map renderer reports its progress.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(value_enum, long, default_value_t)]
+    pub progress: Progress,
+
+    /// Writes the json of `--progress json` to this file instead of stderr.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, requires = "progress")]
+    pub progress_file: Option<PathBuf>,
+
+    /// Writes the bounding box of each rendered tile's content to `manifest.json`, next to the zoom levels.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub tile_manifest: bool,
+
+    /// Also packs the rendered map tiles into a PMTiles archive per plane, as `<layer>/<mapid>/<plane>.pmtiles`.
+    ///
+    /// See [`pmtiles`](crate::output::pmtiles).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub pmtiles: bool,
+
+    /// Also writes the rendered map tiles as a Deep Zoom image per plane, as `<layer>/<mapid>/dzi/<plane>.dzi`,
+    /// for OpenSeadragon based viewers.
+    ///
+    /// See [`deepzoom`](crate::renderers::deepzoom).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub dzi: bool,
+
+    /// Also writes the rendered map tiles as a static IIIF Image API service per plane, in `<layer>/<mapid>/iiif/<plane>`,
+    /// which will be served from under this url.
+    ///
+    /// See [`deepzoom`](crate::renderers::deepzoom).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "URL")]
+    pub iiif: Option<String>,
+
+    /// Also assembles the tiles of `--stitch-zoom` into one big image per plane, as `<layer>/<mapid>/stitched/<plane>.png`.
+    ///
+    /// See [`stitch`](crate::renderers::stitch).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub stitch: bool,
+
+    /// The zoom level that `--stitch` assembles; at 2 a mapsquare is 256 pixels wide.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, default_value_t = 2, allow_hyphen_values = true, value_parser = clap::value_parser!(i8).range(-4..=4), requires = "stitch")]
+    pub stitch_zoom: i8,
+
+    /// Only stitches this rectangle of game coordinates, like `--render-region`, which it defaults to.
+    /// Without either, everything that was rendered is stitched.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "X1,Y1,X2,Y2|NAME", requires = "stitch")]
+    pub stitch_region: Option<map::Region>,
+
+    /// Writes `index.html`, a page to look at the rendered map with in a browser, next to the `mapsquares` folder.
+    ///
+    /// See [`viewer`](crate::renderers::viewer).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub emit_viewer: bool,
+
+    /// Crops rendered tiles to the bounding box in `manifest.json`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, requires = "tile_manifest")]
+    pub trim_tiles: bool,
+
+    /// A file of ids, separated by whitespace or commas.
+    ///
+    /// These are the location ids for `--dump scenery_shots`, and the model ids for `--dump models`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "FILE")]
+    pub ids: Option<PathBuf>,
+
+    /// Also places the models of locations on the ground of `--dump terrain3d`.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+    #[clap(long)]
+    pub terrain_locations: bool,
+
+    /// An older cache to compare against, required by `--dump sprites_diff`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long, value_name = "PATH", required_if_eq("dump", "sprites_diff"))]
+    pub previous: Option<PathBuf>,
+
     /// Dumps the given archives.
     #[clap(value_enum, long, num_args(..))]
     pub dump: Vec<Dump>,
 
+    /// The file format that dumps are written in.
+    #[clap(value_enum, long, default_value_t)]
+    pub format: Format,
+
+    /// Writes dumps in a canonical form, so that dumps of different caches can be compared with `diff`.
+    ///
+    /// See [`canonical`](crate::output::canonical).
+    #[clap(long)]
+    pub deterministic: bool,
+
+    /// The columns of `--format csv`, as in `--columns id,name,params.1397`. Defaults to every top level field.
+    #[clap(long, value_delimiter = ',', num_args(..))]
+    pub columns: Vec<String>,
+
+    /// Only dump definitions matching this expression, as in `--where "name~'altar' && interactable"`.
+    ///
+    /// See [`predicate`](crate::output::predicate) for the syntax.
+    #[clap(long = "where")]
+    pub predicate: Option<Predicate>,
+
+    /// Only dump these definitions: ids and id ranges as in `1000-2000,4151`, or a name glob as in `"*portal*"`.
+    /// Dumps of files named by id, such as sprites, only take ids; see [`Dump::filtering`].
+    ///
+    /// See [`filter`](crate::output::filter) for the syntax.
+    #[clap(long)]
+    pub filter: Option<Filter>,
+
+    /// Names param keys and types their values in dumped definitions, rather than dumping their ids and raw values.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    #[clap(long)]
+    pub resolve_params: bool,
+
+    /// A json object of param ids to names, used by `--resolve-params`.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    #[clap(long, value_name = "FILE", requires = "resolve_params")]
+    pub param_names: Option<PathBuf>,
+
+    /// Leaves out definitions with opcodes that are not implemented, rather than failing,
+    /// and lists those opcodes in `unknown_opcodes.json`.
+    #[clap(long)]
+    pub capture_unknown_opcodes: bool,
+
+    #[clap(skip)]
+    unknown_opcodes: UnknownOpcodes,
+
+    /// Keeps what was decoded of definitions that fail to decode, rather than failing,
+    /// with where decoding stopped in their `_error` field.
+    #[clap(long)]
+    pub recover: bool,
+
+    /// The revision of the game the cache is from, which decides how configs are decoded.
+    /// Guessed from the cache if not given; see [`Config::revision`].
+    #[clap(value_enum, long)]
+    pub revision: Option<Revision>,
+
+    #[clap(skip)]
+    detected_revision: OnceLock<Revision>,
+
     /// Checks whether the cache is in a consistent state.
     /// Indices 14, 40, 54, 55 are not necessarily complete.
     #[clap(long)]
     pub assert_coherence: bool,
+
+    /// Decodes every supported definition type and renders a couple of mapsquares,
+    /// reporting which of them work with this cache.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[clap(long)]
+    pub selftest: bool,
 }
 
 impl Config {
+    /// Where output is written to, as selected by `--output`.
+    pub fn sink(&self) -> CacheResult<&Sink> {
+        if let Some(sink) = self.sink.get() {
+            return Ok(sink);
+        }
+        let sink = Sink::new(&self.output)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let sink = match self.tile_naming() {
+            naming if naming.is_identity() => sink,
+            naming => Sink::with_naming(sink, naming),
+        };
+        #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+        let sink = match self.output_format {
+            OutputFormat::Mbtiles => Sink::with_mbtiles(sink, self.tile_format)?,
+            OutputFormat::Files => sink,
+        };
+        Ok(self.sink.get_or_init(|| sink))
+    }
+
+    /// How rendered tiles are named, as selected by `--tile-template` and `--tile-scheme`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn tile_naming(&self) -> naming::TileNaming {
+        naming::TileNaming {
+            template: self.tile_template.clone(),
+            scheme: self.tile_scheme,
+            extension: self.tile_format.extension(),
+            map_id: self.render_config.map_id,
+        }
+    }
+
+    /// Whether `--filter` lets through the definition with this id.
+    ///
+    /// For dumps that are not json, which can only be filtered by id. Other filters are rejected for them
+    /// before dumping, so they let everything through here.
+    pub fn keeps(&self, id: u32) -> bool {
+        self.filter.as_ref().and_then(|filter| filter.matches_id(id)) != Some(false)
+    }
+
+    /// The revision of the game the cache is from, as given by `--revision`.
+    ///
+    /// Otherwise, shimmed builds guess it from when the archives of the cache were built,
+    /// even if that is older than the shim they were built with, and other builds use the only revision they can decode.
+    pub fn revision(&self) -> Revision {
+        *self
+            .detected_revision
+            .get_or_init(|| self.revision.unwrap_or_else(|| Revision::detect(self)))
+    }
+
+    /// Passes `result` through, unless `--capture-unknown-opcodes` was passed and it failed on an unknown opcode.
+    /// Then the opcode is recorded under `what`, and `None` is returned so the definition can be left out.
+    pub fn capture<T>(&self, what: &'static str, result: Result<T, ReadError>) -> Option<Result<T, ReadError>> {
+        if self.capture_unknown_opcodes {
+            self.unknown_opcodes.capture(what, result)
+        } else {
+            Some(result)
+        }
+    }
+
+    /// Returns the unknown opcodes that [`Config::capture`] recorded so far, and clears them.
+    pub fn take_unknown_opcodes(&self) -> Vec<UnknownOpcode> {
+        self.unknown_opcodes.take()
+    }
+
+    /// A config that reads the cache at `input`, with the same render settings as `self`.
+    ///
+    /// Everything else is left at its default, including the revision, which is guessed anew.
+    pub fn for_cache(&self, input: CachePath) -> Self {
+        Self {
+            input,
+            #[cfg(not(target_arch = "wasm32"))]
+            render_config: self.render_config,
+            ..Default::default()
+        }
+    }
+
     #[cfg(not(feature = "mockdata"))]
     pub fn env() -> Self {
         Self {
@@ -227,3 +1113,91 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::panic::Location;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn capture() {
+        let unknown = || {
+            Err::<(), _>(ReadError::WithInfo {
+                source: Box::new(ReadError::OpcodeNotImplemented {
+                    opcode: 200,
+                    location: Location::caller(),
+                }),
+                #[cfg(debug_assertions)]
+                opcodes: Vec::new(),
+                buffer: Bytes::new(),
+                #[cfg(debug_assertions)]
+                thing: String::new(),
+            })
+        };
+
+        let mut config = Config::default();
+        assert!(config.capture("things", unknown()).unwrap().is_err());
+        assert!(config.take_unknown_opcodes().is_empty());
+
+        config.capture_unknown_opcodes = true;
+        assert!(config.capture("things", unknown()).is_none());
+        assert_eq!(config.take_unknown_opcodes().len(), 1);
+    }
+
+    #[test]
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+    fn previous() {
+        let error = Config::try_parse_from(["rs3cache", "--dump", "sprites", "sprites_diff"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+
+        assert!(Config::try_parse_from(["rs3cache", "--dump", "sprites_diff", "--previous", "old"]).is_ok());
+        assert!(Config::try_parse_from(["rs3cache", "--dump", "all"]).is_ok());
+    }
+
+    #[test]
+    fn accepts() {
+        assert!(Dump::Tiles.accepts(Format::Csv));
+        assert!(Dump::Tiles.accepts(Format::Npy));
+        assert!(!Dump::Tiles.accepts(Format::Json));
+        assert!(!Dump::ItemConfigs.accepts(Format::Npy));
+        assert!(Dump::ItemConfigs.accepts(Format::Csv));
+        assert!(!Dump::ItemConfigs.accepts(Format::Obj));
+        assert!(!Dump::ItemConfigs.accepts(Format::Geojson));
+        assert!(Dump::Locations.accepts(Format::Geojson));
+        assert!(!Dump::Locations.accepts(Format::Gltf));
+    }
+
+    #[test]
+    #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+    fn accepts_meshes() {
+        assert!(Dump::Models.accepts(Format::Obj));
+        assert!(Dump::Models.accepts(Format::Gltf));
+        assert!(!Dump::Models.accepts(Format::Csv));
+        assert!(Dump::Terrain3d.accepts(Format::Gltf));
+        assert!(!Dump::Terrain3d.accepts(Format::Obj));
+    }
+
+    #[test]
+    #[cfg(feature = "2008_3_shim")]
+    fn year_month() {
+        assert_eq!(super::year_month(0), (1970, 1));
+        // 2009-01-01 00:00:00
+        assert_eq!(super::year_month(1_230_768_000), (2009, 1));
+        // 2008-12-31 23:59:59
+        assert_eq!(super::year_month(1_230_767_999), (2008, 12));
+        // 2012-02-29 12:00:00
+        assert_eq!(super::year_month(1_330_516_800), (2012, 2));
+    }
+
+    #[test]
+    #[cfg(feature = "2008_3_shim")]
+    fn at() {
+        let (year, month) = super::year_month(1_230_768_000);
+        assert_eq!(Revision::at(year, month), Revision::R2009_1);
+        assert_eq!(Revision::at(2008, 6), Revision::R2008_3);
+        assert_eq!(Revision::at(2013, 5), Revision::R2013_4);
+    }
+}
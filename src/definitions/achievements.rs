@@ -1,19 +1,12 @@
 //! Describes the properties of Achievements.
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-    iter,
-};
+use std::{collections::BTreeMap, iter};
 
-use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
     buf::{BufExtra, JString},
-    error::{self, CacheResult},
+    error::CacheResult,
     index::CacheIndex,
 };
 use serde::Serialize;
@@ -462,16 +455,9 @@ impl Achievement {
 
 /// Save the Achievement configs as `Achievement>.json`. Exposed as `--dump Achievement`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut achievement_configs = Achievement::dump_all(config)?.into_values().collect::<Vec<_>>();
     achievement_configs.sort_unstable_by_key(|loc| loc.id);
-
-    let path = path!(config.output / "achievements.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&achievement_configs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export(config, "achievements", &achievement_configs)
 }
 #[cfg(test)]
 mod tests {
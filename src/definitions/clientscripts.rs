@@ -0,0 +1,254 @@
+//! Disassembles client scripts (cs2), the bytecode that drives interfaces.
+//!
+//! A script is a list of instructions, each an opcode with one operand, followed by a footer:
+//!
+//! ```text
+//! instruction count: i32
+//! int locals, string locals, int arguments, string arguments: u16
+//! switch tables: u8 count, then per table a u16 count of (i32 key, i32 offset) pairs
+//! length of the switch tables: u16
+//! ```
+//!
+//! Only the opcodes that control flow or touch variables are named; the others are printed by number.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, Eof, JString, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+};
+use serde::Serialize;
+
+use crate::definitions::indextype::IndexType;
+
+const SCONST: u16 = 3;
+const RETURN: u16 = 21;
+const POP_INT: u16 = 38;
+const POP_STRING: u16 = 39;
+const SWITCH: u16 = 60;
+
+/// Opcodes that jump by their operand.
+const BRANCHES: [u16; 7] = [6, 7, 8, 9, 10, 31, 32];
+
+/// The names of the opcodes that control flow or touch variables.
+fn name(opcode: u16) -> Option<&'static str> {
+    let name = match opcode {
+        0 => "iconst",
+        1 => "get_varp",
+        2 => "set_varp",
+        3 => "sconst",
+        6 => "jump",
+        7 => "if_icmpne",
+        8 => "if_icmpeq",
+        9 => "if_icmplt",
+        10 => "if_icmpgt",
+        21 => "return",
+        25 => "get_varbit",
+        27 => "set_varbit",
+        31 => "if_icmple",
+        32 => "if_icmpge",
+        33 => "iload",
+        34 => "istore",
+        35 => "sload",
+        36 => "sstore",
+        37 => "join_string",
+        38 => "pop_int",
+        39 => "pop_string",
+        40 => "invoke",
+        42 => "get_varc_int",
+        43 => "set_varc_int",
+        44 => "define_array",
+        45 => "get_array_int",
+        46 => "set_array_int",
+        47 => "get_varc_string_old",
+        48 => "set_varc_string_old",
+        49 => "get_varc_string",
+        50 => "set_varc_string",
+        60 => "switch",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A client script.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ClientScript {
+    /// Its id.
+    pub id: u32,
+    /// Its name, which only few scripts have.
+    pub name: Option<JString<Bytes>>,
+    pub int_locals: u16,
+    pub string_locals: u16,
+    pub int_arguments: u16,
+    pub string_arguments: u16,
+    /// Maps the keys of each switch table to how far to jump.
+    pub switches: Vec<BTreeMap<i32, i32>>,
+    pub instructions: Vec<Instruction>,
+}
+
+/// An opcode and its operand.
+#[derive(Serialize, Clone, Debug)]
+pub struct Instruction {
+    pub opcode: u16,
+    pub operand: Operand,
+}
+
+/// The operand of an [`Instruction`]. Only `sconst` has a string operand.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Operand {
+    Int(i32),
+    String(JString<Bytes>),
+}
+
+impl ClientScript {
+    /// Returns a mapping of all [`ClientScript`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        CacheIndex::new(IndexType::SCRIPTS, config.input.clone())?
+            .into_iter()
+            .map(|archive| {
+                let archive = archive?;
+                let id = archive.archive_id();
+                let file = archive.take_files().into_values().next().unwrap_or_default();
+                let script = Self::deserialize(id, file).context(error::Read { what: "clientscripts" })?;
+                Ok((id, script))
+            })
+            .collect()
+    }
+
+    pub fn deserialize(id: u32, buffer: Bytes) -> Result<Self, ReadError> {
+        let len = buffer.len();
+        if len < 2 {
+            return Err(Eof::new(2, len));
+        }
+        let switch_len = (&buffer[len - 2..]).get_u16() as usize;
+        let Some(end) = len.checked_sub(2 + switch_len + 12) else {
+            return Err(Eof::new(2 + switch_len + 12, len));
+        };
+
+        let mut footer = buffer.slice(end..len - 2);
+        let count = footer.try_get_i32()?;
+        let mut script = Self {
+            id,
+            int_locals: footer.try_get_u16()?,
+            string_locals: footer.try_get_u16()?,
+            int_arguments: footer.try_get_u16()?,
+            string_arguments: footer.try_get_u16()?,
+            ..Default::default()
+        };
+
+        let tables = footer.try_get_u8()?;
+        for _ in 0..tables {
+            let cases = footer.try_get_u16()?;
+            let table = (0..cases)
+                .map(|_| try { (footer.try_get_i32()?, footer.try_get_i32()?) })
+                .collect::<Result<_, ReadError>>()?;
+            script.switches.push(table);
+        }
+
+        let mut code = buffer.slice(..end);
+        if code.first() == Some(&0) {
+            code.advance(1);
+        } else {
+            script.name = Some(code.try_get_string()?);
+        }
+
+        script.instructions.reserve(count.max(0) as usize);
+        while code.has_remaining() {
+            let opcode = code.try_get_u16()?;
+            let operand = match opcode {
+                SCONST => Operand::String(code.try_get_string()?),
+                RETURN | POP_INT | POP_STRING => Operand::Int(code.try_get_u8()? as i32),
+                opcode if opcode < 100 => Operand::Int(code.try_get_i32()?),
+                _ => Operand::Int(code.try_get_u8()? as i32),
+            };
+            script.instructions.push(Instruction { opcode, operand });
+        }
+        Ok(script)
+    }
+
+    /// Formats the script as readable assembly, with the targets of jumps spelled out.
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+        match &self.name {
+            Some(name) => writeln!(out, "; script {} ({})", self.id, &**name).unwrap(),
+            None => writeln!(out, "; script {}", self.id).unwrap(),
+        }
+        writeln!(
+            out,
+            "; {} int and {} string arguments, {} int and {} string locals",
+            self.int_arguments, self.string_arguments, self.int_locals, self.string_locals
+        )
+        .unwrap();
+
+        for (pc, Instruction { opcode, operand }) in self.instructions.iter().enumerate() {
+            let mnemonic = name(*opcode).map_or_else(|| format!("op_{opcode}"), str::to_string);
+            write!(out, "{pc:>5}: {mnemonic:<20}").unwrap();
+            match operand {
+                Operand::String(s) => write!(out, " {:?}", &**s).unwrap(),
+                Operand::Int(i) => write!(out, " {i}").unwrap(),
+            }
+            match (*opcode, operand) {
+                (opcode, Operand::Int(offset)) if BRANCHES.contains(&opcode) => write!(out, " ; -> {}", pc as i32 + offset + 1).unwrap(),
+                (SWITCH, Operand::Int(table)) => {
+                    for (key, offset) in self.switches.get(*table as usize).into_iter().flatten() {
+                        write!(out, "\n{:>27}{key} -> {}", "", pc as i32 + offset + 1).unwrap();
+                    }
+                }
+                _ => {}
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Saves the disassembly of every script as `clientscripts/<id>.cs2`, and all of them as `clientscripts.json`.
+/// Exposed as `--dump clientscripts`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let scripts = ClientScript::dump_all(config)?.into_values().collect::<Vec<_>>();
+    for script in &scripts {
+        config
+            .sink()?
+            .put(format!("clientscripts/{}.cs2", script.id), script.disassemble().as_bytes())?;
+    }
+    crate::output::export(config, "clientscripts", &scripts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassemble() {
+        #[rustfmt::skip]
+        let buffer = Bytes::from_static(&[
+            // no name
+            0,
+            // iconst 5, jump 0, return
+            0, 0, 0, 0, 0, 5,
+            0, 6, 0, 0, 0, 0,
+            0, 21, 0,
+            // three instructions, locals and arguments
+            0, 0, 0, 3, 0, 1, 0, 0, 0, 2, 0, 0,
+            // no switch tables
+            0,
+            0, 1,
+        ]);
+        let script = ClientScript::deserialize(42, buffer).unwrap();
+
+        assert_eq!(script.int_locals, 1);
+        assert_eq!(script.int_arguments, 2);
+        assert_eq!(script.instructions.len(), 3);
+        assert_eq!(script.instructions[0].operand, Operand::Int(5));
+
+        let text = script.disassemble();
+        assert!(text.starts_with("; script 42\n"));
+        assert!(text.contains("jump"));
+        assert!(text.contains("; -> 2"));
+    }
+}
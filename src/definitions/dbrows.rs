@@ -4,9 +4,10 @@ use std::collections::BTreeMap;
 use ::error::Context;
 use bytes::{Buf, Bytes};
 use rs3cache_backend::{
-    buf::{BufExtra, NotExhausted, ReadError, WithInfo},
+    buf::{BufExtra, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
 use serde::Serialize;
 
@@ -20,7 +21,11 @@ pub struct DbRow {
     pub id: u32,
     pub unknown_1: Option<bool>,
     pub content_type: Option<u8>,
+    /// The values of each column, indexed by column id. Columns this row does not set are empty.
     pub data: Option<Vec<Vec<Value>>>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl DbRow {
@@ -32,11 +37,12 @@ impl DbRow {
             .into_iter();
 
         files
-            .map(|(file_id, file)| try { (file_id, DbRow::deserialize(file_id, file)?) })
+            .map(|(file_id, file)| try { (file_id, DbRow::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("dbrows", result))
             .collect::<Result<BTreeMap<u32, DbRow>, ReadError>>()
             .context(error::Read { what: "dbrows" })
     }
-    fn deserialize(id: u32, mut buffer: Bytes) -> Result<Self, ReadError> {
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
         let mut obj = Self { id, ..Default::default() };
 
         #[cfg(debug_assertions)]
@@ -60,34 +66,21 @@ impl DbRow {
                     1 => obj.unknown_1 = Some(true),
                     3 => {
                         let size = buffer.try_get_u8()?;
-                        let _objects = vec![Value::Null; size as usize];
+                        let mut columns = vec![Vec::new(); size as usize];
                         loop {
                             match buffer.try_get_u8()? {
                                 255 => break,
-                                _index => {
+                                index => {
                                     let amount = buffer.try_get_u8()? as usize;
                                     let types: Vec<_> = std::iter::repeat_with(|| buffer.get_smarts()).take(amount).collect();
-                                    let count = buffer.get_smarts() as usize;
-
-                                    let _subobjects = vec![Value::Null; count * amount];
-                                    for _c in 0..count {
-                                        for (_pos, ty) in types.iter().enumerate() {
-                                            match ty {
-                                                35 => {
-                                                    buffer.try_get_array::<8>()?;
-                                                }
-                                                36 => {
-                                                    buffer.try_get_string()?;
-                                                }
-                                                _ => {
-                                                    buffer.try_get_i32()?;
-                                                }
-                                            }
-                                        }
+                                    let values = decode_values(&mut buffer, &types)?;
+                                    if let Some(column) = columns.get_mut(index as usize) {
+                                        *column = values;
                                     }
                                 }
                             }
                         }
+                        obj.data = Some(columns);
                     }
                     4 => obj.content_type = Some(buffer.try_get_u8()?),
                     opcode => Err(ReadError::OpcodeNotImplemented {
@@ -102,13 +95,17 @@ impl DbRow {
                     opcodes.push(opcode);
                 }
                 Err(e) => {
-                    return Err(e).map_err(Box::new).context(WithInfo {
-                        #[cfg(debug_assertions)]
-                        opcodes,
-                        buffer,
-                        #[cfg(debug_assertions)]
-                        thing: obj.to_string(),
-                    })
+                    obj.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &obj,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(obj);
                 }
             }
         }
@@ -118,10 +115,28 @@ impl DbRow {
 #[derive(Debug, Serialize, Clone)]
 pub enum Value {
     Integer(i32),
+    Long(i64),
     Text(String),
     Null,
 }
 
+/// Reads the values of a column, which consists of a number of fields that each have a value of every type in `types`.
+pub(crate) fn decode_values(buffer: &mut Bytes, types: &[u32]) -> Result<Vec<Value>, ReadError> {
+    let count = buffer.get_smarts() as usize;
+    let mut values = Vec::with_capacity(count * types.len());
+    for _ in 0..count {
+        for ty in types {
+            let value = match ty {
+                35 => Value::Long(i64::from_be_bytes(buffer.try_get_array::<8>()?)),
+                36 => Value::Text(buffer.try_get_string()?.to_string()),
+                _ => Value::Integer(buffer.try_get_i32()?),
+            };
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
 use std::fmt::{self, Display, Formatter};
 
 impl Display for DbRow {
@@ -130,6 +145,12 @@ impl Display for DbRow {
     }
 }
 
+/// Save the dbrows as `dbrows.json`. Exposed as `--dump dbrows`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let rows = DbRow::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "dbrows", &rows)
+}
+
 #[cfg(all(test, feature = "rs3"))]
 mod tests {
     use super::*;
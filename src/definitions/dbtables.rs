@@ -0,0 +1,154 @@
+//! Describes the columns of dbtables, whose rows are [`DbRow`](crate::definitions::dbrows::DbRow)s.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use serde::Serialize;
+
+use crate::definitions::{
+    dbrows::{decode_values, Value},
+    indextype::{ConfigType, IndexType},
+};
+
+#[cfg_attr(feature = "pyo3", pyo3::pyclass)]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DbTable {
+    /// Its id.
+    pub id: u32,
+    /// The columns of this table, by column id.
+    pub columns: Option<BTreeMap<u8, Column>>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+/// A column of a [`DbTable`].
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Column {
+    /// The script types of each value in a field of this column.
+    pub types: Vec<u32>,
+    /// The values of rows that do not set this column.
+    pub default: Option<Vec<Value>>,
+}
+
+impl DbTable {
+    /// Returns a mapping of all [`DbTable`] configurations.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, DbTable>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::DBTABLE)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, DbTable::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("dbtables", result))
+            .collect::<Result<BTreeMap<u32, DbTable>, ReadError>>()
+            .context(error::Read { what: "dbtables" })
+    }
+
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut obj = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(obj);
+                        }
+                    }
+                    1 => {
+                        let _size = buffer.try_get_u8()?;
+                        let mut columns = BTreeMap::new();
+                        loop {
+                            match buffer.try_get_u8()? {
+                                255 => break,
+                                setting => {
+                                    let amount = buffer.try_get_u8()? as usize;
+                                    let types: Vec<_> = std::iter::repeat_with(|| buffer.get_smarts()).take(amount).collect();
+                                    let default = if setting & 0x80 != 0 {
+                                        Some(decode_values(&mut buffer, &types)?)
+                                    } else {
+                                        None
+                                    };
+                                    columns.insert(setting & 0x7F, Column { types, default });
+                                }
+                            }
+                        }
+                        obj.columns = Some(columns);
+                    }
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    obj.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &obj,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(obj);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for DbTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+/// Save the dbtables as `dbtables.json`. Exposed as `--dump dbtables`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let tables = DbTable::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "dbtables", &tables)
+}
+
+#[cfg(all(test, feature = "rs3"))]
+mod tests {
+    use super::*;
+    use crate::cli::Config;
+
+    #[test]
+    fn dump_dbtables() -> CacheResult<()> {
+        let config = Config::env();
+
+        let tables = DbTable::dump_all(&config)?;
+        assert!(tables.values().any(|table| table.columns.is_some()));
+
+        Ok(())
+    }
+}
@@ -1,27 +1,26 @@
 //! Describes the properties of enums.
 
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-    iter,
-};
+use core::panic::Location;
+use std::{collections::BTreeMap, iter};
 
 use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
-    buf::{BufExtra, JString},
+    buf::{BufExtra, JString, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
+use schemars::JsonSchema;
 use serde::Serialize;
 
+#[cfg(feature = "osrs")]
+use crate::definitions::indextype::ConfigType;
 use crate::definitions::indextype::IndexType;
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, JsonSchema, Clone, Copy)]
 pub enum KeyType {
     Uninit = -1,
     Int0 = 0,
@@ -74,6 +73,7 @@ impl TryFrom<u8> for KeyType {
             73 => Ok(Self::Int73),
             74 => Ok(Self::Int74),
             105 => Ok(Self::Int105),
+            115 => Ok(Self::Int115),
             126 => Ok(Self::Int126),
             128 => Ok(Self::Int128),
             other => Err(format!("Unknown keytype discriminant {other}")),
@@ -93,7 +93,7 @@ impl Default for KeyType {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, JsonSchema, Clone, Copy)]
 pub enum ValueType {
     Uninit = -1,
     Int0 = 0,
@@ -198,7 +198,7 @@ impl Default for ValueType {
     }
 }
 
-#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+#[derive(Serialize, JsonSchema, Debug, Eq, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum Value {
     /// The integer variant.
@@ -221,7 +221,7 @@ impl IntoPy<Py<PyAny>> for Value {
 
 #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct Enum {
     /// Its id.
     pub id: u32,
@@ -233,10 +233,14 @@ pub struct Enum {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     pub variants: BTreeMap<i32, Value>,
     pub default: Option<Value>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl Enum {
     /// Returns a mapping of all [`Enum`]s.
+    #[cfg(any(feature = "rs3", feature = "legacy"))]
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
         let archives = CacheIndex::new(IndexType::ENUM_CONFIG, config.input.clone())?.into_iter();
 
@@ -249,56 +253,119 @@ impl Enum {
                     .into_iter()
                     .map(move |(file_id, file)| (archive_id << 8 | file_id, file))
             })
-            .map(|(id, file)| (id, Self::deserialize(id, file)))
-            .collect::<BTreeMap<u32, Self>>();
+            .map(|(id, file)| try { (id, Self::deserialize(id, file, config.recover)?) })
+            .filter_map(|result| config.capture("enums", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "enums" })?;
         Ok(enums)
     }
 
-    pub fn deserialize(id: u32, mut buffer: Bytes) -> Self {
+    /// Returns a mapping of all [`Enum`]s.
+    #[cfg(feature = "osrs")]
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::ENUM_CONFIG)?
+            .take_files()
+            .into_iter()
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("enums", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "enums" })
+    }
+
+    /// What kind of definition the values refer to, if they are references.
+    ///
+    /// The kinds are named as in [`TypedParam::Reference`](crate::structures::paramtable::TypedParam::Reference).
+    pub fn value_kind(&self) -> Option<&'static str> {
+        let kind = match self.value_type {
+            ValueType::Int23 => "graphic",
+            ValueType::Int26 => "enum",
+            ValueType::Int30 => "location",
+            ValueType::Int31 => "model",
+            ValueType::Int32 => "npc",
+            ValueType::Int33 => "item",
+            ValueType::Int37 => "spotanim",
+            ValueType::Int39 => "inventory",
+            ValueType::Int73 => "struct",
+            ValueType::Int74 => "dbrow",
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    pub fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
         let mut r#enum = Self { id, ..Default::default() };
 
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
         loop {
-            match buffer.get_u8() {
-                0 => {
-                    debug_assert!(!buffer.has_remaining());
+            let opcode = buffer.try_get_u8()?;
 
-                    break r#enum;
-                }
-                1 => r#enum.key_type = buffer.get_u8().try_into().unwrap(),
-                2 => r#enum.value_type = buffer.get_u8().try_into().unwrap(),
-                101 => r#enum.key_type = buffer.get_u8().try_into().unwrap(),
-                102 => r#enum.value_type = buffer.get_u8().try_into().unwrap(),
-                3 => r#enum.default = Some(Value::String(buffer.get_string())),
-                4 => r#enum.default = Some(Value::Integer(buffer.get_i32())),
-                5 => {
-                    let count = buffer.get_u16() as usize;
-                    r#enum.variants = iter::repeat_with(|| (buffer.get_i32(), Value::String(buffer.get_string())))
-                        .take(count)
-                        .collect();
-                }
-                6 => {
-                    let count = buffer.get_u16() as usize;
-                    r#enum.variants = iter::repeat_with(|| (buffer.get_i32(), Value::Integer(buffer.get_i32())))
-                        .take(count)
-                        .collect();
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(r#enum);
+                        }
+                    }
+                    1 | 101 => r#enum.key_type = buffer.try_get_u8()?.try_into().unwrap(),
+                    2 | 102 => r#enum.value_type = buffer.try_get_u8()?.try_into().unwrap(),
+                    3 => r#enum.default = Some(Value::String(buffer.try_get_string()?)),
+                    4 => r#enum.default = Some(Value::Integer(buffer.try_get_i32()?)),
+                    5 => {
+                        let count = buffer.try_get_u16()? as usize;
+                        r#enum.variants = iter::repeat_with(|| try { (buffer.try_get_i32()?, Value::String(buffer.try_get_string()?)) })
+                            .take(count)
+                            .collect::<Result<_, ReadError>>()?;
+                    }
+                    6 => {
+                        let count = buffer.try_get_u16()? as usize;
+                        r#enum.variants = iter::repeat_with(|| try { (buffer.try_get_i32()?, Value::Integer(buffer.try_get_i32()?)) })
+                            .take(count)
+                            .collect::<Result<_, ReadError>>()?;
+                    }
+                    7 => {
+                        let _max = buffer.try_get_u16()?;
+                        let count = buffer.try_get_u16()? as usize;
+                        r#enum.variants = iter::repeat_with(|| try { (buffer.try_get_u16()? as i32, Value::String(buffer.try_get_string()?)) })
+                            .take(count)
+                            .collect::<Result<_, ReadError>>()?;
+                    }
+                    8 => {
+                        let _max = buffer.try_get_u16()?;
+                        let count = buffer.try_get_u16()? as usize;
+                        r#enum.variants = iter::repeat_with(|| try { (buffer.try_get_u16()? as i32, Value::Integer(buffer.try_get_i32()?)) })
+                            .take(count)
+                            .collect::<Result<_, ReadError>>()?;
+                    }
+                    131 => r#enum.unknown_131 = Some(true),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
                 }
-                7 => {
-                    let _max = buffer.get_u16();
-                    let count = buffer.get_u16() as usize;
-                    r#enum.variants = iter::repeat_with(|| (buffer.get_u16() as i32, Value::String(buffer.get_string())))
-                        .take(count)
-                        .collect();
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
                 }
-                8 => {
-                    let _max = buffer.get_u16();
-                    let count = buffer.get_u16() as usize;
-                    r#enum.variants = iter::repeat_with(|| (buffer.get_u16() as i32, Value::Integer(buffer.get_i32())))
-                        .take(count)
-                        .collect();
+                Err(e) => {
+                    r#enum.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &r#enum,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(r#enum);
                 }
-                131 => r#enum.unknown_131 = Some(true),
-
-                missing => unimplemented!("Enum::deserialize cannot deserialize opcode {} in id {}", missing, id),
             }
         }
     }
@@ -326,14 +393,7 @@ impl Enum {
 
 /// Save the item configs as `enums.json`. Exposed as `--dump enums`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut enums = Enum::dump_all(config)?.into_values().collect::<Vec<_>>();
     enums.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(config.output / "enums.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-
-    let data = serde_json::to_string_pretty(&enums).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export(config, "enums", &enums)
 }
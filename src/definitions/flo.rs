@@ -1,17 +1,11 @@
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-};
+use std::collections::BTreeMap;
 
-use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
     buf::{BufExtra, JString},
-    error::{self, CacheResult},
+    error::CacheResult,
     index::CacheIndex,
 };
 use serde::Serialize;
@@ -87,15 +81,9 @@ impl Display for Flo {
 
 ///Save the maplabels as `maplabels.json`. Exposed as `--dump maplabels`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut labels = Flo::dump_all(config)?.into_values().collect::<Vec<_>>();
     labels.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(&config.output / "flos.json");
-
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&labels).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-    Ok(())
+    crate::output::export(config, "flos", &labels)
 }
 
 #[cfg(all(test, feature = "legacy"))]
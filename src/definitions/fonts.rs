@@ -0,0 +1,309 @@
+//! Bitmap fonts, such as `p12_full`, which the client draws its text in.
+//!
+//! A font is a sprite with a frame per character of Windows-1252, named after the font.
+//! The file of the same name or id in the font metrics index has how far every character moves the pen;
+//! fonts without one move it past the right edge of each glyph.
+//!
+//! Only caches whose sprites are named have fonts that can be found.
+
+use std::{collections::BTreeMap, panic::Location};
+
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use ::error::Context;
+use bytes::Bytes;
+use image::{imageops, RgbaImage};
+use rs3cache_backend::{
+    buf::{BufExtra, ReadError},
+    cp1252,
+};
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use rs3cache_backend::{
+    error::{CacheError, CacheResult, Read},
+    hash::hash_djb2,
+    index::CacheIndex,
+};
+use serde::Serialize;
+
+use crate::definitions::sprites::{self, Sprite};
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::{cli::Config, definitions::indextype::IndexType};
+
+/// The fonts that `--dump fonts` exports. Not every cache has all of them.
+pub const NAMES: &[&str] = &["p11_full", "p12_full", "b12_full", "q8_full"];
+
+/// The font that map labels are drawn in.
+pub const LABELS: &str = "p12_full";
+
+/// The image of a character, and where it goes relative to the pen.
+#[derive(Clone, Debug)]
+pub struct Glyph {
+    /// How far right of the pen the image starts.
+    pub offset_x: u16,
+    /// How far below the top of the line the image starts.
+    pub offset_y: u16,
+    pub image: Sprite,
+}
+
+/// A bitmap font, with a glyph per character that has one.
+#[derive(Clone, Debug)]
+pub struct Font {
+    pub name: String,
+    /// The height of a line of text, in pixels.
+    pub line_height: u16,
+    /// How far every character moves the pen, by its code.
+    pub advances: Vec<u16>,
+    pub glyphs: BTreeMap<u8, Glyph>,
+}
+
+/// Where a glyph is in the atlas of a font, as listed in `fonts/<name>.json`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct GlyphMetrics {
+    /// The code of the character in Windows-1252.
+    pub code: u8,
+    /// The character, or `None` for the codes that Windows-1252 leaves undefined.
+    pub character: Option<char>,
+    pub advance: u16,
+    /// The left of the glyph in the atlas, in pixels.
+    pub x: u32,
+    /// The top of the glyph in the atlas, in pixels.
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub offset_x: u16,
+    pub offset_y: u16,
+}
+
+/// The metrics of a font, as written to `fonts/<name>.json`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Metrics {
+    pub name: String,
+    pub line_height: u16,
+    pub glyphs: Vec<GlyphMetrics>,
+}
+
+impl Font {
+    /// Decodes the font `name` from its sprite and, if the cache has them, its metrics.
+    pub fn decode(name: impl Into<String>, sprite: Bytes, metrics: Option<Bytes>) -> Result<Self, ReadError> {
+        let ((_, line_height), frames, mut images) = sprites::deserialize_with_info(sprite)?;
+        let glyphs = frames
+            .iter()
+            .filter_map(|frame| {
+                let code = u8::try_from(frame.frame).ok()?;
+                let glyph = Glyph {
+                    offset_x: frame.offset_x,
+                    offset_y: frame.offset_y,
+                    image: images.remove(&frame.frame)?,
+                };
+                Some((code, glyph))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let advances = match metrics {
+            Some(metrics) => advances(metrics)?,
+            None => {
+                let mut advances = vec![0; 256];
+                for (&code, glyph) in &glyphs {
+                    advances[code as usize] = glyph.offset_x + glyph.image.width() as u16 + 1;
+                }
+                // The space has no glyph.
+                advances[b' ' as usize] = (line_height / 3).max(1);
+                advances
+            }
+        };
+
+        Ok(Self {
+            name: name.into(),
+            line_height,
+            advances,
+            glyphs,
+        })
+    }
+
+    /// Reads the font named `name`, such as `p12_full`, or `None` if the cache has no sprite of that name.
+    ///
+    /// Its metrics are read from the font metrics index, by name or else by the id of its sprite, if the cache has that index.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub fn find(config: &Config, name: &str) -> CacheResult<Option<Self>> {
+        let hash = hash_djb2(name);
+        let sprite_index = CacheIndex::new(IndexType::SPRITES, config.input.clone())?;
+        let sprite_id = sprite_index
+            .metadatas()
+            .iter()
+            .find(|(_, meta)| meta.name() == Some(hash))
+            .map(|(_, meta)| meta.archive_id());
+        let Some(sprite_id) = sprite_id else { return Ok(None) };
+        let Some(sprite) = sprite_index.archive(sprite_id)?.file(&0) else { return Ok(None) };
+
+        let metrics = match CacheIndex::new(IndexType::FONTMETRICS, config.input.clone()) {
+            Ok(index) => {
+                let archive_id = index
+                    .metadatas()
+                    .iter()
+                    .find(|(_, meta)| meta.name() == Some(hash))
+                    .or_else(|| index.metadatas().iter().find(|(_, meta)| meta.archive_id() == sprite_id))
+                    .map(|(_, meta)| meta.archive_id());
+                match archive_id {
+                    Some(archive_id) => index.archive(archive_id)?.file(&0),
+                    None => None,
+                }
+            }
+            // Older caches have no font metrics index.
+            Err(CacheError::CannotOpen { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        Self::decode(name, sprite, metrics).context(Read { what: "font" }).map(Some)
+    }
+
+    /// The code of `c` in the font, if it is in Windows-1252.
+    pub fn code(c: char) -> Option<u8> {
+        cp1252::encode(c)
+    }
+
+    /// How far `c` moves the pen, which is 0 for characters that the font does not have.
+    pub fn advance(&self, c: char) -> u16 {
+        Self::code(c).map_or(0, |code| self.advances[code as usize])
+    }
+
+    /// The glyph of `c`, if it has one.
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&Self::code(c)?)
+    }
+
+    /// Lays out every glyph in a grid of sixteen by sixteen cells, one per code, and lists where they are.
+    pub fn atlas(&self) -> (RgbaImage, Metrics) {
+        let cell_width = self.glyphs.values().map(|glyph| glyph.image.width()).max().unwrap_or(0) + 1;
+        let cell_height = self.glyphs.values().map(|glyph| glyph.image.height()).max().unwrap_or(0) + 1;
+        let mut img = RgbaImage::new(cell_width * 16, cell_height * 16);
+
+        let glyphs = (0..=255_u8)
+            .filter(|&code| self.advances[code as usize] != 0 || self.glyphs.contains_key(&code))
+            .map(|code| {
+                let (x, y) = ((code % 16) as u32 * cell_width, (code / 16) as u32 * cell_height);
+                let glyph = self.glyphs.get(&code);
+                if let Some(glyph) = glyph {
+                    imageops::replace(&mut img, &glyph.image, x as i64, y as i64);
+                }
+                GlyphMetrics {
+                    code,
+                    character: cp1252::decode(code),
+                    advance: self.advances[code as usize],
+                    x,
+                    y,
+                    width: glyph.map_or(0, |glyph| glyph.image.width()),
+                    height: glyph.map_or(0, |glyph| glyph.image.height()),
+                    offset_x: glyph.map_or(0, |glyph| glyph.offset_x),
+                    offset_y: glyph.map_or(0, |glyph| glyph.offset_y),
+                }
+            })
+            .collect();
+
+        let metrics = Metrics {
+            name: self.name.clone(),
+            line_height: self.line_height,
+            glyphs,
+        };
+        (img, metrics)
+    }
+}
+
+/// Reads how far every character moves the pen from the font metrics of a font.
+///
+/// Old school has the advance of every character, followed by the ascent.
+/// Runescape 3 has a version and whether there are kerning tables before the advances, and those tables after them,
+/// which are not used here.
+fn advances(mut metrics: Bytes) -> Result<Vec<u16>, ReadError> {
+    if metrics.len() == 257 {
+        return Ok(metrics[..256].iter().map(|&advance| advance as u16).collect());
+    }
+    let version = metrics.try_get_u8()?;
+    if version != 0 {
+        return Err(ReadError::VersionNotImplemented {
+            version,
+            location: Location::caller(),
+        });
+    }
+    let _kerning = metrics.try_get_u8()?;
+    let advances = metrics.try_get_array::<256>()?;
+    Ok(advances.iter().map(|&advance| advance as u16).collect())
+}
+
+/// Saves the atlas of every font in [`NAMES`] as `fonts/<name>.png`, with its metrics in `fonts/<name>.json`.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub fn export(config: &Config) -> CacheResult<()> {
+    for name in NAMES {
+        // Fonts were added over time.
+        let Some(font) = Font::find(config, name)? else { continue };
+        let (img, metrics) = font.atlas();
+        crate::renderers::png::put(config, format!("fonts/{name}.png"), &img)?;
+        crate::output::put_json(config, format!("fonts/{name}.json"), &metrics)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn font() -> Font {
+        let glyph = Glyph {
+            offset_x: 1,
+            offset_y: 2,
+            image: RgbaImage::from_pixel(3, 5, Rgba([255, 255, 255, 255])),
+        };
+        let mut advances = vec![0; 256];
+        advances[b'A' as usize] = 5;
+        advances[b' ' as usize] = 3;
+        advances[0x80] = 7;
+        Font {
+            name: "test".to_string(),
+            line_height: 8,
+            advances,
+            glyphs: BTreeMap::from([(b'A', glyph)]),
+        }
+    }
+
+    #[test]
+    fn characters() {
+        let font = font();
+        assert_eq!(font.advance('A'), 5);
+        assert_eq!(font.advance('é'), 0);
+        // The euro sign is 0x80 in Windows-1252, not U+0080.
+        assert_eq!(Font::code('€'), Some(0x80));
+        assert_eq!(font.advance('€'), 7);
+        assert_eq!(font.advance('\u{80}'), 0);
+        assert!(font.glyph('A').is_some());
+        assert!(font.glyph(' ').is_none());
+    }
+
+    #[test]
+    fn atlas() {
+        let (img, metrics) = font().atlas();
+        assert_eq!(img.dimensions(), (4 * 16, 6 * 16));
+        assert_eq!(metrics.glyphs.len(), 3);
+        let glyph = metrics.glyphs.iter().find(|glyph| glyph.character == Some('A')).unwrap();
+        assert_eq!((glyph.x, glyph.y, glyph.width, glyph.height), (4, 6 * 4, 3, 5));
+        assert_eq!(img.get_pixel(glyph.x, glyph.y)[3], 255);
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn metrics() {
+        let mut old = vec![4; 256];
+        old.push(10);
+        assert_eq!(advances(Bytes::from(old)).unwrap(), vec![4; 256]);
+
+        // Runescape 3's, with kerning tables after the advances.
+        let mut new = vec![0, 1];
+        new.extend([5; 256]);
+        new.extend([0; 512]);
+        assert_eq!(advances(Bytes::from(new)).unwrap(), vec![5; 256]);
+
+        assert!(matches!(
+            advances(Bytes::from_static(&[1, 0])),
+            Err(ReadError::VersionNotImplemented { version: 1, .. })
+        ));
+        assert!(matches!(advances(Bytes::from_static(&[0, 0, 5])), Err(ReadError::Eof { .. })));
+    }
+}
@@ -0,0 +1,303 @@
+//! The frames of animations, which pose [`Model`]s by moving the groups of vertices that their skins put them in.
+//!
+//! A frame is a list of transforms of its [`Skeleton`], which says what kind each transform is and which skins it moves.
+//! Transforms of the transparency of faces are skipped, as models do not decode the skins of faces.
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, Eof, ReadError},
+    error::{self, CacheResult},
+    index::{self, CacheIndex, Initial},
+};
+
+use crate::definitions::models::{self, Model};
+
+/// Sets the point that later rotations and scalings are around, to the middle of the skins plus the delta.
+pub const ORIGIN: u8 = 0;
+/// Moves the skins by the delta.
+pub const TRANSLATE: u8 = 1;
+/// Rotates the skins around the origin, by the delta in 256ths of a turn around the z, x and then y axis.
+pub const ROTATE: u8 = 2;
+/// Scales the skins from the origin, by the delta in 128ths.
+pub const SCALE: u8 = 3;
+
+/// The kinds of transforms that a frame can do, and the skins that each moves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Skeleton {
+    /// The kind of every transform, such as [`ROTATE`].
+    pub kinds: Vec<u8>,
+    /// The skins that every transform moves.
+    pub skins: Vec<Vec<u8>>,
+}
+
+impl Skeleton {
+    /// Decodes a skeleton.
+    pub fn deserialize(mut buffer: Bytes) -> Result<Self, ReadError> {
+        let count = buffer.try_get_u8()? as usize;
+        let kinds = (0..count).map(|_| buffer.try_get_u8()).collect::<Result<Vec<_>, _>>()?;
+        let lengths = (0..count).map(|_| buffer.try_get_u8()).collect::<Result<Vec<_>, _>>()?;
+        let skins = lengths
+            .into_iter()
+            .map(|length| (0..length).map(|_| buffer.try_get_u8()).collect())
+            .collect::<Result<Vec<Vec<_>>, _>>()?;
+        Ok(Self { kinds, skins })
+    }
+}
+
+/// One step of a [`Frame`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transform {
+    /// What it does, such as [`ROTATE`].
+    pub kind: u8,
+    /// The skins it moves.
+    pub skins: Vec<u8>,
+    pub delta: [i32; 3],
+}
+
+/// A pose of a model.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub transforms: Vec<Transform>,
+}
+
+impl Frame {
+    /// Decodes a frame of `skeleton`.
+    pub fn deserialize(data: Bytes, skeleton: &Skeleton) -> Result<Self, ReadError> {
+        let mut flags = data.clone();
+        let _skeleton_id = flags.try_get_u16()?;
+        let count = flags.try_get_u8()? as usize;
+        let mut deltas = data
+            .get(3 + count..)
+            .map(|_| data.slice(3 + count..))
+            .ok_or_else(|| Eof::new(3 + count, data.len()))?;
+
+        let mut transforms = Vec::new();
+        let mut last = None;
+        for i in 0..count {
+            let flag = flags.try_get_u8()?;
+            if flag == 0 {
+                continue;
+            }
+            // Frames made for a later version of their skeleton can have transforms that it does not.
+            let Some(&kind) = skeleton.kinds.get(i) else { break };
+            // Transforms other than setting the origin are around the last origin of the skeleton before them,
+            // even if this frame leaves it where it is.
+            if kind != ORIGIN {
+                let origin = (last.map_or(0, |last| last + 1)..i).rev().find(|&j| skeleton.kinds[j] == ORIGIN);
+                if let Some(origin) = origin {
+                    transforms.push(Transform {
+                        kind: ORIGIN,
+                        skins: skeleton.skins[origin].clone(),
+                        delta: [0; 3],
+                    });
+                }
+            }
+            let default = if kind == SCALE { 128 } else { 0 };
+            let mut delta = [default; 3];
+            for (axis, value) in delta.iter_mut().enumerate() {
+                if flag & (1 << axis) != 0 {
+                    *value = models::try_get_signed_smart(&mut deltas)?;
+                }
+            }
+            transforms.push(Transform {
+                kind,
+                skins: skeleton.skins[i].clone(),
+                delta,
+            });
+            last = Some(i);
+        }
+        Ok(Self { transforms })
+    }
+
+    /// Reads the frame `frame_id` from the `frames` index, and its skeleton from the `skeletons` index.
+    ///
+    /// Frame ids are the archive of the frame in their upper 16 bits and its file in their lower 16 bits.
+    pub fn get(frames: &CacheIndex<Initial>, skeletons: &CacheIndex<Initial>, frame_id: u32) -> CacheResult<Self> {
+        let (archive_id, file_id) = (frame_id >> 16, frame_id & 0xFFFF);
+        let data = frames
+            .archive(archive_id)?
+            .file(&file_id)
+            .context(index::FileMissing {
+                index_id: frames.index_id(),
+                archive_id,
+                file: file_id,
+            })
+            .context(error::Integrity)?;
+        let skeleton_id = data.clone().try_get_u16().context(error::Read { what: "frame" })? as u32;
+        let skeleton = skeletons
+            .archive(skeleton_id)?
+            .file(&0)
+            .context(index::FileMissing {
+                index_id: skeletons.index_id(),
+                archive_id: skeleton_id,
+                file: 0_u32,
+            })
+            .context(error::Integrity)?;
+        let skeleton = Skeleton::deserialize(skeleton).context(error::Read { what: "skeleton" })?;
+        Self::deserialize(data, &skeleton).context(error::Read { what: "frame" })
+    }
+
+    /// Poses `model`. Models without vertex skins are left as they are.
+    pub fn apply(&self, model: &mut Model) {
+        let Some(vertex_skins) = &model.vertex_skins else { return };
+        let mut origin = [0; 3];
+        for transform in &self.transforms {
+            let moved = vertex_skins
+                .iter()
+                .enumerate()
+                .filter(|(_, skin)| transform.skins.contains(skin))
+                .map(|(vertex, _)| vertex)
+                .collect::<Vec<_>>();
+            let [dx, dy, dz] = transform.delta;
+            match transform.kind {
+                ORIGIN => {
+                    let mut sum = [0; 3];
+                    for &vertex in &moved {
+                        for (axis, total) in sum.iter_mut().enumerate() {
+                            *total += model.vertices[vertex][axis];
+                        }
+                    }
+                    let count = (moved.len() as i32).max(1);
+                    origin = [sum[0] / count + dx, sum[1] / count + dy, sum[2] / count + dz];
+                }
+                TRANSLATE => {
+                    for &vertex in &moved {
+                        let [x, y, z] = &mut model.vertices[vertex];
+                        *x += dx;
+                        *y += dy;
+                        *z += dz;
+                    }
+                }
+                ROTATE => {
+                    for &vertex in &moved {
+                        let [mut x, mut y, mut z] = model.vertices[vertex];
+                        x -= origin[0];
+                        y -= origin[1];
+                        z -= origin[2];
+                        let (roll, pitch, yaw) = ((dz & 0xFF) * 8, (dx & 0xFF) * 8, (dy & 0xFF) * 8);
+                        if roll != 0 {
+                            let (sin, cos) = (sine(roll), sine(roll + 512));
+                            (x, y) = ((y * sin + x * cos) >> 16, (y * cos - x * sin) >> 16);
+                        }
+                        if pitch != 0 {
+                            let (sin, cos) = (sine(pitch), sine(pitch + 512));
+                            (y, z) = ((y * cos - z * sin) >> 16, (y * sin + z * cos) >> 16);
+                        }
+                        if yaw != 0 {
+                            let (sin, cos) = (sine(yaw), sine(yaw + 512));
+                            (x, z) = ((z * sin + x * cos) >> 16, (z * cos - x * sin) >> 16);
+                        }
+                        model.vertices[vertex] = [x + origin[0], y + origin[1], z + origin[2]];
+                    }
+                }
+                SCALE => {
+                    for &vertex in &moved {
+                        for (axis, scale) in [dx, dy, dz].into_iter().enumerate() {
+                            let value = &mut model.vertices[vertex][axis];
+                            *value = (*value - origin[axis]) * scale / 128 + origin[axis];
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The sine of `angle` in 2048ths of a turn, in 65536ths, as the game has them in a table.
+fn sine(angle: i32) -> i32 {
+    (65536.0 * ((angle % 2048) as f64 * 0.003_067_961_5).sin()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skeleton() -> Skeleton {
+        Skeleton {
+            kinds: vec![ORIGIN, TRANSLATE, ROTATE],
+            skins: vec![vec![1], vec![1], vec![1]],
+        }
+    }
+
+    #[test]
+    fn skeletons() {
+        #[rustfmt::skip]
+        let data = Bytes::from_static(&[
+            3,
+            ORIGIN, TRANSLATE, ROTATE,
+            1, 1, 2,
+            1, 1, 1, 2,
+        ]);
+        let skeleton = Skeleton::deserialize(data).unwrap();
+        assert_eq!(skeleton.kinds, vec![ORIGIN, TRANSLATE, ROTATE]);
+        assert_eq!(skeleton.skins, vec![vec![1], vec![1], vec![1, 2]]);
+    }
+
+    #[test]
+    fn frames() {
+        // Moves skin 1 up by 10 and turns it a quarter around y, around the origin of the skeleton before them.
+        #[rustfmt::skip]
+        let data = Bytes::from_static(&[
+            0, 7,
+            3,
+            0, 0b010, 0b010,
+            0x40 - 10, 0xC0, 64,
+        ]);
+        let frame = Frame::deserialize(data, &skeleton()).unwrap();
+        assert_eq!(
+            frame.transforms,
+            vec![
+                Transform {
+                    kind: ORIGIN,
+                    skins: vec![1],
+                    delta: [0, 0, 0]
+                },
+                Transform {
+                    kind: TRANSLATE,
+                    skins: vec![1],
+                    delta: [0, -10, 0]
+                },
+                Transform {
+                    kind: ROTATE,
+                    skins: vec![1],
+                    delta: [0, 64, 0]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn posing() {
+        let mut model = Model {
+            vertices: vec![[100, 0, 0], [-100, 0, 0], [0, 0, 0]],
+            vertex_skins: Some(vec![1, 1, 2]),
+            ..Model::default()
+        };
+        let frame = Frame {
+            transforms: vec![
+                Transform {
+                    kind: ORIGIN,
+                    skins: vec![1],
+                    delta: [0, 0, 0],
+                },
+                Transform {
+                    kind: TRANSLATE,
+                    skins: vec![1],
+                    delta: [0, -10, 0],
+                },
+                Transform {
+                    kind: ROTATE,
+                    skins: vec![1],
+                    delta: [0, 64, 0],
+                },
+            ],
+        };
+        frame.apply(&mut model);
+        // A quarter turn around y takes +x to -z, about the middle of the skin, and leaves other skins where they are.
+        let [x, y, z] = model.vertices[0];
+        assert!(x.abs() <= 1 && y == -10 && (z + 100).abs() <= 1, "{:?}", model.vertices[0]);
+        assert_eq!(model.vertices[2], [0, 0, 0]);
+    }
+}
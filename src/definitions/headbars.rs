@@ -0,0 +1,168 @@
+//! Describes the health bars shown above entities.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use serde::Serialize;
+
+use crate::definitions::indextype::{ConfigType, IndexType};
+
+/// Describes a headbar, also known as a health bar.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct HeadBar {
+    /// Its id.
+    pub id: u32,
+    pub unknown_1: Option<u16>,
+    pub unknown_2: Option<u8>,
+    pub unknown_3: Option<u8>,
+    pub unknown_4: Option<bool>,
+    /// How long it is shown after the entity was last hit, in client cycles.
+    pub duration: Option<u16>,
+    pub unknown_6: Option<u8>,
+    /// The sprite of the remaining health.
+    pub front_sprite: Option<u32>,
+    /// The sprite of the missing health.
+    pub back_sprite: Option<u32>,
+    /// How long it takes to fade out, in client cycles.
+    pub fade_out_duration: Option<u16>,
+    /// The width of the bar, in pixels.
+    pub width: Option<u8>,
+    pub padding: Option<u8>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+impl HeadBar {
+    /// Returns a mapping of all [`HeadBar`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::HEADBAR)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("headbars", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "headbars" })
+    }
+
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut headbar = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(headbar);
+                        }
+                    }
+                    1 => headbar.unknown_1 = Some(buffer.try_get_u16()?),
+                    2 => headbar.unknown_2 = Some(buffer.try_get_u8()?),
+                    3 => headbar.unknown_3 = Some(buffer.try_get_u8()?),
+                    4 => headbar.unknown_4 = Some(true),
+                    5 => headbar.duration = Some(buffer.try_get_u16()?),
+                    6 => headbar.unknown_6 = Some(buffer.try_get_u8()?),
+                    7 => headbar.front_sprite = buffer.try_get_smart32()?,
+                    8 => headbar.back_sprite = buffer.try_get_smart32()?,
+                    11 => headbar.fade_out_duration = Some(buffer.try_get_u16()?),
+                    14 => headbar.width = Some(buffer.try_get_u8()?),
+                    15 => headbar.padding = Some(buffer.try_get_u8()?),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    headbar.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &headbar,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(headbar);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for HeadBar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl HeadBar {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("HeadBar({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("HeadBar({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the headbars as `headbars.json`. Exposed as `--dump headbars`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let headbars = HeadBar::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "headbars", &headbars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites() {
+        let buffer = Bytes::from_static(&[1, 0, 100, 4, 5, 0, 30, 7, 0x01, 0x2C, 8, 0x7F, 0xFF, 14, 30, 0]);
+        let headbar = HeadBar::deserialize(2, buffer, false).unwrap();
+
+        assert_eq!(headbar.unknown_1, Some(100));
+        assert_eq!(headbar.unknown_4, Some(true));
+        assert_eq!(headbar.duration, Some(30));
+        assert_eq!((headbar.front_sprite, headbar.back_sprite), (Some(300), None));
+        assert_eq!(headbar.width, Some(30));
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let error = HeadBar::deserialize(2, Bytes::from_static(&[200, 0]), false).unwrap_err();
+        assert!(matches!(error, ReadError::WithInfo { source, .. } if matches!(*source, ReadError::OpcodeNotImplemented { opcode: 200, .. })));
+    }
+}
@@ -0,0 +1,197 @@
+//! Describes the numbers and icons shown when an entity takes damage.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, JString, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use serde::Serialize;
+
+use crate::definitions::indextype::{ConfigType, IndexType};
+
+/// Describes a hitmark, also known as a hitsplat.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct HitMark {
+    /// Its id.
+    pub id: u32,
+    /// The font the damage is written in.
+    pub font: Option<u32>,
+    pub text_colour: Option<u32>,
+    pub left_sprite: Option<u32>,
+    pub left_sprite_2: Option<u32>,
+    pub background_sprite: Option<u32>,
+    pub right_sprite: Option<u32>,
+    pub scroll_to_offset_x: Option<i16>,
+    /// The text that is shown, where `%1` is replaced by the damage.
+    pub format: Option<JString<Bytes>>,
+    /// How long it is shown, in client cycles.
+    pub display_cycles: Option<u16>,
+    pub scroll_to_offset_y: Option<i16>,
+    /// The cycle at which it starts fading out.
+    pub fade_start_cycle: Option<u16>,
+    pub use_damage: Option<u8>,
+    pub text_offset_y: Option<i16>,
+    pub varbit: Option<u16>,
+    pub varp: Option<u16>,
+    /// Hitmarks to use instead, selected by the value of [`varbit`](HitMark::varbit) or [`varp`](HitMark::varp).
+    pub multi_hitmarks: Option<Vec<Option<u16>>>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+impl HitMark {
+    /// Returns a mapping of all [`HitMark`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::HITSPLATS)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("hitmarks", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "hitmarks" })
+    }
+
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut hitmark = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(hitmark);
+                        }
+                    }
+                    1 => hitmark.font = buffer.try_get_smart32()?,
+                    2 => hitmark.text_colour = Some(buffer.try_get_uint(3)? as u32),
+                    3 => hitmark.left_sprite = buffer.try_get_smart32()?,
+                    4 => hitmark.left_sprite_2 = buffer.try_get_smart32()?,
+                    5 => hitmark.background_sprite = buffer.try_get_smart32()?,
+                    6 => hitmark.right_sprite = buffer.try_get_smart32()?,
+                    7 => hitmark.scroll_to_offset_x = Some(buffer.try_get_u16()? as i16),
+                    8 => hitmark.format = Some(buffer.try_get_string()?),
+                    9 => hitmark.display_cycles = Some(buffer.try_get_u16()?),
+                    10 => hitmark.scroll_to_offset_y = Some(buffer.try_get_u16()? as i16),
+                    11 => hitmark.fade_start_cycle = Some(0),
+                    12 => hitmark.use_damage = Some(buffer.try_get_u8()?),
+                    13 => hitmark.text_offset_y = Some(buffer.try_get_u16()? as i16),
+                    14 => hitmark.fade_start_cycle = Some(buffer.try_get_u16()?),
+                    17 | 18 => {
+                        let varbit = buffer.try_get_u16()?;
+                        let varp = buffer.try_get_u16()?;
+                        hitmark.varbit = (varbit != u16::MAX).then_some(varbit);
+                        hitmark.varp = (varp != u16::MAX).then_some(varp);
+
+                        let default = if opcode == 18 { Some(buffer.try_get_u16()?) } else { None };
+
+                        let count = buffer.try_get_u8()? as usize;
+                        let mut multi_hitmarks = (0..=count)
+                            .map(|_| buffer.try_get_u16().map(|id| (id != u16::MAX).then_some(id)))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        if let Some(default) = default {
+                            multi_hitmarks.push((default != u16::MAX).then_some(default));
+                        }
+                        hitmark.multi_hitmarks = Some(multi_hitmarks);
+                    }
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    hitmark.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &hitmark,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(hitmark);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for HitMark {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl HitMark {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("HitMark({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("HitMark({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the hitmarks as `hitmarks.json`. Exposed as `--dump hitmarks`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let hitmarks = HitMark::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "hitmarks", &hitmarks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_hitmarks() {
+        let buffer = Bytes::from_static(b"\x01\x01\xf4\x02\xff\x00\x00\x08%1\x00\x12\xff\xff\x00\x05\x00\x03\x01\x00\x0a\xff\xff\x00");
+        let hitmark = HitMark::deserialize(4, buffer, false).unwrap();
+
+        assert_eq!(hitmark.font, Some(500));
+        assert_eq!(hitmark.text_colour, Some(0xFF0000));
+        assert_eq!(&**hitmark.format.as_ref().unwrap(), "%1");
+        assert_eq!((hitmark.varbit, hitmark.varp), (None, Some(5)));
+        assert_eq!(hitmark.multi_hitmarks, Some(vec![Some(10), None, Some(3)]));
+    }
+
+    #[test]
+    fn recover() {
+        let hitmark = HitMark::deserialize(4, Bytes::from_static(&[1, 0x7F, 0xFF, 200, 0]), true).unwrap();
+        assert_eq!(hitmark.font, None);
+        assert_eq!(
+            hitmark.decode_error.as_deref(),
+            Some("stopped at opcode 200 with 1 bytes left: opcode 200 is not implemented")
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! The huffman code that chat messages and some other strings are compressed with.
+//!
+//! The `huffman` archive only stores the length of the code of each byte.
+//! The codes themselves are handed out in byte order, each being the first unused code of its length.
+
+use std::panic::Location;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, Eof, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+};
+
+use crate::definitions::indextype::IndexType;
+
+/// Compresses and decompresses strings.
+#[derive(Clone, Debug)]
+pub struct Huffman {
+    /// The code of each byte, aligned to the most significant bit.
+    masks: Vec<u32>,
+    /// The length of the code of each byte, or 0 if it cannot be encoded.
+    bits: Vec<u8>,
+    /// The decoding tree. Following a `0` goes to the next node, following a `1` goes to the node this stores.
+    /// Leaves store the complement of their byte.
+    keys: Vec<i32>,
+}
+
+impl Huffman {
+    /// Loads the huffman code from the `huffman` archive of the binary index.
+    pub fn load(config: &crate::cli::Config) -> CacheResult<Self> {
+        let data = CacheIndex::new(IndexType::BINARY, config.input.clone())?.archive_by_name("huffman".to_string())?;
+        Self::new(data.to_vec()).context(error::Read { what: "huffman" })
+    }
+
+    /// Makes the code from the length of the code of each byte.
+    ///
+    /// Fails if a code is longer than 32 bits, or if the lengths do not make a prefix code,
+    /// in which case a code would run into the code of another byte.
+    pub fn new(bits: Vec<u8>) -> Result<Self, ReadError> {
+        let mut masks = vec![0; bits.len()];
+        // The first unused code of every length.
+        let mut next = [0_u32; 33];
+        let mut keys = vec![0_i32; 8];
+        let mut free = 0;
+
+        for (byte, &len) in bits.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let invalid = || ReadError::InvalidHuffmanCode {
+                byte: byte as u8,
+                len,
+                location: Location::caller(),
+            };
+            if len > 32 {
+                return Err(invalid());
+            }
+            let len = len as usize;
+            let last_bit = 1_u32 << (32 - len);
+            let code = next[len];
+            masks[byte] = code;
+
+            // Take the code, and make sure that no shorter or longer code can be a prefix of it.
+            let after = if code & last_bit != 0 {
+                next[len - 1]
+            } else {
+                for shorter in (1..len).rev() {
+                    let other = next[shorter];
+                    if other != code {
+                        break;
+                    }
+                    let shorter_bit = 1_u32 << (32 - shorter);
+                    if other & shorter_bit != 0 {
+                        next[shorter] = next[shorter - 1];
+                        break;
+                    }
+                    next[shorter] = other | shorter_bit;
+                }
+                code | last_bit
+            };
+            next[len] = after;
+            for longer in len + 1..=32 {
+                if next[longer] == code {
+                    next[longer] = after;
+                }
+            }
+
+            let mut node = 0;
+            for i in 0..len {
+                // Passing through a leaf means the code of another byte is a prefix of this one.
+                if keys[node] < 0 {
+                    return Err(invalid());
+                }
+                if code & (0x8000_0000 >> i) != 0 {
+                    if keys[node] == 0 {
+                        keys[node] = free;
+                    }
+                    node = keys[node] as usize;
+                } else {
+                    node += 1;
+                }
+                if node >= keys.len() {
+                    keys.resize((node + 1).max(keys.len() * 2), 0);
+                }
+            }
+            if keys[node] != 0 {
+                return Err(invalid());
+            }
+            keys[node] = !(byte as i32);
+            free = free.max(node as i32 + 1);
+        }
+
+        Ok(Self { masks, bits, keys })
+    }
+
+    /// Decompresses `len` bytes from `data`.
+    pub fn decompress(&self, data: &[u8], len: usize) -> Result<Vec<u8>, ReadError> {
+        self.decode(data, len).map(|(out, _)| out)
+    }
+
+    /// Decompresses `len` bytes from `data`, returning them along with how many bytes of `data` were used.
+    fn decode(&self, data: &[u8], len: usize) -> Result<(Vec<u8>, usize), ReadError> {
+        let mut out = Vec::with_capacity(len);
+        if len == 0 {
+            return Ok((out, 0));
+        }
+
+        let mut node = 0;
+        for (used, byte) in data.iter().enumerate() {
+            for shift in (0..8).rev() {
+                if byte >> shift & 1 == 1 {
+                    node = self.key(node)? as usize;
+                } else {
+                    node += 1;
+                }
+
+                let key = self.key(node)?;
+                if key < 0 {
+                    out.push(!key as u8);
+                    if out.len() == len {
+                        return Ok((out, used + 1));
+                    }
+                    node = 0;
+                }
+            }
+        }
+        Err(Eof::new(1, 0))
+    }
+
+    fn key(&self, node: usize) -> Result<i32, ReadError> {
+        self.keys.get(node).copied().ok_or_else(|| Eof::new(node + 1, self.keys.len()))
+    }
+
+    /// Compresses `data`, or returns `None` if it contains a byte that cannot be encoded.
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut position = 0;
+
+        for &byte in data {
+            let len = *self.bits.get(byte as usize).filter(|&&len| len != 0)?;
+            let code = self.masks[byte as usize];
+            for i in 0..len {
+                if position % 8 == 0 {
+                    out.push(0);
+                }
+                if code & (0x8000_0000 >> i) != 0 {
+                    *out.last_mut().unwrap() |= 0x80 >> (position % 8);
+                }
+                position += 1;
+            }
+        }
+        Some(out)
+    }
+
+    /// Reads a chat message: its length as a smart, followed by the compressed message.
+    pub fn read_message(&self, buffer: &mut Bytes) -> Result<String, ReadError> {
+        let len = buffer.try_get_unsigned_smart()? as usize;
+        let (message, used) = self.decode(buffer.chunk(), len)?;
+        buffer.advance(used);
+        Ok(message.into_iter().map(char::from).collect())
+    }
+
+    /// Writes a chat message as read by [`read_message`](Huffman::read_message),
+    /// or returns `None` if it contains a character that cannot be encoded.
+    pub fn write_message(&self, message: &str) -> Option<Vec<u8>> {
+        let data = message.chars().map(|c| u8::try_from(c).ok()).collect::<Option<Vec<u8>>>()?;
+        let mut out = match data.len() {
+            len @ 0..=0x7F => vec![len as u8],
+            len @ 0x80..=0x7FFF => (len as u16 | 0x8000).to_be_bytes().to_vec(),
+            _ => return None,
+        };
+        out.extend(self.compress(&data)?);
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `a` is `0`, `b` is `10` and `c` is `11`.
+    fn abc() -> Huffman {
+        let mut bits = vec![0; 256];
+        bits[b'a' as usize] = 1;
+        bits[b'b' as usize] = 2;
+        bits[b'c' as usize] = 2;
+        Huffman::new(bits).unwrap()
+    }
+
+    #[test]
+    fn codes() {
+        let huffman = abc();
+        assert_eq!(huffman.compress(b"abcab"), Some(vec![0b0101_1010]));
+        assert_eq!(huffman.decompress(&[0b0101_1010, 0b1000_0000], 5).unwrap(), b"abcab");
+        assert_eq!(huffman.compress(b"d"), None);
+    }
+
+    #[test]
+    fn messages() {
+        let huffman = abc();
+        assert_eq!(huffman.write_message("cabbage"), None);
+
+        let mut data = Bytes::from(huffman.write_message("cab").unwrap());
+        assert_eq!(huffman.read_message(&mut data).unwrap(), "cab");
+        assert!(!data.has_remaining());
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        let mut bits = vec![0; 256];
+        bits[b'a' as usize] = 33;
+        assert!(matches!(
+            Huffman::new(bits),
+            Err(ReadError::InvalidHuffmanCode { byte: b'a', len: 33, .. })
+        ));
+
+        // Three codes of one bit cannot all be told apart.
+        let mut bits = vec![0; 256];
+        bits[b'a' as usize] = 1;
+        bits[b'b' as usize] = 1;
+        bits[b'c' as usize] = 1;
+        assert!(Huffman::new(bits).is_err());
+
+        // Every length up to 32 fits.
+        let bits = (1..=32).chain([32]).collect::<Vec<u8>>();
+        let huffman = Huffman::new(bits).unwrap();
+        let data = (0..33).collect::<Vec<u8>>();
+        assert_eq!(huffman.decompress(&huffman.compress(&data).unwrap(), data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn truncated() {
+        let huffman = abc();
+        // Asks for more than the data holds.
+        assert!(huffman.decompress(&[0b0101_1010], 9).is_err());
+        let mut data = Bytes::from_static(&[5]);
+        assert!(huffman.read_message(&mut data).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! The body parts players can choose from when designing their character.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use serde::Serialize;
+
+use crate::definitions::{
+    indextype::{ConfigType, IndexType},
+    npc_configs::npc_config_fields::{ColourReplacements, Textures},
+};
+
+/// Describes an identity kit: one option for a body part of the player's appearance.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct IdentityKit {
+    /// Its id.
+    pub id: u32,
+    /// Which body part this is, such as the hair (0) or the jaw (1) of male characters.
+    pub body_part: Option<u8>,
+    /// The models that make up the body part.
+    pub models: Option<Vec<u32>>,
+    /// Whether it cannot be chosen in the character designer.
+    pub non_selectable: Option<bool>,
+    pub colour_replacements: Option<ColourReplacements>,
+    pub texture_replacements: Option<Textures>,
+    /// The models used for chatheads.
+    pub head_models: Option<BTreeMap<u8, u32>>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+impl IdentityKit {
+    /// Returns a mapping of all [`IdentityKit`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::IDENTITY_KIT)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("identity kits", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "identity kits" })
+    }
+
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut kit = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(kit);
+                        }
+                    }
+                    1 => kit.body_part = Some(buffer.try_get_u8()?),
+                    2 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        let models = (0..count).map(|_| Self::model(&mut buffer)).collect::<Result<Vec<_>, _>>()?;
+                        kit.models = Some(models.into_iter().flatten().collect());
+                    }
+                    3 => kit.non_selectable = Some(true),
+                    40 => kit.colour_replacements = Some(ColourReplacements::deserialize(&mut buffer)?),
+                    41 => kit.texture_replacements = Some(Textures::deserialize(&mut buffer)?),
+                    60..=69 => {
+                        if let Some(model) = Self::model(&mut buffer)? {
+                            kit.head_models.get_or_insert_default().insert(opcode - 60, model);
+                        }
+                    }
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    kit.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &kit,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(kit);
+                }
+            }
+        }
+    }
+
+    /// Reads a model id; missing models are skipped.
+    #[cfg(feature = "rs3")]
+    fn model(buffer: &mut Bytes) -> Result<Option<u32>, ReadError> {
+        buffer.try_get_smart32()
+    }
+
+    /// Reads a model id; missing models are skipped.
+    #[cfg(not(feature = "rs3"))]
+    fn model(buffer: &mut Bytes) -> Result<Option<u32>, ReadError> {
+        Ok(Some(buffer.try_get_u16()? as u32))
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for IdentityKit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl IdentityKit {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("IdentityKit({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("IdentityKit({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the identity kits as `identkits.json`. Exposed as `--dump identkits`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let kits = IdentityKit::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "identkits", &kits)
+}
+
+#[cfg(all(test, feature = "osrs"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let buffer = Bytes::from_static(&[1, 7, 2, 2, 0x01, 0x00, 0x01, 0x01, 3, 60, 0x02, 0x00, 0]);
+        let kit = IdentityKit::deserialize(5, buffer, false).unwrap();
+
+        assert_eq!(kit.body_part, Some(7));
+        assert_eq!(kit.models, Some(vec![256, 257]));
+        assert_eq!(kit.non_selectable, Some(true));
+        assert_eq!(kit.head_models, Some(BTreeMap::from([(0, 512)])));
+    }
+}
@@ -90,15 +90,7 @@ impl <Name> {
 
 /// Save the <Name> configs as `<Name>>.json`. Exposed as `--dump <Name>`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io {file: config.output.clone()})?;
     let mut <Name>_configs = <Name>::dump_all(config)?.into_values().collect::<Vec<_>>();
     <Name>_configs.sort_unstable_by_key(|loc| loc.id);
-
-    let path = path!(config.output / "<Name>_configs.json");
-
-    let mut file = File::create(&path).with_context(|| error::Io{ file: path.clone()})?;
-    let data = serde_json::to_string_pretty(&<Name>_configs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io {file: path})?;
-
-    Ok(())
+    crate::output::export(config, "<Name>_configs", &<Name>_configs)
 }
@@ -4,14 +4,23 @@
 pub struct IndexType;
 
 impl IndexType {
-    /// Unimplemented.
+    /// Contains the [`Frame`](crate::definitions::frames::Frame)s of animations.
+    #[cfg(feature = "osrs")]
+    pub const FRAMES: u32 = 0;
+    /// Contains the skeletons that the frames of animations are made for.
     pub const BASES: u32 = 1;
     /// Contains various smaller [`ConfigType`] definitions.
     pub const CONFIG: u32 = 2;
-    /// Unimplemented.
+    /// Contains the components of interfaces.
     pub const INTERFACES: u32 = 3;
+    /// Contains [`SoundEffect`](crate::definitions::sound_effects::SoundEffect) definitions.
+    #[cfg(feature = "osrs")]
+    pub const SOUND_EFFECTS: u32 = 4;
     /// Contains [`MapSquare`](../../sqlitecache/definitions/mapsquares/struct.MapSquares.html) definitions.
     pub const MAPSV2: u32 = 5;
+    /// Contains [`MusicTrack`](crate::definitions::midi::MusicTrack)s.
+    #[cfg(feature = "osrs")]
+    pub const MUSIC_TRACKS: u32 = 6;
     /// Discontinued.
     pub const MODELS: u32 = 7;
     /// Contains [`sprite`](../../sqlitecache/definitions/sprites/index.html) definitions.
@@ -21,12 +30,18 @@ impl IndexType {
     pub const TEXTURES: u32 = 9;
     /// Unimplemented.
     pub const BINARY: u32 = 10;
-    /// Contains client side scripts in a bytecode-like format (cs2). Unimplemented.
+    /// Contains jingles, which are [`MusicTrack`](crate::definitions::midi::MusicTrack)s too.
+    #[cfg(feature = "osrs")]
+    pub const MUSIC_JINGLES: u32 = 11;
+    /// Contains client side scripts in a bytecode-like format (cs2).
     pub const SCRIPTS: u32 = 12;
-    /// Unimplemented.
+    /// Contains how far every character of a [`Font`](crate::definitions::fonts::Font) moves the pen.
     pub const FONTMETRICS: u32 = 13;
     /// Unimplemented.
     pub const VORBIS: u32 = 14;
+    /// The instruments that [`MusicTrack`](crate::definitions::midi::MusicTrack)s are played with. Unimplemented.
+    #[cfg(feature = "osrs")]
+    pub const MUSIC_PATCHES: u32 = 15;
     /// Contains the [`LocationConfig`](../../sqlitecache/definitions/location_configs/struct.LocationConfig.html) definitions.
     #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     pub const LOC_CONFIG: u32 = 16;
@@ -37,9 +52,9 @@ impl IndexType {
     pub const NPC_CONFIG: u32 = 18;
     /// Unimplemented.
     pub const OBJ_CONFIG: u32 = 19;
-    /// Unimplemented.
+    /// Contains [`Sequence`](crate::definitions::sequences::Sequence) definitions.
     pub const SEQ_CONFIG: u32 = 20;
-    /// Unimplemented.
+    /// Contains [`SpotAnim`](crate::definitions::spotanims::SpotAnim) definitions.
     pub const SPOT_CONFIG: u32 = 21;
     /// Unimplemented.
     pub const STRUCT_CONFIG: u32 = 22;
@@ -47,9 +62,9 @@ impl IndexType {
     /// [`MapPastes`](../../sqlitecache/definitions/worldmaps/struct.MapPastes.html) definitions,
     /// as well as PNG images of the world map.
     pub const WORLDMAP: u32 = 23;
-    /// Unimplemented.
+    /// Contains quickchat categories and phrases.
     pub const QUICKCHAT: u32 = 24;
-    /// Unimplemented.
+    /// Contains the quickchat categories and phrases that are shared between players, such as in clan chat.
     pub const GLOBAL_QUICKCHAT: u32 = 25;
     /// Unimplemented.
     pub const MATERIALS: u32 = 26;
@@ -116,22 +131,32 @@ impl ConfigType {
     pub const UNDERLAYS: u32 = 1;
     /// Unimplemented.
     pub const HUNT: u32 = 2;
-    /// Unimplemented.
+    /// Contains [`IdentityKit`](crate::definitions::identkits::IdentityKit) definitions.
     pub const IDENTITY_KIT: u32 = 3;
     /// Contains [`Overlay`](../../sqlitecache/definitions/overlays/struct.Overlay.html) definitions.
     pub const OVERLAYS: u32 = 4;
-    /// Unimplemented.
+    /// Contains [`Inventory`](crate::definitions::inventories::Inventory) definitions.
     pub const INVENTORY: u32 = 5;
 
     #[cfg(feature = "osrs")]
     pub const LOC_CONFIG: u32 = 6;
     /// Unimplemented.
     pub const UNKNOWN_7: u32 = 7;
+    /// Contains [`Enum`](crate::definitions::enums::Enum) definitions.
+    #[cfg(feature = "osrs")]
+    pub const ENUM_CONFIG: u32 = 8;
     /// Unimplemented.
     #[cfg(feature = "osrs")]
     pub const NPC_CONFIG: u32 = 9;
 
-    pub const TOOLTIPS: u32 = 11;
+    /// Contains [`ParamConfig`](crate::definitions::params::ParamConfig) definitions.
+    pub const PARAMS: u32 = 11;
+    /// Contains [`Sequence`](crate::definitions::sequences::Sequence) definitions.
+    #[cfg(feature = "osrs")]
+    pub const SEQUENCE: u32 = 12;
+    /// Contains [`SpotAnim`](crate::definitions::spotanims::SpotAnim) definitions.
+    #[cfg(feature = "osrs")]
+    pub const SPOTANIM: u32 = 13;
     /// Unimplemented.
     pub const AREA: u32 = 18;
     /// Unimplemented.
@@ -148,30 +173,42 @@ impl ConfigType {
 
     #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
     pub const MAPLABELS: u32 = 35;
-    /// Unimplemented.
+    /// Contains [`Quest`](crate::definitions::quests::Quest) definitions.
     #[cfg(feature = "rs3")]
     pub const QUESTS: u32 = 35;
     /// Contains [`MapLabelConfig`](../../sqlitecache/definitions/maplabel_configs/struct.MapLabelConfig.html).
     #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     pub const MAPLABELS: u32 = 36;
-    /// Unimplemented.
+    /// Contains [`DbTable`](crate::definitions::dbtables::DbTable) definitions.
     pub const DBTABLE: u32 = 40;
-    /// Unimplemented.
+    /// Contains [`DbRow`](crate::definitions::dbrows::DbRow) definitions.
     pub const DBROWS: u32 = 41;
     /// Unimplemented.
     pub const UNKNOWN_42: u32 = 42;
-    /// Unimplemented.
+    /// Contains [`HitMark`](crate::definitions::hitmarks::HitMark) definitions.
+    #[cfg(not(feature = "osrs"))]
     pub const HITSPLATS: u32 = 46;
+    /// Contains [`HitMark`](crate::definitions::hitmarks::HitMark) definitions.
+    #[cfg(feature = "osrs")]
+    pub const HITSPLATS: u32 = 32;
     /// Unimplemented.
     pub const UNKNOWN_48: u32 = 48;
     /// Unimplemented.
     pub const UNKNOWN_49: u32 = 49;
-    /// Unimplemented.
+    /// Contains [`VarConfig`](crate::definitions::var_configs::VarConfig) definitions of player variables.
+    #[cfg(not(feature = "osrs"))]
     pub const PLAYER: u32 = 60;
+    /// Contains [`VarConfig`](crate::definitions::var_configs::VarConfig) definitions of player variables.
+    #[cfg(feature = "osrs")]
+    pub const PLAYER: u32 = 16;
     /// Unimplemented.
     pub const NPC: u32 = 61;
-    /// Unimplemented.
+    /// Contains [`VarConfig`](crate::definitions::var_configs::VarConfig) definitions of client variables.
+    #[cfg(not(feature = "osrs"))]
     pub const CLIENT: u32 = 62;
+    /// Contains [`VarConfig`](crate::definitions::var_configs::VarConfig) definitions of client variables.
+    #[cfg(feature = "osrs")]
+    pub const CLIENT: u32 = 19;
     /// Unimplemented.
     pub const WORLD: u32 = 63;
     /// Unimplemented.
@@ -184,12 +221,20 @@ impl ConfigType {
     pub const CLAN_SETTING: u32 = 67;
     /// Unimplemented.
     pub const CAMPAIGN: u32 = 68;
-    /// Unimplemented.
+    /// Contains [`VarbitConfig`](crate::definitions::varbit_configs::VarbitConfig) definitions.
+    #[cfg(not(feature = "osrs"))]
     pub const VARBITS: u32 = 69;
+    /// Contains [`VarbitConfig`](crate::definitions::varbit_configs::VarbitConfig) definitions.
+    #[cfg(feature = "osrs")]
+    pub const VARBITS: u32 = 14;
     /// Unimplemented.
     pub const UNKNOWN_70: u32 = 70;
-    /// Unimplemented.
+    /// Contains [`HeadBar`](crate::definitions::headbars::HeadBar) definitions.
+    #[cfg(not(feature = "osrs"))]
     pub const HEADBAR: u32 = 72;
+    /// Contains [`HeadBar`](crate::definitions::headbars::HeadBar) definitions.
+    #[cfg(feature = "osrs")]
+    pub const HEADBAR: u32 = 33;
     /// Unimplemented.
     pub const UNKNOWN_73: u32 = 73;
     /// Unimplemented.
@@ -0,0 +1,565 @@
+//! The components that make up interfaces, such as the inventory or the bank.
+//!
+//! Every interface is an archive of the interfaces index, and each of its files is a [`Component`].
+//! Components are either in the old format, IF1, or in the newer, scriptable IF3 format, which starts with `0xFF`.
+
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, JString, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+};
+use serde::Serialize;
+
+use crate::definitions::indextype::IndexType;
+
+/// The names of the script hooks of IF3 components, in the order they are stored.
+const LISTENERS: [&str; 18] = [
+    "on_load",
+    "on_mouse_over",
+    "on_mouse_leave",
+    "on_target_leave",
+    "on_target_enter",
+    "on_var_transmit",
+    "on_inv_transmit",
+    "on_stat_transmit",
+    "on_timer",
+    "on_op",
+    "on_mouse_repeat",
+    "on_click",
+    "on_click_repeat",
+    "on_release",
+    "on_hold",
+    "on_drag",
+    "on_drag_complete",
+    "on_scroll_wheel",
+];
+
+/// A part of an interface, such as a button, a line of text or a container for other components.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Component {
+    /// Its id, as `interface << 16 | component`.
+    pub id: u32,
+    /// Whether it is in the IF3 format.
+    pub if3: bool,
+    /// What kind of component this is: a layer (0), inventory (2), rectangle (3), text (4), sprite (5), model (6) or line (9).
+    pub r#type: u8,
+    /// What happens when the component is clicked, for IF1 components.
+    pub menu_type: Option<u8>,
+    pub content_type: u16,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: i16,
+    /// How `width`, `height`, `x` and `y` are interpreted; relative to the parent's size, for example.
+    pub width_mode: Option<i8>,
+    pub height_mode: Option<i8>,
+    pub x_mode: Option<i8>,
+    pub y_mode: Option<i8>,
+    /// The id of the layer this is in.
+    pub parent: Option<u32>,
+    /// The component that is shown instead when hovering over this one.
+    pub hovered_sibling: Option<u32>,
+    pub hidden: Option<bool>,
+    pub opacity: Option<u8>,
+    pub scroll_width: Option<u16>,
+    pub scroll_height: Option<u16>,
+    pub no_click_through: Option<bool>,
+    pub sprite: Option<i32>,
+    pub alternate_sprite: Option<i32>,
+    pub texture: Option<u16>,
+    pub sprite_tiling: Option<bool>,
+    pub border_type: Option<u8>,
+    pub shadow_colour: Option<i32>,
+    pub flipped_vertically: Option<bool>,
+    pub flipped_horizontally: Option<bool>,
+    /// The sprites drawn behind the first slots of an IF1 inventory.
+    pub slot_sprites: Option<Vec<SlotSprite>>,
+    /// The distance between the slots of an IF1 inventory.
+    pub padding_x: Option<i16>,
+    pub padding_y: Option<i16>,
+    pub model: Option<u16>,
+    pub alternate_model: Option<u16>,
+    pub animation: Option<u16>,
+    pub alternate_animation: Option<u16>,
+    pub model_zoom: Option<u16>,
+    pub rotation_x: Option<u16>,
+    pub rotation_y: Option<u16>,
+    pub rotation_z: Option<u16>,
+    pub offset_x_2d: Option<i16>,
+    pub offset_y_2d: Option<i16>,
+    pub orthogonal: Option<bool>,
+    pub font: Option<u16>,
+    pub text: Option<JString<Bytes>>,
+    pub alternate_text: Option<JString<Bytes>>,
+    pub line_height: Option<u8>,
+    pub text_x_alignment: Option<u8>,
+    pub text_y_alignment: Option<u8>,
+    pub text_shadowed: Option<bool>,
+    pub text_colour: Option<i32>,
+    pub alternate_text_colour: Option<i32>,
+    pub hovered_text_colour: Option<i32>,
+    pub alternate_hovered_text_colour: Option<i32>,
+    pub filled: Option<bool>,
+    pub line_width: Option<u8>,
+    pub line_direction: Option<bool>,
+    /// Which options and events are enabled, for IF3 components.
+    pub flags: Option<u32>,
+    pub name: Option<JString<Bytes>>,
+    pub actions: Option<Vec<JString<Bytes>>>,
+    pub tooltip: Option<JString<Bytes>>,
+    pub target_verb: Option<JString<Bytes>>,
+    pub spell_name: Option<JString<Bytes>>,
+    pub drag_dead_zone: Option<u8>,
+    pub drag_dead_time: Option<u8>,
+    pub drag_render_behaviour: Option<bool>,
+    /// When the alternate text, sprite or model of an IF1 component is shown.
+    pub conditions: Option<Vec<Condition>>,
+    /// The client scripts of an IF1 component, which the conditions compare against.
+    pub scripts: Option<Vec<Vec<u16>>>,
+    /// The scripts run on events, by the name of the event. The first argument is the script's id.
+    pub listeners: Option<BTreeMap<&'static str, Vec<Argument>>>,
+    pub var_transmit_triggers: Option<Vec<i32>>,
+    pub inv_transmit_triggers: Option<Vec<i32>>,
+    pub stat_transmit_triggers: Option<Vec<i32>>,
+}
+
+/// A sprite drawn behind a slot of an IF1 inventory.
+#[derive(Serialize, Clone, Debug)]
+pub struct SlotSprite {
+    pub slot: u8,
+    pub x: i16,
+    pub y: i16,
+    pub sprite: i32,
+}
+
+/// Compares the result of an IF1 client script against a value.
+#[derive(Serialize, Clone, Debug)]
+pub struct Condition {
+    pub operator: u8,
+    pub value: u16,
+}
+
+/// An argument of a script run by a listener.
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Argument {
+    Int(i32),
+    String(JString<Bytes>),
+}
+
+/// An interface, with its components nested in the layers that contain them.
+#[derive(Serialize, Clone, Debug)]
+pub struct Interface {
+    pub id: u32,
+    pub components: Vec<Node>,
+}
+
+/// A [`Component`] and the components inside it.
+#[derive(Serialize, Clone, Debug)]
+pub struct Node {
+    #[serde(flatten)]
+    pub component: Component,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Node>,
+}
+
+impl Interface {
+    /// Returns a mapping of all [`Interface`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        CacheIndex::new(IndexType::INTERFACES, config.input.clone())?
+            .into_iter()
+            .map(|archive| {
+                let archive = archive?;
+                let id = archive.archive_id();
+                let components = archive
+                    .take_files()
+                    .into_iter()
+                    .map(|(file_id, file)| Component::deserialize(id << 16 | file_id, file))
+                    .collect::<Result<Vec<_>, _>>()
+                    .context(error::Read { what: "interfaces" })?;
+                Ok((id, Self::new(id, components)))
+            })
+            .collect()
+    }
+
+    /// Nests `components` in the layers that contain them.
+    pub fn new(id: u32, components: Vec<Component>) -> Self {
+        let mut by_parent: BTreeMap<Option<u32>, Vec<Component>> = BTreeMap::new();
+        for component in components {
+            by_parent.entry(component.parent).or_default().push(component);
+        }
+
+        fn nest(parent: Option<u32>, by_parent: &mut BTreeMap<Option<u32>, Vec<Component>>) -> Vec<Node> {
+            by_parent
+                .remove(&parent)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|component| {
+                    let children = nest(Some(component.id), by_parent);
+                    Node { component, children }
+                })
+                .collect()
+        }
+
+        Self {
+            id,
+            components: nest(None, &mut by_parent),
+        }
+    }
+}
+
+impl Component {
+    /// Decodes a component in either format.
+    pub fn deserialize(id: u32, buffer: Bytes) -> Result<Self, ReadError> {
+        let mut component = Self { id, ..Default::default() };
+        if buffer.first() == Some(&0xFF) {
+            component.decode_if3(buffer)?;
+        } else {
+            component.decode_if1(buffer)?;
+        }
+        Ok(component)
+    }
+
+    /// Makes a component id from a component in the same interface, where `0xFFFF` means none.
+    fn sibling(&self, component: u16) -> Option<u32> {
+        (component != 0xFFFF).then_some(self.id & !0xFFFF | component as u32)
+    }
+
+    fn decode_if1(&mut self, mut buffer: Bytes) -> Result<(), ReadError> {
+        self.r#type = buffer.try_get_u8()?;
+        let menu_type = buffer.try_get_u8()?;
+        self.menu_type = Some(menu_type);
+        self.content_type = buffer.try_get_u16()?;
+        self.x = buffer.try_get_u16()? as i16;
+        self.y = buffer.try_get_u16()? as i16;
+        self.width = buffer.try_get_u16()?;
+        self.height = buffer.try_get_u16()? as i16;
+        self.opacity = Some(buffer.try_get_u8()?);
+        self.parent = self.sibling(buffer.try_get_u16()?);
+        self.hovered_sibling = self.sibling(buffer.try_get_u16()?);
+
+        let count = buffer.try_get_u8()?;
+        if count > 0 {
+            let conditions = (0..count)
+                .map(|_| try {
+                    Condition {
+                        operator: buffer.try_get_u8()?,
+                        value: buffer.try_get_u16()?,
+                    }
+                })
+                .collect::<Result<_, ReadError>>()?;
+            self.conditions = Some(conditions);
+        }
+
+        let count = buffer.try_get_u8()?;
+        if count > 0 {
+            let scripts = (0..count)
+                .map(|_| {
+                    let len = buffer.try_get_u16()?;
+                    (0..len).map(|_| buffer.try_get_u16()).collect::<Result<Vec<_>, _>>()
+                })
+                .collect::<Result<_, ReadError>>()?;
+            self.scripts = Some(scripts);
+        }
+
+        let r#type = self.r#type;
+        if r#type == 0 {
+            self.scroll_height = Some(buffer.try_get_u16()?);
+            self.hidden = Some(buffer.try_get_u8()? == 1);
+        }
+        if r#type == 1 {
+            // Unused.
+            buffer.try_get_u16()?;
+            buffer.try_get_u8()?;
+        }
+        if r#type == 2 {
+            // Whether items can be swapped, used, interacted with and replaced.
+            let mut flags = 0;
+            for flag in [0x10000000, 0x40000000, 0x80000000, 0x20000000] {
+                if buffer.try_get_u8()? == 1 {
+                    flags |= flag;
+                }
+            }
+            self.flags = Some(flags);
+            self.padding_x = Some(buffer.try_get_u8()? as i16);
+            self.padding_y = Some(buffer.try_get_u8()? as i16);
+
+            let mut slot_sprites = Vec::new();
+            for slot in 0..20 {
+                if buffer.try_get_u8()? == 1 {
+                    slot_sprites.push(SlotSprite {
+                        slot,
+                        x: buffer.try_get_u16()? as i16,
+                        y: buffer.try_get_u16()? as i16,
+                        sprite: buffer.try_get_i32()?,
+                    });
+                }
+            }
+            self.slot_sprites = Some(slot_sprites);
+            self.actions = Some(Self::if1_actions(&mut buffer)?);
+        }
+        if r#type == 3 {
+            self.filled = Some(buffer.try_get_u8()? == 1);
+        }
+        if r#type == 1 || r#type == 4 {
+            self.text_x_alignment = Some(buffer.try_get_u8()?);
+            self.text_y_alignment = Some(buffer.try_get_u8()?);
+            self.line_height = Some(buffer.try_get_u8()?);
+            self.font = Some(buffer.try_get_u16()?).filter(|&font| font != 0xFFFF);
+            self.text_shadowed = Some(buffer.try_get_u8()? == 1);
+        }
+        if r#type == 4 {
+            self.text = Some(buffer.try_get_string()?);
+            self.alternate_text = Some(buffer.try_get_string()?);
+        }
+        if r#type == 1 || r#type == 3 || r#type == 4 {
+            self.text_colour = Some(buffer.try_get_i32()?);
+        }
+        if r#type == 3 || r#type == 4 {
+            self.alternate_text_colour = Some(buffer.try_get_i32()?);
+            self.hovered_text_colour = Some(buffer.try_get_i32()?);
+            self.alternate_hovered_text_colour = Some(buffer.try_get_i32()?);
+        }
+        if r#type == 5 {
+            self.sprite = Some(buffer.try_get_i32()?);
+            self.alternate_sprite = Some(buffer.try_get_i32()?);
+        }
+        if r#type == 6 {
+            self.model = Some(buffer.try_get_u16()?).filter(|&model| model != 0xFFFF);
+            self.alternate_model = Some(buffer.try_get_u16()?).filter(|&model| model != 0xFFFF);
+            self.animation = Some(buffer.try_get_u16()?).filter(|&animation| animation != 0xFFFF);
+            self.alternate_animation = Some(buffer.try_get_u16()?).filter(|&animation| animation != 0xFFFF);
+            self.model_zoom = Some(buffer.try_get_u16()?);
+            self.rotation_x = Some(buffer.try_get_u16()?);
+            self.rotation_z = Some(buffer.try_get_u16()?);
+        }
+        if r#type == 7 {
+            self.text_x_alignment = Some(buffer.try_get_u8()?);
+            self.font = Some(buffer.try_get_u16()?).filter(|&font| font != 0xFFFF);
+            self.text_shadowed = Some(buffer.try_get_u8()? == 1);
+            self.text_colour = Some(buffer.try_get_i32()?);
+            self.padding_x = Some(buffer.try_get_u16()? as i16);
+            self.padding_y = Some(buffer.try_get_u16()? as i16);
+            if buffer.try_get_u8()? == 1 {
+                self.flags = Some(0x40000000);
+            }
+            self.actions = Some(Self::if1_actions(&mut buffer)?);
+        }
+        if r#type == 8 {
+            self.text = Some(buffer.try_get_string()?);
+        }
+        if menu_type == 2 || r#type == 2 {
+            self.target_verb = Some(buffer.try_get_string()?);
+            self.spell_name = Some(buffer.try_get_string()?);
+            // What the spell can be used on.
+            let targets = (buffer.try_get_u16()? & 0x3F) as u32;
+            *self.flags.get_or_insert(0) |= targets << 11;
+        }
+        if matches!(menu_type, 1 | 4 | 5 | 6) {
+            self.tooltip = Some(buffer.try_get_string()?);
+        }
+
+        if buffer.has_remaining() {
+            return Err(NotExhausted::new(buffer.remaining()));
+        }
+        Ok(())
+    }
+
+    /// The five actions of an IF1 inventory; empty ones are skipped.
+    fn if1_actions(buffer: &mut Bytes) -> Result<Vec<JString<Bytes>>, ReadError> {
+        let actions = (0..5).map(|_| buffer.try_get_string()).collect::<Result<Vec<_>, _>>()?;
+        Ok(actions.into_iter().filter(|action| !action.is_empty()).collect())
+    }
+
+    fn decode_if3(&mut self, mut buffer: Bytes) -> Result<(), ReadError> {
+        self.if3 = true;
+        // The marker.
+        buffer.try_get_u8()?;
+
+        self.r#type = buffer.try_get_u8()?;
+        self.content_type = buffer.try_get_u16()?;
+        self.x = buffer.try_get_u16()? as i16;
+        self.y = buffer.try_get_u16()? as i16;
+        self.width = buffer.try_get_u16()?;
+        self.height = buffer.try_get_u16()? as i16;
+        self.width_mode = Some(buffer.try_get_i8()?);
+        self.height_mode = Some(buffer.try_get_i8()?);
+        self.x_mode = Some(buffer.try_get_i8()?);
+        self.y_mode = Some(buffer.try_get_i8()?);
+        self.parent = self.sibling(buffer.try_get_u16()?);
+        self.hidden = Some(buffer.try_get_u8()? == 1);
+
+        match self.r#type {
+            0 => {
+                self.scroll_width = Some(buffer.try_get_u16()?);
+                self.scroll_height = Some(buffer.try_get_u16()?);
+                self.no_click_through = Some(buffer.try_get_u8()? == 1);
+            }
+            3 => {
+                self.text_colour = Some(buffer.try_get_i32()?);
+                self.filled = Some(buffer.try_get_u8()? == 1);
+                self.opacity = Some(buffer.try_get_u8()?);
+            }
+            4 => {
+                self.font = Some(buffer.try_get_u16()?).filter(|&font| font != 0xFFFF);
+                self.text = Some(buffer.try_get_string()?);
+                self.line_height = Some(buffer.try_get_u8()?);
+                self.text_x_alignment = Some(buffer.try_get_u8()?);
+                self.text_y_alignment = Some(buffer.try_get_u8()?);
+                self.text_shadowed = Some(buffer.try_get_u8()? == 1);
+                self.text_colour = Some(buffer.try_get_i32()?);
+            }
+            5 => {
+                self.sprite = Some(buffer.try_get_i32()?);
+                self.texture = Some(buffer.try_get_u16()?);
+                self.sprite_tiling = Some(buffer.try_get_u8()? == 1);
+                self.opacity = Some(buffer.try_get_u8()?);
+                self.border_type = Some(buffer.try_get_u8()?);
+                self.shadow_colour = Some(buffer.try_get_i32()?);
+                self.flipped_vertically = Some(buffer.try_get_u8()? == 1);
+                self.flipped_horizontally = Some(buffer.try_get_u8()? == 1);
+            }
+            6 => {
+                self.model = Some(buffer.try_get_u16()?).filter(|&model| model != 0xFFFF);
+                self.offset_x_2d = Some(buffer.try_get_u16()? as i16);
+                self.offset_y_2d = Some(buffer.try_get_u16()? as i16);
+                self.rotation_x = Some(buffer.try_get_u16()?);
+                self.rotation_z = Some(buffer.try_get_u16()?);
+                self.rotation_y = Some(buffer.try_get_u16()?);
+                self.model_zoom = Some(buffer.try_get_u16()?);
+                self.animation = Some(buffer.try_get_u16()?).filter(|&animation| animation != 0xFFFF);
+                self.orthogonal = Some(buffer.try_get_u8()? == 1);
+                // Unused, followed by the model's size if it is not drawn at its own size.
+                buffer.try_get_u16()?;
+                if self.width_mode != Some(0) {
+                    buffer.try_get_u16()?;
+                }
+                if self.height_mode != Some(0) {
+                    buffer.try_get_u16()?;
+                }
+            }
+            9 => {
+                self.line_width = Some(buffer.try_get_u8()?);
+                self.text_colour = Some(buffer.try_get_i32()?);
+                self.line_direction = Some(buffer.try_get_u8()? == 1);
+            }
+            _ => {}
+        }
+
+        self.flags = Some(buffer.try_get_uint(3)? as u32);
+        self.name = Some(buffer.try_get_string()?);
+
+        let count = buffer.try_get_u8()?;
+        if count > 0 {
+            let actions = (0..count).map(|_| buffer.try_get_string()).collect::<Result<_, _>>()?;
+            self.actions = Some(actions);
+        }
+
+        self.drag_dead_zone = Some(buffer.try_get_u8()?);
+        self.drag_dead_time = Some(buffer.try_get_u8()?);
+        self.drag_render_behaviour = Some(buffer.try_get_u8()? == 1);
+        self.target_verb = Some(buffer.try_get_string()?);
+
+        for name in LISTENERS {
+            if let Some(listener) = Self::listener(&mut buffer)? {
+                self.listeners.get_or_insert_default().insert(name, listener);
+            }
+        }
+
+        self.var_transmit_triggers = Self::triggers(&mut buffer)?;
+        self.inv_transmit_triggers = Self::triggers(&mut buffer)?;
+        self.stat_transmit_triggers = Self::triggers(&mut buffer)?;
+        Ok(())
+    }
+
+    fn listener(buffer: &mut Bytes) -> Result<Option<Vec<Argument>>, ReadError> {
+        let count = buffer.try_get_u8()?;
+        if count == 0 {
+            return Ok(None);
+        }
+        let arguments = (0..count)
+            .map(|_| try {
+                match buffer.try_get_u8()? {
+                    0 => Argument::Int(buffer.try_get_i32()?),
+                    _ => Argument::String(buffer.try_get_string()?),
+                }
+            })
+            .collect::<Result<_, ReadError>>()?;
+        Ok(Some(arguments))
+    }
+
+    fn triggers(buffer: &mut Bytes) -> Result<Option<Vec<i32>>, ReadError> {
+        let count = buffer.try_get_u8()?;
+        if count == 0 {
+            return Ok(None);
+        }
+        let triggers = (0..count).map(|_| buffer.try_get_i32()).collect::<Result<_, _>>()?;
+        Ok(Some(triggers))
+    }
+}
+
+/// Saves every interface as `interfaces/<id>.json`. Exposed as `--dump interfaces`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    for (id, interface) in Interface::dump_all(config)?.into_iter().filter(|(id, _)| config.keeps(*id)) {
+        crate::output::put_json(config, format!("interfaces/{id}.json"), &interface)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if3_text() {
+        #[rustfmt::skip]
+        let buffer = Bytes::from_static(&[
+            0xFF,
+            // type, content type, x, y, width, height
+            4, 0, 0, 0, 10, 0, 20, 0, 100, 0, 15,
+            // modes, parent, hidden
+            0, 0, 0, 0, 0, 1, 0,
+            // font, text, line height, alignments, shadow, colour
+            0x01, 0xEF, b'H', b'i', 0, 0, 1, 1, 1, 0, 0, 0xFF, 0x98,
+            // flags, name, actions
+            0, 0, 0, b'a', 0, 1, b'O', b'k', 0,
+            // drag, target verb
+            0, 0, 0, 0,
+            // an on_load listener calling script 500
+            1, 0, 0, 0, 0x01, 0xF4,
+            // the other listeners and triggers
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        let component = Component::deserialize(12 << 16 | 3, buffer).unwrap();
+
+        assert!(component.if3);
+        assert_eq!(component.parent, Some(12 << 16 | 1));
+        assert_eq!(component.font, Some(495));
+        assert_eq!(component.text.as_deref(), Some("Hi"));
+        assert_eq!(component.text_colour, Some(0xFF98));
+        assert_eq!(&*component.actions.unwrap()[0], "Ok");
+        assert!(matches!(component.listeners.unwrap()["on_load"][..], [Argument::Int(500)]));
+    }
+
+    #[test]
+    fn nesting() {
+        let component = |id, parent| Component {
+            id,
+            parent,
+            ..Default::default()
+        };
+        let interface = Interface::new(
+            1,
+            vec![component(0x10000, None), component(0x10001, Some(0x10000)), component(0x10002, None)],
+        );
+
+        assert_eq!(interface.components.len(), 2);
+        assert_eq!(interface.components[0].children[0].component.id, 0x10001);
+        assert!(interface.components[1].children.is_empty());
+    }
+}
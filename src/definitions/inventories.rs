@@ -0,0 +1,163 @@
+//! Describes inventories, such as the backpack, bank and shop stocks.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::definitions::indextype::{ConfigType, IndexType};
+
+/// Describes an inventory.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct Inventory {
+    /// Its id.
+    pub id: u32,
+    /// How many slots it has.
+    pub capacity: Option<u16>,
+    /// The items it starts out with, such as the default stock of a shop.
+    pub stock: Option<Vec<Stock>>,
+    /// Whether its contents are kept when logging out.
+    #[cfg(feature = "rs3")]
+    pub persist: Option<bool>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+/// An item in an [`Inventory`] and how many of it there are.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[derive(Serialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stock {
+    pub item: u16,
+    pub count: u16,
+}
+
+impl Inventory {
+    /// Returns a mapping of all [`Inventory`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::INVENTORY)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("inventories", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "inventories" })
+    }
+
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut inventory = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(inventory);
+                        }
+                    }
+                    2 => inventory.capacity = Some(buffer.try_get_u16()?),
+                    4 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        let stock = (0..count)
+                            .map(|_| try {
+                                Stock {
+                                    item: buffer.try_get_u16()?,
+                                    count: buffer.try_get_u16()?,
+                                }
+                            })
+                            .collect::<Result<Vec<_>, ReadError>>()?;
+                        inventory.stock = Some(stock);
+                    }
+                    #[cfg(feature = "rs3")]
+                    5 => inventory.persist = Some(true),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    inventory.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &inventory,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(inventory);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for Inventory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl Inventory {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("Inventory({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("Inventory({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the inventories as `inventories.json`. Exposed as `--dump inventories`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let inventories = Inventory::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "inventories", &inventories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stock() {
+        let buffer = Bytes::from_static(&[2, 0, 40, 4, 2, 0x03, 0xE3, 0, 10, 0, 1, 0, 5, 0]);
+        let inventory = Inventory::deserialize(3, buffer, false).unwrap();
+
+        assert_eq!(inventory.capacity, Some(40));
+        assert_eq!(inventory.stock, Some(vec![Stock { item: 995, count: 10 }, Stock { item: 1, count: 5 }]));
+    }
+}
@@ -1,21 +1,19 @@
 //! Describes the properties of items.
 
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-};
+use core::panic::Location;
+use std::collections::BTreeMap;
 
 use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
-    buf::{BufExtra, JString},
+    buf::{BufExtra, JString, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::{definitions::indextype::IndexType, structures::paramtable::ParamTable};
@@ -23,7 +21,7 @@ use crate::{definitions::indextype::IndexType, structures::paramtable::ParamTabl
 
 #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct ItemConfig {
     /// Its id.
     pub id: u32,
@@ -87,11 +85,19 @@ pub struct ItemConfig {
     pub unknown_168: Option<bool>,
     #[serde(flatten)]
     pub params: Option<ParamTable>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl ItemConfig {
     /// Returns a mapping of all [`ItemConfig`]s.
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        Self::decode_all(config)?.map(|item| item.map(|item| (item.id, item))).collect()
+    }
+
+    /// Decodes every [`ItemConfig`] as its archive is read, in order of their ids.
+    pub fn decode_all(config: &crate::cli::Config) -> CacheResult<impl Iterator<Item = CacheResult<Self>> + '_> {
         let archives = CacheIndex::new(IndexType::OBJ_CONFIG, config.input.clone())?.into_iter();
 
         let items = archives
@@ -103,89 +109,123 @@ impl ItemConfig {
                     .into_iter()
                     .map(move |(file_id, file)| (archive_id << 8 | file_id, file))
             })
-            .map(|(id, file)| (id, Self::deserialize(id, file)))
-            .collect::<BTreeMap<u32, Self>>();
+            .map(|(id, file)| Self::deserialize(id, file, config.recover))
+            .filter_map(|result| config.capture("item configs", result))
+            .map(|result| result.context(error::Read { what: "item configs" }));
         Ok(items)
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> Self {
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
         let mut item = Self { id, ..Default::default() };
 
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
         loop {
-            match buffer.get_u8() {
-                0 => {
-                    debug_assert!(!buffer.has_remaining());
-                    break item;
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(item);
+                        }
+                    }
+                    1 => item.base_model = Some(buffer.try_get_smart32()?.unwrap()),
+                    2 => item.name = Some(buffer.try_get_string()?),
+                    3 => item.buff_effect = Some(buffer.try_get_string()?),
+                    4 => item.rotation.get_or_insert_default().yaw = buffer.try_get_u16()?,
+                    5 => item.rotation.get_or_insert_default().pitch = buffer.try_get_u16()?,
+                    6 => item.rotation.get_or_insert_default().roll = buffer.try_get_u16()?,
+                    7 => item.translation.get_or_insert_default().x = buffer.try_get_u16()?,
+                    8 => item.translation.get_or_insert_default().y = buffer.try_get_u16()?,
+                    11 => item.stackable = Some(true),
+                    12 => item.value = Some(buffer.try_get_i32()?),
+                    13 => item.equipslot_id = Some(buffer.try_get_u8()?),
+                    14 => item.equip_id = Some(buffer.try_get_u8()?),
+                    15 => item.unknown_15 = Some(true),
+                    16 => item.is_members = Some(true),
+                    23 => item.male_models.get_or_insert_default()[0] = Some(buffer.try_get_smart32()?.unwrap()),
+                    24 => item.male_models.get_or_insert_default()[1] = Some(buffer.try_get_smart32()?.unwrap()),
+                    25 => item.female_models.get_or_insert_default()[0] = Some(buffer.try_get_smart32()?.unwrap()),
+                    26 => item.female_models.get_or_insert_default()[1] = Some(buffer.try_get_smart32()?.unwrap()),
+                    27 => item.unknown_27 = Some(buffer.try_get_u8()?),
+                    opcode @ 30..=34 => {
+                        item.ground_actions.get_or_insert([None, None, None, None, None])[opcode as usize - 30] = Some(buffer.try_get_string()?)
+                    }
+                    opcode @ 35..=39 => {
+                        item.widget_actions.get_or_insert([None, None, None, None, None])[opcode as usize - 35] = Some(buffer.try_get_string()?)
+                    }
+                    40 => item.colour_replacements = Some(ColourReplacements::deserialize(&mut buffer)?),
+                    41 => item.textures = Some(Textures::deserialize(&mut buffer)?),
+                    42 => item.recolour_palette = Some(RecolourPalette::deserialize(&mut buffer)?),
+                    44 => item.recolour_indices = Some(buffer.try_get_masked_index()?),
+                    45 => item.retexture_indices = Some(buffer.try_get_masked_index()?),
+                    65 => item.is_tradeable = Some(true),
+                    69 => item.ge_buy_limit = Some(buffer.try_get_i32()?),
+                    78 => item.male_models.get_or_insert_default()[2] = Some(buffer.try_get_smart32()?.unwrap()),
+                    79 => item.female_models.get_or_insert_default()[2] = Some(buffer.try_get_smart32()?.unwrap()),
+                    90 => item.male_head_models.get_or_insert_default()[0] = Some(buffer.try_get_smart32()?.unwrap()),
+                    91 => item.female_head_models.get_or_insert_default()[0] = Some(buffer.try_get_smart32()?.unwrap()),
+                    92 => item.male_head_models.get_or_insert_default()[1] = Some(buffer.try_get_smart32()?.unwrap()),
+                    93 => item.female_head_models.get_or_insert_default()[1] = Some(buffer.try_get_smart32()?.unwrap()),
+                    94 => item.category = Some(buffer.try_get_u16()?),
+                    95 => item.model_yaw = Some(buffer.try_get_u16()?),
+                    96 => item.dummy_item = Some(buffer.try_get_u8()?),
+                    97 => item.note_data = Some(buffer.try_get_u16()?),
+                    98 => item.note_template = Some(buffer.try_get_u16()?),
+                    opcode @ 100..=109 => {
+                        item.stack_info.get_or_insert_default()[opcode as usize - 100] = Some((buffer.try_get_u16()?, buffer.try_get_u16()?))
+                    }
+                    opcode @ 110..=112 => item.scale.get_or_insert_default()[opcode as usize - 110] = Some(buffer.try_get_u16()?),
+                    113 => item.ambiance = Some(buffer.try_get_i8()?),
+                    114 => item.contrast = Some(buffer.try_get_i8()?),
+                    115 => item.team = Some(buffer.try_get_u8()?),
+                    121 => item.loan_id = Some(buffer.try_get_u16()?),
+                    122 => item.loan_template = Some(buffer.try_get_u16()?),
+                    125 => item.male_translate = Some(buffer.try_get_uint(3)? as u32),
+                    126 => item.female_translate = Some(buffer.try_get_uint(3)? as u32),
+                    132 => item.quests = Some(Quests::deserialize(&mut buffer)?),
+                    134 => item.pick_size_shift = Some(buffer.try_get_u8()?),
+                    139 => item.unknown_bind_link = Some(buffer.try_get_u16()?),
+                    140 => item.bind_template = Some(buffer.try_get_u16()?),
+                    opcode @ 142..=146 => item.ground_action_cursor.get_or_insert_default()[opcode as usize - 142] = Some(buffer.try_get_u16()?),
+                    opcode @ 150..=154 => item.widget_action_cursor.get_or_insert_default()[opcode as usize - 150] = Some(buffer.try_get_u16()?),
+                    157 => item.randomize_ground_pos = Some(true),
+                    161 => item.combine_info = Some(buffer.try_get_u16()?),
+                    162 => item.combine_template = Some(buffer.try_get_u16()?),
+                    163 => item.combine_num_required = Some(buffer.try_get_u16()?),
+                    164 => item.combine_shard_name = Some(buffer.try_get_string()?),
+                    165 => item.never_stackable = Some(true),
+                    167 => item.unknown_167 = Some(true),
+                    168 => item.unknown_168 = Some(true),
+                    249 => item.params = Some(ParamTable::deserialize(&mut buffer)),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
                 }
-                1 => item.base_model = Some(buffer.get_smart32().unwrap()),
-                2 => item.name = Some(buffer.get_string()),
-                3 => item.buff_effect = Some(buffer.get_string()),
-                4 => item.rotation.get_or_insert_default().yaw = buffer.get_u16(),
-                5 => item.rotation.get_or_insert_default().pitch = buffer.get_u16(),
-                6 => item.rotation.get_or_insert_default().roll = buffer.get_u16(),
-                7 => item.translation.get_or_insert_default().x = buffer.get_u16(),
-                8 => item.translation.get_or_insert_default().y = buffer.get_u16(),
-                11 => item.stackable = Some(true),
-                12 => item.value = Some(buffer.get_i32()),
-                13 => item.equipslot_id = Some(buffer.get_u8()),
-                14 => item.equip_id = Some(buffer.get_u8()),
-                15 => item.unknown_15 = Some(true),
-                16 => item.is_members = Some(true),
-                23 => item.male_models.get_or_insert_default()[0] = Some(buffer.get_smart32().unwrap()),
-                24 => item.male_models.get_or_insert_default()[1] = Some(buffer.get_smart32().unwrap()),
-                25 => item.female_models.get_or_insert_default()[0] = Some(buffer.get_smart32().unwrap()),
-                26 => item.female_models.get_or_insert_default()[1] = Some(buffer.get_smart32().unwrap()),
-                27 => item.unknown_27 = Some(buffer.get_u8()),
-                opcode @ 30..=34 => {
-                    item.ground_actions.get_or_insert([None, None, None, None, None])[opcode as usize - 30] = Some(buffer.get_string())
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
                 }
-                opcode @ 35..=39 => {
-                    item.widget_actions.get_or_insert([None, None, None, None, None])[opcode as usize - 35] = Some(buffer.get_string())
+                Err(e) => {
+                    item.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &item,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(item);
                 }
-                40 => item.colour_replacements = Some(ColourReplacements::deserialize(&mut buffer)),
-                41 => item.textures = Some(Textures::deserialize(&mut buffer)),
-                42 => item.recolour_palette = Some(RecolourPalette::deserialize(&mut buffer)),
-                44 => item.recolour_indices = Some(buffer.get_masked_index()),
-                45 => item.retexture_indices = Some(buffer.get_masked_index()),
-                65 => item.is_tradeable = Some(true),
-                69 => item.ge_buy_limit = Some(buffer.get_i32()),
-                78 => item.male_models.get_or_insert_default()[2] = Some(buffer.get_smart32().unwrap()),
-                79 => item.female_models.get_or_insert_default()[2] = Some(buffer.get_smart32().unwrap()),
-                90 => item.male_head_models.get_or_insert_default()[0] = Some(buffer.get_smart32().unwrap()),
-                91 => item.female_head_models.get_or_insert_default()[0] = Some(buffer.get_smart32().unwrap()),
-                92 => item.male_head_models.get_or_insert_default()[1] = Some(buffer.get_smart32().unwrap()),
-                93 => item.female_head_models.get_or_insert_default()[1] = Some(buffer.get_smart32().unwrap()),
-                94 => item.category = Some(buffer.get_u16()),
-                95 => item.model_yaw = Some(buffer.get_u16()),
-                96 => item.dummy_item = Some(buffer.get_u8()),
-                97 => item.note_data = Some(buffer.get_u16()),
-                98 => item.note_template = Some(buffer.get_u16()),
-                opcode @ 100..=109 => item.stack_info.get_or_insert_default()[opcode as usize - 100] = Some((buffer.get_u16(), buffer.get_u16())),
-                opcode @ 110..=112 => item.scale.get_or_insert_default()[opcode as usize - 110] = Some(buffer.get_u16()),
-                113 => item.ambiance = Some(buffer.get_i8()),
-                114 => item.contrast = Some(buffer.get_i8()),
-                115 => item.team = Some(buffer.get_u8()),
-                121 => item.loan_id = Some(buffer.get_u16()),
-                122 => item.loan_template = Some(buffer.get_u16()),
-                125 => item.male_translate = Some(buffer.get_uint(3) as u32),
-                126 => item.female_translate = Some(buffer.get_uint(3) as u32),
-                132 => item.quests = Some(Quests::deserialize(&mut buffer)),
-                134 => item.pick_size_shift = Some(buffer.get_u8()),
-                139 => item.unknown_bind_link = Some(buffer.get_u16()),
-                140 => item.bind_template = Some(buffer.get_u16()),
-                opcode @ 142..=146 => item.ground_action_cursor.get_or_insert_default()[opcode as usize - 142] = Some(buffer.get_u16()),
-                opcode @ 150..=154 => item.widget_action_cursor.get_or_insert_default()[opcode as usize - 150] = Some(buffer.get_u16()),
-                157 => item.randomize_ground_pos = Some(true),
-                161 => item.combine_info = Some(buffer.get_u16()),
-                162 => item.combine_template = Some(buffer.get_u16()),
-                163 => item.combine_num_required = Some(buffer.get_u16()),
-                164 => item.combine_shard_name = Some(buffer.get_string()),
-                165 => item.never_stackable = Some(true),
-                167 => item.unknown_167 = Some(true),
-                168 => item.unknown_168 = Some(true),
-                249 => item.params = Some(ParamTable::deserialize(&mut buffer)),
-
-                missing => unimplemented!("ItemConfig::deserialize cannot deserialize opcode {} in id {}", missing, id),
             }
         }
     }
@@ -216,13 +256,15 @@ pub mod item_config_fields {
 
     use std::{collections::BTreeMap, iter};
 
-    use bytes::{Buf, Bytes};
+    use bytes::Bytes;
     #[cfg(feature = "pyo3")]
     use pyo3::prelude::*;
+    use rs3cache_backend::buf::{BufExtra, ReadError};
+    use schemars::JsonSchema;
     use serde::Serialize;
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(PartialEq, Eq, Serialize, Debug, Default, Clone, Copy)]
+    #[derive(PartialEq, Eq, Serialize, JsonSchema, Debug, Default, Clone, Copy)]
     pub struct Rotation {
         pub yaw: u16,
         pub pitch: u16,
@@ -230,14 +272,14 @@ pub mod item_config_fields {
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(PartialEq, Eq, Serialize, Debug, Default, Clone, Copy)]
+    #[derive(PartialEq, Eq, Serialize, JsonSchema, Debug, Default, Clone, Copy)]
     pub struct Translation {
         pub x: u16,
         pub y: u16,
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct ColourReplacements {
         pub colours: Vec<(u16, u16)>,
     }
@@ -252,15 +294,17 @@ pub mod item_config_fields {
     }
 
     impl ColourReplacements {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-            let colours = iter::repeat_with(|| (buffer.get_u16(), buffer.get_u16())).take(count).collect::<Vec<_>>();
-            Self { colours }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+            let colours = iter::repeat_with(|| try { (buffer.try_get_u16()?, buffer.try_get_u16()?) })
+                .take(count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { colours })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Textures {
         pub textures: BTreeMap<u16, u16>,
     }
@@ -275,31 +319,31 @@ pub mod item_config_fields {
     }
 
     impl Textures {
-        pub fn deserialize(buffer: &mut Bytes) -> Textures {
-            let count = buffer.get_u8() as usize;
-            let textures = iter::repeat_with(|| (buffer.get_u16(), buffer.get_u16()))
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Textures, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+            let textures = iter::repeat_with(|| try { (buffer.try_get_u16()?, buffer.try_get_u16()?) })
                 .take(count)
-                .collect::<BTreeMap<_, _>>();
-            Textures { textures }
+                .collect::<Result<BTreeMap<_, _>, ReadError>>()?;
+            Ok(Textures { textures })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone)]
+    #[derive(Debug, Serialize, JsonSchema, Clone)]
     pub struct Quests {
         pub quests: Vec<u16>,
     }
 
     impl Quests {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-            let quests = iter::repeat_with(|| buffer.get_u16()).take(count).collect();
-            Self { quests }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+            let quests = iter::repeat_with(|| buffer.try_get_u16()).take(count).collect::<Result<_, ReadError>>()?;
+            Ok(Self { quests })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone, Copy)]
+    #[derive(Debug, Serialize, JsonSchema, Clone, Copy)]
     pub struct StackInfo {
         unknown_1: u16,
         unknown_2: u16,
@@ -319,15 +363,15 @@ pub mod item_config_fields {
     }
 
     impl StackInfo {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let unknown_1 = buffer.get_u16();
-            let unknown_2 = buffer.get_u16();
-            Self { unknown_1, unknown_2 }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let unknown_1 = buffer.try_get_u16()?;
+            let unknown_2 = buffer.try_get_u16()?;
+            Ok(Self { unknown_1, unknown_2 })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone)]
+    #[derive(Debug, Serialize, JsonSchema, Clone)]
     pub struct RecolourPalette {
         pub palette: Vec<i8>,
     }
@@ -342,11 +386,13 @@ pub mod item_config_fields {
     }
 
     impl RecolourPalette {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
 
-            let palette = iter::repeat_with(|| buffer.get_i8()).take(count).collect::<Vec<_>>();
-            Self { palette }
+            let palette = iter::repeat_with(|| buffer.try_get_i8())
+                .take(count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { palette })
         }
     }
 }
@@ -355,14 +401,5 @@ use item_config_fields::*;
 
 /// Save the item configs as `item_configs.json`. Exposed as `--dump item_configs`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
-    let mut item_configs = ItemConfig::dump_all(config)?.into_values().collect::<Vec<_>>();
-    item_configs.sort_unstable_by_key(|loc| loc.id);
-
-    let path = path!(config.output / "item_configs.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&item_configs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export_iter(config, "item_configs", ItemConfig::decode_all(config)?)
 }
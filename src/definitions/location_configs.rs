@@ -1,32 +1,31 @@
 use std::{
     collections::BTreeMap,
     fmt::{self, Display, Formatter},
-    fs::{self, File},
-    io::Write,
 };
 
 use ::error::Context;
-use bytes::{Buf, Bytes};
-use path_macro::path;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rayon::iter::{ParallelBridge, ParallelIterator};
 use rs3cache_backend::{
-    buf::{BufExtra, JString, NotExhausted, OpcodeNotImplemented, ReadError, WithInfo},
+    buf::{BufExtra, BufMutExtra, JString, NotExhausted, OpcodeNotImplemented, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
+use schemars::JsonSchema;
 use serde::Serialize;
 
 #[cfg(any(feature = "rs3", feature = "osrs"))]
 use crate::definitions::indextype::IndexType;
-use crate::structures::paramtable::ParamTable;
+use crate::{cli::Revision, structures::paramtable::ParamTable};
 
 /// Describes the properties of a given [`Location`](crate::definitions::locations::Location).
 
 #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct LocationConfig {
     /// Its id.
     pub id: u32,
@@ -107,10 +106,11 @@ pub struct LocationConfig {
     pub morphs_2: Option<ExtendedLocationMorphTable>,
     pub unknown_93: Option<u16>,
     pub unknown_94: Option<bool>,
-    #[cfg(any(feature = "rs3", feature = "2010_3_shim"))]
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     pub unknown_95: Option<u16>,
-    #[cfg(all(feature = "2008_3_shim", not(feature = "2010_3_shim")))]
-    pub unknown_95: Option<bool>,
+    /// Opcode 95 before 2010_3, which had no value.
+    #[cfg(feature = "2008_3_shim")]
+    pub unknown_95_flag: Option<bool>,
     #[cfg(feature = "2008_3_shim")]
     pub unknown_96: Option<bool>,
     pub unknown_97: Option<bool>,
@@ -135,9 +135,9 @@ pub struct LocationConfig {
     pub unknown_165: Option<u16>,
     pub unknown_166: Option<u16>,
     pub unknown_167: Option<u16>,
-    #[cfg(feature = "2010_3_shim")]
+    #[cfg(feature = "2008_3_shim")]
     pub unknown_168: Option<bool>,
-    #[cfg(feature = "2010_3_shim")]
+    #[cfg(feature = "2008_3_shim")]
     pub unknown_169: Option<bool>,
     pub unknown_170: Option<u16>,
     pub unknown_171: Option<u16>,
@@ -161,6 +161,9 @@ pub struct LocationConfig {
     pub unknown_204: Option<Vec<Unknown204>>,
     #[serde(flatten)]
     pub params: Option<ParamTable>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl LocationConfig {
@@ -168,6 +171,7 @@ impl LocationConfig {
     #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
         let index = IndexType::LOC_CONFIG;
+        let revision = config.revision();
 
         let archives = CacheIndex::new(index, config.input.clone())?.into_iter();
         let locations = archives
@@ -179,7 +183,8 @@ impl LocationConfig {
                     .into_iter()
                     .map(move |(file_id, file)| (archive_id << 8 | file_id, file))
             })
-            .map(|(id, file)| Self::deserialize(id, file).map(|item| (id, item)))
+            .map(|(id, file)| Self::deserialize(id, file, revision, config.recover).map(|item| (id, item)))
+            .filter_map(|result| config.capture("location configs", result))
             .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
             .context(error::Read { what: "location configs" })?;
         Ok(locations)
@@ -189,11 +194,13 @@ impl LocationConfig {
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
         use crate::definitions::indextype::ConfigType;
 
+        let revision = config.revision();
         let locations = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
             .archive(ConfigType::LOC_CONFIG)?
             .take_files()
             .into_iter()
-            .map(|(id, file)| Self::deserialize(id, file).map(|item| (id, item)))
+            .map(|(id, file)| Self::deserialize(id, file, revision, config.recover).map(|item| (id, item)))
+            .filter_map(|result| config.capture("location configs", result))
             .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
             .context(error::Read { what: "location configs" })?;
         Ok(locations)
@@ -209,6 +216,7 @@ impl LocationConfig {
         let mut offset_data = archive.file_named("loc.idx").unwrap();
 
         let mut locations = BTreeMap::new();
+        let revision = config.revision();
 
         let len = offset_data.try_get_u16().unwrap();
         for id in 0..len {
@@ -216,14 +224,15 @@ impl LocationConfig {
                 what: "location config offsets",
             })?;
             let data = file.split_to(piece_len as usize);
-            let loc = LocationConfig::deserialize(id as u32, data).context(error::Read { what: "location configs" })?;
+            let Some(loc) = config.capture("location configs", LocationConfig::deserialize(id as u32, data, revision, config.recover)) else { continue };
+            let loc = loc.context(error::Read { what: "location configs" })?;
             locations.insert(id as u32, loc);
         }
 
         Ok(locations)
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> Result<Self, ReadError> {
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, revision: Revision, recovering: bool) -> Result<Self, ReadError> {
         let mut loc = Self { id, ..Default::default() };
 
         #[cfg(debug_assertions)]
@@ -248,19 +257,19 @@ impl LocationConfig {
                             break Ok(loc);
                         }
                     }
-                    1 => loc.models = Some(Models::deserialize(&mut buffer)?),
+                    1 => loc.models = Some(Models::deserialize(&mut buffer, revision)?),
                     2 => loc.name = Some(buffer.try_get_string()?),
                     // Actually, this field is still in use for a little while after the switch to dat2.
                     #[cfg(feature = "legacy")]
                     3 => loc.description = Some(buffer.try_get_string()?),
                     #[cfg(any(feature = "osrs", feature = "legacy"))]
                     5 => {
-                        #[cfg(feature = "2010_1_shim")]
-                        {
-                            loc.models = Some(Models::deserialize(&mut buffer)?);
+                        #[cfg(feature = "2008_3_shim")]
+                        if revision >= Revision::R2010_1 {
+                            loc.models = Some(Models::deserialize(&mut buffer, revision)?);
                         }
 
-                        loc.models_2 = Some(Models2::deserialize(&mut buffer)?);
+                        loc.models_2 = Some(Models2::deserialize(&mut buffer, revision)?);
                     }
 
                     14 => loc.dim_x = Some(buffer.try_get_u8()?),
@@ -330,7 +339,7 @@ impl LocationConfig {
                     73 => loc.unknown_73 = Some(true),
                     74 => loc.breakroutefinding = Some(true),
                     75 => loc.unknown_75 = Some(buffer.try_get_u8()?),
-                    77 => loc.morphs_1 = Some(LocationMorphTable::deserialize(&mut buffer)?),
+                    77 => loc.morphs_1 = Some(LocationMorphTable::deserialize(&mut buffer, revision)?),
                     78 => loc.unknown_78 = Some(Unknown78::deserialize(&mut buffer)?),
                     79 => loc.unknown_79 = Some(Unknown79::deserialize(&mut buffer)?),
                     81 => loc.unknown_81 = Some(buffer.try_get_u8()?),
@@ -343,19 +352,19 @@ impl LocationConfig {
                     #[cfg(feature = "2008_3_shim")]
                     90 => loc.unknown_90 = Some(true),
                     91 => loc.is_members = Some(true),
-                    92 => loc.morphs_2 = Some(ExtendedLocationMorphTable::deserialize(&mut buffer)?),
+                    92 => loc.morphs_2 = Some(ExtendedLocationMorphTable::deserialize(&mut buffer, revision)?),
                     93 => loc.unknown_93 = Some(buffer.try_get_u16()?),
                     94 => loc.unknown_94 = Some(true),
-                    #[cfg(all(feature = "2008_3_shim", not(feature = "2010_3_shim")))]
-                    95 => loc.unknown_95 = Some(true),
-                    #[cfg(any(feature = "rs3", feature = "2010_3_shim"))]
+                    #[cfg(feature = "2008_3_shim")]
+                    95 if revision < Revision::R2010_3 => loc.unknown_95_flag = Some(true),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
                     95 => loc.unknown_95 = Some(buffer.try_get_u16()?),
                     #[cfg(feature = "2008_3_shim")]
                     96 => loc.unknown_96 = Some(true),
                     97 => loc.unknown_97 = Some(true),
                     98 => loc.unknown_98 = Some(true),
-                    #[cfg(feature = "2009_1_shim")]
-                    opcode @ 99..=100 => {
+                    #[cfg(feature = "2008_3_shim")]
+                    opcode @ 99..=100 if revision >= Revision::R2009_1 => {
                         let cursors = loc.cursors.get_or_insert([None, None, None, None, None, None]);
                         buffer.try_get_u8()?;
                         cursors[opcode as usize - 99] = Some(buffer.try_get_u16()?);
@@ -365,10 +374,9 @@ impl LocationConfig {
                     103 => loc.occludes_2 = Some(false),
                     104 => loc.unknown_104 = Some(buffer.try_get_u8()?),
                     106 => loc.headmodels = Some(HeadModels::deserialize(&mut buffer)?),
-                    #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
-                    107 => loc.mapfunction = Some(buffer.try_get_u16()?),
-                    #[cfg(not(feature = "2010_1_shim"))]
-                    opcode @ 136..=140 => {
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    107 if revision >= Revision::R2009_1 => loc.mapfunction = Some(buffer.try_get_u16()?),
+                    opcode @ 136..=140 if cfg!(not(feature = "2008_3_shim")) || revision < Revision::R2010_1 => {
                         let actions = loc.unknown_array.get_or_insert([None, None, None, None, None]);
                         actions[opcode as usize - 136] = Some(buffer.try_get_u8()?);
                     }
@@ -384,10 +392,10 @@ impl LocationConfig {
                     164 => loc.unknown_164 = Some(buffer.try_get_u16()?),
                     165 => loc.unknown_166 = Some(buffer.try_get_u16()?),
                     167 => loc.unknown_167 = Some(buffer.try_get_u16()?),
-                    #[cfg(feature = "2010_3_shim")]
-                    168 => loc.unknown_168 = Some(true),
-                    #[cfg(feature = "2010_3_shim")]
-                    169 => loc.unknown_169 = Some(true),
+                    #[cfg(feature = "2008_3_shim")]
+                    168 if revision >= Revision::R2010_3 => loc.unknown_168 = Some(true),
+                    #[cfg(feature = "2008_3_shim")]
+                    169 if revision >= Revision::R2010_3 => loc.unknown_169 = Some(true),
                     170 => loc.unknown_170 = Some(buffer.try_get_unsigned_smart()?),
                     171 => loc.unknown_171 = Some(buffer.try_get_unsigned_smart()?),
                     173 => loc.unknown_173 = Some(Unknown173::deserialize(&mut buffer)?),
@@ -426,17 +434,335 @@ impl LocationConfig {
                     opcodes.push(opcode);
                 }
                 Err(e) => {
-                    return Err(e).map_err(Box::new).context(WithInfo {
-                        #[cfg(debug_assertions)]
-                        opcodes,
-                        buffer,
-                        #[cfg(debug_assertions)]
-                        thing: loc.to_string(),
-                    })
+                    loc.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &loc,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(loc);
                 }
             }
         }
     }
+
+    /// Encodes `self` in the format of the cache at `revision`, so that decoding the result gives back `self`.
+    ///
+    /// Fields that several opcodes decode into are written with the opcode that `revision` decodes them from,
+    /// and those that no opcode decodes into are not written.
+    pub fn encode(&self, revision: Revision) -> Bytes {
+        fn flag<T>(buffer: &mut BytesMut, opcode: u8, field: &Option<T>) {
+            if field.is_some() {
+                buffer.put_u8(opcode);
+            }
+        }
+
+        fn strings(buffer: &mut BytesMut, first_opcode: u8, field: &Option<[Option<JString<Bytes>>; 5]>) {
+            for (opcode, s) in (first_opcode..).zip(field.iter().flatten()) {
+                if let Some(s) = s {
+                    buffer.put_u8(opcode);
+                    buffer.put_string(s);
+                }
+            }
+        }
+
+        let mut buffer = BytesMut::new();
+
+        #[cfg(not(feature = "2008_3_shim"))]
+        let models = self.models.as_ref();
+        // From 2010_1 on, opcode 5 carries the models too.
+        #[cfg(feature = "2008_3_shim")]
+        let models = self.models.as_ref().filter(|_| revision < Revision::R2010_1 || self.models_2.is_none());
+        if let Some(models) = models {
+            buffer.put_u8(1);
+            models.encode(&mut buffer, revision);
+        }
+        if let Some(name) = &self.name {
+            buffer.put_u8(2);
+            buffer.put_string(name);
+        }
+        #[cfg(feature = "legacy")]
+        if let Some(description) = &self.description {
+            buffer.put_u8(3);
+            buffer.put_string(description);
+        }
+        #[cfg(any(feature = "osrs", feature = "legacy"))]
+        if let Some(models_2) = &self.models_2 {
+            buffer.put_u8(5);
+            #[cfg(feature = "2008_3_shim")]
+            if revision >= Revision::R2010_1 {
+                self.models
+                    .clone()
+                    .unwrap_or(Models { models: BTreeMap::new() })
+                    .encode(&mut buffer, revision);
+            }
+            models_2.encode(&mut buffer, revision);
+        }
+        if let Some(dim_x) = self.dim_x {
+            buffer.put_u8(14);
+            buffer.put_u8(dim_x);
+        }
+        if let Some(dim_y) = self.dim_y {
+            buffer.put_u8(15);
+            buffer.put_u8(dim_y);
+        }
+        #[cfg(feature = "2008_3_shim")]
+        flag(&mut buffer, 16, &self.unknown_16);
+        // Old school derives `interact_type` from these opcodes, so that is what is checked for there.
+        #[cfg(feature = "osrs")]
+        if self.interact_type == Some(0) && self.breakroutefinding.is_none() {
+            buffer.put_u8(17);
+        }
+        #[cfg(not(feature = "osrs"))]
+        flag(&mut buffer, 17, &self.break_line_of_sight);
+        flag(&mut buffer, 18, &self.is_transparent);
+        if let Some(unknown_19) = self.unknown_19 {
+            buffer.put_u8(19);
+            buffer.put_u8(unknown_19);
+        }
+        flag(&mut buffer, 21, &self.unknown_21);
+        flag(&mut buffer, 22, &self.unknown_22);
+        flag(&mut buffer, 23, &self.occludes_1);
+        if self.unknown_24.is_some() {
+            buffer.put_u8(24);
+            buffer.put_smart32(self.unknown_24);
+        }
+        #[cfg(feature = "legacy")]
+        flag(&mut buffer, 25, &self.unknown_25);
+        flag(&mut buffer, 27, &self.unknown_27);
+        if let Some(unknown_28) = self.unknown_28 {
+            buffer.put_u8(28);
+            buffer.put_u8(unknown_28);
+        }
+        if let Some(ambient) = self.ambient {
+            buffer.put_u8(29);
+            buffer.put_i8(ambient);
+        }
+        strings(&mut buffer, 30, &self.actions);
+        if let Some(contrast) = self.contrast {
+            buffer.put_u8(39);
+            buffer.put_i8(contrast);
+        }
+        if let Some(colour_replacements) = &self.colour_replacements {
+            buffer.put_u8(40);
+            colour_replacements.encode(&mut buffer);
+        }
+        if let Some(textures) = &self.textures {
+            buffer.put_u8(41);
+            textures.encode(&mut buffer);
+        }
+        if let Some(unknown_44) = self.unknown_44 {
+            buffer.put_u8(44);
+            buffer.put_u16(unknown_44);
+        }
+        if let Some(unknown_45) = self.unknown_45 {
+            buffer.put_u8(45);
+            buffer.put_u16(unknown_45);
+        }
+        // Opcode 107 replaced 60 in 2009_1.
+        #[cfg(any(feature = "osrs", feature = "legacy"))]
+        let mapfunction_opcode = if cfg!(feature = "2008_3_shim") && revision >= Revision::R2009_1 {
+            107
+        } else {
+            60
+        };
+        #[cfg(feature = "rs3")]
+        let mapfunction_opcode = 107;
+        if let Some(mapfunction) = self.mapfunction {
+            buffer.put_u8(mapfunction_opcode);
+            buffer.put_u16(mapfunction);
+        }
+        #[cfg(feature = "osrs")]
+        if let Some(category) = self.category {
+            buffer.put_u8(61);
+            buffer.put_u16(category);
+        }
+        flag(&mut buffer, 62, &self.mirror);
+        flag(&mut buffer, 64, &self.model);
+        for (opcode, scale) in [(65, self.scale_x), (66, self.scale_y), (67, self.scale_z)] {
+            if let Some(scale) = scale {
+                buffer.put_u8(opcode);
+                buffer.put_u16(scale);
+            }
+        }
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        if let Some(mapscene) = self.mapscene {
+            buffer.put_u8(68);
+            buffer.put_u16(mapscene);
+        }
+        if let Some(unknown_69) = self.unknown_69 {
+            buffer.put_u8(69);
+            buffer.put_u8(unknown_69);
+        }
+        for (opcode, translate) in [(70, self.translate_x), (71, self.translate_y), (72, self.translate_z)] {
+            if let Some(translate) = translate {
+                buffer.put_u8(opcode);
+                buffer.put_u16(translate);
+            }
+        }
+        flag(&mut buffer, 73, &self.unknown_73);
+        flag(&mut buffer, 74, &self.breakroutefinding);
+        if let Some(unknown_75) = self.unknown_75 {
+            buffer.put_u8(75);
+            buffer.put_u8(unknown_75);
+        }
+        if let Some(morphs_1) = &self.morphs_1 {
+            buffer.put_u8(77);
+            morphs_1.encode(&mut buffer, revision);
+        }
+        if let Some(unknown_78) = &self.unknown_78 {
+            buffer.put_u8(78);
+            unknown_78.encode(&mut buffer);
+        }
+        if let Some(unknown_79) = &self.unknown_79 {
+            buffer.put_u8(79);
+            unknown_79.encode(&mut buffer);
+        }
+        if let Some(unknown_81) = self.unknown_81 {
+            buffer.put_u8(81);
+            buffer.put_u8(unknown_81);
+        }
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        flag(&mut buffer, 82, &self.unknown_82);
+        #[cfg(all(feature = "osrs", not(feature = "2008_3_shim")))]
+        if let Some(maparea_id) = self.maparea_id {
+            buffer.put_u8(82);
+            buffer.put_u16(maparea_id);
+        }
+        flag(&mut buffer, 88, &self.unknown_88);
+        flag(&mut buffer, 89, &self.unknown_89);
+        #[cfg(feature = "2008_3_shim")]
+        flag(&mut buffer, 90, &self.unknown_90);
+        flag(&mut buffer, 91, &self.is_members);
+        if let Some(morphs_2) = &self.morphs_2 {
+            buffer.put_u8(92);
+            morphs_2.encode(&mut buffer, revision);
+        }
+        if let Some(unknown_93) = self.unknown_93 {
+            buffer.put_u8(93);
+            buffer.put_u16(unknown_93);
+        }
+        flag(&mut buffer, 94, &self.unknown_94);
+        #[cfg(feature = "2008_3_shim")]
+        flag(&mut buffer, 95, &self.unknown_95_flag);
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        if let Some(unknown_95) = self.unknown_95 {
+            buffer.put_u8(95);
+            buffer.put_u16(unknown_95);
+        }
+        #[cfg(feature = "2008_3_shim")]
+        flag(&mut buffer, 96, &self.unknown_96);
+        flag(&mut buffer, 97, &self.unknown_97);
+        flag(&mut buffer, 98, &self.unknown_98);
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        if let Some(mapscene) = self.mapscene {
+            buffer.put_u8(102);
+            buffer.put_u16(mapscene);
+        }
+        flag(&mut buffer, 103, &self.occludes_2);
+        if let Some(unknown_104) = self.unknown_104 {
+            buffer.put_u8(104);
+            buffer.put_u8(unknown_104);
+        }
+        if let Some(headmodels) = &self.headmodels {
+            buffer.put_u8(106);
+            headmodels.encode(&mut buffer);
+        }
+        if cfg!(not(feature = "2008_3_shim")) || revision < Revision::R2010_1 {
+            for (opcode, value) in (136..).zip(self.unknown_array.iter().flatten()) {
+                if let Some(value) = value {
+                    buffer.put_u8(opcode);
+                    buffer.put_u8(*value);
+                }
+            }
+        }
+        strings(&mut buffer, 150, &self.member_actions);
+        if let Some(unknown_159) = self.unknown_159 {
+            buffer.put_u8(159);
+            buffer.put_u8(unknown_159);
+        }
+        if let Some(unknown_160) = &self.unknown_160 {
+            buffer.put_u8(160);
+            unknown_160.encode(&mut buffer);
+        }
+        if let Some(unknown_162) = self.unknown_162 {
+            buffer.put_u8(162);
+            buffer.put_i32(unknown_162);
+        }
+        if let Some(unknown_163) = &self.unknown_163 {
+            buffer.put_u8(163);
+            unknown_163.encode(&mut buffer);
+        }
+        // Opcode 165 is decoded into `unknown_166`.
+        for (opcode, value) in [(164, self.unknown_164), (165, self.unknown_166), (167, self.unknown_167)] {
+            if let Some(value) = value {
+                buffer.put_u8(opcode);
+                buffer.put_u16(value);
+            }
+        }
+        #[cfg(feature = "2008_3_shim")]
+        flag(&mut buffer, 168, &self.unknown_168);
+        #[cfg(feature = "2008_3_shim")]
+        flag(&mut buffer, 169, &self.unknown_169);
+        for (opcode, value) in [(170, self.unknown_170), (171, self.unknown_171)] {
+            if let Some(value) = value {
+                buffer.put_u8(opcode);
+                buffer.put_unsigned_smart(value);
+            }
+        }
+        if let Some(unknown_173) = &self.unknown_173 {
+            buffer.put_u8(173);
+            unknown_173.encode(&mut buffer);
+        }
+        flag(&mut buffer, 177, &self.unknown_177);
+        for (opcode, value) in [(178, self.unknown_178), (186, self.unknown_186)] {
+            if let Some(value) = value {
+                buffer.put_u8(opcode);
+                buffer.put_u8(value);
+            }
+        }
+        flag(&mut buffer, 188, &self.unknown_188);
+        flag(&mut buffer, 189, &self.unknown_189);
+        for (opcode, cursor) in (190..).zip(self.cursors.iter().flatten()) {
+            if let Some(cursor) = cursor {
+                buffer.put_u8(opcode);
+                buffer.put_u16(*cursor);
+            }
+        }
+        // Opcode 197 is decoded into `unknown_196` as well.
+        if let Some(unknown_196) = self.unknown_196 {
+            buffer.put_u8(196);
+            buffer.put_u8(unknown_196);
+        }
+        flag(&mut buffer, 198, &self.unknown_198);
+        flag(&mut buffer, 199, &self.unknown_199);
+        if let Some(unknown_201) = &self.unknown_201 {
+            buffer.put_u8(201);
+            unknown_201.encode(&mut buffer);
+        }
+        if let Some(unknown_202) = self.unknown_202 {
+            buffer.put_u8(202);
+            buffer.put_unsigned_smart(unknown_202);
+        }
+        flag(&mut buffer, 203, &self.unknown_203);
+        if let Some(unknown_204) = &self.unknown_204 {
+            buffer.put_u8(204);
+            buffer.put_unsigned_smart(unknown_204.len() as u16);
+            for value in unknown_204 {
+                value.encode(&mut buffer);
+            }
+        }
+        if let Some(params) = &self.params {
+            buffer.put_u8(249);
+            params.encode(&mut buffer);
+        }
+        buffer.put_u8(0);
+        buffer.freeze()
+    }
 }
 
 impl Display for LocationConfig {
@@ -450,24 +776,50 @@ pub mod location_config_fields {
 
     use std::{collections::BTreeMap, iter};
 
-    use bytes::Bytes;
+    use bytes::{BufMut, Bytes, BytesMut};
     #[cfg(feature = "pyo3")]
     use pyo3::prelude::*;
-    use rs3cache_backend::buf::{BufExtra, ReadError};
+    use rs3cache_backend::buf::{BufExtra, BufMutExtra, ReadError};
+    use schemars::JsonSchema;
     use serde::Serialize;
 
-    use crate::types::variables::{Varbit, Varp, VarpOrVarbit};
+    use crate::{
+        cli::Revision,
+        types::variables::{Varbit, Varp, VarpOrVarbit},
+    };
 
-    #[cfg(any(feature = "rs3", feature = "2011_11_shim"))]
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     type IdType = u32;
 
-    #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2011_11_shim")))]
+    #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
     type IdType = u16;
 
+    /// Reads an id, which is a `u16` before 2011_11.
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+    fn get_id(buffer: &mut Bytes, revision: Revision) -> Result<Option<IdType>, ReadError> {
+        if revision < Revision::R2011_11 {
+            Ok(match buffer.try_get_u16()? {
+                0xFFFF => None,
+                id => Some(id as u32),
+            })
+        } else {
+            buffer.try_get_smart32()
+        }
+    }
+
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+    fn put_id(buffer: &mut BytesMut, id: Option<IdType>, revision: Revision) {
+        if revision < Revision::R2011_11 {
+            buffer.put_u16(id.map_or(0xFFFF, |id| id as u16));
+        } else {
+            buffer.put_smart32(id);
+        }
+    }
+
     /// Contains an array of possible ids this location can morph into, controlled by either a varbit or varp.
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct LocationMorphTable {
         #[serde(flatten)]
         pub var: VarpOrVarbit,
@@ -475,28 +827,52 @@ pub mod location_config_fields {
     }
 
     impl LocationMorphTable {
+        /// The ids to encode. The format holds at least one id, so an empty table is written as one without a morph.
+        fn ids_or_none(&self) -> &[Option<IdType>] {
+            if self.ids.is_empty() {
+                &[None]
+            } else {
+                &self.ids
+            }
+        }
+
         /// Constructor for [`LocationMorphTable`]
-        #[cfg(any(feature = "rs3", feature = "2011_11_shim"))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn deserialize(buffer: &mut Bytes, revision: Revision) -> Result<Self, ReadError> {
             let varbit = Varbit::new(buffer.try_get_u16()?);
             let varp = Varp::new(buffer.try_get_u16()?);
             let var = VarpOrVarbit::new(varp, varbit);
 
-            let count = if cfg!(feature = "2011_11_shim") {
+            let count = if revision < Revision::Latest {
                 buffer.try_get_u8()? as usize
             } else {
                 buffer.try_get_unsigned_smart()? as usize
             };
 
-            let ids = iter::repeat_with(|| buffer.try_get_smart32())
+            let ids = iter::repeat_with(|| get_id(buffer, revision))
                 .take(count + 1)
                 .collect::<Result<_, ReadError>>()?;
 
             Ok(Self { var, ids })
         }
 
-        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2011_11_shim")))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn encode(&self, buffer: &mut BytesMut, revision: Revision) {
+            self.var.encode(buffer);
+            let ids = self.ids_or_none();
+            let count = ids.len() - 1;
+            if revision < Revision::Latest {
+                buffer.put_u8(count as u8);
+            } else {
+                buffer.put_unsigned_smart(count as u16);
+            }
+            for &id in ids {
+                put_id(buffer, id, revision);
+            }
+        }
+
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn deserialize(buffer: &mut Bytes, _revision: Revision) -> Result<Self, ReadError> {
             let varbit = Varbit::new(buffer.try_get_u16()?);
             let varp = Varp::new(buffer.try_get_u16()?);
             let var = VarpOrVarbit::new(varp, varbit);
@@ -514,12 +890,22 @@ pub mod location_config_fields {
 
             Ok(Self { var, ids })
         }
+
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn encode(&self, buffer: &mut BytesMut, _revision: Revision) {
+            self.var.encode(buffer);
+            let ids = self.ids_or_none();
+            buffer.put_u8((ids.len() - 1) as u8);
+            for id in ids {
+                buffer.put_u16(id.unwrap_or(0xFFFF));
+            }
+        }
     }
 
     /// Like [`LocationMorphTable`], but with a default value.
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct ExtendedLocationMorphTable {
         #[serde(flatten)]
         pub var: VarpOrVarbit,
@@ -532,34 +918,63 @@ pub mod location_config_fields {
     }
 
     impl ExtendedLocationMorphTable {
+        /// The ids to encode. The format holds at least one id, so an empty table is written as one without a morph.
+        fn ids_or_none(&self) -> &[Option<IdType>] {
+            if self.ids.is_empty() {
+                &[None]
+            } else {
+                &self.ids
+            }
+        }
+
         /// Constructor for [`ExtendedLocationMorphTable`]
-        #[cfg(any(feature = "rs3", feature = "2011_11_shim"))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn deserialize(buffer: &mut Bytes, revision: Revision) -> Result<Self, ReadError> {
             let varbit = Varbit::new(buffer.try_get_u16()?);
             let varp = Varp::new(buffer.try_get_u16()?);
 
             let var = VarpOrVarbit::new(varp, varbit);
 
-            let default = if cfg!(all(feature = "2011_11_shim", not(feature = "2013_shim"))) {
+            let default = if revision < Revision::R2013 {
                 Some(buffer.try_get_u16()? as u32)
             } else {
                 buffer.try_get_smart32()?
             };
 
-            let count = if cfg!(feature = "2011_11_shim") {
+            let count = if revision < Revision::Latest {
                 buffer.try_get_u8()? as usize
             } else {
                 buffer.try_get_unsigned_smart()? as usize
             };
 
-            let ids = iter::repeat_with(|| buffer.try_get_smart32())
+            let ids = iter::repeat_with(|| get_id(buffer, revision))
                 .take(count + 1)
                 .collect::<Result<_, ReadError>>()?;
             Ok(Self { var, ids, default })
         }
 
-        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2011_11_shim")))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn encode(&self, buffer: &mut BytesMut, revision: Revision) {
+            self.var.encode(buffer);
+            if revision < Revision::R2013 {
+                buffer.put_u16(self.default.unwrap_or(0xFFFF) as u16);
+            } else {
+                buffer.put_smart32(self.default);
+            }
+            let ids = self.ids_or_none();
+            let count = ids.len() - 1;
+            if revision < Revision::Latest {
+                buffer.put_u8(count as u8);
+            } else {
+                buffer.put_unsigned_smart(count as u16);
+            }
+            for &id in ids {
+                put_id(buffer, id, revision);
+            }
+        }
+
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn deserialize(buffer: &mut Bytes, _revision: Revision) -> Result<Self, ReadError> {
             let varbit = Varbit::new(buffer.try_get_u16()?);
             let varp = Varp::new(buffer.try_get_u16()?);
 
@@ -582,10 +997,21 @@ pub mod location_config_fields {
 
             Ok(Self { var, ids, default })
         }
+
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn encode(&self, buffer: &mut BytesMut, _revision: Revision) {
+            self.var.encode(buffer);
+            buffer.put_u16(self.default.unwrap_or(0xFFFF));
+            let ids = self.ids_or_none();
+            buffer.put_u8((ids.len() - 1) as u8);
+            for id in ids {
+                buffer.put_u16(id.unwrap_or(0xFFFF));
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct ColourReplacements {
         pub colours: Vec<(u16, u16)>,
     }
@@ -598,25 +1024,45 @@ pub mod location_config_fields {
                 .collect::<Result<Vec<(u16, u16)>, ReadError>>()?;
             Ok(Self { colours })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u8(self.colours.len() as u8);
+            for &(from, to) in &self.colours {
+                buffer.put_u16(from);
+                buffer.put_u16(to);
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Models {
-        #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
         pub models: BTreeMap<i8, Vec<Option<u32>>>,
-        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2010_1_shim")))]
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
         pub models: Vec<(u8, u16)>,
     }
 
     impl Models {
-        #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
-            fn sub_deserialize(buffer: &mut Bytes) -> Result<(i8, Vec<Option<u32>>), ReadError> {
+        /// Before 2010_1, models are listed one by one with their type, and are grouped by type here.
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn deserialize(buffer: &mut Bytes, revision: Revision) -> Result<Self, ReadError> {
+            if revision < Revision::R2010_1 {
+                let count = buffer.try_get_u8()? as usize;
+                let mut models: BTreeMap<i8, Vec<Option<u32>>> = BTreeMap::new();
+                for _ in 0..count {
+                    let model = buffer.try_get_u16()?;
+                    let r#type = buffer.try_get_i8()?;
+                    models.entry(r#type).or_default().push(Some(model as u32));
+                }
+                return Ok(Models { models });
+            }
+
+            fn sub_deserialize(buffer: &mut Bytes, revision: Revision) -> Result<(i8, Vec<Option<u32>>), ReadError> {
                 let ty = buffer.try_get_i8()?;
                 let count = buffer.try_get_u8()? as usize;
                 let values = iter::repeat_with(|| try {
-                    if cfg!(all(feature = "2010_1_shim", not(feature = "2011_11_shim"))) {
+                    if revision < Revision::R2011_11 {
                         Some(buffer.try_get_u16()? as u32)
                     } else {
                         buffer.try_get_smart32()?
@@ -629,14 +1075,40 @@ pub mod location_config_fields {
 
             let count = buffer.try_get_u8()? as usize;
 
-            let models = iter::repeat_with(|| sub_deserialize(buffer))
+            let models = iter::repeat_with(|| sub_deserialize(buffer, revision))
                 .take(count)
                 .collect::<Result<BTreeMap<_, _>, ReadError>>()?;
             Ok(Models { models })
         }
 
-        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2010_1_shim")))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn encode(&self, buffer: &mut BytesMut, revision: Revision) {
+            if revision < Revision::R2010_1 {
+                buffer.put_u8(self.models.values().map(Vec::len).sum::<usize>() as u8);
+                for (&r#type, values) in &self.models {
+                    for &value in values {
+                        buffer.put_u16(value.unwrap_or(0xFFFF) as u16);
+                        buffer.put_i8(r#type);
+                    }
+                }
+                return;
+            }
+            buffer.put_u8(self.models.len() as u8);
+            for (&ty, values) in &self.models {
+                buffer.put_i8(ty);
+                buffer.put_u8(values.len() as u8);
+                for &value in values {
+                    if revision < Revision::R2011_11 {
+                        buffer.put_u16(value.unwrap_or(0xFFFF) as u16);
+                    } else {
+                        buffer.put_smart32(value);
+                    }
+                }
+            }
+        }
+
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn deserialize(buffer: &mut Bytes, _revision: Revision) -> Result<Self, ReadError> {
             let count = buffer.try_get_u8()? as usize;
 
             let models = iter::repeat_with(|| try {
@@ -648,35 +1120,60 @@ pub mod location_config_fields {
             .collect::<Result<_, ReadError>>()?;
             Ok(Models { models })
         }
+
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn encode(&self, buffer: &mut BytesMut, _revision: Revision) {
+            buffer.put_u8(self.models.len() as u8);
+            for &(r#type, model) in &self.models {
+                buffer.put_u16(model);
+                buffer.put_u8(r#type);
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Models2 {
-        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2010_1_shim")))]
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
         pub models_2: Vec<u16>,
-        #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
+        #[cfg(feature = "rs3")]
         pub models_2: BTreeMap<u8, u32>,
+        /// The first model of each type. Before 2010_1 models have no type, and are given 10, that of centrepieces.
+        #[cfg(feature = "2008_3_shim")]
+        pub models_2: Vec<(u8, u32)>,
     }
 
     impl Models2 {
-        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2010_1_shim")))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn deserialize(buffer: &mut Bytes, _revision: Revision) -> Result<Self, ReadError> {
             let count = buffer.try_get_u8()? as usize;
 
             let models_2 = iter::repeat_with(|| buffer.try_get_u16()).take(count).collect::<Result<_, ReadError>>()?;
             Ok(Self { models_2 })
         }
 
-        #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
-        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        #[cfg(all(any(feature = "osrs", feature = "legacy"), not(feature = "2008_3_shim")))]
+        pub fn encode(&self, buffer: &mut BytesMut, _revision: Revision) {
+            buffer.put_u8(self.models_2.len() as u8);
+            for &model in &self.models_2 {
+                buffer.put_u16(model);
+            }
+        }
+
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn deserialize(buffer: &mut Bytes, revision: Revision) -> Result<Self, ReadError> {
             let count = buffer.try_get_u8()? as usize;
 
             let models_2 = iter::repeat_with(|| try {
+                #[cfg(feature = "2008_3_shim")]
+                if revision < Revision::R2010_1 {
+                    return Ok((10, buffer.try_get_u16()? as u32));
+                }
+
                 let r#type = buffer.try_get_u8()?;
                 let subcount = buffer.try_get_u8()?;
 
-                let model = if cfg!(feature = "2011_11_shim") {
+                let model = if revision >= Revision::R2011_11 {
                     buffer.try_get_smart32()?.unwrap()
                 } else {
                     buffer.try_get_u16()? as u32
@@ -692,10 +1189,32 @@ pub mod location_config_fields {
             .collect::<Result<_, ReadError>>()?;
             Ok(Self { models_2 })
         }
+
+        /// The models that follow the first one of each type are not decoded, so they are not written either.
+        #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+        pub fn encode(&self, buffer: &mut BytesMut, revision: Revision) {
+            buffer.put_u8(self.models_2.len() as u8);
+            // Entries of a map and of a list alike.
+            for (&r#type, &model) in self.models_2.iter().map(|(r#type, model)| (r#type, model)) {
+                #[cfg(feature = "2008_3_shim")]
+                if revision < Revision::R2010_1 {
+                    buffer.put_u16(model as u16);
+                    continue;
+                }
+
+                buffer.put_u8(r#type);
+                buffer.put_u8(1);
+                if revision >= Revision::R2011_11 {
+                    buffer.put_smart32(Some(model));
+                } else {
+                    buffer.put_u16(model as u16);
+                }
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Textures {
         pub textures: BTreeMap<u16, u16>,
     }
@@ -708,10 +1227,18 @@ pub mod location_config_fields {
                 .collect::<Result<BTreeMap<_, _>, ReadError>>()?;
             Ok(Textures { textures })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u8(self.textures.len() as u8);
+            for (&from, &to) in &self.textures {
+                buffer.put_u16(from);
+                buffer.put_u16(to);
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Unknown79 {
         pub unknown_1: u16,
 
@@ -739,10 +1266,20 @@ pub mod location_config_fields {
                 values,
             })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u16(self.unknown_1);
+            buffer.put_u16(self.unknown_2);
+            buffer.put_u8(self.unknown_3);
+            buffer.put_u8(self.values.len() as u8);
+            for &value in &self.values {
+                buffer.put_u16(value);
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone, Copy)]
+    #[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
     pub struct Unknown173 {
         pub unknown_1: u16,
 
@@ -756,10 +1293,15 @@ pub mod location_config_fields {
 
             Ok(Unknown173 { unknown_1, unknown_2 })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u16(self.unknown_1);
+            buffer.put_u16(self.unknown_2);
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone, Copy)]
+    #[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
     pub struct Unknown163 {
         pub unknown_1: i8,
 
@@ -784,10 +1326,17 @@ pub mod location_config_fields {
                 unknown_4,
             })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_i8(self.unknown_1);
+            buffer.put_i8(self.unknown_2);
+            buffer.put_i8(self.unknown_3);
+            buffer.put_i8(self.unknown_4);
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone, Copy)]
+    #[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
     pub struct Unknown78 {
         pub unknown_1: u16,
 
@@ -801,10 +1350,15 @@ pub mod location_config_fields {
 
             Ok(Self { unknown_1, unknown_2 })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u16(self.unknown_1);
+            buffer.put_u8(self.unknown_2);
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Unknown160 {
         pub values: Vec<u16>,
     }
@@ -815,10 +1369,17 @@ pub mod location_config_fields {
             let values = iter::repeat_with(|| buffer.try_get_u16()).take(count).collect::<Result<_, ReadError>>()?;
             Ok(Self { values })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u8(self.values.len() as u8);
+            for &value in &self.values {
+                buffer.put_u16(value);
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone, Copy)]
+    #[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
     pub struct Unknown201 {
         pub unknown_1: u16,
 
@@ -851,10 +1412,23 @@ pub mod location_config_fields {
                 unknown_6,
             })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            for value in [
+                self.unknown_1,
+                self.unknown_2,
+                self.unknown_3,
+                self.unknown_4,
+                self.unknown_5,
+                self.unknown_6,
+            ] {
+                buffer.put_unsigned_smart(value);
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct HeadModels {
         pub headmodels: Vec<(Option<u32>, u8)>,
     }
@@ -867,10 +1441,18 @@ pub mod location_config_fields {
                 .collect::<Result<_, ReadError>>()?;
             Ok(HeadModels { headmodels })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u8(self.headmodels.len() as u8);
+            for &(model, unknown) in &self.headmodels {
+                buffer.put_smart32(model);
+                buffer.put_u8(unknown);
+            }
+        }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Unknown204 {
         pub id: u16,
         pub some_bool: bool,
@@ -887,6 +1469,14 @@ pub mod location_config_fields {
 
             Ok(Self { id, some_bool, vec_1, vec_2 })
         }
+
+        pub fn encode(&self, buffer: &mut BytesMut) {
+            buffer.put_u16(self.id);
+            buffer.put_u8(self.some_bool as u8);
+            for value in self.vec_1.into_iter().chain(self.vec_2) {
+                buffer.put_u32(value);
+            }
+        }
     }
 }
 
@@ -894,29 +1484,18 @@ use location_config_fields::*;
 
 /// Save the location configs as `location_configs.json`. Exposed as `--dump location_configs`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let loc_configs = LocationConfig::dump_all(config)?.into_values().collect::<Vec<_>>();
-    let path = path!(config.output / "location_configs.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&loc_configs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export(config, "location_configs", &loc_configs)
 }
 
 ///Save the location configs as individual `json` files.
 pub fn export_each(config: &crate::cli::Config) -> CacheResult<()> {
-    let folder = path!(&config.output / "location_configs");
-    fs::create_dir_all(&folder).with_context(|| error::Io { path: folder.clone() })?;
-
     let configs = LocationConfig::dump_all(config)?;
-    configs.into_iter().par_bridge().try_for_each(|(id, location_config)| {
-        let path = path!(&folder / format!("{id}.json"));
-        let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-        let data = serde_json::to_string_pretty(&location_config).unwrap();
-        file.write_all(data.as_bytes()).context(error::Io { path })?;
-        Ok(())
-    })
+    configs
+        .into_iter()
+        .filter(|(id, _)| config.keeps(*id))
+        .par_bridge()
+        .try_for_each(|(id, location_config)| crate::output::put_json(config, format!("location_configs/{id}.json"), &location_config))
 }
 
 #[cfg(feature = "pyo3")]
@@ -963,7 +1542,7 @@ mod legacy {
         for id in 0..len {
             let piece_len = offset_data.try_get_u16().unwrap();
             let data = file.split_to(piece_len as usize);
-            let _loc = LocationConfig::deserialize(id as u32, data).unwrap();
+            let _loc = LocationConfig::deserialize(id as u32, data, Revision::compiled(), false).unwrap();
             //println!("{}", loc);
         }
         assert_eq!(offset_data, &[].as_slice());
@@ -1005,3 +1584,70 @@ mod map_tests {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{structures::paramtable::Param, types::variables::VarpOrVarbit};
+
+    #[test]
+    fn round_trip() {
+        let revision = Revision::compiled();
+        let loc = LocationConfig {
+            id: 1276,
+            name: Some("Tree".to_string().into()),
+            dim_x: Some(2),
+            dim_y: Some(2),
+            actions: Some([None, Some("Chop down".to_string().into()), None, None, None]),
+            mirror: Some(true),
+            morphs_1: Some(LocationMorphTable {
+                var: VarpOrVarbit::Varbit(5),
+                ids: vec![Some(1276), None],
+            }),
+            is_members: Some(true),
+            params: Some(ParamTable {
+                params: BTreeMap::from([(8178, Param::Integer(50923)), (8179, Param::String("Logs".to_string().into()))]),
+            }),
+            ..Default::default()
+        };
+
+        let data = loc.encode(revision);
+        let decoded = LocationConfig::deserialize(loc.id, data.clone(), revision, false).unwrap();
+        assert_eq!(decoded.to_string(), loc.to_string());
+        assert_eq!(decoded.encode(revision), data);
+    }
+
+    #[test]
+    fn empty_morphs() {
+        let revision = Revision::compiled();
+        let loc = LocationConfig {
+            morphs_1: Some(LocationMorphTable {
+                var: VarpOrVarbit::Varbit(5),
+                ids: Vec::new(),
+            }),
+            ..Default::default()
+        };
+
+        let decoded = LocationConfig::deserialize(loc.id, loc.encode(revision), revision, false).unwrap();
+        assert_eq!(decoded.morphs_1.unwrap().ids, vec![None]);
+    }
+
+    #[test]
+    #[cfg(feature = "2008_3_shim")]
+    fn older_revisions() {
+        // Before 2010_1 models are listed one by one, and before 2010_3 opcode 95 has no value.
+        let data = Bytes::from_static(&[1, 2, 0x12, 0x34, 10, 0x12, 0x35, 22, 95, 0]);
+        let loc = LocationConfig::deserialize(0, data.clone(), Revision::R2008_3, false).unwrap();
+        assert_eq!(loc.models.as_ref().unwrap().models[&10], vec![Some(0x1234)]);
+        assert_eq!(loc.unknown_95_flag, Some(true));
+        assert_eq!(loc.encode(Revision::R2008_3), data);
+
+        // Opcode 107 is only a mapfunction from 2009_1 on.
+        let data = Bytes::from_static(&[107, 0, 1, 0]);
+        assert!(LocationConfig::deserialize(0, data.clone(), Revision::R2008_3, false).is_err());
+        assert_eq!(
+            LocationConfig::deserialize(0, data, Revision::R2009_1, false).unwrap().mapfunction,
+            Some(1)
+        );
+    }
+}
@@ -1,23 +1,30 @@
+use core::panic::Location;
 use std::{
     collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
+    fmt::{self, Display, Formatter},
 };
 
 use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
-    buf::{BufExtra, JString},
+    buf::{BufExtra, JString, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
 use serde::Serialize;
 
+#[cfg(feature = "rs3")]
+use crate::definitions::worldmaps::MapZone;
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::definitions::{location_configs::LocationConfig, mapsquares::MapSquares};
 use crate::{
-    definitions::indextype::{ConfigType, IndexType},
+    definitions::{
+        indextype::{ConfigType, IndexType},
+        locations::Watery,
+    },
     structures::paramtable::ParamTable,
 };
 
@@ -80,65 +87,224 @@ pub struct MapLabelConfig {
     /// | 4149 | `String` | Tooltip |
     #[serde(flatten)]
     pub params: Option<ParamTable>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl MapLabelConfig {
+    /// The sprites this label draws on the map, if any.
+    pub fn sprites(&self) -> impl Iterator<Item = u32> {
+        [self.sprite, self.hover_sprite, self.background_sprite].into_iter().flatten()
+    }
+
     /// Returns a mapping of all [`MapLabelConfig`]s.
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, MapLabelConfig>> {
-        Ok(CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+        CacheIndex::new(IndexType::CONFIG, config.input.clone())?
             .archive(ConfigType::MAPLABELS)?
             .take_files()
             .into_iter()
-            .map(|(file_id, file)| (file_id, MapLabelConfig::deserialize(file_id, file)))
-            .collect())
+            .map(|(file_id, file)| try { (file_id, MapLabelConfig::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("maplabels", result))
+            .collect::<Result<_, ReadError>>()
+            .context(error::Read { what: "maplabels" })
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> MapLabelConfig {
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<MapLabelConfig, ReadError> {
         let mut maplabel = MapLabelConfig { id, ..Default::default() };
 
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
         loop {
-            match buffer.get_u8() {
-                0 => {
-                    debug_assert!(!buffer.has_remaining(), "{buffer:?}");
-                    break maplabel;
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(maplabel);
+                        }
+                    }
+                    1 => maplabel.sprite = Some(buffer.try_get_smart32()?.unwrap()),
+                    2 => maplabel.hover_sprite = Some(buffer.try_get_smart32()?.unwrap()),
+                    3 => maplabel.text = Some(buffer.try_get_string()?),
+                    4 => maplabel.label_colour_1 = Some(buffer.try_get_array()?),
+                    5 => maplabel.label_colour_2 = Some(buffer.try_get_array()?),
+                    6 => maplabel.font_size = Some(buffer.try_get_u8()?),
+                    7 => maplabel.unknown_7 = Some(buffer.try_get_u8()?),
+                    8 => maplabel.unknown_8 = Some(buffer.try_get_u8()?),
+                    9 => maplabel.toggle_1 = Some(Toggle::deserialize(&mut buffer)?),
+                    10 => maplabel.rightclick_1 = Some(buffer.try_get_string()?),
+                    15 => maplabel.polygon = Some(Polygon::deserialize(&mut buffer)?),
+                    17 => maplabel.rightclick_2 = Some(buffer.try_get_string()?),
+                    19 => maplabel.category = Some(buffer.try_get_u16()?),
+                    20 => maplabel.toggle_2 = Some(Toggle::deserialize(&mut buffer)?),
+                    21 => maplabel.unknown_21 = Some(buffer.try_get_array()?),
+                    22 => maplabel.unknown_22 = Some(buffer.try_get_array()?),
+                    25 => maplabel.background_sprite = Some(buffer.try_get_smart32()?.unwrap()),
+                    26 => maplabel.legacy_switch = Some(LegacySwitch::deserialize(&mut buffer)?),
+                    28 => maplabel.unknown_28 = Some(buffer.try_get_u8()?),
+                    30 => maplabel.unknown_30 = Some(buffer.try_get_u8()?),
+                    249 => maplabel.params = Some(ParamTable::deserialize(&mut buffer)),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    maplabel.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &maplabel,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(maplabel);
                 }
-                1 => maplabel.sprite = Some(buffer.get_smart32().unwrap()),
-                2 => maplabel.hover_sprite = Some(buffer.get_smart32().unwrap()),
-                3 => maplabel.text = Some(buffer.get_string()),
-                4 => maplabel.label_colour_1 = Some(buffer.get_rgb()),
-                5 => maplabel.label_colour_2 = Some(buffer.get_rgb()),
-                6 => maplabel.font_size = Some(buffer.get_u8()),
-                7 => maplabel.unknown_7 = Some(buffer.get_u8()),
-                8 => maplabel.unknown_8 = Some(buffer.get_u8()),
-                9 => maplabel.toggle_1 = Some(Toggle::deserialize(&mut buffer)),
-                10 => maplabel.rightclick_1 = Some(buffer.get_string()),
-                15 => maplabel.polygon = Some(Polygon::deserialize(&mut buffer)),
-                17 => maplabel.rightclick_2 = Some(buffer.get_string()),
-                19 => maplabel.category = Some(buffer.get_u16()),
-                20 => maplabel.toggle_2 = Some(Toggle::deserialize(&mut buffer)),
-                21 => maplabel.unknown_21 = Some(buffer.get_array()),
-                22 => maplabel.unknown_22 = Some(buffer.get_array()),
-                25 => maplabel.background_sprite = Some(buffer.get_smart32().unwrap()),
-                26 => maplabel.legacy_switch = Some(LegacySwitch::deserialize(&mut buffer)),
-                28 => maplabel.unknown_28 = Some(buffer.get_u8()),
-                30 => maplabel.unknown_30 = Some(buffer.get_u8()),
-                249 => maplabel.params = Some(ParamTable::deserialize(&mut buffer)),
-                other => unimplemented!("{}", other),
             }
         }
     }
 }
 
+impl Display for MapLabelConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
 ///Save the maplabels as `maplabels.json`. Exposed as `--dump maplabels`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut labels = MapLabelConfig::dump_all(config)?.into_values().collect::<Vec<_>>();
     labels.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(config.output / "map_labels.json");
+    crate::output::export(config, "map_labels", &labels)
+}
+
+/// A [`MapLabelConfig`] placed on the map by a location.
+#[derive(Serialize, Clone, Debug)]
+pub struct PlacedMapLabel<'a> {
+    /// The world coordinate of the location.
+    pub x: u32,
+    pub y: u32,
+    /// The plane of the location.
+    pub plane: Watery,
+    /// The id of the location that places it.
+    pub location: u32,
+    pub label: &'a MapLabelConfig,
+}
+
+/// The label a location places on the map, if any.
+///
+/// On osrs this is its map area. The mapfunction of osrs locations is an index into the mapfunction sprites,
+/// not a label, so only rs3 (and 2008 caches, which have the rs3 opcode) fall back to it.
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn placed_by(loc: &LocationConfig) -> Option<u32> {
+    #[cfg(all(feature = "osrs", not(feature = "2008_3_shim")))]
+    return loc.maparea_id.map(u32::from);
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+    return loc.mapfunction.map(u32::from);
+}
+
+/// A named area of the world map, placed at its centre.
+#[cfg(feature = "rs3")]
+#[derive(Serialize, Debug)]
+pub struct PlacedMapArea<'a> {
+    /// The world coordinate of the centre of the area.
+    pub x: u16,
+    pub y: u16,
+    pub plane: u8,
+    /// The name, default zoom and bounds of the area.
+    pub area: &'a MapZone,
+}
 
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&labels).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
+/// Save every label placed on the map as `map_label_placements.json`, with the world coordinate of each,
+/// so map frontends can draw the same labels as the game's world map. Exposed as `--dump map_label_placements`.
+///
+/// On osrs the labels are the area configs that locations refer to. On rs3, which names its areas
+/// in the world map index instead, those are saved as `map_areas.json`, placed at their centre.
+/// Areas without a centre are left out; `--dump map_zones` has all of them.
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn export_placements(config: &crate::cli::Config) -> CacheResult<()> {
+    let labels = MapLabelConfig::dump_all(config)?;
+    let locations = LocationConfig::dump_all(config)?
+        .into_iter()
+        .filter_map(|(id, loc)| Some((id, placed_by(&loc)?)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut placements = Vec::new();
+    for square in MapSquares::new(config)?.into_iter() {
+        let Some(placed) = square?.take_locations() else { continue };
+        for loc in placed {
+            let Some(label) = locations.get(&loc.id).and_then(|id| labels.get(id)) else { continue };
+            placements.push(PlacedMapLabel {
+                x: loc.i as u32 * 64 + loc.x as u32,
+                y: loc.j as u32 * 64 + loc.y as u32,
+                plane: loc.plane,
+                location: loc.id,
+                label,
+            });
+        }
+    }
+    placements.sort_by_key(|placement| (placement.x, placement.y, placement.location));
+    crate::output::export(config, "map_label_placements", &placements)?;
+
+    #[cfg(feature = "rs3")]
+    {
+        // In id order, so areas at the same centre stay in a stable order.
+        let zones = MapZone::dump_all(config)?.into_iter().collect::<BTreeMap<_, _>>();
+        let mut areas = zones
+            .values()
+            .filter_map(|zone| {
+                let centre = zone.center()?;
+                Some(PlacedMapArea {
+                    x: centre.x,
+                    y: centre.y,
+                    plane: centre.plane,
+                    area: zone,
+                })
+            })
+            .collect::<Vec<_>>();
+        areas.sort_by_key(|area| (area.x, area.y, area.plane));
+        crate::output::export(config, "map_areas", &areas)?;
+    }
+    Ok(())
+}
+
+/// Save the sprites drawn by map labels as `map_icons/<sprite id>.png`,
+/// so they can be placed on rendered tiles together with `map_labels.json`. Exposed as `--dump map_icons`.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub fn export_icons(config: &crate::cli::Config) -> CacheResult<()> {
+    use std::collections::BTreeSet;
+
+    use path_macro::path;
+    use rs3cache_backend::warning::{self, Warning};
+
+    use crate::{definitions::sprites, renderers::png};
+
+    let ids = MapLabelConfig::dump_all(config)?
+        .values()
+        .flat_map(MapLabelConfig::sprites)
+        .filter(|id| config.keeps(*id))
+        .collect::<BTreeSet<u32>>();
+    let icons = sprites::dumps(1, ids.iter().copied().collect(), config)?;
+
+    for id in ids {
+        match icons.get(&(id, 0)) {
+            Some(icon) => png::put(config, path!("map_icons" / format!("{id}.png")), icon)?,
+            None => warning::warn(Warning::MissingSprite { id, frame: 0 }),
+        }
+    }
     Ok(())
 }
 
@@ -147,10 +313,11 @@ pub mod maplabel_config_fields {
 
     use std::iter;
 
-    use bytes::{Buf, Bytes};
+    use bytes::Bytes;
     use itertools::izip;
     #[cfg(feature = "pyo3")]
     use pyo3::prelude::*;
+    use rs3cache_backend::buf::{BufExtra, ReadError};
     use serde::Serialize;
 
     use crate::types::variables::{Varbit, Varp, VarpOrVarbit};
@@ -171,25 +338,27 @@ pub mod maplabel_config_fields {
     }
 
     impl Polygon {
-        pub fn deserialize(buffer: &mut Bytes) -> Polygon {
-            let point_count = buffer.get_u8() as usize;
-            let xy = iter::repeat_with(|| (buffer.get_i16(), buffer.get_i16()))
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Polygon, ReadError> {
+            let point_count = buffer.try_get_u8()? as usize;
+            let xy = iter::repeat_with(|| try { (buffer.try_get_u16()? as i16, buffer.try_get_u16()? as i16) })
                 .take(point_count)
-                .collect::<Vec<(i16, i16)>>();
+                .collect::<Result<Vec<(i16, i16)>, ReadError>>()?;
 
-            let colour = [buffer.get_u8(), buffer.get_u8(), buffer.get_u8(), buffer.get_u8()];
-            assert_eq!(buffer.get_u8(), 1_u8);
+            let colour = buffer.try_get_array()?;
+            assert_eq!(buffer.try_get_u8()?, 1_u8);
 
-            let background_colour = [buffer.get_u8(), buffer.get_u8(), buffer.get_u8(), buffer.get_u8()];
+            let background_colour = buffer.try_get_array()?;
 
-            let planes = iter::repeat_with(|| buffer.get_u8()).take(point_count).collect::<Vec<_>>();
+            let planes = iter::repeat_with(|| buffer.try_get_u8())
+                .take(point_count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
             let points = izip!(planes, xy).map(|(plane, (dx, dy))| PolygonPoint { plane, dx, dy }).collect();
 
-            Polygon {
+            Ok(Polygon {
                 colour,
                 background_colour,
                 points,
-            }
+            })
         }
     }
 
@@ -210,15 +379,15 @@ pub mod maplabel_config_fields {
     }
 
     impl Toggle {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let varbit = Varbit::new(buffer.get_u16());
-            let varp = Varp::new(buffer.get_u16());
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let varbit = Varbit::new(buffer.try_get_u16()?);
+            let varp = Varp::new(buffer.try_get_u16()?);
             let var = VarpOrVarbit::new(varp, varbit);
 
-            let lower = buffer.get_u32();
-            let upper = buffer.get_u32();
+            let lower = buffer.try_get_u32()?;
+            let upper = buffer.try_get_u32()?;
 
-            Self { var, lower, upper }
+            Ok(Self { var, lower, upper })
         }
     }
 
@@ -242,22 +411,22 @@ pub mod maplabel_config_fields {
     }
 
     impl LegacySwitch {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let varbit = Varbit::new(buffer.get_u16());
-            let varp = Varp::new(buffer.get_u16());
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let varbit = Varbit::new(buffer.try_get_u16()?);
+            let varp = Varp::new(buffer.try_get_u16()?);
             let var = VarpOrVarbit::new(varp, varbit);
 
             // always 0 or 1 (boolean)
-            let value = buffer.get_u8();
-            let default_reference = buffer.get_u16();
-            let legacy_reference = buffer.get_u16();
+            let value = buffer.try_get_u8()?;
+            let default_reference = buffer.try_get_u16()?;
+            let legacy_reference = buffer.try_get_u16()?;
 
-            Self {
+            Ok(Self {
                 var,
                 value,
                 default_reference,
                 legacy_reference,
-            }
+            })
         }
     }
 
@@ -289,4 +458,31 @@ mod tests {
         MapLabelConfig::dump_all(&config)?;
         Ok(())
     }
+
+    #[test]
+    fn sprites() {
+        let label = MapLabelConfig {
+            sprite: Some(1),
+            background_sprite: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(label.sprites().collect::<Vec<_>>(), vec![1, 3]);
+    }
+
+    #[test]
+    fn placed() {
+        let loc = LocationConfig {
+            mapfunction: Some(5),
+            ..Default::default()
+        };
+        // Only rs3 mapfunctions are labels.
+        #[cfg(feature = "rs3")]
+        assert_eq!(placed_by(&loc), Some(5));
+        #[cfg(all(feature = "osrs", not(feature = "2008_3_shim")))]
+        {
+            assert_eq!(placed_by(&loc), None);
+            let loc = LocationConfig { maparea_id: Some(7), ..loc };
+            assert_eq!(placed_by(&loc), Some(7));
+        }
+    }
 }
@@ -5,7 +5,7 @@ use rs3cache_backend::{buf::BufExtra, error::CacheResult, index::CacheIndex};
 
 use crate::definitions::indextype::{ConfigType, IndexType};
 /// A configuration of a sprite that can be drawn on the world map.
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct MapScene {
     /// Its id.
     pub id: u32,
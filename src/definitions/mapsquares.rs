@@ -26,13 +26,16 @@ use itertools::{iproduct, Product};
 use ndarray::{iter::LanesIter, s, Axis, Dim};
 use path_macro::path;
 use rayon::iter::{ParallelBridge, ParallelIterator};
-#[cfg(all(feature = "osrs", not(feature = "2013_4_shim")))]
-use rs3cache_backend::xtea::Xtea;
 use rs3cache_backend::{
     error::{self, CacheResult},
     index::{CacheIndex, Initial},
 };
-use rs3cache_utils::rangeclamp::RangeClamp;
+#[cfg(all(feature = "osrs", not(feature = "2013_4_shim")))]
+use rs3cache_backend::{
+    warning::{self, Warning},
+    xtea::Xtea,
+};
+use rs3cache_utils::{bar::Render, rangeclamp::RangeClamp};
 #[cfg(any(feature = "rs3", feature = "2013_4_shim"))]
 use {
     crate::definitions::indextype::MapFileType,
@@ -43,6 +46,7 @@ use {
 
 pub use self::iterator::*;
 use crate::definitions::{
+    location_configs::LocationConfig,
     locations::Location,
     tiles::{Tile, TileArray},
 };
@@ -102,10 +106,7 @@ impl MapSquare {
 
     #[cfg(all(test, any(feature = "rs3", feature = "2013_4_shim")))]
     pub fn new(i: u8, j: u8, config: &crate::cli::Config) -> CacheResult<MapSquare> {
-        assert!(i < 0x7F, "Index out of range.");
-        let archive_id = (i as u32) | (j as u32) << 7;
-        let archive = CacheIndex::new(crate::definitions::indextype::IndexType::MAPSV2, config.input.clone())?.archive(archive_id)?;
-        Ok(Self::from_archive(archive))
+        MapSquares::new(config)?.get(i, j)
     }
 
     #[cfg(all(feature = "osrs", not(feature = "2013_4_shim")))]
@@ -124,7 +125,13 @@ impl MapSquare {
         let locations = match land {
             Ok(land) => Some(Location::dump(i, j, &tiles, land)),
             // most likely xtea error...
-            Err(_) => None,
+            Err(_) => {
+                warning::warn(Warning::MissingKey {
+                    what: "xtea for mapsquare",
+                    key: format!("{i}_{j}"),
+                });
+                None
+            }
         };
 
         Ok(MapSquare {
@@ -214,6 +221,13 @@ impl MapSquare {
         self.locations
     }
 
+    /// Whether it has tiles but its locations could not be read, as happens in osrs when its xtea key is missing.
+    ///
+    /// Other caches are not encrypted, so there a mapsquare without locations simply has none.
+    pub fn is_missing_locations(&self) -> bool {
+        cfg!(all(feature = "osrs", not(feature = "2013_4_shim"))) && self.tiles.is_some() && self.locations.is_none()
+    }
+
     /// Returns a view over the `locations` field, if present.
     #[cfg(any(feature = "rs3", feature = "2013_4_shim"))]
     pub fn water_locations(&self) -> Option<&Result<Vec<Location>, CacheError>> {
@@ -221,6 +235,18 @@ impl MapSquare {
     }
 }
 
+/// The mapsquares of a cache, to [`get`](MapSquares::get) one at a time or to iterate over.
+///
+/// Every backend reads them the same way:
+///
+/// ```no_run
+/// # use rs3cache::{cli::Config, definitions::mapsquares::MapSquares};
+/// let mapsquares = MapSquares::new(&Config::env())?;
+/// let lumbridge = mapsquares.get(50, 50)?;
+/// assert_eq!((lumbridge.i(), lumbridge.j()), (50, 50));
+/// assert!(mapsquares.contains(50, 50));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 pub struct MapSquares {
     index: CacheIndex<Initial>,
     #[cfg(all(feature = "osrs", not(feature = "2013_4_shim")))]
@@ -233,33 +259,64 @@ impl IntoIterator for MapSquares {
     type Item = CacheResult<MapSquare>;
     type IntoIter = MapSquareIterator;
 
-    #[cfg(any(feature = "rs3", feature = "2013_4_shim"))]
     fn into_iter(self) -> Self::IntoIter {
-        let state = self
+        let state = self.coordinates().into_iter();
+        MapSquareIterator { mapsquares: self, state }
+    }
+}
+
+impl MapSquares {
+    /// The crcs of the archives that each mapsquare is made of, by the coordinates of the mapsquare.
+    ///
+    /// A mapsquare has the same tiles and locations in two caches if these are equal.
+    #[cfg(any(feature = "rs3", feature = "2013_4_shim"))]
+    pub fn crcs(&self) -> CacheResult<std::collections::BTreeMap<(u8, u8), Vec<i32>>> {
+        Ok(self
             .index
             .metadatas()
-            .keys()
-            .map(|id| ((id & 0x7F) as u8, (id >> 7) as u8))
-            .collect::<Vec<_>>()
-            .into_iter();
-        MapSquareIterator { mapsquares: self, state }
+            .iter()
+            .map(|(id, meta)| (((id & 0x7F) as u8, (id >> 7) as u8), vec![meta.crc()]))
+            .collect())
     }
 
+    /// The crcs of the archives that each mapsquare is made of, by the coordinates of the mapsquare.
+    ///
+    /// A mapsquare has the same tiles and locations in two caches if these are equal.
     #[cfg(all(feature = "osrs", not(feature = "2013_4_shim")))]
-    fn into_iter(self) -> Self::IntoIter {
-        let state = self
-            .mapping
-            .keys()
-            .filter_map(|(ty, i, j)| if *ty == "m" { Some((*i, *j)) } else { None })
-            .collect::<Vec<_>>()
-            .into_iter();
-        MapSquareIterator { mapsquares: self, state }
+    pub fn crcs(&self) -> CacheResult<std::collections::BTreeMap<(u8, u8), Vec<i32>>> {
+        let mut crcs = std::collections::BTreeMap::<_, Vec<i32>>::new();
+        for ((ty, i, j), archive_id) in &self.mapping {
+            if let ("l" | "m", Some(meta)) = (*ty, self.index.metadatas().get(archive_id)) {
+                crcs.entry((*i, *j)).or_default().push(meta.crc());
+            }
+        }
+        Ok(crcs)
     }
 
+    /// The crcs of the archives that each mapsquare is made of, by the coordinates of the mapsquare.
+    ///
+    /// A mapsquare has the same tiles and locations in two caches if these are equal.
+    /// These caches have no crcs of their archives, so they are computed from the files.
     #[cfg(feature = "legacy")]
-    fn into_iter(self) -> Self::IntoIter {
-        let state = self.meta.keys().copied().collect::<Vec<_>>().into_iter();
-        MapSquareIterator { mapsquares: self, state }
+    pub fn crcs(&self) -> CacheResult<std::collections::BTreeMap<(u8, u8), Vec<i32>>> {
+        self.meta
+            .iter()
+            .map(|(coordinates, meta)| {
+                let crcs = [meta.locfile, meta.mapfile]
+                    .into_iter()
+                    .map(|archive_id| {
+                        let file = self
+                            .index
+                            .archive(archive_id as u32)?
+                            .file(&0)
+                            .context(rs3cache_backend::index::Other)
+                            .context(error::Integrity)?;
+                        Ok(rs3cache_backend::hash::crc32(file))
+                    })
+                    .collect::<CacheResult<Vec<i32>>>()?;
+                Ok((*coordinates, crcs))
+            })
+            .collect()
     }
 }
 
@@ -342,6 +399,51 @@ impl GroupMapSquare {
     }
 }
 
+/// Calls `visit` on every [`MapSquare`], in parallel and with a progress bar labelled `name`.
+///
+/// Meant for custom scans of the world, such as counting resources. Stops at the first mapsquare that fails to decode.
+///
+/// ```rust,no_run
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # use rs3cache::{cli::Config, definitions::mapsquares::for_each_square};
+/// let config = Config::env();
+/// let trees = AtomicUsize::new(0);
+/// for_each_square(&config, "counting trees", |sq| {
+///     let count = sq.locations().into_iter().flatten().filter(|loc| loc.id == 1276).count();
+///     trees.fetch_add(count, Ordering::Relaxed);
+/// })?;
+/// # Ok::<(), rs3cache_backend::error::CacheError>(())
+/// ```
+pub fn for_each_square<F>(config: &crate::cli::Config, name: &'static str, visit: F) -> CacheResult<()>
+where
+    F: Fn(&MapSquare) + Sync + Send,
+{
+    visit_each(MapSquares::new(config)?.into_iter(), name, visit)
+}
+
+/// Calls `visit` on every item of `items` that decoded, stopping at the first that did not.
+fn visit_each<T, F>(items: impl ExactSizeIterator<Item = CacheResult<T>> + Send, name: &'static str, visit: F) -> CacheResult<()>
+where
+    T: Send,
+    F: Fn(&T) + Sync + Send,
+{
+    items.render(name).try_for_each(|(item, _)| {
+        visit(&item?);
+        Ok(())
+    })
+}
+
+/// Like [`for_each_square`], but `visit` also gets the surrounding mapsquares, as the map renderer does.
+pub fn for_each_group<F>(config: &crate::cli::Config, name: &'static str, visit: F) -> CacheResult<()>
+where
+    F: Fn(&GroupMapSquare) + Sync + Send,
+{
+    GroupMapSquareIterator::new(-1_i32..=1_i32, -1_i32..=1_i32, config)?
+        .render(name)
+        .for_each(|(gsq, _)| visit(&gsq));
+    Ok(())
+}
+
 /// Saves all occurences of every object id as a `json` file to the folder `out/data/rs3/locations`.
 pub fn export_locations_by_id(config: &crate::cli::Config) -> CacheResult<()> {
     let out = path_macro::path!(config.output / "locations");
@@ -377,16 +479,71 @@ pub fn export_locations_by_id(config: &crate::cli::Config) -> CacheResult<()> {
         })
         .par_bridge()
         .for_each(|(id, id_locs)| {
-            if !id_locs.is_empty() && id != 83 {
+            if !id_locs.is_empty() && id != 83 && config.keeps(id) {
                 let mut file = File::create(path!(&out / format!("{id}.json"))).unwrap();
-                let data = serde_json::to_string_pretty(&id_locs).unwrap();
-                file.write_all(data.as_bytes()).unwrap();
+                file.write_all(&crate::output::to_json(config, &id_locs)).unwrap();
             }
         });
 
     Ok(())
 }
 
+/// Saves every placed location as a point feature in `locations.geojson`. Exposed as `--dump locations --format geojson`.
+///
+/// Points are in game coordinates, at the centre of their tile. This is the coordinate system rendered tiles use,
+/// so the output can be loaded straight into Leaflet (with `CRS.Simple`) or QGIS.
+pub fn export_locations_geojson(config: &crate::cli::Config) -> CacheResult<()> {
+    let names = LocationConfig::dump_all(config)?
+        .into_iter()
+        .filter_map(|(id, loc)| Some((id, loc.name?)))
+        .collect::<HashMap<_, _>>();
+
+    let mut out = br#"{"type":"FeatureCollection","features":["#.to_vec();
+    let mut first = true;
+    for sq in MapSquares::new(config)?.into_iter() {
+        let Some(locations) = sq?.take_locations() else { continue };
+        for loc in locations.into_iter().filter(|loc| config.keeps(loc.id)) {
+            if !first {
+                out.push(b',');
+            }
+            first = false;
+
+            let x = loc.i as f64 * 64.0 + loc.x as f64 + 0.5;
+            let y = loc.j as f64 * 64.0 + loc.y as f64 + 0.5;
+            let mut feature = serde_json::json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": [x, y] },
+                "properties": {
+                    "id": loc.id,
+                    "name": names.get(&loc.id).map(|name| &**name),
+                    "type": loc.r#type,
+                    "rotation": loc.rotation,
+                    "plane": loc.plane,
+                },
+            });
+            if config.deterministic {
+                crate::output::canonical::canonicalize(&mut feature);
+            }
+            serde_json::to_writer(&mut out, &feature).unwrap();
+        }
+    }
+    out.extend(b"]}");
+    config.sink()?.put("locations.geojson", &out)
+}
+
+/// Saves every placed location as a line of `locations.jsonl`, a mapsquare at a time. Exposed as `--dump locations --format jsonl`.
+pub fn export_locations_jsonl(config: &crate::cli::Config) -> CacheResult<()> {
+    let locations = MapSquares::new(config)?.into_iter().flat_map(|sq| {
+        // A mapsquare that failed to decode is passed on as its error, which stops the export.
+        let (locations, error) = match sq {
+            Ok(sq) => (sq.take_locations().unwrap_or_default(), None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        locations.into_iter().map(Ok).chain(error.map(Err))
+    });
+    crate::output::export_iter(config, "locations", locations)
+}
+
 /// Saves all occurences of every object id as a `json` file to the folder `out/data/rs3/locations`.
 pub fn export_locations_by_square(config: &crate::cli::Config) -> CacheResult<()> {
     let out = path_macro::path!(config.output / "locations");
@@ -399,8 +556,7 @@ pub fn export_locations_by_square(config: &crate::cli::Config) -> CacheResult<()
         if let Some(locations) = sq.take_locations() {
             if !locations.is_empty() {
                 let mut file = File::create(path!(&out / format!("{i}_{j}.json"))).unwrap();
-                let data = serde_json::to_string_pretty(&locations).unwrap();
-                file.write_all(data.as_bytes()).unwrap();
+                file.write_all(&crate::output::to_json(config, &locations)).unwrap();
             }
         }
     });
@@ -420,8 +576,7 @@ pub fn export_tiles_by_square(config: &crate::cli::Config) -> CacheResult<()> {
         if let Some(tiles) = sq.take_tiles() {
             if !tiles.is_empty() {
                 let mut file = File::create(path!(&out / format!("{i}_{j}.json"))).unwrap();
-                let data = serde_json::to_string_pretty(&tiles).unwrap();
-                file.write_all(data.as_bytes()).unwrap();
+                file.write_all(&crate::output::to_json(config, &tiles)).unwrap();
             }
         }
     });
@@ -429,6 +584,87 @@ pub fn export_tiles_by_square(config: &crate::cli::Config) -> CacheResult<()> {
     Ok(())
 }
 
+/// Saves the raw data of the tiles of every mapsquare, as `tiles/<i>_<j>.csv` with `--format csv`
+/// or as `tiles/<i>_<j>.npy` with `--format npy`. Other formats are rejected by [`Dump::accepts`](crate::cli::Dump::accepts).
+///
+/// The csv has a row per tile, with its `plane`, `x` and `y` and then its [`fields`](Tile::fields); missing ones are empty.
+/// The npy array has a shape of `(4, 64, 64, 6)`, indexed by plane, x, y and field, in which missing fields are `-1`.
+pub fn export_tiles_table(config: &crate::cli::Config) -> CacheResult<()> {
+    use crate::cli::Format;
+
+    let sink = config.sink()?;
+    MapSquares::new(config)?.into_iter().par_bridge().try_for_each(|sq| {
+        let sq = sq?;
+        let (i, j) = (sq.i, sq.j);
+        let Some(tiles) = sq.tiles() else { return Ok(()) };
+        match config.format {
+            Format::Npy => sink.put(format!("tiles/{i}_{j}.npy"), &tiles_npy(tiles)),
+            Format::Csv => sink.put(format!("tiles/{i}_{j}.csv"), tiles_csv(tiles).as_bytes()),
+            format => unreachable!("--dump tiles does not accept {format:?}"),
+        }
+    })
+}
+
+fn tiles_npy(tiles: &TileArray) -> Vec<u8> {
+    let data = tiles
+        .iter()
+        .flat_map(|tile| tile.fields().map(|field| field.unwrap_or(-1)))
+        .collect::<Vec<_>>();
+    let shape = [4, 64, 64, Tile::FIELDS.len()];
+    crate::output::npy::write_i32(&shape, &data)
+}
+
+fn tiles_csv(tiles: &TileArray) -> String {
+    let mut out = format!("plane,x,y,{}\r\n", Tile::FIELDS.join(","));
+    for ((plane, x, y), tile) in tiles.indexed_iter() {
+        let fields = tile.fields().map(|field| field.map_or_else(String::new, |field| field.to_string()));
+        out.push_str(&format!("{plane},{x},{y},{}\r\n", fields.join(",")));
+    }
+    out
+}
+
+#[cfg(test)]
+mod table_tests {
+    use ndarray::Array;
+
+    use super::*;
+
+    fn tiles() -> TileArray {
+        let mut tiles = Array::default((4, 64, 64));
+        tiles[[1, 2, 3]] = Tile {
+            shape: Some(0b1010),
+            height: Some(20),
+            ..Default::default()
+        };
+        tiles
+    }
+
+    #[test]
+    fn csv() {
+        let csv = tiles_csv(&tiles());
+        let mut lines = csv.split("\r\n");
+        assert_eq!(
+            lines.next(),
+            Some("plane,x,y,height,overlay_id,overlay_shape,overlay_rotation,underlay_id,settings")
+        );
+        assert_eq!(lines.next(), Some("0,0,0,,,,,,"));
+        assert!(csv.contains("\r\n1,2,3,20,,2,2,,\r\n"));
+        assert_eq!(csv.lines().count(), 1 + 4 * 64 * 64);
+    }
+
+    #[test]
+    fn npy() {
+        let npy = tiles_npy(&tiles());
+        let fields = Tile::FIELDS.len();
+        // each value is four bytes, after the header
+        let data = &npy[npy.len() - 4 * 64 * 64 * fields * 4..];
+        let index = |plane: usize, x: usize, y: usize| ((plane * 64 + x) * 64 + y) * fields;
+        let offset = index(1, 2, 3) * 4;
+        let value = |field: usize| i32::from_le_bytes(data[offset + field * 4..][..4].try_into().unwrap());
+        assert_eq!((0..fields).map(value).collect::<Vec<_>>(), vec![20, -1, 2, 2, -1, -1]);
+    }
+}
+
 #[cfg(all(test, any(feature = "rs3", feature = "osrs")))]
 mod tests {
     use super::*;
@@ -521,6 +757,60 @@ mod map_tests {
         Ok(())
     }
 
+    #[test]
+    fn visit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let sum = AtomicUsize::new(0);
+        let items = vec![Ok(1), Ok(2), Ok(3)];
+        visit_each(items.into_iter(), "test", |item| {
+            sum.fetch_add(*item, Ordering::Relaxed);
+        })
+        .unwrap();
+        assert_eq!(sum.into_inner(), 6);
+
+        let failed = Err(std::io::Error::from(std::io::ErrorKind::InvalidData)).context(error::Io { path: "test".into() });
+        let items = vec![Ok(1), failed, Ok(3)];
+        assert!(visit_each(items.into_iter(), "test", |_| {}).is_err());
+    }
+
+    #[test]
+    fn visit_squares() -> CacheResult<()> {
+        let config = crate::cli::Config::env();
+        let count = std::sync::atomic::AtomicUsize::new(0);
+
+        for_each_square(&config, "test", |_| {
+            count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        })?;
+        assert_eq!(count.into_inner(), MapSquares::new(&config)?.into_iter().len());
+        Ok(())
+    }
+
+    #[test]
+    fn random_access() -> CacheResult<()> {
+        let config = crate::cli::Config::env();
+        let mapsquares = MapSquares::new(&config)?;
+
+        let square = mapsquares.get(50, 50)?;
+        assert_eq!((square.i(), square.j()), (50, 50));
+        assert!(mapsquares.contains(50, 50));
+        assert!(mapsquares.coordinates().contains(&(50, 50)));
+
+        assert!(!mapsquares.contains(127, 255));
+        assert!(mapsquares.get(127, 255).is_err());
+
+        // Would alias 50, 50 if it were not rejected.
+        assert!(!mapsquares.contains(0x80 + 50, 49));
+        assert!(matches!(
+            mapsquares.get(0x80 + 50, 49),
+            Err(rs3cache_backend::error::CacheError::Integrity {
+                source: rs3cache_backend::index::IntegrityError::ArchiveMissing { .. },
+                ..
+            })
+        ));
+        Ok(())
+    }
+
     #[test]
     fn members() -> CacheResult<()> {
         let config = crate::cli::Config::env();
@@ -1,27 +1,48 @@
 use core::ops::RangeInclusive;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use ::error::Context;
 use itertools::iproduct;
 use rs3cache_backend::{
-    error::CacheResult,
+    error::{self, CacheResult},
     index::{self, CacheIndex, MapsquareMeta},
 };
 
 use crate::definitions::mapsquares::{GroupMapSquare, MapSquare, MapSquares};
 
 impl MapSquares {
+    /// Opens the mapsquares of the cache, without reading any of them yet.
     pub fn new(config: &crate::cli::Config) -> CacheResult<MapSquares> {
         let mut index = CacheIndex::new(4, config.input.clone())?;
         let meta = index.get_index();
         Ok(MapSquares { index, meta })
     }
 
+    /// Reads the mapsquare at `i, j`, such as `50, 50` for Lumbridge.
+    ///
+    /// Fails if the cache has no mapsquare there.
     pub fn get(&self, i: u8, j: u8) -> CacheResult<MapSquare> {
-        let loc = self.meta[&(i, j)].locfile as u32;
-        let map = self.meta[&(i, j)].mapfile as u32;
-        let sq = MapSquare::new(&self.index, loc, map, i, j)?;
+        let meta = self
+            .meta
+            .get(&(i, j))
+            .with_context(|| index::ArchiveMissingNamed {
+                index_id: 4,
+                name: format!("m{i}_{j}"),
+            })
+            .context(error::Integrity)?;
+        let sq = MapSquare::new(&self.index, meta.locfile as u32, meta.mapfile as u32, i, j)?;
         Ok(sq)
     }
+
+    /// Whether the cache has a mapsquare at `i, j`.
+    pub fn contains(&self, i: u8, j: u8) -> bool {
+        self.meta.contains_key(&(i, j))
+    }
+
+    /// The coordinates of every mapsquare, in order.
+    pub fn coordinates(&self) -> Vec<(u8, u8)> {
+        self.meta.keys().copied().collect()
+    }
 }
 
 /// Iterates over all [`MapSquare`]s in arbitrary order.
@@ -30,13 +51,6 @@ pub struct MapSquareIterator {
     pub(crate) state: std::vec::IntoIter<(u8, u8)>,
 }
 
-impl MapSquareIterator {
-    /// Constructor for MapSquareIterator.
-    pub fn new() -> CacheResult<Self> {
-        todo!()
-    }
-}
-
 impl Iterator for MapSquareIterator {
     type Item = CacheResult<MapSquare>;
 
@@ -75,6 +89,23 @@ impl GroupMapSquareIterator {
             range_j,
         })
     }
+
+    /// Only iterates over those of the mapsquares at `coordinates` that are in the cache.
+    pub fn new_only(
+        range_i: RangeInclusive<i32>,
+        range_j: RangeInclusive<i32>,
+        coordinates: Vec<(u8, u8)>,
+        config: &crate::cli::Config,
+    ) -> CacheResult<GroupMapSquareIterator> {
+        let coordinates = coordinates.into_iter().collect::<BTreeSet<_>>();
+        Ok(Self::new(range_i, range_j, config)?.retain(|square| coordinates.contains(square)))
+    }
+
+    /// Only iterates over the mapsquares whose coordinates satisfy `keep`.
+    pub fn retain(mut self, keep: impl FnMut(&(u8, u8)) -> bool) -> GroupMapSquareIterator {
+        self.state = self.state.filter(keep).collect::<Vec<_>>().into_iter();
+        self
+    }
 }
 
 impl Iterator for GroupMapSquareIterator {
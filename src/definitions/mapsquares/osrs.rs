@@ -1,5 +1,5 @@
 use core::ops::RangeInclusive;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use ::error::Context;
 use itertools::iproduct;
@@ -13,6 +13,7 @@ use crate::definitions::{
     mapsquares::{GroupMapSquare, MapSquare, MapSquares},
 };
 impl MapSquares {
+    /// Opens the mapsquares of the cache, without reading any of them yet.
     pub fn new(config: &crate::cli::Config) -> CacheResult<MapSquares> {
         let index = CacheIndex::new(IndexType::MAPSV2, config.input.clone())?;
         let land_hashes: HashMap<i32, (u8, u8)> = iproduct!(0..100, 0..200)
@@ -56,6 +57,9 @@ impl MapSquares {
         Ok(MapSquares { index, mapping })
     }
 
+    /// Reads the mapsquare at `i, j`, such as `50, 50` for Lumbridge.
+    ///
+    /// Fails if the cache has no mapsquare there. Its locations are missing if there is no xtea key for them.
     pub fn get(&self, i: u8, j: u8) -> CacheResult<MapSquare> {
         let land = self
             .mapping
@@ -65,13 +69,33 @@ impl MapSquares {
                 name: format!("l{i}_{j}"),
             })
             .context(error::Integrity)?;
-        let map = self.mapping.get(&("m", i, j)).unwrap();
+        let map = self
+            .mapping
+            .get(&("m", i, j))
+            .with_context(|| index::ArchiveMissingNamed {
+                index_id: 5,
+                name: format!("m{i}_{j}"),
+            })
+            .context(error::Integrity)?;
         let env = self.mapping.get(&("e", i, j)).copied();
-        let xtea = self.index.xteas().as_ref().unwrap().get(&(((i as u32) << 8) | j as u32));
+        let xtea = self.index.xteas().as_ref().and_then(|xteas| xteas.get(&(((i as u32) << 8) | j as u32)));
 
         let sq = MapSquare::new(&self.index, xtea.copied(), *land, *map, env, i, j)?;
         Ok(sq)
     }
+
+    /// Whether the cache has a mapsquare at `i, j`.
+    pub fn contains(&self, i: u8, j: u8) -> bool {
+        self.mapping.contains_key(&("m", i, j))
+    }
+
+    /// The coordinates of every mapsquare, in order.
+    pub fn coordinates(&self) -> Vec<(u8, u8)> {
+        self.mapping
+            .keys()
+            .filter_map(|(ty, i, j)| if *ty == "m" { Some((*i, *j)) } else { None })
+            .collect()
+    }
 }
 
 /// Iterates over all [`MapSquare`]s in arbitrary order.
@@ -151,6 +175,23 @@ impl GroupMapSquareIterator {
             state,
         })
     }
+
+    /// Only iterates over those of the mapsquares at `coordinates` that are in the cache.
+    pub fn new_only(
+        range_i: RangeInclusive<i32>,
+        range_j: RangeInclusive<i32>,
+        coordinates: Vec<(u8, u8)>,
+        config: &crate::cli::Config,
+    ) -> CacheResult<GroupMapSquareIterator> {
+        let coordinates = coordinates.into_iter().collect::<BTreeSet<_>>();
+        Ok(Self::new(range_i, range_j, config)?.retain(|square| coordinates.contains(square)))
+    }
+
+    /// Only iterates over the mapsquares whose coordinates satisfy `keep`.
+    pub fn retain(mut self, keep: impl FnMut(&(u8, u8)) -> bool) -> GroupMapSquareIterator {
+        self.state = self.state.filter(keep).collect::<Vec<_>>().into_iter();
+        self
+    }
 }
 
 impl Iterator for GroupMapSquareIterator {
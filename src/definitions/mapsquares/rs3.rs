@@ -1,9 +1,10 @@
 use core::ops::RangeInclusive;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
+use ::error::Context;
 use itertools::iproduct;
 use rs3cache_backend::{
-    error::CacheResult,
+    error::{self, CacheResult},
     index::{self, CacheIndex},
 };
 
@@ -12,18 +13,40 @@ use crate::definitions::{
     mapsquares::{GroupMapSquare, MapSquare, MapSquares},
 };
 impl MapSquares {
+    /// Opens the mapsquares of the cache, without reading any of them yet.
     pub fn new(config: &crate::cli::Config) -> CacheResult<MapSquares> {
         let index = CacheIndex::new(IndexType::MAPSV2, config.input.clone())?;
 
         Ok(MapSquares { index })
     }
 
+    /// Reads the mapsquare at `i, j`, such as `50, 50` for Lumbridge.
+    ///
+    /// Fails if the cache has no mapsquare there, which it never has if `i` is `0x80` or more.
     pub fn get(&self, i: u8, j: u8) -> CacheResult<MapSquare> {
         let archive_id = (i as u32) | (j as u32) << 7;
+        // The archive id only has 7 bits for `i`, so a larger one would read the mapsquare at `i - 0x80, j + 1`.
+        (i < 0x80)
+            .then_some(())
+            .context(index::ArchiveMissing {
+                index_id: IndexType::MAPSV2,
+                archive_id,
+            })
+            .context(error::Integrity)?;
         let archive = self.index.archive(archive_id)?;
 
         Ok(MapSquare::from_archive(archive))
     }
+
+    /// Whether the cache has a mapsquare at `i, j`.
+    pub fn contains(&self, i: u8, j: u8) -> bool {
+        i < 0x80 && self.index.metadatas().contains_key(&((i as u32) | (j as u32) << 7))
+    }
+
+    /// The coordinates of every mapsquare, in the order of their archives in the index.
+    pub fn coordinates(&self) -> Vec<(u8, u8)> {
+        self.index.metadatas().keys().map(|id| ((id & 0x7F) as u8, (id >> 7) as u8)).collect()
+    }
 }
 /// Iterates over all [`MapSquare`]s in arbitrary order.
 pub struct MapSquareIterator {
@@ -73,21 +96,21 @@ impl GroupMapSquareIterator {
         })
     }
 
-    #[doc(hidden)]
+    /// Only iterates over those of the mapsquares at `coordinates` that are in the cache.
     pub fn new_only(
         range_i: RangeInclusive<i32>,
         range_j: RangeInclusive<i32>,
         coordinates: Vec<(u8, u8)>,
         config: &crate::cli::Config,
     ) -> CacheResult<GroupMapSquareIterator> {
-        let index = CacheIndex::new(IndexType::MAPSV2, config.input.clone())?;
+        let coordinates = coordinates.into_iter().collect::<BTreeSet<_>>();
+        Ok(Self::new(range_i, range_j, config)?.retain(|square| coordinates.contains(square)))
+    }
 
-        Ok(GroupMapSquareIterator {
-            index,
-            range_i,
-            range_j,
-            state: coordinates.into_iter(),
-        })
+    /// Only iterates over the mapsquares whose coordinates satisfy `keep`.
+    pub fn retain(mut self, keep: impl FnMut(&(u8, u8)) -> bool) -> GroupMapSquareIterator {
+        self.state = self.state.filter(keep).collect::<Vec<_>>().into_iter();
+        self
     }
 }
 
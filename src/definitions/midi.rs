@@ -0,0 +1,415 @@
+//! Music and jingles, which are stored as MIDI files with their events regrouped so that they compress better.
+//!
+//! A track is laid out as its status bytes, then the delta times of the events, then the numbers of the
+//! controllers that change, followed by one stream for every kind of value: notes, velocities, values of
+//! certain controllers and so on. Most values are stored as the difference with the previous value of their kind.
+//!
+//! The instruments they are played with, the patches in [`MUSIC_PATCHES`](IndexType::MUSIC_PATCHES), and the samples
+//! those are made of are a format of their own, and are not decoded here. The MIDI files play with
+//! the General MIDI instruments of whatever synthesizer they are opened in, which are close but not the same.
+
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, Eof, ReadError},
+    error::{self, CacheResult},
+    hash::hash_djb2,
+    index::CacheIndex,
+};
+use serde::Serialize;
+
+use crate::definitions::{
+    enums::{Enum, Value},
+    indextype::IndexType,
+};
+
+/// The enum that maps the tracks of the music player to their names.
+const TRACK_NAMES: u32 = 812;
+
+const NOTE_ON: u8 = 0;
+const NOTE_OFF: u8 = 1;
+const CONTROLLER: u8 = 2;
+const PITCH_BEND: u8 = 3;
+const CHANNEL_PRESSURE: u8 = 4;
+const KEY_PRESSURE: u8 = 5;
+const PROGRAM_CHANGE: u8 = 6;
+const END_OF_TRACK: u8 = 7;
+const TEMPO: u8 = 23;
+
+/// The MIDI status of each kind of event, on channel 0.
+const STATUS: [u8; 7] = [0x90, 0x80, 0xB0, 0xE0, 0xD0, 0xA0, 0xC0];
+
+// The streams of values, in the order they are stored in.
+const HOLD: usize = 0;
+const KEY_PRESSURE_VALUE: usize = 1;
+const CHANNEL_PRESSURE_VALUE: usize = 2;
+const PITCH_BEND_HIGH: usize = 3;
+const MODULATION: usize = 4;
+const VOLUME: usize = 5;
+const PAN: usize = 6;
+const NOTE: usize = 7;
+const NOTE_ON_VELOCITY: usize = 8;
+const OTHER_CONTROLLER: usize = 9;
+const NOTE_OFF_VELOCITY: usize = 10;
+const MODULATION_FINE: usize = 11;
+const VOLUME_FINE: usize = 12;
+const PAN_FINE: usize = 13;
+const PROGRAM: usize = 14;
+const PITCH_BEND_LOW: usize = 15;
+/// As long as the channel pressure values, but never read.
+const UNUSED: usize = 16;
+const NRPN: usize = 17;
+const NRPN_FINE: usize = 18;
+const RPN: usize = 19;
+const RPN_FINE: usize = 20;
+const TEMPO_VALUE: usize = 21;
+
+/// The stream that the values of a controller are in.
+fn controller_stream(controller: u8) -> usize {
+    match controller {
+        // Bank selects share the stream of program changes.
+        0 | 32 => PROGRAM,
+        1 => MODULATION,
+        33 => MODULATION_FINE,
+        7 => VOLUME,
+        39 => VOLUME_FINE,
+        10 => PAN,
+        42 => PAN_FINE,
+        99 => NRPN,
+        98 => NRPN_FINE,
+        101 => RPN,
+        100 => RPN_FINE,
+        64 | 65 | 120 | 121 | 123 => HOLD,
+        _ => OTHER_CONTROLLER,
+    }
+}
+
+/// A music track or jingle.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct MusicTrack {
+    /// The archive it is in.
+    pub id: u32,
+    /// Its name in the music player, if it has one.
+    pub name: Option<String>,
+    /// Its id in the music player, if it has one.
+    pub track: Option<i32>,
+    /// Whether it is a jingle rather than music.
+    pub jingle: bool,
+    /// The number of MIDI tracks.
+    pub tracks: u8,
+    /// Ticks per quarter note.
+    pub division: u16,
+    /// The standard MIDI file.
+    #[serde(skip)]
+    pub midi: Vec<u8>,
+}
+
+/// Reads a variable length quantity, as used for MIDI delta times.
+fn try_get_var_int(buffer: &mut Bytes) -> Result<u32, ReadError> {
+    let mut value = 0;
+    loop {
+        let byte = buffer.try_get_u8()?;
+        value = (value << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn put_var_int(out: &mut Vec<u8>, value: u32) {
+    for shift in [28, 21, 14, 7] {
+        if value >> shift != 0 {
+            out.push(0x80 | (value >> shift) as u8);
+        }
+    }
+    out.push(value as u8 & 0x7F);
+}
+
+/// Reads the values of each stream, which are signed bytes.
+struct Streams<'a> {
+    data: &'a [u8],
+    offsets: [usize; 22],
+}
+
+impl<'a> Streams<'a> {
+    fn new(data: &'a [u8], start: usize, lengths: [usize; 22]) -> Self {
+        let mut offsets = [0; 22];
+        let mut offset = start;
+        for (stream, len) in lengths.into_iter().enumerate() {
+            offsets[stream] = offset;
+            offset += len;
+        }
+        Self { data, offsets }
+    }
+
+    fn next(&mut self, stream: usize) -> Result<i32, ReadError> {
+        let offset = self.offsets[stream];
+        let byte = *self.data.get(offset).ok_or_else(|| Eof::new(offset + 1, self.data.len()))?;
+        self.offsets[stream] += 1;
+        Ok(byte as i8 as i32)
+    }
+}
+
+impl MusicTrack {
+    /// Returns a mapping of all music tracks and jingles, which are keyed by whether they are a jingle and their archive.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<(bool, u32), Self>> {
+        let names = Enum::dump_all(config)?
+            .remove(&TRACK_NAMES)
+            .map(|names| names.variants)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(track, name)| match name {
+                Value::String(name) => Some((hash_djb2(name.to_lowercase()), (track, (*name).to_owned()))),
+                Value::Integer(_) => None,
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut all = BTreeMap::new();
+        for (index_id, jingle) in [(IndexType::MUSIC_TRACKS, false), (IndexType::MUSIC_JINGLES, true)] {
+            let index = CacheIndex::new(index_id, config.input.clone())?;
+            let hashes = index
+                .metadatas()
+                .iter()
+                .filter_map(|(&id, metadata)| Some((id, metadata.name()?)))
+                .collect::<BTreeMap<_, _>>();
+
+            for archive in index {
+                let archive = archive?;
+                let id = archive.archive_id();
+                let data = archive.take_files().into_values().next().unwrap_or_default();
+                let mut music = Self::deserialize(id, data).context(error::Read { what: "music" })?;
+                music.jingle = jingle;
+                if let Some((track, name)) = hashes.get(&id).and_then(|hash| names.get(hash)) {
+                    music.track = Some(*track);
+                    music.name = Some(name.clone());
+                }
+                all.insert((jingle, id), music);
+            }
+        }
+        Ok(all)
+    }
+
+    /// Decodes a track and rebuilds the standard MIDI file it was made from.
+    pub fn deserialize(id: u32, data: Bytes) -> Result<Self, ReadError> {
+        let len = data.len();
+        if len < 3 {
+            return Err(Eof::new(3, len));
+        }
+        let mut footer = data.slice(len - 3..);
+        let tracks = footer.try_get_u8()?;
+        let division = footer.try_get_u16()?;
+
+        // Count the values of each stream, to find where they start.
+        let mut lengths = [0_usize; 22];
+        let mut statuses = data.slice(..len - 3);
+        let mut events = 0;
+        let mut controllers = 0;
+        for _ in 0..tracks {
+            loop {
+                let status = statuses.try_get_u8()?;
+                events += 1;
+                let streams: &[usize] = match status {
+                    END_OF_TRACK => break,
+                    TEMPO => &[TEMPO_VALUE; 3],
+                    status => match status & 0xF {
+                        NOTE_ON => &[NOTE, NOTE_ON_VELOCITY],
+                        NOTE_OFF => &[NOTE, NOTE_OFF_VELOCITY],
+                        CONTROLLER => {
+                            controllers += 1;
+                            &[]
+                        }
+                        PITCH_BEND => &[PITCH_BEND_LOW, PITCH_BEND_HIGH],
+                        CHANNEL_PRESSURE => &[CHANNEL_PRESSURE_VALUE, UNUSED],
+                        KEY_PRESSURE => &[NOTE, KEY_PRESSURE_VALUE],
+                        PROGRAM_CHANGE => &[PROGRAM],
+                        _ => {
+                            return Err(ReadError::OpcodeNotImplemented {
+                                location: core::panic::Location::caller(),
+                                opcode: status as _,
+                            })
+                        }
+                    },
+                };
+                for &stream in streams {
+                    lengths[stream] += 1;
+                }
+            }
+        }
+
+        let time_start = len - 3 - statuses.remaining();
+        let mut times = statuses;
+        for _ in 0..events {
+            try_get_var_int(&mut times)?;
+        }
+
+        let controller_start = len - 3 - times.remaining();
+        let mut controller_numbers = times;
+        let mut controller = 0_u8;
+        for _ in 0..controllers {
+            controller = controller.wrapping_add(controller_numbers.try_get_u8()?) & 0x7F;
+            lengths[controller_stream(controller)] += 1;
+        }
+
+        let mut streams = Streams::new(&data, len - 3 - controller_numbers.remaining(), lengths);
+        let mut statuses = data.slice(..time_start);
+        let mut times = data.slice(time_start..controller_start);
+        let mut controller_numbers = data.slice(controller_start..);
+
+        let mut midi = Vec::with_capacity(len * 2);
+        midi.extend(b"MThd");
+        midi.extend(6_u32.to_be_bytes());
+        midi.extend((if tracks > 1 { 1_u16 } else { 0 }).to_be_bytes());
+        midi.extend((tracks as u16).to_be_bytes());
+        midi.extend(division.to_be_bytes());
+
+        // All of these carry over between tracks.
+        let mut channel = 0;
+        let mut note = 0;
+        let mut on_velocity = 0;
+        let mut off_velocity = 0;
+        let mut bend = 0;
+        let mut pressure = 0;
+        let mut key_pressure = 0;
+        let mut controller = 0_u8;
+        let mut values = [0_i32; 128];
+
+        for _ in 0..tracks {
+            midi.extend(b"MTrk");
+            let start = midi.len();
+            midi.extend([0; 4]);
+
+            // A status is left out if it is the same as the previous one, which is only the case
+            // if the channel did not change.
+            let mut last = None;
+            loop {
+                put_var_int(&mut midi, try_get_var_int(&mut times)?);
+                let status = statuses.try_get_u8()?;
+                let running = last == Some(status);
+                last = Some(status & 0xF);
+
+                match status {
+                    END_OF_TRACK => {
+                        if !running {
+                            midi.push(0xFF);
+                        }
+                        midi.extend([0x2F, 0]);
+                        let track_len = (midi.len() - start - 4) as u32;
+                        midi[start..start + 4].copy_from_slice(&track_len.to_be_bytes());
+                        break;
+                    }
+                    TEMPO => {
+                        if !running {
+                            midi.push(0xFF);
+                        }
+                        midi.extend([0x51, 3]);
+                        for _ in 0..3 {
+                            midi.push(streams.next(TEMPO_VALUE)? as u8);
+                        }
+                    }
+                    status => {
+                        channel ^= status >> 4;
+                        let kind = status & 0xF;
+                        if !running {
+                            midi.push(STATUS[kind as usize] + channel);
+                        }
+                        match kind {
+                            NOTE_ON => {
+                                note += streams.next(NOTE)?;
+                                on_velocity += streams.next(NOTE_ON_VELOCITY)?;
+                                midi.extend([(note & 0x7F) as u8, (on_velocity & 0x7F) as u8]);
+                            }
+                            NOTE_OFF => {
+                                note += streams.next(NOTE)?;
+                                off_velocity += streams.next(NOTE_OFF_VELOCITY)?;
+                                midi.extend([(note & 0x7F) as u8, (off_velocity & 0x7F) as u8]);
+                            }
+                            CONTROLLER => {
+                                controller = controller.wrapping_add(controller_numbers.try_get_u8()?) & 0x7F;
+                                let value = &mut values[controller as usize];
+                                *value += streams.next(controller_stream(controller))?;
+                                midi.extend([controller, (*value & 0x7F) as u8]);
+                            }
+                            PITCH_BEND => {
+                                bend += streams.next(PITCH_BEND_LOW)?;
+                                bend += streams.next(PITCH_BEND_HIGH)? << 7;
+                                midi.extend([(bend & 0x7F) as u8, ((bend >> 7) & 0x7F) as u8]);
+                            }
+                            CHANNEL_PRESSURE => {
+                                pressure += streams.next(CHANNEL_PRESSURE_VALUE)?;
+                                midi.push((pressure & 0x7F) as u8);
+                            }
+                            KEY_PRESSURE => {
+                                note += streams.next(NOTE)?;
+                                key_pressure += streams.next(KEY_PRESSURE_VALUE)?;
+                                midi.extend([(note & 0x7F) as u8, (key_pressure & 0x7F) as u8]);
+                            }
+                            _ => midi.push(streams.next(PROGRAM)? as u8),
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            id,
+            tracks,
+            division,
+            midi,
+            ..Default::default()
+        })
+    }
+}
+
+/// Saves every track as `music/<id>.mid` and every jingle as `jingles/<id>.mid`,
+/// along with a listing of them as `music.json`. Exposed as `--dump music`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let all = MusicTrack::dump_all(config)?;
+    for ((jingle, id), music) in all.iter().filter(|((_, id), _)| config.keeps(*id)) {
+        let folder = if *jingle { "jingles" } else { "music" };
+        config.sink()?.put(format!("{folder}/{id}.mid"), &music.midi)?;
+    }
+    crate::output::export(config, "music", &all.into_values().collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_int() {
+        for value in [0, 0x7F, 0x80, 0x3FFF, 0x4000, 0x0FFF_FFFF] {
+            let mut out = Vec::new();
+            put_var_int(&mut out, value);
+            assert_eq!(try_get_var_int(&mut Bytes::from(out)).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn rebuild() {
+        #[rustfmt::skip]
+        let data = Bytes::from_static(&[
+            // note on, channel 1; note off, same channel; end of track
+            0x10, 0x01, 0x07,
+            // delta times
+            0x00, 0x60, 0x00,
+            // the note, the note on velocity and the note off velocity
+            60, 0, 100, 64,
+            // one track, 96 ticks per quarter note
+            1, 0, 96,
+        ]);
+        let music = MusicTrack::deserialize(0, data).unwrap();
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 12,
+            0x00, 0x91, 60, 100,
+            0x60, 0x81, 60, 64,
+            0x00, 0xFF, 0x2F, 0,
+        ];
+        assert_eq!(music.midi, expected);
+    }
+}
@@ -0,0 +1,453 @@
+//! The 3d models that everything in the game is drawn with.
+//!
+//! Only the geometry, the colours of faces and the skins of vertices are decoded;
+//! face skins, priorities and texture coordinates are skipped.
+//! All four encodings are supported; they differ in the layout of the footer and which sections are present.
+
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, Eof, ReadError},
+    error::{self, CacheResult},
+    index::{CacheIndex, Initial},
+};
+
+use crate::definitions::indextype::IndexType;
+
+/// A decoded model.
+#[derive(Clone, Debug, Default)]
+pub struct Model {
+    /// Its id.
+    pub id: u32,
+    /// The position of each vertex. One tile is 128 units wide, and `y` points down.
+    pub vertices: Vec<[i32; 3]>,
+    /// The vertices of each face.
+    pub faces: Vec<[u16; 3]>,
+    /// The colour of each face, as a 16 bit hsl value. See [`hsl_to_rgb`].
+    pub face_colours: Vec<u16>,
+    /// The transparency of each face, where 0 is opaque.
+    pub face_alphas: Option<Vec<u8>>,
+    /// The texture of each face, if it is textured.
+    pub face_textures: Option<Vec<Option<u16>>>,
+    /// The group of each vertex that the [frames](crate::definitions::frames::Frame) of animations move it by.
+    pub vertex_skins: Option<Vec<u8>>,
+}
+
+/// Where the sections of an encoded model are, and which of them are present.
+#[derive(Debug, Default)]
+struct Layout {
+    vertex_count: usize,
+    face_count: usize,
+    /// Whether faces have render types, which indicate textures in the old encodings.
+    render_types: bool,
+    alphas: bool,
+    /// Whether faces have texture ids, in the new encodings.
+    textures: bool,
+
+    vertex_flags: usize,
+    vertex_x: usize,
+    vertex_y: usize,
+    vertex_z: usize,
+    face_types: usize,
+    face_indices: usize,
+    face_colours: usize,
+    face_render_types: usize,
+    face_alphas: usize,
+    face_textures: usize,
+    /// Where the skins of vertices are, if they have them.
+    vertex_skins: Option<usize>,
+}
+
+impl Layout {
+    /// Reads the footer of `data` and works out where each section starts.
+    fn new(data: &Bytes) -> Result<Self, ReadError> {
+        match data.get(data.len().saturating_sub(2)..) {
+            Some([0xFF, 0xFF]) => Self::new_format(data, false),
+            Some([0xFF, 0xFD]) => Self::new_format(data, true),
+            Some([0xFF, 0xFE]) => Self::old_format(data, true),
+            _ => Self::old_format(data, false),
+        }
+    }
+
+    /// The original encoding, and its extension with an additional footer field for animation groups.
+    fn old_format(data: &Bytes, extended: bool) -> Result<Self, ReadError> {
+        let footer_len = if extended { 23 } else { 18 };
+        let mut footer = footer(data, footer_len)?;
+
+        let vertex_count = footer.try_get_u16()? as usize;
+        let face_count = footer.try_get_u16()? as usize;
+        let textured_face_count = footer.try_get_u8()? as usize;
+        let render_types = footer.try_get_u8()? == 1;
+        let priorities = footer.try_get_u8()? == 255;
+        let alphas = footer.try_get_u8()? == 1;
+        let face_skins = footer.try_get_u8()? == 1;
+        let vertex_skins = footer.try_get_u8()? == 1;
+        if extended {
+            let _animaya = footer.try_get_u8()?;
+        }
+        let x_len = footer.try_get_u16()? as usize;
+        let y_len = footer.try_get_u16()? as usize;
+        let _z_len = footer.try_get_u16()? as usize;
+        let face_index_len = footer.try_get_u16()? as usize;
+        // The extended encoding stores vertex skins and animation groups together.
+        let skin_len = if extended {
+            footer.try_get_u16()? as usize
+        } else if vertex_skins {
+            vertex_count
+        } else {
+            0
+        };
+
+        let mut layout = Self {
+            vertex_count,
+            face_count,
+            render_types,
+            alphas,
+            ..Default::default()
+        };
+
+        let mut position = 0;
+        let mut section = |len: usize| {
+            let start = position;
+            position += len;
+            start
+        };
+        layout.vertex_flags = section(vertex_count);
+        layout.face_types = section(face_count);
+        section(if priorities { face_count } else { 0 });
+        section(if face_skins { face_count } else { 0 });
+        layout.face_render_types = section(if render_types { face_count } else { 0 });
+        let skins = section(skin_len);
+        layout.vertex_skins = vertex_skins.then_some(skins);
+        layout.face_alphas = section(if alphas { face_count } else { 0 });
+        layout.face_indices = section(face_index_len);
+        layout.face_colours = section(face_count * 2);
+        section(textured_face_count * 6);
+        layout.vertex_x = section(x_len);
+        layout.vertex_y = section(y_len);
+        layout.vertex_z = section(0);
+
+        Ok(layout)
+    }
+
+    /// The newer encoding with texture ids, and its extension for animation groups.
+    fn new_format(data: &Bytes, extended: bool) -> Result<Self, ReadError> {
+        let footer_len = if extended { 26 } else { 23 };
+        let mut footer = footer(data, footer_len)?;
+
+        let vertex_count = footer.try_get_u16()? as usize;
+        let face_count = footer.try_get_u16()? as usize;
+        let textured_face_count = footer.try_get_u8()? as usize;
+        let render_types = footer.try_get_u8()? == 1;
+        let priorities = footer.try_get_u8()? == 255;
+        let alphas = footer.try_get_u8()? == 1;
+        let face_skins = footer.try_get_u8()? == 1;
+        let textures = footer.try_get_u8()? == 1;
+        let vertex_skins = footer.try_get_u8()? == 1;
+        if extended {
+            let _animaya = footer.try_get_u8()?;
+        }
+        let x_len = footer.try_get_u16()? as usize;
+        let y_len = footer.try_get_u16()? as usize;
+        let _z_len = footer.try_get_u16()? as usize;
+        let face_index_len = footer.try_get_u16()? as usize;
+        let texture_coord_len = footer.try_get_u16()? as usize;
+        let skin_len = if extended {
+            footer.try_get_u16()? as usize
+        } else if vertex_skins {
+            vertex_count
+        } else {
+            0
+        };
+
+        let mut layout = Self {
+            vertex_count,
+            face_count,
+            // Render types no longer indicate textures; those are separate.
+            render_types: false,
+            alphas,
+            textures,
+            ..Default::default()
+        };
+
+        let mut position = textured_face_count;
+        let mut section = |len: usize| {
+            let start = position;
+            position += len;
+            start
+        };
+        layout.vertex_flags = section(vertex_count);
+        section(if render_types { face_count } else { 0 });
+        layout.face_types = section(face_count);
+        section(if priorities { face_count } else { 0 });
+        section(if face_skins { face_count } else { 0 });
+        let skins = section(skin_len);
+        layout.vertex_skins = vertex_skins.then_some(skins);
+        layout.face_alphas = section(if alphas { face_count } else { 0 });
+        layout.face_indices = section(face_index_len);
+        layout.face_textures = section(if textures { face_count * 2 } else { 0 });
+        section(texture_coord_len);
+        layout.face_colours = section(face_count * 2);
+        layout.vertex_x = section(x_len);
+        layout.vertex_y = section(y_len);
+        layout.vertex_z = section(0);
+
+        Ok(layout)
+    }
+}
+
+/// Returns the last `len` bytes of `data`.
+fn footer(data: &Bytes, len: usize) -> Result<Bytes, ReadError> {
+    match data.len().checked_sub(len) {
+        Some(start) => Ok(data.slice(start..)),
+        None => Err(Eof::new(len, data.len())),
+    }
+}
+
+/// Returns `data` from `start` onwards.
+fn at(data: &Bytes, start: usize) -> Result<Bytes, ReadError> {
+    data.get(start..).map(|_| data.slice(start..)).ok_or_else(|| Eof::new(start, data.len()))
+}
+
+/// Reads a smart that is signed, as used for deltas between vertices and between face indices.
+pub(crate) fn try_get_signed_smart(buffer: &mut Bytes) -> Result<i32, ReadError> {
+    match buffer.first() {
+        Some(&byte) if byte < 128 => Ok(buffer.try_get_u8()? as i32 - 0x40),
+        _ => Ok(buffer.try_get_u16()? as i32 - 0xC000),
+    }
+}
+
+impl Model {
+    /// Decodes a model.
+    pub fn deserialize(id: u32, data: Bytes) -> Result<Self, ReadError> {
+        let layout = Layout::new(&data)?;
+
+        let mut flags = at(&data, layout.vertex_flags)?;
+        let mut xs = at(&data, layout.vertex_x)?;
+        let mut ys = at(&data, layout.vertex_y)?;
+        let mut zs = at(&data, layout.vertex_z)?;
+
+        let mut vertex = [0; 3];
+        let mut vertices = Vec::with_capacity(layout.vertex_count);
+        for _ in 0..layout.vertex_count {
+            let flag = flags.try_get_u8()?;
+            for (axis, buffer) in [&mut xs, &mut ys, &mut zs].into_iter().enumerate() {
+                if flag & (1 << axis) != 0 {
+                    vertex[axis] += try_get_signed_smart(buffer)?;
+                }
+            }
+            vertices.push(vertex);
+        }
+
+        // The extended encodings keep the skins of vertices at the start of their section for animation groups.
+        let vertex_skins = match layout.vertex_skins {
+            Some(start) => {
+                let skins = at(&data, start)?;
+                let skins = skins
+                    .get(..layout.vertex_count)
+                    .ok_or_else(|| Eof::new(layout.vertex_count, skins.len()))?;
+                Some(skins.to_vec())
+            }
+            None => None,
+        };
+
+        let mut colours = at(&data, layout.face_colours)?;
+        let mut render_types = at(&data, layout.face_render_types)?;
+        let mut alphas = at(&data, layout.face_alphas)?;
+        let mut textures = at(&data, layout.face_textures)?;
+
+        let mut face_colours = Vec::with_capacity(layout.face_count);
+        let mut face_alphas = layout.alphas.then(Vec::new);
+        let mut face_textures = (layout.render_types || layout.textures).then(Vec::new);
+        for _ in 0..layout.face_count {
+            let colour = colours.try_get_u16()?;
+            face_colours.push(colour);
+            if let Some(face_alphas) = &mut face_alphas {
+                face_alphas.push(alphas.try_get_u8()?);
+            }
+            if let Some(face_textures) = &mut face_textures {
+                let texture = if layout.render_types {
+                    // In the old encodings, textured faces store their texture in place of their colour.
+                    (render_types.try_get_u8()? & 2 != 0).then_some(colour)
+                } else {
+                    textures.try_get_u16()?.checked_sub(1)
+                };
+                face_textures.push(texture);
+            }
+        }
+
+        let mut types = at(&data, layout.face_types)?;
+        let mut indices = at(&data, layout.face_indices)?;
+
+        let (mut a, mut b, mut c, mut previous) = (0, 0, 0, 0);
+        let mut next = |indices: &mut Bytes| -> Result<i32, ReadError> {
+            previous += try_get_signed_smart(indices)?;
+            Ok(previous)
+        };
+        let mut faces = Vec::with_capacity(layout.face_count);
+        for _ in 0..layout.face_count {
+            // Faces are stored as strips where possible, reusing two of the vertices of the previous face.
+            match types.try_get_u8()? {
+                1 => {
+                    a = next(&mut indices)?;
+                    b = next(&mut indices)?;
+                    c = next(&mut indices)?;
+                }
+                2 => {
+                    b = c;
+                    c = next(&mut indices)?;
+                }
+                3 => {
+                    a = c;
+                    c = next(&mut indices)?;
+                }
+                4 => {
+                    std::mem::swap(&mut a, &mut b);
+                    c = next(&mut indices)?;
+                }
+                _ => {}
+            }
+            faces.push([a as u16, b as u16, c as u16]);
+        }
+
+        Ok(Self {
+            id,
+            vertices,
+            faces,
+            face_colours,
+            face_alphas,
+            face_textures,
+            vertex_skins,
+        })
+    }
+
+    /// Reads the model `id` from the models `index`.
+    pub fn get(index: &CacheIndex<Initial>, id: u32) -> CacheResult<Self> {
+        let archive = index.archive(id)?;
+        let file = archive.file(&0).context(rs3cache_backend::index::Other).context(error::Integrity)?;
+        Self::deserialize(id, file).context(error::Read { what: "model" })
+    }
+
+    /// Returns the models with the given ids.
+    pub fn dump(config: &crate::cli::Config, ids: impl IntoIterator<Item = u32>) -> CacheResult<BTreeMap<u32, Self>> {
+        let index = CacheIndex::new(IndexType::MODELS, config.input.clone())?;
+        ids.into_iter().map(|id| Ok((id, Self::get(&index, id)?))).collect()
+    }
+}
+
+/// Converts a 16 bit hsl colour, as used by models, to rgb.
+pub fn hsl_to_rgb(hsl: u16) -> [u8; 3] {
+    let hue = (hsl >> 10 & 0x3F) as f64 / 64.0 + 1.0 / 128.0;
+    let saturation = (hsl >> 7 & 0x07) as f64 / 8.0 + 1.0 / 16.0;
+    let lightness = (hsl & 0x7F) as f64 / 128.0;
+
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue * 6.0) % 2.0 - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+
+    let (r, g, b) = match (hue * 6.0) as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    [r, g, b].map(|channel| ((channel + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// Saves every model, or those in `--ids` and `--filter`, as `models/<id>.glb` or `models/<id>.obj` depending on `--format`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    use path_macro::path;
+    use rayon::iter::ParallelIterator;
+    use rs3cache_utils::bar::Render;
+
+    use crate::{cli::Format, output::mesh};
+
+    let ids: Vec<u32> = match &config.ids {
+        Some(file) => {
+            let text = std::fs::read_to_string(file).context(error::Io { path: file.clone() })?;
+            crate::renderers::scenery::parse_ids(&text).into_iter().collect()
+        }
+        None => CacheIndex::new(IndexType::MODELS, config.input.clone())?
+            .metadatas()
+            .keys()
+            .copied()
+            .collect(),
+    };
+
+    let models = Model::dump(config, ids.into_iter().filter(|id| config.keeps(*id)))?;
+    let sink = config.sink()?;
+    models.into_values().render("models").try_for_each(|(model, _)| match config.format {
+        Format::Obj => sink.put(path!("models" / format!("{}.obj", model.id)), mesh::obj(&model).as_bytes()),
+        // Models without faces are left out, as they cannot be written as glTF.
+        _ => match mesh::glb(&model) {
+            Some(glb) => sink.put(path!("models" / format!("{}.glb", model.id)), &glb),
+            None => Ok(()),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours() {
+        assert_eq!(hsl_to_rgb(0), [0, 0, 0]);
+        assert!(hsl_to_rgb(127).iter().all(|&channel| channel > 250));
+
+        let [r, g, b] = hsl_to_rgb(0b000000_111_1000000);
+        assert!(r > g && r > b, "hue 0 is red");
+    }
+
+    #[test]
+    fn old_format() {
+        // One face between three vertices: (0, 0, 0), (10, 0, 0) and (10, 0, 10).
+        #[rustfmt::skip]
+        let data = Bytes::from_static(&[
+            // vertex flags
+            0, 1, 4,
+            // face types
+            1,
+            // face indices
+            0x40, 0x41, 0x41,
+            // face colours
+            0x12, 0x34,
+            // vertex x, z
+            0x4A, 0x4A,
+            // footer
+            0, 3, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 3,
+        ]);
+        let model = Model::deserialize(1, data).unwrap();
+
+        assert_eq!(model.vertices, vec![[0, 0, 0], [10, 0, 0], [10, 0, 10]]);
+        assert_eq!(model.faces, vec![[0, 1, 2]]);
+        assert_eq!(model.face_colours, vec![0x1234]);
+        assert!(model.face_alphas.is_none());
+    }
+
+    #[test]
+    fn vertex_skins() {
+        // The same model, with its vertices in skins 2, 2 and 5.
+        #[rustfmt::skip]
+        let data = Bytes::from_static(&[
+            0, 1, 4,
+            1,
+            // vertex skins
+            2, 2, 5,
+            0x40, 0x41, 0x41,
+            0x12, 0x34,
+            0x4A, 0x4A,
+            0, 3, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 1, 0, 3,
+        ]);
+        let model = Model::deserialize(1, data).unwrap();
+
+        assert_eq!(model.vertex_skins, Some(vec![2, 2, 5]));
+        assert_eq!(model.vertices, vec![[0, 0, 0], [10, 0, 0], [10, 0, 10]]);
+        assert_eq!(model.faces, vec![[0, 1, 2]]);
+    }
+}
@@ -1,14 +1,13 @@
-use std::{
-    fs::{self, File},
-    io::Write,
-    process::Command,
-};
-
+use ::error::Context;
 use bytes::{Buf, Bytes};
 use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
-use path_macro::path;
-use rs3cache_backend::{buf::BufExtra, error::CacheResult, index::CacheIndex};
+use rs3cache_backend::{
+    buf::BufExtra,
+    error::{self, CacheResult},
+    index::CacheIndex,
+    warning::{self, Warning},
+};
 
 use crate::definitions::{
     enums::{Enum, Value},
@@ -18,8 +17,8 @@ use crate::definitions::{
 pub fn export_each(config: &crate::cli::Config) -> CacheResult<()> {
     let enum_archives = CacheIndex::new(IndexType::ENUM_CONFIG, config.input.clone())?;
     let archive = enum_archives.archive(5)?;
-    let music_names = Enum::deserialize(5 << 8 | 65, archive.file(&65).unwrap());
-    let music_indices = Enum::deserialize(5 << 8 | 71, archive.file(&71).unwrap());
+    let music_names = Enum::deserialize(5 << 8 | 65, archive.file(&65).unwrap(), config.recover).context(error::Read { what: "music names" })?;
+    let music_indices = Enum::deserialize(5 << 8 | 71, archive.file(&71).unwrap(), config.recover).context(error::Read { what: "music indices" })?;
     let audio_archives = CacheIndex::new(IndexType::AUDIOSTREAMS, config.input.clone())?;
 
     let progress = ProgressBar::new(music_names.variants.len() as u64).with_style(
@@ -29,11 +28,15 @@ pub fn export_each(config: &crate::cli::Config) -> CacheResult<()> {
     );
 
     for (archive_id, name) in music_names.variants.into_iter() {
+        if !config.keeps(archive_id as u32) {
+            progress.inc(1);
+            continue;
+        }
         let name = match name {
             Value::String(s) if s.chars().all(|c| c == ' ') => format!("Unnamed track {archive_id}"),
             // Check for bad filenames
             // Almost never happens, so we check first before possibly creating a new string
-            Value::String(s) if s.chars().any(|c| ['?', '/', '\\'].contains(&c)) => s.chars().filter(|c| ['?', '/', '\\'].contains(c)).collect(),
+            Value::String(s) if s.chars().any(|c| ['?', '/', '\\'].contains(&c)) => s.chars().filter(|c| !['?', '/', '\\'].contains(c)).collect(),
             Value::String(s) => (*s).to_owned(),
             _ => unreachable!(),
         };
@@ -42,7 +45,7 @@ pub fn export_each(config: &crate::cli::Config) -> CacheResult<()> {
             Some(Value::Integer(i)) => *i as u32,
             Some(_) => unreachable!(),
             None => {
-                progress.println(format!("    {} unable to create `{name}` is not present", style("Warning").yellow()));
+                warning::warn(Warning::MissingTrack { name });
                 progress.inc(1);
                 continue;
             }
@@ -52,50 +55,38 @@ pub fn export_each(config: &crate::cli::Config) -> CacheResult<()> {
             Ok(file) => file.file(&0).unwrap(),
             _ => {
                 // Seems like things are lazily loaded.
-                progress.println(format!("    {} `{name}` is not present", style("Warning").yellow()));
+                warning::warn(Warning::MissingTrack { name });
                 progress.inc(1);
                 continue;
             }
         };
 
         let jaga = decode_first(&mut data);
-        let out = path_macro::path!(config.output / "music" / name);
-        fs::create_dir_all(&out).unwrap();
-
-        let file_name = path!(&out / format!("{music_archive_id}.ogg"));
-        let mut file = File::create(&file_name).unwrap();
-        file.write_all(&data).unwrap();
-
-        // Prepare a process that invokes `Sox` to concatenate all these files.
-        // This is really scuffed, but SoX seems to be only program capable of handling it
-        // Simply concatenating the files should normally work for .ogg files, but not for these...
-        // See also https://github.com/villermen/runescape-cache-tools/issues/8
-        let mut command = Command::new("sox");
-        command.arg(file_name);
-
-        // The first one is the one that's already written
+        let mut chunks = vec![data];
+        // The first one is the one that was just read
         for chunk in jaga.chunks.iter().skip(1) {
-            let archive_id = chunk.archive_id;
-            let more_data = audio_archives.archive(archive_id).unwrap().file(&0).unwrap();
-            assert_eq!(more_data.len(), chunk.length as usize);
-            let file_name = path!(&out / format!("{archive_id}.ogg"));
-            let mut file = File::create(&file_name).unwrap();
-            file.write_all(&more_data).unwrap();
-            command.arg(file_name);
+            let more_data = audio_archives.archive(chunk.archive_id).ok().and_then(|archive| archive.file(&0));
+            match more_data {
+                Some(more_data) if more_data.len() == chunk.length as usize => chunks.push(more_data),
+                _ => break,
+            }
         }
 
-        command.arg(path!(config.output / "music" / format!("{name}.ogg")));
-        if let Err(e) = command.output() {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                progress.println(format!(
-                    "    {} The `sox` program is required to concatenate audio files. Please ensure it is installed{}.",
-                    style("Error").red(),
-                    if cfg!(windows) { "and visible in PATH" } else { "" }
-                ));
-                progress.finish_and_clear();
-                return Ok(());
-            }
-            panic!("{e}");
+        if chunks.len() != jaga.chunks.len() {
+            warning::warn(Warning::IncompleteTrack {
+                name: name.clone(),
+                chunks: chunks.len(),
+                expected: jaga.chunks.len(),
+            });
+        }
+
+        match reassemble(&chunks) {
+            Ok(ogg) => config.sink()?.put(format!("music/{name}.ogg"), &ogg)?,
+            Err(unjoinable) => warning::warn(Warning::UnjoinableTrack {
+                name,
+                chunk: unjoinable.chunk(),
+                reason: unjoinable.reason(),
+            }),
         }
         progress.inc(1);
     }
@@ -146,3 +137,222 @@ fn decode_first(buffer: &mut Bytes) -> Jaga {
         chunks,
     }
 }
+
+/// A page of an Ogg stream.
+#[derive(Debug, Clone)]
+struct OggPage {
+    flags: u8,
+    granule: i64,
+    serial: u32,
+    lacing: Bytes,
+    data: Bytes,
+}
+
+impl OggPage {
+    const CONTINUED: u8 = 0x1;
+    const FIRST: u8 = 0x2;
+    const LAST: u8 = 0x4;
+
+    /// Splits a chunk into its pages, or returns `None` if it is not an Ogg stream.
+    fn read_all(mut buffer: Bytes) -> Option<Vec<Self>> {
+        let mut pages = Vec::new();
+        while buffer.has_remaining() {
+            if buffer.remaining() < 27 || buffer.get_array::<4>() != *b"OggS" || buffer.get_u8() != 0 {
+                return None;
+            }
+            let flags = buffer.get_u8();
+            let granule = buffer.get_i64_le();
+            let serial = buffer.get_u32_le();
+            // The sequence number and checksum are rewritten anyway.
+            buffer.advance(8);
+
+            let segments = buffer.get_u8() as usize;
+            if buffer.remaining() < segments {
+                return None;
+            }
+            let lacing = buffer.split_to(segments);
+            let len = lacing.iter().map(|&l| l as usize).sum();
+            if buffer.remaining() < len {
+                return None;
+            }
+            let data = buffer.split_to(len);
+            pages.push(Self {
+                flags,
+                granule,
+                serial,
+                lacing,
+                data,
+            });
+        }
+        Some(pages)
+    }
+
+    fn write(&self, sequence: u32, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend(b"OggS");
+        out.push(0);
+        out.push(self.flags);
+        out.extend(self.granule.to_le_bytes());
+        out.extend(self.serial.to_le_bytes());
+        out.extend(sequence.to_le_bytes());
+        out.extend([0; 4]);
+        out.push(self.lacing.len() as u8);
+        out.extend(&self.lacing);
+        out.extend(&self.data);
+
+        let crc = ogg_crc(&out[start..]);
+        out[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+    }
+}
+
+/// The checksum of an Ogg page, which is a CRC-32 that is not reflected.
+fn ogg_crc(page: &[u8]) -> u32 {
+    page.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u32) << 24), |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Why the chunks of a track could not be joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unjoinable {
+    /// The chunk is not an Ogg stream.
+    NotOgg { chunk: usize },
+    /// The chunk was encoded with other settings than the first one, so it cannot continue it.
+    Headers { chunk: usize },
+}
+
+impl Unjoinable {
+    /// The chunk that could not be joined.
+    pub const fn chunk(&self) -> usize {
+        match self {
+            Self::NotOgg { chunk } | Self::Headers { chunk } => *chunk,
+        }
+    }
+
+    /// What is wrong with the chunk.
+    pub const fn reason(&self) -> &'static str {
+        match self {
+            Self::NotOgg { .. } => "is not an Ogg stream",
+            Self::Headers { .. } => "has other Vorbis headers than the first chunk",
+        }
+    }
+}
+
+/// Joins the chunks of a track into one Ogg stream.
+///
+/// The chunks are separate streams that continue each other. They each repeat the Vorbis headers,
+/// which must be the same for the audio of one to be decodable with the headers of another.
+/// The headers of the first one are kept, and the pages of the others are renumbered and have their positions shifted to follow on.
+pub fn reassemble(chunks: &[Bytes]) -> Result<Vec<u8>, Unjoinable> {
+    let mut pages = Vec::new();
+    let mut first_headers = Vec::new();
+    let mut offset = 0;
+    for (chunk, data) in chunks.iter().enumerate() {
+        let mut chunk_pages = OggPage::read_all(data.clone()).ok_or(Unjoinable::NotOgg { chunk })?;
+        // The headers have no position. Their packets can be split over pages differently, so their data is compared.
+        let count = chunk_pages.iter().take_while(|page| page.granule == 0).count();
+        let headers = chunk_pages[..count].iter().flat_map(|page| &page.data[..]).copied().collect::<Vec<u8>>();
+        if chunk == 0 {
+            first_headers = headers;
+        } else {
+            if headers != first_headers {
+                return Err(Unjoinable::Headers { chunk });
+            }
+            chunk_pages.drain(..count);
+        }
+        let end = chunk_pages.iter().map(|page| page.granule).max().unwrap_or(0);
+        for mut page in chunk_pages {
+            if page.granule != -1 {
+                page.granule += offset;
+            }
+            pages.push(page);
+        }
+        offset += end;
+    }
+
+    let serial = pages.first().ok_or(Unjoinable::NotOgg { chunk: 0 })?.serial;
+    let count = pages.len();
+    let mut out = Vec::with_capacity(chunks.iter().map(Bytes::len).sum());
+    for (sequence, mut page) in pages.into_iter().enumerate() {
+        page.serial = serial;
+        page.flags &= OggPage::CONTINUED;
+        if sequence == 0 {
+            page.flags |= OggPage::FIRST;
+        }
+        if sequence == count - 1 {
+            page.flags |= OggPage::LAST;
+        }
+        page.write(sequence as u32, &mut out);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(flags: u8, granule: i64, serial: u32, data: &'static [u8]) -> Bytes {
+        let page = OggPage {
+            flags,
+            granule,
+            serial,
+            lacing: Bytes::from(vec![data.len() as u8]),
+            data: Bytes::from_static(data),
+        };
+        let mut out = Vec::new();
+        page.write(0, &mut out);
+        Bytes::from(out)
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(ogg_crc(b"123456789"), 0x89A1_897F);
+    }
+
+    #[test]
+    fn reassemble_chunks() {
+        let first = [page(OggPage::FIRST, 0, 1, b"header"), page(0, 100, 1, b"audio")].concat();
+        let second = [page(OggPage::FIRST, 0, 2, b"header"), page(OggPage::LAST, 50, 2, b"more audio")].concat();
+        let ogg = reassemble(&[Bytes::from(first), Bytes::from(second)]).unwrap();
+
+        let pages = OggPage::read_all(Bytes::from(ogg.clone())).unwrap();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages.iter().map(|page| page.granule).collect::<Vec<_>>(), [0, 100, 150]);
+        assert!(pages.iter().all(|page| page.serial == 1));
+        assert_eq!(pages[0].flags, OggPage::FIRST);
+        assert_eq!(pages[2].flags, OggPage::LAST);
+        assert_eq!(&pages[2].data[..], b"more audio");
+
+        // Every page has a valid checksum.
+        let mut offset = 0;
+        for (sequence, page) in pages.iter().enumerate() {
+            let len = 27 + page.lacing.len() + page.data.len();
+            let mut copy = ogg[offset..offset + len].to_vec();
+            assert_eq!(u32::from_le_bytes(copy[18..22].try_into().unwrap()), sequence as u32);
+            let crc = u32::from_le_bytes(copy[22..26].try_into().unwrap());
+            copy[22..26].fill(0);
+            assert_eq!(ogg_crc(&copy), crc);
+            offset += len;
+        }
+    }
+
+    #[test]
+    fn mismatched_headers() {
+        let first = [page(OggPage::FIRST, 0, 1, b"header"), page(0, 100, 1, b"audio")].concat();
+        let second = [page(OggPage::FIRST, 0, 2, b"other header"), page(OggPage::LAST, 50, 2, b"more audio")].concat();
+        assert_eq!(
+            reassemble(&[Bytes::from(first.clone()), Bytes::from(second)]),
+            Err(Unjoinable::Headers { chunk: 1 })
+        );
+        assert_eq!(
+            reassemble(&[Bytes::from(first), Bytes::from_static(b"not ogg")]),
+            Err(Unjoinable::NotOgg { chunk: 1 })
+        );
+    }
+}
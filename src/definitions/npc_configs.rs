@@ -1,51 +1,52 @@
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-};
+use core::panic::Location;
+use std::collections::BTreeMap;
 
 use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
-    buf::{BufExtra, JString},
+    buf::{BufExtra, JString, NotExhausted, ReadError},
     error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
 };
+use schemars::JsonSchema;
 use serde::Serialize;
-#[cfg(any(feature = "rs3", feature = "osrs"))]
-use {crate::definitions::indextype::IndexType, rs3cache_backend::index::CacheIndex};
 
 #[cfg(feature = "osrs")]
 use crate::definitions::indextype::ConfigType;
-use crate::structures::paramtable::ParamTable;
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::definitions::indextype::IndexType;
+use crate::{cli::Revision, structures::paramtable::ParamTable};
 
 /// Describes the properties of a given [`Npc`](crate::definitions::npcs::Npc).
 
 #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct NpcConfig {
     /// Its id.
     pub id: u32,
     #[serde(flatten)]
     pub models: Option<NpcModels>,
     pub name: Option<JString<Bytes>>,
+    #[cfg(feature = "legacy")]
+    pub description: Option<JString<Bytes>>,
     pub size: Option<u8>,
-    #[cfg(feature = "osrs")]
+    #[cfg(any(feature = "osrs", feature = "legacy"))]
     pub standing_animation: Option<u16>,
     #[cfg(feature = "osrs")]
     pub idle_90_left_animation: Option<u16>,
     #[cfg(feature = "osrs")]
     pub idle_90_right_animation: Option<u16>,
-    #[cfg(feature = "osrs")]
+    #[cfg(any(feature = "osrs", feature = "legacy"))]
     pub walking_animation: Option<u16>,
-    #[cfg(feature = "osrs")]
+    #[cfg(any(feature = "osrs", feature = "legacy"))]
     pub rotate_180_animation: Option<u16>,
-    #[cfg(feature = "osrs")]
+    #[cfg(any(feature = "osrs", feature = "legacy"))]
     pub rotate_90_right_animation: Option<u16>,
-    #[cfg(feature = "osrs")]
+    #[cfg(any(feature = "osrs", feature = "legacy"))]
     pub rotate_90_left_animation: Option<u16>,
     #[cfg(feature = "osrs")]
     pub run_animation: Option<u16>,
@@ -86,7 +87,7 @@ pub struct NpcConfig {
     pub model_contract: Option<i8>,
     #[cfg(feature = "rs3")]
     pub head_icon_data: Option<Vec<(Option<u32>, Option<u32>)>>,
-    #[cfg(feature = "osrs")]
+    #[cfg(any(feature = "osrs", feature = "legacy"))]
     pub head_icon_data: Option<u16>,
     pub unknown_103: Option<u16>,
     pub morphs_1: Option<NpcMorphTable>,
@@ -132,12 +133,16 @@ pub struct NpcConfig {
     pub unknown_184: Option<u16>,
     #[serde(flatten)]
     pub params: Option<ParamTable>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl NpcConfig {
     /// Returns a mapping of all [npc configurations](NpcConfig)
     #[cfg(feature = "rs3")]
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let revision = config.revision();
         let archives = CacheIndex::new(IndexType::NPC_CONFIG, config.input.clone())?.into_iter();
 
         let npc_configs = archives
@@ -149,145 +154,202 @@ impl NpcConfig {
                     .into_iter()
                     .map(move |(file_id, file)| (archive_id << 7 | file_id, file))
             })
-            .map(|(id, file)| (id, Self::deserialize(id, file)))
-            .collect::<BTreeMap<u32, Self>>();
+            .map(|(id, file)| try { (id, Self::deserialize(id, file, revision, config.recover)?) })
+            .filter_map(|result| config.capture("npc configs", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "npc configs" })?;
         Ok(npc_configs)
     }
 
     #[cfg(feature = "osrs")]
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
-        Ok(CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+        let revision = config.revision();
+        CacheIndex::new(IndexType::CONFIG, config.input.clone())?
             .archive(ConfigType::NPC_CONFIG)?
             .take_files()
             .into_iter()
-            .map(|(file_id, file)| (file_id, Self::deserialize(file_id, file)))
-            .collect())
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, revision, config.recover)?) })
+            .filter_map(|result| config.capture("npc configs", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "npc configs" })
     }
 
     #[cfg(feature = "legacy")]
-    pub fn dump_all(_config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
-        todo!()
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let cache = CacheIndex::new(0, config.input.clone())?;
+        let archive = cache.archive(2)?;
+        let mut file = archive.file_named("npc.dat")?;
+        let mut offset_data = archive.file_named("npc.idx")?;
+
+        let _count = file.try_get_u16().context(error::Read { what: "npc configs" })?;
+        let len = offset_data.try_get_u16().context(error::Read { what: "npc config offsets" })?;
+
+        let revision = config.revision();
+        let mut npcs = BTreeMap::new();
+        for id in 0..len {
+            let piece_len = offset_data.try_get_u16().context(error::Read { what: "npc config offsets" })?;
+            let data = file.split_to(piece_len as usize);
+            let Some(npc) = config.capture("npc configs", Self::deserialize(id as u32, data, revision, config.recover)) else { continue };
+            npcs.insert(id as u32, npc.context(error::Read { what: "npc configs" })?);
+        }
+
+        Ok(npcs)
     }
 
-    pub fn deserialize(id: u32, mut buffer: Bytes) -> Self {
+    pub fn deserialize(id: u32, mut buffer: Bytes, revision: Revision, recovering: bool) -> Result<Self, ReadError> {
         let mut npc = Self { id, ..Default::default() };
 
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
         loop {
-            match buffer.get_u8() {
-                0 => {
-                    debug_assert_eq!(buffer.remaining(), 0, "The buffer was not fully read. {npc}");
-                    break npc;
-                }
-                1 => npc.models = Some(NpcModels::deserialize(&mut buffer)),
-                2 => npc.name = Some(buffer.get_string()),
-                12 => npc.size = Some(buffer.get_u8()),
-                #[cfg(feature = "osrs")]
-                13 => npc.standing_animation = Some(buffer.get_u16()),
-                #[cfg(feature = "osrs")]
-                14 => npc.walking_animation = Some(buffer.get_u16()),
-                #[cfg(feature = "osrs")]
-                15 => npc.idle_90_left_animation = Some(buffer.get_u16()),
-                #[cfg(feature = "osrs")]
-                16 => npc.idle_90_right_animation = Some(buffer.get_u16()),
-                #[cfg(feature = "osrs")]
-                17 => {
-                    npc.walking_animation = Some(buffer.get_u16());
-                    npc.rotate_180_animation = Some(buffer.get_u16());
-                    npc.rotate_90_right_animation = Some(buffer.get_u16());
-                    npc.rotate_90_left_animation = Some(buffer.get_u16());
-                }
-                #[cfg(feature = "osrs")]
-                18 => npc.category = Some(buffer.get_u16()),
-                opcode @ 30..=34 => {
-                    let actions = npc.actions.get_or_insert([None, None, None, None, None]);
-                    actions[opcode as usize - 30] = Some(buffer.get_string());
-                }
-                40 => npc.colour_replacements = Some(ColourReplacements::deserialize(&mut buffer)),
-                41 => npc.texture_replacements = Some(Textures::deserialize(&mut buffer)),
-                42 => npc.recolour_palette = Some(RecolourPalette::deserialize(&mut buffer)),
-                44 => npc.recolour_indices = Some(buffer.get_masked_index()),
-                45 => npc.retexture_indices = Some(buffer.get_masked_index()),
-                60 => npc.head_models = Some(HeadModels::deserialize(&mut buffer)),
-                93 => npc.draw_map_dot = Some(false),
-                95 => npc.combat = Some(buffer.get_u16()),
-                97 => npc.scale_xz = Some(buffer.get_u16()),
-                98 => npc.scale_y = Some(buffer.get_u16()),
-                99 => npc.unknown_99 = Some(false),
-                100 => npc.ambience = Some(buffer.get_i8()),
-                101 => npc.ambience = Some(buffer.get_i8()),
-                #[cfg(feature = "rs3")]
-                102 => npc.head_icon_data = Some(buffer.get_masked_data()),
-                #[cfg(feature = "osrs")]
-                102 => npc.head_icon_data = Some(buffer.get_u16()),
-                103 => npc.unknown_103 = Some(buffer.get_u16()),
-                106 => npc.morphs_1 = Some(NpcMorphTable::deserialize(&mut buffer)),
-                107 => npc.unknown_107 = Some(false),
-                109 => npc.slow_walk = Some(false),
-                111 => npc.animate_idle = Some(false),
-                113 => npc.shadow = Some(Shadow::deserialize(&mut buffer)),
-                #[cfg(feature = "rs3")]
-                114 => npc.shadow_alpha_intensity = Some(ShadowIntensity::deserialize(&mut buffer)),
-                #[cfg(feature = "osrs")]
-                114 => npc.run_animation = Some(buffer.get_u16()),
-                #[cfg(feature = "osrs")]
-                115 => {
-                    npc.run_animation = Some(buffer.get_u16());
-                    npc.run_180_animation = Some(buffer.get_u16());
-                    npc.run_90_left_animation = Some(buffer.get_u16());
-                    npc.run_90_right_animation = Some(buffer.get_u16());
-                }
-                #[cfg(feature = "osrs")]
-                116 => npc.crawl_animation = Some(buffer.get_u16()),
-                #[cfg(feature = "osrs")]
-                117 => {
-                    npc.crawl_animation = Some(buffer.get_u16());
-                    npc.crawl_180_animation = Some(buffer.get_u16());
-                    npc.crawl_90_left_animation = Some(buffer.get_u16());
-                    npc.crawl_90_right_animation = Some(buffer.get_u16());
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(npc);
+                        }
+                    }
+                    1 => npc.models = Some(NpcModels::deserialize(&mut buffer)?),
+                    2 => npc.name = Some(buffer.try_get_string()?),
+                    #[cfg(feature = "legacy")]
+                    3 => npc.description = Some(buffer.try_get_string()?),
+                    12 => npc.size = Some(buffer.try_get_u8()?),
+                    #[cfg(any(feature = "osrs", feature = "legacy"))]
+                    13 => npc.standing_animation = Some(buffer.try_get_u16()?),
+                    #[cfg(any(feature = "osrs", feature = "legacy"))]
+                    14 => npc.walking_animation = Some(buffer.try_get_u16()?),
+                    #[cfg(feature = "osrs")]
+                    15 => npc.idle_90_left_animation = Some(buffer.try_get_u16()?),
+                    #[cfg(feature = "osrs")]
+                    16 => npc.idle_90_right_animation = Some(buffer.try_get_u16()?),
+                    #[cfg(any(feature = "osrs", feature = "legacy"))]
+                    17 => {
+                        npc.walking_animation = Some(buffer.try_get_u16()?);
+                        npc.rotate_180_animation = Some(buffer.try_get_u16()?);
+                        npc.rotate_90_right_animation = Some(buffer.try_get_u16()?);
+                        npc.rotate_90_left_animation = Some(buffer.try_get_u16()?);
+                    }
+                    #[cfg(feature = "osrs")]
+                    18 => npc.category = Some(buffer.try_get_u16()?),
+                    opcode @ 30..=34 => {
+                        let actions = npc.actions.get_or_insert([None, None, None, None, None]);
+                        actions[opcode as usize - 30] = Some(buffer.try_get_string()?);
+                    }
+                    40 => npc.colour_replacements = Some(ColourReplacements::deserialize(&mut buffer)?),
+                    41 => npc.texture_replacements = Some(Textures::deserialize(&mut buffer)?),
+                    42 => npc.recolour_palette = Some(RecolourPalette::deserialize(&mut buffer)?),
+                    44 => npc.recolour_indices = Some(buffer.try_get_masked_index()?),
+                    45 => npc.retexture_indices = Some(buffer.try_get_masked_index()?),
+                    60 => npc.head_models = Some(HeadModels::deserialize(&mut buffer)?),
+                    // Unused, for example the legacy clients never read these.
+                    #[cfg(feature = "legacy")]
+                    90..=92 => {
+                        buffer.try_get_u16()?;
+                    }
+                    93 => npc.draw_map_dot = Some(false),
+                    95 => npc.combat = Some(buffer.try_get_u16()?),
+                    97 => npc.scale_xz = Some(buffer.try_get_u16()?),
+                    98 => npc.scale_y = Some(buffer.try_get_u16()?),
+                    99 => npc.unknown_99 = Some(false),
+                    100 => npc.ambience = Some(buffer.try_get_i8()?),
+                    101 => npc.ambience = Some(buffer.try_get_i8()?),
+                    #[cfg(feature = "rs3")]
+                    102 => npc.head_icon_data = Some(buffer.get_masked_data()),
+                    #[cfg(any(feature = "osrs", feature = "legacy"))]
+                    102 => npc.head_icon_data = Some(buffer.try_get_u16()?),
+                    103 => npc.unknown_103 = Some(buffer.try_get_u16()?),
+                    106 => npc.morphs_1 = Some(NpcMorphTable::deserialize(&mut buffer, revision)?),
+                    107 => npc.unknown_107 = Some(false),
+                    109 => npc.slow_walk = Some(false),
+                    111 => npc.animate_idle = Some(false),
+                    113 => npc.shadow = Some(Shadow::deserialize(&mut buffer)?),
+                    #[cfg(feature = "rs3")]
+                    114 => npc.shadow_alpha_intensity = Some(ShadowIntensity::deserialize(&mut buffer)?),
+                    #[cfg(feature = "osrs")]
+                    114 => npc.run_animation = Some(buffer.try_get_u16()?),
+                    #[cfg(feature = "osrs")]
+                    115 => {
+                        npc.run_animation = Some(buffer.try_get_u16()?);
+                        npc.run_180_animation = Some(buffer.try_get_u16()?);
+                        npc.run_90_left_animation = Some(buffer.try_get_u16()?);
+                        npc.run_90_right_animation = Some(buffer.try_get_u16()?);
+                    }
+                    #[cfg(feature = "osrs")]
+                    116 => npc.crawl_animation = Some(buffer.try_get_u16()?),
+                    #[cfg(feature = "osrs")]
+                    117 => {
+                        npc.crawl_animation = Some(buffer.try_get_u16()?);
+                        npc.crawl_180_animation = Some(buffer.try_get_u16()?);
+                        npc.crawl_90_left_animation = Some(buffer.try_get_u16()?);
+                        npc.crawl_90_right_animation = Some(buffer.try_get_u16()?);
+                    }
+                    118 => npc.morphs_2 = Some(ExtendedNpcMorphTable::deserialize(&mut buffer, revision)?),
+                    119 => npc.movement_capabilities = Some(buffer.try_get_i8()?),
+                    121 => npc.translations = Some(Translations::deserialize(&mut buffer)?),
+                    123 => npc.icon_height = Some(buffer.try_get_u16()?),
+                    125 => npc.respawn_direction = Some(buffer.try_get_i8()?),
+                    127 => npc.animation_group = Some(buffer.try_get_u16()?),
+                    128 => npc.movement_type = Some(buffer.try_get_i8()?),
+                    134 => npc.ambient_sound = Some(AmbientSounds::deserialize(&mut buffer)?),
+                    135 => npc.old_cursor = Some(OldCursors::deserialize(&mut buffer)?),
+                    136 => npc.old_cursor_2 = Some(OldCursors::deserialize(&mut buffer)?),
+                    137 => npc.attack_cursor = Some(buffer.try_get_u16()?),
+                    138 => npc.army_icon = Some(buffer.try_get_smart32()?.unwrap()),
+                    140 => npc.unknown_140 = Some(buffer.try_get_u8()?),
+                    141 => npc.animate_idle = Some(true),
+                    142 => npc.mapfunction = Some(buffer.try_get_u16()?),
+                    143 => npc.unknown_143 = Some(true),
+                    opcode @ 150..=154 => {
+                        let actions = npc.member_actions.get_or_insert([None, None, None, None, None]);
+                        actions[opcode as usize - 150] = Some(buffer.try_get_string()?);
+                    }
+                    155 => npc.unknown_155 = Some(Unknown155::deserialize(&mut buffer)?),
+                    158 => npc.unknown_158 = Some(true),
+                    159 => npc.unknown_159 = Some(false),
+                    160 => npc.quests = Some(Quests::deserialize(&mut buffer)?),
+                    162 => npc.unknown_162 = Some(true),
+                    163 => npc.unknown_163 = Some(buffer.try_get_u8()?),
+                    164 => npc.unknown_164 = Some(Unknown164::deserialize(&mut buffer)?),
+                    165 => npc.unknown_165 = Some(buffer.try_get_u8()?),
+                    168 => npc.unknown_168 = Some(buffer.try_get_u8()?),
+                    169 => npc.unknown_169 = Some(false),
+                    opcode @ 170..=175 => {
+                        let actions = npc.action_cursors.get_or_insert([None, None, None, None, None, None]);
+                        actions[opcode as usize - 170] = Some(buffer.try_get_u16()?);
+                    }
+                    178 => npc.unknown_178 = Some(true),
+                    179 => npc.unknown_179 = Some(Unknown179::deserialize(&mut buffer)?),
+                    182 => npc.unknown_182 = Some(true),
+                    184 => npc.unknown_184 = Some(buffer.try_get_unsigned_smart()?),
+                    249 => npc.params = Some(ParamTable::deserialize(&mut buffer)),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
                 }
-                118 => npc.morphs_2 = Some(ExtendedNpcMorphTable::deserialize(&mut buffer)),
-                119 => npc.movement_capabilities = Some(buffer.get_i8()),
-                121 => npc.translations = Some(Translations::deserialize(&mut buffer)),
-                123 => npc.icon_height = Some(buffer.get_u16()),
-                125 => npc.respawn_direction = Some(buffer.get_i8()),
-                127 => npc.animation_group = Some(buffer.get_u16()),
-                128 => npc.movement_type = Some(buffer.get_i8()),
-                134 => npc.ambient_sound = Some(AmbientSounds::deserialize(&mut buffer)),
-                135 => npc.old_cursor = Some(OldCursors::deserialize(&mut buffer)),
-                136 => npc.old_cursor_2 = Some(OldCursors::deserialize(&mut buffer)),
-                137 => npc.attack_cursor = Some(buffer.get_u16()),
-                138 => npc.army_icon = Some(buffer.get_smart32().unwrap()),
-                140 => npc.unknown_140 = Some(buffer.get_u8()),
-                141 => npc.animate_idle = Some(true),
-                142 => npc.mapfunction = Some(buffer.get_u16()),
-                143 => npc.unknown_143 = Some(true),
-                opcode @ 150..=154 => {
-                    let actions = npc.member_actions.get_or_insert([None, None, None, None, None]);
-                    actions[opcode as usize - 150] = Some(buffer.get_string());
-                }
-                155 => npc.unknown_155 = Some(Unknown155::deserialize(&mut buffer)),
-                158 => npc.unknown_158 = Some(true),
-                159 => npc.unknown_159 = Some(false),
-                160 => npc.quests = Some(Quests::deserialize(&mut buffer)),
-                162 => npc.unknown_162 = Some(true),
-                163 => npc.unknown_163 = Some(buffer.get_u8()),
-                164 => npc.unknown_164 = Some(Unknown164::deserialize(&mut buffer)),
-                165 => npc.unknown_165 = Some(buffer.get_u8()),
-                168 => npc.unknown_168 = Some(buffer.get_u8()),
-                169 => npc.unknown_169 = Some(false),
-                opcode @ 170..=175 => {
-                    let actions = npc.action_cursors.get_or_insert([None, None, None, None, None, None]);
-                    actions[opcode as usize - 170] = Some(buffer.get_u16());
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
                 }
-                178 => npc.unknown_178 = Some(true),
-                179 => npc.unknown_179 = Some(Unknown179::deserialize(&mut buffer)),
-                180 => unimplemented!(),
-                181 => unimplemented!(),
-                182 => npc.unknown_182 = Some(true),
-                184 => npc.unknown_184 = Some(buffer.get_unsigned_smart()),
-                249 => npc.params = Some(ParamTable::deserialize(&mut buffer)),
-                missing => {
-                    unimplemented!("NpcConfig::deserialize cannot deserialize opcode {} in npc: \n {}\n", missing, npc)
+                Err(e) => {
+                    npc.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &npc,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(npc);
                 }
             }
         }
@@ -319,16 +381,20 @@ pub mod npc_config_fields {
 
     use std::{collections::BTreeMap, iter};
 
-    use bytes::{Buf, Bytes};
+    use bytes::Bytes;
     #[cfg(feature = "pyo3")]
     use pyo3::prelude::*;
-    use rs3cache_backend::buf::BufExtra;
+    use rs3cache_backend::buf::{BufExtra, ReadError};
+    use schemars::JsonSchema;
     use serde::Serialize;
 
-    use crate::types::variables::{Varbit, Varp, VarpOrVarbit};
+    use crate::{
+        cli::Revision,
+        types::variables::{Varbit, Varp, VarpOrVarbit},
+    };
     /// Contains an array of possible ids this npc can morph into, controlled by either a varbit or varp.
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct NpcMorphTable {
         #[serde(flatten)]
         pub var: VarpOrVarbit,
@@ -338,30 +404,32 @@ pub mod npc_config_fields {
 
     impl NpcMorphTable {
         /// Constructor for [`NpcMorphTable`]
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let varbit = Varbit::new(buffer.get_u16());
-            let varp = Varp::new(buffer.get_u16());
+        pub fn deserialize(buffer: &mut Bytes, revision: Revision) -> Result<Self, ReadError> {
+            let varbit = Varbit::new(buffer.try_get_u16()?);
+            let varp = Varp::new(buffer.try_get_u16()?);
             let var = VarpOrVarbit::new(varp, varbit);
 
-            let count = if cfg!(feature = "rs3") {
-                buffer.get_unsigned_smart() as usize
+            let count = if revision < Revision::Latest {
+                buffer.try_get_u8()? as usize
             } else {
-                buffer.get_u8() as usize
+                buffer.try_get_unsigned_smart()? as usize
             };
 
-            let ids = iter::repeat_with(|| match buffer.get_u16() {
-                u16::MAX => None,
-                id => Some(id as u32),
+            let ids = iter::repeat_with(|| {
+                buffer.try_get_u16().map(|id| match id {
+                    u16::MAX => None,
+                    id => Some(id as u32),
+                })
             })
             .take(count + 1)
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, ReadError>>()?;
 
-            Self { var, ids }
+            Ok(Self { var, ids })
         }
     }
     /// Like [`NpcMorphTable`], but with a default value.
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct ExtendedNpcMorphTable {
         pub var: VarpOrVarbit,
 
@@ -372,62 +440,68 @@ pub mod npc_config_fields {
 
     impl ExtendedNpcMorphTable {
         /// Constructor for [`ExtendedNpcMorphTable`]
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let varbit = Varbit::new(buffer.get_u16());
-            let varp = Varp::new(buffer.get_u16());
+        pub fn deserialize(buffer: &mut Bytes, revision: Revision) -> Result<Self, ReadError> {
+            let varbit = Varbit::new(buffer.try_get_u16()?);
+            let varp = Varp::new(buffer.try_get_u16()?);
 
             let var = VarpOrVarbit::new(varp, varbit);
 
-            let default_id = buffer.get_smart32();
+            let default_id = buffer.try_get_smart32()?;
 
-            let count = if cfg!(feature = "rs3") {
-                buffer.get_unsigned_smart() as usize
+            let count = if revision < Revision::Latest {
+                buffer.try_get_u8()? as usize
             } else {
-                buffer.get_u8() as usize
+                buffer.try_get_unsigned_smart()? as usize
             };
 
-            let ids = iter::repeat_with(|| match buffer.get_u16() {
-                u16::MAX => None,
-                id => Some(id as u32),
+            let ids = iter::repeat_with(|| {
+                buffer.try_get_u16().map(|id| match id {
+                    u16::MAX => None,
+                    id => Some(id as u32),
+                })
             })
             .take(count + 1)
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, ReadError>>()?;
 
-            Self { var, ids, default_id }
+            Ok(Self { var, ids, default_id })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct NpcModels {
         pub models: Vec<Option<u32>>,
     }
 
     impl NpcModels {
         #[cfg(feature = "rs3")]
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_i8() as usize;
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_i8()? as usize;
 
-            let models = iter::repeat_with(|| buffer.get_smart32()).take(count).collect::<Vec<_>>();
-            Self { models }
+            let models = iter::repeat_with(|| buffer.try_get_smart32())
+                .take(count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { models })
         }
 
         #[cfg(any(feature = "osrs", feature = "legacy"))]
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-
-            let models = iter::repeat_with(|| match buffer.get_u16() {
-                u16::MAX => None,
-                other => Some(other as u32),
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+
+            let models = iter::repeat_with(|| {
+                buffer.try_get_u16().map(|model| match model {
+                    u16::MAX => None,
+                    other => Some(other as u32),
+                })
             })
             .take(count)
-            .collect::<Vec<_>>();
-            Self { models }
+            .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { models })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone, Copy)]
+    #[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
     pub struct ShadowIntensity {
         pub src_colour: i8,
 
@@ -435,15 +509,15 @@ pub mod npc_config_fields {
     }
 
     impl ShadowIntensity {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let src_colour = buffer.get_i8();
-            let dst_colour = buffer.get_i8();
-            Self { src_colour, dst_colour }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let src_colour = buffer.try_get_i8()?;
+            let dst_colour = buffer.try_get_i8()?;
+            Ok(Self { src_colour, dst_colour })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone, Copy)]
+    #[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
     pub struct Shadow {
         pub src_colour: u16,
 
@@ -451,15 +525,15 @@ pub mod npc_config_fields {
     }
 
     impl Shadow {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let src_colour = buffer.get_u16();
-            let dst_colour = buffer.get_u16();
-            Self { src_colour, dst_colour }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let src_colour = buffer.try_get_u16()?;
+            let dst_colour = buffer.try_get_u16()?;
+            Ok(Self { src_colour, dst_colour })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct HeadModels {
         #[cfg(feature = "rs3")]
         pub models: Vec<Option<u32>>,
@@ -469,58 +543,64 @@ pub mod npc_config_fields {
 
     impl HeadModels {
         #[cfg(feature = "rs3")]
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_i8() as usize;
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_i8()? as usize;
 
-            let models = iter::repeat_with(|| buffer.get_smart32()).take(count).collect::<Vec<_>>();
-            Self { models }
+            let models = iter::repeat_with(|| buffer.try_get_smart32())
+                .take(count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { models })
         }
 
         #[cfg(any(feature = "osrs", feature = "legacy"))]
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-
-            let models = iter::repeat_with(|| match buffer.get_u16() {
-                u16::MAX => None,
-                other => Some(other),
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+
+            let models = iter::repeat_with(|| {
+                buffer.try_get_u16().map(|model| match model {
+                    u16::MAX => None,
+                    other => Some(other),
+                })
             })
             .take(count)
-            .collect::<Vec<_>>();
-            Self { models }
+            .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { models })
         }
     }
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct ColourReplacements {
         pub colour_replacements: Vec<(u16, u16)>,
     }
 
     impl ColourReplacements {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-            let colour_replacements = iter::repeat_with(|| (buffer.get_u16(), buffer.get_u16())).take(count).collect::<Vec<_>>();
-            Self { colour_replacements }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+            let colour_replacements = iter::repeat_with(|| try { (buffer.try_get_u16()?, buffer.try_get_u16()?) })
+                .take(count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { colour_replacements })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, JsonSchema, Debug, Clone)]
     pub struct Textures {
         pub textures: BTreeMap<u16, u16>,
     }
 
     impl Textures {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-            let textures = iter::repeat_with(|| (buffer.get_u16(), buffer.get_u16()))
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+            let textures = iter::repeat_with(|| try { (buffer.try_get_u16()?, buffer.try_get_u16()?) })
                 .take(count)
-                .collect::<BTreeMap<_, _>>();
-            Self { textures }
+                .collect::<Result<BTreeMap<_, _>, ReadError>>()?;
+            Ok(Self { textures })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone, Copy)]
+    #[derive(Debug, Serialize, JsonSchema, Clone, Copy)]
     pub struct AmbientSounds {
         pub unknown_1: u16,
 
@@ -534,56 +614,58 @@ pub mod npc_config_fields {
     }
 
     impl AmbientSounds {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let unknown_1 = buffer.get_u16();
-            let unknown_2 = buffer.get_u16();
-            let unknown_3 = buffer.get_u16();
-            let unknown_4 = buffer.get_u16();
-            let unknown_5 = buffer.get_u8();
-
-            Self {
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let unknown_1 = buffer.try_get_u16()?;
+            let unknown_2 = buffer.try_get_u16()?;
+            let unknown_3 = buffer.try_get_u16()?;
+            let unknown_4 = buffer.try_get_u16()?;
+            let unknown_5 = buffer.try_get_u8()?;
+
+            Ok(Self {
                 unknown_1,
                 unknown_2,
                 unknown_3,
                 unknown_4,
                 unknown_5,
-            }
+            })
         }
     }
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone)]
+    #[derive(Debug, Serialize, JsonSchema, Clone)]
     pub struct Translations {
         pub translations: Vec<[u8; 4]>,
     }
 
     impl Translations {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-            let translations = iter::repeat_with(|| [buffer.get_u8(), buffer.get_u8(), buffer.get_u8(), buffer.get_u8()])
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+            let translations = iter::repeat_with(|| buffer.try_get_array::<4>())
                 .take(count)
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>, ReadError>>()?;
 
-            Self { translations }
+            Ok(Self { translations })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone)]
+    #[derive(Debug, Serialize, JsonSchema, Clone)]
     pub struct RecolourPalette {
         pub recolour_palette: Vec<i8>,
     }
 
     impl RecolourPalette {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
 
-            let recolour_palette = iter::repeat_with(|| buffer.get_i8()).take(count).collect::<Vec<_>>();
-            Self { recolour_palette }
+            let recolour_palette = iter::repeat_with(|| buffer.try_get_i8())
+                .take(count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { recolour_palette })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone, Copy)]
+    #[derive(Debug, Serialize, JsonSchema, Clone, Copy)]
     pub struct OldCursors {
         pub op: u8,
 
@@ -591,15 +673,15 @@ pub mod npc_config_fields {
     }
 
     impl OldCursors {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let op = buffer.get_u8();
-            let cursor = buffer.get_u16();
-            Self { op, cursor }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let op = buffer.try_get_u8()?;
+            let cursor = buffer.try_get_u16()?;
+            Ok(Self { op, cursor })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone, Copy)]
+    #[derive(Debug, Serialize, JsonSchema, Clone, Copy)]
     pub struct Unknown155 {
         pub unknown_1: i8,
         pub unknown_2: i8,
@@ -608,23 +690,23 @@ pub mod npc_config_fields {
     }
 
     impl Unknown155 {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let unknown_1 = buffer.get_i8();
-            let unknown_2 = buffer.get_i8();
-            let unknown_3 = buffer.get_i8();
-            let unknown_4 = buffer.get_i8();
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let unknown_1 = buffer.try_get_i8()?;
+            let unknown_2 = buffer.try_get_i8()?;
+            let unknown_3 = buffer.try_get_i8()?;
+            let unknown_4 = buffer.try_get_i8()?;
 
-            Self {
+            Ok(Self {
                 unknown_1,
                 unknown_2,
                 unknown_3,
                 unknown_4,
-            }
+            })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone, Copy)]
+    #[derive(Debug, Serialize, JsonSchema, Clone, Copy)]
     pub struct Unknown179 {
         pub unknown_1: u16,
         pub unknown_2: u16,
@@ -635,52 +717,54 @@ pub mod npc_config_fields {
     }
 
     impl Unknown179 {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let unknown_1 = buffer.get_unsigned_smart();
-            let unknown_2 = buffer.get_unsigned_smart();
-            let unknown_3 = buffer.get_unsigned_smart();
-            let unknown_4 = buffer.get_unsigned_smart();
-            let unknown_5 = buffer.get_unsigned_smart();
-            let unknown_6 = buffer.get_unsigned_smart();
-
-            Self {
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let unknown_1 = buffer.try_get_unsigned_smart()?;
+            let unknown_2 = buffer.try_get_unsigned_smart()?;
+            let unknown_3 = buffer.try_get_unsigned_smart()?;
+            let unknown_4 = buffer.try_get_unsigned_smart()?;
+            let unknown_5 = buffer.try_get_unsigned_smart()?;
+            let unknown_6 = buffer.try_get_unsigned_smart()?;
+
+            Ok(Self {
                 unknown_1,
                 unknown_2,
                 unknown_3,
                 unknown_4,
                 unknown_5,
                 unknown_6,
-            }
+            })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone, Copy)]
+    #[derive(Debug, Serialize, JsonSchema, Clone, Copy)]
     pub struct Unknown164 {
         pub unknown_1: u16,
         pub unknown_2: u16,
     }
 
     impl Unknown164 {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let unknown_1 = buffer.get_u16();
-            let unknown_2 = buffer.get_u16();
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let unknown_1 = buffer.try_get_u16()?;
+            let unknown_2 = buffer.try_get_u16()?;
 
-            Self { unknown_1, unknown_2 }
+            Ok(Self { unknown_1, unknown_2 })
         }
     }
 
     #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-    #[derive(Debug, Serialize, Clone)]
+    #[derive(Debug, Serialize, JsonSchema, Clone)]
     pub struct Quests {
         pub quests: Vec<u16>,
     }
 
     impl Quests {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let count = buffer.get_u8() as usize;
-            let quests = iter::repeat_with(|| buffer.get_u16()).take(count).collect::<Vec<_>>();
-            Self { quests }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let count = buffer.try_get_u8()? as usize;
+            let quests = iter::repeat_with(|| buffer.try_get_u16())
+                .take(count)
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            Ok(Self { quests })
         }
     }
 }
@@ -689,17 +773,9 @@ use npc_config_fields::*;
 
 /// Save the npc configs as `npc_configs.json`. Exposed as `--dump npc_configs`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut npc_configs = NpcConfig::dump_all(config)?.into_values().collect::<Vec<_>>();
     npc_configs.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(config.output / "npc_configs.json");
-
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-
-    let data = serde_json::to_string_pretty(&npc_configs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export(config, "npc_configs", &npc_configs)
 }
 
 #[cfg(feature = "rs3")]
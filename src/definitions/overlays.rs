@@ -1,23 +1,23 @@
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-};
+use core::panic::Location;
+use std::collections::BTreeMap;
 
 use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
-    buf::BufExtra,
+    buf::{BufExtra, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
 use serde::Serialize;
 use serde_with::skip_serializing_none;
 
-use crate::definitions::indextype::{ConfigType, IndexType};
+use crate::{
+    cli::Revision,
+    definitions::indextype::{ConfigType, IndexType},
+};
 /// Describes (part of) ground colour.
 #[cfg_attr(feature = "pyo3", pyclass(frozen))]
 #[skip_serializing_none]
@@ -59,60 +59,98 @@ pub struct Overlay {
     #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     op_14: Option<u8>,
 
-    #[cfg(feature = "2009_1_shim")]
+    #[cfg(feature = "2008_3_shim")]
     op_15: Option<u16>,
 
-    #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     op_16: Option<u8>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl Overlay {
     /// Returns a mapping of all [`Overlay`] configurations.
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Overlay>> {
-        Ok(CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+        let revision = config.revision();
+        CacheIndex::new(IndexType::CONFIG, config.input.clone())?
             .archive(ConfigType::OVERLAYS)?
             .take_files()
             .into_iter()
-            .map(|(file_id, file)| (file_id, Overlay::deserialize(file_id, file)))
-            .collect())
+            .map(|(file_id, file)| try { (file_id, Overlay::deserialize(file_id, file, revision, config.recover)?) })
+            .filter_map(|result| config.capture("overlays", result))
+            .collect::<Result<BTreeMap<u32, Overlay>, ReadError>>()
+            .context(error::Read { what: "overlays" })
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> Overlay {
+    #[allow(unused_variables)]
+    fn deserialize(id: u32, mut buffer: Bytes, revision: Revision, recovering: bool) -> Result<Overlay, ReadError> {
         let mut overlay = Overlay { id, ..Default::default() };
 
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
         loop {
-            let opcode = buffer.get_u8();
-            match opcode {
-                0 => {
-                    assert!(!buffer.has_remaining());
-                    break overlay;
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(overlay);
+                        }
+                    }
+                    1 => overlay.primary_colour = Some(buffer.try_get_array()?),
+                    #[cfg(feature = "osrs")]
+                    2 => overlay.texture = Some(buffer.try_get_u8()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    3 => overlay.op_3 = Some(buffer.try_get_u16()?),
+                    5 => overlay.op_5 = Some(true),
+                    7 => overlay.secondary_colour = Some(buffer.try_get_array()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    8 => overlay.op_8 = Some(true),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    9 => overlay.op_9 = Some(buffer.try_get_u16()?),
+                    #[cfg(feature = "rs3")]
+                    10 => overlay.op_10 = Some(true),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    11 => overlay.op_11 = Some(buffer.try_get_u8()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    12 => overlay.op_12 = Some(true),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    13 => overlay.ternary_colour = Some(buffer.try_get_array()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    14 => overlay.op_14 = Some(buffer.try_get_u8()?),
+                    #[cfg(feature = "2008_3_shim")]
+                    15 if revision >= Revision::R2009_1 => overlay.op_15 = Some(buffer.try_get_u16()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    16 if revision >= Revision::R2010_1 => overlay.op_16 = Some(buffer.try_get_u8()?),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    overlay.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &overlay,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(overlay);
                 }
-                1 => overlay.primary_colour = Some(buffer.get_rgb()),
-                #[cfg(feature = "osrs")]
-                2 => overlay.texture = Some(buffer.get_u8()),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                3 => overlay.op_3 = Some(buffer.get_u16()),
-                5 => overlay.op_5 = Some(true),
-                7 => overlay.secondary_colour = Some(buffer.get_rgb()),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                8 => overlay.op_8 = Some(true),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                9 => overlay.op_9 = Some(buffer.get_u16()),
-                #[cfg(feature = "rs3")]
-                10 => overlay.op_10 = Some(true),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                11 => overlay.op_11 = Some(buffer.get_u8()),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                12 => overlay.op_12 = Some(true),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                13 => overlay.ternary_colour = Some(buffer.get_rgb()),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                14 => overlay.op_14 = Some(buffer.get_u8()),
-                #[cfg(feature = "2009_1_shim")]
-                15 => overlay.op_15 = Some(buffer.get_u16()),
-                #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
-                16 => overlay.op_16 = Some(buffer.get_u8()),
-                missing => unimplemented!("Overlay::deserialize cannot deserialize opcode {} in id {}: {:?}", missing, id, buffer),
             }
         }
     }
@@ -128,13 +166,7 @@ impl Display for Overlay {
 
 ///Save the maplabels as `maplabels.json`. Exposed as `--dump maplabels`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut labels = Overlay::dump_all(config)?.into_values().collect::<Vec<_>>();
     labels.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(&config.output / "overlays.json");
-
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&labels).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-    Ok(())
+    crate::output::export(config, "overlays", &labels)
 }
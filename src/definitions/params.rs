@@ -0,0 +1,226 @@
+//! Describes the keys of [`ParamTable`](crate::structures::paramtable::ParamTable)s.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, JString, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    cli::Config,
+    definitions::indextype::{ConfigType, IndexType},
+    structures::paramtable::{Param, ParamTable},
+};
+
+/// Describes what kind of value a param key holds.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct ParamConfig {
+    /// Its id.
+    pub id: u32,
+    /// The script type of the value, as the character used for it in cs2.
+    pub type_char: Option<char>,
+    /// The script type of the value, used instead of [`type_char`](ParamConfig::type_char) by newer caches.
+    #[cfg(feature = "rs3")]
+    pub type_id: Option<u16>,
+    /// The value of this param for things that do not have it, if it holds an integer.
+    pub default_int: Option<i32>,
+    /// The value of this param for things that do not have it, if it holds a string.
+    pub default_string: Option<JString<Bytes>>,
+    /// Whether members-only values are disabled on free worlds. This is `true` unless it is turned off.
+    pub autodisable: Option<bool>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+impl ParamConfig {
+    /// Returns a mapping of all [`ParamConfig`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::PARAMS)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("params", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "params" })
+    }
+
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut param = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(param);
+                        }
+                    }
+                    1 => param.type_char = Some(buffer.try_get_u8()? as char),
+                    2 => param.default_int = Some(buffer.try_get_i32()?),
+                    4 => param.autodisable = Some(false),
+                    5 => param.default_string = Some(buffer.try_get_string()?),
+                    #[cfg(feature = "rs3")]
+                    101 => param.type_id = Some(buffer.try_get_unsigned_smart()?),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    param.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &param,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(param);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for ParamConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl ParamConfig {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("ParamConfig({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("ParamConfig({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the param configs as `params.json`. Exposed as `--dump params`.
+pub fn export(config: &Config) -> CacheResult<()> {
+    let params = ParamConfig::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "params", &params)
+}
+
+/// Types (and names) the params of dumped definitions, as used by `--resolve-params`.
+pub struct ParamResolver {
+    configs: BTreeMap<u32, ParamConfig>,
+    names: BTreeMap<u32, String>,
+}
+
+impl ParamResolver {
+    /// Loads the param configs, and the names passed with `--param-names`.
+    pub fn load(config: &Config) -> CacheResult<Self> {
+        let names = match &config.param_names {
+            Some(path) => {
+                let file = std::fs::read_to_string(path).context(error::Io { path: path.clone() })?;
+                serde_json::from_str(&file).context(error::JsonDecode { file: path.clone() })?
+            }
+            None => BTreeMap::new(),
+        };
+        let configs = ParamConfig::dump_all(config)?;
+        Ok(Self { configs, names })
+    }
+
+    /// Replaces the `params` of a serialized definition with their resolved form,
+    /// see [`ParamTable::resolve`].
+    pub fn resolve(&self, definition: &mut Value) {
+        let Some(Value::Object(params)) = definition.get("params") else { return };
+
+        let params = params
+            .iter()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    Value::Number(n) => Param::Integer(n.as_i64()? as i32),
+                    Value::String(s) => Param::String(JString::from(s.clone())),
+                    _ => return None,
+                };
+                Some((key.parse().ok()?, value))
+            })
+            .collect();
+        let resolved = ParamTable { params }.resolve(&self.configs, &self.names);
+        definition["params"] = serde_json::to_value(resolved).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let resolver = ParamResolver {
+            configs: BTreeMap::from([(
+                1,
+                ParamConfig {
+                    id: 1,
+                    type_char: Some('o'),
+                    ..Default::default()
+                },
+            )]),
+            names: BTreeMap::from([(2, "tooltip".to_string())]),
+        };
+        let mut definition = serde_json::json!({"id": 7, "params": {"1": 995, "2": "Coins", "3": 5}});
+        resolver.resolve(&mut definition);
+
+        assert_eq!(
+            definition,
+            serde_json::json!({"id": 7, "params": {"1": {"item": 995}, "tooltip": "Coins", "3": 5}})
+        );
+    }
+
+    #[test]
+    fn malformed_names() {
+        let folder = crate::output::sink::temp_folder("param_names");
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("names.json");
+        std::fs::write(&path, "{\"1\": ").unwrap();
+
+        let config = Config {
+            param_names: Some(path),
+            ..Default::default()
+        };
+        let result = ParamResolver::load(&config);
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        assert!(matches!(result, Err(error::CacheError::JsonDecode { .. })));
+    }
+}
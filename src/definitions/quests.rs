@@ -0,0 +1,267 @@
+//! Describes the properties of quests and miniquests.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, JString, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use serde::Serialize;
+
+use crate::{
+    definitions::indextype::{ConfigType, IndexType},
+    structures::paramtable::ParamTable,
+};
+
+/// Describes a quest.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Quest {
+    /// Its id.
+    pub id: u32,
+    /// Its name, as shown in the quest journal.
+    pub name: Option<JString<Bytes>>,
+    /// The name it is sorted by in the quest list.
+    pub sort_name: Option<JString<Bytes>>,
+    /// The varps that track progress through the quest.
+    pub varp_progress: Option<Vec<Progress>>,
+    /// The varbits that track progress through the quest.
+    pub varbit_progress: Option<Vec<Progress>>,
+    pub unknown_5: Option<u16>,
+    /// Whether this is a quest, miniquest, saga and so on.
+    pub category: Option<u8>,
+    pub difficulty: Option<u8>,
+    pub is_members: Option<bool>,
+    /// The quest points it rewards.
+    pub quest_points: Option<u8>,
+    /// Where the quest starts, as packed coordinates.
+    pub start_locations: Option<Vec<u32>>,
+    pub unknown_12: Option<i32>,
+    /// The quests that must be completed first.
+    pub required_quests: Option<Vec<u16>>,
+    pub skill_requirements: Option<Vec<SkillRequirement>>,
+    /// How many quest points are needed to start it.
+    pub quest_point_requirement: Option<u16>,
+    pub sprite_id: Option<u32>,
+    pub varp_requirements: Option<Vec<VarRequirement>>,
+    pub varbit_requirements: Option<Vec<VarRequirement>>,
+    #[serde(flatten)]
+    pub params: Option<ParamTable>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+/// A variable that tracks progress through a quest: it is started at `start` and completed at `end`.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub var: u16,
+    pub start: i32,
+    pub end: i32,
+}
+
+/// A skill level needed to start a quest.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SkillRequirement {
+    pub skill: u8,
+    pub level: u8,
+}
+
+/// A requirement on the value of a variable, with how it is described to the player.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[derive(Serialize, Clone, Debug)]
+pub struct VarRequirement {
+    pub var: i32,
+    pub min: i32,
+    pub max: i32,
+    pub description: JString<Bytes>,
+}
+
+impl Quest {
+    /// Returns a mapping of all [`Quest`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::QUESTS)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("quests", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "quests" })
+    }
+
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut quest = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(quest);
+                        }
+                    }
+                    1 => quest.name = Some(buffer.try_get_string()?),
+                    2 => quest.sort_name = Some(buffer.try_get_string()?),
+                    3 => quest.varp_progress = Some(Progress::deserialize_many(&mut buffer)?),
+                    4 => quest.varbit_progress = Some(Progress::deserialize_many(&mut buffer)?),
+                    5 => quest.unknown_5 = Some(buffer.try_get_u16()?),
+                    6 => quest.category = Some(buffer.try_get_u8()?),
+                    7 => quest.difficulty = Some(buffer.try_get_u8()?),
+                    8 => quest.is_members = Some(true),
+                    9 => quest.quest_points = Some(buffer.try_get_u8()?),
+                    10 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        let locations = (0..count).map(|_| buffer.try_get_u32()).collect::<Result<_, ReadError>>()?;
+                        quest.start_locations = Some(locations);
+                    }
+                    12 => quest.unknown_12 = Some(buffer.try_get_i32()?),
+                    13 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        let quests = (0..count).map(|_| buffer.try_get_u16()).collect::<Result<_, ReadError>>()?;
+                        quest.required_quests = Some(quests);
+                    }
+                    14 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        let requirements = (0..count)
+                            .map(|_| try {
+                                SkillRequirement {
+                                    skill: buffer.try_get_u8()?,
+                                    level: buffer.try_get_u8()?,
+                                }
+                            })
+                            .collect::<Result<_, ReadError>>()?;
+                        quest.skill_requirements = Some(requirements);
+                    }
+                    15 => quest.quest_point_requirement = Some(buffer.try_get_u16()?),
+                    17 => quest.sprite_id = buffer.try_get_smart32()?,
+                    18 => quest.varp_requirements = Some(VarRequirement::deserialize_many(&mut buffer)?),
+                    19 => quest.varbit_requirements = Some(VarRequirement::deserialize_many(&mut buffer)?),
+                    249 => quest.params = Some(ParamTable::deserialize(&mut buffer)),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    quest.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &quest,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(quest);
+                }
+            }
+        }
+    }
+}
+
+impl Progress {
+    fn deserialize_many(buffer: &mut Bytes) -> Result<Vec<Self>, ReadError> {
+        let count = buffer.try_get_u8()? as usize;
+        (0..count)
+            .map(|_| try {
+                Self {
+                    var: buffer.try_get_u16()?,
+                    start: buffer.try_get_i32()?,
+                    end: buffer.try_get_i32()?,
+                }
+            })
+            .collect()
+    }
+}
+
+impl VarRequirement {
+    fn deserialize_many(buffer: &mut Bytes) -> Result<Vec<Self>, ReadError> {
+        let count = buffer.try_get_u8()? as usize;
+        (0..count)
+            .map(|_| try {
+                Self {
+                    var: buffer.try_get_i32()?,
+                    min: buffer.try_get_i32()?,
+                    max: buffer.try_get_i32()?,
+                    description: buffer.try_get_string()?,
+                }
+            })
+            .collect()
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for Quest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl Quest {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("Quest({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("Quest({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the quests as `quests.json`. Exposed as `--dump quests`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let quests = Quest::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "quests", &quests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requirements() {
+        let buffer = Bytes::from_static(
+            b"\x01Cook's Assistant\x00\x03\x01\x00\x1d\x00\x00\x00\x00\x00\x00\x00\x02\x08\x09\x01\
+              \x0e\x01\x07\x05\x12\x01\x00\x00\x00\x01\x00\x00\x00\x03\x00\x00\x00\x05Talk to the cook\x00\x00",
+        );
+        let quest = Quest::deserialize(6, buffer, false).unwrap();
+
+        assert_eq!(&**quest.name.as_ref().unwrap(), "Cook's Assistant");
+        assert_eq!(quest.varp_progress, Some(vec![Progress { var: 29, start: 0, end: 2 }]));
+        assert_eq!(quest.is_members, Some(true));
+        assert_eq!(quest.quest_points, Some(1));
+        assert_eq!(quest.skill_requirements, Some(vec![SkillRequirement { skill: 7, level: 5 }]));
+        let requirement = &quest.varp_requirements.as_ref().unwrap()[0];
+        assert_eq!((requirement.var, requirement.min, requirement.max), (1, 3, 5));
+        assert_eq!(&*requirement.description, "Talk to the cook");
+    }
+}
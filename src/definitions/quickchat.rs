@@ -0,0 +1,411 @@
+//! Describes quickchat: the menus of predefined phrases that players without free chat can say.
+//!
+//! Both the quickchat and the global quickchat index have an archive of categories (0) and of phrases (1).
+//! Ids in the global index are offset by `0x8000`, which is also how categories refer to them.
+
+use core::panic::Location;
+use std::collections::{BTreeMap, BTreeSet};
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, JString, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::definitions::{huffman::Huffman, indextype::IndexType};
+
+/// Ids in the global quickchat index are offset by this.
+const GLOBAL: u32 = 0x8000;
+
+/// A menu of phrases and other categories.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct QuickChatCategory {
+    /// Its id.
+    pub id: u32,
+    pub description: Option<JString<Bytes>>,
+    pub subcategories: Option<Vec<Shortcut>>,
+    pub phrases: Option<Vec<Shortcut>>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+/// A category or phrase in a menu, and the key that selects it.
+#[derive(Serialize, Clone, Debug)]
+pub struct Shortcut {
+    pub id: u32,
+    pub key: char,
+}
+
+/// A phrase. Its text is split where dynamic values, such as a skill level, are inserted.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct QuickChatPhrase {
+    /// Its id.
+    pub id: u32,
+    pub text: Option<Vec<String>>,
+    /// The phrases offered as a reply to this one.
+    pub responses: Option<Vec<u32>>,
+    pub dynamics: Option<Vec<Dynamic>>,
+    /// Whether it cannot be found by searching.
+    pub unsearchable: Option<bool>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+/// A value inserted into a phrase.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug)]
+pub struct Dynamic {
+    pub command: u16,
+    pub name: Option<&'static str>,
+    pub arguments: Vec<u16>,
+}
+
+/// The name and number of arguments of each kind of [`Dynamic`].
+fn command(id: u16) -> Option<(&'static str, usize)> {
+    let command = match id {
+        0 => ("list_dialog", 1),
+        1 => ("obj_dialog", 0),
+        2 => ("count_dialog", 0),
+        4 => ("stat_base", 1),
+        6 => ("enum_string", 2),
+        7 => ("enum_string_clan", 1),
+        8 => ("tostring_varp", 1),
+        9 => ("tostring_varbit", 1),
+        10 => ("obj_trade_dialog", 0),
+        11 => ("enum_string_statbase", 2),
+        12 => ("acc_getcount_world", 0),
+        13 => ("acc_getmeancombatlevel", 0),
+        14 => ("tostring_shared", 1),
+        15 => ("active_combat_class", 0),
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// Returns the files of `archive` in both quickchat indices, with their ids.
+fn files(config: &crate::cli::Config, archive: u32) -> CacheResult<Vec<(u32, Bytes)>> {
+    let mut files = Vec::new();
+    for (index_id, offset) in [(IndexType::QUICKCHAT, 0), (IndexType::GLOBAL_QUICKCHAT, GLOBAL)] {
+        let archive = CacheIndex::new(index_id, config.input.clone())?.archive(archive)?;
+        files.extend(archive.take_files().into_iter().map(|(id, file)| (id + offset, file)));
+    }
+    Ok(files)
+}
+
+impl QuickChatCategory {
+    /// Returns a mapping of all [`QuickChatCategory`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        files(config, 0)?
+            .into_iter()
+            .map(|(id, file)| try { (id, Self::deserialize(id, file, config.recover)?) })
+            .filter_map(|result| config.capture("quickchat categories", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read {
+                what: "quickchat categories",
+            })
+    }
+
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut category = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(category);
+                        }
+                    }
+                    1 => category.description = Some(buffer.try_get_string()?),
+                    2 => category.subcategories = Some(Self::shortcuts(&mut buffer)?),
+                    3 => category.phrases = Some(Self::shortcuts(&mut buffer)?),
+                    // Marks the category as global, which its id already says.
+                    4 => {}
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    category.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &category,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(category);
+                }
+            }
+        }
+    }
+
+    fn shortcuts(buffer: &mut Bytes) -> Result<Vec<Shortcut>, ReadError> {
+        let count = buffer.try_get_u8()?;
+        (0..count)
+            .map(|_| try {
+                Shortcut {
+                    id: buffer.try_get_u16()? as u32,
+                    key: buffer.try_get_u8()? as char,
+                }
+            })
+            .collect()
+    }
+}
+
+impl QuickChatPhrase {
+    /// Returns a mapping of all [`QuickChatPhrase`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        files(config, 1)?
+            .into_iter()
+            .map(|(id, file)| try { (id, Self::deserialize(id, file, config.recover)?) })
+            .filter_map(|result| config.capture("quickchat phrases", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "quickchat phrases" })
+    }
+
+    fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut phrase = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(phrase);
+                        }
+                    }
+                    1 => phrase.text = Some(buffer.try_get_string()?.split('<').map(str::to_string).collect()),
+                    2 => {
+                        let count = buffer.try_get_u8()?;
+                        let responses = (0..count).map(|_| buffer.try_get_u16().map(u32::from)).collect::<Result<_, _>>()?;
+                        phrase.responses = Some(responses);
+                    }
+                    3 => {
+                        let count = buffer.try_get_u8()?;
+                        let mut dynamics = Vec::with_capacity(count as usize);
+                        for _ in 0..count {
+                            let command_id = buffer.try_get_u16()?;
+                            let (name, arguments) = command(command_id).unzip();
+                            let arguments = (0..arguments.unwrap_or(0)).map(|_| buffer.try_get_u16()).collect::<Result<_, _>>()?;
+                            dynamics.push(Dynamic {
+                                command: command_id,
+                                name,
+                                arguments,
+                            });
+                        }
+                        phrase.dynamics = Some(dynamics);
+                    }
+                    4 => phrase.unsearchable = Some(true),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    phrase.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &phrase,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(phrase);
+                }
+            }
+        }
+    }
+
+    /// The text of the phrase, with its dynamic values written as `<name>`.
+    pub fn template(&self) -> Option<String> {
+        let text = self.text.as_ref()?;
+        let mut dynamics = self.dynamics.iter().flatten();
+
+        let mut out = String::new();
+        for (i, piece) in text.iter().enumerate() {
+            if i > 0 {
+                match dynamics.next() {
+                    Some(Dynamic { name: Some(name), .. }) => out.push_str(&format!("<{name}>")),
+                    Some(Dynamic { command, .. }) => out.push_str(&format!("<{command}>")),
+                    None => out.push_str("<>"),
+                }
+            }
+            out.push_str(piece);
+        }
+        Some(out)
+    }
+
+    /// The [`template`](QuickChatPhrase::template) compressed as a chat message, in hex,
+    /// or `None` if it has no text or contains a character that the code cannot encode.
+    pub fn message(&self, huffman: &Huffman) -> Option<String> {
+        let message = huffman.write_message(&self.template()?)?;
+        Some(message.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for QuickChatCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+impl Display for QuickChatPhrase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+/// Nests the phrases and subcategories of `id` under it.
+fn tree(
+    id: u32,
+    key: Option<char>,
+    categories: &BTreeMap<u32, QuickChatCategory>,
+    phrases: &BTreeMap<u32, QuickChatPhrase>,
+    huffman: &Huffman,
+    seen: &mut BTreeSet<u32>,
+) -> Value {
+    let Some(category) = categories.get(&id) else { return json!({ "id": id, "key": key }) };
+    // Categories can be reachable from several others, but should not contain themselves.
+    if !seen.insert(id) {
+        return json!({ "id": id, "key": key, "description": category.description });
+    }
+
+    let subcategories = category
+        .subcategories
+        .iter()
+        .flatten()
+        .map(|shortcut| tree(shortcut.id, Some(shortcut.key), categories, phrases, huffman, seen))
+        .collect::<Vec<_>>();
+    let entries = category
+        .phrases
+        .iter()
+        .flatten()
+        .map(|shortcut| {
+            let phrase = phrases.get(&shortcut.id);
+            json!({
+                "id": shortcut.id,
+                "key": shortcut.key,
+                "text": phrase.and_then(QuickChatPhrase::template),
+                "message": phrase.and_then(|phrase| phrase.message(huffman)),
+                "responses": phrase.and_then(|phrase| phrase.responses.as_ref()),
+            })
+        })
+        .collect::<Vec<_>>();
+    seen.remove(&id);
+
+    json!({
+        "id": id,
+        "key": key,
+        "description": category.description,
+        "subcategories": subcategories,
+        "phrases": entries,
+    })
+}
+
+/// Saves the quickchat menus as `quickchat.json`, starting with the categories that are not in any other.
+/// Each phrase also has the huffman-compressed `message` it is sent as, so that captured messages can be matched to it.
+/// Exposed as `--dump quickchat`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let categories = QuickChatCategory::dump_all(config)?;
+    let phrases = QuickChatPhrase::dump_all(config)?;
+    let huffman = Huffman::load(config)?;
+
+    let nested = categories
+        .values()
+        .flat_map(|category| category.subcategories.iter().flatten().map(|shortcut| shortcut.id))
+        .collect::<BTreeSet<_>>();
+    let roots = categories
+        .keys()
+        .filter(|id| !nested.contains(id))
+        .map(|&id| tree(id, None, &categories, &phrases, &huffman, &mut BTreeSet::new()))
+        .collect::<Vec<_>>();
+
+    crate::output::export(config, "quickchat", &roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phrase() {
+        #[rustfmt::skip]
+        let buffer = Bytes::from_static(&[
+            1, b'M', b'y', b' ', b'l', b'e', b'v', b'e', b'l', b' ', b'i', b's', b' ', b'<', b'.', 0,
+            3, 1, 0, 4, 0, 6,
+            0,
+        ]);
+        let phrase = QuickChatPhrase::deserialize(GLOBAL + 1, buffer, false).unwrap();
+
+        assert_eq!(phrase.text.as_ref().unwrap().len(), 2);
+        assert_eq!(phrase.dynamics.as_ref().unwrap()[0].arguments, vec![6]);
+        assert_eq!(phrase.template().unwrap(), "My level is <stat_base>.");
+    }
+
+    #[test]
+    fn message() {
+        // Every ascii character has a code of seven bits.
+        let huffman = Huffman::new(vec![7; 128]).unwrap();
+        let phrase = QuickChatPhrase {
+            text: Some(vec!["Hi".to_string()]),
+            ..Default::default()
+        };
+        let message = phrase.message(&huffman).unwrap();
+        assert_eq!(message.len(), 2 * 3);
+
+        let bytes = (0..message.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&message[i..i + 2], 16).unwrap())
+            .collect::<Vec<u8>>();
+        assert_eq!(huffman.read_message(&mut Bytes::from(bytes)).unwrap(), "Hi");
+
+        let unencodable = QuickChatPhrase {
+            text: Some(vec!["é".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(unencodable.message(&huffman), None);
+    }
+}
@@ -0,0 +1,117 @@
+//! Describes which revision of the game a cache belongs to.
+//!
+//! The cache does not contain the client's build number, but every index records a version
+//! in its reference table, and every archive records the version it was built at.
+//! For most caches the latter is a timestamp, which is usually enough to label a dump with.
+
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    error::CacheResult,
+    index::{self, CacheIndex},
+};
+use serde::Serialize;
+
+/// The revision of a single index.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct IndexRevision {
+    /// Its id.
+    pub index_id: u32,
+    /// The format of its reference table.
+    pub format: Option<i8>,
+    /// The version of its reference table.
+    pub version: Option<i32>,
+    /// The amount of archives in it.
+    pub archive_count: usize,
+    /// The highest version of any of its archives.
+    pub latest_archive_version: Option<i32>,
+}
+
+/// Revision information of a cache.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct CacheRevision {
+    /// The highest version of any archive in the cache.
+    pub latest_archive_version: Option<i32>,
+    pub indices: Vec<IndexRevision>,
+}
+
+impl CacheRevision {
+    /// Reads the revision of every index in the cache. Indices that cannot be opened are skipped.
+    pub fn dump(config: &crate::cli::Config) -> CacheResult<Self> {
+        let indices = index::index_ids(&config.input)?
+            .into_iter()
+            .filter_map(|index_id| CacheIndex::new(index_id, config.input.clone()).ok())
+            .map(|index| {
+                let metadatas = index.metadatas();
+                IndexRevision {
+                    index_id: index.index_id(),
+                    format: metadatas.format(),
+                    version: metadatas.version(),
+                    archive_count: metadatas.keys().len(),
+                    latest_archive_version: metadatas.latest_archive_version(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let latest_archive_version = indices.iter().filter_map(|index| index.latest_archive_version).max();
+
+        Ok(Self {
+            latest_archive_version,
+            indices,
+        })
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl IndexRevision {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("IndexRevision({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("IndexRevision({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl CacheRevision {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("CacheRevision({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("CacheRevision({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the revision of every index in the cache as `revision`, in the configured format. Exposed as `--dump revision`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let revision = CacheRevision::dump(config)?;
+    crate::output::export(config, "revision", &revision.indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_from_cache() -> CacheResult<()> {
+        let config = crate::cli::Config::default();
+        let ids = index::index_ids(&config.input)?;
+        let revision = CacheRevision::dump(&config)?;
+
+        assert!(!revision.indices.is_empty());
+        assert!(revision.indices.iter().all(|index| ids.contains(&index.index_id)));
+        assert_eq!(
+            revision.latest_archive_version,
+            revision.indices.iter().filter_map(|index| index.latest_archive_version).max()
+        );
+        Ok(())
+    }
+}
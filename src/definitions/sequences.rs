@@ -0,0 +1,280 @@
+//! Animation definitions.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[cfg(feature = "osrs")]
+use crate::definitions::indextype::ConfigType;
+use crate::definitions::indextype::IndexType;
+#[cfg(feature = "rs3")]
+use crate::structures::paramtable::ParamTable;
+
+/// Describes an animation: the frames it is made of and how it interacts with other animations.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct Sequence {
+    /// Its id.
+    pub id: u32,
+    /// How long each frame is shown, in client ticks.
+    pub frame_lengths: Option<Vec<u16>>,
+    /// The frames of this animation.
+    pub frame_ids: Option<Vec<u32>>,
+    /// The frame to restart at when looping, counted from the end.
+    pub frame_step: Option<u16>,
+    /// Which skeleton groups this animation takes over when played together with another.
+    pub interleave_order: Option<Vec<u8>>,
+    pub stretches: Option<bool>,
+    pub forced_priority: Option<u8>,
+    /// Overrides the item held in the left hand (usually a shield) while playing.
+    pub left_hand_item: Option<u16>,
+    /// Overrides the item held in the right hand (usually a weapon) while playing.
+    pub right_hand_item: Option<u16>,
+    pub max_loops: Option<u8>,
+    pub precedence_animating: Option<u8>,
+    pub priority: Option<u8>,
+    pub reply_mode: Option<u8>,
+    /// Frames used when this animation is shown on a chathead.
+    pub chat_frame_ids: Option<Vec<u32>>,
+    /// Sound effects for each frame; the first element is the sound id.
+    #[cfg(feature = "rs3")]
+    pub sound_effects: Option<Vec<Option<Vec<u32>>>>,
+    /// Sound effects for each frame.
+    #[cfg(feature = "osrs")]
+    pub sound_effects: Option<Vec<u32>>,
+    #[cfg(feature = "rs3")]
+    pub tweened: Option<bool>,
+    #[cfg(feature = "osrs")]
+    pub skeletal_id: Option<i32>,
+    /// Sound effects keyed by the frame they are played at.
+    #[cfg(feature = "osrs")]
+    pub skeletal_sounds: Option<BTreeMap<u16, u32>>,
+    #[cfg(feature = "osrs")]
+    pub skeletal_range: Option<(u16, u16)>,
+    #[cfg(feature = "osrs")]
+    pub skeletal_masks: Option<Vec<u8>>,
+    #[cfg(feature = "rs3")]
+    #[serde(flatten)]
+    pub params: Option<ParamTable>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+impl Sequence {
+    /// Returns a mapping of all [`Sequence`]s.
+    #[cfg(feature = "rs3")]
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let archives = CacheIndex::new(IndexType::SEQ_CONFIG, config.input.clone())?.into_iter();
+
+        archives
+            .map(Result::unwrap)
+            .flat_map(|archive| {
+                let archive_id = archive.archive_id();
+                archive
+                    .take_files()
+                    .into_iter()
+                    .map(move |(file_id, file)| (archive_id << 7 | file_id, file))
+            })
+            .map(|(id, file)| try { (id, Self::deserialize(id, file, config.recover)?) })
+            .filter_map(|result| config.capture("sequences", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "sequences" })
+    }
+
+    /// Returns a mapping of all [`Sequence`]s.
+    #[cfg(feature = "osrs")]
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::SEQUENCE)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("sequences", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "sequences" })
+    }
+
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut seq = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(seq);
+                        }
+                    }
+                    1 => {
+                        let count = buffer.try_get_u16()? as usize;
+                        let frame_lengths = (0..count).map(|_| buffer.try_get_u16()).collect::<Result<Vec<_>, _>>()?;
+                        let mut frame_ids = (0..count).map(|_| buffer.try_get_u16().map(u32::from)).collect::<Result<Vec<_>, _>>()?;
+                        for frame_id in &mut frame_ids {
+                            *frame_id |= (buffer.try_get_u16()? as u32) << 16;
+                        }
+                        seq.frame_lengths = Some(frame_lengths);
+                        seq.frame_ids = Some(frame_ids);
+                    }
+                    2 => seq.frame_step = Some(buffer.try_get_u16()?),
+                    3 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        seq.interleave_order = Some((0..count).map(|_| buffer.try_get_u8()).collect::<Result<Vec<_>, _>>()?);
+                    }
+                    4 => seq.stretches = Some(true),
+                    5 => seq.forced_priority = Some(buffer.try_get_u8()?),
+                    6 => seq.left_hand_item = Some(buffer.try_get_u16()?),
+                    7 => seq.right_hand_item = Some(buffer.try_get_u16()?),
+                    8 => seq.max_loops = Some(buffer.try_get_u8()?),
+                    9 => seq.precedence_animating = Some(buffer.try_get_u8()?),
+                    10 => seq.priority = Some(buffer.try_get_u8()?),
+                    11 => seq.reply_mode = Some(buffer.try_get_u8()?),
+                    12 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        let mut frame_ids = (0..count).map(|_| buffer.try_get_u16().map(u32::from)).collect::<Result<Vec<_>, _>>()?;
+                        for frame_id in &mut frame_ids {
+                            *frame_id |= (buffer.try_get_u16()? as u32) << 16;
+                        }
+                        seq.chat_frame_ids = Some(frame_ids);
+                    }
+                    #[cfg(feature = "rs3")]
+                    13 => {
+                        let count = buffer.try_get_u16()? as usize;
+                        let mut sound_effects = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            let len = buffer.try_get_u8()? as usize;
+                            if len == 0 {
+                                sound_effects.push(None);
+                            } else {
+                                let mut sound = vec![buffer.try_get_uint(3)? as u32];
+                                for _ in 1..len {
+                                    sound.push(buffer.try_get_u16()? as u32);
+                                }
+                                sound_effects.push(Some(sound));
+                            }
+                        }
+                        seq.sound_effects = Some(sound_effects);
+                    }
+                    #[cfg(feature = "osrs")]
+                    13 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        seq.sound_effects = Some(
+                            (0..count)
+                                .map(|_| buffer.try_get_uint(3).map(|s| s as u32))
+                                .collect::<Result<Vec<_>, _>>()?,
+                        );
+                    }
+                    #[cfg(feature = "rs3")]
+                    14 => seq.tweened = Some(true),
+                    #[cfg(feature = "osrs")]
+                    14 => seq.skeletal_id = Some(buffer.try_get_i32()?),
+                    #[cfg(feature = "osrs")]
+                    15 => {
+                        let count = buffer.try_get_u16()? as usize;
+                        let mut sounds = BTreeMap::new();
+                        for _ in 0..count {
+                            let frame = buffer.try_get_u16()?;
+                            let sound = buffer.try_get_uint(3)? as u32;
+                            sounds.insert(frame, sound);
+                        }
+                        seq.skeletal_sounds = Some(sounds);
+                    }
+                    #[cfg(feature = "osrs")]
+                    16 => seq.skeletal_range = Some((buffer.try_get_u16()?, buffer.try_get_u16()?)),
+                    #[cfg(feature = "osrs")]
+                    17 => {
+                        let count = buffer.try_get_u8()? as usize;
+                        seq.skeletal_masks = Some((0..count).map(|_| buffer.try_get_u8()).collect::<Result<Vec<_>, _>>()?);
+                    }
+                    #[cfg(feature = "rs3")]
+                    249 => seq.params = Some(ParamTable::deserialize(&mut buffer)),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    seq.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &seq,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(seq);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for Sequence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl Sequence {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("Sequence({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("Sequence({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the animations as `sequences.json`. Exposed as `--dump sequences`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let sequences = Sequence::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "sequences", &sequences)
+}
+
+#[cfg(all(test, feature = "rs3"))]
+mod tests {
+    use super::*;
+    use crate::cli::Config;
+
+    #[test]
+    fn dump_sequences() -> CacheResult<()> {
+        let config = Config::env();
+        let sequences = Sequence::dump_all(&config)?;
+        assert!(sequences.values().any(|seq| seq.frame_ids.is_some()));
+        Ok(())
+    }
+}
@@ -0,0 +1,664 @@
+//! Sound effects, which are synthesized by the client rather than stored as samples.
+//!
+//! A sound effect mixes up to ten instruments. Each instrument sums several oscillators,
+//! whose pitch and volume follow envelopes, and may be gated, echoed and filtered.
+//! The result is 8-bit mono audio at 22050 Hz.
+
+use std::{collections::BTreeMap, f64::consts::PI, sync::LazyLock};
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+use rs3cache_backend::{
+    buf::{BufExtra, Eof, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+};
+use serde::Serialize;
+
+use crate::definitions::indextype::IndexType;
+
+/// The sample rate of synthesized sound effects.
+pub const SAMPLE_RATE: u32 = 22050;
+
+/// One period of a sine wave, scaled to 14 bits.
+static SINE: LazyLock<Vec<i32>> = LazyLock::new(|| (0..32768).map(|i| ((i as f64 / 5215.1903).sin() * 16384.0) as i32).collect());
+
+/// White noise. This is the same noise as the client's, which seeds `java.util.Random` with 0.
+static NOISE: LazyLock<Vec<i32>> = LazyLock::new(|| {
+    let mut seed: u64 = 0x5DEECE66D;
+    (0..32768)
+        .map(|_| {
+            seed = (seed.wrapping_mul(0x5DEECE66D).wrapping_add(0xB)) & ((1 << 48) - 1);
+            let next = (seed >> 16) as i32;
+            (next & 2) - 1
+        })
+        .collect()
+});
+
+/// A sound effect.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct SoundEffect {
+    /// Its id.
+    pub id: u32,
+    pub instruments: Vec<Instrument>,
+    /// Where the sound loops, in milliseconds. Looping is not rendered.
+    pub loop_start: u16,
+    pub loop_end: u16,
+}
+
+/// A value that changes over time, by interpolating between its segments.
+#[derive(Serialize, Clone, Debug)]
+pub struct Envelope {
+    /// The waveform of the oscillators this drives: none (0), square (1), sine (2), saw (3) or noise (4).
+    pub form: u8,
+    pub start: i32,
+    pub end: i32,
+    /// When each segment ends, as a fraction of 65536 of the length.
+    pub durations: Vec<u16>,
+    /// The value at the end of each segment, as a fraction of 65536.
+    pub phases: Vec<u16>,
+}
+
+/// An oscillator of an [`Instrument`].
+#[derive(Serialize, Clone, Debug)]
+pub struct Oscillator {
+    /// The volume, in percent.
+    pub volume: u16,
+    /// The pitch, in tenths of a semitone.
+    pub pitch: i32,
+    /// How long it waits before it starts, in milliseconds.
+    pub delay: u16,
+}
+
+/// A two sided IIR filter, whose poles and zeroes move between two positions.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Filter {
+    /// The number of zeroes (0) and poles (1).
+    pub pairs: [usize; 2],
+    /// The gain at the start and end.
+    pub unity: [u16; 2],
+    /// The frequency of each pair, at the start and at the end.
+    pub phases: [[Vec<u16>; 2]; 2],
+    /// The magnitude of each pair, at the start and at the end.
+    pub magnitudes: [[Vec<u16>; 2]; 2],
+    /// How the filter moves from start to end.
+    pub envelope: Option<Envelope>,
+}
+
+/// A synthesized instrument.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug)]
+pub struct Instrument {
+    pub pitch: Envelope,
+    pub volume: Envelope,
+    /// Vibrato.
+    pub pitch_modifier: Option<(Envelope, Envelope)>,
+    /// Tremolo.
+    pub volume_modifier: Option<(Envelope, Envelope)>,
+    /// Switches the sound off and on.
+    pub gate: Option<(Envelope, Envelope)>,
+    pub oscillators: Vec<Oscillator>,
+    /// The delay of the echo, in milliseconds.
+    pub delay_time: u16,
+    /// The volume of the echo, in percent.
+    pub delay_decay: u16,
+    /// How long it plays, in milliseconds.
+    pub duration: u16,
+    /// When it starts playing, in milliseconds.
+    pub offset: u16,
+    pub filter: Filter,
+}
+
+/// Reads a smart that is signed.
+fn try_get_signed_smart(buffer: &mut Bytes) -> Result<i32, ReadError> {
+    match buffer.first() {
+        Some(&byte) if byte < 128 => Ok(buffer.try_get_u8()? as i32 - 0x40),
+        _ => Ok(buffer.try_get_u16()? as i32 - 0xC000),
+    }
+}
+
+/// Whether an optional part follows, which is the case if the next byte is not zero.
+fn has_next(buffer: &mut Bytes) -> Result<bool, ReadError> {
+    match buffer.first() {
+        Some(0) => {
+            buffer.advance(1);
+            Ok(false)
+        }
+        Some(_) => Ok(true),
+        None => Err(Eof::new(1, 0)),
+    }
+}
+
+impl Envelope {
+    fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        let form = buffer.try_get_u8()?;
+        let start = buffer.try_get_i32()?;
+        let end = buffer.try_get_i32()?;
+        let mut envelope = Self {
+            form,
+            start,
+            end,
+            durations: Vec::new(),
+            phases: Vec::new(),
+        };
+        envelope.deserialize_segments(buffer)?;
+        Ok(envelope)
+    }
+
+    fn deserialize_segments(&mut self, buffer: &mut Bytes) -> Result<(), ReadError> {
+        let count = buffer.try_get_u8()?;
+        self.durations.clear();
+        self.phases.clear();
+        for _ in 0..count {
+            self.durations.push(buffer.try_get_u16()?);
+            self.phases.push(buffer.try_get_u16()?);
+        }
+        Ok(())
+    }
+
+    fn pair(buffer: &mut Bytes) -> Result<Option<(Self, Self)>, ReadError> {
+        Ok(if has_next(buffer)? {
+            Some((Self::deserialize(buffer)?, Self::deserialize(buffer)?))
+        } else {
+            None
+        })
+    }
+
+    fn stepper(&self, period: usize) -> Stepper<'_> {
+        Stepper {
+            envelope: self,
+            period,
+            threshold: 0,
+            segment: 0,
+            step: 0,
+            amplitude: 0,
+            ticks: 0,
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            form: 0,
+            start: 0,
+            end: 0,
+            durations: vec![0, 65535],
+            phases: vec![0, 65535],
+        }
+    }
+}
+
+/// Walks through an [`Envelope`], one sample at a time.
+struct Stepper<'a> {
+    envelope: &'a Envelope,
+    period: usize,
+    threshold: i32,
+    segment: usize,
+    step: i32,
+    amplitude: i32,
+    ticks: i32,
+}
+
+impl Stepper<'_> {
+    fn next(&mut self) -> i32 {
+        let segments = self.envelope.phases.len();
+        if self.ticks >= self.threshold && segments > 0 {
+            self.amplitude = (self.envelope.phases[self.segment] as i32) << 15;
+            self.segment = (self.segment + 1).min(segments - 1);
+            self.threshold = (self.envelope.durations[self.segment] as f64 / 65536.0 * self.period as f64) as i32;
+            if self.threshold > self.ticks {
+                self.step = (((self.envelope.phases[self.segment] as i32) << 15) - self.amplitude) / (self.threshold - self.ticks);
+            }
+        }
+        self.amplitude = self.amplitude.wrapping_add(self.step);
+        self.ticks += 1;
+        self.amplitude.wrapping_sub(self.step) >> 15
+    }
+}
+
+/// Evaluates a waveform at `phase`, where 32768 is one period.
+fn wave(phase: i32, amplitude: i32, form: u8) -> i32 {
+    match form {
+        1 if phase & 0x7FFF < 0x4000 => amplitude,
+        1 => -amplitude,
+        2 => SINE[(phase & 0x7FFF) as usize].wrapping_mul(amplitude) >> 14,
+        3 => (amplitude.wrapping_mul(phase & 0x7FFF) >> 14) - amplitude,
+        4 => amplitude.wrapping_mul(NOISE[((phase / 2607) & 0x7FFF) as usize]),
+        _ => 0,
+    }
+}
+
+impl Filter {
+    fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        let mut filter = Self::default();
+        let pairs = buffer.try_get_u8()?;
+        filter.pairs = [(pairs >> 4) as usize, (pairs & 0xF) as usize];
+        if pairs == 0 {
+            return Ok(filter);
+        }
+
+        filter.unity = [buffer.try_get_u16()?, buffer.try_get_u16()?];
+        let moving = buffer.try_get_u8()?;
+        for side in 0..2 {
+            for _ in 0..filter.pairs[side] {
+                filter.phases[side][0].push(buffer.try_get_u16()?);
+                filter.magnitudes[side][0].push(buffer.try_get_u16()?);
+            }
+        }
+        for side in 0..2 {
+            for pair in 0..filter.pairs[side] {
+                if moving as u32 & (1 << (side * 4) << pair) != 0 {
+                    filter.phases[side][1].push(buffer.try_get_u16()?);
+                    filter.magnitudes[side][1].push(buffer.try_get_u16()?);
+                } else {
+                    filter.phases[side][1].push(filter.phases[side][0][pair]);
+                    filter.magnitudes[side][1].push(filter.magnitudes[side][0][pair]);
+                }
+            }
+        }
+        if moving != 0 || filter.unity[1] != filter.unity[0] {
+            let mut envelope = Envelope::default();
+            envelope.deserialize_segments(buffer)?;
+            filter.envelope = Some(envelope);
+        }
+        Ok(filter)
+    }
+
+    fn magnitude(&self, side: usize, pair: usize, t: f32) -> f32 {
+        let [from, to] = &self.magnitudes[side];
+        let magnitude = (from[pair] as f32 + t * (to[pair] as f32 - from[pair] as f32)) * 0.0015258789;
+        1.0 - 10.0_f64.powf((-magnitude / 20.0) as f64) as f32
+    }
+
+    fn frequency(&self, side: usize, pair: usize, t: f32) -> f32 {
+        let [from, to] = &self.phases[side];
+        let octaves = (from[pair] as f32 + t * (to[pair] as f32 - from[pair] as f32)) * 1.2207031e-4;
+        let hertz = 32.703197 * 2.0_f64.powf(octaves as f64) as f32;
+        hertz * PI as f32 / 11025.0
+    }
+
+    /// Computes the coefficients of one side at `t`, returning how many there are.
+    fn compute(&self, side: usize, t: f32, state: &mut FilterState) -> usize {
+        let mut minimised = [0.0_f32; 32];
+        if side == 0 {
+            let unity = (self.unity[0] as f32 + (self.unity[1] as f32 - self.unity[0] as f32) * t) * 0.0030517578;
+            state.forward = 0.1_f64.powf((unity / 20.0) as f64) as f32;
+            state.forward_multiplier = (state.forward * 65536.0) as i32;
+        }
+        let pairs = self.pairs[side];
+        if pairs == 0 {
+            return 0;
+        }
+
+        let magnitude = self.magnitude(side, 0, t);
+        minimised[0] = -2.0 * magnitude * (self.frequency(side, 0, t) as f64).cos() as f32;
+        minimised[1] = magnitude * magnitude;
+        for pair in 1..pairs {
+            let magnitude = self.magnitude(side, pair, t);
+            let a = -2.0 * magnitude * (self.frequency(side, pair, t) as f64).cos() as f32;
+            let b = magnitude * magnitude;
+            minimised[pair * 2 + 1] = minimised[pair * 2 - 1] * b;
+            minimised[pair * 2] = minimised[pair * 2 - 1] * a + minimised[pair * 2 - 2] * b;
+            for i in (2..pair * 2).rev() {
+                minimised[i] += minimised[i - 1] * a + minimised[i - 2] * b;
+            }
+            minimised[1] += minimised[0] * a + b;
+            minimised[0] += a;
+        }
+        if side == 0 {
+            for coefficient in &mut minimised[..pairs * 2] {
+                *coefficient *= state.forward;
+            }
+        }
+        for (coefficient, minimised) in state.coefficients[side].iter_mut().zip(&minimised[..pairs * 2]) {
+            *coefficient = (minimised * 65536.0) as i32;
+        }
+        pairs * 2
+    }
+}
+
+/// The coefficients a [`Filter`] is at.
+#[derive(Default)]
+struct FilterState {
+    forward: f32,
+    forward_multiplier: i32,
+    coefficients: [[i32; 32]; 2],
+}
+
+impl Instrument {
+    fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        let pitch = Envelope::deserialize(buffer)?;
+        let volume = Envelope::deserialize(buffer)?;
+        let pitch_modifier = Envelope::pair(buffer)?;
+        let volume_modifier = Envelope::pair(buffer)?;
+        let gate = Envelope::pair(buffer)?;
+
+        let mut oscillators = Vec::new();
+        for _ in 0..10 {
+            let volume = buffer.try_get_unsigned_smart()?;
+            if volume == 0 {
+                break;
+            }
+            oscillators.push(Oscillator {
+                volume,
+                pitch: try_get_signed_smart(buffer)?,
+                delay: buffer.try_get_unsigned_smart()?,
+            });
+        }
+
+        Ok(Self {
+            pitch,
+            volume,
+            pitch_modifier,
+            volume_modifier,
+            gate,
+            oscillators,
+            delay_time: buffer.try_get_unsigned_smart()?,
+            delay_decay: buffer.try_get_unsigned_smart()?,
+            duration: buffer.try_get_u16()?,
+            offset: buffer.try_get_u16()?,
+            filter: Filter::deserialize(buffer)?,
+        })
+    }
+
+    /// Synthesizes `len` samples of 16 bits.
+    pub fn synthesize(&self, len: usize) -> Vec<i32> {
+        let mut samples = vec![0_i32; len];
+        if self.duration < 10 {
+            return samples;
+        }
+        let samples_per_ms = len as f64 / self.duration as f64;
+
+        let mut pitch = self.pitch.stepper(len);
+        let mut volume = self.volume.stepper(len);
+
+        let mut pitch_modifier = self.pitch_modifier.as_ref().map(|(rate, amplitude)| {
+            let step = ((rate.end - rate.start) as f64 * 32.768 / samples_per_ms) as i32;
+            let base = (rate.start as f64 * 32.768 / samples_per_ms) as i32;
+            (rate.stepper(len), amplitude.stepper(len), rate.form, step, base, 0_i32)
+        });
+        let mut volume_modifier = self.volume_modifier.as_ref().map(|(rate, amplitude)| {
+            let step = ((rate.end - rate.start) as f64 * 32.768 / samples_per_ms) as i32;
+            let base = (rate.start as f64 * 32.768 / samples_per_ms) as i32;
+            (rate.stepper(len), amplitude.stepper(len), rate.form, step, base, 0_i32)
+        });
+
+        let oscillators = self
+            .oscillators
+            .iter()
+            .map(|oscillator| {
+                let delay = (oscillator.delay as f64 * samples_per_ms) as usize;
+                let volume = ((oscillator.volume as i32) << 14) / 100;
+                let pitch_step =
+                    ((self.pitch.end - self.pitch.start) as f64 * 32.768 * 1.0057929410678534_f64.powi(oscillator.pitch) / samples_per_ms) as i32;
+                let pitch_base = (self.pitch.start as f64 * 32.768 / samples_per_ms) as i32;
+                (delay, volume, pitch_step, pitch_base)
+            })
+            .collect::<Vec<_>>();
+        let mut phases = vec![0_i32; oscillators.len()];
+
+        for i in 0..len {
+            let mut pitch_value = pitch.next();
+            let mut volume_value = volume.next();
+
+            if let Some((rate, amplitude, form, step, base, phase)) = &mut pitch_modifier {
+                let rate = rate.next();
+                let amplitude = amplitude.next();
+                pitch_value = pitch_value.wrapping_add(wave(*phase, amplitude, *form) >> 1);
+                *phase = phase.wrapping_add(*base).wrapping_add(rate.wrapping_mul(*step) >> 16);
+            }
+            if let Some((rate, amplitude, form, step, base, phase)) = &mut volume_modifier {
+                let rate = rate.next();
+                let amplitude = amplitude.next();
+                volume_value = volume_value.wrapping_mul((wave(*phase, amplitude, *form) >> 1) + 32768) >> 15;
+                *phase = phase.wrapping_add(*base).wrapping_add(rate.wrapping_mul(*step) >> 16);
+            }
+
+            for ((delay, volume, pitch_step, pitch_base), phase) in oscillators.iter().zip(&mut phases) {
+                let at = delay + i;
+                if at < len {
+                    samples[at] = samples[at].wrapping_add(wave(*phase, volume_value.wrapping_mul(*volume) >> 15, self.pitch.form));
+                    *phase = phase.wrapping_add((pitch_value.wrapping_mul(*pitch_step) >> 16).wrapping_add(*pitch_base));
+                }
+            }
+        }
+
+        if let Some((on, off)) = &self.gate {
+            let mut on_steps = on.stepper(len);
+            let mut off_steps = off.stepper(len);
+            let mut counter = 0;
+            let mut open = true;
+            for sample in &mut samples {
+                let on_value = on_steps.next();
+                let off_value = off_steps.next();
+                let threshold = if open {
+                    ((on_value * (on.end - on.start)) >> 8) + on.start
+                } else {
+                    ((off_value * (on.end - on.start)) >> 8) + on.start
+                };
+                counter += 256;
+                if counter >= threshold {
+                    counter = 0;
+                    open = !open;
+                }
+                if open {
+                    *sample = 0;
+                }
+            }
+        }
+
+        if self.delay_time > 0 && self.delay_decay > 0 {
+            let delay = (self.delay_time as f64 * samples_per_ms) as usize;
+            for i in delay..len {
+                samples[i] = samples[i].wrapping_add(samples[i - delay].wrapping_mul(self.delay_decay as i32) / 100);
+            }
+        }
+
+        if self.filter.pairs != [0, 0] {
+            self.apply_filter(&mut samples);
+        }
+
+        for sample in &mut samples {
+            *sample = (*sample).clamp(-32768, 32767);
+        }
+        samples
+    }
+
+    fn apply_filter(&self, samples: &mut [i32]) {
+        let len = samples.len();
+        let default = Envelope::default();
+        let mut envelope = self.filter.envelope.as_ref().unwrap_or(&default).stepper(len + 1);
+        let mut state = FilterState::default();
+
+        let mut t = envelope.next();
+        let mut forward = self.filter.compute(0, t as f32 / 65536.0, &mut state);
+        let mut backward = self.filter.compute(1, t as f32 / 65536.0, &mut state);
+        if len < forward + backward {
+            return;
+        }
+
+        let filtered = |samples: &[i32], i: usize, forward: usize, backward: usize, state: &FilterState| {
+            let mut value = if i + forward < len {
+                ((samples[i + forward] as i64 * state.forward_multiplier as i64) >> 16) as i32
+            } else {
+                0
+            };
+            for k in (i + forward).saturating_sub(len)..forward {
+                value = value.wrapping_add(((samples[i + forward - 1 - k] as i64 * state.coefficients[0][k] as i64) >> 16) as i32);
+            }
+            for k in 0..backward.min(i) {
+                value = value.wrapping_sub(((samples[i - 1 - k] as i64 * state.coefficients[1][k] as i64) >> 16) as i32);
+            }
+            value
+        };
+
+        // The coefficients move every 128 samples.
+        let mut i = 0;
+        let mut until = 128.min(len - forward);
+        loop {
+            while i < until {
+                samples[i] = filtered(samples, i, forward, backward, &state);
+                t = envelope.next();
+                i += 1;
+            }
+            if i >= len.saturating_sub(forward) {
+                while i < len {
+                    samples[i] = filtered(samples, i, forward, backward, &state);
+                    envelope.next();
+                    i += 1;
+                }
+                break;
+            }
+            forward = self.filter.compute(0, t as f32 / 65536.0, &mut state);
+            backward = self.filter.compute(1, t as f32 / 65536.0, &mut state);
+            until = (until + 128).min(len.saturating_sub(forward));
+        }
+    }
+}
+
+impl SoundEffect {
+    /// Returns a mapping of all [`SoundEffect`]s.
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        CacheIndex::new(IndexType::SOUND_EFFECTS, config.input.clone())?
+            .into_iter()
+            .map(|archive| {
+                let archive = archive?;
+                let id = archive.archive_id();
+                let file = archive.take_files().into_values().next().unwrap_or_default();
+                let sound = Self::deserialize(id, file).context(error::Read { what: "sound effects" })?;
+                Ok((id, sound))
+            })
+            .collect()
+    }
+
+    pub fn deserialize(id: u32, mut buffer: Bytes) -> Result<Self, ReadError> {
+        let mut instruments = Vec::new();
+        for _ in 0..10 {
+            if has_next(&mut buffer)? {
+                instruments.push(Instrument::deserialize(&mut buffer)?);
+            }
+        }
+        Ok(Self {
+            id,
+            instruments,
+            loop_start: buffer.try_get_u16()?,
+            loop_end: buffer.try_get_u16()?,
+        })
+    }
+
+    /// Mixes the instruments into signed 8-bit samples.
+    pub fn mix(&self) -> Vec<i8> {
+        let ms_to_samples = |ms: usize| ms * SAMPLE_RATE as usize / 1000;
+
+        let len = self
+            .instruments
+            .iter()
+            .map(|instrument| ms_to_samples(instrument.duration as usize + instrument.offset as usize))
+            .max()
+            .unwrap_or(0);
+        let mut out = vec![0_i32; len];
+
+        for instrument in &self.instruments {
+            let offset = ms_to_samples(instrument.offset as usize);
+            let samples = instrument.synthesize(ms_to_samples(instrument.duration as usize));
+            for (out, sample) in out[offset..].iter_mut().zip(samples) {
+                *out = ((sample >> 8) + *out).clamp(-128, 127);
+            }
+        }
+        out.into_iter().map(|sample| sample as i8).collect()
+    }
+
+    /// Encodes the sound as an 8-bit mono WAV file.
+    pub fn wav(&self) -> Vec<u8> {
+        let samples = self.mix();
+        let len = samples.len() as u32;
+
+        let mut out = Vec::with_capacity(44 + samples.len());
+        out.extend(b"RIFF");
+        out.extend((36 + len).to_le_bytes());
+        out.extend(b"WAVEfmt ");
+        out.extend(16_u32.to_le_bytes());
+        // PCM, one channel
+        out.extend(1_u16.to_le_bytes());
+        out.extend(1_u16.to_le_bytes());
+        out.extend(SAMPLE_RATE.to_le_bytes());
+        // bytes per second, bytes per sample, bits per sample
+        out.extend(SAMPLE_RATE.to_le_bytes());
+        out.extend(1_u16.to_le_bytes());
+        out.extend(8_u16.to_le_bytes());
+        out.extend(b"data");
+        out.extend(len.to_le_bytes());
+        // 8-bit WAV is unsigned.
+        out.extend(samples.into_iter().map(|sample| (sample as u8) ^ 0x80));
+        out
+    }
+}
+
+/// Saves every sound effect as `sounds/<id>.wav`. Exposed as `--dump sounds`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    for (id, sound) in SoundEffect::dump_all(config)?.into_iter().filter(|(id, _)| config.keeps(*id)) {
+        config.sink()?.put(format!("sounds/{id}.wav"), &sound.wav())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sine wave at a constant pitch and volume.
+    fn tone() -> SoundEffect {
+        let flat = |form, value| Envelope {
+            form,
+            start: value,
+            end: value,
+            durations: vec![0, 65535],
+            phases: vec![65535, 65535],
+        };
+        SoundEffect {
+            id: 0,
+            instruments: vec![Instrument {
+                pitch: flat(2, 440),
+                volume: flat(0, 100),
+                pitch_modifier: None,
+                volume_modifier: None,
+                gate: None,
+                oscillators: vec![Oscillator {
+                    volume: 100,
+                    pitch: 0,
+                    delay: 0,
+                }],
+                delay_time: 0,
+                delay_decay: 0,
+                duration: 100,
+                offset: 0,
+                filter: Filter::default(),
+            }],
+            loop_start: 0,
+            loop_end: 0,
+        }
+    }
+
+    #[test]
+    fn noise() {
+        // The first values of `new Random(0).nextInt()` are -1155484576 and -723955400.
+        assert_eq!(&NOISE[..2], &[(-1155484576 & 2) - 1, (-723955400 & 2) - 1]);
+    }
+
+    #[test]
+    fn synthesize() {
+        let samples = tone().mix();
+        assert_eq!(samples.len(), 2205);
+        assert!(samples.iter().any(|&sample| sample > 32));
+        assert!(samples.iter().any(|&sample| sample < -32));
+    }
+
+    #[test]
+    fn wav_header() {
+        let wav = tone().wav();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()) as usize, wav.len() - 8);
+        assert_eq!(&wav[36..40], b"data");
+    }
+}
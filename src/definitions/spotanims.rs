@@ -0,0 +1,200 @@
+//! Graphics played on top of entities, such as spell effects.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[cfg(feature = "osrs")]
+use crate::definitions::indextype::ConfigType;
+use crate::definitions::{
+    indextype::IndexType,
+    npc_configs::npc_config_fields::{ColourReplacements, Textures},
+};
+
+/// Describes a spotanim (also known as a graphic).
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct SpotAnim {
+    /// Its id.
+    pub id: u32,
+    /// The model that is drawn.
+    pub model: Option<u32>,
+    /// The [`Sequence`](crate::definitions::sequences::Sequence) the model plays.
+    pub animation: Option<u32>,
+    /// Horizontal scale, where 128 is the original size.
+    pub resize_x: Option<u16>,
+    /// Vertical scale, where 128 is the original size.
+    pub resize_y: Option<u16>,
+    pub rotation: Option<u16>,
+    pub ambient: Option<u8>,
+    pub contrast: Option<u8>,
+    pub unknown_9: Option<bool>,
+    pub unknown_10: Option<bool>,
+    pub colour_replacements: Option<ColourReplacements>,
+    pub texture_replacements: Option<Textures>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+impl SpotAnim {
+    /// Returns a mapping of all [`SpotAnim`]s.
+    #[cfg(feature = "rs3")]
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let archives = CacheIndex::new(IndexType::SPOT_CONFIG, config.input.clone())?.into_iter();
+
+        archives
+            .map(Result::unwrap)
+            .flat_map(|archive| {
+                let archive_id = archive.archive_id();
+                archive
+                    .take_files()
+                    .into_iter()
+                    .map(move |(file_id, file)| (archive_id << 8 | file_id, file))
+            })
+            .map(|(id, file)| try { (id, Self::deserialize(id, file, config.recover)?) })
+            .filter_map(|result| config.capture("spotanims", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "spotanims" })
+    }
+
+    /// Returns a mapping of all [`SpotAnim`]s.
+    #[cfg(feature = "osrs")]
+    pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(ConfigType::SPOTANIM)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("spotanims", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "spotanims" })
+    }
+
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut spotanim = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(spotanim);
+                        }
+                    }
+                    #[cfg(feature = "rs3")]
+                    1 => spotanim.model = buffer.try_get_smart32()?,
+                    #[cfg(feature = "osrs")]
+                    1 => spotanim.model = Some(buffer.try_get_u16()? as u32),
+                    #[cfg(feature = "rs3")]
+                    2 => spotanim.animation = buffer.try_get_smart32()?,
+                    #[cfg(feature = "osrs")]
+                    2 => spotanim.animation = Some(buffer.try_get_u16()? as u32),
+                    4 => spotanim.resize_x = Some(buffer.try_get_u16()?),
+                    5 => spotanim.resize_y = Some(buffer.try_get_u16()?),
+                    6 => spotanim.rotation = Some(buffer.try_get_u16()?),
+                    7 => spotanim.ambient = Some(buffer.try_get_u8()?),
+                    8 => spotanim.contrast = Some(buffer.try_get_u8()?),
+                    9 => spotanim.unknown_9 = Some(true),
+                    10 => spotanim.unknown_10 = Some(true),
+                    40 => spotanim.colour_replacements = Some(ColourReplacements::deserialize(&mut buffer)?),
+                    41 => spotanim.texture_replacements = Some(Textures::deserialize(&mut buffer)?),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    spotanim.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &spotanim,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(spotanim);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for SpotAnim {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl SpotAnim {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("SpotAnim({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("SpotAnim({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the spotanims as `spotanims.json`. Exposed as `--dump spotanims`.
+pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
+    let spotanims = SpotAnim::dump_all(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "spotanims", &spotanims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replacements() {
+        let buffer = Bytes::from_static(&[1, 0x01, 0x2C, 4, 0, 128, 5, 0, 64, 7, 20, 9, 40, 1, 0x12, 0x34, 0x56, 0x78, 0]);
+        let spotanim = SpotAnim::deserialize(90, buffer, false).unwrap();
+
+        assert_eq!(spotanim.model, Some(300));
+        assert_eq!((spotanim.resize_x, spotanim.resize_y), (Some(128), Some(64)));
+        assert_eq!(spotanim.ambient, Some(20));
+        assert_eq!(spotanim.unknown_9, Some(true));
+        assert_eq!(spotanim.colour_replacements.unwrap().colour_replacements, vec![(0x1234, 0x5678)]);
+    }
+
+    #[test]
+    fn truncated_replacements() {
+        let error = SpotAnim::deserialize(90, Bytes::from_static(&[40, 1, 0x12]), false).unwrap_err();
+        assert!(matches!(error, ReadError::WithInfo { source, .. } if matches!(*source, ReadError::Eof { .. })));
+    }
+}
@@ -14,6 +14,7 @@ use rs3cache_backend::{
     error::{self, CacheResult, Read},
     index::CacheIndex,
 };
+use serde::Serialize;
 #[cfg(any(feature = "rs3", feature = "osrs"))]
 use {rayon::iter::ParallelIterator, rs3cache_utils::bar::Render};
 
@@ -22,7 +23,8 @@ use crate::definitions::indextype::IndexType;
 /// Type alias for a rgba image.
 pub type Sprite = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
-/// Saves an image of every sprite to disk.
+/// Saves an image of every frame of every sprite to disk as `sprites/<id>-<frame>.png`,
+/// and lists their dimensions in `sprites/index.json`.
 #[cfg(any(feature = "rs3", feature = "osrs"))]
 pub fn save_all(config: &crate::cli::Config) -> CacheResult<()> {
     let path = path!(config.output / "sprites");
@@ -37,31 +39,73 @@ pub fn save_all(config: &crate::cli::Config) -> CacheResult<()> {
         .map(|(_, meta)| (meta.archive_id(), ::filetime::FileTime::from_unix_time(meta.version() as i64, 0)))
         .collect();
 
-    index.into_iter().render("sprites").for_each(|(archive, _)| {
-        let archive = archive.unwrap();
-        debug_assert_eq!(archive.file_count(), 1);
-
-        let file = archive
-            .file(&0)
-            .unwrap_or_else(|| panic!("File for sprite {} is missing", archive.archive_id()));
-        let images = deserialize(file).unwrap_or_else(|error| panic!("Error decoding sprite {}: {}", archive.archive_id(), error));
-        images.into_iter().for_each(|(frame, img)| {
-            let id = archive.archive_id();
-            let filename = path!(config.output / "sprites" / format!("{id}-{frame}.png"));
-            img.save(&filename)
-                .unwrap_or_else(|_| panic!("Unable to save sprite {}-{} to {}", id, frame, filename.to_string_lossy()));
+    let mut infos = index
+        .into_iter()
+        .render("sprites")
+        .filter_map(|(archive, _)| {
+            let archive = archive.unwrap();
+            if !config.keeps(archive.archive_id()) {
+                return None;
+            }
+            debug_assert_eq!(archive.file_count(), 1);
+
+            let file = archive
+                .file(&0)
+                .unwrap_or_else(|| panic!("File for sprite {} is missing", archive.archive_id()));
+            let ((width, height), frames, images) =
+                deserialize_with_info(file).unwrap_or_else(|error| panic!("Error decoding sprite {}: {}", archive.archive_id(), error));
+            images.into_iter().for_each(|(frame, img)| {
+                let id = archive.archive_id();
+                let filename = path!(config.output / "sprites" / format!("{id}-{frame}.png"));
+                img.save(&filename)
+                    .unwrap_or_else(|_| panic!("Unable to save sprite {}-{} to {}", id, frame, filename.to_string_lossy()));
+
+                #[cfg(feature = "rs3")]
+                {
+                    let file = ::std::fs::OpenOptions::new().write(true).open(&filename).unwrap();
+
+                    let date = versions[&id];
+
+                    ::filetime::set_file_handle_times(&file, Some(date), Some(date)).unwrap();
+                }
+            });
+            Some(SpriteInfo {
+                id: archive.archive_id(),
+                width,
+                height,
+                frames,
+            })
+        })
+        .collect::<Vec<_>>();
+    infos.sort_unstable_by_key(|info| info.id);
+    crate::output::export(config, "sprites/index", &infos)
+}
 
-            #[cfg(feature = "rs3")]
-            {
-                let file = ::std::fs::OpenOptions::new().write(true).open(&filename).unwrap();
+/// Saves only the sprites that were added or changed since the cache passed with `--previous`,
+/// along with `sprites_diff/manifest.json`, which lists the added, changed and removed sprite ids.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub fn export_diff(config: &crate::cli::Config) -> CacheResult<()> {
+    use rs3cache_backend::index;
 
-                let date = versions[&id];
+    let previous = config.previous.as_ref().expect("clap requires `--previous` for `--dump sprites_diff`");
+    let changes = crate::diff::Changes::of_index(IndexType::SPRITES, previous, &config.input)?;
 
-                ::filetime::set_file_handle_times(&file, Some(date), Some(date)).unwrap();
+    CacheIndex::new(IndexType::SPRITES, config.input.clone())?
+        .retain(changes.updated())
+        .into_iter()
+        .render("changed sprites")
+        .try_for_each(|(archive, _)| -> CacheResult<()> {
+            let archive = archive?;
+            let id = archive.archive_id();
+            let file = archive.file(&0).context(index::Other).context(error::Integrity)?;
+            let frames = deserialize(file).context(Read { what: "sprite frames" })?;
+            for (frame, img) in frames {
+                crate::renderers::png::put(config, path!("sprites_diff" / format!("{id}-{frame}.png")), &img)?;
             }
-        })
-    });
-    Ok(())
+            Ok(())
+        })?;
+
+    crate::output::put_json(config, "sprites_diff/manifest.json", &changes)
 }
 
 #[derive(Debug)]
@@ -73,17 +117,20 @@ pub struct IndexEntry {
 }
 
 impl IndexEntry {
-    pub fn deserialize(buffer: &mut Bytes) -> Self {
-        let max_width = buffer.get_u16();
-        let max_height = buffer.get_u16();
-        let colour_count = buffer.get_u8().checked_sub(1).unwrap();
-        let palette = iter::repeat_with(|| buffer.get_rgb()).take(colour_count as usize).collect();
-        Self {
+    pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        let max_width = buffer.try_get_u16()?;
+        let max_height = buffer.try_get_u16()?;
+        // the palette size includes the transparent colour, which is not stored
+        let colour_count = buffer.try_get_u8()?.saturating_sub(1);
+        let palette = iter::repeat_with(|| buffer.try_get_array())
+            .take(colour_count as usize)
+            .collect::<Result<_, ReadError>>()?;
+        Ok(Self {
             max_width,
             max_height,
             colour_count,
             palette,
-        }
+        })
     }
 }
 
@@ -97,14 +144,14 @@ pub struct Entry {
 }
 
 impl Entry {
-    pub fn deserialize(buffer: &mut Bytes) -> Self {
-        Self {
-            offset_x: buffer.get_u8(),
-            offset_y: buffer.get_u8(),
-            width: buffer.get_u16(),
-            height: buffer.get_u16(),
-            transposed: buffer.get_u8(),
-        }
+    pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+        Ok(Self {
+            offset_x: buffer.try_get_u8()?,
+            offset_y: buffer.try_get_u8()?,
+            width: buffer.try_get_u16()?,
+            height: buffer.try_get_u16()?,
+            transposed: buffer.try_get_u8()?,
+        })
     }
 }
 
@@ -129,63 +176,186 @@ fn make_image(index_entry: &IndexEntry, entry: &Entry, data: Bytes) -> Sprite {
     image
 }
 
+/// Names of the sprites in the `title` and `media` archives of legacy caches.
+///
+/// The archives only store a hash of each file name. These are the names that can be recovered from it;
+/// sprites with any other name are saved under their hash.
 #[cfg(feature = "legacy")]
-pub fn save_all(config: &crate::cli::Config) -> CacheResult<()> {
-    use rs3cache_backend::hash::hash_archive;
+const LEGACY_SPRITE_NAMES: &[&str] = &[
+    "b12_full",
+    "backbase1",
+    "backbase2",
+    "backhmid1",
+    "backhmid2",
+    "backleft1",
+    "backleft2",
+    "backright1",
+    "backright2",
+    "backtop1",
+    "backtop2",
+    "backvmid1",
+    "backvmid2",
+    "backvmid3",
+    "chatback",
+    "combatboxes",
+    "combaticons",
+    "combaticons2",
+    "combaticons3",
+    "compass",
+    "cross",
+    "gnomeball_buttons",
+    "headicons",
+    "hitmarks",
+    "invback",
+    "leftarrow",
+    "logo",
+    "magicoff",
+    "magicoff2",
+    "magicon",
+    "magicon2",
+    "mapback",
+    "mapdots",
+    "mapedge",
+    "mapfunction",
+    "mapmarker",
+    "mapscene",
+    "miscgraphics",
+    "miscgraphics2",
+    "miscgraphics3",
+    "mod_icons",
+    "p11_full",
+    "p12_full",
+    "prayerglow",
+    "prayeroff",
+    "prayeron",
+    "q8_full",
+    "redstone1",
+    "redstone2",
+    "redstone3",
+    "rightarrow",
+    "runes",
+    "scrollbar",
+    "sideicons",
+    "staticons",
+    "staticons2",
+    "steelborder",
+    "steelborder2",
+    "sworddecor",
+    "titlebox",
+    "titlebutton",
+    "tradebacking",
+    "wornicons",
+];
+
+/// Files in the `title` and `media` archives that are not sprites.
+#[cfg(feature = "legacy")]
+const LEGACY_OTHER_FILES: &[&str] = &["index", "title"];
 
-    let path = path!(config.output / "sprites");
-    std::fs::create_dir_all(&path).context(error::Io { path })?;
+/// Decodes every frame of the legacy sprite named `name`, such as `mapscene.dat`, in the `media` archive.
+#[cfg(feature = "legacy")]
+fn legacy_frames(name: &'static str, config: &crate::cli::Config) -> CacheResult<Vec<Sprite>> {
+    let archive = CacheIndex::new(0, config.input.clone())?.archive(4)?;
+    decode_legacy(archive.file_named("index.dat")?, archive.file_named(name)?).context(error::Read { what: name })
+}
 
-    let index = CacheIndex::new(0, config.input.clone())?;
-    let mut files = index.archive(4)?.take_files_named();
+/// Decodes a legacy sprite, given the `index.dat` of its archive.
+///
+/// The sprite starts with the offset of its palette and frame sizes in `index.dat`, followed by the pixels of every frame.
+#[cfg(feature = "legacy")]
+fn decode_legacy(meta: Bytes, mut data: Bytes) -> Result<Vec<Sprite>, ReadError> {
+    use rs3cache_backend::buf::Eof;
 
-    let meta = files.remove(&hash_archive("index.dat")).unwrap();
-    let mut data = files.remove(&hash_archive("mapscene.dat")).unwrap();
-    let offset = data.get_u16();
-    let mut entry_data = meta.slice((offset as usize)..);
-    let index_entry = IndexEntry::deserialize(&mut entry_data);
+    let offset = data.try_get_u16()? as usize;
+    if offset > meta.len() {
+        return Err(Eof::new(offset, meta.len()));
+    }
+    let mut entry_data = meta.slice(offset..);
+    let index_entry = IndexEntry::deserialize(&mut entry_data)?;
 
-    let mut id = 0;
+    let mut out = Vec::new();
     while data.has_remaining() {
-        let entry = Entry::deserialize(&mut entry_data);
-        let this_data = data.split_to((entry.width * entry.height) as usize);
-        let img = make_image(&index_entry, &entry, this_data);
-        let filename = path!(config.output / "sprites" / format!("mapscene-{id}.png"));
-        img.save(&filename).unwrap();
-
-        id += 1;
+        let entry = Entry::deserialize(&mut entry_data)?;
+        let size = entry.width as usize * entry.height as usize;
+        if data.remaining() < size {
+            return Err(Eof::new(size, data.remaining()));
+        }
+        let this_data = data.split_to(size);
+        out.push(make_image(&index_entry, &entry, this_data));
     }
-
-    Ok(())
+    Ok(out)
 }
 
+/// Saves an image of every frame of every sprite in the `title` and `media` archives to disk, as `sprites/<name>-<frame>.png`.
+///
+/// Sprites whose name is not known are saved as `sprites/<hash>-<frame>.png`, with the hash of their name in hexadecimal.
 #[cfg(feature = "legacy")]
-pub fn get_mapscenes(scale: u32, config: &crate::cli::Config) -> CacheResult<BTreeMap<(u32, u32), Sprite>> {
-    use rs3cache_backend::hash::hash_archive;
+pub fn save_all(config: &crate::cli::Config) -> CacheResult<()> {
+    use rs3cache_backend::{
+        hash::hash_archive,
+        warning::{self, Warning},
+    };
 
     let path = path!(config.output / "sprites");
     std::fs::create_dir_all(&path).context(error::Io { path })?;
 
+    let names: BTreeMap<i32, &str> = LEGACY_SPRITE_NAMES
+        .iter()
+        .map(|name| (hash_archive(&format!("{name}.dat")), *name))
+        .collect();
+    let others = LEGACY_OTHER_FILES
+        .iter()
+        .map(|name| hash_archive(&format!("{name}.dat")))
+        .collect::<Vec<_>>();
+
     let index = CacheIndex::new(0, config.input.clone())?;
-    let mut files = index.archive(4)?.take_files_named();
+    for archive_id in [1, 4] {
+        let archive = index.archive(archive_id)?;
+        let meta = archive.file_named("index.dat")?;
 
-    let meta = files.remove(&hash_archive("index.dat")).unwrap();
-    let mut data = files.remove(&hash_archive("mapscene.dat")).unwrap();
-    let offset = data.get_u16();
-    let mut entry_data = meta.slice((offset as usize)..);
-    let index_entry = IndexEntry::deserialize(&mut entry_data);
+        for (hash, data) in archive.take_files_named() {
+            if others.contains(&hash) {
+                continue;
+            }
+            let name = match names.get(&hash) {
+                Some(name) => name.to_string(),
+                None => format!("{hash:08x}"),
+            };
+            let Ok(frames) = decode_legacy(meta.clone(), data) else {
+                warning::warn(Warning::UndecodableSprite { name });
+                continue;
+            };
+            for (frame, img) in frames.into_iter().enumerate() {
+                let filename = path!(config.output / "sprites" / format!("{name}-{frame}.png"));
+                img.save(&filename).unwrap();
+            }
+        }
+    }
 
-    let mut out = BTreeMap::new();
+    Ok(())
+}
 
-    let mut frame = 0;
-    while data.has_remaining() {
-        let entry = Entry::deserialize(&mut entry_data);
-        let this_data = data.split_to((entry.width * entry.height) as usize);
-        let img = make_image(&index_entry, &entry, this_data);
-        let img = imageops::resize(&img, img.width() * scale, img.height() * scale, imageops::Nearest);
-        out.insert((317, frame), img);
-        frame += 1;
-    }
+/// Returns the frames of the legacy `mapscene` sprite, keyed like the osrs sprite 317 they correspond to.
+#[cfg(feature = "legacy")]
+pub fn get_mapscenes(scale: u32, config: &crate::cli::Config) -> CacheResult<BTreeMap<(u32, u32), Sprite>> {
+    get_legacy("mapscene.dat", 317, scale, config)
+}
+
+/// Returns the frames of the legacy `mapfunction` sprite, keyed like the osrs sprite 318 they correspond to.
+#[cfg(feature = "legacy")]
+pub fn get_mapfunctions(scale: u32, config: &crate::cli::Config) -> CacheResult<BTreeMap<(u32, u32), Sprite>> {
+    get_legacy("mapfunction.dat", 318, scale, config)
+}
+
+#[cfg(feature = "legacy")]
+fn get_legacy(name: &'static str, id: u32, scale: u32, config: &crate::cli::Config) -> CacheResult<BTreeMap<(u32, u32), Sprite>> {
+    let out = legacy_frames(name, config)?
+        .into_iter()
+        .enumerate()
+        .map(|(frame, img)| {
+            let img = imageops::resize(&img, img.width() * scale, img.height() * scale, imageops::Nearest);
+            ((id, frame as u32), img)
+        })
+        .collect();
     Ok(out)
 }
 
@@ -227,7 +397,34 @@ pub fn dumps(scale: u32, ids: Vec<u32>, config: &crate::cli::Config) -> CacheRes
     Ok(sprites)
 }
 
+/// Where a frame of a sprite is placed on the sprite's canvas.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub frame: usize,
+    pub offset_x: u16,
+    pub offset_y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// The dimensions of a sprite and of its frames, as listed in `sprites/index.json`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct SpriteInfo {
+    pub id: u32,
+    /// The width of the canvas that frames are placed on.
+    pub width: u16,
+    /// The height of the canvas that frames are placed on.
+    pub height: u16,
+    pub frames: Vec<Frame>,
+}
+
 pub fn deserialize(buffer: Bytes) -> Result<BTreeMap<usize, Sprite>, ReadError> {
+    let (_, _, imgs) = deserialize_with_info(buffer)?;
+    Ok(imgs)
+}
+
+/// Like [`deserialize`], but also returns the size of the sprite's canvas and where each frame goes on it.
+pub fn deserialize_with_info(buffer: Bytes) -> Result<((u16, u16), Vec<Frame>, BTreeMap<usize, Sprite>), ReadError> {
     let mut buffer = Cursor::new(buffer);
 
     buffer.seek(SeekFrom::End(-2)).context(FileSeek)?;
@@ -236,19 +433,30 @@ pub fn deserialize(buffer: Bytes) -> Result<BTreeMap<usize, Sprite>, ReadError>
     let format = data >> 15;
     let count = (data & 0x7FFF) as usize;
 
-    let imgs = match format {
+    let ret = match format {
         0 => {
             buffer.seek(SeekFrom::End(-7 - (count as i64) * 8)).context(FileSeek)?;
 
-            let _big_width = buffer.get_u16();
-            let _big_height = buffer.get_u16();
+            let big_width = buffer.get_u16();
+            let big_height = buffer.get_u16();
             let palette_count = buffer.get_u8() as usize;
 
-            let _min_xs = iter::repeat_with(|| buffer.get_u16()).take(count).collect::<Vec<_>>();
-            let _min_ys = iter::repeat_with(|| buffer.get_u16()).take(count).collect::<Vec<_>>();
+            let min_xs = iter::repeat_with(|| buffer.get_u16()).take(count).collect::<Vec<_>>();
+            let min_ys = iter::repeat_with(|| buffer.get_u16()).take(count).collect::<Vec<_>>();
             let widths = iter::repeat_with(|| buffer.get_u16()).take(count).collect::<Vec<_>>();
             let heights = iter::repeat_with(|| buffer.get_u16()).take(count).collect::<Vec<_>>();
 
+            let frames = izip!(0..count, &min_xs, &min_ys, &widths, &heights)
+                .filter(|(_, _, _, &width, &height)| width != 0 && height != 0)
+                .map(|(frame, &offset_x, &offset_y, &width, &height)| Frame {
+                    frame,
+                    offset_x,
+                    offset_y,
+                    width,
+                    height,
+                })
+                .collect::<Vec<_>>();
+
             let pos = -7 - (count as i64) * 8 - (palette_count as i64) * 3;
 
             buffer.seek(SeekFrom::End(pos)).context(FileSeek)?;
@@ -257,7 +465,7 @@ pub fn deserialize(buffer: Bytes) -> Result<BTreeMap<usize, Sprite>, ReadError>
 
             buffer.seek(SeekFrom::Start(0)).context(FileSeek)?;
 
-            izip!(0..count, widths, heights)
+            let imgs = izip!(0..count, widths, heights)
                 .filter_map(|(index, width, height)| {
                     let pixel_count = width as usize * height as usize;
                     let [transposed, alpha, ..] = buffer.get_bitflags();
@@ -297,7 +505,8 @@ pub fn deserialize(buffer: Bytes) -> Result<BTreeMap<usize, Sprite>, ReadError>
                         None
                     }
                 })
-                .collect::<BTreeMap<_, _>>()
+                .collect::<BTreeMap<_, _>>();
+            ((big_width, big_height), frames, imgs)
         }
         1 => {
             buffer.seek(SeekFrom::Start(0)).context(FileSeek)?;
@@ -330,11 +539,70 @@ pub fn deserialize(buffer: Bytes) -> Result<BTreeMap<usize, Sprite>, ReadError>
             let mut images = BTreeMap::new();
             images.insert(0_usize, img);
 
-            images
+            let frame = Frame {
+                frame: 0,
+                offset_x: 0,
+                offset_y: 0,
+                width,
+                height,
+            };
+            ((width, height), vec![frame], images)
         }
         _ => unimplemented!("Unknown sprite format..."),
     };
-    Ok(imgs)
+    Ok(ret)
+}
+
+#[cfg(test)]
+#[cfg(feature = "legacy")]
+mod legacy_tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        #[rustfmt::skip]
+        let meta = Bytes::from_static(&[
+            // canvas size, palette size
+            0, 2, 0, 1, 2,
+            // palette
+            10, 20, 30,
+            // offsets and size of the only frame, not transposed
+            0, 0, 0, 2, 0, 1, 0,
+        ]);
+        // the offset in `meta`, then the palette indices of the pixels
+        let data = Bytes::from_static(&[0, 0, 0, 1]);
+
+        let frames = decode_legacy(meta, data).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].get_pixel(0, 0)[3], 0);
+        assert_eq!(frames[0].get_pixel(1, 0), &Rgba([10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn truncated() {
+        #[rustfmt::skip]
+        let meta = Bytes::from_static(&[
+            0, 2, 0, 1, 2,
+            10, 20, 30,
+            0, 0, 0, 2, 0, 1, 0,
+        ]);
+        // one of the two pixels is missing
+        assert!(decode_legacy(meta.clone(), Bytes::from_static(&[0, 0, 0])).is_err());
+        // the offset points past the end of `index.dat`
+        assert!(decode_legacy(meta, Bytes::from_static(&[0, 20, 0, 1])).is_err());
+    }
+
+    #[test]
+    fn names() {
+        use rs3cache_backend::hash::hash_archive;
+
+        let hashes = LEGACY_SPRITE_NAMES
+            .iter()
+            .chain(LEGACY_OTHER_FILES)
+            .map(|name| hash_archive(&format!("{name}.dat")))
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(hashes.len(), LEGACY_SPRITE_NAMES.len() + LEGACY_OTHER_FILES.len());
+    }
 }
 
 #[cfg(test)]
@@ -342,6 +610,37 @@ pub fn deserialize(buffer: Bytes) -> Result<BTreeMap<usize, Sprite>, ReadError>
 mod sprite_tests {
     use super::*;
 
+    #[test]
+    fn frame_info() {
+        #[rustfmt::skip]
+        let data = Bytes::from_static(&[
+            // flags, then the palette index of the only pixel
+            0, 1,
+            // palette
+            10, 20, 30,
+            // canvas size, palette size
+            0, 4, 0, 4, 1,
+            // offsets and size of the only frame
+            0, 2, 0, 3, 0, 1, 0, 1,
+            // format 0, one frame
+            0, 1,
+        ]);
+        let (size, frames, images) = deserialize_with_info(data).unwrap();
+
+        assert_eq!(size, (4, 4));
+        assert_eq!(
+            frames,
+            vec![Frame {
+                frame: 0,
+                offset_x: 2,
+                offset_y: 3,
+                width: 1,
+                height: 1
+            }]
+        );
+        assert_eq!(images[&0].get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+    }
+
     #[test]
     fn render_some_0() -> CacheResult<()> {
         fn dump(id: u32, frame: u32) -> CacheResult<Sprite> {
@@ -1,20 +1,19 @@
 //! Describes the properties of structs.
 
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-};
+use core::panic::Location;
+use std::collections::BTreeMap;
 
 use ::error::Context;
-use bytes::{Buf, Bytes};
-use path_macro::path;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::{definitions::indextype::IndexType, structures::paramtable::ParamTable};
@@ -23,13 +22,16 @@ use crate::{definitions::indextype::IndexType, structures::paramtable::ParamTabl
 
 #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct Struct {
     /// Its id.
     pub id: u32,
 
     #[serde(flatten)]
     pub params: Option<ParamTable>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl Struct {
@@ -46,25 +48,70 @@ impl Struct {
                     .into_iter()
                     .map(move |(file_id, file)| (archive_id << 5 | file_id, file))
             })
-            .map(|(id, file)| (id, Self::deserialize(id, file)))
-            .collect::<BTreeMap<u32, Self>>();
+            .map(|(id, file)| try { (id, Self::deserialize(id, file, config.recover)?) })
+            .filter_map(|result| config.capture("structs", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "structs" })?;
         Ok(locations)
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> Self {
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
         let mut r#struct = Self { id, ..Default::default() };
 
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
         loop {
-            match buffer.get_u8() {
-                0 => {
-                    debug_assert!(!buffer.has_remaining());
-                    break r#struct;
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(r#struct);
+                        }
+                    }
+                    249 => r#struct.params = Some(ParamTable::deserialize(&mut buffer)),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    r#struct.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &r#struct,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(r#struct);
                 }
-                249 => r#struct.params = Some(ParamTable::deserialize(&mut buffer)),
-                missing => unimplemented!("Struct::deserialize cannot deserialize opcode {} in id {}", missing, id),
             }
         }
     }
+
+    /// Encodes `self` in the format of the cache, so that decoding the result gives back `self`.
+    pub fn encode(&self) -> Bytes {
+        let mut buffer = BytesMut::new();
+        if let Some(params) = &self.params {
+            buffer.put_u8(249);
+            params.encode(&mut buffer);
+        }
+        buffer.put_u8(0);
+        buffer.freeze()
+    }
 }
 
 use std::fmt::{self, Display, Formatter};
@@ -89,15 +136,34 @@ impl Struct {
 
 /// Save the item configs as `structs.json`. Exposed as `--dump structs`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut structs = Struct::dump_all(config)?.into_values().collect::<Vec<_>>();
     structs.sort_unstable_by_key(|loc| loc.id);
+    crate::output::export(config, "structs", &structs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let path = path!(&config.output / "structs.json");
+    #[test]
+    fn round_trip() {
+        let data = Bytes::from_static(b"\xf9\x02\x00\x00\x02\x8e\x00\x00\x00\x05\x01\x00\x02\x8fCoins\x00\x00");
+        let r#struct = Struct::deserialize(7, data.clone(), false).unwrap();
+        assert_eq!(r#struct.encode(), data);
+    }
 
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&structs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
+    #[test]
+    fn unknown_opcode() {
+        let error = Struct::deserialize(7, Bytes::from_static(&[200, 0]), false).unwrap_err();
+        assert!(matches!(error, ReadError::WithInfo { source, .. } if matches!(*source, ReadError::OpcodeNotImplemented { opcode: 200, .. })));
+    }
 
-    Ok(())
+    #[test]
+    fn recover() {
+        let r#struct = Struct::deserialize(7, Bytes::from_static(&[200, 0]), true).unwrap();
+        assert_eq!(
+            r#struct.decode_error.as_deref(),
+            Some("stopped at opcode 200 with 1 bytes left: opcode 200 is not implemented")
+        );
+    }
 }
@@ -1,20 +1,11 @@
 #![cfg(feature = "osrs")]
 
-use std::{
-    collections::HashMap,
-    fs::{self, File},
-    io::Write,
-};
+use std::collections::HashMap;
 
-use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
-use rs3cache_backend::{
-    error::{self, CacheResult},
-    index::CacheIndex,
-};
+use rs3cache_backend::{error::CacheResult, index::CacheIndex};
 use serde::Serialize;
 
 use crate::definitions::indextype::IndexType;
@@ -63,14 +54,7 @@ impl Display for TextureConfig {
 
 /// Save the textures as `textures.json`. Exposed as `--dump item_configs`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
-
     let mut loc_configs = TextureConfig::dump_all(config)?.into_values().collect::<Vec<_>>();
     loc_configs.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(config.output / "textures.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&loc_configs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export(config, "textures", &loc_configs)
 }
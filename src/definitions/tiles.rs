@@ -32,6 +32,21 @@ pub struct Tile {
 }
 
 impl Tile {
+    /// The names of the [`fields`](Tile::fields) of a tile, as the columns of `--dump tiles`.
+    pub const FIELDS: [&'static str; 6] = ["height", "overlay_id", "overlay_shape", "overlay_rotation", "underlay_id", "settings"];
+
+    /// Its height, overlay id, overlay shape and rotation, underlay id and settings, as named by [`FIELDS`](Tile::FIELDS).
+    pub fn fields(&self) -> [Option<i32>; 6] {
+        [
+            self.height.map(i32::from),
+            self.overlay_id.map(i32::from),
+            self.shape.map(|shape| i32::from(shape >> 2)),
+            self.shape.map(|shape| i32::from(shape & 0x3)),
+            self.underlay_id.map(i32::from),
+            self.settings.map(i32::from),
+        ]
+    }
+
     /// Constructor for a sequence of [`Tile`]s.
     #[cfg(any(feature = "rs3", feature = "2013_shim"))]
     pub fn dump(buffer: &mut Bytes) -> TileArray {
@@ -124,3 +139,21 @@ impl Tile {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let tile = Tile {
+            shape: Some(0b1011),
+            overlay_id: Some(300),
+            settings: Some(1),
+            underlay_id: None,
+            height: Some(20),
+        };
+        assert_eq!(tile.fields(), [Some(20), Some(300), Some(2), Some(3), None, Some(1)]);
+        assert_eq!(Tile::default().fields(), [None; 6]);
+    }
+}
@@ -1,23 +1,23 @@
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-};
+use core::panic::Location;
+use std::collections::BTreeMap;
 
 use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
-    buf::BufExtra,
+    buf::{BufExtra, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
 use serde::Serialize;
 use serde_with::skip_serializing_none;
 
-use crate::definitions::indextype::{ConfigType, IndexType};
+use crate::{
+    cli::Revision,
+    definitions::indextype::{ConfigType, IndexType},
+};
 
 /// Describes the general ground colour. This colour is blended with surrounding tiles.
 #[cfg_attr(feature = "pyo3", pyclass(frozen))]
@@ -35,57 +35,95 @@ pub struct Underlay {
     op_3: Option<u16>,
     #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     op_4: Option<bool>,
-    #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
     op_5: Option<bool>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl Underlay {
     /// Returns a mapping of all [`Underlay`] configurations.
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Underlay>> {
-        Ok(CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+        let revision = config.revision();
+        CacheIndex::new(IndexType::CONFIG, config.input.clone())?
             .archive(ConfigType::UNDERLAYS)?
             .take_files()
             .into_iter()
-            .map(|(file_id, file)| (file_id, Underlay::deserialize(file_id, file)))
-            .collect())
+            .map(|(file_id, file)| try { (file_id, Underlay::deserialize(file_id, file, revision, config.recover)?) })
+            .filter_map(|result| config.capture("underlays", result))
+            .collect::<Result<BTreeMap<u32, Underlay>, ReadError>>()
+            .context(error::Read { what: "underlays" })
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> Underlay {
+    #[allow(unused_variables)]
+    fn deserialize(id: u32, mut buffer: Bytes, revision: Revision, recovering: bool) -> Result<Underlay, ReadError> {
         let mut underlay = Underlay { id, ..Default::default() };
 
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
         loop {
-            let opcode = buffer.get_u8();
-            match opcode {
-                0 => {
-                    assert!(!buffer.has_remaining());
-                    break underlay;
-                }
-                1 => underlay.colour = Some(buffer.get_rgb()),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                2 => underlay.op_2 = Some(buffer.get_u16()),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                3 => underlay.op_3 = Some(buffer.get_u16()),
-                #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
-                4 => underlay.op_4 = Some(true),
-                #[cfg(any(feature = "rs3", feature = "2010_1_shim"))]
-                5 => underlay.op_5 = Some(true),
+            let opcode = buffer.try_get_u8()?;
 
-                missing => unimplemented!("Underlay::deserialize cannot deserialize opcode {} in id {}", missing, id),
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(underlay);
+                        }
+                    }
+                    1 => underlay.colour = Some(buffer.try_get_array()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    2 => underlay.op_2 = Some(buffer.try_get_u16()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    3 => underlay.op_3 = Some(buffer.try_get_u16()?),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    4 => underlay.op_4 = Some(true),
+                    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+                    5 if revision >= Revision::R2010_1 => underlay.op_5 = Some(true),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    underlay.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &underlay,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(underlay);
+                }
             }
         }
     }
 }
 
+use std::fmt::{self, Display, Formatter};
+
+impl Display for Underlay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
 /// Save the location configs as `location_configs.json`. Exposed as `--dump location_configs`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut underlay = Underlay::dump_all(config)?.into_values().collect::<Vec<_>>();
     underlay.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(config.output / "underlays.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-
-    let data = serde_json::to_string_pretty(&underlay).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export(config, "underlays", &underlay)
 }
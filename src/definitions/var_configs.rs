@@ -0,0 +1,171 @@
+//! Player and client variables.
+//!
+//! See also [`VarbitConfig`](crate::definitions::varbit_configs::VarbitConfig),
+//! which describe ranges of bits within player variables.
+
+use core::panic::Location;
+use std::collections::BTreeMap;
+
+use ::error::Context;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
+    error::{self, CacheResult},
+    index::CacheIndex,
+    recovery::Stopped,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::definitions::indextype::{ConfigType, IndexType};
+
+/// Configuration of a player (varp) or client (varc) variable.
+#[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct VarConfig {
+    /// Its id.
+    pub id: u32,
+    /// The script type of the value it holds.
+    #[cfg(not(feature = "osrs"))]
+    pub data_type: Option<u8>,
+    /// Whether (and how long) the variable is kept around.
+    #[cfg(not(feature = "osrs"))]
+    pub lifetime: Option<u8>,
+    /// Used by the client to react to changes of this variable.
+    #[cfg(feature = "osrs")]
+    pub client_code: Option<u16>,
+    /// Whether this variable is saved between sessions.
+    #[cfg(feature = "osrs")]
+    pub persist: Option<bool>,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
+}
+
+impl VarConfig {
+    /// Returns a mapping of all player variables.
+    pub fn dump_varps(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        Self::dump_archive(config, ConfigType::PLAYER, "varps")
+    }
+
+    /// Returns a mapping of all client variables.
+    pub fn dump_varcs(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
+        Self::dump_archive(config, ConfigType::CLIENT, "varcs")
+    }
+
+    fn dump_archive(config: &crate::cli::Config, archive_id: u32, what: &'static str) -> CacheResult<BTreeMap<u32, Self>> {
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+            .archive(archive_id)?
+            .take_files()
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture(what, result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what })
+    }
+
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut var = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
+
+        loop {
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(var);
+                        }
+                    }
+                    #[cfg(feature = "osrs")]
+                    2 => var.persist = Some(true),
+                    #[cfg(not(feature = "osrs"))]
+                    3 => var.data_type = Some(buffer.try_get_u8()?),
+                    #[cfg(not(feature = "osrs"))]
+                    4 => var.lifetime = Some(buffer.try_get_u8()?),
+                    #[cfg(feature = "osrs")]
+                    5 => var.client_code = Some(buffer.try_get_u16()?),
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
+                }
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
+                }
+                Err(e) => {
+                    var.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &var,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(var);
+                }
+            }
+        }
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+
+impl Display for VarConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl VarConfig {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("VarConfig({})", serde_json::to_string(self).unwrap()))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        Ok(format!("VarConfig({})", serde_json::to_string(self).unwrap()))
+    }
+}
+
+/// Save the player variables as `varps.json`. Exposed as `--dump varps`.
+pub fn export_varps(config: &crate::cli::Config) -> CacheResult<()> {
+    let varps = VarConfig::dump_varps(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "varps", &varps)
+}
+
+/// Save the client variables as `varcs.json`. Exposed as `--dump varcs`.
+pub fn export_varcs(config: &crate::cli::Config) -> CacheResult<()> {
+    let varcs = VarConfig::dump_varcs(config)?.into_values().collect::<Vec<_>>();
+    crate::output::export(config, "varcs", &varcs)
+}
+
+#[cfg(all(test, feature = "rs3"))]
+mod tests {
+    use super::*;
+    use crate::cli::Config;
+
+    #[test]
+    fn dump_varps() -> CacheResult<()> {
+        let config = Config::env();
+        let varps = VarConfig::dump_varps(&config)?;
+        assert!(!varps.is_empty());
+        Ok(())
+    }
+}
@@ -3,21 +3,20 @@
 //!
 //! See also [`Varp`](crate::types::variables::Varp) and [`Varbit`](crate::types::variables::Varbit).
 
-use std::{
-    collections::BTreeMap,
-    fs::{self, File},
-    io::Write,
-};
+use core::panic::Location;
+use std::collections::BTreeMap;
 
 use ::error::Context;
 use bytes::{Buf, Bytes};
-use path_macro::path;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use rs3cache_backend::{
+    buf::{BufExtra, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
+    recovery::Stopped,
 };
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::definitions::indextype::{ConfigType, IndexType};
@@ -27,73 +26,121 @@ use crate::definitions::indextype::{ConfigType, IndexType};
 
 #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct VarbitConfig {
     /// Id of the [`Varbit`](crate::types::variables::Varbit).
     pub id: u32,
+    #[cfg(not(feature = "osrs"))]
     pub unknown_1: u8,
     /// The Varp that this varbit maps to.
     pub index: u16,
     pub least_significant_bit: u8,
     pub most_significant_bit: u8,
+    /// Where decoding stopped, if it failed and `--recover` was passed.
+    #[serde(rename = "_error")]
+    pub decode_error: Option<String>,
 }
 
 impl VarbitConfig {
     /// Returns a mapping of all [`VarbitConfig`]s.
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<BTreeMap<u32, Self>> {
-        Ok(CacheIndex::new(IndexType::CONFIG, config.input.clone())?
+        let files = CacheIndex::new(IndexType::CONFIG, config.input.clone())?
             .archive(ConfigType::VARBITS)?
             .take_files()
-            .into_iter()
-            .map(|(file_id, file)| (file_id, VarbitConfig::deserialize(file_id, file)))
-            .collect())
+            .into_iter();
+
+        files
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file, config.recover)?) })
+            .filter_map(|result| config.capture("varbit configs", result))
+            .collect::<Result<BTreeMap<u32, Self>, ReadError>>()
+            .context(error::Read { what: "varbit configs" })
+    }
+
+    /// Extracts the value of this varbit from the value of its Varp.
+    ///
+    /// Returns 0 if its bits are out of order.
+    pub fn extract(&self, varp_value: u32) -> u32 {
+        let Some(span) = self.most_significant_bit.checked_sub(self.least_significant_bit) else { return 0 };
+        let mask = u32::MAX >> 31_u32.saturating_sub(span as u32);
+        varp_value.checked_shr(self.least_significant_bit as u32).unwrap_or(0) & mask
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> Self {
-        let mut unknown_1 = None;
-        let mut index = None;
-        let mut least_significant_bit = None;
-        let mut most_significant_bit = None;
+    pub(crate) fn deserialize(id: u32, mut buffer: Bytes, recovering: bool) -> Result<Self, ReadError> {
+        let mut varbit = Self { id, ..Default::default() };
+
+        #[cfg(debug_assertions)]
+        let mut opcodes = Vec::new();
 
         loop {
-            match buffer.get_u8() {
-                0 => {
-                    assert!(!buffer.has_remaining());
-                    break Self {
-                        id,
-                        unknown_1: unknown_1.expect("opcode 1 was not read"),
-                        index: index.expect("opcode 1 was not read"),
-                        least_significant_bit: least_significant_bit.expect("opcode 2 was not read"),
-                        most_significant_bit: most_significant_bit.expect("opcode 2 was not read"),
-                    };
+            let opcode = buffer.try_get_u8()?;
+
+            let read: Result<(), ReadError> = try {
+                match opcode {
+                    0 => {
+                        if buffer.has_remaining() {
+                            return Err(NotExhausted::new(buffer.remaining()));
+                        } else {
+                            break Ok(varbit);
+                        }
+                    }
+                    #[cfg(feature = "osrs")]
+                    1 => {
+                        varbit.index = buffer.try_get_u16()?;
+                        varbit.least_significant_bit = buffer.try_get_u8()?;
+                        varbit.most_significant_bit = buffer.try_get_u8()?;
+                    }
+                    #[cfg(not(feature = "osrs"))]
+                    1 => {
+                        varbit.unknown_1 = buffer.try_get_u8()?;
+                        varbit.index = buffer.try_get_u16()?;
+                    }
+                    #[cfg(not(feature = "osrs"))]
+                    2 => {
+                        varbit.least_significant_bit = buffer.try_get_u8()?;
+                        varbit.most_significant_bit = buffer.try_get_u8()?;
+                    }
+                    opcode => Err(ReadError::OpcodeNotImplemented {
+                        location: Location::caller(),
+                        opcode,
+                    })?,
                 }
-                1 => {
-                    unknown_1 = Some(buffer.get_u8());
-                    index = Some(buffer.get_u16());
+            };
+            match read {
+                Ok(()) => {
+                    #[cfg(debug_assertions)]
+                    opcodes.push(opcode);
                 }
-                2 => {
-                    least_significant_bit = Some(buffer.get_u8());
-                    most_significant_bit = Some(buffer.get_u8());
+                Err(e) => {
+                    varbit.decode_error = Some(
+                        Stopped {
+                            opcode,
+                            #[cfg(debug_assertions)]
+                            opcodes,
+                            buffer,
+                            thing: &varbit,
+                        }
+                        .recover(recovering, e)?,
+                    );
+                    break Ok(varbit);
                 }
-                opcode => unimplemented!("unknown varbit_config opcode {}", opcode),
             }
         }
     }
 }
 
+use std::fmt::{self, Display, Formatter};
+
+impl Display for VarbitConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string_pretty(&self).unwrap())
+    }
+}
+
 /// Save the varbit configs as `varbit_configs.json`. Exposed as `--dump varbit_configs`.
 pub fn export(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     let mut vb_configs = VarbitConfig::dump_all(config)?.into_values().collect::<Vec<_>>();
     vb_configs.sort_unstable_by_key(|loc| loc.id);
-
-    let path = path!(config.output / "varbit_configs.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-
-    let data = serde_json::to_string_pretty(&vb_configs).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-
-    Ok(())
+    crate::output::export(config, "varbit_configs", &vb_configs)
 }
 
 #[cfg(feature = "pyo3")]
@@ -107,3 +154,51 @@ impl VarbitConfig {
         Ok(format!("VarbitConfig({})", serde_json::to_string(self).unwrap()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract() {
+        let varbit = VarbitConfig {
+            least_significant_bit: 4,
+            most_significant_bit: 7,
+            ..Default::default()
+        };
+        assert_eq!(varbit.extract(0b1011_0110), 0b1011);
+
+        let whole = VarbitConfig {
+            least_significant_bit: 0,
+            most_significant_bit: 31,
+            ..Default::default()
+        };
+        assert_eq!(whole.extract(u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let varbit = VarbitConfig {
+            least_significant_bit: 7,
+            most_significant_bit: 4,
+            ..Default::default()
+        };
+        assert_eq!(varbit.extract(u32::MAX), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "osrs")]
+    fn decode() {
+        let varbit = VarbitConfig::deserialize(3, Bytes::from_static(&[1, 0, 42, 4, 7, 0]), false).unwrap();
+        assert_eq!((varbit.index, varbit.least_significant_bit, varbit.most_significant_bit), (42, 4, 7));
+
+        assert!(VarbitConfig::deserialize(3, Bytes::from_static(&[1, 0, 42, 0, 1]), false).is_err());
+        assert!(VarbitConfig::deserialize(3, Bytes::from_static(&[200, 0]), false).is_err());
+
+        let varbit = VarbitConfig::deserialize(3, Bytes::from_static(&[200, 0]), true).unwrap();
+        assert_eq!(
+            varbit.decode_error.as_deref(),
+            Some("stopped at opcode 200 with 1 bytes left: opcode 200 is not implemented")
+        );
+    }
+}
@@ -11,7 +11,7 @@ use ::error::Context;
 use bytes::{Buf, Bytes};
 use path_macro::path;
 use rs3cache_backend::{
-    buf::{BufExtra, JString},
+    buf::{BufExtra, JString, NotExhausted, ReadError},
     error::{self, CacheResult},
     index::CacheIndex,
 };
@@ -38,7 +38,7 @@ pub struct MapZone {
     id: u32,
     internal_name: JString<Bytes>,
     name: JString<Bytes>,
-    center: Coordinate,
+    center: Option<Coordinate>,
     unknown_1: u32,
     show: bool,
     default_zoom: u8,
@@ -49,32 +49,34 @@ pub struct MapZone {
 impl MapZone {
     /// Returns a mapping of all [`MapZone`] configurations.
     pub fn dump_all(config: &crate::cli::Config) -> CacheResult<HashMap<u32, Self>> {
-        Ok(CacheIndex::new(IndexType::WORLDMAP, config.input.clone())?
+        CacheIndex::new(IndexType::WORLDMAP, config.input.clone())?
             .archive(WorldMapType::ZONES)?
             .take_files()
             .into_iter()
-            .map(|(file_id, file)| (file_id, Self::deserialize(file_id, file)))
-            .collect())
+            .map(|(file_id, file)| try { (file_id, Self::deserialize(file_id, file)?) })
+            .collect::<Result<_, ReadError>>()
+            .context(error::Read { what: "map zones" })
     }
 
-    fn deserialize(id: u32, mut buffer: Bytes) -> Self {
-        let internal_name = buffer.get_string();
-        let name = buffer.get_string();
-        let center = buffer.get_u32().try_into().unwrap();
-        let unknown_1 = buffer.get_u32();
-        let show = match buffer.get_u8() {
-            0 => false,
-            1 => true,
-            other => unimplemented!("Cannot convert value {} for 'show' to boolean", other),
-        };
-        let default_zoom = buffer.get_u8();
-        let unknown_2 = buffer.get_u8();
-        let count = buffer.get_u8() as usize;
-        let bounds = iter::repeat_with(|| BoundDef::deserialize(&mut buffer)).take(count).collect();
-
-        debug_assert_eq!(buffer.remaining(), 0);
+    fn deserialize(id: u32, mut buffer: Bytes) -> Result<Self, ReadError> {
+        let internal_name = buffer.try_get_string()?;
+        let name = buffer.try_get_string()?;
+        // -1 if the zone has no centre
+        let center = buffer.try_get_u32()?.try_into().ok();
+        let unknown_1 = buffer.try_get_u32()?;
+        let show = buffer.try_get_u8()? == 1;
+        let default_zoom = buffer.try_get_u8()?;
+        let unknown_2 = buffer.try_get_u8()?;
+        let count = buffer.try_get_u8()? as usize;
+        let bounds = iter::repeat_with(|| BoundDef::deserialize(&mut buffer))
+            .take(count)
+            .collect::<Result<_, ReadError>>()?;
+
+        if buffer.has_remaining() {
+            return Err(NotExhausted::new(buffer.remaining()));
+        }
 
-        Self {
+        Ok(Self {
             id,
             internal_name,
             name,
@@ -84,7 +86,7 @@ impl MapZone {
             default_zoom,
             unknown_2,
             bounds,
-        }
+        })
     }
 
     /// Get a reference to the map zone's internal name.
@@ -97,8 +99,8 @@ impl MapZone {
         &self.name
     }
 
-    /// Get the map zone's center coordinate.
-    pub const fn center(&self) -> Coordinate {
+    /// Get the map zone's center coordinate, if it has one.
+    pub const fn center(&self) -> Option<Coordinate> {
         self.center
     }
 
@@ -130,7 +132,8 @@ impl MapZone {
 
 mod mapzone_fields_impl {
 
-    use bytes::{Buf, Bytes};
+    use bytes::Bytes;
+    use rs3cache_backend::buf::{BufExtra, ReadError};
     use serde::Serialize;
 
     #[derive(Debug, Serialize)]
@@ -141,11 +144,11 @@ mod mapzone_fields_impl {
     }
 
     impl BoundDef {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let plane = buffer.get_u8();
-            let src = Bound::deserialize(buffer);
-            let dst = Bound::deserialize(buffer);
-            Self { plane, src, dst }
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let plane = buffer.try_get_u8()?;
+            let src = Bound::deserialize(buffer)?;
+            let dst = Bound::deserialize(buffer)?;
+            Ok(Self { plane, src, dst })
         }
     }
 
@@ -159,13 +162,13 @@ mod mapzone_fields_impl {
     }
 
     impl Bound {
-        pub fn deserialize(buffer: &mut Bytes) -> Self {
-            let west = buffer.get_u16();
-            let south = buffer.get_u16();
-            let east = buffer.get_u16();
-            let north = buffer.get_u16();
+        pub fn deserialize(buffer: &mut Bytes) -> Result<Self, ReadError> {
+            let west = buffer.try_get_u16()?;
+            let south = buffer.try_get_u16()?;
+            let east = buffer.try_get_u16()?;
+            let north = buffer.try_get_u16()?;
 
-            Self { west, south, east, north }
+            Ok(Self { west, south, east, north })
         }
     }
 }
@@ -308,28 +311,16 @@ pub use mappaste_fields_impl::*;
 
 /// Exports all world map pastes to `out/map_pastes.json`.
 pub fn export_pastes(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
     // btreemap has deterministic order
     let map_pastes: BTreeMap<u32, MapPastes> = MapPastes::dump_all(config)?.into_iter().collect();
-
-    let path = path!(config.output / "map_pastes.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&map_pastes).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-    Ok(())
+    crate::output::put_json(config, "map_pastes.json", &map_pastes)
 }
 
-/// Exports all world map zones to `out/map_zones.json`.
+/// Exports all world map zones to `out/map_zones.json`. Exposed as `--dump map_zones`.
 pub fn export_zones(config: &crate::cli::Config) -> CacheResult<()> {
-    fs::create_dir_all(&config.output).with_context(|| error::Io { path: config.output.clone() })?;
-
     let mut map_zones = MapZone::dump_all(config)?.into_values().collect::<Vec<_>>();
     map_zones.sort_unstable_by_key(|loc| loc.id);
-    let path = path!(config.output / "map_zones.json");
-    let mut file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
-    let data = serde_json::to_string_pretty(&map_zones).unwrap();
-    file.write_all(data.as_bytes()).context(error::Io { path })?;
-    Ok(())
+    crate::output::export(config, "map_zones", &map_zones)
 }
 
 /// Exports small images of world maps to `out/world_map_small`.
@@ -368,3 +359,54 @@ pub fn dump_big(config: &crate::cli::Config) -> CacheResult<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(center: u32, bounds: u8) -> Vec<u8> {
+        let mut data = b"misthalin\0Misthalin\0".to_vec();
+        data.extend(center.to_be_bytes());
+        data.extend(7_u32.to_be_bytes());
+        data.extend([1, 3, 0, bounds]);
+        for _ in 0..bounds {
+            data.push(0);
+            for value in [50_u16, 50, 52, 53, 50, 50, 52, 53] {
+                data.extend(value.to_be_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn dump_zones() -> CacheResult<()> {
+        let config = crate::cli::Config::env();
+
+        MapZone::dump_all(&config)?;
+        Ok(())
+    }
+
+    #[test]
+    fn decode() {
+        let center = (3200 << 14) | 3200;
+        let zone = MapZone::deserialize(1, Bytes::from(zone(center, 1))).unwrap();
+        assert_eq!(zone.name(), "Misthalin");
+        assert_eq!(zone.center(), Some(Coordinate { plane: 0, x: 3200, y: 3200 }));
+        assert!(zone.show());
+        assert_eq!(zone.default_zoom(), 3);
+        assert_eq!(zone.bounds().len(), 1);
+    }
+
+    #[test]
+    fn no_center() {
+        let zone = MapZone::deserialize(1, Bytes::from(zone(u32::MAX, 0))).unwrap();
+        assert_eq!(zone.center(), None);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut data = zone(0, 1);
+        data.pop();
+        assert!(MapZone::deserialize(1, Bytes::from(data)).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! Compares the cache against an older one, passed with `--previous`.
+//!
+//! Groups are compared by the crc in their index's metadata, so nothing needs to be decoded to find what changed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use rs3cache_backend::{error::CacheResult, index::CacheIndex, path::CachePath};
+use serde::Serialize;
+
+/// The archives of an index that differ between two caches.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub added: BTreeSet<u32>,
+    pub changed: BTreeSet<u32>,
+    pub removed: BTreeSet<u32>,
+}
+
+impl Changes {
+    /// Compares the archives of index `index_id` of the cache at `previous` against those of `current`.
+    pub fn of_index(index_id: u32, previous: &Path, current: &CachePath) -> CacheResult<Self> {
+        let previous = CacheIndex::new(index_id, CachePath::Argument(previous.into()))?;
+        let current = CacheIndex::new(index_id, current.clone())?;
+
+        let crcs = |index: &CacheIndex<_>| -> BTreeMap<u32, i32> { index.metadatas().iter().map(|(id, meta)| (*id, meta.crc())).collect() };
+        Ok(Self::between(crcs(&previous), crcs(&current)))
+    }
+
+    /// Compares the crcs of two sets of archives.
+    fn between(previous: BTreeMap<u32, i32>, current: BTreeMap<u32, i32>) -> Self {
+        let mut changes = Self {
+            removed: previous.keys().copied().collect(),
+            ..Default::default()
+        };
+
+        for (id, crc) in current {
+            match previous.get(&id) {
+                Some(previous_crc) if *previous_crc == crc => {}
+                Some(_) => {
+                    changes.changed.insert(id);
+                }
+                None => {
+                    changes.added.insert(id);
+                }
+            }
+            changes.removed.remove(&id);
+        }
+        changes
+    }
+
+    /// The archives that are new or changed.
+    pub fn updated(&self) -> Vec<u32> {
+        self.added.union(&self.changed).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn between() {
+        let changes = Changes::between(BTreeMap::from([(1, 10), (2, 20), (3, 30)]), BTreeMap::from([(2, 20), (3, 31), (4, 40)]));
+        assert_eq!(changes.added, BTreeSet::from([4]));
+        assert_eq!(changes.changed, BTreeSet::from([3]));
+        assert_eq!(changes.removed, BTreeSet::from([1]));
+        assert_eq!(changes.updated(), vec![3, 4]);
+    }
+}
@@ -18,8 +18,15 @@
 //! get_npc_configs()
 //! get_item_configs()
 //! get_varbit_configs()
+//! get_inventory_configs()
+//! get_identkit_configs()
+//! get_varp_configs()
+//! get_varc_configs()
 //! get_struct_configs()
 //! get_enum_configs()
+//! get_param_configs()
+//! get_sequence_configs()
+//! get_revision()
 //! ```
 //! ## Classes
 //!
@@ -45,6 +52,10 @@ pub use sprites::*;
 
 #[cfg(feature = "rs3")]
 use crate::definitions::achievements::Achievement;
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::definitions::{
+    identkits::IdentityKit, inventories::Inventory, params::ParamConfig, revision::CacheRevision, sequences::Sequence, var_configs::VarConfig,
+};
 use crate::{
     cli::Config,
     definitions::{
@@ -60,8 +71,22 @@ pub fn initializer(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(get_npc_configs, m)?)?;
     m.add_function(wrap_pyfunction!(get_item_configs, m)?)?;
     m.add_function(wrap_pyfunction!(get_varbit_configs, m)?)?;
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    m.add_function(wrap_pyfunction!(get_inventory_configs, m)?)?;
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    m.add_function(wrap_pyfunction!(get_identkit_configs, m)?)?;
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    m.add_function(wrap_pyfunction!(get_varp_configs, m)?)?;
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    m.add_function(wrap_pyfunction!(get_varc_configs, m)?)?;
     m.add_function(wrap_pyfunction!(get_struct_configs, m)?)?;
     m.add_function(wrap_pyfunction!(get_enum_configs, m)?)?;
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    m.add_function(wrap_pyfunction!(get_param_configs, m)?)?;
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    m.add_function(wrap_pyfunction!(get_sequence_configs, m)?)?;
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    m.add_function(wrap_pyfunction!(get_revision, m)?)?;
     m.add_function(wrap_pyfunction!(hash_djb2, m)?)?;
 
     m.add_class::<PyMapSquares>()?;
@@ -149,6 +174,83 @@ pub fn get_varbit_configs(path: Option<PathBuf>) -> PyResult<BTreeMap<u32, Varbi
     Ok(VarbitConfig::dump_all(&config)?)
 }
 
+/// Wrapper for [`ParamConfig::dump_all`]
+#[pyfunction]
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn get_param_configs(path: Option<PathBuf>) -> PyResult<BTreeMap<u32, ParamConfig>> {
+    let mut config = Config::env();
+    if let Some(path) = path {
+        config.input = CachePath::Argument(path.into())
+    }
+    Ok(ParamConfig::dump_all(&config)?)
+}
+
+/// Wrapper for [`IdentityKit::dump_all`]
+#[pyfunction]
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn get_identkit_configs(path: Option<PathBuf>) -> PyResult<BTreeMap<u32, IdentityKit>> {
+    let mut config = Config::env();
+    if let Some(path) = path {
+        config.input = CachePath::Argument(path.into())
+    }
+    Ok(IdentityKit::dump_all(&config)?)
+}
+
+/// Wrapper for [`Inventory::dump_all`]
+#[pyfunction]
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn get_inventory_configs(path: Option<PathBuf>) -> PyResult<BTreeMap<u32, Inventory>> {
+    let mut config = Config::env();
+    if let Some(path) = path {
+        config.input = CachePath::Argument(path.into())
+    }
+    Ok(Inventory::dump_all(&config)?)
+}
+
+/// Wrapper for [`Sequence::dump_all`]
+#[pyfunction]
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn get_sequence_configs(path: Option<PathBuf>) -> PyResult<BTreeMap<u32, Sequence>> {
+    let mut config = Config::env();
+    if let Some(path) = path {
+        config.input = CachePath::Argument(path.into())
+    }
+    Ok(Sequence::dump_all(&config)?)
+}
+
+/// Wrapper for [`VarConfig::dump_varps`]
+#[pyfunction]
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn get_varp_configs(path: Option<PathBuf>) -> PyResult<BTreeMap<u32, VarConfig>> {
+    let mut config = Config::env();
+    if let Some(path) = path {
+        config.input = CachePath::Argument(path.into())
+    }
+    Ok(VarConfig::dump_varps(&config)?)
+}
+
+/// Wrapper for [`VarConfig::dump_varcs`]
+#[pyfunction]
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn get_varc_configs(path: Option<PathBuf>) -> PyResult<BTreeMap<u32, VarConfig>> {
+    let mut config = Config::env();
+    if let Some(path) = path {
+        config.input = CachePath::Argument(path.into())
+    }
+    Ok(VarConfig::dump_varcs(&config)?)
+}
+
+/// Wrapper for [`CacheRevision::dump`]
+#[pyfunction]
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub fn get_revision(path: Option<PathBuf>) -> PyResult<CacheRevision> {
+    let mut config = Config::env();
+    if let Some(path) = path {
+        config.input = CachePath::Argument(path.into())
+    }
+    Ok(CacheRevision::dump(&config)?)
+}
+
 #[pyfunction]
 pub fn hash_djb2(s: &str) -> i32 {
     rs3cache_backend::hash::hash_djb2(s)
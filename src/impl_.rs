@@ -1,5 +1,7 @@
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::Write,
     sync::Mutex,
     time::{Duration, Instant},
 };
@@ -9,7 +11,10 @@ use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::cli::{Config, Dump};
+use crate::{
+    cli::{Config, Dump, Filtering},
+    output::filter::Filter,
+};
 
 /// Entry point for the program. Run the executable with `--help` for a list of commands.
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -17,20 +22,82 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let start = Instant::now();
 
+    // Before anything else uses the global thread pool, which would otherwise start one thread per cpu.
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(threads) = config.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads as usize).build_global()?;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    crate::cancel::install();
+
+    // Every map would overwrite the tiles of the others.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "rs3"))]
+    if config.all_maps && !config.tile_template.has_map_id() {
+        return Err(format!(
+            "--all-maps needs a --tile-template with {{map_id}}, which {} does not have",
+            config.tile_template
+        )
+        .into());
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs", feature = "legacy")))]
+    let _progress = if config.progress == crate::cli::Progress::Json {
+        use crate::renderers::progress;
+        Some(match &config.progress_file {
+            Some(path) => progress::write_json(File::create(path)?),
+            None => progress::write_json(std::io::stderr()),
+        })
+    } else {
+        None
+    };
+
     #[cfg(all(feature = "rs3", not(feature = "mockdata"), not(feature = "save_mockdata")))]
     if config.assert_coherence {
         rs3cache_backend::index::assert_coherence(config.input.clone())?;
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if config.selftest && !crate::selftest::run(&config) {
+        return Err("selftest failed".into());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(command) = &config.command {
+        match command {
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            crate::cli::Command::Show(show) => crate::show::run(&config, show)?,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            crate::cli::Command::At(at) => crate::at::run(&config, at)?,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            crate::cli::Command::Schema(schema) => crate::schema::run(&config, schema)?,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            crate::cli::Command::Legend(legend) => crate::legend::run(&config, legend)?,
+            crate::cli::Command::Serve(serve) => crate::serve::run(&config, serve)?,
+        }
+        return Ok(());
+    }
+
     {
-        let mut to_dump = config.dump.clone();
+        // `all` and `configs` leave out the dumps that cannot be written in the chosen format.
+        let mut to_dump = config.dump.iter().flat_map(|dump| dump.expand(&config)).collect::<Vec<_>>();
 
-        if config.dump.contains(&Dump::Configs) {
-            to_dump.extend_from_slice(Dump::configs())
+        for dump in &to_dump {
+            if !dump.accepts(config.format) {
+                let format = config.format.to_possible_value().unwrap();
+                return Err(format!("--dump {dump} cannot be written as --format {}", format.get_name()).into());
+            }
         }
 
-        if config.dump.contains(&Dump::All) {
-            to_dump.extend_from_slice(Dump::value_variants())
+        if let Some(filter) = &config.filter {
+            for dump in &to_dump {
+                match dump.filtering() {
+                    Filtering::All => {}
+                    Filtering::Ids if matches!(filter, Filter::Ids(_)) => {}
+                    Filtering::Ids => return Err(format!("--dump {dump} can only be filtered by id").into()),
+                    Filtering::Nothing => return Err(format!("--dump {dump} cannot be filtered").into()),
+                }
+            }
         }
 
         let dump_sprites = to_dump.contains(&Dump::Sprites);
@@ -38,10 +105,37 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         #[cfg(feature = "rs3")]
         let dump_music = to_dump.contains(&Dump::Music);
 
-        #[cfg(feature = "rs3")]
+        #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+        let dump_models = to_dump.contains(&Dump::Models);
+
+        #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+        let dump_terrain = to_dump.contains(&Dump::Terrain3d);
+
+        #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+        let dump_npc_renders = to_dump.contains(&Dump::NpcRenders);
+
+        #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+        // Only `--dump sprites_diff` itself requires `--previous`, the others skip it when there is nothing to compare against.
+        let dump_sprites_diff = to_dump.contains(&Dump::SpritesDiff) && config.previous.is_some();
+
+        #[cfg(all(not(target_arch = "wasm32"), feature = "rs3"))]
+        let has_bars = [Dump::All, Dump::Configs, Dump::Sprites, Dump::SpritesDiff, Dump::Music];
+
+        #[cfg(all(target_arch = "wasm32", feature = "rs3"))]
         let has_bars = [Dump::All, Dump::Configs, Dump::Sprites, Dump::Music];
 
-        #[cfg(feature = "osrs")]
+        #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+        let has_bars = [
+            Dump::All,
+            Dump::Configs,
+            Dump::Sprites,
+            Dump::SpritesDiff,
+            Dump::Models,
+            Dump::Terrain3d,
+            Dump::NpcRenders,
+        ];
+
+        #[cfg(all(target_arch = "wasm32", feature = "osrs"))]
         let has_bars = [Dump::All, Dump::Configs, Dump::Sprites];
 
         #[cfg(feature = "legacy")]
@@ -67,6 +161,10 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             .into_par_iter()
             .map(|a| {
                 let name = a.as_str();
+                if cancelled() {
+                    progress.println(format!("    {} {name}", style("Skipped").yellow()));
+                    return Ok(());
+                }
                 {
                     let mut messages = messages.lock().unwrap();
                     messages.push(name);
@@ -100,21 +198,51 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             .collect::<Result<Vec<_>, _>>()?;
         progress.finish_and_clear();
 
-        if dump_sprites {
+        if dump_sprites && !cancelled() {
             Dump::Sprites.call()(&config)?;
         }
 
+        #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+        if dump_sprites_diff && !cancelled() {
+            Dump::SpritesDiff.call()(&config)?;
+        }
+
         #[cfg(feature = "rs3")]
-        if dump_music {
+        if dump_music && !cancelled() {
             Dump::Music.call()(&config)?;
         }
 
+        #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+        if dump_models && !cancelled() {
+            Dump::Models.call()(&config)?;
+        }
+
+        #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+        if dump_terrain && !cancelled() {
+            Dump::Terrain3d.call()(&config)?;
+        }
+
+        #[cfg(all(not(target_arch = "wasm32"), feature = "osrs"))]
+        if dump_npc_renders && !cancelled() {
+            Dump::NpcRenders.call()(&config)?;
+        }
+
         #[cfg(not(target_arch = "wasm32"))]
         for map in &(config.render) {
+            if cancelled() {
+                break;
+            }
             map.call(&config)?;
         }
     }
 
+    report_warnings(&config)?;
+    report_unknown_opcodes(&config)?;
+
+    if cancelled() {
+        println!("\n{} before finishing, output may be incomplete", style("Cancelled").yellow());
+    }
+
     let dt = start.elapsed();
 
     if dt > Duration::from_secs(1) {
@@ -125,3 +253,62 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Whether ctrl-C was pressed.
+fn cancelled() -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        crate::cancel::is_cancelled()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        false
+    }
+}
+
+/// Writes the unknown opcodes that `--capture-unknown-opcodes` ran into to `unknown_opcodes.json`, and summarizes them.
+fn report_unknown_opcodes(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let unknown = config.take_unknown_opcodes();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    crate::output::put_json(config, "unknown_opcodes.json", &unknown)?;
+    let count = unknown.iter().map(|unknown| unknown.count).sum::<usize>();
+    println!(
+        "\n{} left out {count} definitions with {} unknown opcodes, see unknown_opcodes.json",
+        style("Warning").yellow(),
+        unknown.len(),
+    );
+    Ok(())
+}
+
+/// Writes the warnings of this run to `warnings.jsonl`, and summarizes them.
+fn report_warnings(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let warnings = rs3cache_backend::warning::take();
+    if warnings.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = config.sink()?.create("warnings.jsonl")?;
+    let mut kinds = BTreeMap::new();
+    for warning in &warnings {
+        serde_json::to_writer(&mut file, warning)?;
+        writeln!(file)?;
+        *kinds.entry(warning.kind()).or_insert(0) += 1;
+    }
+    file.finish()?;
+
+    let summary = kinds
+        .into_iter()
+        .map(|(kind, count)| format!("{count} {kind}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "\n{} {} warnings ({summary}), see warnings.jsonl",
+        style("Warning").yellow(),
+        warnings.len(),
+    );
+    Ok(())
+}
@@ -0,0 +1,270 @@
+//! Generates a key to the map, as in `rs3 legend`.
+//!
+//! Writes `legend.png`, with the colours of walls and doors and every mapscene and map icon that locations place,
+//! each next to what it means, and `legend.json` with the same entries, for maps that draw their own key.
+//! As they are read from the cache, they are as current as the map itself.
+//!
+//! What an entry means is taken from the configs: the text of a map label, or else the most common name
+//! of the locations that place it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::Args;
+use image::{imageops, Rgba, RgbaImage};
+use rs3cache_backend::error::CacheResult;
+use rs3cache_utils::color::Color;
+use serde::Serialize;
+
+#[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
+use crate::definitions::mapscenes::MapScene;
+use crate::{
+    cli::Config,
+    definitions::{
+        fonts::{self, Font},
+        location_configs::LocationConfig,
+        maplabel_configs::{self, MapLabelConfig},
+        sprites::{self, Sprite},
+    },
+    renderers::{
+        map::{minimap, text, Style},
+        png,
+    },
+};
+
+/// Arguments of the `legend` subcommand.
+#[derive(Args, Clone, Debug)]
+pub struct Legend {
+    /// Leaves out mapscenes and icons that fewer than this many kinds of location place, to keep the key short.
+    #[clap(long, default_value_t = 1)]
+    pub min_locations: usize,
+}
+
+/// What an [`Entry`] is drawn as on the map.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Wall,
+    Door,
+    Mapscene,
+    Icon,
+}
+
+/// One line of the key.
+#[derive(Serialize, Clone, Debug)]
+pub struct Entry {
+    pub kind: Kind,
+    /// The id of the mapscene or map label.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    /// The id and frame of the sprite it is drawn with, as they are named by `--dump sprites`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<(u32, u32)>,
+    /// The colour it is drawn with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colour: Option<[u8; 4]>,
+    pub meaning: String,
+    /// How many kinds of location place it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locations: Option<usize>,
+}
+
+/// The space between and around the entries of the image, in pixels at scale 1.
+const PADDING: u32 = 4;
+
+/// The background of the image, dark so that white walls show.
+const BACKGROUND: [u8; 4] = [40, 40, 40, 255];
+
+/// The most common name of `locations`, leaving out those without one.
+fn most_common_name<'a>(locations: impl Iterator<Item = &'a LocationConfig>) -> Option<String> {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for name in locations.filter_map(|loc| loc.name.as_deref()) {
+        if !name.is_empty() && name != "null" {
+            *counts.entry(name).or_default() += 1;
+        }
+    }
+    // The first of the most common, so that ties are broken the same way every time.
+    let max = counts.values().copied().max()?;
+    counts.into_iter().find(|&(_, count)| count == max).map(|(name, _)| name.to_string())
+}
+
+/// The entries of the key, with the sprites they are drawn with.
+fn entries(config: &Config, legend: &Legend) -> CacheResult<(Vec<Entry>, BTreeMap<(u32, u32), Sprite>)> {
+    let render = &config.render_config;
+    let location_configs = LocationConfig::dump_all(config)?;
+    let palette = config.palette.clone().unwrap_or_default();
+
+    let (wall, door) = match render.style {
+        Style::Map => (Color::WHITE, Color::PURE_RED),
+        Style::Minimap => (minimap::WALL, minimap::DOOR),
+    };
+    let mut entries = vec![
+        Entry {
+            kind: Kind::Wall,
+            id: None,
+            sprite: None,
+            colour: Some(render.wall_colour.unwrap_or(palette.wall(wall).0)),
+            meaning: "Wall or fence".to_string(),
+            locations: None,
+        },
+        Entry {
+            kind: Kind::Door,
+            id: None,
+            sprite: None,
+            colour: Some(render.door_colour.unwrap_or(palette.door(door).0)),
+            meaning: "Door or gate".to_string(),
+            locations: None,
+        },
+    ];
+
+    // Mapscenes, by the locations that place them.
+    let mut placing = BTreeMap::<u32, Vec<&LocationConfig>>::new();
+    for loc in location_configs.values() {
+        if let Some(mapscene) = loc.mapscene {
+            placing.entry(mapscene as u32).or_default().push(loc);
+        }
+    }
+    #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
+    let mapscene_sprites = {
+        let mapscenes = MapScene::dump_all(config)?;
+        placing
+            .keys()
+            .filter_map(|id| Some((*id, (mapscenes.get(id)?.sprite_id?, 0))))
+            .collect::<BTreeMap<u32, (u32, u32)>>()
+    };
+    // 317 is the sprite named "mapscene", whose frames form all the mapscenes.
+    #[cfg(all(feature = "osrs", not(feature = "2009_1_shim")))]
+    let mapscene_sprites = placing.keys().map(|id| (*id, (317, *id))).collect::<BTreeMap<u32, (u32, u32)>>();
+
+    for (id, locations) in &placing {
+        let Some(&(sprite, frame)) = mapscene_sprites.get(id) else { continue };
+        if locations.len() < legend.min_locations {
+            continue;
+        }
+        entries.push(Entry {
+            kind: Kind::Mapscene,
+            id: Some(*id),
+            sprite: Some((sprite, frame)),
+            colour: None,
+            meaning: most_common_name(locations.iter().copied()).unwrap_or_else(|| format!("Mapscene {id}")),
+            locations: Some(locations.len()),
+        });
+    }
+
+    // Icons, by the locations that place them.
+    let map_labels = MapLabelConfig::dump_all(config)?;
+    let mut placing = BTreeMap::<u32, Vec<&LocationConfig>>::new();
+    for loc in location_configs.values() {
+        if let Some(label) = maplabel_configs::placed_by(loc) {
+            placing.entry(label).or_default().push(loc);
+        }
+    }
+    for (id, locations) in &placing {
+        let Some(label) = map_labels.get(id) else { continue };
+        let Some(sprite) = label.sprite else { continue };
+        if locations.len() < legend.min_locations {
+            continue;
+        }
+        let meaning = label
+            .text
+            .as_deref()
+            .map(|text| text.replace("<br>", " "))
+            .or_else(|| most_common_name(locations.iter().copied()))
+            .unwrap_or_else(|| format!("Map icon {id}"));
+        entries.push(Entry {
+            kind: Kind::Icon,
+            id: Some(*id),
+            sprite: Some((sprite, 0)),
+            colour: None,
+            meaning,
+            locations: Some(locations.len()),
+        });
+    }
+
+    let ids = entries.iter().filter_map(|entry| Some(entry.sprite?.0)).collect::<BTreeSet<u32>>();
+    let sprites = sprites::dumps(render.scale, ids.into_iter().collect(), config)?;
+
+    // Those whose sprites are missing would be drawn as nothing.
+    entries.retain(|entry| entry.sprite.map_or(true, |sprite| sprites.contains_key(&sprite)));
+    Ok((entries, sprites))
+}
+
+/// Draws `entries` below one another, each with its sprite or colour left of its meaning in `font`.
+fn draw(entries: &[Entry], sprites: &BTreeMap<(u32, u32), Sprite>, scale: u32, font: Option<&Font>) -> RgbaImage {
+    let padding = PADDING * scale;
+    let sprite_of = |entry: &Entry| sprites.get(&entry.sprite?);
+
+    let swatch = (8 * scale, 2 * scale);
+    let sprite_width = entries
+        .iter()
+        .map(|entry| sprite_of(entry).map_or(swatch.0, |sprite| sprite.width()))
+        .max()
+        .unwrap_or(0);
+    let text_width = entries.iter().map(|entry| text::size(&entry.meaning, scale, font).0).max().unwrap_or(0);
+    let heights = entries
+        .iter()
+        .map(|entry| {
+            let sprite = sprite_of(entry).map_or(swatch.1, |sprite| sprite.height());
+            sprite.max(text::size(&entry.meaning, scale, font).1)
+        })
+        .collect::<Vec<_>>();
+
+    let width = padding * 3 + sprite_width + text_width;
+    let height = padding + heights.iter().map(|height| height + padding).sum::<u32>();
+    let mut img = RgbaImage::from_pixel(width, height, Rgba(BACKGROUND));
+
+    let mut top = padding;
+    for (entry, height) in entries.iter().zip(heights) {
+        let center_b = top + height / 2;
+        match (sprite_of(entry), entry.colour) {
+            (Some(sprite), _) => {
+                let left = padding + (sprite_width - sprite.width()) / 2;
+                imageops::overlay(&mut img, sprite, left as i64, (center_b - sprite.height() / 2) as i64);
+            }
+            (None, Some(colour)) => {
+                let left = padding + (sprite_width - swatch.0) / 2;
+                let bar = RgbaImage::from_pixel(swatch.0, swatch.1, Rgba(colour));
+                imageops::overlay(&mut img, &bar, left as i64, (center_b - swatch.1 / 2) as i64);
+            }
+            (None, None) => {}
+        }
+        let (text_width, _) = text::size(&entry.meaning, scale, font);
+        let center_a = padding * 2 + sprite_width + text_width / 2;
+        text::draw(&mut img, &entry.meaning, center_a as i32, center_b as i32, scale, [255, 255, 255], font);
+        top += height + padding;
+    }
+    img
+}
+
+/// Writes `legend.png` and `legend.json`.
+pub fn run(config: &Config, legend: &Legend) -> CacheResult<()> {
+    let (entries, sprites) = entries(config, legend)?;
+    let font = Font::find(config, fonts::LABELS)?;
+    // Text at half the scale of sprites, as on the map.
+    let img = draw(&entries, &sprites, config.render_config.scale.max(2) / 2, font.as_ref());
+    png::put(config, "legend.png", &img)?;
+
+    crate::output::put_json(config, "legend.json", &entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drawing() {
+        let entries = [Kind::Wall, Kind::Door].map(|kind| Entry {
+            kind,
+            id: None,
+            sprite: None,
+            colour: Some([255, 0, 0, 255]),
+            meaning: "Bank".to_string(),
+            locations: None,
+        });
+        let img = draw(&entries, &BTreeMap::new(), 1, None);
+        let (text_width, text_height) = text::size("Bank", 1, None);
+        assert_eq!(img.dimensions(), (PADDING * 3 + 8 + text_width, PADDING * 3 + text_height * 2));
+        // The swatch is left of the text, centered on its line.
+        assert_eq!(img.get_pixel(PADDING, PADDING + text_height / 2), &Rgba([255, 0, 0, 255]));
+        assert_eq!(img.get_pixel(0, 0), &Rgba(BACKGROUND));
+    }
+}
@@ -45,18 +45,50 @@ pub mod definitions {
     pub mod achievements;
 
     pub mod dbrows;
+    pub mod dbtables;
 
     #[cfg(feature = "legacy")]
     pub mod flo;
 
+    /// Bitmap fonts, and their glyph atlases.
+    pub mod fonts;
+
+    /// The frames of animations, which pose models.
+    #[cfg(feature = "osrs")]
+    pub mod frames;
+
     /// Configuration of game locations.
     pub mod location_configs;
 
     /// Describes the id, position, type and rotation of game objects.
     pub mod locations;
 
+    /// Describes health bars.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod headbars;
+
+    /// Compresses and decompresses chat messages.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod huffman;
+
+    /// Describes hitmarks.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod hitmarks;
+
+    /// Describes the body parts of player appearances.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod identkits;
+
     pub mod indextype;
 
+    /// Describes the components of interfaces.
+    #[cfg(feature = "osrs")]
+    pub mod interfaces;
+
+    /// Describes inventories.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod inventories;
+
     pub mod item_configs;
 
     /// Configuration of npcs.
@@ -74,15 +106,55 @@ pub mod definitions {
 
     pub mod mapsquares;
 
+    /// Decodes 3d models.
+    #[cfg(feature = "osrs")]
+    pub mod models;
+
+    /// Extracts music as Ogg Vorbis files.
     #[cfg(feature = "rs3")]
     pub mod music;
 
+    /// Describes the keys of param tables.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod params;
+
+    /// Describes quickchat categories and phrases.
+    #[cfg(feature = "rs3")]
+    pub mod quickchat;
+
+    /// Describes quests and their requirements.
+    #[cfg(feature = "rs3")]
+    pub mod quests;
+
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod revision;
+
+    /// Describes animations.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod sequences;
+
+    /// Describes graphics such as spell effects.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod spotanims;
+
     /// Describes the colours of tiles.
     #[cfg(any(feature = "rs3", feature = "osrs"))]
     pub mod overlays;
     /// Images displayed by the game client.
     pub mod sprites;
 
+    /// Disassembles client scripts.
+    #[cfg(feature = "osrs")]
+    pub mod clientscripts;
+
+    /// Rebuilds MIDI files of music and jingles.
+    #[cfg(feature = "osrs")]
+    pub mod midi;
+
+    /// Decodes and synthesizes sound effects.
+    #[cfg(feature = "osrs")]
+    pub mod sound_effects;
+
     pub mod enums;
     pub mod structs;
     #[cfg(feature = "osrs")]
@@ -96,6 +168,10 @@ pub mod definitions {
 
     pub mod varbit_configs;
 
+    /// Configuration of player and client variables.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub mod var_configs;
+
     #[cfg(feature = "rs3")]
     pub mod worldmaps;
 }
@@ -103,16 +179,106 @@ pub mod definitions {
 /// Functions for rendering the map.
 #[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs", feature = "legacy")))]
 pub mod renderers {
+    /// Finds the mapsquares that changed since an older cache.
+    pub mod changed;
+
+    /// Compares rendered images against golden ones.
+    pub mod compare;
+
+    /// Writes rendered tiles as Deep Zoom images and IIIF services.
+    pub mod deepzoom;
+
+    /// Composites and downscales map tiles on the gpu.
+    #[cfg(feature = "gpu")]
+    pub mod gpu;
+
     /// Exports map tiles.
     pub mod map;
 
+    /// Renders npcs and their chatheads.
+    #[cfg(feature = "osrs")]
+    pub mod npcs;
+
+    /// Writes rendered tiles to disk.
+    pub mod png;
+
+    /// Reports the progress of the map renderer.
+    pub mod progress;
+
+    /// Reduces images to a palette, for indexed pngs.
+    pub mod quantize;
+
+    /// Records which mapsquares were rendered, for `--resume`.
+    pub mod resume;
+
     pub mod scale;
 
+    /// Renders images of placed locations.
+    #[cfg(feature = "osrs")]
+    pub mod scenery;
+
+    /// Assembles rendered tiles into one image per plane.
+    pub mod stitch;
+
+    /// Exports mapsquares as 3d scenes.
+    #[cfg(feature = "osrs")]
+    pub mod terrain;
+
+    /// Describes the content bounds of rendered tiles.
+    pub mod trim;
+
+    /// Writes a page to look at rendered maps with.
+    pub mod viewer;
+
     /// Creates successive tiles for different zoom levels,
     /// for use with a [leaflet.js](https://leafletjs.com/) based map.
     pub mod zoom;
 }
 
+/// Reports what is at a coordinate.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub mod at;
+
+/// Stops long runs cleanly on ctrl-C.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs", feature = "legacy")))]
+pub mod cancel;
+
+/// Compares the cache against an older one.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub mod diff;
+
+/// Generates a key to the map.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub mod legend;
+
+/// Writes JSON Schemas of dumped definitions.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub mod schema;
+
+/// Serves rendered tiles over HTTP.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs", feature = "legacy")))]
+pub mod serve;
+
+/// Prints single definitions.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs")))]
+pub mod show;
+
+/// Finds which definitions refer to which others.
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub mod xref;
+
+/// Reports how param keys are used across definitions.
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub mod params_usage;
+
+/// Checks whether this build can make sense of the cache.
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "rs3", feature = "osrs", feature = "legacy")))]
+pub mod selftest;
+
+/// Writes dumped definitions to disk.
+#[cfg(any(feature = "rs3", feature = "osrs", feature = "legacy"))]
+pub mod output;
+
 /// Contains structures that are used in multiple different configs.
 #[cfg(any(feature = "rs3", feature = "osrs", feature = "legacy"))]
 pub mod structures {
@@ -0,0 +1,154 @@
+pub mod canonical;
+pub mod csv;
+pub mod filter;
+#[cfg(feature = "mbtiles")]
+pub mod mbtiles;
+#[cfg(feature = "osrs")]
+pub mod mesh;
+pub mod naming;
+pub mod npy;
+#[cfg(feature = "arrow")]
+pub mod parquet;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pmtiles;
+pub mod predicate;
+pub mod sink;
+
+use std::{io::Write, path::PathBuf};
+
+use ::error::Context;
+use rs3cache_backend::error::{self, CacheResult};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::cli::{Config, Format};
+
+/// How much higher zoom levels are stored than the renderer's in tile archives, which have no negative zoom levels.
+/// MBTiles and PMTiles archives both use it, so they number the same tiles alike.
+///
+/// This is enough for tiles to fit in the `2^zoom` by `2^zoom` grid of their zoom level:
+/// the map is 200 mapsquares, or 200 tiles of zoom level 2, high.
+pub const ZOOM_OFFSET: i8 = 6;
+
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+type Resolver = crate::definitions::params::ParamResolver;
+#[cfg(not(any(feature = "rs3", feature = "osrs")))]
+type Resolver = std::convert::Infallible;
+
+/// Loads the param names and types, if `--resolve-params` was passed.
+#[cfg_attr(not(any(feature = "rs3", feature = "osrs")), allow(unused_variables))]
+fn resolver(config: &Config) -> CacheResult<Option<Resolver>> {
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    if config.resolve_params {
+        return Ok(Some(crate::definitions::params::ParamResolver::load(config)?));
+    }
+    Ok(None)
+}
+
+/// Serializes `item`, resolving its params if `resolver` is given.
+#[cfg_attr(not(any(feature = "rs3", feature = "osrs")), allow(unused_variables))]
+fn to_value<T: Serialize>(item: &T, resolver: Option<&Resolver>) -> Value {
+    #[allow(unused_mut)]
+    let mut item = serde_json::to_value(item).unwrap();
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    if let Some(resolver) = resolver {
+        resolver.resolve(&mut item);
+    }
+    item
+}
+
+/// Whether `item` matches `--where` and `--filter`.
+fn selected(config: &Config, item: &Value) -> bool {
+    config.predicate.as_ref().map_or(true, |predicate| predicate.matches(item)) && config.filter.as_ref().map_or(true, |filter| filter.matches(item))
+}
+
+/// Saves `items` as `{name}.json` in the output folder (or bucket),
+/// or in another format with `--format csv`, `parquet`, `msgpack`, `cbor` or `jsonl`.
+///
+/// If `--resolve-params` was passed, their params are typed and named first.
+/// If `--where` or `--filter` were passed, only the items that match them are saved.
+/// With `--deterministic`, they are put in [`canonical`] form first.
+pub fn export<T: Serialize>(config: &Config, name: &str, items: &[T]) -> CacheResult<()> {
+    if config.format == Format::Jsonl {
+        return export_iter(config, name, items.iter().map(Ok));
+    }
+    let resolver = resolver(config)?;
+
+    let (data, extension) = match (&config.predicate, &config.filter, &resolver, config.format) {
+        (None, None, None, format) if !format.is_tabular() && !config.deterministic => encode(items, format),
+        (_, _, resolver, format) => {
+            let mut items = items
+                .iter()
+                .map(|item| to_value(item, resolver.as_ref()))
+                .filter(|item| selected(config, item))
+                .collect::<Vec<_>>();
+            if config.deterministic {
+                items.iter_mut().for_each(canonical::canonicalize);
+                canonical::sort(&mut items);
+            }
+            match format {
+                Format::Csv => (csv::write(&items, &config.columns).into_bytes(), "csv"),
+                #[cfg(feature = "arrow")]
+                Format::Parquet => (parquet::write(&items, &config.columns, name)?, "parquet"),
+                Format::Json if config.deterministic => (canonical::to_vec(&items), "json"),
+                format => encode(&items, format),
+            }
+        }
+    };
+    config.sink()?.put(format!("{name}.{extension}"), &data)
+}
+
+/// Like [`export`], but takes the items as they are decoded, stopping at the first that failed to.
+///
+/// With `--format jsonl` they are written to `{name}.jsonl` one per line as they come,
+/// so memory use does not grow with the size of the dump. Other formats collect them first.
+pub fn export_iter<T: Serialize>(config: &Config, name: &str, items: impl IntoIterator<Item = CacheResult<T>>) -> CacheResult<()> {
+    if config.format != Format::Jsonl {
+        return export(config, name, &items.into_iter().collect::<CacheResult<Vec<_>>>()?);
+    }
+    let resolver = resolver(config)?;
+    let path = format!("{name}.jsonl");
+    let mut out = config.sink()?.create(&path)?;
+    for item in items {
+        let mut item = to_value(&item?, resolver.as_ref());
+        if config.deterministic {
+            canonical::canonicalize(&mut item);
+        }
+        if selected(config, &item) {
+            let mut line = serde_json::to_vec(&item).unwrap();
+            line.push(b'\n');
+            out.write_all(&line).with_context(|| error::Io { path: PathBuf::from(&path) })?;
+        }
+    }
+    out.finish()
+}
+
+/// Saves `value` as pretty json at `path` in the output folder (or bucket), for dumps that are a single document
+/// rather than a list of definitions. With `--deterministic`, it is put in [`canonical`] form first.
+pub fn put_json<T: Serialize>(config: &Config, path: impl AsRef<std::path::Path>, value: &T) -> CacheResult<()> {
+    config.sink()?.put(path, &to_json(config, value))
+}
+
+/// Formats `value` as pretty json, in [`canonical`] form with `--deterministic`.
+pub fn to_json<T: Serialize>(config: &Config, value: &T) -> Vec<u8> {
+    if config.deterministic {
+        let mut value = serde_json::to_value(value).unwrap();
+        canonical::canonicalize(&mut value);
+        canonical::to_vec(&value)
+    } else {
+        serde_json::to_vec_pretty(value).unwrap()
+    }
+}
+
+/// Serializes `items` in the given format, with its file extension. Json is the fallback for formats that are not for definitions.
+fn encode<T: Serialize>(items: &[T], format: Format) -> (Vec<u8>, &'static str) {
+    match format {
+        Format::Msgpack => (rmp_serde::to_vec_named(items).unwrap(), "msgpack"),
+        Format::Cbor => {
+            let mut data = Vec::new();
+            ciborium::ser::into_writer(items, &mut data).unwrap();
+            (data, "cbor")
+        }
+        _ => (serde_json::to_vec_pretty(items).unwrap(), "json"),
+    }
+}
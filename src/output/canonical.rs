@@ -0,0 +1,92 @@
+//! Puts dumps in a canonical form for `--deterministic`, so that dumps of two caches can be compared with `diff`.
+//!
+//! - Object keys are sorted. [`serde_json::Map`] already keeps them sorted, as the `preserve_order` feature is not enabled,
+//!   so this holds for anything that goes through a [`Value`].
+//! - Floats are rounded to [`DECIMALS`] decimals, and `-0.0` is written as `0.0`.
+//! - Definitions are sorted by id. Arrays within definitions keep their order, as it is meaningful there;
+//!   the actions of an item are indexed by their position, for example.
+//!   The exceptions are the fields in [`UNORDERED`], which are sets of ids and are sorted.
+//! - Json is pretty printed with two spaces and ends with a newline.
+
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+/// How many decimals floats are rounded to.
+pub const DECIMALS: i32 = 6;
+
+/// Fields whose arrays are sets, so that their order is meaningless.
+pub const UNORDERED: &[&str] = &[
+    "quests",
+    "required_quests",
+    "required_quest_ids",
+    "quest_req_for_miniquests",
+    "previous_achievements",
+    "var_transmit_triggers",
+    "inv_transmit_triggers",
+    "stat_transmit_triggers",
+];
+
+/// Rounds the floats of `value` and sorts the arrays of [`UNORDERED`] fields, recursively.
+pub fn canonicalize(value: &mut Value) {
+    match value {
+        Value::Number(n) if n.is_f64() => {
+            let scale = 10f64.powi(DECIMALS);
+            let rounded = (n.as_f64().unwrap() * scale).round() / scale;
+            // Adding zero turns -0.0 into 0.0.
+            if let Some(rounded) = Number::from_f64(rounded + 0.0) {
+                *n = rounded;
+            }
+        }
+        Value::Array(elements) => elements.iter_mut().for_each(canonicalize),
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                canonicalize(value);
+                match value {
+                    Value::Array(elements) if UNORDERED.contains(&key.as_str()) => {
+                        elements.sort_by_cached_key(|element| (element.as_i64(), element.to_string()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Sorts definitions by their id. Definitions without one go last, in the order of their json.
+pub fn sort(items: &mut [Value]) {
+    items.sort_by_cached_key(|item| match item.get("id").and_then(Value::as_i64) {
+        Some(id) => (false, id, String::new()),
+        None => (true, 0, item.to_string()),
+    });
+}
+
+/// Formats `value` as pretty json with a trailing newline.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    let mut data = serde_json::to_vec_pretty(value).unwrap();
+    data.push(b'\n');
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn canonical() {
+        let mut items = vec![
+            json!({"name": "b", "id": 2, "offset": -0.0, "scale": 0.1 + 0.2}),
+            json!({"id": 1, "actions": ["Open", null], "quests": [12, 3]}),
+        ];
+        items.iter_mut().for_each(canonicalize);
+        sort(&mut items);
+
+        assert_eq!(
+            String::from_utf8(to_vec(&items)).unwrap(),
+            "[\n  {\n    \"actions\": [\n      \"Open\",\n      null\n    ],\n    \"id\": 1,\n    \"quests\": [\n      3,\n      12\n    ]\n  },\n  \
+             {\n    \"id\": 2,\n    \"name\": \"b\",\n    \"offset\": 0.0,\n    \"scale\": 0.3\n  }\n]\n"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Writes dumps as CSV, for `--format csv`.
+//!
+//! Each definition is a row. The columns are picked with `--columns`, as in `--columns id,name,params.1397`,
+//! and are otherwise every top level field that any definition has, with `id` and `name` first.
+//! Missing fields are empty cells, and fields that are arrays or objects are written as json.
+
+use serde_json::Value;
+
+/// The default columns: every top level field of `items`.
+pub(super) fn columns(items: &[Value]) -> Vec<String> {
+    let mut columns = vec!["id".to_string(), "name".to_string()];
+    for item in items {
+        if let Value::Object(map) = item {
+            for key in map.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+    columns.retain(|column| items.iter().any(|item| item.get(column).is_some()));
+    columns
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value @ (Value::Bool(_) | Value::Number(_))) => value.to_string(),
+        Some(value) => serde_json::to_string(value).unwrap(),
+    }
+}
+
+fn escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Formats `items` as CSV, with a header row. Columns are dotted paths into each item, as in `params.1397`.
+pub fn write(items: &[Value], columns: &[String]) -> String {
+    let columns = if columns.is_empty() { self::columns(items) } else { columns.to_vec() };
+    let pointers = columns.iter().map(|column| format!("/{}", column.replace('.', "/"))).collect::<Vec<_>>();
+
+    let mut out = columns.iter().map(|column| escape(column)).collect::<Vec<_>>().join(",");
+    out.push_str("\r\n");
+    for item in items {
+        let row = pointers.iter().map(|pointer| escape(&cell(item.pointer(pointer)))).collect::<Vec<_>>();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn rows() {
+        let items = [
+            json!({"id": 4151, "name": "Abyssal whip", "params": {"1397": "a, b"}}),
+            json!({"id": 378, "actions": ["Open", null], "name": "Chest \"big\""}),
+        ];
+        assert_eq!(
+            write(&items, &[]),
+            "id,name,params,actions\r\n\
+             4151,Abyssal whip,\"{\"\"1397\"\":\"\"a, b\"\"}\",\r\n\
+             378,\"Chest \"\"big\"\"\",,\"[\"\"Open\"\",null]\"\r\n"
+        );
+        assert_eq!(
+            write(&items, &["name".to_string(), "params.1397".to_string()]),
+            "name,params.1397\r\nAbyssal whip,\"a, b\"\r\n\"Chest \"\"big\"\"\",\r\n"
+        );
+    }
+}
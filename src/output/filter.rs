@@ -0,0 +1,142 @@
+//! Narrows dumps down to a few definitions, as in `--filter 1000-2000`.
+//!
+//! A filter is one of:
+//! - Ids and id ranges, separated by commas, as in `4151` or `1000-2000,4151`.
+//! - Otherwise, a glob on the name, as in `"*portal*"`. `*` matches any text and `?` any character, ignoring case.
+//!
+//! Filtering on params or other fields is done with `--where`, as in `--where "params.1397 ~ 'abyss'"`;
+//! see [`predicate`](super::predicate). Filters that look like one of those are rejected rather than read as a name.
+
+use std::{ops::RangeInclusive, str::FromStr};
+
+use serde_json::Value;
+
+/// A parsed `--filter` argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Ids(Vec<RangeInclusive<u32>>),
+    Name(String),
+}
+
+impl Filter {
+    /// Whether the definition with this id passes, if the filter only looks at ids.
+    pub fn matches_id(&self, id: u32) -> Option<bool> {
+        match self {
+            Self::Ids(ranges) => Some(ranges.iter().any(|range| range.contains(&id))),
+            _ => None,
+        }
+    }
+
+    /// Whether `value`, a serialized definition, passes.
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            Self::Ids(_) => match value.get("id").and_then(Value::as_u64) {
+                Some(id) => self.matches_id(id as u32) == Some(true),
+                None => false,
+            },
+            Self::Name(pattern) => match value.get("name").and_then(Value::as_str) {
+                Some(name) => glob(pattern, name),
+                None => false,
+            },
+        }
+    }
+}
+
+/// Whether `text` matches `pattern`, ignoring case.
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+
+    // Where to resume after the last `*`, if a later character fails to match.
+    let mut backtrack = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn ids(s: &str) -> Option<Vec<RangeInclusive<u32>>> {
+    s.split(',')
+        .map(|part| {
+            let part = part.trim();
+            match part.split_once('-') {
+                Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+                None => {
+                    let id = part.parse().ok()?;
+                    Some(id..=id)
+                }
+            }
+        })
+        .collect()
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(ids) = ids(s) {
+            Ok(Self::Ids(ids))
+        } else if s.contains(['=', '<', '>', '~', '&', '|', '!']) {
+            Err("expressions are passed with `--where`".to_string())
+        } else if s.trim().is_empty() {
+            Err("expected ids or a name".to_string())
+        } else {
+            Ok(Self::Name(s.trim().to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        let portal = json!({"id": 1500, "name": "Portal", "params": {"1397": "Abyssal"}});
+        let chest = json!({"id": 378, "name": "Chest"});
+
+        let filter = "1000-2000,378".parse::<Filter>().unwrap();
+        assert_eq!(filter, Filter::Ids(vec![1000..=2000, 378..=378]));
+        assert!(filter.matches(&portal) && filter.matches(&chest));
+        assert_eq!(filter.matches_id(999), Some(false));
+
+        let filter = "*portal*".parse::<Filter>().unwrap();
+        assert!(filter.matches(&portal) && !filter.matches(&chest));
+        assert_eq!(filter.matches_id(1500), None);
+
+        assert!("params.1397 ~ 'abyss'".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("*portal*", "Portal (Abyss)"));
+        assert!(glob("ch?st", "Chest"));
+        assert!(glob("*a*b", "xaxxab"));
+        assert!(!glob("*a*b", "xaxxa"));
+        assert!(!glob("chest", "Chests"));
+    }
+}
@@ -0,0 +1,259 @@
+//! Stores rendered tiles in [MBTiles](https://github.com/mapbox/mbtiles-spec) files, for `--output-format mbtiles`.
+//!
+//! Every plane of a layer gets its own file, at `<layer>/<mapid>/<plane>.mbtiles`.
+//! Tiles are stored by the [TMS](https://wiki.osgeo.org/wiki/Tile_Map_Service_Specification) scheme that MBTiles uses,
+//! which matches the tile coordinates of the renderer: rows count from the south.
+//! MBTiles has no negative zoom levels, so zoom levels are stored [`ZOOM_OFFSET`] higher than they are rendered at.
+//! The coordinates are not web mercator; like the tile files these are meant for a simple, flat projection.
+//!
+//! Only tiles go into these files. Everything else, such as `manifest.json`, is written as usual.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use ::error::Context;
+use rs3cache_backend::error::{self, CacheResult};
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub use super::ZOOM_OFFSET;
+use crate::cli::TileFormat;
+
+/// A tile, as addressed by its path `<layer>/<mapid>/<zoom>/<plane>_<x>_<y>.<format>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TilePath {
+    /// The file the tile is stored in.
+    pub file: PathBuf,
+    pub zoom: i8,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TilePath {
+    /// Parses the path of a tile, or returns `None` if `relative` is not one.
+    pub fn parse(relative: &Path) -> Option<Self> {
+        let name = relative.file_name()?.to_str()?;
+        let name = name.strip_suffix(".png").or_else(|| name.strip_suffix(".webp"))?;
+        let zoom_folder = relative.parent()?;
+        let zoom = zoom_folder.file_name()?.to_str()?.parse::<i8>().ok()?;
+        let map_folder = zoom_folder.parent()?;
+        map_folder.file_name()?.to_str()?.parse::<i32>().ok()?;
+
+        let mut parts = name.split('_').map(str::parse::<i32>);
+        let (Some(Ok(plane)), Some(Ok(x)), Some(Ok(y)), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return None;
+        };
+        Some(Self {
+            file: map_folder.join(format!("{plane}.mbtiles")),
+            zoom,
+            x,
+            y,
+        })
+    }
+
+    /// If `relative` is a folder of tiles, `<layer>/<mapid>/<zoom>`, the folder of their files and the zoom level.
+    pub fn parse_folder(relative: &Path) -> Option<(PathBuf, i8)> {
+        let zoom = relative.file_name()?.to_str()?.parse::<i8>().ok()?;
+        let map_folder = relative.parent()?;
+        map_folder.file_name()?.to_str()?.parse::<i32>().ok()?;
+        Some((map_folder.to_path_buf(), zoom))
+    }
+}
+
+/// The MBTiles files of a folder, opened as they are needed.
+///
+/// Each file has its own lock, so tiles of different files are written at the same time.
+#[derive(Debug)]
+pub struct MbTiles {
+    folder: PathBuf,
+    format: TileFormat,
+    files: RwLock<HashMap<PathBuf, Arc<Mutex<Connection>>>>,
+}
+
+impl MbTiles {
+    /// Stores tiles of `format` in `folder`.
+    pub fn new(folder: PathBuf, format: TileFormat) -> Self {
+        Self {
+            folder,
+            format,
+            files: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Runs `f` on the file at `relative`, creating it if it does not exist.
+    fn with<T>(&self, relative: &Path, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> CacheResult<T> {
+        let path = self.folder.join(relative);
+        let connection = self.connection(relative, &path)?;
+        let connection = connection.lock().unwrap();
+        f(&connection).map_err(sql_error).context(error::Io { path })
+    }
+
+    /// The connection to the file at `path`, which is opened the first time it is needed.
+    fn connection(&self, relative: &Path, path: &Path) -> CacheResult<Arc<Mutex<Connection>>> {
+        if let Some(connection) = self.files.read().unwrap().get(path) {
+            return Ok(connection.clone());
+        }
+        let mut files = self.files.write().unwrap();
+        let connection = match files.entry(path.to_path_buf()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| error::Io { path: parent.to_path_buf() })?;
+                }
+                let connection = Connection::open(path).and_then(|connection| {
+                    create(&connection, relative, self.format)?;
+                    Ok(connection)
+                });
+                let connection = connection.map_err(sql_error).with_context(|| error::Io { path: path.to_path_buf() })?;
+                entry.insert(Arc::new(Mutex::new(connection)))
+            }
+        };
+        Ok(connection.clone())
+    }
+
+    /// Stores the encoded image `data` as the tile at `tile`.
+    pub fn put(&self, tile: &TilePath, data: &[u8]) -> CacheResult<()> {
+        self.with(&tile.file, |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                params![tile.zoom + ZOOM_OFFSET, tile.x, tile.y, data],
+            )?;
+            // Keep the zoom range in the metadata up to date, as consumers use it to limit zooming.
+            for (name, aggregate) in [("minzoom", "MIN"), ("maxzoom", "MAX")] {
+                connection.execute(
+                    &format!("INSERT OR REPLACE INTO metadata (name, value) SELECT ?1, {aggregate}(zoom_level) FROM tiles"),
+                    params![name],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Reads the tile at `tile`, if it exists.
+    pub fn get(&self, tile: &TilePath) -> CacheResult<Option<Vec<u8>>> {
+        if !self.folder.join(&tile.file).exists() {
+            return Ok(None);
+        }
+        self.with(&tile.file, |connection| {
+            connection
+                .query_row(
+                    "SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                    params![tile.zoom + ZOOM_OFFSET, tile.x, tile.y],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+    }
+
+    /// Lists the tiles of zoom level `zoom` in the files in `map_folder`, named as they would be as files.
+    pub fn list(&self, map_folder: &Path, zoom: i8) -> CacheResult<Vec<String>> {
+        let path = self.folder.join(map_folder);
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context(error::Io { path }),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry.with_context(|| error::Io { path: path.clone() })?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(plane) = file_name.strip_suffix(".mbtiles").and_then(|plane| plane.parse::<i32>().ok()) else { continue };
+
+            let tiles = self.with(&map_folder.join(&file_name), |connection| {
+                let mut statement = connection.prepare("SELECT tile_column, tile_row FROM tiles WHERE zoom_level = ?1")?;
+                let rows = statement.query_map(params![zoom + ZOOM_OFFSET], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)))?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()
+            })?;
+            names.extend(tiles.into_iter().map(|(x, y)| format!("{plane}_{x}_{y}.{}", self.format.extension())));
+        }
+        Ok(names)
+    }
+}
+
+/// Sets up the tables of a new file, named after its layer and plane.
+fn create(connection: &Connection, relative: &Path, format: TileFormat) -> rusqlite::Result<()> {
+    connection.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         CREATE TABLE IF NOT EXISTS metadata (name TEXT, value TEXT, UNIQUE (name));
+         CREATE TABLE IF NOT EXISTS tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB,
+             UNIQUE (zoom_level, tile_column, tile_row));",
+    )?;
+    let name = relative.with_extension("").to_string_lossy().replace('\\', "/");
+    let description = format!("Zoom levels are {ZOOM_OFFSET} higher than those of the tile files; coordinates are not web mercator.");
+    for (key, value) in [
+        ("name", name.as_str()),
+        ("format", format.extension()),
+        ("type", "baselayer"),
+        ("version", "1"),
+        ("description", description.as_str()),
+    ] {
+        connection.execute("INSERT OR IGNORE INTO metadata (name, value) VALUES (?1, ?2)", params![key, value])?;
+    }
+    Ok(())
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            TilePath::parse(Path::new("mapsquares/-1/-2/0_3_4.png")),
+            Some(TilePath {
+                file: PathBuf::from("mapsquares/-1/0.mbtiles"),
+                zoom: -2,
+                x: 3,
+                y: 4,
+            })
+        );
+        assert_eq!(TilePath::parse(Path::new("mapsquares/-1/manifest.json")), None);
+        assert_eq!(TilePath::parse(Path::new("map_icons/12.png")), None);
+        assert_eq!(
+            TilePath::parse_folder(Path::new("mapsquares/-1/3")),
+            Some((PathBuf::from("mapsquares/-1"), 3))
+        );
+    }
+
+    #[test]
+    fn round_trip() -> CacheResult<()> {
+        let folder = crate::output::sink::temp_folder("mbtiles");
+        let tiles = MbTiles::new(folder.clone(), TileFormat::Png);
+
+        let tile = TilePath::parse(Path::new("mapsquares/-1/2/1_50_50.png")).unwrap();
+        tiles.put(&tile, b"png")?;
+        assert_eq!(tiles.get(&tile)?.as_deref(), Some(&b"png"[..]));
+        assert_eq!(tiles.list(Path::new("mapsquares/-1"), 2)?, vec!["1_50_50.png".to_string()]);
+        assert_eq!(tiles.list(Path::new("mapsquares/-1"), 3)?, Vec::<String>::new());
+        // Stored the same way as in pmtiles archives.
+        let stored = tiles.with(&tile.file, |connection| {
+            connection.query_row("SELECT zoom_level FROM tiles", [], |row| row.get::<_, i8>(0))
+        })?;
+        assert_eq!(stored, 2 + crate::output::pmtiles::ZOOM_OFFSET);
+
+        // Planes are separate files, which are written to at the same time.
+        std::thread::scope(|scope| {
+            for plane in 0..4 {
+                let tiles = &tiles;
+                scope.spawn(move || {
+                    let tile = TilePath::parse(Path::new(&format!("mapsquares/-1/2/{plane}_1_1.png"))).unwrap();
+                    tiles.put(&tile, b"png").unwrap();
+                });
+            }
+        });
+        assert_eq!(tiles.list(Path::new("mapsquares/-1"), 2)?.len(), 5);
+
+        drop(tiles);
+        fs::remove_dir_all(folder).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! Writes [`Model`]s as glTF 2.0 binaries (`.glb`) or Wavefront OBJ files.
+//!
+//! Faces are coloured rather than vertices, so every face gets its own three vertices.
+//! Positions are scaled so that a tile is one unit wide, and rotated so that `y` points up.
+//! Textured faces are drawn in grey, as textures are not exported.
+
+use std::fmt::Write;
+
+use serde_json::json;
+
+use crate::definitions::models::{hsl_to_rgb, Model};
+
+/// The colour of textured faces.
+pub const TEXTURED: [u8; 3] = [128, 128, 128];
+
+/// The corners of a face, in tiles, and its rgba colour.
+pub type Triangle = ([[f32; 3]; 3], [u8; 4]);
+
+/// A face's corners and its rgba colour.
+pub fn triangles(model: &Model) -> impl Iterator<Item = Triangle> + '_ {
+    model.faces.iter().enumerate().filter_map(|(i, face)| {
+        let corners = face.map(|vertex| model.vertices.get(vertex as usize).copied());
+        let [Some(first), Some(second), Some(third)] = corners else { return None };
+
+        let textured = model.face_textures.as_ref().map_or(false, |textures| textures[i].is_some());
+        let [r, g, b] = if textured { TEXTURED } else { hsl_to_rgb(model.face_colours[i]) };
+        let alpha = model.face_alphas.as_ref().map_or(255, |alphas| 255 - alphas[i]);
+
+        // Rotating half a turn around the x axis keeps the winding of faces intact, unlike only flipping y.
+        let position = |[x, y, z]: [i32; 3]| [x as f32 / 128.0, -y as f32 / 128.0, -z as f32 / 128.0];
+        Some(([position(first), position(second), position(third)], [r, g, b, alpha]))
+    })
+}
+
+/// Formats `model` as an OBJ file, with vertex colours as `v x y z r g b`. Transparency is lost.
+pub fn obj(model: &Model) -> String {
+    let mut out = format!("# model {}\no model_{}\n", model.id, model.id);
+    let mut faces = String::new();
+
+    for (i, (corners, [r, g, b, _])) in triangles(model).enumerate() {
+        for [x, y, z] in corners {
+            writeln!(
+                out,
+                "v {x} {y} {z} {:.4} {:.4} {:.4}",
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0
+            )
+            .unwrap();
+        }
+        let first = i * 3 + 1;
+        writeln!(faces, "f {} {} {}", first, first + 1, first + 2).unwrap();
+    }
+
+    out.push_str(&faces);
+    out
+}
+
+/// Encodes `model` as a binary glTF 2.0 file, unless it has no faces.
+pub fn glb(model: &Model) -> Option<Vec<u8>> {
+    scene(&format!("model_{}", model.id), triangles(model))
+}
+
+/// Encodes `triangles` as a binary glTF 2.0 file, with a single mesh called `name`.
+///
+/// Returns `None` if there are no triangles, as glTF does not allow the empty buffer that mesh would need.
+pub fn scene(name: &str, triangles: impl IntoIterator<Item = Triangle>) -> Option<Vec<u8>> {
+    let mut positions = Vec::new();
+    let mut colours = Vec::new();
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    let mut transparent = false;
+
+    for (corners, colour) in triangles {
+        for corner in corners {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(corner[axis]);
+                max[axis] = max[axis].max(corner[axis]);
+            }
+            positions.extend(corner.iter().flat_map(|c| c.to_le_bytes()));
+            colours.extend(colour);
+        }
+        transparent |= colour[3] != 255;
+    }
+    let count = positions.len() / 12;
+    if count == 0 {
+        return None;
+    }
+
+    let mut bin = positions;
+    let colour_offset = bin.len();
+    bin.extend(&colours);
+    pad(&mut bin, 0);
+
+    let gltf = json!({
+        "asset": { "version": "2.0", "generator": "rs3cache" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "name": name }],
+        "meshes": [{
+            "primitives": [{
+                "attributes": { "POSITION": 0, "COLOR_0": 1 },
+                "material": 0,
+            }],
+        }],
+        "materials": [{
+            "pbrMetallicRoughness": { "metallicFactor": 0.0, "roughnessFactor": 1.0 },
+            "alphaMode": if transparent { "BLEND" } else { "OPAQUE" },
+            "doubleSided": true,
+        }],
+        "buffers": [{ "byteLength": bin.len() }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": colour_offset, "target": 34962 },
+            { "buffer": 0, "byteOffset": colour_offset, "byteLength": colours.len(), "target": 34962 },
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": count, "type": "VEC3", "min": min, "max": max },
+            { "bufferView": 1, "componentType": 5121, "normalized": true, "count": count, "type": "VEC4" },
+        ],
+    });
+    let mut json = serde_json::to_vec(&gltf).unwrap();
+    pad(&mut json, b' ');
+
+    let mut out = Vec::with_capacity(12 + 8 + json.len() + 8 + bin.len());
+    out.extend(b"glTF");
+    out.extend(2_u32.to_le_bytes());
+    out.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    out.extend((json.len() as u32).to_le_bytes());
+    out.extend(b"JSON");
+    out.extend(json);
+    out.extend((bin.len() as u32).to_le_bytes());
+    out.extend(b"BIN\0");
+    out.extend(bin);
+    Some(out)
+}
+
+/// Chunks of a glb file are aligned to four bytes.
+fn pad(data: &mut Vec<u8>, with: u8) {
+    while data.len() % 4 != 0 {
+        data.push(with);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Model {
+        Model {
+            id: 7,
+            vertices: vec![[0, 0, 0], [128, 0, 0], [128, 0, 128]],
+            faces: vec![[0, 1, 2]],
+            face_colours: vec![127],
+            face_alphas: Some(vec![0]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn obj_faces() {
+        let text = obj(&triangle());
+        assert_eq!(text.lines().filter(|line| line.starts_with("v ")).count(), 3);
+        assert!(text.contains("v 1 0 -1 "));
+        assert!(text.ends_with("f 1 2 3\n"));
+    }
+
+    #[test]
+    fn glb_layout() {
+        let data = glb(&triangle()).unwrap();
+        assert_eq!(&data[0..4], b"glTF");
+        assert_eq!(u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize, data.len());
+        assert_eq!(data.len() % 4, 0);
+
+        let json_len = u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize;
+        let gltf: serde_json::Value = serde_json::from_slice(&data[20..20 + json_len]).unwrap();
+        assert_eq!(gltf["accessors"][0]["count"], 3);
+        assert_eq!(gltf["materials"][0]["alphaMode"], "OPAQUE");
+    }
+
+    #[test]
+    fn empty() {
+        let model = Model { id: 2, ..Default::default() };
+        assert!(glb(&model).is_none());
+        assert!(scene("nothing", []).is_none());
+    }
+}
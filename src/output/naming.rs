@@ -0,0 +1,424 @@
+//! Names rendered tiles by a template, for `--tile-template` and `--tile-scheme`.
+//!
+//! The renderer names tiles `<layer>/<mapid>/<zoom>/<plane>_<x>_<y>.<ext>`, with rows counting from the south.
+//! A [`TileNaming`] translates those names to the ones of a template, such as `{plane}/{zoom}/{x}/{y}.{ext}`,
+//! so that the output fits the layout some web map stack expects. Everything that reads tiles back, such as the
+//! zoom levels and `--tile-manifest`, keeps using the renderer's names; only what ends up in the output changes.
+//!
+//! Templates are relative to the folder of the layer. With the [`Xyz`](TileScheme::Xyz) scheme rows count from the north instead,
+//! of a grid of `2^(zoom + 6)` rows; that is, 256 mapsquares high at zoom level 2.
+
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use rs3cache_backend::error::CacheResult;
+
+use crate::output::sink::Sink;
+
+/// Which way tile rows count.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[clap(rename_all = "snake_case")]
+pub enum TileScheme {
+    /// From the south, as the renderer does.
+    #[default]
+    Tms,
+    /// From the north, as most web map libraries expect.
+    Xyz,
+}
+
+/// A placeholder of a [`Template`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Var {
+    MapId,
+    Zoom,
+    Plane,
+    X,
+    Y,
+    Ext,
+}
+
+impl Var {
+    const ALL: [(&'static str, Var); 6] = [
+        ("map_id", Var::MapId),
+        ("zoom", Var::Zoom),
+        ("plane", Var::Plane),
+        ("x", Var::X),
+        ("y", Var::Y),
+        ("ext", Var::Ext),
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Var(Var),
+}
+
+/// A tile path template, as in `{map_id}/{zoom}/{plane}_{x}_{y}.{ext}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    /// The parts of every path component.
+    components: Vec<Vec<Part>>,
+}
+
+impl Template {
+    /// Whether tiles of different maps get different paths, which is needed to render more than one.
+    pub fn has_map_id(&self) -> bool {
+        self.components.iter().flatten().any(|part| *part == Part::Var(Var::MapId))
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self::from_str("{map_id}/{zoom}/{plane}_{x}_{y}.{ext}").unwrap()
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = Vec::new();
+        for component in s.split('/') {
+            if component.is_empty() || component == "." || component == ".." {
+                return Err(format!("{s:?} has an empty, \".\" or \"..\" path component"));
+            }
+
+            let mut parts = Vec::new();
+            let mut rest = component;
+            while !rest.is_empty() {
+                match rest.find('{') {
+                    Some(0) => {
+                        let end = rest.find('}').ok_or_else(|| format!("unclosed placeholder in {s:?}"))?;
+                        let name = &rest[1..end];
+                        let var = Var::ALL.iter().find(|(n, _)| *n == name).map(|(_, var)| *var).ok_or_else(|| {
+                            format!("unknown placeholder {{{name}}}, expected one of {{map_id}}, {{zoom}}, {{plane}}, {{x}}, {{y}} or {{ext}}")
+                        })?;
+                        if let Some(Part::Var(_)) = parts.last() {
+                            return Err(format!(
+                                "placeholders in {s:?} must be separated, as there is no telling where one number ends"
+                            ));
+                        }
+                        parts.push(Part::Var(var));
+                        rest = &rest[end + 1..];
+                    }
+                    Some(start) => {
+                        parts.push(Part::Literal(rest[..start].to_string()));
+                        rest = &rest[start..];
+                    }
+                    None => {
+                        parts.push(Part::Literal(rest.to_string()));
+                        rest = "";
+                    }
+                }
+            }
+            components.push(parts);
+        }
+
+        for (name, var) in Var::ALL {
+            let count = components.iter().flatten().filter(|part| **part == Part::Var(var)).count();
+            match var {
+                Var::Zoom | Var::Plane | Var::X | Var::Y if count != 1 => return Err(format!("{s:?} must have {{{name}}} exactly once")),
+                Var::MapId | Var::Ext if count > 1 => return Err(format!("{s:?} must have {{{name}}} at most once")),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            source: s.to_string(),
+            components,
+        })
+    }
+}
+
+/// Where a tile is, in the renderer's coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub map_id: i32,
+    pub zoom: i8,
+    pub plane: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Tile {
+    /// Parses the renderer's name of a tile, `<layer>/<mapid>/<zoom>/<plane>_<x>_<y>.<extension>`, into its layer folder and itself.
+    pub fn parse(relative: &Path, extension: &str) -> Option<(PathBuf, Self)> {
+        let name = relative.file_name()?.to_str()?.strip_suffix(extension)?.strip_suffix('.')?;
+        let mut parts = name.split('_').map(str::parse::<i32>);
+        let (Some(Ok(plane)), Some(Ok(x)), Some(Ok(y)), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return None;
+        };
+        let (layer, map_id, zoom) = parse_folder(relative.parent()?)?;
+        Some((layer, Self { map_id, zoom, plane, x, y }))
+    }
+}
+
+/// Parses a folder of tiles, `<layer>/<mapid>/<zoom>`.
+fn parse_folder(relative: &Path) -> Option<(PathBuf, i32, i8)> {
+    let zoom = relative.file_name()?.to_str()?.parse::<i8>().ok()?;
+    let map_folder = relative.parent()?;
+    let map_id = map_folder.file_name()?.to_str()?.parse::<i32>().ok()?;
+    let layer = map_folder.parent()?;
+    (!layer.as_os_str().is_empty()).then(|| (layer.to_path_buf(), map_id, zoom))
+}
+
+/// Translates the renderer's tile names to those of a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TileNaming {
+    pub template: Template,
+    pub scheme: TileScheme,
+    /// What `{ext}` stands for, and the extension of the renderer's names.
+    pub extension: &'static str,
+    /// The map id of tiles whose template has no `{map_id}`.
+    pub map_id: i32,
+}
+
+impl TileNaming {
+    /// Whether this names tiles as the renderer does, so nothing needs translating.
+    pub fn is_identity(&self) -> bool {
+        self.template == Template::default() && self.scheme == TileScheme::Tms
+    }
+
+    /// Flips rows between the two schemes; flipping twice gives back the row.
+    fn flip(&self, zoom: i8, y: i64) -> i64 {
+        match self.scheme {
+            TileScheme::Tms => y,
+            TileScheme::Xyz => (1i64 << (zoom as i64 + 6).max(0)) - 1 - y,
+        }
+    }
+
+    fn value(&self, var: Var, tile: &Tile) -> String {
+        match var {
+            Var::MapId => tile.map_id.to_string(),
+            Var::Zoom => tile.zoom.to_string(),
+            Var::Plane => tile.plane.to_string(),
+            Var::X => tile.x.to_string(),
+            Var::Y => self.flip(tile.zoom, tile.y as i64).to_string(),
+            Var::Ext => self.extension.to_string(),
+        }
+    }
+
+    fn component(&self, parts: &[Part], tile: &Tile) -> String {
+        parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                Part::Var(var) => self.value(*var, tile),
+            })
+            .collect()
+    }
+
+    /// The path of `tile` in the layer folder.
+    pub fn path(&self, tile: &Tile) -> PathBuf {
+        self.template.components.iter().map(|parts| self.component(parts, tile)).collect()
+    }
+
+    /// Translates the renderer's name of a tile, or returns `None` if `relative` is not one.
+    pub fn rename(&self, relative: &Path) -> Option<PathBuf> {
+        let (layer, tile) = Tile::parse(relative, self.extension)?;
+        Some(layer.join(self.path(&tile)))
+    }
+
+    /// Matches `name` against the parts of a path component, adding the numbers it has to `values`.
+    fn matches(&self, parts: &[Part], mut name: &str, values: &mut Vec<(Var, i64)>) -> bool {
+        for part in parts {
+            match part {
+                Part::Literal(literal) => match name.strip_prefix(literal.as_str()) {
+                    Some(rest) => name = rest,
+                    None => return false,
+                },
+                Part::Var(Var::Ext) => match name.strip_prefix(self.extension) {
+                    Some(rest) => name = rest,
+                    None => return false,
+                },
+                Part::Var(var) => {
+                    let digits = name.strip_prefix('-').unwrap_or(name);
+                    let len = name.len() - digits.len() + digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+                    let Ok(value) = name[..len].parse::<i64>() else { return false };
+                    // A placeholder can appear in more than one component, as long as it stands for the same number.
+                    if values.iter().any(|&(v, other)| v == *var && other != value) {
+                        return false;
+                    }
+                    values.push((*var, value));
+                    name = &name[len..];
+                }
+            }
+        }
+        name.is_empty()
+    }
+
+    fn tile(&self, values: &[(Var, i64)]) -> Option<Tile> {
+        let get = |var| values.iter().find(|(v, _)| *v == var).map(|(_, value)| *value);
+        let zoom = i8::try_from(get(Var::Zoom)?).ok()?;
+        Some(Tile {
+            map_id: get(Var::MapId).map_or(Some(self.map_id), |map_id| i32::try_from(map_id).ok())?,
+            zoom,
+            plane: i32::try_from(get(Var::Plane)?).ok()?,
+            x: i32::try_from(get(Var::X)?).ok()?,
+            y: i32::try_from(self.flip(zoom, get(Var::Y)?)).ok()?,
+        })
+    }
+
+    /// Parses a path in the layer folder made by [`path`](Self::path).
+    pub fn parse(&self, relative: &Path) -> Option<Tile> {
+        let names = relative.iter().map(|name| name.to_str()).collect::<Option<Vec<_>>>()?;
+        if names.len() != self.template.components.len() {
+            return None;
+        }
+        let mut values = Vec::new();
+        for (parts, name) in self.template.components.iter().zip(names) {
+            if !self.matches(parts, name, &mut values) {
+                return None;
+            }
+        }
+        self.tile(&values)
+    }
+
+    /// Lists the tiles of zoom level `zoom` of map `map_id` in the layer folder `layer` of `sink`,
+    /// as the names the renderer gives them in their folder, `<plane>_<x>_<y>.<extension>`.
+    fn list(&self, sink: &Sink, layer: &Path, map_id: i32, zoom: i8) -> CacheResult<Vec<String>> {
+        let known = vec![(Var::MapId, map_id as i64), (Var::Zoom, zoom as i64)];
+        let mut tiles = Vec::new();
+        self.walk(sink, layer.to_path_buf(), 0, known, &mut tiles)?;
+        Ok(tiles
+            .into_iter()
+            .filter(|tile| tile.map_id == map_id && tile.zoom == zoom)
+            .map(|Tile { plane, x, y, .. }| format!("{plane}_{x}_{y}.{}", self.extension))
+            .collect())
+    }
+
+    /// Descends into the folders that can have tiles, from the template component `depth` on.
+    fn walk(&self, sink: &Sink, folder: PathBuf, depth: usize, values: Vec<(Var, i64)>, tiles: &mut Vec<Tile>) -> CacheResult<()> {
+        let Some(parts) = self.template.components.get(depth) else {
+            tiles.extend(self.tile(&values));
+            return Ok(());
+        };
+        let is_known = |part: &Part| match part {
+            Part::Literal(_) | Part::Var(Var::Ext) => true,
+            Part::Var(var) => values.iter().any(|(v, _)| v == var),
+        };
+
+        // Components without unknown numbers do not need to be looked for.
+        let names = if parts.iter().all(is_known) && depth + 1 < self.template.components.len() {
+            let get = |var| values.iter().find(|(v, _)| *v == var).map_or(0, |(_, value)| *value);
+            let name = parts
+                .iter()
+                .map(|part| match part {
+                    Part::Literal(literal) => literal.clone(),
+                    Part::Var(Var::Ext) => self.extension.to_string(),
+                    Part::Var(var) => get(*var).to_string(),
+                })
+                .collect::<String>();
+            vec![name]
+        } else {
+            sink.list(&folder)?
+        };
+
+        for name in names {
+            let mut values = values.clone();
+            if self.matches(parts, &name, &mut values) {
+                self.walk(sink, folder.join(&name), depth + 1, values, tiles)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink {
+    /// Names tiles written to `inner` by `naming`, and writes everything else as usual.
+    pub fn with_naming(inner: Sink, naming: TileNaming) -> Self {
+        Self::Named {
+            inner: Box::new(inner),
+            naming,
+        }
+    }
+
+    /// For [`Sink::Named`], what the renderer's tile folder `relative` has in it.
+    pub(crate) fn list_named(inner: &Sink, naming: &TileNaming, relative: &Path) -> CacheResult<Vec<String>> {
+        match parse_folder(relative) {
+            Some((layer, map_id, zoom)) => naming.list(inner, &layer, map_id, zoom),
+            None => inner.list(relative),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naming(template: &str, scheme: TileScheme) -> TileNaming {
+        TileNaming {
+            template: template.parse().unwrap(),
+            scheme,
+            extension: "png",
+            map_id: -1,
+        }
+    }
+
+    #[test]
+    fn templates() {
+        assert!("{plane}/{zoom}/{x}/{y}.png".parse::<Template>().is_ok());
+        assert!("{zoom}/{x}{y}.png".parse::<Template>().is_err());
+        assert!("{zoom}/{x}/{y}/{x}.png".parse::<Template>().is_err());
+        assert!("{zoom}/{x}.png".parse::<Template>().is_err());
+        assert!("{zoom}/../{plane}_{x}_{y}.png".parse::<Template>().is_err());
+        assert!("{zoom}/{plane}_{x}_{z}.png".parse::<Template>().is_err());
+
+        assert!(Template::default().has_map_id());
+        assert!(!"{plane}/{zoom}/{x}/{y}.png".parse::<Template>().unwrap().has_map_id());
+    }
+
+    #[test]
+    fn renames() {
+        let identity = naming("{map_id}/{zoom}/{plane}_{x}_{y}.{ext}", TileScheme::Tms);
+        assert!(identity.is_identity());
+        assert_eq!(
+            identity.rename(Path::new("mapsquares/-1/-2/0_3_4.png")),
+            Some(PathBuf::from("mapsquares/-1/-2/0_3_4.png"))
+        );
+
+        let xyz = naming("{plane}/{zoom}/{x}/{y}.png", TileScheme::Xyz);
+        assert!(!xyz.is_identity());
+        let renamed = xyz.rename(Path::new("mapsquares/-1/2/1_50_60.png")).unwrap();
+        assert_eq!(renamed, PathBuf::from("mapsquares/1/2/50/195.png"));
+        assert_eq!(
+            xyz.parse(renamed.strip_prefix("mapsquares").unwrap()),
+            Some(Tile {
+                map_id: -1,
+                zoom: 2,
+                plane: 1,
+                x: 50,
+                y: 60
+            })
+        );
+        assert_eq!(xyz.rename(Path::new("mapsquares/-1/manifest.json")), None);
+    }
+
+    #[test]
+    fn lists() -> CacheResult<()> {
+        let folder = crate::output::sink::temp_folder("naming");
+        let naming = naming("{plane}/{zoom}/{x}/{y}.png", TileScheme::Xyz);
+        let sink = Sink::with_naming(Sink::Local(folder.clone()), naming);
+
+        sink.put("mapsquares/-1/2/1_50_60.png", b"png")?;
+        sink.put("mapsquares/-1/3/1_100_120.png", b"png")?;
+        assert_eq!(sink.get("mapsquares/-1/2/1_50_60.png")?.as_deref(), Some(&b"png"[..]));
+        assert_eq!(sink.list("mapsquares/-1/2")?, vec!["1_50_60.png".to_string()]);
+        assert_eq!(sink.list("mapsquares/-1/4")?, Vec::<String>::new());
+
+        std::fs::remove_dir_all(folder).unwrap();
+        Ok(())
+    }
+}
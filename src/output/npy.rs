@@ -0,0 +1,46 @@
+//! Writes NumPy `.npy` arrays, for `--format npy`.
+//!
+//! Arrays are little endian 32 bit integers in C order, in version 1.0 of the
+//! [format](https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html), which `numpy.load` reads.
+
+/// Encodes `data` as an `.npy` array of `shape`, whose product must be the length of `data`.
+pub fn write_i32(shape: &[usize], data: &[i32]) -> Vec<u8> {
+    debug_assert_eq!(shape.iter().product::<usize>(), data.len());
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        shape => format!("({})", shape.iter().map(usize::to_string).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '<i4', 'fortran_order': False, 'shape': {shape}, }}");
+    // The magic string, version and header length take 10 bytes, and the header is padded to a multiple of 64 with a newline at the end.
+    let padding = 63 - (10 + header.len()) % 64;
+    header.extend(std::iter::repeat(' ').take(padding));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(10 + header.len() + data.len() * 4);
+    out.extend_from_slice(b"\x93NUMPY\x01\x00");
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    for value in data {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrays() {
+        let out = write_i32(&[2, 3], &[0, 1, 2, 3, 4, -1]);
+        assert_eq!(&out[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([out[8], out[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&out[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<i4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(&out[10 + header_len..10 + header_len + 4], &0_i32.to_le_bytes());
+        assert_eq!(&out[out.len() - 4..], &(-1_i32).to_le_bytes());
+        assert_eq!(out.len(), 10 + header_len + 6 * 4);
+    }
+}
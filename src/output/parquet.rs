@@ -0,0 +1,95 @@
+//! Writes dumps as Apache Parquet, for `--format parquet` with the `arrow` feature.
+//!
+//! The columns are those of [`csv`](super::csv), and can be picked with `--columns` in the same way.
+//! A column is an integer, float or boolean column if every value in it is one, and a string column otherwise.
+//! Fields that are arrays or objects, such as params, are written as json strings.
+
+use std::{io, path::PathBuf, sync::Arc};
+
+use ::error::Context;
+use arrow::{
+    array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray},
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use parquet::arrow::ArrowWriter;
+use rs3cache_backend::error::{self, CacheResult};
+use serde_json::Value;
+
+fn column(values: &[Option<&Value>]) -> (DataType, ArrayRef) {
+    let present = || values.iter().flatten().filter(|value| !value.is_null());
+    if present().all(|value| value.is_i64()) {
+        let array = values.iter().map(|value| value.and_then(Value::as_i64)).collect::<Int64Array>();
+        (DataType::Int64, Arc::new(array))
+    } else if present().all(|value| value.is_number()) {
+        let array = values.iter().map(|value| value.and_then(Value::as_f64)).collect::<Float64Array>();
+        (DataType::Float64, Arc::new(array))
+    } else if present().all(|value| value.is_boolean()) {
+        let array = values.iter().map(|value| value.and_then(Value::as_bool)).collect::<BooleanArray>();
+        (DataType::Boolean, Arc::new(array))
+    } else {
+        let array = values
+            .iter()
+            .map(|value| match value {
+                None | Some(Value::Null) => None,
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(value) => Some(serde_json::to_string(value).unwrap()),
+            })
+            .collect::<StringArray>();
+        (DataType::Utf8, Arc::new(array))
+    }
+}
+
+/// Formats `items` as a Parquet file with a single row group. `name` is only used in errors.
+pub fn write(items: &[Value], columns: &[String], name: &str) -> CacheResult<Vec<u8>> {
+    let columns = if columns.is_empty() {
+        super::csv::columns(items)
+    } else {
+        columns.to_vec()
+    };
+
+    let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = columns
+        .iter()
+        .map(|column| {
+            let pointer = format!("/{}", column.replace('.', "/"));
+            let values = items.iter().map(|item| item.pointer(&pointer)).collect::<Vec<_>>();
+            let (data_type, array) = self::column(&values);
+            (Field::new(column, data_type, true), array)
+        })
+        .unzip();
+
+    let mut out = Vec::new();
+    let result: Result<(), parquet::errors::ParquetError> = try {
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+        let mut writer = ArrowWriter::try_new(&mut out, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+    };
+    result.map_err(|e| io::Error::new(io::ErrorKind::Other, e)).context(error::Io {
+        path: PathBuf::from(format!("{name}.parquet")),
+    })?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn types() {
+        let a = json!({"id": 1, "cost": 2.5, "members": true, "params": {"1": 2}});
+        let b = json!({"id": 2, "cost": 3, "name": "b"});
+        let values = [a.get("cost"), b.get("cost")];
+        assert_eq!(column(&values).0, DataType::Float64);
+        let values = [a.get("members"), b.get("members")];
+        assert_eq!(column(&values).0, DataType::Boolean);
+        let values = [a.get("params"), b.get("name")];
+        assert_eq!(column(&values).0, DataType::Utf8);
+
+        let file = write(&[a, b], &[], "test").unwrap();
+        assert_eq!(&file[..4], b"PAR1");
+    }
+}
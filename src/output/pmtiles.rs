@@ -0,0 +1,310 @@
+//! Packs rendered tiles into [PMTiles](https://github.com/protomaps/PMTiles) archives, for `--pmtiles`.
+//!
+//! A PMTiles archive is a single file that web map clients read with range requests,
+//! so a rendered map can be hosted on static storage, such as a bucket or GitHub Pages, without a tile server.
+//!
+//! Every plane of a layer gets its own archive, at `<layer>/<mapid>/<plane>.pmtiles`, next to the tiles they are made from.
+//! PMTiles numbers rows from the north and has no negative zoom levels, so rows are flipped
+//! and zoom levels are stored [`ZOOM_OFFSET`] higher than they are rendered at.
+//! The coordinates are not web mercator; like the tile files these are meant for a simple, flat projection.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    ops::RangeInclusive,
+    path::PathBuf,
+};
+
+use ::error::Context;
+use path_macro::path;
+use rs3cache_backend::error::{self, CacheResult};
+
+pub use super::ZOOM_OFFSET;
+use crate::cli::{Config, TileFormat};
+
+/// The size of the header of an archive.
+const HEADER_LEN: usize = 127;
+
+/// The header and root directory must fit in this many bytes, so clients can fetch both at once.
+const ROOT_LEN: usize = 16384;
+
+/// Where a tile is, in the order of the [Hilbert curve](https://en.wikipedia.org/wiki/Hilbert_curve) of each zoom level in turn.
+pub fn tile_id(z: u8, x: u32, y: u32) -> u64 {
+    // Tiles of lower zoom levels come first.
+    let mut id = ((1u64 << (2 * z as u64)) - 1) / 3;
+    let n = 1u64 << z;
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        id += s * s * ((3 * rx) ^ ry);
+        // Rotate the quadrant; only the bits below `s` matter from here on.
+        if ry == 0 {
+            if rx == 1 {
+                x = (n - 1).wrapping_sub(x);
+                y = (n - 1).wrapping_sub(y);
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    id
+}
+
+/// A run of tiles in a directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u32,
+    /// How many consecutive tiles have this data, or zero if this points to a leaf directory.
+    run_length: u32,
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Encodes a directory, uncompressed.
+fn directory(entries: &[Entry]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_varint(&mut buf, entries.len() as u64);
+    let mut last_id = 0;
+    for entry in entries {
+        put_varint(&mut buf, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        put_varint(&mut buf, entry.run_length as u64);
+    }
+    for entry in entries {
+        put_varint(&mut buf, entry.length as u64);
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        // Zero means the data directly follows that of the previous entry.
+        match i.checked_sub(1).map(|previous| &entries[previous]) {
+            Some(previous) if previous.offset + previous.length as u64 == entry.offset => put_varint(&mut buf, 0),
+            _ => put_varint(&mut buf, entry.offset + 1),
+        }
+    }
+    buf
+}
+
+/// Splits `entries` into a root directory and leaf directories, if they do not all fit in the root.
+fn directories(entries: &[Entry]) -> (Vec<u8>, Vec<u8>) {
+    let root = directory(entries);
+    if root.len() <= ROOT_LEN - HEADER_LEN {
+        return (root, Vec::new());
+    }
+
+    let mut leaf_size = 4096;
+    loop {
+        let mut leaves = Vec::new();
+        let mut root_entries = Vec::new();
+        for chunk in entries.chunks(leaf_size) {
+            let leaf = directory(chunk);
+            root_entries.push(Entry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u32,
+                run_length: 0,
+            });
+            leaves.extend(leaf);
+        }
+        let root = directory(&root_entries);
+        if root.len() <= ROOT_LEN - HEADER_LEN {
+            return (root, leaves);
+        }
+        leaf_size *= 2;
+    }
+}
+
+/// The counts and zoom levels that go in the header.
+struct Summary {
+    tiles: u64,
+    tile_type: u8,
+    min_zoom: u8,
+    max_zoom: u8,
+}
+
+fn header(root: &[u8], metadata: &[u8], leaves: &[u8], tile_data_len: u64, summary: &Summary) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    buf.extend(b"PMTiles");
+    buf.push(3);
+
+    let root_offset = HEADER_LEN as u64;
+    let metadata_offset = root_offset + root.len() as u64;
+    let leaves_offset = metadata_offset + metadata.len() as u64;
+    let tile_data_offset = leaves_offset + leaves.len() as u64;
+    for value in [
+        root_offset,
+        root.len() as u64,
+        metadata_offset,
+        metadata.len() as u64,
+        leaves_offset,
+        leaves.len() as u64,
+        tile_data_offset,
+        tile_data_len,
+        // Addressed tiles, tile entries and tile contents; every tile has its own entry and data.
+        summary.tiles,
+        summary.tiles,
+        summary.tiles,
+    ] {
+        buf.extend(value.to_le_bytes());
+    }
+
+    // Clustered, no internal compression, no tile compression.
+    buf.extend([1, 1, 1, summary.tile_type, summary.min_zoom, summary.max_zoom]);
+    // The bounds and center, which only mean something for web mercator maps.
+    for value in [-180_0000000i32, -85_0000000, 180_0000000, 85_0000000] {
+        buf.extend(value.to_le_bytes());
+    }
+    buf.push(summary.min_zoom);
+    buf.extend(0i32.to_le_bytes());
+    buf.extend(0i32.to_le_bytes());
+
+    debug_assert_eq!(buf.len(), HEADER_LEN);
+    buf
+}
+
+/// Packs the tiles of `zoom_levels` of the layer `name` of map `mapid` into an archive per plane.
+pub fn export(config: &Config, name: &str, mapid: i32, zoom_levels: RangeInclusive<i8>) -> CacheResult<()> {
+    let sink = config.sink()?;
+    let extension = config.tile_format.extension();
+    let tile_type = match config.tile_format {
+        TileFormat::Png => 2,
+        #[cfg(feature = "webp")]
+        TileFormat::Webp => 4,
+    };
+
+    // Tiles by plane, with their place in the archive.
+    let mut planes = std::collections::BTreeMap::<i32, Vec<(u64, PathBuf)>>::new();
+    let mut min_zoom = u8::MAX;
+    let mut max_zoom = 0;
+    for zoom in zoom_levels {
+        let folder = path!(name / format!("{mapid}/{zoom}"));
+        let z = (zoom + ZOOM_OFFSET) as u8;
+        for filename in sink.list(&folder)? {
+            let Some((plane, x, y)) = parse(&filename, extension) else { continue };
+            let row = (1u32 << z).checked_sub(1 + y);
+            let Some(row) = row.filter(|_| x < 1 << z) else { continue };
+            planes.entry(plane).or_default().push((tile_id(z, x, row), path!(folder / filename)));
+            (min_zoom, max_zoom) = (min_zoom.min(z), max_zoom.max(z));
+        }
+    }
+
+    for (plane, mut tiles) in planes {
+        tiles.sort_unstable_by_key(|(id, _)| *id);
+
+        // The tile data goes last, but the directories need to know where it is; it is staged in a temporary file.
+        let staging = std::env::temp_dir().join(format!("rs3cache-{}-{name}-{mapid}-{plane}.pmtiles.tmp", std::process::id()).replace('/', "_"));
+        let mut entries = Vec::with_capacity(tiles.len());
+        {
+            let mut staged = BufWriter::new(File::create(&staging).with_context(|| error::Io { path: staging.clone() })?);
+            let mut offset = 0;
+            for (tile_id, path) in &tiles {
+                let Some(data) = sink.get(path)? else { continue };
+                staged.write_all(&data).with_context(|| error::Io { path: staging.clone() })?;
+                entries.push(Entry {
+                    tile_id: *tile_id,
+                    offset,
+                    length: data.len() as u32,
+                    run_length: 1,
+                });
+                offset += data.len() as u64;
+            }
+            staged.flush().with_context(|| error::Io { path: staging.clone() })?;
+        }
+
+        let (root, leaves) = directories(&entries);
+        let metadata = serde_json::to_vec(&serde_json::json!({
+            "name": format!("{name} {mapid} plane {plane}"),
+            "description": format!("Rows count from the north, and zoom levels are {ZOOM_OFFSET} higher than those of the tile files."),
+            "zoom_offset": ZOOM_OFFSET,
+        }))
+        .unwrap();
+        let summary = Summary {
+            tiles: entries.len() as u64,
+            tile_type,
+            min_zoom,
+            max_zoom,
+        };
+        let tile_data_len = entries.last().map_or(0, |last| last.offset + last.length as u64);
+
+        let path = path!(name / format!("{mapid}/{plane}.pmtiles"));
+        let mut out = sink.create(&path)?;
+        let written: io::Result<()> = try {
+            out.write_all(&header(&root, &metadata, &leaves, tile_data_len, &summary))?;
+            out.write_all(&root)?;
+            out.write_all(&metadata)?;
+            out.write_all(&leaves)?;
+            io::copy(&mut File::open(&staging)?.take(tile_data_len), &mut out)?;
+        };
+        written.with_context(|| error::Io { path: path.clone() })?;
+        out.finish()?;
+        fs::remove_file(&staging).with_context(|| error::Io { path: staging.clone() })?;
+    }
+    Ok(())
+}
+
+/// Parses the name of a tile, `<plane>_<x>_<y>.<extension>`.
+pub(crate) fn parse(filename: &str, extension: &str) -> Option<(i32, u32, u32)> {
+    let mut parts = filename.strip_suffix(extension)?.strip_suffix('.')?.split('_');
+    let (Some(plane), Some(x), Some(y), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    Some((plane.parse().ok()?, x.parse().ok()?, y.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_ids() {
+        // From the examples of the specification.
+        assert_eq!(tile_id(0, 0, 0), 0);
+        assert_eq!(tile_id(1, 0, 0), 1);
+        assert_eq!(tile_id(1, 0, 1), 2);
+        assert_eq!(tile_id(1, 1, 1), 3);
+        assert_eq!(tile_id(1, 1, 0), 4);
+        assert_eq!(tile_id(2, 0, 0), 5);
+        assert_eq!(tile_id(12, 3423, 1763), 19078479);
+    }
+
+    #[test]
+    fn directories() {
+        let entries = [
+            Entry {
+                tile_id: 5,
+                offset: 0,
+                length: 10,
+                run_length: 1,
+            },
+            Entry {
+                tile_id: 7,
+                offset: 10,
+                length: 20,
+                run_length: 1,
+            },
+        ];
+        assert_eq!(directory(&entries), [2, 5, 2, 1, 1, 10, 20, 1, 0]);
+
+        let many = (0..100_000)
+            .map(|i| Entry {
+                tile_id: i,
+                offset: i * 100,
+                length: 100,
+                run_length: 1,
+            })
+            .collect::<Vec<_>>();
+        let (root, leaves) = super::directories(&many);
+        assert!(root.len() <= ROOT_LEN - HEADER_LEN);
+        assert!(!leaves.is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+//! A small expression language to filter dumps with, as in
+//! `--where "name~'altar' && interactable"`.
+//!
+//! Expressions are evaluated against the serialized form of a definition:
+//! - `field` is true if the field is present and not `false`, `0`, `""` or empty.
+//! - `field == value`, `!=`, `<`, `<=`, `>`, `>=` compare against a string, number, `true`, `false` or `null`.
+//! - `field ~ 'text'` is true if the field contains `text`, ignoring case.
+//! - Nested fields are accessed with a dot, as in `params.1234`.
+//! - If a field is an array, a comparison holds if it holds for any of its elements.
+//! - Expressions can be combined with `&&`, `||`, `!` and parentheses.
+
+use std::{fmt, str::FromStr};
+
+use serde_json::Value;
+
+/// A parsed `--where` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Or(Box<Predicate>, Box<Predicate>),
+    And(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+    Truthy(Vec<String>),
+    Compare(Vec<String>, Op, Literal),
+}
+
+/// A comparison operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+/// The right hand side of a comparison.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+impl Predicate {
+    /// Whether `value` satisfies this predicate.
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            Self::Or(lhs, rhs) => lhs.matches(value) || rhs.matches(value),
+            Self::And(lhs, rhs) => lhs.matches(value) && rhs.matches(value),
+            Self::Not(inner) => !inner.matches(value),
+            Self::Truthy(path) => lookup(value, path).map_or(false, truthy),
+            Self::Compare(path, op, literal) => match lookup(value, path) {
+                Some(Value::Array(elements)) => elements.iter().any(|element| compare(element, *op, literal)),
+                Some(value) => compare(value, *op, literal),
+                None => compare(&Value::Null, *op, literal),
+            },
+        }
+    }
+}
+
+fn lookup<'a>(mut value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    for segment in path {
+        value = match value {
+            Value::Object(map) => map.get(segment)?,
+            Value::Array(elements) => elements.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(elements) => !elements.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    match (value, literal) {
+        (_, _) if op == Op::Contains => {
+            let needle = match literal {
+                Literal::String(s) => s.to_lowercase(),
+                Literal::Number(n) => n.to_string(),
+                Literal::Bool(b) => b.to_string(),
+                Literal::Null => return false,
+            };
+            match value {
+                Value::String(s) => s.to_lowercase().contains(&needle),
+                Value::Number(n) => n.to_string().contains(&needle),
+                _ => false,
+            }
+        }
+        (Value::Null, Literal::Null) => matches!(op, Op::Eq | Op::Le | Op::Ge),
+        (_, Literal::Null) | (Value::Null, _) => op == Op::Ne,
+        (Value::Bool(lhs), Literal::Bool(rhs)) => ordering(lhs.cmp(rhs), op),
+        (Value::Number(lhs), Literal::Number(rhs)) => match lhs.as_f64().and_then(|lhs| lhs.partial_cmp(rhs)) {
+            Some(o) => ordering(o, op),
+            None => false,
+        },
+        (Value::String(lhs), Literal::String(rhs)) => ordering(lhs.as_str().cmp(rhs.as_str()), op),
+        _ => op == Op::Ne,
+    }
+}
+
+fn ordering(ordering: std::cmp::Ordering, op: Op) -> bool {
+    use std::cmp::Ordering::*;
+    match op {
+        Op::Eq => ordering == Equal,
+        Op::Ne => ordering != Equal,
+        Op::Lt => ordering == Less,
+        Op::Le => ordering != Greater,
+        Op::Gt => ordering == Greater,
+        Op::Ge => ordering != Less,
+        Op::Contains => unreachable!(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A run of digits, optionally negative. Kept apart from other literals,
+    /// as it can be a field name as well as (part of) a number.
+    Integer(String),
+    Literal(Literal),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Dot,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((pos, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '.' => Token::Dot,
+            '~' => Token::Op(Op::Contains),
+            '&' if chars.next_if(|(_, c)| *c == '&').is_some() => Token::And,
+            '|' if chars.next_if(|(_, c)| *c == '|').is_some() => Token::Or,
+            '=' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::Op(Op::Eq),
+            '!' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            quote @ ('\'' | '"') => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, c)) if c == quote => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => s.push(c),
+                            None => return Err(format!("unterminated string starting at {pos}")),
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => return Err(format!("unterminated string starting at {pos}")),
+                    }
+                }
+                Token::Literal(Literal::String(s))
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut s = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    s.push(c);
+                }
+                if s == "-" {
+                    return Err(format!("expected a number after `-` at {pos}"));
+                }
+                Token::Integer(s)
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut s = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    s.push(c);
+                }
+                match &*s {
+                    "true" => Token::Literal(Literal::Bool(true)),
+                    "false" => Token::Literal(Literal::Bool(false)),
+                    "null" => Token::Literal(Literal::Null),
+                    _ => Token::Ident(s),
+                }
+            }
+            other => return Err(format!("unexpected character {other:?} at {pos}")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Predicate, String> {
+        let mut lhs = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            lhs = Predicate::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Predicate, String> {
+        let mut lhs = self.unary()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            lhs = Predicate::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Predicate, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Predicate::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(inner),
+                    other => Err(format!("expected `)`, found {other:?}")),
+                }
+            }
+            Some(Token::Ident(first)) => {
+                let mut path = vec![first];
+                while self.tokens.next_if_eq(&Token::Dot).is_some() {
+                    match self.tokens.next() {
+                        Some(Token::Ident(segment) | Token::Integer(segment)) => path.push(segment),
+                        other => return Err(format!("expected a field name after `.`, found {other:?}")),
+                    }
+                }
+                match self.tokens.next_if(|token| matches!(token, Token::Op(_))) {
+                    Some(Token::Op(op)) => match self.tokens.next() {
+                        Some(Token::Literal(literal)) => Ok(Predicate::Compare(path, op, literal)),
+                        Some(Token::Integer(integer)) => Ok(Predicate::Compare(path, op, self.number(integer)?)),
+                        other => Err(format!("expected a value to compare with, found {other:?}")),
+                    },
+                    _ => Ok(Predicate::Truthy(path)),
+                }
+            }
+            other => Err(format!("expected a field, `!` or `(`, found {other:?}")),
+        }
+    }
+
+    /// Reads the fractional part of a number, if `integer` has one.
+    fn number(&mut self, mut integer: String) -> Result<Literal, String> {
+        if self.tokens.next_if_eq(&Token::Dot).is_some() {
+            match self.tokens.next() {
+                Some(Token::Integer(fraction)) if !fraction.starts_with('-') => {
+                    integer.push('.');
+                    integer.push_str(&fraction);
+                }
+                other => return Err(format!("expected digits after `{integer}.`, found {other:?}")),
+            }
+        }
+        let n = integer.parse().map_err(|_| format!("invalid number {integer:?}"))?;
+        Ok(Literal::Number(n))
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let predicate = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(predicate),
+            Some(token) => Err(format!("unexpected {token:?} after expression")),
+        }
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Or(lhs, rhs) => write!(f, "({lhs} || {rhs})"),
+            Self::And(lhs, rhs) => write!(f, "({lhs} && {rhs})"),
+            Self::Not(inner) => write!(f, "!{inner}"),
+            Self::Truthy(path) => write!(f, "{}", path.join(".")),
+            Self::Compare(path, op, literal) => {
+                let op = match op {
+                    Op::Eq => "==",
+                    Op::Ne => "!=",
+                    Op::Lt => "<",
+                    Op::Le => "<=",
+                    Op::Gt => ">",
+                    Op::Ge => ">=",
+                    Op::Contains => "~",
+                };
+                write!(f, "{} {op} ", path.join("."))?;
+                match literal {
+                    Literal::String(s) => write!(f, "{s:?}"),
+                    Literal::Number(n) => write!(f, "{n}"),
+                    Literal::Bool(b) => write!(f, "{b}"),
+                    Literal::Null => write!(f, "null"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn eval(expr: &str, value: &Value) -> bool {
+        expr.parse::<Predicate>().unwrap().matches(value)
+    }
+
+    #[test]
+    fn altar() {
+        let altar = json!({"id": 409, "name": "Altar", "actions": [null, "Pray-at"], "interactable": true});
+        let chest = json!({"id": 378, "name": "Chest", "actions": ["Open"]});
+
+        assert!(eval("name~'altar' && interactable", &altar));
+        assert!(!eval("name~'altar' && interactable", &chest));
+        assert!(eval("actions~'open' || id >= 400", &chest));
+        assert!(eval("actions~'open' || id >= 400", &altar));
+        assert!(eval("!interactable && id == 378", &chest));
+        assert!(eval("(id < 400) && actions.0 == \"Open\"", &chest));
+    }
+
+    #[test]
+    fn params() {
+        let item = json!({"id": 4151, "params": {"14": 2, "1397": "Abyssal"}});
+        assert!(eval("params.14 == 2", &item));
+        assert!(eval("params.1397 ~ 'abyss'", &item));
+        assert!(!eval("params.15", &item));
+        assert!(eval("params.15 == null", &item));
+    }
+
+    #[test]
+    fn nested() {
+        let item = json!({"params": {"14": {"foo": 1.5}}, "weight": -2.25});
+        assert_eq!(
+            "params.14.foo".parse::<Predicate>().unwrap(),
+            Predicate::Truthy(vec!["params".into(), "14".into(), "foo".into()])
+        );
+        assert!(eval("params.14.foo == 1.5", &item));
+        assert!(eval("weight < -2", &item));
+        assert!(eval("weight == -2.25", &item));
+        assert!("weight == 2.".parse::<Predicate>().is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!("name ~".parse::<Predicate>().is_err());
+        assert!("(id == 1".parse::<Predicate>().is_err());
+        assert!("id == 'unterminated".parse::<Predicate>().is_err());
+        assert!("id == 1 id".parse::<Predicate>().is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! Where output ends up.
+//!
+//! By default this is a folder, but with the `s3` feature `--output s3://bucket/prefix` uploads
+//! tiles and dumps straight to S3 compatible storage instead. The region and endpoint are read from
+//! `AWS_REGION` and `AWS_ENDPOINT`, so other providers such as GCS work by pointing `AWS_ENDPOINT` at them.
+//! Credentials are read from the usual environment variables or profile.
+//!
+//! With the `mbtiles` feature, `--output-format mbtiles` puts rendered tiles in [MBTiles](super::mbtiles) files
+//! rather than writing them one by one; other output is written as usual.
+//! Otherwise, `--tile-template` and `--tile-scheme` rename tiles as they are written, see [`naming`](super::naming).
+//!
+//! Not every exporter writes through this yet; sprites, music and the per-square and per-id dumps
+//! are always written to a local folder.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use ::error::Context;
+use rs3cache_backend::error::{self, CacheResult};
+#[cfg(feature = "s3")]
+use s3::{bucket::Bucket, creds::Credentials, region::Region};
+
+use crate::output::naming::TileNaming;
+#[cfg(feature = "mbtiles")]
+use crate::{
+    cli::TileFormat,
+    output::mbtiles::{MbTiles, TilePath},
+};
+
+/// The scheme that selects the S3 sink.
+#[cfg(feature = "s3")]
+const S3_SCHEME: &str = "s3://";
+
+/// Objects larger than this are sent as multipart uploads.
+#[cfg(feature = "s3")]
+const MULTIPART_THRESHOLD: usize = 8 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Sink {
+    /// Writes to a folder.
+    Local(PathBuf),
+    /// Uploads to a bucket, with `prefix` prepended to every key.
+    #[cfg(feature = "s3")]
+    S3 { bucket: Bucket, prefix: String },
+    /// Stores tiles in MBTiles files in a folder, and writes everything else to `inner`.
+    #[cfg(feature = "mbtiles")]
+    MbTiles { inner: Box<Sink>, tiles: MbTiles },
+    /// Names tiles by a template, and writes everything to `inner`.
+    Named { inner: Box<Sink>, naming: TileNaming },
+}
+
+impl Sink {
+    /// Picks a sink for `--output`.
+    pub fn new(output: &Path) -> CacheResult<Self> {
+        #[cfg(feature = "s3")]
+        if let Some(location) = output.to_str().and_then(|output| output.strip_prefix(S3_SCHEME)) {
+            let (name, prefix) = location.split_once('/').unwrap_or((location, ""));
+            let bucket: Result<Bucket, Box<dyn std::error::Error + Send + Sync>> = try {
+                let region = Region::from_default_env()?;
+                let credentials = Credentials::default()?;
+                Bucket::new(name, region, credentials)?
+            };
+            let bucket = bucket
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                .context(error::Io { path: output.to_path_buf() })?;
+
+            return Ok(Self::S3 {
+                bucket,
+                prefix: prefix.trim_end_matches('/').to_string(),
+            });
+        }
+
+        Ok(Self::Local(output.to_path_buf()))
+    }
+
+    /// Puts rendered tiles in MBTiles files in the folder of `inner`, for `--output-format mbtiles`.
+    #[cfg(feature = "mbtiles")]
+    pub fn with_mbtiles(inner: Sink, format: TileFormat) -> CacheResult<Self> {
+        match inner {
+            Self::Local(ref folder) => Ok(Self::MbTiles {
+                tiles: MbTiles::new(folder.clone(), format),
+                inner: Box::new(inner),
+            }),
+            #[allow(unreachable_patterns)]
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "mbtiles can only be written to a local folder",
+            ))
+            .context(error::Io { path: PathBuf::new() }),
+        }
+    }
+
+    /// Writes `data` to `relative`, creating folders as necessary.
+    pub fn put(&self, relative: impl AsRef<Path>, data: &[u8]) -> CacheResult<()> {
+        match self {
+            #[cfg(feature = "mbtiles")]
+            Self::MbTiles { inner, tiles } => match TilePath::parse(relative.as_ref()) {
+                Some(tile) => tiles.put(&tile, data),
+                None => inner.put(relative, data),
+            },
+            Self::Named { inner, naming } => match naming.rename(relative.as_ref()) {
+                Some(renamed) => inner.put(renamed, data),
+                None => inner.put(relative, data),
+            },
+            Self::Local(folder) => {
+                let path = folder.join(relative);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| error::Io { path: parent.to_path_buf() })?;
+                }
+                fs::write(&path, data).context(error::Io { path })
+            }
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => {
+                let key = Self::key(prefix, relative.as_ref());
+                // Tiles are uploaded in parallel by the renderer already; only large dumps are worth splitting up.
+                let response = if data.len() > MULTIPART_THRESHOLD {
+                    bucket.put_object_stream(&mut &*data, &key)
+                } else {
+                    bucket.put_object(&key, data).map(|response| response.status_code())
+                };
+                let status = response
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                    .context(error::Io { path: PathBuf::from(&key) })?;
+                match status {
+                    200..=299 => Ok(()),
+                    status => {
+                        Err(io::Error::new(io::ErrorKind::Other, format!("status code {status}"))).context(error::Io { path: PathBuf::from(key) })
+                    }
+                }
+            }
+        }
+    }
+
+    /// Opens `relative` for writing piece by piece, creating folders as necessary.
+    ///
+    /// Local files are written as they go; uploads are buffered and sent by [`SinkWriter::finish`].
+    pub fn create(&self, relative: impl AsRef<Path>) -> CacheResult<SinkWriter> {
+        match self {
+            #[cfg(feature = "mbtiles")]
+            Self::MbTiles { inner, .. } => inner.create(relative),
+            Self::Named { inner, naming } => match naming.rename(relative.as_ref()) {
+                Some(renamed) => inner.create(renamed),
+                None => inner.create(relative),
+            },
+            Self::Local(folder) => {
+                let path = folder.join(relative);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| error::Io { path: parent.to_path_buf() })?;
+                }
+                let file = File::create(&path).with_context(|| error::Io { path: path.clone() })?;
+                Ok(SinkWriter::Local(BufWriter::new(file), path))
+            }
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => Ok(SinkWriter::Buffered {
+                sink: Self::S3 {
+                    bucket: bucket.clone(),
+                    prefix: prefix.clone(),
+                },
+                relative: relative.as_ref().to_path_buf(),
+                data: Vec::new(),
+            }),
+        }
+    }
+
+    /// Reads `relative`, if it exists.
+    pub fn get(&self, relative: impl AsRef<Path>) -> CacheResult<Option<Vec<u8>>> {
+        match self {
+            #[cfg(feature = "mbtiles")]
+            Self::MbTiles { inner, tiles } => match TilePath::parse(relative.as_ref()) {
+                Some(tile) => tiles.get(&tile),
+                None => inner.get(relative),
+            },
+            Self::Named { inner, naming } => match naming.rename(relative.as_ref()) {
+                Some(renamed) => inner.get(renamed),
+                None => inner.get(relative),
+            },
+            Self::Local(folder) => {
+                let path = folder.join(relative);
+                match fs::read(&path) {
+                    Ok(data) => Ok(Some(data)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                    Err(e) => Err(e).context(error::Io { path }),
+                }
+            }
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => {
+                let key = Self::key(prefix, relative.as_ref());
+                let response = bucket
+                    .get_object(&key)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                    .context(error::Io { path: PathBuf::from(&key) })?;
+                match response.status_code() {
+                    200 => Ok(Some(response.bytes().to_vec())),
+                    404 => Ok(None),
+                    status => {
+                        Err(io::Error::new(io::ErrorKind::Other, format!("status code {status}"))).context(error::Io { path: PathBuf::from(key) })
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lists the names of the files in the folder `relative`, which may not exist.
+    pub fn list(&self, relative: impl AsRef<Path>) -> CacheResult<Vec<String>> {
+        match self {
+            #[cfg(feature = "mbtiles")]
+            Self::MbTiles { inner, tiles } => match TilePath::parse_folder(relative.as_ref()) {
+                Some((map_folder, zoom)) => tiles.list(&map_folder, zoom),
+                None => inner.list(relative),
+            },
+            Self::Named { inner, naming } => Self::list_named(inner, naming, relative.as_ref()),
+            Self::Local(folder) => {
+                let path = folder.join(relative);
+                let entries = match fs::read_dir(&path) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                    Err(e) => return Err(e).context(error::Io { path }),
+                };
+                entries
+                    .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+                    .collect::<Result<_, _>>()
+                    .context(error::Io { path })
+            }
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => {
+                let key = format!("{}/", Self::key(prefix, relative.as_ref()));
+                let pages = bucket
+                    .list(key.clone(), Some("/".to_string()))
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                    .context(error::Io { path: PathBuf::from(&key) })?;
+                Ok(pages
+                    .into_iter()
+                    .flat_map(|page| page.contents)
+                    .filter_map(|object| object.key.strip_prefix(&key).map(ToOwned::to_owned))
+                    .collect())
+            }
+        }
+    }
+
+    #[cfg(feature = "s3")]
+    fn key(prefix: &str, relative: &Path) -> String {
+        let relative = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if prefix.is_empty() {
+            relative
+        } else {
+            format!("{prefix}/{relative}")
+        }
+    }
+}
+
+/// A file being written to a [`Sink`], from [`Sink::create`].
+pub enum SinkWriter {
+    Local(BufWriter<File>, PathBuf),
+    #[cfg(feature = "s3")]
+    Buffered {
+        sink: Sink,
+        relative: PathBuf,
+        data: Vec<u8>,
+    },
+}
+
+impl SinkWriter {
+    /// Flushes or uploads what was written.
+    pub fn finish(self) -> CacheResult<()> {
+        match self {
+            Self::Local(mut file, path) => file.flush().context(error::Io { path }),
+            #[cfg(feature = "s3")]
+            Self::Buffered { sink, relative, data } => sink.put(relative, &data),
+        }
+    }
+}
+
+impl Write for SinkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Local(file, _) => file.write(buf),
+            #[cfg(feature = "s3")]
+            Self::Buffered { data, .. } => data.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Local(file, _) => file.flush(),
+            #[cfg(feature = "s3")]
+            Self::Buffered { .. } => Ok(()),
+        }
+    }
+}
+
+/// A folder in the temporary directory that nothing else, in this process or another, uses.
+#[cfg(any(test, all(feature = "webp", not(target_arch = "wasm32"))))]
+pub(crate) fn temp_folder(name: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("rs3cache_{name}_{}_{count}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local() -> CacheResult<()> {
+        let folder = temp_folder("sink_test");
+        let sink = Sink::new(&folder)?;
+
+        sink.put("a/b.json", b"[]")?;
+        assert_eq!(sink.get("a/b.json")?.as_deref(), Some(&b"[]"[..]));
+        assert_eq!(sink.get("a/c.json")?, None);
+        assert_eq!(sink.list("a")?, vec!["b.json".to_string()]);
+
+        let mut writer = sink.create("a/d.jsonl")?;
+        writer.write_all(b"{}\n").unwrap();
+        writer.finish()?;
+        assert_eq!(sink.get("a/d.jsonl")?.as_deref(), Some(&b"{}\n"[..]));
+
+        fs::remove_dir_all(folder).unwrap();
+        Ok(())
+    }
+}
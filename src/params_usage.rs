@@ -0,0 +1,183 @@
+//! Reports how each param key is used across all definitions, as a help in figuring out
+//! what unnamed keys mean. Exposed as `--dump params_usage`.
+//!
+//! For every key, this lists which definitions set it, the type of the values it holds,
+//! and how those values are distributed.
+
+use std::collections::BTreeMap;
+
+use rs3cache_backend::error::CacheResult;
+use serde::Serialize;
+
+use crate::{
+    cli::Config,
+    definitions::{
+        item_configs::ItemConfig, location_configs::LocationConfig, maplabel_configs::MapLabelConfig, npc_configs::NpcConfig, params::ParamConfig,
+        sequences::Sequence, structs::Struct,
+    },
+    structures::paramtable::{Param, ParamTable},
+};
+
+/// How many of the most common values of a key are listed.
+pub const COMMON: usize = 10;
+
+/// A value of a param, in a form that can be counted.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum Sample {
+    Integer(i32),
+    String(String),
+}
+
+impl From<&Param> for Sample {
+    fn from(param: &Param) -> Self {
+        match param {
+            Param::Integer(value) => Self::Integer(*value),
+            Param::String(value) => Self::String(value.to_string()),
+        }
+    }
+}
+
+/// How a param key is used.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// `integer`, `string`, or `mixed` if the key holds both.
+    pub value_type: &'static str,
+    /// The script type of the key, according to its [`ParamConfig`].
+    pub type_char: Option<char>,
+    /// How many definitions set it.
+    pub count: usize,
+    /// The ids of the definitions that set it, by their kind.
+    pub used_by: BTreeMap<&'static str, Vec<u32>>,
+    /// How many different values it has.
+    pub distinct: usize,
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+    /// Its most common values, with how often they occur.
+    pub common: Vec<(Sample, usize)>,
+}
+
+/// Counts the uses of param keys.
+#[derive(Clone, Debug, Default)]
+pub struct Tally {
+    keys: BTreeMap<u32, (BTreeMap<&'static str, Vec<u32>>, BTreeMap<Sample, usize>)>,
+}
+
+impl Tally {
+    /// Counts the params of the definition `kind:id`.
+    pub fn add(&mut self, kind: &'static str, id: u32, params: Option<&ParamTable>) {
+        for (key, value) in params.into_iter().flat_map(|table| &table.params) {
+            let (used_by, values) = self.keys.entry(*key).or_default();
+            used_by.entry(kind).or_default().push(id);
+            *values.entry(Sample::from(value)).or_default() += 1;
+        }
+    }
+
+    /// The usage of every key that was seen.
+    pub fn finish(self, configs: &BTreeMap<u32, ParamConfig>) -> BTreeMap<u32, Usage> {
+        self.keys
+            .into_iter()
+            .map(|(key, (used_by, values))| {
+                let integers = values.keys().filter_map(|value| match value {
+                    Sample::Integer(value) => Some(*value),
+                    Sample::String(_) => None,
+                });
+                let has_strings = values.keys().any(|value| matches!(value, Sample::String(_)));
+                let value_type = match (integers.clone().next().is_some(), has_strings) {
+                    (true, true) => "mixed",
+                    (false, true) => "string",
+                    _ => "integer",
+                };
+
+                let mut common = values.iter().map(|(value, count)| (value.clone(), *count)).collect::<Vec<_>>();
+                // Stable, so ties stay in the order of their values.
+                common.sort_by(|(_, a), (_, b)| b.cmp(a));
+                common.truncate(COMMON);
+
+                let usage = Usage {
+                    value_type,
+                    type_char: configs.get(&key).and_then(|config| config.type_char),
+                    count: values.values().sum(),
+                    used_by,
+                    distinct: values.len(),
+                    min: integers.clone().min(),
+                    max: integers.max(),
+                    common,
+                };
+                (key, usage)
+            })
+            .collect()
+    }
+}
+
+/// Decodes every definition type that has params, and counts how their keys are used.
+pub fn collect(config: &Config) -> CacheResult<BTreeMap<u32, Usage>> {
+    let mut tally = Tally::default();
+
+    for (id, item) in ItemConfig::dump_all(config)? {
+        tally.add("item", id, item.params.as_ref());
+    }
+    for (id, loc) in LocationConfig::dump_all(config)? {
+        tally.add("location", id, loc.params.as_ref());
+    }
+    for (id, npc) in NpcConfig::dump_all(config)? {
+        tally.add("npc", id, npc.params.as_ref());
+    }
+    for (id, r#struct) in Struct::dump_all(config)? {
+        tally.add("struct", id, r#struct.params.as_ref());
+    }
+    for (id, sequence) in Sequence::dump_all(config)? {
+        tally.add("sequence", id, sequence.params.as_ref());
+    }
+    for (id, label) in MapLabelConfig::dump_all(config)? {
+        tally.add("maplabel", id, label.params.as_ref());
+    }
+
+    Ok(tally.finish(&ParamConfig::dump_all(config)?))
+}
+
+/// Saves how every param key is used as `params_usage.json`. Exposed as `--dump params_usage`.
+pub fn export(config: &Config) -> CacheResult<()> {
+    crate::output::put_json(config, "params_usage.json", &collect(config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(params: impl IntoIterator<Item = (u32, Param)>) -> ParamTable {
+        ParamTable {
+            params: params.into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn usage() {
+        let mut tally = Tally::default();
+        tally.add(
+            "item",
+            1,
+            Some(&table([(14, Param::Integer(5)), (15, Param::String("a".to_string().into()))])),
+        );
+        tally.add("item", 2, Some(&table([(14, Param::Integer(5))])));
+        tally.add("npc", 3, Some(&table([(14, Param::Integer(-1)), (15, Param::Integer(2))])));
+        tally.add("npc", 4, None);
+
+        let usage = tally.finish(&BTreeMap::new());
+        assert_eq!(
+            usage[&14],
+            Usage {
+                value_type: "integer",
+                type_char: None,
+                count: 3,
+                used_by: BTreeMap::from([("item", vec![1, 2]), ("npc", vec![3])]),
+                distinct: 2,
+                min: Some(-1),
+                max: Some(5),
+                common: vec![(Sample::Integer(5), 2), (Sample::Integer(-1), 1)],
+            }
+        );
+        assert_eq!(usage[&15].value_type, "mixed");
+    }
+}
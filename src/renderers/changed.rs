@@ -0,0 +1,162 @@
+//! Finds the mapsquares that look different since an older cache, for `--render-changed`.
+//!
+//! A mapsquare is rendered again if
+//! - its tiles or locations changed, which is found by comparing the crcs of its archives,
+//! - it has tiles or locations whose definitions changed,
+//! - it has locations with a mapscene or map function, and any sprites changed,
+//! - or it is next to such a mapsquare, as mapsquares are drawn with parts of their neighbours.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use itertools::iproduct;
+use rs3cache_backend::{error::CacheResult, path::CachePath};
+use serde::Serialize;
+
+use crate::{
+    cli::Config,
+    definitions::mapsquares::{MapSquare, MapSquares},
+    renderers::map::Definitions,
+};
+
+/// Returns the coordinates of every mapsquare that has to be rendered again,
+/// because it looks different in the current cache than in the cache at `previous`.
+pub fn mapsquares(config: &Config, previous: &Path) -> CacheResult<BTreeSet<(u8, u8)>> {
+    let previous = config.for_cache(CachePath::Argument(previous.into()));
+
+    let current_squares = MapSquares::new(config)?;
+    let mut changed = changed_by(&MapSquares::new(&previous)?.crcs()?, &current_squares.crcs()?, PartialEq::eq);
+
+    let uses = Uses::between(&Definitions::load(&previous)?, &Definitions::load(config)?);
+    if !uses.is_empty() {
+        for square in current_squares {
+            let square = square?;
+            if uses.any_in(&square) {
+                changed.insert((square.i(), square.j()));
+            }
+        }
+    }
+
+    Ok(with_neighbours(&changed))
+}
+
+/// The ids of the entries that are only in one of `previous` and `current`, or are not the `same` in both.
+fn changed_by<K: Ord + Copy, T>(previous: &BTreeMap<K, T>, current: &BTreeMap<K, T>, same: impl Fn(&T, &T) -> bool) -> BTreeSet<K> {
+    let removed = previous.keys().filter(|key| !current.contains_key(key));
+    let updated = current.iter().filter_map(|(key, value)| match previous.get(key) {
+        Some(previous_value) if same(previous_value, value) => None,
+        _ => Some(key),
+    });
+    removed.chain(updated).copied().collect()
+}
+
+/// Compares the serialized forms of two definitions, for those that do not implement [`PartialEq`].
+fn same_json<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+fn with_neighbours(squares: &BTreeSet<(u8, u8)>) -> BTreeSet<(u8, u8)> {
+    squares
+        .iter()
+        .flat_map(|&(i, j)| iproduct!(-1..=1, -1..=1).map(move |(di, dj)| (i as i32 + di, j as i32 + dj)))
+        .filter_map(|(i, j)| Some((u8::try_from(i).ok()?, u8::try_from(j).ok()?)))
+        .collect()
+}
+
+/// The definitions that changed, by the ids that tiles and locations refer to them with.
+#[derive(Debug, Default)]
+struct Uses {
+    locations: BTreeSet<u32>,
+    overlays: BTreeSet<u32>,
+    underlays: BTreeSet<u32>,
+}
+
+impl Uses {
+    fn between(previous: &Definitions, current: &Definitions) -> Self {
+        let mut locations = changed_by(&previous.location_configs, &current.location_configs, same_json);
+
+        #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
+        {
+            let mapscenes = changed_by(&previous.mapscenes, &current.mapscenes, PartialEq::eq);
+            locations.extend(
+                current
+                    .location_configs
+                    .values()
+                    .filter(|loc| loc.mapscene.map_or(false, |mapscene| mapscenes.contains(&(mapscene as u32))))
+                    .map(|loc| loc.id),
+            );
+        }
+
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        let (overlays, underlays) = (
+            changed_by(&previous.overlays, &current.overlays, same_json),
+            changed_by(&previous.underlays, &current.underlays, same_json),
+        );
+
+        // Flos are both the overlays and underlays of these caches.
+        #[cfg(feature = "legacy")]
+        let (overlays, underlays) = {
+            let flos = changed_by(&previous.flos, &current.flos, same_json);
+            (flos.clone(), flos)
+        };
+
+        // Which sprite a location is drawn with is too different between games to follow, so all icons are drawn again.
+        if !changed_by(&previous.sprites, &current.sprites, PartialEq::eq).is_empty() {
+            locations.extend(
+                current
+                    .location_configs
+                    .values()
+                    .filter(|loc| loc.mapscene.is_some() || loc.mapfunction.is_some())
+                    .map(|loc| loc.id),
+            );
+        }
+
+        Self {
+            locations,
+            overlays,
+            underlays,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.locations.is_empty() && self.overlays.is_empty() && self.underlays.is_empty()
+    }
+
+    /// Whether `square` has anything that looks different because of these changes.
+    fn any_in(&self, square: &MapSquare) -> bool {
+        // Tiles refer to overlays and underlays by their id plus one.
+        let uses = |changed: &BTreeSet<u32>, id: Option<u16>| id.map_or(false, |id| id > 0 && changed.contains(&(id as u32 - 1)));
+        let tiles = square.tiles().map_or(false, |tiles| {
+            tiles
+                .iter()
+                .any(|tile| uses(&self.overlays, tile.overlay_id) || uses(&self.underlays, tile.underlay_id))
+        });
+
+        let locations = square
+            .locations()
+            .map_or(false, |locations| locations.iter().any(|loc| self.locations.contains(&loc.id)));
+
+        tiles || locations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes() {
+        let previous = BTreeMap::from([((50, 50), vec![1]), ((50, 51), vec![2]), ((60, 60), vec![3])]);
+        let current = BTreeMap::from([((50, 50), vec![1]), ((50, 51), vec![4]), ((70, 70), vec![5])]);
+        assert_eq!(
+            changed_by(&previous, &current, PartialEq::eq),
+            BTreeSet::from([(50, 51), (60, 60), (70, 70)])
+        );
+
+        let squares = with_neighbours(&BTreeSet::from([(0, 10)]));
+        assert_eq!(squares.len(), 6);
+        assert!(squares.contains(&(1, 11)) && !squares.contains(&(0, 12)));
+    }
+}
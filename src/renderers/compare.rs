@@ -0,0 +1,127 @@
+//! Compares rendered images against golden ones, to notice when the renderer changes its output.
+//!
+//! ```no_run
+//! # use rs3cache::{cli::Config, renderers::{compare, map}};
+//! let config = Config::env();
+//! let region = "lumbridge".parse::<map::Region>()?;
+//! let planes = map::render_to_images(&config, &region)?;
+//! let difference = compare::compare_golden(&planes[0], "tests/golden/lumbridge_0.png", 2)?;
+//! assert!(difference.is_none(), "{difference:?}");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{fmt, io, path::Path};
+
+use ::error::Context;
+use image::{ImageError, Rgba, RgbaImage};
+use rs3cache_backend::error::{self, CacheResult};
+
+/// The colour of pixels that differ, in difference images.
+pub const DIFFERENT: [u8; 4] = [255, 0, 0, 255];
+
+/// How an image differs from what was expected.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// How many pixels differ by more than the tolerance, including those that only one of the images has.
+    pub pixels: u64,
+    /// The biggest difference in any channel of any pixel.
+    pub max: u8,
+    /// The expected image, faded, with the pixels that differ in [`DIFFERENT`].
+    pub image: RgbaImage,
+}
+
+impl fmt::Debug for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Difference")
+            .field("pixels", &self.pixels)
+            .field("max", &self.max)
+            .field("dimensions", &self.image.dimensions())
+            .finish()
+    }
+}
+
+/// Compares `actual` against `expected`, where channels may differ by up to `tolerance`.
+///
+/// Returns `None` if they are the same, within the tolerance. Images of different sizes always differ.
+pub fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> Option<Difference> {
+    let (width, height) = (actual.width().max(expected.width()), actual.height().max(expected.height()));
+    let (mut pixels, mut max) = (0, 0);
+
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        let get = |img: &RgbaImage| img.get_pixel_checked(x, y).map(|pixel| pixel.0);
+        match (get(actual), get(expected)) {
+            (Some(a), Some(e)) => {
+                let difference = a.iter().zip(e).map(|(a, e)| a.abs_diff(e)).max().unwrap_or(0);
+                max = max.max(difference);
+                if difference > tolerance {
+                    pixels += 1;
+                    Rgba(DIFFERENT)
+                } else {
+                    // Faded, so that the differences stand out.
+                    let [r, g, b, _] = e;
+                    let grey = ((r as u32 + g as u32 + b as u32) / 3 / 4) as u8 + 192;
+                    Rgba([grey, grey, grey, 255])
+                }
+            }
+            _ => {
+                pixels += 1;
+                max = u8::MAX;
+                Rgba(DIFFERENT)
+            }
+        }
+    });
+
+    (pixels != 0).then_some(Difference { pixels, max, image })
+}
+
+/// Compares `actual` against the png at `golden`, as [`compare`] does.
+///
+/// If `golden` does not exist yet, `actual` is saved there and counts as the same; delete it to accept a new rendering.
+/// If they differ, the difference image is saved next to it, as `<golden>.diff.png`.
+pub fn compare_golden(actual: &RgbaImage, golden: impl AsRef<Path>, tolerance: u8) -> CacheResult<Option<Difference>> {
+    let golden = golden.as_ref();
+    if !golden.exists() {
+        actual.save(golden).map_err(to_io).context(error::Io { path: golden.to_path_buf() })?;
+        return Ok(None);
+    }
+    let expected = image::open(golden)
+        .map_err(to_io)
+        .context(error::Io { path: golden.to_path_buf() })?
+        .into_rgba8();
+
+    let difference = compare(actual, &expected, tolerance);
+    if let Some(difference) = &difference {
+        let path = golden.with_extension("diff.png");
+        difference.image.save(&path).map_err(to_io).context(error::Io { path })?;
+    }
+    Ok(difference)
+}
+
+fn to_io(e: ImageError) -> io::Error {
+    match e {
+        ImageError::IoError(e) => e,
+        other => io::Error::new(io::ErrorKind::InvalidData, other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differences() {
+        let expected = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 1, Rgba([102, 100, 100, 255]));
+        assert_eq!(compare(&actual, &expected, 2), None);
+
+        actual.put_pixel(2, 2, Rgba([110, 100, 100, 255]));
+        let difference = compare(&actual, &expected, 2).unwrap();
+        assert_eq!((difference.pixels, difference.max), (1, 10));
+        assert_eq!(difference.image.get_pixel(2, 2), &Rgba(DIFFERENT));
+        assert_ne!(difference.image.get_pixel(1, 1), &Rgba(DIFFERENT));
+
+        let smaller = RgbaImage::from_pixel(4, 3, Rgba([100, 100, 100, 255]));
+        assert_eq!(compare(&smaller, &expected, 0).unwrap().pixels, 4);
+    }
+}
@@ -0,0 +1,312 @@
+//! Writes rendered tiles as [Deep Zoom](https://openseadragon.github.io/examples/tilesource-dzi/) images, for `--dzi`,
+//! and as static [IIIF Image API](https://iiif.io/api/image/3.0/) services, for `--iiif`, as OpenSeadragon based viewers read them.
+//!
+//! Every plane of a layer becomes one image, whose top left corner is the north west corner of the map:
+//! `<layer>/<mapid>/dzi/<plane>.dzi` with its tiles in `<plane>_files`, and `<layer>/<mapid>/iiif/<plane>/info.json`
+//! with its tiles where a level 0 service has them. The image is laid out so that every zoom level of the renderer
+//! is a level of it with the same tiles, so those are copied as they are.
+//!
+//! The levels below the lowest zoom level are made from it, each by halving the one above it tile by tile.
+//! This reads the tiles before `--trim-tiles` crops them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
+
+use image::{
+    imageops::{self, FilterType},
+    RgbaImage,
+};
+use itertools::iproduct;
+use path_macro::path;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rs3cache_backend::error::CacheResult;
+use serde_json::json;
+
+use crate::{cli::Config, output::pmtiles, renderers::png};
+
+/// Where the tiles of a plane are in a deep zoom image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// The width and height of a tile, in pixels.
+    pub tile: u32,
+    /// The width of the image at its highest level, in pixels.
+    pub width: u32,
+    /// The height of the image at its highest level, in pixels.
+    pub height: u32,
+    /// The highest level, which is the highest zoom level of the renderer.
+    pub max_level: u32,
+    /// The highest zoom level of the renderer.
+    pub top_zoom: i8,
+}
+
+impl Layout {
+    /// The layout of tiles of `tile` pixels, where those of zoom level `zoom` go up to column `max_i` and row `max_j`,
+    /// and `top_zoom` is the highest zoom level.
+    ///
+    /// The image starts at column and row 0 of every zoom level, so that each of them is made of whole tiles.
+    pub fn new(tile: u32, zoom: i8, max_i: u32, max_j: u32, top_zoom: i8) -> Self {
+        let factor = 1 << (top_zoom - zoom);
+        let (width, height) = ((max_i + 1) * tile * factor, (max_j + 1) * tile * factor);
+        let max_level = u32::BITS - (width.max(height) - 1).leading_zeros();
+        Self {
+            tile,
+            width,
+            height,
+            max_level,
+            top_zoom,
+        }
+    }
+
+    /// The level of zoom level `zoom` of the renderer, if it has one.
+    pub fn level(&self, zoom: i8) -> Option<u32> {
+        self.max_level.checked_sub((self.top_zoom - zoom) as u32)
+    }
+
+    /// The width and height of the image at `level`.
+    pub fn size(&self, level: u32) -> (u32, u32) {
+        let scale = 1_u64 << (self.max_level - level);
+        let shrink = |n: u32| ((n as u64 + scale - 1) / scale) as u32;
+        (shrink(self.width), shrink(self.height))
+    }
+
+    /// The level, column and row of the tile `i, j` of zoom level `zoom` of the renderer, whose rows count from the south.
+    pub fn position(&self, zoom: i8, i: u32, j: u32) -> Option<(u32, u32, u32)> {
+        let level = self.level(zoom)?;
+        let (width, height) = self.size(level);
+        let row = (height / self.tile).checked_sub(1 + j)?;
+        (i < width / self.tile).then_some((level, i, row))
+    }
+
+    /// The `.dzi` file of the image.
+    pub fn dzi(&self, extension: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" Format="{extension}" Overlap="0" TileSize="{}">
+  <Size Width="{}" Height="{}"/>
+</Image>
+"#,
+            self.tile, self.width, self.height
+        )
+    }
+
+    /// The `info.json` of the image as a level 0 IIIF Image API 3 service at `id`.
+    pub fn iiif_info(&self, id: &str, extension: &str) -> serde_json::Value {
+        json!({
+            "@context": "http://iiif.io/api/image/3/context.json",
+            "id": id,
+            "type": "ImageService3",
+            "protocol": "http://iiif.io/api/image",
+            "profile": "level0",
+            "width": self.width,
+            "height": self.height,
+            "preferredFormats": [extension],
+            "tiles": [{
+                "width": self.tile,
+                "scaleFactors": (0..=self.max_level).map(|k| 1_u64 << k).collect::<Vec<_>>(),
+            }],
+        })
+    }
+
+    /// Where the tile at `column, row` of `level` is in a IIIF service, relative to its `id`, as viewers ask for it.
+    pub fn iiif_path(&self, level: u32, column: u32, row: u32, extension: &str) -> String {
+        let scale = 1_u64 << (self.max_level - level);
+        let (level_width, level_height) = self.size(level);
+        if level_width <= self.tile && level_height <= self.tile {
+            let size = if scale == 1 {
+                "max".to_string()
+            } else {
+                format!("{level_width},{level_height}")
+            };
+            return format!("full/{size}/0/default.{extension}");
+        }
+        let span = self.tile as u64 * scale;
+        let (x, y) = (column as u64 * span, row as u64 * span);
+        let (width, height) = (span.min(self.width as u64 - x), span.min(self.height as u64 - y));
+        let (scaled_width, scaled_height) = ((width + scale - 1) / scale, (height + scale - 1) / scale);
+        format!("{x},{y},{width},{height}/{scaled_width},{scaled_height}/0/default.{extension}")
+    }
+}
+
+/// Writes the tiles of `zoom_levels` of the layer `name` of map `mapid` as a deep zoom image per plane with `--dzi`,
+/// and as a IIIF service per plane with `--iiif`.
+pub fn export(config: &Config, name: &str, mapid: i32, zoom_levels: RangeInclusive<i8>) -> CacheResult<()> {
+    let sink = config.sink()?;
+    let extension = config.tile_format.extension();
+    let tile_path = |zoom: i8, plane: i32, i: u32, j: u32| path!(name / format!("{mapid}/{zoom}/{plane}_{i}_{j}.{extension}"));
+
+    // The tiles of every zoom level of every plane.
+    let mut planes = BTreeMap::<i32, BTreeMap<i8, Vec<(u32, u32)>>>::new();
+    for zoom in zoom_levels {
+        for filename in sink.list(path!(name / format!("{mapid}/{zoom}")))? {
+            let Some((plane, i, j)) = pmtiles::parse(&filename, extension) else { continue };
+            planes.entry(plane).or_default().entry(zoom).or_default().push((i, j));
+        }
+    }
+
+    for (plane, zooms) in planes {
+        let (Some((&lowest, tiles)), Some(&top)) = (zooms.first_key_value(), zooms.keys().last()) else { continue };
+        let Some(&(i, j)) = tiles.first() else { continue };
+        // Tiles are 256 pixels wide, unless the map was rendered with another `--tile-size`.
+        let Some(first) = png::get_tile(config, tile_path(lowest, plane, i, j))? else { continue };
+        let size = first.width();
+        let max_i = tiles.iter().map(|&(i, _)| i).max().unwrap_or(0);
+        let max_j = tiles.iter().map(|&(_, j)| j).max().unwrap_or(0);
+        let layout = Layout::new(size, lowest, max_i, max_j, top);
+
+        let dzi = path!(name / format!("{mapid}/dzi/{plane}_files"));
+        let iiif = path!(name / format!("{mapid}/iiif/{plane}"));
+        let put = |level: u32, column: u32, row: u32, data: &[u8]| -> CacheResult<()> {
+            if config.dzi {
+                sink.put(path!(dzi / format!("{level}/{column}_{row}.{extension}")), data)?;
+            }
+            if config.iiif.is_some() {
+                sink.put(path!(iiif / layout.iiif_path(level, column, row, extension)), data)?;
+            }
+            Ok(())
+        };
+
+        for (&zoom, tiles) in &zooms {
+            tiles.clone().into_par_iter().try_for_each(|(i, j)| {
+                let Some((level, column, row)) = layout.position(zoom, i, j) else { return Ok(()) };
+                match sink.get(tile_path(zoom, plane, i, j))? {
+                    Some(data) => put(level, column, row, &data),
+                    None => Ok(()),
+                }
+            })?;
+        }
+
+        // The levels below the lowest zoom level, down to a single pixel. Every tile is made of the (up to) four
+        // tiles of the level above it, so that only one level is held at a time, and the lowest zoom level not at all.
+        let Some(lowest_level) = layout.level(lowest) else { continue };
+        let positions = tiles
+            .iter()
+            .filter_map(|&(i, j)| {
+                let (_, column, row) = layout.position(lowest, i, j)?;
+                Some(((column, row), (i, j)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut above = BTreeMap::<(u32, u32), RgbaImage>::new();
+        for level in (0..lowest_level).rev() {
+            let from_lowest = level + 1 == lowest_level;
+            let (width, height) = layout.size(level);
+            let (above_width, above_height) = layout.size(level + 1);
+            let sources = if from_lowest {
+                positions.keys().collect::<Vec<_>>()
+            } else {
+                above.keys().collect()
+            };
+            let targets = sources.into_iter().map(|&(column, row)| (column / 2, row / 2)).collect::<BTreeSet<_>>();
+
+            above = targets
+                .into_par_iter()
+                .map(|(column, row)| -> CacheResult<_> {
+                    let mut canvas = RgbaImage::new(2 * size, 2 * size);
+                    for (dx, dy) in iproduct!(0..2, 0..2) {
+                        let source = (2 * column + dx, 2 * row + dy);
+                        let (x, y) = ((dx * size) as i64, (dy * size) as i64);
+                        if from_lowest {
+                            let Some(&(i, j)) = positions.get(&source) else { continue };
+                            if let Some(img) = png::get_tile(config, tile_path(lowest, plane, i, j))? {
+                                imageops::replace(&mut canvas, &img, x, y);
+                            }
+                        } else if let Some(img) = above.get(&source) {
+                            imageops::replace(&mut canvas, img, x, y);
+                        }
+                    }
+
+                    let (x, y) = (column * size, row * size);
+                    let (tile_width, tile_height) = (size.min(width - x), size.min(height - y));
+                    let (source_width, source_height) = ((2 * tile_width).min(above_width - 2 * x), (2 * tile_height).min(above_height - 2 * y));
+                    let source = imageops::crop_imm(&canvas, 0, 0, source_width, source_height).to_image();
+                    let part = imageops::resize(&source, tile_width, tile_height, FilterType::Triangle);
+                    if config.dzi {
+                        png::put_tile(config, path!(dzi / format!("{level}/{column}_{row}.{extension}")), &part)?;
+                    }
+                    if config.iiif.is_some() {
+                        png::put_tile(config, path!(iiif / layout.iiif_path(level, column, row, extension)), &part)?;
+                    }
+                    Ok(((column, row), part))
+                })
+                .collect::<CacheResult<_>>()?;
+        }
+
+        if config.dzi {
+            sink.put(path!(name / format!("{mapid}/dzi/{plane}.dzi")), layout.dzi(extension).as_bytes())?;
+        }
+        if let Some(url) = &config.iiif {
+            let id = format!("{}/{name}/{mapid}/iiif/{plane}", url.trim_end_matches('/'));
+            let info = serde_json::to_string_pretty(&layout.iiif_info(&id, extension)).unwrap();
+            sink.put(path!(iiif / "info.json"), info.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// The whole map, at the default zoom levels: two by four tiles at zoom level -4.
+    const WORLD: Layout = Layout {
+        tile: 256,
+        width: 131072,
+        height: 262144,
+        max_level: 18,
+        top_zoom: 4,
+    };
+
+    #[test]
+    fn layout() {
+        assert_eq!(Layout::new(256, -4, 1, 3, 4), WORLD);
+        assert_eq!(WORLD.level(4), Some(18));
+        assert_eq!(WORLD.level(-4), Some(10));
+        assert_eq!(WORLD.size(10), (512, 1024));
+        assert_eq!(WORLD.size(0), (1, 1));
+
+        // Rows count from the north.
+        assert_eq!(WORLD.position(-4, 0, 0), Some((10, 0, 3)));
+        assert_eq!(WORLD.position(2, 50, 50), Some((16, 50, 255 - 50)));
+        assert_eq!(WORLD.position(-4, 2, 0), None);
+    }
+
+    #[test]
+    fn iiif_paths() {
+        assert_eq!(WORLD.iiif_path(18, 0, 0, "png"), "0,0,256,256/256,256/0/default.png");
+        assert_eq!(WORLD.iiif_path(10, 1, 3, "png"), "65536,196608,65536,65536/256,256/0/default.png");
+        assert_eq!(WORLD.iiif_path(0, 0, 0, "png"), "full/1,1/0/default.png");
+        assert!(WORLD.dzi("png").contains(r#"<Size Width="131072" Height="262144"/>"#));
+    }
+
+    #[test]
+    fn lower_levels() -> CacheResult<()> {
+        let config = Config {
+            output: crate::output::sink::temp_folder("deepzoom_lower_levels"),
+            dzi: true,
+            ..Config::default()
+        };
+        // Three by two tiles of four pixels at zoom level -4, each column in its own colour.
+        for (i, j) in iproduct!(0..3, 0..2) {
+            let img = RgbaImage::from_pixel(4, 4, Rgba([i as u8 * 100, 0, 0, 255]));
+            png::put_tile(&config, format!("map/0/-4/0_{i}_{j}.png"), &img)?;
+        }
+        export(&config, "map", 0, -4..=-4)?;
+
+        // Twelve by eight pixels at level 4, so six by four at level 3, in two tiles.
+        let tile = |level: u32, column: u32, row: u32| png::get_tile(&config, format!("map/0/dzi/0_files/{level}/{column}_{row}.png"));
+        assert_eq!(tile(4, 2, 1)?.map(|img| img.dimensions()), Some((4, 4)));
+        assert_eq!(tile(3, 1, 0)?, Some(RgbaImage::from_pixel(2, 4, Rgba([200, 0, 0, 255]))));
+        assert_eq!(tile(3, 0, 0)?.map(|img| img.dimensions()), Some((4, 4)));
+        assert_eq!(tile(2, 0, 0)?.map(|img| img.dimensions()), Some((3, 2)));
+        assert_eq!(tile(0, 0, 0)?.map(|img| img.dimensions()), Some((1, 1)));
+        assert_eq!(tile(2, 1, 0)?, None);
+
+        // A tile that does not decode is an error.
+        config.sink()?.put("map/0/-4/0_0_0.png", b"not a png")?;
+        assert!(export(&config, "map", 0, -4..=-4).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+//! Draws the ground of map tiles, composites and downscales them on the gpu, with `--gpu` and the `gpu` feature.
+//!
+//! These are the passes that touch every pixel of every plane of a mapsquare, which is what dominates at a high `--tile-size`.
+//! Underlays are blended and tile shapes filled on the gpu; shading, lines and sprites are still drawn on the cpu.
+//! The results are the same as those of the cpu, as the shaders do the same integer arithmetic.
+//!
+//! Pipelines are made once, buffers are kept to be used again, and the passes of a mapsquare are submitted together.
+//! Without a usable adapter, or for images bigger than it can take, everything is done on the cpu instead.
+
+use std::{
+    borrow::Cow,
+    ops::RangeInclusive,
+    sync::{LazyLock, Mutex, PoisonError},
+};
+
+use console::style;
+use image::RgbaImage;
+
+use crate::renderers::map::{base::GroundLayer, tileshape, RenderConfig};
+
+/// Picks the highest plane that is not transparent, and dims it if it is below the plane being composited.
+const COMPOSITE: &str = r"
+struct Params { width: u32, height: u32, plane: u32, brightness: u32 }
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<u32>;
+@group(0) @binding(2) var<storage, read_write> output: array<u32>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+    let index = id.y * params.width + id.x;
+    let size = params.width * params.height;
+
+    var p = params.plane;
+    loop {
+        let pixel = input[p * size + index];
+        if (p == 0u || (pixel >> 24u) != 0u) {
+            if (p == params.plane) {
+                output[index] = pixel;
+            } else {
+                var dimmed = pixel & 0xFF000000u;
+                for (var shift = 0u; shift < 24u; shift = shift + 8u) {
+                    dimmed = dimmed | ((((pixel >> shift) & 0xFFu) * params.brightness / 100u) << shift);
+                }
+                output[index] = dimmed;
+            }
+            return;
+        }
+        p = p - 1u;
+    }
+}
+";
+
+/// Averages every square of `factor` by `factor` pixels into one.
+const DOWNSCALE: &str = r"
+struct Params { width: u32, height: u32, factor: u32, unused: u32 }
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<u32>;
+@group(0) @binding(2) var<storage, read_write> output: array<u32>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+    let stride = params.width * params.factor;
+    var sums = vec4<u32>(0u, 0u, 0u, 0u);
+    for (var dy = 0u; dy < params.factor; dy = dy + 1u) {
+        for (var dx = 0u; dx < params.factor; dx = dx + 1u) {
+            let pixel = input[(id.y * params.factor + dy) * stride + id.x * params.factor + dx];
+            sums = sums + vec4<u32>(pixel & 0xFFu, (pixel >> 8u) & 0xFFu, (pixel >> 16u) & 0xFFu, pixel >> 24u);
+        }
+    }
+    let mean = sums / (params.factor * params.factor);
+    output[id.y * params.width + id.x] = mean.x | (mean.y << 8u) | (mean.z << 16u) | (mean.w << 24u);
+}
+";
+
+/// Averages the colours of the underlays within `interp` of every tile of every plane, where the tile has one of its own.
+///
+/// The grid of each plane has `side` by `side` tiles, and is `interp` tiles bigger than the mapsquare on every side.
+const BLEND: &str = r"
+struct Params { side: u32, interp: u32, unused: u32, unused2: u32 }
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> grids: array<u32>;
+@group(0) @binding(2) var<storage, read_write> output: array<u32>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let plane = params.side * params.side * id.z;
+    var sums = vec3<u32>(0u, 0u, 0u);
+    var count = 0u;
+    for (var dx = 0u; dx <= 2u * params.interp; dx = dx + 1u) {
+        for (var dy = 0u; dy <= 2u * params.interp; dy = dy + 1u) {
+            let colour = grids[plane + (id.x + dx) * params.side + id.y + dy];
+            if ((colour >> 24u) != 0u) {
+                sums = sums + vec3<u32>(colour & 0xFFu, (colour >> 8u) & 0xFFu, (colour >> 16u) & 0xFFu);
+                count = count + 1u;
+            }
+        }
+    }
+    var blended = 0u;
+    if (count != 0u) {
+        let mean = sums / count;
+        blended = mean.x | (mean.y << 8u) | (mean.z << 16u) | 0xFF000000u;
+    }
+    output[id.z * 4096u + id.x * 64u + id.y] = blended;
+}
+";
+
+/// Fills every pixel with the last of the layers of its tile that covers it, as [`tileshape`] draws them.
+///
+/// Each tile has up to 4 layers, whose first word has a bit for whether it is there, whether it has a shape,
+/// whether it has an underlay and whether it has an overlay, then the shape and the plane it is from.
+/// The second is the colour of the overlay.
+const FILL: &str = r"
+struct Params { tile_size: u32, dim: u32, unused: u32, unused2: u32 }
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> layers: array<vec2<u32>>;
+@group(0) @binding(2) var<storage, read> blended: array<u32>;
+@group(0) @binding(3) var<storage, read> masks: array<u32>;
+@group(0) @binding(4) var<storage, read> gamma: array<u32>;
+@group(0) @binding(5) var<storage, read_write> output: array<u32>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.dim || id.y >= params.dim) {
+        return;
+    }
+    let x = id.x / params.tile_size;
+    let y = 63u - id.y / params.tile_size;
+    let cell = (id.y % params.tile_size * 4u / params.tile_size) * 4u + id.x % params.tile_size * 4u / params.tile_size;
+    let tile = x * 64u + y;
+
+    for (var k = 0u; k < 4u; k = k + 1u) {
+        let layer = layers[tile * 4u + k];
+        let flags = layer.x;
+        if ((flags & 1u) == 0u) {
+            break;
+        }
+        let has_shape = (flags & 2u) != 0u;
+        let covered = ((masks[(flags >> 8u) & 0xFFu] >> cell) & 1u) != 0u;
+        let underlay = blended[((flags >> 16u) & 3u) * 4096u + tile];
+        if ((flags & 4u) != 0u && (!has_shape || !covered) && underlay != 0u) {
+            output[id.y * params.dim + id.x] = gamma[underlay & 0xFFu] | (gamma[(underlay >> 8u) & 0xFFu] << 8u)
+                | (gamma[(underlay >> 16u) & 0xFFu] << 16u) | 0xFF000000u;
+        }
+        if ((flags & 8u) != 0u && covered) {
+            output[id.y * params.dim + id.x] = layer.y;
+        }
+    }
+}
+";
+
+/// The size of the workgroups of the shaders, in both directions.
+const WORKGROUP: u32 = 16;
+
+/// How many unused buffers are kept, at most.
+const POOL: usize = 64;
+
+/// The buffers of a shader: uniforms, storage that is only read, and storage that is written to and read back.
+const UNIFORM: wgpu::BufferUsages = wgpu::BufferUsages::UNIFORM.union(wgpu::BufferUsages::COPY_DST);
+const STORAGE: wgpu::BufferUsages = wgpu::BufferUsages::STORAGE
+    .union(wgpu::BufferUsages::COPY_DST)
+    .union(wgpu::BufferUsages::COPY_SRC);
+const STAGING: wgpu::BufferUsages = wgpu::BufferUsages::MAP_READ.union(wgpu::BufferUsages::COPY_DST);
+
+static GPU: LazyLock<Option<Gpu>> = LazyLock::new(|| {
+    let gpu = pollster::block_on(Gpu::new());
+    if gpu.is_none() {
+        println!("    {} no gpu adapter was found, rendering on the cpu", style("Warning").yellow());
+    }
+    gpu
+});
+
+/// The gpu, if there is one, set up on first use.
+pub fn get() -> Option<&'static Gpu> {
+    GPU.as_ref()
+}
+
+/// A device with the pipelines of the shaders.
+pub struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    limits: wgpu::Limits,
+    composite: wgpu::ComputePipeline,
+    downscale: wgpu::ComputePipeline,
+    blend: wgpu::ComputePipeline,
+    fill: wgpu::ComputePipeline,
+    /// Buffers that are done with, to be used again by passes that need the same size and usage.
+    pool: Mutex<Vec<wgpu::Buffer>>,
+}
+
+impl Gpu {
+    async fn new() -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await?;
+        let limits = adapter.limits();
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("rs3cache"),
+                    features: wgpu::Features::empty(),
+                    limits: limits.clone(),
+                },
+                None,
+            )
+            .await
+            .ok()?;
+
+        let pipeline = |label: &str, source: &'static str| {
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: None,
+                module: &module,
+                entry_point: "main",
+            })
+        };
+        let composite = pipeline("composite", COMPOSITE);
+        let downscale = pipeline("downscale", DOWNSCALE);
+        let blend = pipeline("blend", BLEND);
+        let fill = pipeline("fill", FILL);
+
+        Some(Self {
+            device,
+            queue,
+            limits,
+            composite,
+            downscale,
+            blend,
+            fill,
+            pool: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Composites each of `planes` of `imgs`, which must all be the same size, as [`save_smallest`](super::map::save_smallest) does:
+    /// transparent pixels show the planes below, dimmed to `brightness` percent.
+    pub fn composite(&self, imgs: &[RgbaImage; 4], planes: RangeInclusive<usize>, brightness: u8) -> Option<Vec<RgbaImage>> {
+        let (width, height) = imgs[0].dimensions();
+        let input = self.upload(STORAGE, &imgs.iter().flat_map(|img| img.as_raw()).copied().collect::<Vec<u8>>())?;
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut buffers = vec![];
+        let mut outputs = vec![];
+        for plane in planes {
+            let params = self.upload(UNIFORM, &words(&[width, height, plane as u32, brightness as u32]))?;
+            let output = self.buffer(STORAGE, width as u64 * height as u64 * 4)?;
+            self.dispatch(
+                &mut encoder,
+                &self.composite,
+                &[&params, &input, &output],
+                [width, height, 1].map(workgroups),
+            );
+            buffers.push(params);
+            outputs.push(output);
+        }
+        let images = self.finish(encoder, &outputs);
+        self.recycle(buffers.into_iter().chain(outputs).chain(Some(input)));
+        images?.into_iter().map(|output| RgbaImage::from_raw(width, height, output)).collect()
+    }
+
+    /// Resizes `img` to a `factor`th of its size, as [`resize_half`](super::scale::resize_half)
+    /// and [`resize_quarter`](super::scale::resize_quarter) do.
+    pub fn resize(&self, img: &RgbaImage, factor: u32) -> Option<RgbaImage> {
+        let (width, height) = (img.width() / factor, img.height() / factor);
+        let params = self.upload(UNIFORM, &words(&[width, height, factor, 0]))?;
+        let input = self.upload(STORAGE, img.as_raw())?;
+        let output = self.buffer(STORAGE, width as u64 * height as u64 * 4)?;
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.dispatch(
+            &mut encoder,
+            &self.downscale,
+            &[&params, &input, &output],
+            [width, height, 1].map(workgroups),
+        );
+        let resized = self.finish(encoder, std::slice::from_ref(&output));
+        self.recycle([params, input, output]);
+        RgbaImage::from_raw(width, height, resized?.pop()?)
+    }
+
+    /// Draws the ground of a mapsquare over `img`, as [`base::put`](super::map::base::put) does with [`Blending::Smooth`](super::map::Blending::Smooth).
+    ///
+    /// `layers` are those of every tile, with the unblended colours of their underlays, and `grids` the colours of the underlays
+    /// of every plane within [`RenderConfig::interp`] of the mapsquare. `gamma` brightens the blended colours.
+    pub fn ground(
+        &self,
+        render: &RenderConfig,
+        img: &RgbaImage,
+        layers: &[Vec<GroundLayer>],
+        grids: &[Vec<Option<[u8; 3]>>; 4],
+        gamma: &[u8],
+    ) -> Option<RgbaImage> {
+        let dim = img.width();
+        let interp = u32::try_from(render.interp).ok()?;
+        let side = 64 + 2 * interp;
+
+        let mut packed = vec![0; 64 * 64 * 4 * 2];
+        for (tile, layers) in layers.iter().enumerate() {
+            for (k, layer) in layers.iter().enumerate().take(4) {
+                // Shapes that the cpu does not know of are left to it, which panics on them.
+                if layer.shape.map_or(false, |shape| shape >= 48) {
+                    return None;
+                }
+                let flags = 1
+                    | (layer.shape.is_some() as u32) << 1
+                    | (layer.underlay.is_some() as u32) << 2
+                    | (layer.overlay.is_some() as u32) << 3
+                    | (layer.shape.unwrap_or(0) as u32) << 8
+                    | (layer.plane as u32) << 16;
+                packed[(tile * 4 + k) * 2] = flags;
+                packed[(tile * 4 + k) * 2 + 1] = layer.overlay.map_or(0, |overlay| u32::from_le_bytes(overlay.0));
+            }
+        }
+        let grids = grids
+            .iter()
+            .flatten()
+            .map(|colour| colour.map_or(0, |[red, green, blue]| u32::from_le_bytes([red, green, blue, 255])))
+            .collect::<Vec<_>>();
+        let masks = (0..48).map(|shape| tileshape::mask(shape) as u32).collect::<Vec<_>>();
+        let gamma = gamma.iter().map(|&value| value as u32).collect::<Vec<_>>();
+
+        let blend_params = self.upload(UNIFORM, &words(&[side, interp, 0, 0]))?;
+        let grids = self.upload(STORAGE, &words(&grids))?;
+        let blended = self.buffer(STORAGE, 4 * 64 * 64 * 4)?;
+        let fill_params = self.upload(UNIFORM, &words(&[render.tile_size, dim, 0, 0]))?;
+        let layers = self.upload(STORAGE, &words(&packed))?;
+        let masks = self.upload(STORAGE, &words(&masks))?;
+        let gamma = self.upload(STORAGE, &words(&gamma))?;
+        let output = self.upload(STORAGE, img.as_raw())?;
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.dispatch(&mut encoder, &self.blend, &[&blend_params, &grids, &blended], [8, 8, 4]);
+        self.dispatch(
+            &mut encoder,
+            &self.fill,
+            &[&fill_params, &layers, &blended, &masks, &gamma, &output],
+            [dim, dim, 1].map(workgroups),
+        );
+        let ground = self.finish(encoder, std::slice::from_ref(&output));
+        self.recycle([blend_params, grids, blended, fill_params, layers, masks, gamma, output]);
+        RgbaImage::from_raw(dim, dim, ground?.pop()?)
+    }
+
+    /// A buffer of `size` bytes for `usage`, from the pool if there is one, or `None` if it is too big to bind.
+    fn buffer(&self, usage: wgpu::BufferUsages, size: u64) -> Option<wgpu::Buffer> {
+        if size == 0
+            || size > self.limits.max_storage_buffer_binding_size as u64
+            || (usage == UNIFORM && size > self.limits.max_uniform_buffer_binding_size as u64)
+        {
+            return None;
+        }
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(position) = pool.iter().position(|buffer| buffer.size() == size && buffer.usage() == usage) {
+            return Some(pool.swap_remove(position));
+        }
+        drop(pool);
+        Some(self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage,
+            mapped_at_creation: false,
+        }))
+    }
+
+    /// A buffer for `usage` that holds `contents`, which is written with the next submission.
+    fn upload(&self, usage: wgpu::BufferUsages, contents: &[u8]) -> Option<wgpu::Buffer> {
+        let buffer = self.buffer(usage, contents.len() as u64)?;
+        self.queue.write_buffer(&buffer, 0, contents);
+        Some(buffer)
+    }
+
+    /// Returns `buffers` to the pool, dropping the oldest ones if it is full.
+    fn recycle(&self, buffers: impl IntoIterator<Item = wgpu::Buffer>) {
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        pool.extend(buffers);
+        let excess = pool.len().saturating_sub(POOL);
+        pool.drain(..excess);
+    }
+
+    /// Records a pass of `pipeline` over `workgroups`, with `buffers` bound in order.
+    fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, pipeline: &wgpu::ComputePipeline, buffers: &[&wgpu::Buffer], workgroups: [u32; 3]) {
+        let entries = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>();
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        let [x, y, z] = workgroups;
+        pass.dispatch_workgroups(x, y, z);
+    }
+
+    /// Submits the passes of `encoder` at once, and reads back `outputs` when they are done.
+    fn finish(&self, mut encoder: wgpu::CommandEncoder, outputs: &[wgpu::Buffer]) -> Option<Vec<Vec<u8>>> {
+        let staging = outputs
+            .iter()
+            .map(|output| {
+                let staging = self.buffer(STAGING, output.size())?;
+                encoder.copy_buffer_to_buffer(output, 0, &staging, 0, output.size());
+                Some(staging)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        self.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for staging in &staging {
+            let sender = sender.clone();
+            staging
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        }
+        self.device.poll(wgpu::Maintain::Wait);
+        let mapped = staging.iter().all(|_| matches!(receiver.recv(), Ok(Ok(()))));
+
+        let data = mapped.then(|| {
+            staging
+                .iter()
+                .map(|staging| {
+                    let data = staging.slice(..).get_mapped_range().to_vec();
+                    staging.unmap();
+                    data
+                })
+                .collect()
+        });
+        // Buffers that failed to map are not used again.
+        if mapped {
+            self.recycle(staging);
+        }
+        data
+    }
+}
+
+/// The bytes of `words`, as the shaders read them.
+fn words(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// How many workgroups cover `pixels` in one direction.
+fn workgroups(pixels: u32) -> u32 {
+    (pixels + WORKGROUP - 1) / WORKGROUP
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+    use crate::renderers::scale;
+
+    #[test]
+    fn same_as_cpu() {
+        // Machines without a gpu have nothing to compare.
+        let Some(gpu) = get() else { return };
+
+        let img = RgbaImage::from_fn(64, 32, |x, y| {
+            Rgba([x as u8 * 4, y as u8 * 8, (x ^ y) as u8, if x % 3 == 0 { 0 } else { 255 }])
+        });
+        assert_eq!(gpu.resize(&img, 2), Some(scale::resize_half(img.clone())));
+        assert_eq!(gpu.resize(&img, 4), Some(scale::resize_quarter(img.clone())));
+
+        // The upper planes are only drawn on even columns, where the ground shows through elsewhere.
+        let imgs = [0, 1, 2, 3].map(|p| RgbaImage::from_fn(32, 32, |x, _| Rgba([200, 100, 50, if p == 0 || x % 2 == 0 { 255 } else { 0 }])));
+        let composited = gpu.composite(&imgs, 2..=2, 50).unwrap().remove(0);
+        assert_eq!(composited.get_pixel(0, 0), &Rgba([200, 100, 50, 255]));
+        assert_eq!(composited.get_pixel(1, 0), &Rgba([100, 50, 25, 255]));
+    }
+}
@@ -1,54 +1,157 @@
 /// Renders the ground colours.
 pub mod base;
+/// Renders the world maps that are pasted together from parts of the game map, such as those of dungeons.
+#[cfg(feature = "rs3")]
+pub mod composite;
+/// Draws the grid of `--debug-grid`.
+pub mod grid;
+/// Responsible for tinting the locations of `--highlight-loc`.
+pub mod highlights;
+/// Responsible for drawing the icons and text of map labels, for `--layers`.
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+pub mod icons;
 /// Responsible for drawing lines - doors, fences, walls and so on.
 pub mod lines;
 /// Describes the shape of lines drawn by the map renderer.
 pub mod lineshape;
+/// Responsible for drawing the map function icons of legacy maps.
+#[cfg(feature = "legacy")]
+pub mod mapfunctions;
 /// Responsible for drawing [`MapScene`](crate::definitions::mapscenes::MapScene).
 pub mod mapscenes;
+pub mod markers;
+/// Draws the map in the style of the in-game minimap.
+pub mod minimap;
+pub mod palette;
+/// Draws text in the game's fonts, or in a small built-in one.
+pub mod text;
 /// Describes the shape of overlays drawn by the map renderer.
 pub mod tileshape;
 
-use std::{collections::BTreeMap, fs};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
 
-use ::error::Context;
-use image::{GenericImageView, ImageBuffer, Pixel, Rgba, RgbaImage};
+use image::{imageops, GenericImageView, ImageBuffer, Pixel, Rgba, RgbaImage};
 use indicatif::ProgressIterator;
 use itertools::iproduct;
 use path_macro::path;
 use rayon::iter::{ParallelBridge, ParallelIterator};
-use rs3cache_backend::error::{self, CacheResult};
+use rs3cache_backend::{
+    error::CacheResult,
+    warning::{self, Warning},
+};
 use rs3cache_utils::color::Color;
 
 #[cfg(feature = "legacy")]
 use crate::definitions::flo::Flo;
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::definitions::fonts;
 #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
 use crate::definitions::mapscenes::MapScene;
 #[cfg(any(feature = "rs3", feature = "osrs"))]
-use crate::definitions::{overlays::Overlay, underlays::Underlay};
+use crate::definitions::{maplabel_configs::MapLabelConfig, overlays::Overlay, underlays::Underlay};
+#[cfg(feature = "gpu")]
+use crate::renderers::gpu;
 use crate::{
+    cancel,
     cli::Config,
     definitions::{
+        fonts::Font,
         location_configs::LocationConfig,
-        mapsquares::{GroupMapSquare, GroupMapSquareIterator},
+        mapsquares::{GroupMapSquare, GroupMapSquareIterator, MapSquare},
         sprites::{self, Sprite},
     },
-    renderers::{scale, zoom},
+    output::pmtiles,
+    renderers::{
+        changed, deepzoom, png,
+        progress::{self, Event},
+        resume, scale, stitch, trim, viewer, zoom,
+    },
 };
-///
+/// Settings of the map renderer, as given on the command line.
+#[derive(clap::Args, Clone, Copy, Debug, PartialEq, Eq)]
 pub struct RenderConfig {
-    /// -1 is the "real" world map.
+    /// The id of the map to render. -1 is the "real" world map.
+    #[clap(long, allow_hyphen_values = true, default_value_t = RenderConfig::DEFAULT.map_id)]
     pub map_id: i32,
-    /// Scale factor, this cannot be zero.
+    /// Scale factor of sprites drawn on the map, this cannot be zero.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.scale, value_parser = clap::value_parser!(u32).range(1..))]
     pub scale: u32,
     /// The height and width of a [`Tile`](crate::definitions::tiles::Tile) in pixels.
+    ///
+    /// This must be a power of two, of at least 4. Zoom levels are cut into tiles of 256 pixels at the default of 16;
+    /// other sizes make bigger or smaller tiles.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.tile_size, value_parser = parse_tile_size)]
     pub tile_size: u32,
     /// The highest zoom level.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.initial_zoom, value_parser = clap::value_parser!(i8).range(2..=4))]
     pub initial_zoom: i8,
-    /// The range at which underlays are blended.
+    /// The range at which underlays are blended, in tiles in every direction. The game uses 5; at 0 they are not blended.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.interp)]
     pub interp: isize,
-    /// The height and width of a full [`MapSquare`](crate::definitions::mapsquares::MapSquare) in pixels.
-    pub dim: u32,
+    /// How the colours of underlays are blended.
+    #[clap(value_enum, long, default_value_t = RenderConfig::DEFAULT.blending)]
+    pub blending: Blending,
+    /// The exponent of the curve that the colours of the ground are brightened by, in percent, as the game's brightness setting does.
+    /// At 100 they are left as they are; the game uses 60 at its brightest and 90 at its darkest.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.gamma, value_parser = clap::value_parser!(u8).range(10..=200))]
+    pub gamma: u8,
+    /// The colour of water, as in `#607696`, instead of that of its overlay.
+    #[clap(long, value_parser = parse_colour)]
+    pub water_colour: Option<[u8; 4]>,
+    /// How strongly slopes are shaded by their height, from 0 to 100. At 0 the ground is flat.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.light_intensity, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub light_intensity: u8,
+    /// Where the light that shades slopes comes from, in degrees clockwise from north.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.light_azimuth, value_parser = clap::value_parser!(u16).range(0..360))]
+    pub light_azimuth: u16,
+    /// What the map looks like.
+    #[clap(value_enum, long, default_value_t = RenderConfig::DEFAULT.style)]
+    pub style: Style,
+    /// Writes a single tile tree, as plane 0, in which upper planes cover lower ones wherever they have something,
+    /// instead of a tile tree for every plane. The lower planes are not dimmed in it.
+    #[clap(long)]
+    pub composite_planes: bool,
+    /// How thick walls and fences are, in percent of a tile.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.line_width, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub line_width: u8,
+    /// Draws walls and fences with smooth edges.
+    #[clap(long)]
+    pub antialias_lines: bool,
+    /// The colour of walls and fences, as in `#ffffff` or `#ffffff80`, instead of that of the style.
+    #[clap(long, value_parser = parse_colour)]
+    pub wall_colour: Option<[u8; 4]>,
+    /// The colour of doors and other lines that can be interacted with, as in `#ff0000`, instead of that of the style.
+    #[clap(long, value_parser = parse_colour)]
+    pub door_colour: Option<[u8; 4]>,
+    /// What is done with the upper planes above tiles that are under a roof.
+    #[clap(value_enum, long, default_value_t = RenderConfig::DEFAULT.roofs)]
+    pub roofs: Roofs,
+    /// How bright lower planes are drawn under upper ones, in percent. At 100 they are not dimmed at all.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.lower_plane_brightness, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub lower_plane_brightness: u8,
+    /// How much of the colour of the land next to water is blended into it, in percent, so that coasts stand out.
+    /// At 0 water is a single colour.
+    #[clap(long, default_value_t = RenderConfig::DEFAULT.shoreline, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub shoreline: u8,
+    /// Leaves the planes below a plane out of its tiles, rather than showing them where it has nothing,
+    /// dimmed by `--lower-plane-brightness`.
+    #[clap(long)]
+    pub hide_lower_planes: bool,
+    /// The colour of pixels of the map that no plane covers, as in `#000000`. They are transparent by default,
+    /// and always in the tiles of `--layers`.
+    #[clap(long, value_parser = parse_colour)]
+    pub backfill: Option<[u8; 4]>,
+    /// Also writes tiles that nothing covers, which are left out by default.
+    #[clap(long)]
+    pub keep_empty_tiles: bool,
 }
 
 impl RenderConfig {
@@ -58,8 +161,24 @@ impl RenderConfig {
             scale: 4,
             tile_size: 16,
             interp: 5,
-            dim: 1024,
+            blending: Blending::Smooth,
+            gamma: 100,
+            water_colour: None,
             initial_zoom: 3,
+            light_intensity: 0,
+            light_azimuth: 315,
+            style: Style::Map,
+            line_width: 25,
+            antialias_lines: false,
+            wall_colour: None,
+            door_colour: None,
+            composite_planes: false,
+            roofs: Roofs::Clip,
+            lower_plane_brightness: 50,
+            shoreline: 25,
+            hide_lower_planes: false,
+            backfill: None,
+            keep_empty_tiles: false,
         }
     }
 
@@ -69,235 +188,905 @@ impl RenderConfig {
             scale: 4,
             tile_size: 16,
             interp: 5,
-            dim: 1024,
+            blending: Blending::Smooth,
+            gamma: 100,
+            water_colour: None,
             initial_zoom: 4,
+            light_intensity: 0,
+            light_azimuth: 315,
+            style: Style::Map,
+            line_width: 25,
+            antialias_lines: false,
+            wall_colour: None,
+            door_colour: None,
+            composite_planes: false,
+            roofs: Roofs::Clip,
+            lower_plane_brightness: 50,
+            shoreline: 25,
+            hide_lower_planes: false,
+            backfill: None,
+            keep_empty_tiles: false,
+        }
+    }
+
+    /// The defaults, which skip the highest zoom level with the `fast` feature.
+    #[cfg(feature = "fast")]
+    pub const DEFAULT: Self = Self::fast();
+
+    /// The defaults, which skip the highest zoom level with the `fast` feature.
+    #[cfg(not(feature = "fast"))]
+    pub const DEFAULT: Self = Self::detailed();
+
+    /// The height and width of a full [`MapSquare`](crate::definitions::mapsquares::MapSquare) in pixels.
+    pub const fn dim(&self) -> u32 {
+        self.tile_size * 64
+    }
+}
+
+/// Parses a colour, as in `#ff0000`, or with an alpha channel, as in `#ff000080`.
+pub fn parse_colour(arg: &str) -> Result<[u8; 4], String> {
+    let hex = arg.trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            .ok_or_else(|| format!("{arg:?} is not a colour like #ff0000"))
+    };
+    match hex.len() {
+        6 => Ok([channel(0)?, channel(2)?, channel(4)?, 255]),
+        8 => Ok([channel(0)?, channel(2)?, channel(4)?, channel(6)?]),
+        _ => Err(format!("{arg:?} is not a colour like #ff0000")),
+    }
+}
+
+fn parse_tile_size(arg: &str) -> Result<u32, String> {
+    match arg.parse::<u32>() {
+        Ok(size) if size >= 4 && size.is_power_of_two() => Ok(size),
+        Ok(size) => Err(format!("{size} is not a power of two of at least 4")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The look of the map, as selected by `--style`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Style {
+    /// The world map.
+    #[default]
+    Map,
+    /// The in-game minimap: tiles are not blended or shaded, walls have the minimap's colours
+    /// and map labels are drawn as dots.
+    Minimap,
+}
+
+/// How the colours of underlays are blended with those around them, as selected by `--blending`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Blending {
+    /// Their red, green and blue are averaged, which makes smooth gradients.
+    #[default]
+    Smooth,
+    /// Their hue, saturation and lightness are averaged, with hues weighted by how colourful they are,
+    /// and rounded to the 16 bit colours of the game. This approximates how its clients blend them,
+    /// but is not checked against what they draw.
+    Game,
+}
+
+/// What is drawn above tiles that are under a roof, as selected by `--roofs`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Roofs {
+    /// Upper planes are drawn onto lower ones wherever the tiles say they are visible from below, even if they are a roof.
+    Keep,
+    /// Upper planes are not drawn onto roofed tiles of lower planes, as the game draws its surface map.
+    #[default]
+    Clip,
+    /// Like `clip`, and the ground above roofed tiles is also left out of the upper planes themselves,
+    /// so that what is under the roofs shows through.
+    Remove,
+}
+
+/// A layer that is rendered into its own tile tree next to the map, as selected by `--layers`.
+///
+/// This lets frontends toggle them independently; the map itself still has all of them flattened into it.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[clap(rename_all = "snake_case")]
+pub enum Layer {
+    /// The ground: underlays, overlays and their shading.
+    Base,
+    /// Walls, fences and the like.
+    Walls,
+    /// Mapscenes, such as trees and rocks.
+    Mapscenes,
+    /// The icons of map labels, such as those of banks and altars.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Icons,
+    /// The text of map labels, such as the names of towns.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    Labels,
+    /// The tiles covered by the locations of `--highlight-loc`, which always renders this layer.
+    Highlights,
+    /// Mapsquare boundaries with their coordinates and region ids, and the lines between tiles, as `--debug-grid` renders.
+    Grid,
+    /// Chunk boundaries, and stripes over mapsquares whose locations could not be decrypted, as `--debug-chunks` renders.
+    Chunks,
+}
+
+impl Layer {
+    /// The folder its tiles are written to.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Base => "base",
+            Self::Walls => "walls",
+            Self::Mapscenes => "mapscenes",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Self::Icons => "icons",
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Self::Labels => "labels",
+            Self::Highlights => "highlights",
+            Self::Grid => "grid",
+            Self::Chunks => "chunks",
         }
     }
 }
 
-#[cfg(feature = "fast")]
-pub static CONFIG: RenderConfig = RenderConfig::fast();
+/// The layers that are rendered next to the map: those of `--layers`, and those that other options imply.
+pub fn layers(config: &Config) -> Vec<Layer> {
+    let mut layers = config.layers.clone();
+    if !config.highlight_loc.is_empty() && !layers.contains(&Layer::Highlights) {
+        layers.push(Layer::Highlights);
+    }
+    if config.debug_grid && !layers.contains(&Layer::Grid) {
+        layers.push(Layer::Grid);
+    }
+    if config.debug_chunks && !layers.contains(&Layer::Chunks) {
+        layers.push(Layer::Chunks);
+    }
+    layers
+}
+
+/// A rectangle of the map, in game coordinates, as given by `--render-region`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x1: u32,
+    pub y1: u32,
+    pub x2: u32,
+    pub y2: u32,
+}
+
+impl Region {
+    /// Regions that can be given by name, rounded out to whole mapsquares.
+    pub const NAMED: &'static [(&'static str, Region)] = &[
+        ("lumbridge", Region::new(3136, 3136, 3263, 3263)),
+        ("draynor", Region::new(3072, 3200, 3199, 3263)),
+        ("varrock", Region::new(3136, 3328, 3327, 3519)),
+        ("falador", Region::new(2880, 3264, 3071, 3391)),
+        ("al_kharid", Region::new(3264, 3072, 3391, 3263)),
+    ];
+
+    pub const fn new(x1: u32, y1: u32, x2: u32, y2: u32) -> Self {
+        Self { x1, y1, x2, y2 }
+    }
+
+    /// The coordinates of the mapsquares that are (partially) inside this region.
+    pub fn mapsquares(&self) -> impl Iterator<Item = (u8, u8)> {
+        let range_i = (self.x1.min(self.x2) / 64)..=(self.x1.max(self.x2) / 64).min(u8::MAX as u32);
+        let range_j = (self.y1.min(self.y2) / 64)..=(self.y1.max(self.y2) / 64).min(u8::MAX as u32);
+        iproduct!(range_i, range_j).map(|(i, j)| (i as u8, j as u8))
+    }
+}
 
-#[cfg(not(feature = "fast"))]
-pub static CONFIG: RenderConfig = RenderConfig::detailed();
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(',') {
+            return match Self::NAMED.iter().find(|(name, _)| name.eq_ignore_ascii_case(s.trim())) {
+                Some((_, region)) => Ok(*region),
+                None => {
+                    let names = Self::NAMED.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ");
+                    Err(format!("expected x1,y1,x2,y2 or one of {names}"))
+                }
+            };
+        }
+        let coordinates = s
+            .split(',')
+            .map(|c| c.trim().parse::<u32>().map_err(|e| format!("{c:?} is not a coordinate: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        match coordinates[..] {
+            [x1, y1, x2, y2] => Ok(Self::new(x1, y1, x2, y2)),
+            _ => Err(format!("expected four coordinates, not {}", coordinates.len())),
+        }
+    }
+}
+
+/// Parses the coordinates of a mapsquare, as in `--mapsquare 50,50`.
+pub fn parse_mapsquare(arg: &str) -> Result<(u8, u8), String> {
+    let (i, j) = arg.split_once(',').ok_or("expected i,j")?;
+    let parse = |c: &str| c.trim().parse::<u8>().map_err(|e| format!("{c:?} is not a mapsquare coordinate: {e}"));
+    Ok((parse(i)?, parse(j)?))
+}
+
+/// The mapsquares selected by `--render-region`, `--mapsquare` and `--render-changed`, or `None` if the whole map is to be rendered.
+pub fn selection(config: &Config) -> CacheResult<Option<BTreeSet<(u8, u8)>>> {
+    if config.render_region.is_none() && config.mapsquare.is_empty() && config.render_changed.is_none() {
+        return Ok(None);
+    }
+    let region = config.render_region.iter().flat_map(Region::mapsquares);
+    let mut selection = region.chain(config.mapsquare.iter().copied()).collect::<BTreeSet<_>>();
+    if let Some(previous) = &config.render_changed {
+        selection.extend(changed::mapsquares(config, previous)?);
+    }
+    Ok(Some(selection))
+}
+
+/// The folder the map itself is written to.
+const NAME: &str = "mapsquares";
 
 /// Entry point for the map renderer.
+///
+/// Reports its progress to the listeners of [`progress`], including any error it fails with.
 pub fn render(config: &Config) -> CacheResult<()> {
-    const NAME: &str = "mapsquares";
+    let result = render_all(config);
+    if let Err(e) = &result {
+        progress::emit(Event::Error { message: e.to_string() });
+    }
+    result
+}
 
-    let map_id = CONFIG.map_id;
-    for zoom in 2..=4 {
-        let path = path!(config.output / NAME / format!("{map_id}/{zoom}"));
+fn render_all(config: &Config) -> CacheResult<()> {
+    if config.highlight_json {
+        highlights::export(config)?;
+    }
 
-        fs::create_dir_all(&path).context(error::Io { path })?;
+    #[cfg(feature = "rs3")]
+    if config.all_maps || config.render_config.map_id != -1 {
+        let definitions = Definitions::load(config)?;
+        let map_ids = if config.all_maps {
+            composite::map_ids(config)?
+        } else {
+            vec![config.render_config.map_id]
+        };
+        if config.all_maps {
+            render_map(
+                config,
+                &RenderConfig {
+                    map_id: -1,
+                    ..config.render_config
+                },
+            )?;
+        }
+        for map_id in map_ids {
+            if cancel::is_cancelled() {
+                break;
+            }
+            let render = RenderConfig {
+                map_id,
+                ..config.render_config
+            };
+            println!("    {} map {map_id}", console::style("Rendering").green().bright());
+            composite::render(config, NAME, &render, &definitions)?;
+            finish(config, &render, None)?;
+        }
+        if config.emit_viewer {
+            viewer::export(config, NAME)?;
+        }
+        return Ok(());
+    }
+
+    render_map(config, &config.render_config)?;
+    if config.emit_viewer {
+        viewer::export(config, NAME)?;
+    }
+    Ok(())
+}
+
+/// Renders the map as it is in the game, rather than a composite of it.
+fn render_map(config: &Config, render: &RenderConfig) -> CacheResult<()> {
+    let map_id = render.map_id;
+    let selection = selection(config)?;
+    let manifest = resume::Manifest::new(config, NAME, map_id)?;
+    let mut iter = match &selection {
+        Some(selection) => GroupMapSquareIterator::new_only(-1_i32..=1_i32, -1_i32..=1_i32, selection.iter().copied().collect(), config)?,
+        None => GroupMapSquareIterator::new(-1_i32..=1_i32, -1_i32..=1_i32, config)?,
+    };
+    if config.resume {
+        let total = iter.len();
+        iter = iter.retain(|coordinates| !manifest.is_done(coordinates));
+        println!(
+            "    {} {} mapsquares rendered earlier",
+            console::style("Skipping").cyan(),
+            total - iter.len()
+        );
     }
 
-    let iter = GroupMapSquareIterator::new(-1_i32..=1_i32, -1_i32..=1_i32, config)?;
+    inner_render(config, render, NAME, iter, Some(&manifest))?;
+    manifest.save(config)?;
 
-    inner_render(config, NAME, iter)?;
+    finish(config, render, selection.as_ref())
+}
 
-    zoom::render_zoom_levels(config, NAME, map_id, -4..2, Color::ALPHA)?;
+/// Renders `region` of the map with `config.render_config`, as one image per plane, without writing anything.
+///
+/// Each plane is composited with the planes below it and backfilled, as its tiles are written;
+/// with `--composite-planes`, the image of plane 3 is the one that is written as plane 0.
+/// The images are cropped to the region, with its north west corner at the top left. For comparing renders
+/// against earlier ones, see [`compare`](crate::renderers::compare).
+pub fn render_to_images(config: &Config, region: &Region) -> CacheResult<[Img; 4]> {
+    region_images(config, region, NAME, |squares, definitions| {
+        render_styled(&config.render_config, squares, definitions)
+    })
+}
+
+/// Renders `layer` of `region`, as [`render_to_images`] renders the map.
+pub fn render_layer_to_images(config: &Config, region: &Region, layer: Layer) -> CacheResult<[Img; 4]> {
+    region_images(config, region, layer.name(), |squares, definitions| {
+        render_layer(&config.render_config, layer, squares, definitions)
+    })
+}
+
+/// Renders the mapsquares of `region` with `draw`, and composites them as the tiles of `name` are.
+fn region_images(config: &Config, region: &Region, name: &str, draw: impl Fn(&GroupMapSquare, &Definitions) -> [Img; 4]) -> CacheResult<[Img; 4]> {
+    let render = &config.render_config;
+    let (width, height) = (
+        (region.x1.abs_diff(region.x2) + 1) * render.tile_size,
+        (region.y1.abs_diff(region.y2) + 1) * render.tile_size,
+    );
+    let mut imgs = [(); 4].map(|_| RgbaImage::from_pixel(width, height, Rgba(Color::ALPHA)));
+
+    let definitions = Definitions::load(config)?;
+    let iter = GroupMapSquareIterator::new_only(-1_i32..=1_i32, -1_i32..=1_i32, region.mapsquares().collect(), config)?;
+    for gsq in iter {
+        let planes = composite(config, render, &draw(&gsq, &definitions), 0..=3);
+        let planes = planes.into_iter().map(|img| backfilled(render, name, img));
+        place(render, region, (gsq.core_i(), gsq.core_j()), &mut imgs, planes);
+    }
+    Ok(imgs)
+}
+
+/// Copies each of `planes` of the mapsquare at `i, j` into the image of that plane of `region`, where they overlap.
+fn place(render: &RenderConfig, region: &Region, (i, j): (u8, u8), imgs: &mut [Img; 4], planes: impl IntoIterator<Item = Img>) {
+    let tile_size = render.tile_size as i64;
+    let (west, north) = (region.x1.min(region.x2) as i64, region.y1.max(region.y2) as i64);
+    // Where the north west corner of the mapsquare lands, which may be outside the region.
+    let left = (i as i64 * 64 - west) * tile_size;
+    let top = (north - (j as i64 * 64 + 63)) * tile_size;
+    for (img, plane) in imgs.iter_mut().zip(planes) {
+        imageops::replace(img, &plane, left, top);
+    }
+}
+
+/// Makes the zoom levels, archives and manifests of the map and its layers, once their mapsquares are rendered.
+fn finish(config: &Config, render: &RenderConfig, selection: Option<&BTreeSet<(u8, u8)>>) -> CacheResult<()> {
+    let map_id = render.map_id;
+    let mut names = vec![NAME];
+    names.extend(layers(config).into_iter().map(Layer::name));
+
+    for name in names {
+        // The layers are drawn over the map, so only the map itself is backfilled.
+        let backfill = if name == NAME { render.backfill } else { None }.unwrap_or(Color::ALPHA);
+        // A cancelled render skips the zoom levels, so that they are made from every mapsquare once it is resumed.
+        let cancelled = cancel::is_cancelled();
+        if !cancelled {
+            match selection {
+                // The other tiles are left as they were, so only the ones containing a selected mapsquare are remade.
+                Some(selection) => zoom::render_zoom_levels_of(config, name, map_id, -4..2, backfill, selection)?,
+                None => zoom::render_zoom_levels(config, name, map_id, -4..2, backfill)?,
+            }
+        }
+
+        // Before the tiles are trimmed, as archives expect tiles of the same size.
+        if config.stitch && !cancel::is_cancelled() {
+            let region = config.stitch_region.or(config.render_region);
+            stitch::export(config, name, map_id, config.stitch_zoom.min(render.initial_zoom), region.as_ref())?;
+        }
+        if config.pmtiles && !cancel::is_cancelled() {
+            pmtiles::export(config, name, map_id, -4..=render.initial_zoom)?;
+        }
+        if (config.dzi || config.iiif.is_some()) && !cancel::is_cancelled() {
+            deepzoom::export(config, name, map_id, -4..=render.initial_zoom)?;
+        }
+        png::forget_sources(path!(name / format!("{map_id}")));
+
+        if config.tile_manifest {
+            // Zoom levels left over from an earlier run would not match the tiles written by this one.
+            let lowest = if cancelled { 2 } else { -4 };
+            trim::export_manifest(config, name, map_id, lowest..=render.initial_zoom)?;
+        }
+    }
     Ok(())
 }
 
 // Separated for use in tests.
 
-fn inner_render(config: &Config, name: &str, iter: GroupMapSquareIterator) -> CacheResult<()> {
-    let location_definitions = LocationConfig::dump_all(config)?;
+fn inner_render(
+    config: &Config,
+    render_config: &RenderConfig,
+    name: &str,
+    iter: GroupMapSquareIterator,
+    manifest: Option<&resume::Manifest>,
+) -> CacheResult<()> {
+    let definitions = Definitions::load(config)?;
+
+    let total = iter.len();
+    let rendered = AtomicUsize::new(0);
+    let start = Instant::now();
+    progress::emit(Event::Started {
+        name: name.to_string(),
+        total,
+    });
+    let render = |gsq: GroupMapSquare| -> CacheResult<()> {
+        // Tiles that are already being rendered are finished, the rest are skipped.
+        if cancel::is_cancelled() {
+            return Ok(());
+        }
+        let (i, j) = (gsq.core_i(), gsq.core_j());
+        render_tile(config, render_config, name, gsq, &definitions);
+        let done = rendered.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(manifest) = manifest {
+            manifest.record(config, i, j)?;
+        }
+        progress::emit(Event::Square {
+            i,
+            j,
+            done,
+            total,
+            eta_secs: Event::eta(start.elapsed(), done, total),
+        });
+        Ok(())
+    };
 
+    match config.memory_limit {
+        Some(limit) => {
+            let threads = low_memory_threads(limit, &config.render_config);
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("unable to start render threads");
+            pool.install(|| iter.progress().par_bridge().try_for_each(render))?;
+        }
+        None => iter.progress().par_bridge().try_for_each(render)?,
+    }
+
+    let rendered = rendered.into_inner();
+    progress::emit(Event::Finished {
+        name: name.to_string(),
+        done: rendered,
+        elapsed_secs: start.elapsed().as_secs_f64(),
+    });
+    if cancel::is_cancelled() {
+        println!(
+            "    {} after rendering {rendered} of {total} mapsquares",
+            console::style("Cancelled").yellow(),
+        );
+    }
+    Ok(())
+}
+
+/// Everything besides the mapsquares themselves that is needed to render the map.
+pub struct Definitions {
+    pub location_configs: BTreeMap<u32, LocationConfig>,
     #[cfg(any(feature = "rs3", feature = "osrs"))]
-    let overlay_definitions = Overlay::dump_all(config)?;
+    pub overlays: BTreeMap<u32, Overlay>,
     #[cfg(any(feature = "rs3", feature = "osrs"))]
-    let underlay_definitions = Underlay::dump_all(config)?;
-
+    pub underlays: BTreeMap<u32, Underlay>,
     #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
-    let mapscenes = MapScene::dump_all(config)?;
+    pub mapscenes: BTreeMap<u32, MapScene>,
+    #[cfg(feature = "legacy")]
+    pub flos: BTreeMap<u32, Flo>,
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub map_labels: BTreeMap<u32, MapLabelConfig>,
+    pub sprites: BTreeMap<(u32, u32), Sprite>,
+    pub palette: palette::Palette,
+    pub markers: markers::Markers,
+    /// The ids of `--highlight-loc`.
+    pub highlights: BTreeSet<u32>,
+    /// The font of the game's map labels, if the cache has it.
+    pub font: Option<Font>,
+    /// The gpu that draws the ground, with `--gpu`.
+    #[cfg(feature = "gpu")]
+    pub gpu: Option<&'static gpu::Gpu>,
+}
 
-    #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
-    let sprites = sprites::dumps(
-        CONFIG.scale,
-        mapscenes.values().filter_map(|mapscene| mapscene.sprite_id).collect::<Vec<_>>(),
-        config,
-    )?;
+impl Definitions {
+    /// Loads the definitions used by the map renderer.
+    pub fn load(config: &Config) -> CacheResult<Self> {
+        let location_configs = LocationConfig::dump_all(config)?;
 
-    #[cfg(all(feature = "osrs", not(feature = "2009_1_shim")))]
-    let sprites = sprites::dumps(CONFIG.scale, vec![317], config)?; // 317 is the sprite named "mapscene"
+        let palette = config.palette.clone().unwrap_or_default();
 
-    #[cfg(feature = "legacy")]
-    let sprites: BTreeMap<(u32, u32), Sprite> = sprites::get_mapscenes(CONFIG.scale, config)?;
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        let mut overlays = Overlay::dump_all(config)?;
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        let mut underlays = Underlay::dump_all(config)?;
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        palette.apply(&mut underlays, &mut overlays);
 
-    #[cfg(feature = "legacy")]
-    let flos = Flo::dump_all(config)?;
+        #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
+        let mapscenes = MapScene::dump_all(config)?;
 
-    iter.progress().par_bridge().for_each(|gsq| {
-        render_tile(
+        #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
+        let sprites = sprites::dumps(
+            config.render_config.scale,
+            mapscenes.values().filter_map(|mapscene| mapscene.sprite_id).collect::<Vec<_>>(),
             config,
-            name,
-            gsq,
-            &location_definitions,
+        )?;
+
+        #[cfg(all(feature = "osrs", not(feature = "2009_1_shim")))]
+        let sprites = sprites::dumps(config.render_config.scale, vec![317], config)?; // 317 is the sprite named "mapscene"
+
+        #[cfg(feature = "legacy")]
+        let sprites: BTreeMap<(u32, u32), Sprite> = {
+            let mut sprites = sprites::get_mapscenes(config.render_config.scale, config)?;
+            sprites.extend(sprites::get_mapfunctions(config.render_config.scale, config)?);
+            sprites
+        };
+
+        #[cfg(feature = "legacy")]
+        let mut flos = Flo::dump_all(config)?;
+        #[cfg(feature = "legacy")]
+        palette.apply(&mut flos);
+
+        // Only the icons and labels layers draw map labels.
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        let map_labels = if config.layers.contains(&Layer::Icons) || config.layers.contains(&Layer::Labels) {
+            MapLabelConfig::dump_all(config)?
+        } else {
+            BTreeMap::new()
+        };
+
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        let sprites = {
+            let mut sprites = sprites;
+            if config.layers.contains(&Layer::Icons) {
+                let ids = map_labels.values().filter_map(|label| label.sprite).collect::<BTreeSet<_>>();
+                sprites.extend(sprites::dumps(config.render_config.scale, ids.into_iter().collect(), config)?);
+            }
+            sprites
+        };
+
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        let font = Font::find(config, fonts::LABELS)?;
+        #[cfg(feature = "legacy")]
+        let font = None;
+
+        Ok(Self {
+            location_configs,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
-            &overlay_definitions,
+            overlays,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
-            &underlay_definitions,
+            underlays,
             #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
-            &mapscenes,
+            mapscenes,
             #[cfg(feature = "legacy")]
-            &flos,
-            &sprites,
-        );
-    });
-    Ok(())
+            flos,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            map_labels,
+            sprites,
+            palette,
+            markers: config.markers.clone().unwrap_or_default(),
+            highlights: config.highlight_loc.iter().copied().collect(),
+            font,
+            #[cfg(feature = "gpu")]
+            gpu: config.gpu.then(gpu::get).flatten(),
+        })
+    }
+}
+
+/// Roughly what the definitions, sprites and the cache index take up while rendering, in MiB.
+const DEFINITIONS_FOOTPRINT: u64 = 384;
+
+/// Roughly what a single [`render_tile`] call has in flight, in MiB:
+/// a [`GroupMapSquare`] of nine mapsquares, an image for each plane and the composited image being saved.
+///
+/// The layers of `--layers` do not add to it: they are rendered one after another,
+/// and the images of the map and of each layer are saved and dropped before the next is rendered.
+const fn tile_footprint(render: &RenderConfig) -> u64 {
+    9 + 5 * (render.dim() as u64 * render.dim() as u64 * 4) / (1024 * 1024)
+}
+
+/// How many mapsquares can be rendered at once without going over `limit` MiB.
+///
+/// Always at least one, so the renderer can make progress no matter the limit.
+fn low_memory_threads(limit: u64, render: &RenderConfig) -> usize {
+    let budget = limit.saturating_sub(DEFINITIONS_FOOTPRINT) / tile_footprint(render);
+    (budget as usize).clamp(1, rayon::current_num_threads())
 }
 
 /// Responsible for rendering a single [`MapSquare`](crate::definitions::mapsquares::MapSquare).
-pub fn render_tile(
-    config: &Config,
-    name: &str,
-    squares: GroupMapSquare,
-    location_config: &BTreeMap<u32, LocationConfig>,
-    #[cfg(any(feature = "rs3", feature = "osrs"))] overlay_definitions: &BTreeMap<u32, Overlay>,
-    #[cfg(any(feature = "rs3", feature = "osrs"))] underlay_definitions: &BTreeMap<u32, Underlay>,
-    #[cfg(any(feature = "rs3", feature = "2009_1_shim"))] mapscenes: &BTreeMap<u32, MapScene>,
-    #[cfg(feature = "legacy")] flos: &BTreeMap<u32, Flo>,
-    sprites: &BTreeMap<(u32, u32), Sprite>,
-) {
+pub fn render_tile(config: &Config, render: &RenderConfig, name: &str, squares: GroupMapSquare, definitions: &Definitions) {
+    let imgs = render_styled(render, &squares, definitions);
+    save_smallest(config, render, name, squares.core_i(), squares.core_j(), imgs);
+
+    for layer in layers(config) {
+        let imgs = render_layer(render, layer, &squares, definitions);
+        save_smallest(config, render, layer.name(), squares.core_i(), squares.core_j(), imgs);
+    }
+}
+
+/// Renders every plane of the core [`MapSquare`](crate::definitions::mapsquares::MapSquare) of `squares` in the style of `render`.
+pub fn render_styled(render: &RenderConfig, squares: &GroupMapSquare, definitions: &Definitions) -> [Img; 4] {
+    let mut imgs = match render.style {
+        Style::Map => render_planes(render, squares, definitions),
+        Style::Minimap => minimap::render_planes(render, squares, definitions),
+    };
+    definitions.markers.put(render, squares, &mut imgs, definitions.font.as_ref());
+    imgs
+}
+
+/// Renders every plane of `layer` of the core [`MapSquare`](crate::definitions::mapsquares::MapSquare) of `squares`,
+/// on a transparent background.
+pub fn render_layer(render: &RenderConfig, layer: Layer, squares: &GroupMapSquare, definitions: &Definitions) -> [Img; 4] {
+    let func = |plane| {
+        let mut img = RgbaImage::from_pixel(render.dim(), render.dim(), Rgba(Color::ALPHA));
+        match layer {
+            Layer::Base => {
+                base::put(
+                    render,
+                    plane,
+                    &mut img,
+                    squares,
+                    #[cfg(any(feature = "rs3", feature = "osrs"))]
+                    &definitions.underlays,
+                    #[cfg(any(feature = "rs3", feature = "osrs"))]
+                    &definitions.overlays,
+                    #[cfg(feature = "legacy")]
+                    &definitions.flos,
+                    #[cfg(feature = "gpu")]
+                    definitions.gpu,
+                );
+                base::shade(render, plane, &mut img, squares);
+            }
+            Layer::Walls => lines::put_coloured(
+                render,
+                plane,
+                &mut img,
+                squares,
+                &definitions.location_configs,
+                definitions.palette.wall(Color::WHITE),
+                definitions.palette.door(Color::PURE_RED),
+            ),
+            Layer::Grid => grid::put(render, &mut img, squares.core_i(), squares.core_j(), definitions.font.as_ref()),
+            Layer::Chunks => grid::put_chunks(
+                render,
+                &mut img,
+                squares.core().map_or(false, MapSquare::is_missing_locations),
+                definitions.font.as_ref(),
+            ),
+            Layer::Highlights => highlights::put(render, plane, &mut img, squares, &definitions.location_configs, &definitions.highlights),
+            Layer::Mapscenes => {
+                mapscenes::put(
+                    render,
+                    plane,
+                    &mut img,
+                    squares,
+                    &definitions.location_configs,
+                    #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
+                    &definitions.mapscenes,
+                    &definitions.sprites,
+                );
+                #[cfg(feature = "legacy")]
+                mapfunctions::put(render, plane, &mut img, squares, &definitions.location_configs, &definitions.sprites);
+            }
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Layer::Icons => icons::put_icons(
+                render,
+                plane,
+                &mut img,
+                squares,
+                &definitions.location_configs,
+                &definitions.map_labels,
+                &definitions.sprites,
+            ),
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            Layer::Labels => icons::put_labels(
+                render,
+                plane,
+                &mut img,
+                squares,
+                &definitions.location_configs,
+                &definitions.map_labels,
+                definitions.font.as_ref(),
+            ),
+        }
+        img
+    };
+
+    [func(0), func(1), func(2), func(3)]
+}
+
+/// Renders every plane of the core [`MapSquare`](crate::definitions::mapsquares::MapSquare) of `squares`, without saving them.
+pub fn render_planes(render: &RenderConfig, squares: &GroupMapSquare, definitions: &Definitions) -> [Img; 4] {
     let func = |plane| {
         let backfill = Rgba(Color::ALPHA);
 
-        let mut img = RgbaImage::from_pixel(CONFIG.dim, CONFIG.dim, backfill);
+        let mut img = RgbaImage::from_pixel(render.dim(), render.dim(), backfill);
 
         base::put(
+            render,
             plane,
             &mut img,
-            &squares,
+            squares,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
-            underlay_definitions,
+            &definitions.underlays,
             #[cfg(any(feature = "rs3", feature = "osrs"))]
-            overlay_definitions,
+            &definitions.overlays,
             #[cfg(feature = "legacy")]
-            flos,
+            &definitions.flos,
+            #[cfg(feature = "gpu")]
+            definitions.gpu,
+        );
+        base::shade(render, plane, &mut img, squares);
+        lines::put_coloured(
+            render,
+            plane,
+            &mut img,
+            squares,
+            &definitions.location_configs,
+            definitions.palette.wall(Color::WHITE),
+            definitions.palette.door(Color::PURE_RED),
         );
-        lines::put(plane, &mut img, &squares, location_config);
         mapscenes::put(
+            render,
             plane,
             &mut img,
-            &squares,
-            location_config,
+            squares,
+            &definitions.location_configs,
             #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
-            mapscenes,
-            sprites,
+            &definitions.mapscenes,
+            &definitions.sprites,
         );
+        #[cfg(feature = "legacy")]
+        mapfunctions::put(render, plane, &mut img, squares, &definitions.location_configs, &definitions.sprites);
         img
     };
 
-    let imgs = [func(0), func(1), func(2), func(3)];
+    [func(0), func(1), func(2), func(3)]
+}
+
+pub type Img = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
-    #[cfg(test)]
-    {
-        let filename = format!("test_data/tiles/{}_{}_{}.png", 0, squares.core_i(), squares.core_j());
-        imgs[0].save(filename).unwrap();
+/// Composites each of `planes` of `imgs` as it is seen from above, on the gpu with `--gpu`.
+///
+/// Transparent pixels show the planes below, dimmed by `--lower-plane-brightness`, unless `--hide-lower-planes` is given.
+/// With `--composite-planes` they are not dimmed, as the planes make up one map.
+#[cfg_attr(not(feature = "gpu"), allow(unused_variables))]
+fn composite(config: &Config, render: &RenderConfig, imgs: &[Img; 4], planes: RangeInclusive<usize>) -> Vec<RgbaImage> {
+    if render.hide_lower_planes {
+        return planes.map(|plane| imgs[plane].clone()).collect();
     }
+    let brightness = if render.composite_planes { 100 } else { render.lower_plane_brightness };
+    #[cfg(feature = "gpu")]
+    if config.gpu {
+        if let Some(composited) = gpu::get().and_then(|gpu| gpu.composite(imgs, planes.clone(), brightness)) {
+            return composited;
+        }
+    }
+    let dim = render.dim();
+    planes
+        .map(|plane| {
+            RgbaImage::from_fn(dim, dim, |x, y| {
+                let mut i = (0..=plane).rev();
 
-    save_smallest(config, name, squares.core_i(), squares.core_j(), imgs);
-}
+                loop {
+                    // SAFETY (1): this will always be valid....
+                    let p = unsafe { i.next().unwrap_unchecked() };
 
-type Img = ImageBuffer<Rgba<u8>, Vec<u8>>;
+                    // SAFETY (2):..these getters are always valid.
+                    let pixel = unsafe { imgs.get_unchecked(p).unsafe_get_pixel(x, y) };
 
-pub fn save_smallest(config: &Config, name: &str, i: u8, j: u8, imgs: [Img; 4]) {
-    let map_id = CONFIG.map_id;
+                    // SAFETY (1): ...as this exit condition always exits the loop if p == 0.
+                    if p == 0 || pixel[3] != 0 {
+                        break if p == plane {
+                            pixel
+                        } else {
+                            pixel.map_without_alpha(|channel| (channel as u32 * brightness as u32 / 100) as u8)
+                        };
+                    }
+                }
+            })
+        })
+        .collect()
+}
 
-    // SAFETY (2) these checks assure that...
-    assert_eq!(CONFIG.dim % 4, 0);
-    for img in &imgs {
-        assert_eq!(img.dimensions(), (CONFIG.dim, CONFIG.dim));
+/// `img` drawn over `--backfill`, if it is given and `img` is a tile of the map rather than of one of its layers.
+fn backfilled(render: &RenderConfig, name: &str, mut img: RgbaImage) -> RgbaImage {
+    if let Some(backfill) = render.backfill.filter(|_| name == NAME) {
+        for pixel in img.pixels_mut() {
+            let mut filled = Rgba(backfill);
+            filled.blend(pixel);
+            *pixel = filled;
+        }
     }
+    img
+}
 
-    for plane in 0..=3 {
-        let base = RgbaImage::from_fn(CONFIG.dim, CONFIG.dim, |x, y| {
-            let mut i = (0..=plane).rev();
+/// Whether a tile with `pixels` is written, which it is if anything covers it or with `--keep-empty-tiles`.
+fn is_kept(render: &RenderConfig, mut pixels: impl Iterator<Item = Rgba<u8>>) -> bool {
+    render.keep_empty_tiles || pixels.any(|pixel| pixel[3] != 0)
+}
 
-            loop {
-                // SAFETY (1): this will always be valid....
-                let p = unsafe { i.next().unwrap_unchecked() };
+/// Writes a tile of [`save_smallest`]. A tile that cannot be written is reported as a warning and a [progress event](Event::TileFailed),
+/// and left out rather than failing the render.
+fn put_tile(config: &Config, filename: &Path, img: &RgbaImage) {
+    if let Err(e) = png::put_tile(config, filename, img) {
+        let path = filename.to_string_lossy().into_owned();
+        let message = e.to_string();
+        warning::warn(Warning::TileFailed {
+            path: path.clone(),
+            message: message.clone(),
+        });
+        progress::emit(Event::TileFailed { path, message });
+    }
+}
 
-                // SAFETY (2):..these getters are always valid.
-                let pixel = unsafe { imgs.get_unchecked(p).unsafe_get_pixel(x, y) };
+pub fn save_smallest(config: &Config, render: &RenderConfig, name: &str, i: u8, j: u8, imgs: [Img; 4]) {
+    let map_id = render.map_id;
+    let dim = render.dim();
+    let extension = config.tile_format.extension();
 
-                // SAFETY (1): ...as this exit condition always exits the loop if p == 0.
-                if p == 0 || pixel[3] != 0 {
-                    break if p == plane {
-                        pixel
-                    } else {
-                        pixel.map_without_alpha(|channel| channel / 2)
-                    };
-                }
-            }
-        });
+    // SAFETY (2) these checks assure that...
+    assert_eq!(dim % 4, 0);
+    for img in &imgs {
+        assert_eq!(img.dimensions(), (dim, dim));
+    }
+
+    // With `--composite-planes` only the view from the highest plane is written, as if it were plane 0.
+    let planes = if render.composite_planes { 3..=3 } else { 0..=3 };
+    for (plane, base) in planes.clone().zip(composite(config, render, &imgs, planes)) {
+        let tile_plane = if render.composite_planes { 0 } else { plane };
 
-        if CONFIG.initial_zoom >= 4 {
+        if render.initial_zoom >= 4 {
             let base_i = i as u32 * 4;
             let base_j = j as u32 * 4;
-            for (x, y) in iproduct!(0..4u32, 0..4u32) {
-                let sub_image = base.view(
-                    (CONFIG.dim / 4) * x,
-                    CONFIG.dim - (CONFIG.dim / 4) * (y + 1),
-                    CONFIG.dim / 4,
-                    CONFIG.dim / 4,
-                );
-                debug_assert_eq!(sub_image.width(), 256);
-                debug_assert_eq!(sub_image.height(), 256);
+            iproduct!(0..4u32, 0..4u32).par_bridge().for_each(|(x, y)| {
+                let sub_image = base.view((dim / 4) * x, dim - (dim / 4) * (y + 1), dim / 4, dim / 4);
+                debug_assert_eq!(sub_image.width(), dim / 4);
+                debug_assert_eq!(sub_image.height(), dim / 4);
 
-                if cfg!(not(test)) && sub_image.pixels().any(|(_, _, pixel)| pixel[3] != 0)
-                /* don't save useless tiles */
-                {
+                let sub_image = sub_image.to_image();
+                if cfg!(not(test)) && is_kept(render, sub_image.pixels().copied()) {
                     let xx = base_i + x;
                     let yy = base_j + y;
-                    let filename = path!(config.output / name / format!("{map_id}/4/{plane}_{xx}_{yy}.png"));
-                    sub_image.to_image().save(filename).unwrap();
+                    let filename = path!(name / format!("{map_id}/4/{tile_plane}_{xx}_{yy}.{extension}"));
+                    put_tile(config, &filename, &backfilled(render, name, sub_image));
                 }
-            }
+            });
         }
 
-        if CONFIG.initial_zoom >= 3 {
+        if render.initial_zoom >= 3 {
             let base_i = i as u32 * 2;
             let base_j = j as u32 * 2;
             for (x, y) in iproduct!(0..2u32, 0..2u32) {
-                let sub_image = base.view(
-                    (CONFIG.dim / 2) * x,
-                    CONFIG.dim - (CONFIG.dim / 2) * (y + 1),
-                    CONFIG.dim / 2,
-                    CONFIG.dim / 2,
-                );
+                let sub_image = base.view((dim / 2) * x, dim - (dim / 2) * (y + 1), dim / 2, dim / 2);
 
-                if cfg!(not(test)) && sub_image.pixels().any(|(_, _, pixel)| pixel[3] != 0)
-                /* don't save useless tiles */
-                {
+                if cfg!(not(test)) && is_kept(render, sub_image.pixels().map(|(_, _, pixel)| pixel)) {
                     let resized = scale::resize_half(*sub_image);
 
-                    debug_assert_eq!(resized.width(), 256);
-                    debug_assert_eq!(resized.height(), 256);
+                    debug_assert_eq!(resized.width(), dim / 4);
+                    debug_assert_eq!(resized.height(), dim / 4);
                     let xx = base_i + x;
                     let yy = base_j + y;
-                    let filename = path!(config.output / name / format!("{map_id}/3/{plane}_{xx}_{yy}.png"));
-                    resized.save(filename).unwrap();
+                    let filename = path!(name / format!("{map_id}/3/{tile_plane}_{xx}_{yy}.{extension}"));
+                    put_tile(config, &filename, &backfilled(render, name, resized));
                 }
             }
         }
 
-        if CONFIG.initial_zoom >= 2 {
+        if render.initial_zoom >= 2 {
             let base_i = i as u32;
             let base_j = j as u32;
 
-            let resized = scale::resize_quarter(base);
+            let resized = scale::downscale(config, base, 4);
 
-            debug_assert_eq!(resized.width(), 256);
-            debug_assert_eq!(resized.height(), 256);
+            debug_assert_eq!(resized.width(), dim / 4);
+            debug_assert_eq!(resized.height(), dim / 4);
 
-            if cfg!(not(test)) && resized.pixels().any(|&pixel| pixel[3] != 0)
-            /* don't save useless tiles */
-            {
-                let filename = path!(config.output / name / format!("{map_id}/2/{plane}_{base_i}_{base_j}.png"));
-                resized.save(filename).unwrap();
+            if cfg!(not(test)) && is_kept(render, resized.pixels().copied()) {
+                let filename = path!(name / format!("{map_id}/2/{tile_plane}_{base_i}_{base_j}.{extension}"));
+                put_tile(config, &filename, &backfilled(render, name, resized));
             }
         }
     }
@@ -312,7 +1101,7 @@ pub fn render_bench() -> CacheResult<()> {
     let coordinates: Vec<(u8, u8)> = iproduct!(45..55, 45..55).collect();
 
     let iter = GroupMapSquareIterator::new_only(-1_i32..=1_i32, -1_i32..=1_i32, coordinates, &config)?;
-    inner_render(&config, path, iter)?;
+    inner_render(&config, &config.render_config, path, iter, None)?;
 
     Ok(())
 }
@@ -320,17 +1109,127 @@ pub fn render_bench() -> CacheResult<()> {
 #[cfg(all(test, feature = "rs3"))]
 mod map_tests {
     use super::*;
+    use crate::renderers::compare;
+
+    #[test]
+    fn region() {
+        let varrock = "varrock".parse::<Region>().unwrap();
+        assert_eq!(varrock, "3136, 3328, 3327, 3519".parse().unwrap());
+        assert_eq!(varrock.mapsquares().count(), 9);
+        assert_eq!(Region::new(3263, 3200, 3200, 3264).mapsquares().collect::<Vec<_>>(), [(50, 50), (50, 51)]);
+        assert!("3136,3328".parse::<Region>().is_err());
+        assert!("atlantis".parse::<Region>().is_err());
+        assert_eq!(parse_mapsquare("50, 51"), Ok((50, 51)));
+    }
+
+    #[test]
+    fn colours() {
+        assert_eq!(parse_colour("#ff8000"), Ok([255, 128, 0, 255]));
+        assert_eq!(parse_colour("ff800080"), Ok([255, 128, 0, 128]));
+        assert!(parse_colour("#ff80").is_err());
+        assert!(parse_colour("#gg8000").is_err());
+    }
+
+    #[test]
+    fn tile_policies() {
+        let empty = RgbaImage::from_pixel(4, 4, Rgba(Color::ALPHA));
+        let render = RenderConfig::default();
+        assert!(!is_kept(&render, empty.pixels().copied()));
+        assert!(is_kept(
+            &RenderConfig {
+                keep_empty_tiles: true,
+                ..render
+            },
+            empty.pixels().copied()
+        ));
+
+        assert_eq!(backfilled(&render, NAME, empty.clone()), empty);
+        let black = RenderConfig {
+            backfill: Some([0, 0, 0, 255]),
+            ..render
+        };
+        let mut img = empty;
+        img.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        assert_eq!(backfilled(&black, Layer::Walls.name(), img.clone()), img);
+        let filled = backfilled(&black, NAME, img);
+        assert_eq!(filled.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(filled.get_pixel(1, 0), &Rgba([0, 0, 0, 255]));
+    }
 
     #[test]
-    #[ignore]
-    fn render_some() -> CacheResult<()> {
+    fn composited_planes() {
         let config = Config::default();
+        let render = RenderConfig {
+            tile_size: 1,
+            ..RenderConfig::default()
+        };
+        let mut imgs: [Img; 4] = std::array::from_fn(|_| RgbaImage::new(render.dim(), render.dim()));
+        imgs[0].put_pixel(0, 0, Rgba([200, 100, 50, 255]));
+        imgs[2].put_pixel(1, 0, Rgba([10, 20, 30, 255]));
+
+        let dimmed = composite(&config, &render, &imgs, 3..=3).remove(0);
+        assert_eq!(dimmed.get_pixel(0, 0), &Rgba([100, 50, 25, 255]));
+
+        let composited = composite(
+            &config,
+            &RenderConfig {
+                composite_planes: true,
+                ..render
+            },
+            &imgs,
+            3..=3,
+        )
+        .remove(0);
+        assert_eq!(composited.get_pixel(0, 0), &Rgba([200, 100, 50, 255]));
+        assert_eq!(composited.get_pixel(1, 0), &Rgba([10, 20, 30, 255]));
+        assert_eq!(composited.get_pixel(2, 0)[3], 0);
+    }
+
+    #[test]
+    fn memory_limit() {
+        let render = RenderConfig::default();
+        assert_eq!(low_memory_threads(0, &render), 1);
+        assert_eq!(
+            low_memory_threads(DEFINITIONS_FOOTPRINT + 2 * tile_footprint(&render), &render),
+            2.min(rayon::current_num_threads())
+        );
+        assert_eq!(low_memory_threads(u64::MAX, &render), rayon::current_num_threads());
 
-        let path = "test_data/tiles";
-        fs::create_dir_all(path).unwrap();
-        let coordinates: Vec<(u8, u8)> = vec![(50, 50), (41, 63), (47, 50), (56, 49), (34, 66), (33, 72), (49, 108), (43, 46)];
+        // Bigger tiles take more memory, so fewer are rendered at once.
+        let detailed = RenderConfig { tile_size: 32, ..render };
+        assert!(tile_footprint(&detailed) > tile_footprint(&render));
+    }
+
+    #[test]
+    fn placed() {
+        let render = RenderConfig {
+            tile_size: 1,
+            ..RenderConfig::default()
+        };
+        // Half of mapsquare 50, 50 and half of 51, 50.
+        let region = Region::new(3232, 3200, 3295, 3263);
+        let mut imgs: [Img; 4] = std::array::from_fn(|_| RgbaImage::new(64, 64));
+        let filled = |colour| std::array::from_fn::<Img, 4, _>(|_| RgbaImage::from_pixel(64, 64, Rgba(colour)));
+        place(&render, &region, (50, 50), &mut imgs, filled([255, 0, 0, 255]));
+        place(&render, &region, (51, 50), &mut imgs, filled([0, 0, 255, 255]));
+        assert_eq!(imgs[3].get_pixel(31, 63), &Rgba([255, 0, 0, 255]));
+        assert_eq!(imgs[3].get_pixel(32, 0), &Rgba([0, 0, 255, 255]));
+    }
 
-        let iter = GroupMapSquareIterator::new_only(-1_i32..=1_i32, -1_i32..=1_i32, coordinates, &config)?;
-        inner_render(&config, path, iter)
+    /// Renders some mapsquares, and compares them against the goldens in `test_data/golden`, which are made if they are missing.
+    #[test]
+    fn golden() -> CacheResult<()> {
+        let mut config = Config::default();
+        config.render_config.tile_size = 4;
+        fs::create_dir_all("test_data/golden").unwrap();
+        for (i, j) in [(50, 50), (41, 63), (47, 50), (56, 49), (34, 66), (33, 72), (49, 108), (43, 46)] {
+            let (x, y) = (i * 64, j * 64);
+            let planes = render_to_images(&config, &Region::new(x, y, x + 63, y + 63))?;
+            for (plane, img) in planes.iter().enumerate() {
+                let difference = compare::compare_golden(img, format!("test_data/golden/{i}_{j}_{plane}.png"), 0)?;
+                assert!(difference.is_none(), "{i}_{j}_{plane} differs: {difference:?}");
+            }
+        }
+        Ok(())
     }
 }
@@ -1,199 +1,421 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, f32::consts::FRAC_1_SQRT_2};
 
 use image::{GenericImage, Rgba, RgbaImage};
+#[cfg(feature = "gpu")]
+use itertools::iproduct;
 use ndarray::{ArrayBase, Dim, ViewRepr};
 
-use super::{tileshape, CONFIG};
+use super::{tileshape, Blending, RenderConfig, Roofs};
 #[cfg(feature = "legacy")]
 use crate::definitions::flo::Flo;
 use crate::definitions::{mapsquares::GroupMapSquare, tiles::Tile};
 #[cfg(any(feature = "rs3", feature = "osrs"))]
 use crate::definitions::{overlays::Overlay, underlays::Underlay};
+#[cfg(feature = "gpu")]
+use crate::renderers::gpu::Gpu;
 
-/// Applies ground colouring to the base image.
+/// The overlay that is water on rs3, which [`shoreline`] colours with `--water-colour`.
+#[cfg(feature = "rs3")]
+pub const WATER: u32 = 111;
+
+/// The texture of the overlays that are water on osrs, where water has no overlay of its own.
+#[cfg(feature = "osrs")]
+pub const WATER_TEXTURE: u8 = 1;
+
+/// The setting of plane 1 tiles that are a bridge, which the game draws a plane lower along with every plane above them.
+pub const BRIDGE: u8 = 0x2;
+
+/// The setting of tiles that are under a roof, which the game removes when the player stands on them.
+pub const ROOFED: u8 = 0x4;
+
+/// The setting of tiles that are also drawn on plane 0, such as the tops of tall buildings.
+pub const VISIBLE_BELOW: u8 = 0x8;
+
+/// Whether a column of tiles with `settings` is a bridge. As in the game, only the setting of plane 1 counts.
+pub fn is_bridge(settings: [u8; 4]) -> bool {
+    settings[1] & BRIDGE != 0
+}
+
+/// Whether plane `p` of a column of tiles with `settings` is drawn in the image of `plane`.
+///
+/// On a bridge, plane 1 is drawn over plane 0, and every plane above it is drawn a plane lower.
+fn drawn(plane: usize, p: usize, settings: [u8; 4]) -> bool {
+    let bridge = is_bridge(settings);
+    (p == 0 && plane == 0) || (p == plane && !bridge) || (p == plane + 1 && bridge) || (plane == 0 && settings[p] & VISIBLE_BELOW != 0)
+}
+
+/// Whether plane `p` of a column of tiles with `settings` is left out of the image of `plane`, because it is a roof.
+///
+/// Bridges, which are drawn a plane lower, are never left out.
+fn clipped(roofs: Roofs, plane: usize, p: usize, settings: [u8; 4]) -> bool {
+    let bridge = is_bridge(settings);
+    let roofed = |p: usize| settings[p] & ROOFED != 0;
+    match roofs {
+        Roofs::Keep => false,
+        Roofs::Clip => p > plane && !bridge && roofed(plane),
+        Roofs::Remove => (p > plane && !bridge && roofed(plane)) || (p == plane && p > 0 && !bridge && roofed(p - 1)),
+    }
+}
+
+/// What is drawn on a tile of the image of a plane, for one of the planes of its column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroundLayer {
+    /// The plane of the column it is from.
+    pub plane: usize,
+    /// The shape of its overlay. The underlay covers the rest of the tile, or all of it without a shape.
+    pub shape: Option<u8>,
+    /// The colour of its underlay, blended with those around it and brightened.
+    /// When the gpu blends them, it is the colour of this tile alone.
+    pub underlay: Option<Rgba<u8>>,
+    /// The colour of its overlay, brightened.
+    pub overlay: Option<Rgba<u8>>,
+}
+
+/// Applies ground colouring to the base image, on `gpu` if it is given and can.
 pub fn put(
+    render: &RenderConfig,
     plane: usize,
     img: &mut RgbaImage,
     squares: &GroupMapSquare,
     #[cfg(any(feature = "rs3", feature = "osrs"))] underlay_definitions: &BTreeMap<u32, Underlay>,
     #[cfg(any(feature = "rs3", feature = "osrs"))] overlay_definitions: &BTreeMap<u32, Overlay>,
     #[cfg(feature = "legacy")] flos: &BTreeMap<u32, Flo>,
+    #[cfg(feature = "gpu")] gpu: Option<&Gpu>,
 ) {
-    if let Some(core) = squares.core() {
-        if let Some(columns) = core.indexed_columns() {
-            columns.for_each(|(column, (x, y))| {
-                for p in plane..=3_usize {
-                    let condition: bool = unsafe {
-                        (p == 0 && plane == 0)
-                            || (p == plane && column.uget(1).settings.unwrap_or(0) & 0x2 == 0)
-                            || (p == plane + 1 && (column.uget(1).settings.unwrap_or(0) & 0x2 != 0))
-                            || (p >= plane && column.uget(0).settings.unwrap_or(0) & 0x2 != 0)
-                            || (plane == 0 && column.uget(p).settings.unwrap_or(0) & 0x8 != 0)
-                    };
-
-                    if condition {
-                        // Underlays
-                        #[cfg(any(feature = "rs3", feature = "osrs"))]
-                        if let Some([red, green, blue]) = get_underlay_colour(column, underlay_definitions, squares, p, x as usize, y as usize) {
-                            let fill = Rgba([red, green, blue, 255u8]);
-
-                            tileshape::draw_underlay(column[p].shape, CONFIG.tile_size, |(a, b)| unsafe {
-                                debug_assert!(
-                                    (CONFIG.tile_size * x + a) < img.width() && (CONFIG.tile_size * (63u32 - y) + b) < img.height(),
-                                    "Index out of range."
-                                );
-                                img.unsafe_put_pixel(CONFIG.tile_size * x + a, CONFIG.tile_size * (63u32 - y) + b, fill)
-                            })
-                        }
+    let layers = |blend| {
+        layers(
+            render,
+            plane,
+            squares,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            underlay_definitions,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            overlay_definitions,
+            #[cfg(feature = "legacy")]
+            flos,
+            blend,
+        )
+    };
 
-                        // Overlays
-                        #[cfg(any(feature = "rs3", feature = "osrs"))]
-                        if let Some(id) = column[p].overlay_id {
-                            let ov = &overlay_definitions[&(id.checked_sub(1).expect("Not 100% sure about this invariant.") as u32)];
-                            for colour in [ov.primary_colour, ov.secondary_colour] {
-                                if Some([255, 0, 255]) != colour {
-                                    if let Some([red, green, blue]) = colour {
-                                        let fill = if id == 112 && colour == Some([255, 255, 255]) {
-                                            // Gross hack to make ocean colours work past hd update
-                                            Rgba([96, 118, 154, 255])
-                                        } else {
-                                            Rgba([red, green, blue, 255])
-                                        };
-
-                                        tileshape::draw_overlay(column[p].shape.unwrap_or(0), CONFIG.tile_size, |(a, b)| unsafe {
-                                            debug_assert!(
-                                                (CONFIG.tile_size * x + a) < img.width() && (CONFIG.tile_size * (63u32 - y) + b) < img.height(),
-                                                "Index out of range."
-                                            );
-                                            img.unsafe_put_pixel(CONFIG.tile_size * x + a, CONFIG.tile_size * (63u32 - y) + b, fill)
-                                        })
-                                    }
-                                }
-                            }
-                        }
+    // The gpu only averages colours; blending them like the game takes floats, which it would not round the same.
+    #[cfg(feature = "gpu")]
+    if let Some(gpu) = gpu.filter(|_| render.blending == Blending::Smooth && (0..=64).contains(&render.interp)) {
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        let colour = |tile: &Tile| underlay_definitions.get(&(tile.underlay_id?.checked_sub(1)? as u32))?.colour;
+        #[cfg(feature = "legacy")]
+        let colour = |tile: &Tile| flos.get(&(tile.underlay_id?.checked_sub(1)? as u32))?.primary_colour;
 
-                        // The osrs client gets the average colour of textures here.
-                        //
-                        // The map has tiles, whose overlay_id points to an overlay config whose texture property points to a texture whose
-                        // id points to a texture that has a field that refers to an index in a palette.
-                        //
-                        // To simplify the implementation, we simply hardcode these values.
-                        // They don't change much, so this should be OK.
-                        #[cfg(feature = "osrs")]
-                        if let Some(id) = column[p].overlay_id {
-                            if let Some(texture_id) =
-                                &overlay_definitions[&(id.checked_sub(1).expect("Not 100% sure about this invariant.") as u32)].texture
-                            {
-                                let (red, green, blue) = match texture_id {
-                                    1 => (87, 108, 157),
-                                    2 => (70, 67, 63),
-                                    3 => (74, 45, 23),
-                                    11 => (64, 60, 56),
-                                    15 => (91, 87, 98),
-                                    23 => (50, 43, 28),
-                                    25 => (44, 103, 84),
-                                    31 => (213, 120, 8),
-                                    35 => (127, 110, 70),
-                                    43 => (87, 87, 78),
-                                    46 => (83, 77, 74),
-                                    51 => (118, 80, 37),
-                                    91 => (171, 176, 181),
-                                    unknown => unimplemented!("unimplemented texture id {}", unknown),
-                                };
-                                let fill = Rgba([red, green, blue, 255]);
-
-                                tileshape::draw_overlay(column[p].shape.unwrap_or(0), CONFIG.tile_size, |(a, b)| unsafe {
-                                    debug_assert!(
-                                        (CONFIG.tile_size * x + a) < img.width() && (CONFIG.tile_size * (63u32 - y) + b) < img.height(),
-                                        "Index out of range."
-                                    );
-
-                                    img.unsafe_put_pixel(CONFIG.tile_size * x + a, CONFIG.tile_size * (63u32 - y) + b, fill)
-                                });
-                            }
-                        }
+        let grids = [0, 1, 2, 3].map(|p| underlay_grid(render, squares, p, colour));
+        let gamma = (0..=255)
+            .map(|value| brightened(render, Rgba([value, 0, 0, 255]))[0])
+            .collect::<Vec<u8>>();
+        if let Some(ground) = gpu.ground(render, img, &layers(false), &grids, &gamma) {
+            *img = ground;
+            return;
+        }
+    }
+
+    draw(render, img, &layers(true));
+}
+
+/// Draws the `layers` of every tile, as made by [`layers`], over `img`.
+fn draw(render: &RenderConfig, img: &mut RgbaImage, layers: &[Vec<GroundLayer>]) {
+    for (index, layers) in layers.iter().enumerate() {
+        let (x, y) = ((index / 64) as u32, (index % 64) as u32);
+        let mut put_pixel = |fill, (a, b): (u32, u32)| unsafe {
+            debug_assert!(
+                (render.tile_size * x + a) < img.width() && (render.tile_size * (63u32 - y) + b) < img.height(),
+                "Index out of range."
+            );
+            img.unsafe_put_pixel(render.tile_size * x + a, render.tile_size * (63u32 - y) + b, fill)
+        };
+        for layer in layers {
+            if let Some(fill) = layer.underlay {
+                tileshape::draw_underlay(layer.shape, render.tile_size, |pixel| put_pixel(fill, pixel));
+            }
+            if let Some(fill) = layer.overlay {
+                tileshape::draw_overlay(layer.shape.unwrap_or(0), render.tile_size, |pixel| put_pixel(fill, pixel));
+            }
+        }
+    }
+}
+
+/// The colours of the underlays of plane `p` within [`RenderConfig::interp`] of the core of `squares`, for the gpu to blend.
+///
+/// They are in columns from the west, each running from the south, and both `64 + 2 * interp` tiles long.
+#[cfg(feature = "gpu")]
+fn underlay_grid(render: &RenderConfig, squares: &GroupMapSquare, p: usize, colour: impl Fn(&Tile) -> Option<[u8; 3]>) -> Vec<Option<[u8; 3]>> {
+    let range = -render.interp..64 + render.interp;
+    iproduct!(range.clone(), range)
+        .map(|(x, y)| tile(squares, p, x, y).and_then(&colour))
+        .collect()
+}
+
+/// The layers of every tile of the image of `plane`, indexed by `x * 64 + y`, from the bottom up.
+///
+/// Unless `blend` is set, underlays are given the colour of their own tile, for the gpu to blend.
+fn layers(
+    render: &RenderConfig,
+    plane: usize,
+    squares: &GroupMapSquare,
+    #[cfg(any(feature = "rs3", feature = "osrs"))] underlay_definitions: &BTreeMap<u32, Underlay>,
+    #[cfg(any(feature = "rs3", feature = "osrs"))] overlay_definitions: &BTreeMap<u32, Overlay>,
+    #[cfg(feature = "legacy")] flos: &BTreeMap<u32, Flo>,
+    blend: bool,
+) -> Vec<Vec<GroundLayer>> {
+    let mut layers = vec![Vec::new(); 64 * 64];
+    let Some(columns) = squares.core().and_then(|core| core.indexed_columns()) else { return layers };
+
+    columns.for_each(|(column, (x, y))| {
+        let settings = unsafe { [0, 1, 2, 3].map(|p| column.uget(p).settings.unwrap_or(0)) };
+        for p in plane..=3_usize {
+            if !drawn(plane, p, settings) || clipped(render.roofs, plane, p, settings) {
+                continue;
+            }
+            let mut layer = GroundLayer {
+                plane: p,
+                shape: column[p].shape,
+                underlay: None,
+                overlay: None,
+            };
+
+            // Underlays
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            let underlay = if blend {
+                get_underlay_colour(column, underlay_definitions, squares, p, x as usize, y as usize, render)
+            } else {
+                column[p]
+                    .underlay_id
+                    .map(|id| underlay_definitions[&(id.checked_sub(1).unwrap() as u32)].colour.unwrap())
+            };
+            #[cfg(feature = "legacy")]
+            let underlay = if blend {
+                get_underlay_colour(column, flos, squares, p, x as usize, y as usize, render)
+            } else {
+                column[p]
+                    .underlay_id
+                    .map(|id| flos[&(id.checked_sub(1).unwrap() as u32)].primary_colour.unwrap())
+            };
+            layer.underlay = underlay.map(|[red, green, blue]| brightened(render, Rgba([red, green, blue, 255u8])));
 
-                        // Underlays
-                        #[cfg(feature = "legacy")]
-                        if let Some([red, green, blue]) = get_underlay_colour(column, flos, squares, p, x as usize, y as usize) {
-                            let fill = Rgba([red, green, blue, 255u8]);
-
-                            tileshape::draw_underlay(column[p].shape, CONFIG.tile_size, |(a, b)| unsafe {
-                                debug_assert!(
-                                    (CONFIG.tile_size * x + a) < img.width() && (CONFIG.tile_size * (63u32 - y) + b) < img.height(),
-                                    "Index out of range."
-                                );
-                                img.unsafe_put_pixel(CONFIG.tile_size * x + a, CONFIG.tile_size * (63u32 - y) + b, fill)
-                            })
+            // Overlays
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            if let Some(id) = column[p].overlay_id {
+                let ov = &overlay_definitions[&(id.checked_sub(1).expect("Not 100% sure about this invariant.") as u32)];
+                for colour in [ov.primary_colour, ov.secondary_colour] {
+                    if Some([255, 0, 255]) != colour {
+                        if let Some([red, green, blue]) = colour {
+                            let fill = if id == 112 && colour == Some([255, 255, 255]) {
+                                // Gross hack to make ocean colours work past hd update
+                                Rgba([96, 118, 154, 255])
+                            } else {
+                                Rgba([red, green, blue, 255])
+                            };
+                            let fill = shoreline(render, squares, underlay_definitions, overlay_definitions, &column[p], p, x, y, fill);
+                            layer.overlay = Some(brightened(render, fill));
                         }
+                    }
+                }
+            }
+
+            // The osrs client gets the average colour of textures here.
+            //
+            // The map has tiles, whose overlay_id points to an overlay config whose texture property points to a texture whose
+            // id points to a texture that has a field that refers to an index in a palette.
+            //
+            // To simplify the implementation, we simply hardcode these values.
+            // They don't change much, so this should be OK.
+            #[cfg(feature = "osrs")]
+            if let Some(id) = column[p].overlay_id {
+                if let Some(texture_id) = &overlay_definitions[&(id.checked_sub(1).expect("Not 100% sure about this invariant.") as u32)].texture {
+                    let fill = Rgba(texture_colour(*texture_id));
+                    let fill = shoreline(render, squares, underlay_definitions, overlay_definitions, &column[p], p, x, y, fill);
+                    layer.overlay = Some(brightened(render, fill));
+                }
+            }
 
-                        // Overlays
-                        #[cfg(feature = "legacy")]
-                        if let Some(id) = column[p].overlay_id {
-                            let ov = &flos[&(id.checked_sub(1).expect("Not 100% sure about this invariant.") as u32)];
-                            for colour in [ov.primary_colour, ov.secondary_colour] {
-                                if Some([255, 0, 255]) != colour {
-                                    if let Some([red, green, blue]) = colour {
-                                        let fill = Rgba([red, green, blue, 255]);
-
-                                        tileshape::draw_overlay(column[p].shape.unwrap_or(0), CONFIG.tile_size, |(a, b)| unsafe {
-                                            debug_assert!(
-                                                (CONFIG.tile_size * x + a) < img.width() && (CONFIG.tile_size * (63u32 - y) + b) < img.height(),
-                                                "Index out of range."
-                                            );
-                                            img.unsafe_put_pixel(CONFIG.tile_size * x + a, CONFIG.tile_size * (63u32 - y) + b, fill)
-                                        })
-                                    }
-                                }
-                            }
+            // Overlays
+            #[cfg(feature = "legacy")]
+            if let Some(id) = column[p].overlay_id {
+                let ov = &flos[&(id.checked_sub(1).expect("Not 100% sure about this invariant.") as u32)];
+                for colour in [ov.primary_colour, ov.secondary_colour] {
+                    if Some([255, 0, 255]) != colour {
+                        if let Some([red, green, blue]) = colour {
+                            layer.overlay = Some(brightened(render, Rgba([red, green, blue, 255])));
                         }
+                    }
+                }
+                if let Some(texture_id) = &ov.texture {
+                    layer.overlay = Some(brightened(render, Rgba(texture_colour(*texture_id))));
+                }
+            }
+
+            layers[(x * 64 + y) as usize].push(layer);
+        }
+    });
+    layers
+}
+
+/// The average colour of a texture, as the client takes it from the texture's palette. See [`layers`].
+#[cfg(any(feature = "osrs", feature = "legacy"))]
+fn texture_colour(texture_id: impl Into<u32>) -> [u8; 4] {
+    let (red, green, blue) = match texture_id.into() {
+        1 => (87, 108, 157),
+        2 => (70, 67, 63),
+        3 => (74, 45, 23),
+        11 => (64, 60, 56),
+        15 => (91, 87, 98),
+        23 => (50, 43, 28),
+        25 => (44, 103, 84),
+        31 => (213, 120, 8),
+        35 => (127, 110, 70),
+        43 => (87, 87, 78),
+        46 => (83, 77, 74),
+        51 => (118, 80, 37),
+        91 => (171, 176, 181),
+        unknown => unimplemented!("unimplemented texture id {}", unknown),
+    };
+    [red, green, blue, 255]
+}
 
-                        // The osrs client gets the average colour of textures here.
-                        //
-                        // The map has tiles, whose overlay_id points to an overlay config whose texture property points to a texture whose
-                        // id points to a texture that has a field that refers to an index in a palette.
-                        //
-                        // To simplify the implementation, we simply hardcode these values.
-                        // They don't change much, so this should be OK.
-                        #[cfg(feature = "legacy")]
-                        if let Some(id) = column[p].overlay_id {
-                            if let Some(texture_id) = &flos[&(id.checked_sub(1).expect("Not 100% sure about this invariant.") as u32)].texture {
-                                let (red, green, blue) = match texture_id {
-                                    1 => (87, 108, 157),
-                                    2 => (70, 67, 63),
-                                    3 => (74, 45, 23),
-                                    11 => (64, 60, 56),
-                                    15 => (91, 87, 98),
-                                    23 => (50, 43, 28),
-                                    25 => (44, 103, 84),
-                                    31 => (213, 120, 8),
-                                    35 => (127, 110, 70),
-                                    43 => (87, 87, 78),
-                                    46 => (83, 77, 74),
-                                    51 => (118, 80, 37),
-                                    91 => (171, 176, 181),
-                                    unknown => unimplemented!("unimplemented texture id {}", unknown),
-                                };
-                                let fill = Rgba([red, green, blue, 255]);
-
-                                tileshape::draw_overlay(column[p].shape.unwrap_or(0), CONFIG.tile_size, |(a, b)| unsafe {
-                                    debug_assert!(
-                                        (CONFIG.tile_size * x + a) < img.width() && (CONFIG.tile_size * (63u32 - y) + b) < img.height(),
-                                        "Index out of range."
-                                    );
-
-                                    img.unsafe_put_pixel(CONFIG.tile_size * x + a, CONFIG.tile_size * (63u32 - y) + b, fill)
-                                });
-                            }
+/// Whether `tile` is water, which on rs3 is the [`WATER`] overlay.
+#[cfg(feature = "rs3")]
+fn is_water(tile: &Tile, _overlay_definitions: &BTreeMap<u32, Overlay>) -> bool {
+    tile.overlay_id.and_then(|id| id.checked_sub(1)).map(u32::from) == Some(WATER)
+}
+
+/// Whether `tile` is water, which on osrs is any overlay with the [`WATER_TEXTURE`].
+#[cfg(feature = "osrs")]
+fn is_water(tile: &Tile, overlay_definitions: &BTreeMap<u32, Overlay>) -> bool {
+    tile.overlay_id
+        .and_then(|id| id.checked_sub(1))
+        .and_then(|id| overlay_definitions.get(&(id as u32)))
+        .map_or(false, |overlay| overlay.texture == Some(WATER_TEXTURE))
+}
+
+/// Blends the colour of the land around the tile at `x, y` of `plane` into `fill`, by [`RenderConfig::shoreline`], if the tile is water.
+/// Water is first given [`RenderConfig::water_colour`], if there is one.
+///
+/// Only water next to land is changed, so the coast gets an edge of its own, as it has on the game's map.
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+#[allow(clippy::too_many_arguments)]
+fn shoreline(
+    render: &RenderConfig,
+    squares: &GroupMapSquare,
+    underlay_definitions: &BTreeMap<u32, Underlay>,
+    overlay_definitions: &BTreeMap<u32, Overlay>,
+    tile: &Tile,
+    plane: usize,
+    x: u32,
+    y: u32,
+    fill: Rgba<u8>,
+) -> Rgba<u8> {
+    if !is_water(tile, overlay_definitions) {
+        return fill;
+    }
+    let fill = render.water_colour.map_or(fill, Rgba);
+    if render.shoreline == 0 {
+        return fill;
+    }
+    let land = squares
+        .tiles_iter(plane, x as usize, y as usize, 1)
+        .filter(|tile| !is_water(tile, overlay_definitions))
+        .filter_map(|tile| underlay_definitions.get(&(tile.underlay_id?.checked_sub(1)? as u32))?.colour)
+        .collect::<Vec<_>>();
+    shore(fill, &land, render.shoreline)
+}
+
+/// Blends the average of the `land` colours into `fill`, by `share` percent. Does nothing if there is no land.
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+fn shore(fill: Rgba<u8>, land: &[[u8; 3]], share: u8) -> Rgba<u8> {
+    if land.is_empty() || share == 0 {
+        return fill;
+    }
+    let share = share as u32;
+    let mut blended = fill;
+    for (channel, value) in blended.0[..3].iter_mut().enumerate() {
+        let average = land.iter().map(|colour| colour[channel] as u32).sum::<u32>() / land.len() as u32;
+        *value = ((*value as u32 * (100 - share) + average * share) / 100) as u8;
+    }
+    blended
+}
+
+/// How high a step of [`Tile::height`] is, in tiles; a tile is 128 units wide, and a step 8 units high.
+pub(crate) const HEIGHT_STEP: f32 = 8.0 / 128.0;
+
+/// Lightens the slopes of the ground that face the light and darkens those that face away from it,
+/// by [`RenderConfig::light_intensity`]. Does nothing if that is zero.
+pub fn shade(render: &RenderConfig, plane: usize, img: &mut RgbaImage, squares: &GroupMapSquare) {
+    if render.light_intensity == 0 {
+        return;
+    }
+
+    for x in 0..64_isize {
+        for y in 0..64_isize {
+            // Tiles without a height get theirs from the game's terrain generator, which this does not replicate.
+            // On bridges, it is the ground of the plane above that is drawn, so it is that which is shaded.
+            let ground = |x, y| height(squares, ground_plane(squares, plane, x, y), x, y);
+            let Some(here) = ground(x, y) else { continue };
+            let at = |dx, dy| ground(x + dx, y + dy).unwrap_or(here);
+            let slope_x = (at(1, 0) - at(-1, 0)) as f32 * HEIGHT_STEP / 2.0;
+            let slope_y = (at(0, 1) - at(0, -1)) as f32 * HEIGHT_STEP / 2.0;
+
+            let factor = shade_factor(render, slope_x, slope_y);
+            if factor == 1.0 {
+                continue;
+            }
+            let (left, top) = (render.tile_size * x as u32, render.tile_size * (63 - y as u32));
+            for a in 0..render.tile_size {
+                for b in 0..render.tile_size {
+                    let pixel = img.get_pixel_mut(left + a, top + b);
+                    if pixel[3] != 0 {
+                        for channel in &mut pixel.0[..3] {
+                            *channel = (*channel as f32 * factor).round().clamp(0.0, 255.0) as u8;
                         }
                     }
                 }
-            })
-        };
+            }
+        }
+    }
+}
+
+/// How much brighter a tile is for its slope, which is how much it rises to the east and north per tile.
+///
+/// The light comes from [`RenderConfig::light_azimuth`], at 45 degrees above the horizon; flat ground is left as it is.
+fn shade_factor(render: &RenderConfig, slope_x: f32, slope_y: f32) -> f32 {
+    let azimuth = (render.light_azimuth as f32).to_radians();
+    let (light_x, light_y, light_z) = (azimuth.sin() * FRAC_1_SQRT_2, azimuth.cos() * FRAC_1_SQRT_2, FRAC_1_SQRT_2);
+    let length = (slope_x * slope_x + slope_y * slope_y + 1.0).sqrt();
+    let lit = (-slope_x * light_x - slope_y * light_y + light_z) / length / light_z;
+    1.0 + (render.light_intensity as f32 / 100.0) * (lit - 1.0)
+}
+
+/// The tile at `x, y` of `plane`, relative to the core of `squares`, which may be in a neighbouring mapsquare.
+fn tile(squares: &GroupMapSquare, plane: usize, x: isize, y: isize) -> Option<&Tile> {
+    let i = u8::try_from(squares.core_i() as isize + x.div_euclid(64)).ok()?;
+    let j = u8::try_from(squares.core_j() as isize + y.div_euclid(64)).ok()?;
+    let tiles = squares.get(&(i, j))?.tiles()?;
+    Some(&tiles[[plane, x.rem_euclid(64) as usize, y.rem_euclid(64) as usize]])
+}
+
+/// The height of the tile at `x, y` of `plane`, relative to the core of `squares`, which may be in a neighbouring mapsquare.
+pub(crate) fn height(squares: &GroupMapSquare, plane: usize, x: isize, y: isize) -> Option<i32> {
+    tile(squares, plane, x, y)?.height.map(i32::from)
+}
+
+/// The plane whose ground is drawn in the image of `plane` at `x, y`, which on bridges is the one above it.
+fn ground_plane(squares: &GroupMapSquare, plane: usize, x: isize, y: isize) -> usize {
+    let bridge = tile(squares, 1, x, y)
+        .and_then(|tile| tile.settings)
+        .map_or(false, |settings| settings & BRIDGE != 0);
+    if bridge {
+        (plane + 1).min(3)
+    } else {
+        plane
     }
 }
 
-/// Averages out the [`Underlay`] colours, with a range specified by [`INTERP`].
+/// Blends the [`Underlay`] colours within [`RenderConfig::interp`] of a tile, by [`RenderConfig::blending`].
 #[cfg(any(feature = "rs3", feature = "osrs"))]
 fn get_underlay_colour(
     column: ArrayBase<ViewRepr<&Tile>, Dim<[usize; 1]>>,
@@ -202,35 +424,19 @@ fn get_underlay_colour(
     plane: usize,
     x: usize,
     y: usize,
+    render: &RenderConfig,
 ) -> Option<[u8; 3]> {
     // only compute a colour average if the tile has a underlay
-    column[plane].underlay_id.map(|_| {
-        let tiles = squares.tiles_iter(plane, x, y, CONFIG.interp);
-
-        let underlays = tiles.filter_map(|elem| elem.underlay_id);
-
-        let colours = underlays.map(|id| {
-            (
-                1usize, /* weight, todo? */
-                underlay_definitions[&(id.checked_sub(1).unwrap() as u32)].colour.unwrap(),
-            )
-        });
-
-        let (weight, (reds, greens, blues)) = colours
-            .map(|(w, [r, g, b])| (w, (r as usize * w, g as usize * w, b as usize * w)))
-            .fold((0, (0, 0, 0)), |(acc_w, (acc_r, acc_g, acc_b)), (w, (r, g, b))| {
-                (acc_w + w, (acc_r + r, acc_g + g, acc_b + b))
-            });
-
-        [
-            (reds / weight).try_into().unwrap(),
-            (greens / weight).try_into().unwrap(),
-            (blues / weight).try_into().unwrap(),
-        ]
-    })
+    column[plane].underlay_id?;
+    let colours = squares
+        .tiles_iter(plane, x, y, render.interp)
+        .filter_map(|elem| elem.underlay_id)
+        .map(|id| underlay_definitions[&(id.checked_sub(1).unwrap() as u32)].colour.unwrap())
+        .collect::<Vec<_>>();
+    blend(render.blending, &colours)
 }
 
-/// Averages out the [`Underlay`] colours, with a range specified by [`INTERP`].
+/// Blends the [`Flo`] colours within [`RenderConfig::interp`] of a tile, by [`RenderConfig::blending`].
 #[cfg(feature = "legacy")]
 fn get_underlay_colour(
     column: ArrayBase<ViewRepr<&Tile>, Dim<[usize; 1]>>,
@@ -239,29 +445,260 @@ fn get_underlay_colour(
     plane: usize,
     x: usize,
     y: usize,
+    render: &RenderConfig,
 ) -> Option<[u8; 3]> {
     // only compute a colour average if the tile has a underlay
-    column[plane].underlay_id.map(|_| {
-        let tiles = squares.tiles_iter(plane, x, y, CONFIG.interp);
+    column[plane].underlay_id?;
+    // Only the tiles that have an underlay are averaged, so that the edges of the ground are not darkened.
+    let colours = squares
+        .tiles_iter(plane, x, y, render.interp)
+        .filter_map(|elem| elem.underlay_id)
+        .map(|id| flos[&(id.checked_sub(1).unwrap() as u32)].primary_colour.unwrap())
+        .collect::<Vec<_>>();
+    blend(render.blending, &colours)
+}
 
-        let underlays = tiles.filter_map(|elem| elem.underlay_id);
+/// Averages `colours` by `blending`, if there are any.
+fn blend(blending: Blending, colours: &[[u8; 3]]) -> Option<[u8; 3]> {
+    if colours.is_empty() {
+        return None;
+    }
+    let count = colours.len();
+    match blending {
+        Blending::Smooth => Some([0, 1, 2].map(|channel| (colours.iter().map(|colour| colour[channel] as usize).sum::<usize>() / count) as u8)),
+        Blending::Game => {
+            let (mut hue, mut weights, mut saturation, mut lightness) = (0.0, 0.0, 0.0, 0.0);
+            for &colour in colours {
+                let (h, s, l) = to_hsl(colour);
+                // Greys hardly pull the hue towards theirs.
+                let weight = (l.min(1.0 - l) * s).max(1.0 / 512.0);
+                hue += h * weight;
+                weights += weight;
+                saturation += s;
+                lightness += l;
+            }
+            // The game's colours have 64 hues, 8 saturations and 128 lightnesses.
+            let rounded = |value: f32, steps: f32| (value * steps).floor().clamp(0.0, steps - 1.0) / steps;
+            let count = count as f32;
+            Some(from_hsl(
+                rounded(hue / weights, 64.0),
+                rounded(saturation / count, 8.0),
+                rounded(lightness / count, 128.0),
+            ))
+        }
+    }
+}
+
+/// The hue, saturation and lightness of `colour`, each from 0 to 1.
+fn to_hsl(colour: [u8; 3]) -> (f32, f32, f32) {
+    let [r, g, b] = colour.map(|channel| channel as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, lightness);
+    }
+    let chroma = max - min;
+    let saturation = chroma / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (hue / 6.0, saturation, lightness)
+}
+
+/// The colour of `hue`, `saturation` and `lightness`, each from 0 to 1.
+fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> [u8; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue * 6.0) % 2.0 - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+    let (r, g, b) = match (hue * 6.0) as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    [r, g, b].map(|channel| ((channel + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
 
-        let colours = underlays.map(|id| {
+/// Brightens `fill` by the curve of [`RenderConfig::gamma`].
+fn brightened(render: &RenderConfig, fill: Rgba<u8>) -> Rgba<u8> {
+    if render.gamma == 100 {
+        return fill;
+    }
+    let exponent = render.gamma as f32 / 100.0;
+    let mut fill = fill;
+    for channel in &mut fill.0[..3] {
+        *channel = ((*channel as f32 / 255.0).powf(exponent) * 255.0).round() as u8;
+    }
+    fill
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roofs() {
+        let roofed = [ROOFED, 0, 0, 0];
+        // Upper planes do not cover a roofed tile, unless the roofs are kept.
+        assert!(!clipped(Roofs::Keep, 0, 1, roofed));
+        assert!(clipped(Roofs::Clip, 0, 1, roofed));
+        assert!(!clipped(Roofs::Clip, 0, 0, roofed));
+        assert!(!clipped(Roofs::Clip, 0, 1, [0; 4]));
+        // Only with roofs removed is the upper plane itself left out above it.
+        assert!(!clipped(Roofs::Clip, 1, 1, roofed));
+        assert!(clipped(Roofs::Remove, 1, 1, roofed));
+        // Bridges are never left out.
+        assert!(!clipped(Roofs::Remove, 0, 1, [ROOFED, BRIDGE, 0, 0]));
+    }
+
+    #[test]
+    fn bridges() {
+        let bridge = [0, BRIDGE, 0, 0];
+        // The bridge is drawn over what is under it, and everything above it a plane lower.
+        assert!(drawn(0, 0, bridge) && drawn(0, 1, bridge));
+        assert!(!drawn(1, 1, bridge) && drawn(1, 2, bridge));
+        assert!(!drawn(2, 2, bridge) && drawn(2, 3, bridge));
+        // Only plane 1 makes a bridge.
+        let flagged_ground = [BRIDGE, 0, 0, 0];
+        assert!(drawn(0, 0, flagged_ground) && !drawn(0, 1, flagged_ground) && drawn(1, 1, flagged_ground));
+        // Tiles visible from below are also drawn on plane 0.
+        assert!(drawn(0, 2, [0, 0, VISIBLE_BELOW, 0]));
+        assert!(!drawn(1, 2, [0, 0, VISIBLE_BELOW, 0]));
+    }
+
+    #[test]
+    fn blending() {
+        let colours = [[255, 0, 0], [128, 128, 128]];
+        assert_eq!(blend(Blending::Smooth, &colours), Some([191, 64, 64]));
+        // Only the colours that are there are averaged, however few, so the edges of the ground are not darkened.
+        assert_eq!(blend(Blending::Smooth, &[[200, 100, 50]]), Some([200, 100, 50]));
+        // Grey does not change the hue, only the saturation and lightness.
+        let [r, g, b] = blend(Blending::Game, &colours).unwrap();
+        assert!(r > g && g == b, "{r} {g} {b}");
+        assert_eq!(blend(Blending::Game, &[]), None);
+
+        let render = RenderConfig {
+            gamma: 60,
+            ..RenderConfig::default()
+        };
+        assert_eq!(brightened(&RenderConfig::default(), Rgba([100, 0, 255, 255])), Rgba([100, 0, 255, 255]));
+        let brighter = brightened(&render, Rgba([100, 0, 255, 255]));
+        assert!(brighter[0] > 100 && brighter[1] == 0 && brighter[2] == 255, "{brighter:?}");
+    }
+
+    #[test]
+    fn shading() {
+        let render = RenderConfig {
+            light_intensity: 100,
+            light_azimuth: 315,
+            ..RenderConfig::default()
+        };
+        assert_eq!(shade_factor(&render, 0.0, 0.0), 1.0);
+        // Ground rising to the south east faces a light in the north west, and ground rising to the north west faces away from it.
+        let towards = shade_factor(&render, 1.0, -1.0);
+        let away = shade_factor(&render, -1.0, 1.0);
+        assert!(towards > 1.0 && away < 1.0, "{towards} {away}");
+        assert_eq!(shade_factor(&RenderConfig::default(), -1.0, 1.0), 1.0);
+    }
+
+    #[test]
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    fn shores() {
+        let water = Rgba([0, 0, 200, 255]);
+        assert_eq!(shore(water, &[[100, 100, 0], [200, 100, 0]], 50), Rgba([75, 50, 100, 255]));
+        // Water away from land, and water that is not to be blended, keeps its colour.
+        assert_eq!(shore(water, &[], 50), water);
+        assert_eq!(shore(water, &[[100, 100, 0]], 0), water);
+    }
+
+    #[test]
+    #[cfg(feature = "rs3")]
+    fn water() {
+        let tile = |overlay_id| Tile {
+            overlay_id,
+            ..Tile::default()
+        };
+        assert!(is_water(&tile(Some(WATER as u16 + 1)), &BTreeMap::new()));
+        assert!(!is_water(&tile(Some(WATER as u16)), &BTreeMap::new()));
+        assert!(!is_water(&tile(None), &BTreeMap::new()));
+    }
+
+    #[test]
+    #[cfg(feature = "osrs")]
+    fn water() {
+        let tile = |overlay_id| Tile {
+            overlay_id,
+            ..Tile::default()
+        };
+        let overlays = BTreeMap::from([
             (
-                1usize, /* weight, todo? */
-                flos[&(id.checked_sub(1).unwrap() as u32)].primary_colour.unwrap(),
-            )
+                5,
+                Overlay {
+                    id: 5,
+                    texture: Some(WATER_TEXTURE),
+                    ..Overlay::default()
+                },
+            ),
+            (6, Overlay { id: 6, ..Overlay::default() }),
+        ]);
+        // Any overlay with the water texture is water, whatever its id.
+        assert!(is_water(&tile(Some(6)), &overlays));
+        assert!(!is_water(&tile(Some(7)), &overlays));
+        assert!(!is_water(&tile(Some(112)), &overlays));
+        assert!(!is_water(&tile(None), &overlays));
+    }
+
+    /// The gpu draws the same ground as the cpu, blending and all.
+    #[test]
+    #[cfg(feature = "gpu")]
+    fn gpu_ground() {
+        // Machines without a gpu have nothing to compare.
+        let Some(gpu) = crate::renderers::gpu::get() else { return };
+
+        let render = RenderConfig {
+            tile_size: 4,
+            interp: 1,
+            gamma: 80,
+            ..RenderConfig::default()
+        };
+        let side = 66;
+        let grids = [0, 1, 2, 3].map(|p| {
+            iproduct!(0..side, 0..side)
+                .map(|(x, y)| ((x + y + p) % 3 != 0).then_some([(x * 3) as u8, (y * 3) as u8, (p * 60) as u8]))
+                .collect::<Vec<_>>()
         });
+        let layer = |blended: bool, tile: usize, p: usize| {
+            let (x, y) = (tile / 64, tile % 64);
+            let own = grids[p][(x + 1) * side + y + 1];
+            let colours = iproduct!(x..x + 3, y..y + 3)
+                .filter_map(|(a, b)| grids[p][a * side + b])
+                .collect::<Vec<_>>();
+            let underlay = if blended { own.and(blend(Blending::Smooth, &colours)) } else { own };
+            GroundLayer {
+                plane: p,
+                shape: (tile % 5 != 0).then_some((tile * 7 % 48) as u8),
+                underlay: underlay.map(|[red, green, blue]| brightened(&render, Rgba([red, green, blue, 255]))),
+                overlay: (tile % 3 == 0).then_some(Rgba([10, 20, 30, 255])),
+            }
+        };
+        let layers = |blended| {
+            (0..64 * 64)
+                .map(|tile| (0..=tile % 3).map(|p| layer(blended, tile, p)).collect())
+                .collect::<Vec<Vec<_>>>()
+        };
+        let gamma = (0..=255)
+            .map(|value| brightened(&render, Rgba([value, 0, 0, 255]))[0])
+            .collect::<Vec<u8>>();
 
-        let (_weight, (reds, greens, blues)) = colours
-            .map(|(w, [r, g, b])| (w, (r as usize * w, g as usize * w, b as usize * w)))
-            .fold((0, (0, 0, 0)), |(acc_w, (acc_r, acc_g, acc_b)), (w, (r, g, b))| {
-                (acc_w + w, (acc_r + r, acc_g + g, acc_b + b))
-            });
-        [
-            (reds / 121).try_into().unwrap(),
-            (greens / 121).try_into().unwrap(),
-            (blues / 121).try_into().unwrap(),
-        ]
-    })
+        let img = RgbaImage::from_pixel(render.dim(), render.dim(), Rgba([0, 0, 0, 0]));
+        let mut cpu = img.clone();
+        draw(&render, &mut cpu, &layers(true));
+        assert_eq!(gpu.ground(&render, &img, &layers(false), &grids, &gamma), Some(cpu));
+    }
 }
@@ -0,0 +1,167 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    iter,
+};
+
+use image::{GenericImage, GenericImageView, Rgba, RgbaImage};
+use indicatif::ProgressIterator;
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use rs3cache_backend::{
+    error::CacheResult,
+    warning::{self, Warning},
+};
+use rs3cache_utils::color::Color;
+
+use super::{layers, render_layer, render_styled, save_smallest, Definitions, Img, RenderConfig};
+use crate::{
+    cancel,
+    cli::Config,
+    definitions::{
+        mapsquares::{GroupMapSquare, GroupMapSquareIterator},
+        worldmaps::{MapPastes, Paste},
+    },
+};
+
+/// The ids of all maps that are pasted together from the game map.
+pub fn map_ids(config: &Config) -> CacheResult<Vec<i32>> {
+    let mut ids = MapPastes::dump_all(config)?
+        .into_keys()
+        .filter_map(|id| i32::try_from(id).ok())
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// The area a [`Paste`] copies, in tiles: its south west corner in the source mapsquare,
+/// its south west corner in the destination mapsquare and its size.
+fn area(paste: &Paste) -> Option<((u32, u32), (u32, u32), u32)> {
+    match (&paste.src_chunk, &paste.dst_chunk) {
+        (Some(src), Some(dst)) => {
+            let corner = |x: u8, y: u8| (x < 8 && y < 8).then_some((x as u32 * 8, y as u32 * 8));
+            Some((corner(src.x, src.y)?, corner(dst.x, dst.y)?, 8))
+        }
+        _ => Some(((0, 0), (0, 0), 64)),
+    }
+}
+
+/// Copies what `paste` takes from the planes of its source mapsquare, `src`, onto those of its destination, `dst`.
+fn copy(render: &RenderConfig, paste: &Paste, src: &[Img; 4], dst: &mut [Img; 4]) {
+    let Some(((src_x, src_y), (dst_x, dst_y), size)) = area(paste) else { return };
+    let tile_size = render.tile_size;
+    let width = size * tile_size;
+    // Images start at the north, tiles at the south.
+    let top = |y: u32| render.dim() - (y + size) * tile_size;
+
+    for plane in 0..paste.n_planes as usize {
+        let (src_plane, dst_plane) = (paste.src_plane as usize + plane, paste.dst_plane as usize + plane);
+        if src_plane > 3 || dst_plane > 3 {
+            continue;
+        }
+        let view = src[src_plane].view(src_x * tile_size, top(src_y), width, width);
+        dst[dst_plane]
+            .copy_from(&view, dst_x * tile_size, top(dst_y))
+            .expect("the area is always inside the mapsquare");
+    }
+}
+
+/// Renders map `render.map_id`, and its `--layers`, by pasting together parts of the game map as its [`MapPastes`] describe.
+///
+/// Every mapsquare of it is rendered; `--render-region`, `--mapsquare` and `--resume` only apply to the game map itself.
+pub fn render(config: &Config, name: &str, render: &RenderConfig, definitions: &Definitions) -> CacheResult<()> {
+    let mut maps = MapPastes::dump_all(config)?;
+    let Some(map) = u32::try_from(render.map_id).ok().and_then(|id| maps.remove(&id)) else {
+        warning::warn(Warning::MissingKey {
+            what: "world map",
+            key: render.map_id.to_string(),
+        });
+        return Ok(());
+    };
+
+    let mut by_destination = BTreeMap::<(u8, u8), Vec<&Paste>>::new();
+    for paste in &map.pastes {
+        if let (Ok(i), Ok(j)) = (u8::try_from(paste.dst_i), u8::try_from(paste.dst_j)) {
+            by_destination.entry((i, j)).or_default().push(paste);
+        }
+    }
+
+    let layers = layers(config);
+    let names = iter::once(name).chain(layers.iter().map(|layer| layer.name())).collect::<Vec<_>>();
+    let draw = |squares: &GroupMapSquare| {
+        iter::once(render_styled(render, squares, definitions))
+            .chain(layers.iter().map(|&layer| render_layer(render, layer, squares, definitions)))
+            .collect::<Vec<_>>()
+    };
+    let blank = || [(); 4].map(|_| RgbaImage::from_pixel(render.dim(), render.dim(), Rgba(Color::ALPHA)));
+
+    let total = by_destination.len() as u64;
+    by_destination
+        .iter()
+        .progress_count(total)
+        .par_bridge()
+        .try_for_each(|(&(i, j), pastes)| -> CacheResult<()> {
+            // Tiles that are already being rendered are finished, the rest are skipped.
+            if cancel::is_cancelled() {
+                return Ok(());
+            }
+            let sources = pastes
+                .iter()
+                .filter_map(|paste| Some((u8::try_from(paste.src_i).ok()?, u8::try_from(paste.src_j).ok()?)))
+                .collect::<BTreeSet<_>>();
+
+            let mut outputs = names.iter().map(|_| blank()).collect::<Vec<_>>();
+            for squares in GroupMapSquareIterator::new_only(-1_i32..=1_i32, -1_i32..=1_i32, sources.into_iter().collect(), config)? {
+                let drawn = draw(&squares);
+                let source = (squares.core_i() as u16, squares.core_j() as u16);
+                for paste in pastes.iter().filter(|paste| (paste.src_i, paste.src_j) == source) {
+                    for (src, dst) in drawn.iter().zip(outputs.iter_mut()) {
+                        copy(render, paste, src, dst);
+                    }
+                }
+            }
+
+            for (name, imgs) in names.iter().zip(outputs) {
+                save_smallest(config, render, name, i, j, imgs);
+            }
+            Ok(())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::worldmaps::Chunk;
+
+    fn paste(src_chunk: Option<Chunk>, dst_chunk: Option<Chunk>) -> Paste {
+        Paste {
+            src_plane: 0,
+            n_planes: 1,
+            src_i: 50,
+            src_j: 50,
+            src_chunk,
+            dst_plane: 1,
+            dst_i: 10,
+            dst_j: 10,
+            dst_chunk,
+        }
+    }
+
+    #[test]
+    fn copies() {
+        let render = RenderConfig::default();
+        let dim = render.dim();
+        assert_eq!(area(&paste(None, None)), Some(((0, 0), (0, 0), 64)));
+
+        let chunk = paste(Some(Chunk { x: 0, y: 0 }), Some(Chunk { x: 7, y: 7 }));
+        assert_eq!(area(&chunk), Some(((0, 0), (56, 56), 8)));
+        assert_eq!(area(&paste(Some(Chunk { x: 8, y: 0 }), Some(Chunk { x: 0, y: 0 }))), None);
+
+        let src = [(); 4].map(|_| RgbaImage::from_pixel(dim, dim, Rgba(Color::WHITE)));
+        let mut dst = [(); 4].map(|_| RgbaImage::from_pixel(dim, dim, Rgba(Color::ALPHA)));
+        copy(&render, &chunk, &src, &mut dst);
+
+        // The south west chunk of plane 0 lands in the north east chunk of plane 1.
+        assert_eq!(dst[1].get_pixel(dim - 1, 0), &Rgba(Color::WHITE));
+        assert_eq!(dst[1].get_pixel(dim - 8 * render.tile_size - 1, 0), &Rgba(Color::ALPHA));
+        assert_eq!(dst[0].get_pixel(dim - 1, 0), &Rgba(Color::ALPHA));
+    }
+}
@@ -0,0 +1,112 @@
+use image::{Pixel, Rgba, RgbaImage};
+
+use super::{text, RenderConfig};
+use crate::definitions::fonts::Font;
+
+/// The colour of mapsquare boundaries and their labels.
+pub const BOUNDARY: [u8; 4] = [255, 255, 0, 255];
+
+/// The colour of the lines between tiles.
+pub const TILE: [u8; 4] = [255, 255, 255, 64];
+
+/// The colour of the boundaries of chunks, the 8 by 8 tile parts of a mapsquare that the game copies around.
+pub const CHUNK: [u8; 4] = [0, 255, 255, 160];
+
+/// The colour of the stripes over mapsquares whose locations are missing.
+pub const MISSING: [u8; 4] = [255, 0, 0, 96];
+
+/// The label of mapsquare `i, j`: its coordinates, and its region id as the game uses it.
+pub fn label(i: u8, j: u8) -> String {
+    format!("{i}_{j} / {}", (i as u32) << 8 | j as u32)
+}
+
+/// Draws the boundary of mapsquare `i, j`, its label in `font` and the lines between its tiles.
+pub fn put(render: &RenderConfig, img: &mut RgbaImage, i: u8, j: u8, font: Option<&Font>) {
+    let dim = img.width();
+    let tile_size = render.tile_size;
+
+    // The lines between tiles would be more line than tile when tiles are small.
+    if tile_size >= 8 {
+        for (a, b, pixel) in img.enumerate_pixels_mut() {
+            if a % tile_size == 0 || b % tile_size == 0 {
+                pixel.blend(&Rgba(TILE));
+            }
+        }
+    }
+
+    for n in 0..dim {
+        for (a, b) in [(n, 0), (n, dim - 1), (0, n), (dim - 1, n)] {
+            img.put_pixel(a, b, Rgba(BOUNDARY));
+        }
+    }
+
+    let label = label(i, j);
+    let scale = (render.scale / 2).max(1);
+    let (width, height) = text::size(&label, scale, font);
+    let [red, green, blue, _] = BOUNDARY;
+    text::draw(
+        img,
+        &label,
+        (width / 2 + tile_size) as i32,
+        (height / 2 + tile_size) as i32,
+        scale,
+        [red, green, blue],
+        font,
+    );
+}
+
+/// Draws the boundaries of the chunks of a mapsquare, and if `missing_locations`, stripes over all of it with a note saying so,
+/// so that it does not look like it simply has no locations. The note is in `font`.
+pub fn put_chunks(render: &RenderConfig, img: &mut RgbaImage, missing_locations: bool, font: Option<&Font>) {
+    let chunk = render.tile_size * 8;
+    let stripe = render.tile_size * 2;
+    for (a, b, pixel) in img.enumerate_pixels_mut() {
+        if missing_locations && (a + b) / stripe % 2 == 0 {
+            pixel.blend(&Rgba(MISSING));
+        }
+        if a % chunk == 0 || b % chunk == 0 || a % chunk == chunk - 1 || b % chunk == chunk - 1 {
+            pixel.blend(&Rgba(CHUNK));
+        }
+    }
+
+    if missing_locations {
+        let note = "locations missing";
+        let scale = (render.scale / 2).max(1);
+        let [red, green, blue, _] = MISSING;
+        let center = (img.width() / 2) as i32;
+        text::draw(img, note, center, center, scale, [red, green, blue], font);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid() {
+        assert_eq!(label(50, 50), "50_50 / 12850");
+
+        let render = RenderConfig::default();
+        let mut img = RgbaImage::new(render.dim(), render.dim());
+        put(&render, &mut img, 50, 50, None);
+        assert_eq!(img.get_pixel(0, 100), &Rgba(BOUNDARY));
+        assert_eq!(img.get_pixel(render.dim() - 1, 100), &Rgba(BOUNDARY));
+        assert_ne!(img.get_pixel(render.tile_size * 10, render.dim() - 100)[3], 0);
+        assert_eq!(img.get_pixel(render.tile_size * 10 + 1, render.dim() - 100)[3], 0);
+    }
+
+    #[test]
+    fn chunks() {
+        let render = RenderConfig::default();
+        let mut img = RgbaImage::new(render.dim(), render.dim());
+        put_chunks(&render, &mut img, false, None);
+        let chunk = render.tile_size * 8;
+        assert_ne!(img.get_pixel(chunk, 1)[3], 0);
+        assert_ne!(img.get_pixel(chunk - 1, 1)[3], 0);
+        assert_eq!(img.get_pixel(chunk + 1, 1)[3], 0);
+
+        let mut missing = RgbaImage::new(render.dim(), render.dim());
+        put_chunks(&render, &mut missing, true, None);
+        assert_ne!(missing.get_pixel(chunk + 1, 1)[3], 0);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use image::{Pixel, Rgba, RgbaImage};
+use itertools::iproduct;
+use rs3cache_backend::error::CacheResult;
+
+use crate::{
+    cli::Config,
+    definitions::{
+        location_configs::LocationConfig,
+        locations::{Location, Watery},
+        mapsquares::{GroupMapSquare, MapSquares},
+    },
+    renderers::map::RenderConfig,
+};
+
+/// The tint that highlighted locations are drawn with.
+pub const TINT: [u8; 4] = [255, 0, 255, 160];
+
+/// Tints every tile that the locations with one of `ids` cover.
+pub fn put(
+    render: &RenderConfig,
+    plane: usize,
+    img: &mut RgbaImage,
+    squares: &GroupMapSquare,
+    location_configs: &BTreeMap<u32, LocationConfig>,
+    ids: &BTreeSet<u32>,
+) {
+    if ids.is_empty() {
+        return;
+    }
+    let tiles = covered(
+        (squares.core_i(), squares.core_j()),
+        squares.all_locations_iter(),
+        plane,
+        location_configs,
+        ids,
+    );
+    tint(img, &tiles, render.tile_size as i32);
+}
+
+/// The tiles of the core mapsquare that the locations with one of `ids` cover.
+///
+/// Tiles covered by more than one location are only listed once, so they are not tinted twice.
+fn covered<'a>(
+    (core_i, core_j): (u8, u8),
+    locations: impl Iterator<Item = &'a Location>,
+    plane: usize,
+    location_configs: &BTreeMap<u32, LocationConfig>,
+    ids: &BTreeSet<u32>,
+) -> BTreeSet<(i32, i32)> {
+    let mut tiles = BTreeSet::new();
+    for loc in locations.filter(|loc| ids.contains(&loc.id) && loc.plane.matches(&(plane as u8))) {
+        let config = location_configs.get(&loc.id);
+        let (dim_x, dim_y) = (
+            config.and_then(|config| config.dim_x).unwrap_or(1) as i32,
+            config.and_then(|config| config.dim_y).unwrap_or(1) as i32,
+        );
+        // Locations that are turned a quarter have their sides swapped.
+        let (dim_x, dim_y) = if loc.rotation % 2 == 1 { (dim_y, dim_x) } else { (dim_x, dim_y) };
+
+        // Relative to the core mapsquare, as locations of its neighbours can reach into it.
+        let x = (loc.i as i32 - core_i as i32) * 64 + loc.x as i32;
+        let y = (loc.j as i32 - core_j as i32) * 64 + loc.y as i32;
+
+        tiles.extend(iproduct!(x..x + dim_x, y..y + dim_y).filter(|&(x, y)| (0..64).contains(&x) && (0..64).contains(&y)));
+    }
+    tiles
+}
+
+/// Blends [`TINT`] over every pixel of `tiles`.
+fn tint(img: &mut RgbaImage, tiles: &BTreeSet<(i32, i32)>, tile_size: i32) {
+    for &(tile_x, tile_y) in tiles {
+        for (a, b) in iproduct!(0..tile_size, 0..tile_size) {
+            let a = (tile_x * tile_size + a) as u32;
+            let b = ((63 - tile_y) * tile_size + b) as u32;
+            img.get_pixel_mut(a, b).blend(&Rgba(TINT));
+        }
+    }
+}
+
+/// Saves every placement of the locations with one of `--highlight-loc` as `highlights`, in the configured format.
+pub fn export(config: &Config) -> CacheResult<()> {
+    let ids = config.highlight_loc.iter().copied().collect::<BTreeSet<_>>();
+    let mut placements = Vec::new();
+    for sq in MapSquares::new(config)?.into_iter() {
+        let Some(locations) = sq?.take_locations() else { continue };
+        placements.extend(locations.into_iter().filter(|loc| ids.contains(&loc.id)));
+    }
+    crate::output::export(config, "highlights", &placements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(id: u32, i: u8, x: u8, y: u8, rotation: u8) -> Location {
+        Location {
+            plane: Watery::False(0),
+            i,
+            j: 50,
+            x,
+            y,
+            id,
+            r#type: 10,
+            rotation,
+        }
+    }
+
+    #[test]
+    fn overlapping() {
+        let configs = BTreeMap::from([(
+            1,
+            LocationConfig {
+                id: 1,
+                dim_x: Some(2),
+                dim_y: Some(3),
+                ..Default::default()
+            },
+        )]);
+        let locations = [
+            location(1, 50, 0, 0, 1),
+            location(2, 50, 1, 1, 0),
+            location(3, 50, 5, 5, 0),
+            location(1, 49, 63, 10, 0),
+        ];
+        let tiles = covered((50, 50), locations.iter(), 0, &configs, &BTreeSet::from([1, 2]));
+
+        // The first is turned, so it is three wide and two high; the second lies within it.
+        // Only the second column of the last reaches into the core mapsquare.
+        assert_eq!(
+            tiles,
+            BTreeSet::from([(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1), (0, 10), (0, 11), (0, 12)])
+        );
+    }
+
+    #[test]
+    fn tinted_once() {
+        let background = Rgba([0, 0, 0, 255]);
+        let mut img = RgbaImage::from_pixel(128, 128, background);
+        tint(&mut img, &BTreeSet::from([(0, 63)]), 2);
+
+        let mut expected = background;
+        expected.blend(&Rgba(TINT));
+        assert_eq!(*img.get_pixel(1, 1), expected);
+        assert_eq!(*img.get_pixel(2, 2), background);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use image::{Rgba, RgbaImage};
+use itertools::iproduct;
+use rs3cache_backend::warning::{self, Warning};
+use rs3cache_utils::rangeclamp::RangeClamp;
+
+use super::text;
+use crate::{
+    definitions::{
+        fonts::Font,
+        location_configs::LocationConfig,
+        maplabel_configs::{self, MapLabelConfig},
+        mapsquares::GroupMapSquare,
+        sprites::Sprite,
+    },
+    renderers::map::RenderConfig,
+};
+
+/// The labels that locations of `plane` place on the map, with where they are centered in pixels.
+fn placed<'a>(
+    render: &'a RenderConfig,
+    plane: usize,
+    squares: &'a GroupMapSquare,
+    location_configs: &'a BTreeMap<u32, LocationConfig>,
+    map_labels: &'a BTreeMap<u32, MapLabelConfig>,
+) -> impl Iterator<Item = (&'a MapLabelConfig, (i32, i32))> + 'a {
+    squares
+        .all_locations_iter()
+        .filter(move |loc| loc.plane.matches(&(plane as u8)))
+        .filter_map(move |loc| {
+            let label = map_labels.get(&maplabel_configs::placed_by(location_configs.get(&loc.id)?)?)?;
+            let tile_size = render.tile_size as i32;
+            let center_a = tile_size * ((loc.i as i32 - squares.core_i() as i32) * 64 + loc.x as i32) + tile_size / 2;
+            let center_b = tile_size * (63 - (loc.j as i32 - squares.core_j() as i32) * 64 - loc.y as i32) + tile_size / 2;
+            Some((label, (center_a, center_b)))
+        })
+}
+
+/// Draws the sprites of the [`MapLabelConfig`]s that locations place, such as those of banks and altars, centered on their tile.
+pub fn put_icons(
+    render: &RenderConfig,
+    plane: usize,
+    img: &mut RgbaImage,
+    squares: &GroupMapSquare,
+    location_configs: &BTreeMap<u32, LocationConfig>,
+    map_labels: &BTreeMap<u32, MapLabelConfig>,
+    sprites: &BTreeMap<(u32, u32), Sprite>,
+) {
+    for (label, (center_a, center_b)) in placed(render, plane, squares, location_configs, map_labels) {
+        let Some(id) = label.sprite else { continue };
+        let Some(sprite) = sprites.get(&(id, 0)) else {
+            warning::warn(Warning::MissingSprite { id, frame: 0 });
+            continue;
+        };
+
+        let offset_a = center_a - sprite.width() as i32 / 2;
+        let offset_b = center_b - sprite.height() as i32 / 2;
+
+        let range_a = (offset_a..(offset_a + sprite.width() as i32)).clamp(0, img.width() as i32);
+        let range_b = (offset_b..(offset_b + sprite.height() as i32)).clamp(0, img.height() as i32);
+
+        for (a, b) in iproduct!(range_a, range_b) {
+            let sprite_pixel = sprite.get_pixel((a - offset_a) as u32, (b - offset_b) as u32);
+            if sprite_pixel[3] != 0 {
+                img.put_pixel(a as u32, b as u32, *sprite_pixel);
+            }
+        }
+    }
+}
+
+/// Draws the [`MapLabelConfig`]s that locations place as dots, as the minimap does, centered on their tile.
+pub fn put_dots(
+    render: &RenderConfig,
+    plane: usize,
+    img: &mut RgbaImage,
+    squares: &GroupMapSquare,
+    location_configs: &BTreeMap<u32, LocationConfig>,
+    map_labels: &BTreeMap<u32, MapLabelConfig>,
+) {
+    let radius = render.scale as i32;
+    for (label, (center_a, center_b)) in placed(render, plane, squares, location_configs, map_labels) {
+        let [red, green, blue] = label.label_colour_1.unwrap_or([255, 255, 0]);
+        let range_a = ((center_a - radius)..(center_a + radius + 1)).clamp(0, img.width() as i32);
+        let range_b = ((center_b - radius)..(center_b + radius + 1)).clamp(0, img.height() as i32);
+
+        for (a, b) in iproduct!(range_a, range_b) {
+            let distance = (a - center_a).pow(2) + (b - center_b).pow(2);
+            // The dot is outlined in black, so that it stands out on any ground.
+            if distance <= (radius - 1).pow(2) {
+                img.put_pixel(a as u32, b as u32, Rgba([red, green, blue, 255]));
+            } else if distance <= radius.pow(2) {
+                img.put_pixel(a as u32, b as u32, Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
+}
+
+/// Draws the text of the [`MapLabelConfig`]s that locations place, such as the names of towns, centered on their tile, in `font`.
+pub fn put_labels(
+    render: &RenderConfig,
+    plane: usize,
+    img: &mut RgbaImage,
+    squares: &GroupMapSquare,
+    location_configs: &BTreeMap<u32, LocationConfig>,
+    map_labels: &BTreeMap<u32, MapLabelConfig>,
+    font: Option<&Font>,
+) {
+    // Text is drawn at half the scale of sprites, which is about as large as the game draws it.
+    let scale = (render.scale / 2).max(1);
+    for (label, (center_a, center_b)) in placed(render, plane, squares, location_configs, map_labels) {
+        let Some(text) = label.text.as_deref() else { continue };
+        text::draw(
+            img,
+            text,
+            center_a,
+            center_b,
+            scale,
+            label.label_colour_1.unwrap_or([255, 255, 255]),
+            font,
+        );
+    }
+}
@@ -1,15 +1,40 @@
 use std::collections::BTreeMap;
 
-use image::{GenericImage, Rgba, RgbaImage};
+use image::{GenericImage, Pixel, Rgba, RgbaImage};
 use rs3cache_utils::color::Color;
 
 use crate::{
-    definitions::{location_configs::LocationConfig, mapsquares::GroupMapSquare},
-    renderers::map::{lineshape, CONFIG},
+    definitions::{
+        location_configs::LocationConfig,
+        locations::{Location, Watery},
+        mapsquares::GroupMapSquare,
+    },
+    renderers::map::{base, lineshape, RenderConfig},
 };
 
 /// Applies lines of doors, fences, walls and so on to the base image.
-pub fn put(plane: usize, img: &mut RgbaImage, squares: &GroupMapSquare, location_config: &BTreeMap<u32, LocationConfig>) {
+pub fn put(render: &RenderConfig, plane: usize, img: &mut RgbaImage, squares: &GroupMapSquare, location_config: &BTreeMap<u32, LocationConfig>) {
+    put_coloured(render, plane, img, squares, location_config, Rgba(Color::WHITE), Rgba(Color::PURE_RED))
+}
+
+/// Whether `location`, on a tile with `settings`, is drawn in the image of `plane`.
+///
+/// As with the ground, walls on bridges are drawn a plane lower, and those on plane 0 under a bridge are not drawn at all.
+fn is_drawn(location: &Location, plane: usize, settings: u8) -> bool {
+    location.plane.matches(&(plane as u8)) || (plane == 0 && settings & base::VISIBLE_BELOW != 0 && location.plane != Watery::True(0))
+}
+
+/// Like [`put`], but draws walls with `wall` and the lines that can be interacted with, such as doors, with `door`,
+/// unless `--wall-colour` or `--door-colour` say otherwise.
+pub fn put_coloured(
+    render: &RenderConfig,
+    plane: usize,
+    img: &mut RgbaImage,
+    squares: &GroupMapSquare,
+    location_config: &BTreeMap<u32, LocationConfig>,
+    wall: Rgba<u8>,
+    door: Rgba<u8>,
+) {
     if let Some(core) = squares.core() {
         if let Some(locations) = core.locations() {
             let tiles = core.tiles().expect("always some if it has locations");
@@ -19,39 +44,74 @@ pub fn put(plane: usize, img: &mut RgbaImage, squares: &GroupMapSquare, location
                 .filter(|(location, properties)| unsafe {
                     (location.r#type == 0 || location.r#type == 2 || location.r#type == 9)
                         && properties.mapscene.is_none()
-                        && ((location.plane.matches(&0) && plane == 0)
-                            || location.plane.contains(&(plane as u8))
-                            || (tiles.uget((0, location.x as usize, location.y as usize)).settings.unwrap_or(0) & 0x2 != 0
-                                && location.plane.inner() >= plane as u8)
-                            || (tiles
+                        && is_drawn(
+                            location,
+                            plane,
+                            tiles
                                 .uget((location.plane.inner() as usize, location.x as usize, location.y as usize))
                                 .settings
-                                .unwrap_or(0)
-                                & 0x8
-                                != 0
-                                && plane == 0))
+                                .unwrap_or(0),
+                        )
                 })
                 .for_each(|(location, properties)| {
                     let fill = if properties.unknown_19.contains(&1) || properties.actions.is_some() {
-                        Rgba(Color::PURE_RED)
+                        render.door_colour.map_or(door, Rgba)
                     } else {
-                        Rgba(Color::WHITE)
+                        render.wall_colour.map_or(wall, Rgba)
                     };
+                    let offset_a = render.tile_size * location.x as u32;
+                    let offset_b = render.tile_size * (63u32 - location.y as u32);
 
-                    lineshape::draw(location.r#type, location.rotation, CONFIG.tile_size, |(a, b)| unsafe {
-                        debug_assert!(
-                            (CONFIG.tile_size * location.x as u32 + a) < img.width()
-                                && (CONFIG.tile_size * (63u32 - location.y as u32) + b) < img.height(),
-                            "Index out of range."
+                    if render.antialias_lines {
+                        lineshape::draw_smooth(
+                            location.r#type,
+                            location.rotation,
+                            render.tile_size,
+                            render.line_width,
+                            |(a, b), coverage| {
+                                let mut fill = fill;
+                                fill[3] = (fill[3] as f32 * coverage).round() as u8;
+                                img.get_pixel_mut(offset_a + a, offset_b + b).blend(&fill);
+                            },
                         );
+                    } else {
+                        lineshape::draw(location.r#type, location.rotation, render.tile_size, render.line_width, |(a, b)| unsafe {
+                            debug_assert!((offset_a + a) < img.width() && (offset_b + b) < img.height(), "Index out of range.");
 
-                        img.unsafe_put_pixel(
-                            CONFIG.tile_size * location.x as u32 + a,
-                            CONFIG.tile_size * (63u32 - location.y as u32) + b,
-                            fill,
-                        );
-                    });
+                            img.unsafe_put_pixel(offset_a + a, offset_b + b, fill);
+                        });
+                    }
                 });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wall(plane: Watery) -> Location {
+        Location {
+            plane,
+            i: 50,
+            j: 50,
+            x: 0,
+            y: 0,
+            id: 0,
+            r#type: 0,
+            rotation: 0,
+        }
+    }
+
+    #[test]
+    fn bridges() {
+        // Walls under a bridge are not drawn, not even when their tile is visible from below.
+        let under = wall(Watery::True(0));
+        assert!((0..4).all(|plane| !is_drawn(&under, plane, 0) && !is_drawn(&under, plane, base::VISIBLE_BELOW)));
+        // Walls on a bridge are drawn a plane lower.
+        assert!(is_drawn(&wall(Watery::True(1)), 0, 0) && !is_drawn(&wall(Watery::True(1)), 1, 0));
+        assert!(is_drawn(&wall(Watery::False(0)), 0, 0) && !is_drawn(&wall(Watery::False(0)), 1, 0));
+        // Walls visible from below are also drawn on plane 0.
+        assert!(is_drawn(&wall(Watery::False(2)), 0, base::VISIBLE_BELOW) && !is_drawn(&wall(Watery::False(2)), 0, 0));
+    }
+}
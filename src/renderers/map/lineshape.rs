@@ -1,34 +1,56 @@
-pub fn draw(ty: u8, rotation: u8, size: u32, fun: impl FnMut((u32, u32))) {
+/// How many samples a pixel is split into along each axis by [`draw_smooth`].
+const SAMPLES: u32 = 4;
+
+/// Calls `fun` with every pixel of the line of a location of type `ty` with `rotation`, on a tile of `size` pixels.
+///
+/// The line is `width` percent of a tile thick.
+pub fn draw(ty: u8, rotation: u8, size: u32, width: u8, fun: impl FnMut((u32, u32))) {
     debug_assert!(size.is_power_of_two(), "{size} is an invalid size Only 2^n values are allowed.");
+    let thickness = (size * width as u32 / 100).clamp(1, size);
+    // Diagonal lines extend this far to either side.
+    let half = (thickness + 1) / 2;
 
     match (ty, rotation) {
-        (0, 0) => (0..(size / 4)).flat_map(|x| (0..size).map(move |y| (x, y))).for_each(fun),
-        (0, 1) => (0..size).flat_map(|x| (0..(size / 4)).map(move |y| (x, y))).for_each(fun),
-        (0, 2) => ((size * 3 / 4)..size).flat_map(|x| (0..size).map(move |y| (x, y))).for_each(fun),
-        (0, 3) => (0..size).flat_map(|x| ((size * 3 / 4)..size).map(move |y| (x, y))).for_each(fun),
+        (0, 0) => (0..thickness).flat_map(|x| (0..size).map(move |y| (x, y))).for_each(fun),
+        (0, 1) => (0..size).flat_map(|x| (0..thickness).map(move |y| (x, y))).for_each(fun),
+        (0, 2) => ((size - thickness)..size).flat_map(|x| (0..size).map(move |y| (x, y))).for_each(fun),
+        (0, 3) => (0..size).flat_map(|x| ((size - thickness)..size).map(move |y| (x, y))).for_each(fun),
         (2, 0) => (0..size)
-            .flat_map(|x| (0..if x < size / 4 { size } else { size / 4 }).map(move |y| (x, y)))
+            .flat_map(|x| (0..if x < thickness { size } else { thickness }).map(move |y| (x, y)))
             .for_each(fun),
         (2, 1) => (0..size)
-            .flat_map(|x| (0..if x < size * 3 / 4 { size / 4 } else { size }).map(move |y| (x, y)))
+            .flat_map(|x| (0..if x < size - thickness { thickness } else { size }).map(move |y| (x, y)))
             .for_each(fun),
         (2, 2) => (0..size)
-            .flat_map(|x| (if x < size * 3 / 4 { size * 3 / 4 } else { 0 }..size).map(move |y| (x, y)))
+            .flat_map(|x| (if x < size - thickness { size - thickness } else { 0 }..size).map(move |y| (x, y)))
             .for_each(fun),
         (2, 3) => (0..size)
-            .flat_map(|x| (if x < size / 4 { 0 } else { size * 3 / 4 }..size).map(move |y| (x, y)))
+            .flat_map(|x| (if x < thickness { 0 } else { size - thickness }..size).map(move |y| (x, y)))
             .for_each(fun),
         (9, 0) | (9, 2) => (0..size)
-            .flat_map(|x| ((size - x).saturating_sub(size / 8)..(size - x + size / 8).clamp(0, size)).map(move |y| (x, y)))
+            .flat_map(|x| ((size - x).saturating_sub(half)..(size - x + half).clamp(0, size)).map(move |y| (x, y)))
             .for_each(fun),
         (9, 1) | (9, 3) => (0..size)
-            .flat_map(|x| (x.saturating_sub(size / 8)..(x + size / 8).clamp(0, size)).map(move |y| (x, y)))
+            .flat_map(|x| (x.saturating_sub(half)..(x + half).clamp(0, size)).map(move |y| (x, y)))
             .for_each(fun),
 
         (other_type, other_rot) => unimplemented!("LineShape for type {} with rotation {} is not implemented.", other_type, other_rot),
     };
 }
 
+/// Like [`draw`], but calls `fun` with every pixel that the line covers, and how much of it it covers, from 0 to 1.
+pub fn draw_smooth(ty: u8, rotation: u8, size: u32, width: u8, mut fun: impl FnMut((u32, u32), f32)) {
+    let mut coverage = vec![0_u32; (size * size) as usize];
+    draw(ty, rotation, size * SAMPLES, width, |(a, b)| {
+        coverage[((b / SAMPLES) * size + a / SAMPLES) as usize] += 1
+    });
+
+    for (i, &covered) in coverage.iter().enumerate().filter(|(_, &covered)| covered != 0) {
+        let i = i as u32;
+        fun((i % size, i / size), covered as f32 / (SAMPLES * SAMPLES) as f32);
+    }
+}
+
 #[cfg(test)]
 mod line_shape_tests {
     use super::*;
@@ -42,13 +64,39 @@ mod line_shape_tests {
         for ty in &types {
             for rot in &rotations {
                 for size in &sizes {
-                    draw(*ty, *rot, *size, |(x, y)| {
-                        if !(x < *size && y < *size) {
-                            panic!("{x} {y} {ty} {rot} {size}")
-                        }
-                    });
+                    for width in [1, 25, 100] {
+                        draw(*ty, *rot, *size, width, |(x, y)| {
+                            if !(x < *size && y < *size) {
+                                panic!("{x} {y} {ty} {rot} {size}")
+                            }
+                        });
+                        draw_smooth(*ty, *rot, *size, width, |(x, y), _| {
+                            if !(x < *size && y < *size) {
+                                panic!("{x} {y} {ty} {rot} {size}")
+                            }
+                        });
+                    }
                 }
             }
         }
     }
+    #[test]
+    fn smooth() {
+        // A wall a quarter of a tile thick is four whole pixels wide on a tile of 16, and so covers them fully.
+        let mut pixels = Vec::new();
+        draw(0, 0, 16, 25, |pixel| pixels.push(pixel));
+        assert_eq!(pixels.len(), 4 * 16);
+        draw_smooth(0, 0, 16, 25, |pixel, coverage| {
+            assert!(pixels.contains(&pixel));
+            assert_eq!(coverage, 1.0);
+        });
+
+        // A diagonal wall covers some pixels only partially.
+        let mut partial = 0;
+        draw_smooth(9, 0, 16, 25, |_, coverage| {
+            assert!(coverage > 0.0 && coverage <= 1.0);
+            partial += (coverage < 1.0) as usize;
+        });
+        assert!(partial > 0);
+    }
 }
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use image::{GenericImage, GenericImageView, RgbaImage};
+use itertools::iproduct;
+use rs3cache_backend::warning::{self, Warning};
+use rs3cache_utils::rangeclamp::RangeClamp;
+
+use crate::{
+    definitions::{location_configs::LocationConfig, mapsquares::GroupMapSquare, sprites::Sprite},
+    renderers::map::RenderConfig,
+};
+
+/// Draws the map function icons of locations, such as banks and altars, centered on their tile.
+///
+/// Period world maps showed these icons directly on the map, rather than as a separate layer.
+pub fn put(
+    render: &RenderConfig,
+    plane: usize,
+    img: &mut RgbaImage,
+    squares: &GroupMapSquare,
+    location_config: &BTreeMap<u32, LocationConfig>,
+    sprites: &BTreeMap<(u32, u32), Sprite>,
+) {
+    squares
+        .all_locations_iter()
+        .filter(|loc| loc.plane.matches(&(plane as u8)))
+        .filter_map(|loc| {
+            let mapfunction = location_config.get(&loc.id)?.mapfunction?;
+            // 318 is the sprite named "mapfunction", whose frames form all the icons.
+            match sprites.get(&(318, mapfunction as u32)) {
+                Some(sprite) => Some((loc, sprite)),
+                None => {
+                    warning::warn(Warning::MissingSprite {
+                        id: 318,
+                        frame: mapfunction as u32,
+                    });
+                    None
+                }
+            }
+        })
+        .for_each(|(loc, sprite)| {
+            let center_a = render.tile_size as i32 * ((loc.i as i32 - squares.core_i() as i32) * 64 + loc.x as i32) + render.tile_size as i32 / 2;
+            let center_b =
+                render.tile_size as i32 * (63 - (loc.j as i32 - squares.core_j() as i32) * 64 - loc.y as i32) + render.tile_size as i32 / 2;
+
+            let offset_a = center_a - sprite.width() as i32 / 2;
+            let offset_b = center_b - sprite.height() as i32 / 2;
+
+            let range_a = (offset_a..(offset_a + sprite.width() as i32)).clamp(0, img.width() as i32);
+            let range_b = (offset_b..(offset_b + sprite.height() as i32)).clamp(0, img.height() as i32);
+
+            for (a, b) in iproduct!(range_a, range_b) {
+                let sprite_pixel = sprite.get_pixel((a - offset_a) as u32, (b - offset_b) as u32);
+                if sprite_pixel[3] != 0 {
+                    img.put_pixel(a as u32, b as u32, *sprite_pixel);
+                }
+            }
+        });
+}
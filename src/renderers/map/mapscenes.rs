@@ -2,16 +2,19 @@ use std::collections::BTreeMap;
 
 use image::{GenericImage, GenericImageView, RgbaImage};
 use itertools::iproduct;
+#[cfg(any(all(feature = "osrs", not(feature = "2009_1_shim")), feature = "legacy"))]
+use rs3cache_backend::warning::{self, Warning};
 use rs3cache_utils::rangeclamp::RangeClamp;
 
 #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
 use crate::definitions::mapscenes::MapScene;
 use crate::{
     definitions::{location_configs::LocationConfig, mapsquares::GroupMapSquare, sprites::Sprite},
-    renderers::map::CONFIG,
+    renderers::map::RenderConfig,
 };
 /// Applies [`MapScene`]s to the base image.
 pub fn put(
+    render: &RenderConfig,
     plane: usize,
     img: &mut RgbaImage,
     squares: &GroupMapSquare,
@@ -44,16 +47,21 @@ pub fn put(
                                 .map(|sprite_id| (loc, &sprites[&(sprite_id, 0)]))
                         }
 
-                        #[cfg(all(feature = "osrs", not(feature = "2009_1_shim")))]
+                        #[cfg(any(all(feature = "osrs", not(feature = "2009_1_shim")), feature = "legacy"))]
                         {
                             // 317 is the sprite named "mapscene", whose frames form all the mapscenes.
                             // 22 is missing and indicates the empty mapscene, which is why this does not index
-                            sprites.get(&(317, mapscene_id as u32)).map(|s| (loc, s))
-                        }
-
-                        #[cfg(feature = "legacy")]
-                        {
-                            sprites.get(&(317, mapscene_id as u32)).map(|s| (loc, s))
+                            match sprites.get(&(317, mapscene_id as u32)) {
+                                Some(sprite) => Some((loc, sprite)),
+                                None if mapscene_id == 22 => None,
+                                None => {
+                                    warning::warn(Warning::MissingSprite {
+                                        id: 317,
+                                        frame: mapscene_id as u32,
+                                    });
+                                    None
+                                }
+                            }
                         }
                     })
             } else {
@@ -61,8 +69,8 @@ pub fn put(
             }
         })
         .for_each(|(loc, sprite)| {
-            let offset_a = CONFIG.tile_size as i32 * ((loc.i as i32 - squares.core_i() as i32) * 64 + loc.x as i32);
-            let offset_b = CONFIG.tile_size as i32 * (63 - (loc.j as i32 - squares.core_j() as i32) * 64 - loc.y as i32);
+            let offset_a = render.tile_size as i32 * ((loc.i as i32 - squares.core_i() as i32) * 64 + loc.x as i32);
+            let offset_b = render.tile_size as i32 * (63 - (loc.j as i32 - squares.core_j() as i32) * 64 - loc.y as i32);
 
             let dim_a = sprite.width() as i32;
             let dim_b = sprite.height() as i32;
@@ -0,0 +1,288 @@
+//! Points, lines, polygons and labels from a GeoJSON file that are drawn onto the map, as given by `--markers markers.geojson`.
+//!
+//! Coordinates are game coordinates, like those of `--dump locations --format geojson`: `[3222.5, 3218.5]` is the centre
+//! of the tile at 3222, 3218. Features can have these properties, which are all optional:
+//!
+//! - `plane`: the plane they are drawn on, 0 by default.
+//! - `colour`: their colour, as in `#ff0000` or `#ff000080`. Red by default.
+//! - `width`: how thick lines and outlines are, and the radius of points, in pixels.
+//! - `label`: text that is drawn at points and at the start of lines and polygons.
+
+use std::fs;
+
+use image::{Pixel, Rgba, RgbaImage};
+use serde_json::Value;
+
+use super::{parse_colour, text, Img, RenderConfig};
+use crate::definitions::{fonts::Font, mapsquares::GroupMapSquare};
+
+/// A point in game coordinates.
+type Point = (f32, f32);
+
+/// The geometry of a [`Marker`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Points(Vec<Point>),
+    Lines(Vec<Vec<Point>>),
+    /// Polygons, each an outer ring followed by its holes.
+    Polygons(Vec<Vec<Vec<Point>>>),
+}
+
+/// A feature of the markers file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    pub plane: usize,
+    pub shape: Shape,
+    pub colour: [u8; 4],
+    pub width: f32,
+    pub label: Option<String>,
+}
+
+/// Everything that `--markers` draws onto the map.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Markers {
+    pub markers: Vec<Marker>,
+}
+
+fn point(value: &Value) -> Result<Point, String> {
+    match value
+        .as_array()
+        .map(|coordinates| coordinates.iter().map(Value::as_f64).collect::<Vec<_>>())
+        .as_deref()
+    {
+        Some([Some(x), Some(y), ..]) => Ok((*x as f32, *y as f32)),
+        _ => Err(format!("{value} is not a coordinate")),
+    }
+}
+
+fn points(value: &Value) -> Result<Vec<Point>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("{value} is not a list of coordinates"))?
+        .iter()
+        .map(point)
+        .collect()
+}
+
+fn lists<T>(value: &Value, item: impl Fn(&Value) -> Result<T, String>) -> Result<Vec<T>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("{value} is not a list"))?
+        .iter()
+        .map(item)
+        .collect()
+}
+
+fn shape(geometry: &Value) -> Result<Shape, String> {
+    let coordinates = &geometry["coordinates"];
+    match geometry["type"].as_str() {
+        Some("Point") => Ok(Shape::Points(vec![point(coordinates)?])),
+        Some("MultiPoint") => Ok(Shape::Points(points(coordinates)?)),
+        Some("LineString") => Ok(Shape::Lines(vec![points(coordinates)?])),
+        Some("MultiLineString") => Ok(Shape::Lines(lists(coordinates, points)?)),
+        Some("Polygon") => Ok(Shape::Polygons(vec![lists(coordinates, points)?])),
+        Some("MultiPolygon") => Ok(Shape::Polygons(lists(coordinates, |polygon| lists(polygon, points))?)),
+        _ => Err(format!("{} is not a supported geometry", geometry["type"])),
+    }
+}
+
+impl Markers {
+    /// Parses a GeoJSON feature collection, or a single feature.
+    pub fn from_geojson(geojson: &str) -> Result<Self, String> {
+        let value = serde_json::from_str::<Value>(geojson).map_err(|e| e.to_string())?;
+        let features = match value["type"].as_str() {
+            Some("FeatureCollection") => value["features"].as_array().cloned().unwrap_or_default(),
+            Some("Feature") => vec![value],
+            _ => return Err("expected a Feature or FeatureCollection".to_string()),
+        };
+
+        let markers = features
+            .iter()
+            .map(|feature| {
+                let properties = &feature["properties"];
+                Ok(Marker {
+                    plane: properties["plane"].as_u64().unwrap_or(0).min(3) as usize,
+                    shape: shape(&feature["geometry"])?,
+                    colour: properties["colour"]
+                        .as_str()
+                        .or_else(|| properties["color"].as_str())
+                        .map_or(Ok([255, 0, 0, 255]), parse_colour)?,
+                    width: properties["width"].as_f64().unwrap_or(2.0) as f32,
+                    label: properties["label"].as_str().map(str::to_string),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self { markers })
+    }
+
+    /// Draws the markers that are on the core [`MapSquare`](crate::definitions::mapsquares::MapSquare) of `squares` onto its planes,
+    /// with their labels in `font`.
+    pub fn put(&self, render: &RenderConfig, squares: &GroupMapSquare, imgs: &mut [Img; 4], font: Option<&Font>) {
+        // Markers are drawn in pixels relative to the core mapsquare; what falls outside of it is left out.
+        let origin = (squares.core_i() as f32 * 64.0, squares.core_j() as f32 * 64.0);
+        let tile_size = render.tile_size as f32;
+        let pixel = |(x, y): Point| ((x - origin.0) * tile_size, (64.0 - (y - origin.1)) * tile_size);
+
+        for marker in &self.markers {
+            let img = &mut imgs[marker.plane];
+            let colour = Rgba(marker.colour);
+            let (label_a, label_b) = match &marker.shape {
+                Shape::Points(points) => {
+                    for &point in points {
+                        circle(img, pixel(point), marker.width, colour);
+                    }
+                    points.first().map(|&point| pixel(point)).unwrap_or_default()
+                }
+                Shape::Lines(lines) => {
+                    for line in lines {
+                        for segment in line.windows(2) {
+                            stroke(img, pixel(segment[0]), pixel(segment[1]), marker.width, colour);
+                        }
+                    }
+                    lines.iter().flatten().next().map(|&point| pixel(point)).unwrap_or_default()
+                }
+                Shape::Polygons(polygons) => {
+                    let mut fill = colour;
+                    fill[3] /= 3;
+                    for polygon in polygons {
+                        let rings = polygon
+                            .iter()
+                            .map(|ring| ring.iter().map(|&point| pixel(point)).collect::<Vec<_>>())
+                            .collect::<Vec<_>>();
+                        fill_polygon(img, &rings, fill);
+                        for ring in &rings {
+                            for segment in ring.windows(2) {
+                                stroke(img, segment[0], segment[1], marker.width, colour);
+                            }
+                        }
+                    }
+                    polygons.iter().flatten().flatten().next().map(|&point| pixel(point)).unwrap_or_default()
+                }
+            };
+
+            if let Some(label) = &marker.label {
+                let [red, green, blue, _] = marker.colour;
+                let (_, height) = text::size(label, render.scale.max(1), font);
+                let above = label_b as i32 - marker.width as i32 - height as i32 / 2 - 1;
+                text::draw(img, label, label_a as i32, above, render.scale.max(1), [red, green, blue], font);
+            }
+        }
+    }
+}
+
+/// Blends `colour` over the pixel at `(a, b)`, if it is in `img`.
+fn blend(img: &mut RgbaImage, a: i64, b: i64, colour: Rgba<u8>) {
+    if (0..img.width() as i64).contains(&a) && (0..img.height() as i64).contains(&b) {
+        img.get_pixel_mut(a as u32, b as u32).blend(&colour);
+    }
+}
+
+/// The pixels in `img` whose centres are within the bounds, which may be outside it.
+fn pixels_within(img: &RgbaImage, (min_a, min_b): Point, (max_a, max_b): Point) -> impl Iterator<Item = (i64, i64)> {
+    let clamp_a = |a: f32| (a.floor() as i64).clamp(0, img.width() as i64);
+    let clamp_b = |b: f32| (b.floor() as i64).clamp(0, img.height() as i64);
+    let (range_a, range_b) = (clamp_a(min_a)..clamp_a(max_a) + 1, clamp_b(min_b)..clamp_b(max_b) + 1);
+    range_b.flat_map(move |b| range_a.clone().map(move |a| (a, b)))
+}
+
+fn circle(img: &mut RgbaImage, (center_a, center_b): Point, radius: f32, colour: Rgba<u8>) {
+    let pixels = pixels_within(img, (center_a - radius, center_b - radius), (center_a + radius, center_b + radius)).collect::<Vec<_>>();
+    for (a, b) in pixels {
+        let (da, db) = (a as f32 + 0.5 - center_a, b as f32 + 0.5 - center_b);
+        if da * da + db * db <= radius * radius {
+            blend(img, a, b, colour);
+        }
+    }
+}
+
+/// Draws the segment from `start` to `end`, `width` pixels thick.
+fn stroke(img: &mut RgbaImage, start: Point, end: Point, width: f32, colour: Rgba<u8>) {
+    let half = width / 2.0;
+    let min = (start.0.min(end.0) - half, start.1.min(end.1) - half);
+    let max = (start.0.max(end.0) + half, start.1.max(end.1) + half);
+    let (da, db) = (end.0 - start.0, end.1 - start.1);
+    let length = da * da + db * db;
+
+    let pixels = pixels_within(img, min, max).collect::<Vec<_>>();
+    for (a, b) in pixels {
+        let (pa, pb) = (a as f32 + 0.5, b as f32 + 0.5);
+        // The distance from the centre of the pixel to the closest point of the segment.
+        let t = if length == 0.0 {
+            0.0
+        } else {
+            (((pa - start.0) * da + (pb - start.1) * db) / length).clamp(0.0, 1.0)
+        };
+        let (ca, cb) = (start.0 + t * da - pa, start.1 + t * db - pb);
+        if ca * ca + cb * cb <= half * half {
+            blend(img, a, b, colour);
+        }
+    }
+}
+
+/// Fills the pixels whose centres are inside an odd number of `rings`, so that inner rings are holes.
+fn fill_polygon(img: &mut RgbaImage, rings: &[Vec<Point>], colour: Rgba<u8>) {
+    let points = rings.iter().flatten();
+    let min = points.clone().fold((f32::MAX, f32::MAX), |min, &(a, b)| (min.0.min(a), min.1.min(b)));
+    let max = points.fold((f32::MIN, f32::MIN), |max, &(a, b)| (max.0.max(a), max.1.max(b)));
+
+    let pixels = pixels_within(img, min, max).collect::<Vec<_>>();
+    for (a, b) in pixels {
+        let (pa, pb) = (a as f32 + 0.5, b as f32 + 0.5);
+        let crossings = rings
+            .iter()
+            .flat_map(|ring| ring.iter().zip(ring.iter().cycle().skip(1)))
+            .filter(|((a1, b1), (a2, b2))| (*b1 > pb) != (*b2 > pb) && pa < a1 + (pb - b1) / (b2 - b1) * (a2 - a1))
+            .count();
+        if crossings % 2 == 1 {
+            blend(img, a, b, colour);
+        }
+    }
+}
+
+/// Reads the GeoJSON file at `path`, for `--markers`.
+pub fn parse(path: &str) -> Result<Markers, String> {
+    let geojson = fs::read_to_string(path).map_err(|e| format!("cannot read {path:?}: {e}"))?;
+    Markers::from_geojson(&geojson)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses() {
+        let markers = Markers::from_geojson(
+            r##"{"type": "FeatureCollection", "features": [
+                {"type": "Feature", "geometry": {"type": "Point", "coordinates": [3222.5, 3218.5]},
+                 "properties": {"label": "Lumbridge", "plane": 1}},
+                {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[3200, 3200], [3210, 3200]]},
+                 "properties": {"colour": "#00ff00", "width": 4}}
+            ]}"##,
+        )
+        .unwrap();
+        assert_eq!(markers.markers[0].shape, Shape::Points(vec![(3222.5, 3218.5)]));
+        assert_eq!((markers.markers[0].plane, markers.markers[0].label.as_deref()), (1, Some("Lumbridge")));
+        assert_eq!((markers.markers[1].colour, markers.markers[1].width), ([0, 255, 0, 255], 4.0));
+        assert!(Markers::from_geojson(r#"{"type": "Feature", "geometry": {"type": "Circle"}}"#).is_err());
+    }
+
+    #[test]
+    fn draws() {
+        let mut img = RgbaImage::new(16, 16);
+        let red = Rgba([255, 0, 0, 255]);
+        stroke(&mut img, (0.0, 8.0), (16.0, 8.0), 2.0, red);
+        assert_ne!(img.get_pixel(5, 7)[3], 0);
+        assert_eq!(img.get_pixel(5, 4)[3], 0);
+
+        let mut img = RgbaImage::new(16, 16);
+        let square = vec![(2.0, 2.0), (14.0, 2.0), (14.0, 14.0), (2.0, 14.0)];
+        let hole = vec![(6.0, 6.0), (10.0, 6.0), (10.0, 10.0), (6.0, 10.0)];
+        fill_polygon(&mut img, &[square, hole], red);
+        assert_ne!(img.get_pixel(3, 3)[3], 0);
+        assert_eq!(img.get_pixel(8, 8)[3], 0);
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+
+        // Anything outside the image is left out.
+        circle(&mut img, (-100.0, -100.0), 4.0, red);
+    }
+}
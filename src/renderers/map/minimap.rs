@@ -0,0 +1,72 @@
+use image::{Rgba, RgbaImage};
+use rs3cache_utils::color::Color;
+
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use super::icons;
+#[cfg(feature = "legacy")]
+use super::mapfunctions;
+use super::{base, lines, mapscenes, Definitions, Img, RenderConfig};
+use crate::definitions::mapsquares::GroupMapSquare;
+
+/// The colour of walls on the minimap.
+pub const WALL: [u8; 4] = [238, 238, 238, 255];
+
+/// The colour of doors and other lines that can be interacted with on the minimap.
+pub const DOOR: [u8; 4] = [238, 0, 0, 255];
+
+/// Renders every plane of the core [`MapSquare`](crate::definitions::mapsquares::MapSquare) of `squares` as the minimap draws it,
+/// without saving them.
+pub fn render_planes(render: &RenderConfig, squares: &GroupMapSquare, definitions: &Definitions) -> [Img; 4] {
+    // The minimap colours every tile by itself.
+    let flat = RenderConfig {
+        interp: 0,
+        light_intensity: 0,
+        shoreline: 0,
+        ..*render
+    };
+
+    let func = |plane| {
+        let mut img = RgbaImage::from_pixel(render.dim(), render.dim(), Rgba(Color::ALPHA));
+
+        base::put(
+            &flat,
+            plane,
+            &mut img,
+            squares,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            &definitions.underlays,
+            #[cfg(any(feature = "rs3", feature = "osrs"))]
+            &definitions.overlays,
+            #[cfg(feature = "legacy")]
+            &definitions.flos,
+            #[cfg(feature = "gpu")]
+            definitions.gpu,
+        );
+        lines::put_coloured(
+            render,
+            plane,
+            &mut img,
+            squares,
+            &definitions.location_configs,
+            definitions.palette.wall(WALL),
+            definitions.palette.door(DOOR),
+        );
+        mapscenes::put(
+            render,
+            plane,
+            &mut img,
+            squares,
+            &definitions.location_configs,
+            #[cfg(any(feature = "rs3", feature = "2009_1_shim"))]
+            &definitions.mapscenes,
+            &definitions.sprites,
+        );
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        icons::put_dots(render, plane, &mut img, squares, &definitions.location_configs, &definitions.map_labels);
+        #[cfg(feature = "legacy")]
+        mapfunctions::put(render, plane, &mut img, squares, &definitions.location_configs, &definitions.sprites);
+        img
+    };
+
+    [func(0), func(1), func(2), func(3)]
+}
@@ -0,0 +1,168 @@
+//! Colours that override those of the cache and of the render passes, as given by `--palette palette.toml`.
+//!
+//! A palette looks like this; every part of it is optional:
+//!
+//! ```toml
+//! # The colours of underlays and overlays, by id.
+//! [underlays]
+//! 12 = "#4a7a2a"
+//!
+//! [overlays]
+//! 5 = "#606060"
+//!
+//! # The colours of features, by their class.
+//! [classes]
+//! wall = "#e0e0e0"
+//! door = "#c04040"
+//! ```
+//!
+//! Underlays and overlays are drawn opaque, so their colours cannot be transparent.
+//! `--wall-colour` and `--door-colour` take precedence over the classes of a palette.
+//! Water is coloured by `--water-colour` rather than by the palette, as it is not one overlay in every game.
+
+use std::{collections::BTreeMap, fs};
+
+use image::Rgba;
+use serde::Deserialize;
+
+#[cfg(feature = "legacy")]
+use crate::definitions::flo::Flo;
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::definitions::{overlays::Overlay, underlays::Underlay};
+use crate::renderers::map::parse_colour;
+
+/// A palette as it is written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Raw {
+    #[serde(default)]
+    underlays: BTreeMap<String, String>,
+    #[serde(default)]
+    overlays: BTreeMap<String, String>,
+    #[serde(default)]
+    classes: Classes<String>,
+}
+
+/// Colours of features, rather than of a specific definition.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Classes<C> {
+    /// Walls and fences.
+    pub wall: Option<C>,
+    /// Doors and other lines that can be interacted with.
+    pub door: Option<C>,
+}
+
+/// Colours that override those of the cache and of the render passes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Palette {
+    /// The colours of underlays, by id.
+    pub underlays: BTreeMap<u32, [u8; 3]>,
+    /// The colours of overlays, by id.
+    pub overlays: BTreeMap<u32, [u8; 3]>,
+    /// The colours of features.
+    pub classes: Classes<[u8; 4]>,
+}
+
+impl Palette {
+    /// Parses the toml of a palette.
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        let raw: Raw = toml::from_str(toml).map_err(|e| e.to_string())?;
+        let colours = |table: BTreeMap<String, String>| {
+            table
+                .into_iter()
+                .map(|(id, colour)| {
+                    let id = id.parse::<u32>().map_err(|e| format!("{id:?} is not an id: {e}"))?;
+                    match parse_colour(&colour)? {
+                        [red, green, blue, 255] => Ok((id, [red, green, blue])),
+                        _ => Err(format!("the colour of {id}, {colour:?}, is transparent")),
+                    }
+                })
+                .collect::<Result<BTreeMap<_, _>, String>>()
+        };
+        let class = |colour: Option<String>| colour.as_deref().map(parse_colour).transpose();
+
+        Ok(Self {
+            underlays: colours(raw.underlays)?,
+            overlays: colours(raw.overlays)?,
+            classes: Classes {
+                wall: class(raw.classes.wall)?,
+                door: class(raw.classes.door)?,
+            },
+        })
+    }
+
+    /// The colour of walls, or `default` if the palette has none.
+    pub fn wall(&self, default: [u8; 4]) -> Rgba<u8> {
+        Rgba(self.classes.wall.unwrap_or(default))
+    }
+
+    /// The colour of doors, or `default` if the palette has none.
+    pub fn door(&self, default: [u8; 4]) -> Rgba<u8> {
+        Rgba(self.classes.door.unwrap_or(default))
+    }
+
+    /// Replaces the colours of `underlays` and `overlays` with those of the palette.
+    #[cfg(any(feature = "rs3", feature = "osrs"))]
+    pub fn apply(&self, underlays: &mut BTreeMap<u32, Underlay>, overlays: &mut BTreeMap<u32, Overlay>) {
+        for (id, colour) in &self.underlays {
+            if let Some(underlay) = underlays.get_mut(id) {
+                underlay.colour = Some(*colour);
+            }
+        }
+        for (id, colour) in &self.overlays {
+            if let Some(overlay) = overlays.get_mut(id) {
+                overlay.primary_colour = Some(*colour);
+                overlay.secondary_colour = None;
+                overlay.texture = None;
+            }
+        }
+    }
+
+    /// Replaces the colours of `flos` with those of the palette, where underlays and overlays are both flos.
+    #[cfg(feature = "legacy")]
+    pub fn apply(&self, flos: &mut BTreeMap<u32, Flo>) {
+        for (id, colour) in self.underlays.iter().chain(&self.overlays) {
+            if let Some(flo) = flos.get_mut(id) {
+                flo.primary_colour = Some(*colour);
+                flo.secondary_colour = None;
+                flo.texture = None;
+            }
+        }
+    }
+}
+
+/// Reads the palette file at `path`, for `--palette`.
+pub fn parse(path: &str) -> Result<Palette, String> {
+    let toml = fs::read_to_string(path).map_err(|e| format!("cannot read {path:?}: {e}"))?;
+    Palette::from_toml(&toml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes() {
+        let palette = Palette::from_toml(
+            r##"
+            [overlays]
+            5 = "#606060"
+
+            [classes]
+            wall = "#e0e0e080"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(palette.overlays[&5], [96, 96, 96]);
+        assert_eq!(palette.wall([255; 4]), Rgba([224, 224, 224, 128]));
+        assert_eq!(palette.door([255; 4]), Rgba([255; 4]));
+
+        assert_eq!(Palette::from_toml("").unwrap(), Palette::default());
+        assert!(Palette::from_toml("[overlays]\nfive = \"#606060\"").is_err());
+        assert!(Palette::from_toml("[classes]\nroof = \"#606060\"").is_err());
+        // Water is coloured by `--water-colour`, and the ground is opaque.
+        assert!(Palette::from_toml("[classes]\nwater = \"#203050\"").is_err());
+        assert!(Palette::from_toml("[underlays]\n12 = \"#4a7a2a80\"").is_err());
+    }
+}
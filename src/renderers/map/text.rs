@@ -0,0 +1,230 @@
+use image::{Rgba, RgbaImage};
+
+use crate::definitions::fonts::Font;
+
+/// The glyphs of the printable ASCII characters, from `' '` to `'~'`.
+///
+/// Every glyph is five columns of seven pixels, with the top pixel in the lowest bit.
+/// Other characters are drawn as `'?'`.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x00, 0x08, 0x14, 0x22, 0x41], [0x14, 0x14, 0x14, 0x14, 0x14], [0x41, 0x22, 0x14, 0x08, 0x00], [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x01, 0x01], [0x3E, 0x41, 0x41, 0x51, 0x32],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x04, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x00, 0x7F, 0x41, 0x41],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x41, 0x41, 0x7F, 0x00, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x08, 0x14, 0x54, 0x54, 0x3C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00], [0x00, 0x7F, 0x10, 0x28, 0x44],
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x10, 0x08, 0x08, 0x10, 0x08],
+];
+
+/// The width of a glyph and the space after it, in unscaled pixels.
+const ADVANCE: i32 = 6;
+
+/// The height of a line and the space below it, in unscaled pixels.
+const LINE_HEIGHT: i32 = 9;
+
+fn glyph(c: char) -> &'static [u8; 5] {
+    match c {
+        ' '..='~' => &GLYPHS[c as usize - ' ' as usize],
+        _ => &GLYPHS['?' as usize - ' ' as usize],
+    }
+}
+
+/// What text is drawn in.
+enum Face<'a> {
+    /// The glyphs in [`GLYPHS`], which every game can draw in.
+    Builtin,
+    Cache(&'a Font),
+}
+
+impl<'a> Face<'a> {
+    fn new(font: Option<&'a Font>) -> Self {
+        font.map_or(Self::Builtin, Self::Cache)
+    }
+
+    /// The character that is drawn for `c`, as `'?'` stands in for characters that the face does not have.
+    fn character(&self, c: char) -> char {
+        match self {
+            Self::Builtin => c,
+            Self::Cache(font) if font.advance(c) == 0 => '?',
+            Self::Cache(_) => c,
+        }
+    }
+
+    /// How far `c` moves the pen.
+    fn advance(&self, c: char) -> i32 {
+        match self {
+            Self::Builtin => ADVANCE,
+            Self::Cache(font) => font.advance(self.character(c)) as i32,
+        }
+    }
+
+    fn line_height(&self) -> i32 {
+        match self {
+            Self::Builtin => LINE_HEIGHT,
+            Self::Cache(font) => font.line_height as i32,
+        }
+    }
+
+    /// The space after the last character of a line and below the last line, which is not part of the text.
+    fn trailing(&self) -> (i32, i32) {
+        match self {
+            Self::Builtin => (ADVANCE - 5, LINE_HEIGHT - 7),
+            Self::Cache(_) => (0, 0),
+        }
+    }
+
+    /// Calls `put` with every pixel of `c`, relative to the pen at the top of the line.
+    fn pixels(&self, c: char, mut put: impl FnMut(i32, i32)) {
+        match self {
+            Self::Builtin => {
+                for (x, bits) in glyph(c).iter().enumerate() {
+                    for y in (0..8).filter(|y| bits >> y & 1 != 0) {
+                        put(x as i32, y);
+                    }
+                }
+            }
+            Self::Cache(font) => {
+                let Some(glyph) = font.glyph(self.character(c)) else { return };
+                for (x, y, pixel) in glyph.image.enumerate_pixels() {
+                    if pixel[3] != 0 {
+                        put(glyph.offset_x as i32 + x as i32, glyph.offset_y as i32 + y as i32);
+                    }
+                }
+            }
+        }
+    }
+
+    fn line_width(&self, line: &str) -> i32 {
+        (line.chars().map(|c| self.advance(c)).sum::<i32>() - self.trailing().0).max(0)
+    }
+
+    fn size(&self, text: &str, scale: u32) -> (u32, u32) {
+        let width = lines(text).map(|line| self.line_width(line)).max().unwrap_or(0);
+        let rows = lines(text).count() as i32;
+        let height = (rows * self.line_height() - self.trailing().1).max(0);
+        (width as u32 * scale, height as u32 * scale)
+    }
+
+    fn draw(&self, img: &mut RgbaImage, text: &str, center_a: i32, center_b: i32, scale: u32, colour: [u8; 3]) {
+        let (width, height) = self.size(text, scale);
+        let left = center_a - width as i32 / 2;
+        let top = center_b - height as i32 / 2;
+        let [red, green, blue] = colour;
+
+        let mut put = |a: i32, b: i32, pixel: Rgba<u8>| {
+            if (0..img.width() as i32).contains(&a) && (0..img.height() as i32).contains(&b) {
+                img.put_pixel(a as u32, b as u32, pixel);
+            }
+        };
+        let scale = scale as i32;
+        // The shadow is drawn first, so that the text goes over the shadow of the glyph before it.
+        for (shadow, pixel) in [(scale.max(1) / 2 + 1, Rgba([0, 0, 0, 255])), (0, Rgba([red, green, blue, 255]))] {
+            for (row, line) in lines(text).enumerate() {
+                // Every line is centered by itself.
+                let line_left = left + (width as i32 - self.line_width(line) * scale) / 2;
+                let mut pen = 0;
+                for c in line.chars() {
+                    self.pixels(c, |x, y| {
+                        let a = line_left + (pen + x) * scale + shadow;
+                        let b = top + (row as i32 * self.line_height() + y) * scale + shadow;
+                        for (da, db) in itertools::iproduct!(0..scale, 0..scale) {
+                            put(a + da, b + db, pixel);
+                        }
+                    });
+                    pen += self.advance(c);
+                }
+            }
+        }
+    }
+}
+
+/// The lines of a label; the game separates them with `<br>`.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split("<br>").map(str::trim)
+}
+
+/// The width and height of `text` drawn in `font` at `scale`, in pixels.
+pub fn size(text: &str, scale: u32, font: Option<&Font>) -> (u32, u32) {
+    Face::new(font).size(text, scale)
+}
+
+/// Draws `text` in `colour` centered on `center_a, center_b`, with a black shadow so that it stays legible on any ground.
+///
+/// Text is drawn in `font`, which is the font of the game's map labels if the cache has it,
+/// or else in built in glyphs.
+///
+/// Pixels outside of `img` are left out.
+pub fn draw(img: &mut RgbaImage, text: &str, center_a: i32, center_b: i32, scale: u32, colour: [u8; 3], font: Option<&Font>) {
+    Face::new(font).draw(img, text, center_a, center_b, scale, colour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(Face::Builtin.size("Bank", 1), (23, 7));
+        assert_eq!(Face::Builtin.size("Bank", 2), (46, 14));
+        assert_eq!(Face::Builtin.size("Lumbridge<br>Castle", 1), (53, 16));
+    }
+
+    #[test]
+    fn draws_inside() {
+        let mut img = RgbaImage::new(64, 64);
+        draw(&mut img, "Bank", 0, 0, 2, [255, 255, 255], None);
+        draw(&mut img, "Bank", 32, 32, 2, [255, 255, 255], None);
+        assert!(img.pixels().any(|pixel| pixel[0] == 255));
+    }
+
+    #[test]
+    fn cache_fonts() {
+        use std::collections::BTreeMap;
+
+        use crate::definitions::fonts::Glyph;
+
+        // Every character is a two by three block, and the space is three pixels wide.
+        let mut advances = vec![0; 256];
+        advances[b'?' as usize] = 3;
+        advances[b' ' as usize] = 3;
+        let glyph = Glyph {
+            offset_x: 0,
+            offset_y: 1,
+            image: RgbaImage::from_pixel(2, 3, Rgba([255, 255, 255, 255])),
+        };
+        let font = Font {
+            name: "test".to_string(),
+            line_height: 5,
+            advances,
+            glyphs: BTreeMap::from([(b'?', glyph)]),
+        };
+        let face = Face::new(Some(&font));
+
+        // Characters that the font does not have are drawn as '?'.
+        assert_eq!(face.size("ab c", 1), (12, 5));
+        assert_eq!(face.size("a<br>b", 2), (6, 20));
+
+        let mut img = RgbaImage::new(16, 16);
+        face.draw(&mut img, "a", 8, 8, 1, [255, 0, 0]);
+        assert_eq!(img.get_pixel(7, 7), &Rgba([255, 0, 0, 255]));
+        assert_eq!(img.get_pixel(7, 6)[3], 0);
+    }
+}
@@ -1,147 +1,71 @@
+//! The shapes that tiles are split into by their overlay.
+//!
+//! Shapes are numbered as in the cache: the upper bits are one of the 12 kinds of shape of the client,
+//! and the lowest two bits are how many quarter turns it is rotated by. Like the client draws tiles on the minimap,
+//! a tile is split into 4 by 4 cells, each of which is covered by either its overlay or its underlay.
+
+/// Which of the 4 by 4 cells of a tile the overlay covers, for each kind of shape, in rows from the top.
+#[rustfmt::skip]
+const MASKS: [[u8; 16]; 12] = [
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 0, 0, 0, 1, 1, 0, 0, 1, 1, 1, 0, 1, 1, 1, 1],
+    [1, 1, 0, 0, 1, 1, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0],
+    [0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 0, 1, 1, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0],
+    [1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 1, 0, 0],
+    [1, 1, 1, 1, 1, 1, 1, 1, 0, 1, 1, 1, 0, 0, 1, 1],
+    [1, 1, 1, 1, 1, 1, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 1, 1, 1],
+];
+
+/// For each rotation, which cell of the unrotated [mask](MASKS) each cell is taken from.
+#[rustfmt::skip]
+const ROTATIONS: [[usize; 16]; 4] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [12, 8, 4, 0, 13, 9, 5, 1, 14, 10, 6, 2, 15, 11, 7, 3],
+    [15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+    [3, 7, 11, 15, 2, 6, 10, 14, 1, 5, 9, 13, 0, 4, 8, 12],
+];
+
+/// Which of the 4 by 4 cells of a tile the overlay of `shape` covers, with a bit for each, in rows from the top.
+pub fn mask(shape: u8) -> u16 {
+    let Some(mask) = MASKS.get((shape >> 2) as usize) else { unimplemented!("Shape {} is not implemented.", shape) };
+    (0..16)
+        .filter(|&cell| mask[ROTATIONS[(shape & 3) as usize][cell]] == 1)
+        .fold(0, |bits, cell| bits | 1 << cell)
+}
+
+/// Whether the overlay of `shape` covers the pixel at `x`, `y` of a tile of `size` pixels.
+fn covers(shape: u8, size: u32, (x, y): (u32, u32)) -> bool {
+    let cell = (y * 4 / size) * 4 + x * 4 / size;
+    mask(shape) & 1 << cell != 0
+}
+
+fn pixels(size: u32) -> impl Iterator<Item = (u32, u32)> {
+    (0..size).flat_map(move |x| (0..size).map(move |y| (x, y)))
+}
+
 pub fn draw_overlay(shape: u8, size: u32, fun: impl FnMut((u32, u32))) {
     debug_assert!(size.is_power_of_two(), "{size} is an invalid size, only 2^n values are allowed.");
 
-    match shape {
-        0 => (0..size).flat_map(move |x| (0..size).map(move |y| (x, y))).for_each(fun),
-
-        4 | 39 | 41 => (0..size).flat_map(move |x| (x..size).map(move |y| (x, y))).for_each(fun),
-        5 | 36 | 42 => (0..size).flat_map(move |x| (0..(size - x)).map(move |y| (x, y))).for_each(fun),
-        6 | 37 | 43 => (0..size).flat_map(move |x| (0..x).map(move |y| (x, y))).for_each(fun),
-        7 | 38 | 40 => (0..size).flat_map(move |x| ((size - x)..size).map(move |y| (x, y))).for_each(fun),
-
-        8 => (0..(size / 2)).flat_map(move |x| (0..(size - 2 * x)).map(move |y| (x, y))).for_each(fun),
-        9 => (0..size).flat_map(move |x| (0..(x / 2)).map(move |y| (x, y))).for_each(fun),
-        10 => ((size / 2)..size)
-            .flat_map(move |x| ((size - 2 * (x - size / 2))..size).map(move |y| (x, y)))
-            .for_each(fun),
-        11 => (0..size).flat_map(move |x| (((x + size) / 2)..size).map(move |y| (x, y))).for_each(fun),
-        12 => ((size / 2)..size)
-            .flat_map(move |x| (0..(2 * x - size)).map(move |y| (x, y)))
-            .for_each(fun),
-        13 => (0..size)
-            .flat_map(move |x| ((size - 1 - (x / 2))..size).map(move |y| (x, y)))
-            .for_each(fun),
-        14 => (0..(size / 2)).flat_map(move |x| ((2 * x)..size).map(move |y| (x, y))).for_each(fun),
-        15 => (0..size).flat_map(move |x| (0..((size - x) / 2)).map(move |y| (x, y))).for_each(fun),
-
-        16 => (0..size)
-            .flat_map(move |x| ((size.saturating_sub(2 * x))..size).map(move |y| (x, y)))
-            .for_each(fun),
-        17 => (0..size).flat_map(move |x| ((x / 2)..size).map(move |y| (x, y))).for_each(fun),
-        18 => (0..size)
-            .flat_map(move |x| (0..(2 * (size - x)).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        19 => (0..size).flat_map(move |x| (0..((x + size) / 2)).map(move |y| (x, y))).for_each(fun),
-        20 => (0..size)
-            .flat_map(move |x| ((2 * x).saturating_sub(size)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        21 => (0..size).flat_map(move |x| (0..(size - 1 - (x / 2))).map(move |y| (x, y))).for_each(fun),
-        22 => (0..size)
-            .flat_map(move |x| (0..(2 * x).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        23 => (0..size).flat_map(move |x| (((size - x) / 2)..size).map(move |y| (x, y))).for_each(fun),
-
-        24 => (0..size / 2).flat_map(move |x| (0..size).map(move |y| (x, y))).for_each(fun),
-        25 => (0..size).flat_map(move |x| (0..size / 2).map(move |y| (x, y))).for_each(fun),
-        26 => (size / 2..size).flat_map(move |x| (0..size).map(move |y| (x, y))).for_each(fun),
-        27 => (0..size).flat_map(move |x| (size / 2..size).map(move |y| (x, y))).for_each(fun),
-
-        28 => (0..size / 2).flat_map(move |x| ((size / 2 + x)..size).map(move |y| (x, y))).for_each(fun),
-        29 => (0..size / 2).flat_map(move |x| (0..(size / 2 - x)).map(move |y| (x, y))).for_each(fun),
-        30 => (size / 2..size).flat_map(move |x| (0..(x - size / 2)).map(move |y| (x, y))).for_each(fun),
-        31 => (size / 2..size)
-            .flat_map(move |x| ((size + size / 2 - x)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        32 | 45 => (0..size)
-            .flat_map(move |x| (0..(size / 2 + x).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        33 | 46 => (0..size)
-            .flat_map(move |x| ((size / 2).saturating_sub(x)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        34 | 47 => (0..size)
-            .flat_map(move |x| (x.saturating_sub(size / 2)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        35 | 44 => (0..size)
-            .flat_map(move |x| (0..(size + size / 2 - x).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        other => unimplemented!("Shape {} is not implemented.", other),
-    };
+    pixels(size).filter(|&pixel| covers(shape, size, pixel)).for_each(fun)
 }
 
 pub fn draw_underlay(shape: Option<u8>, size: u32, fun: impl FnMut((u32, u32))) {
     debug_assert!(size.is_power_of_two(), "{size} is an invalid size, only 2^n values are allowed.");
 
     match shape {
-        None => (0..size).flat_map(move |x| (0..size).map(move |y| (x, y))).for_each(fun),
-        Some(0) => { /* draw nothing */ }
-
-        Some(4) | Some(39) | Some(41) => (0..size).flat_map(move |x| (0..x).map(move |y| (x, y))).for_each(fun),
-        Some(5) | Some(36) | Some(42) => (0..size).flat_map(move |x| ((size - x)..size).map(move |y| (x, y))).for_each(fun),
-        Some(6) | Some(37) | Some(43) => (0..size).flat_map(move |x| (x..size).map(move |y| (x, y))).for_each(fun),
-        Some(7) | Some(38) | Some(40) => (0..size).flat_map(move |x| (0..(size - x)).map(move |y| (x, y))).for_each(fun),
-
-        Some(8) => (0..size)
-            .flat_map(move |x| ((size.saturating_sub(2 * x))..size).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(9) => (0..size).flat_map(move |x| ((x / 2)..size).map(move |y| (x, y))).for_each(fun),
-        Some(10) => (0..size)
-            .flat_map(move |x| (0..(2 * (size - x)).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(11) => (0..size).flat_map(move |x| (0..((x + size) / 2)).map(move |y| (x, y))).for_each(fun),
-        Some(12) => (0..size)
-            .flat_map(move |x| ((2 * x).saturating_sub(size)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(13) => (0..size).flat_map(move |x| (0..(size - 1 - (x / 2))).map(move |y| (x, y))).for_each(fun),
-        Some(14) => (0..size)
-            .flat_map(move |x| (0..(2 * x).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(15) => (0..size).flat_map(move |x| (((size - x) / 2)..size).map(move |y| (x, y))).for_each(fun),
-
-        Some(16) => (0..(size / 2)).flat_map(move |x| (0..(size - 2 * x)).map(move |y| (x, y))).for_each(fun),
-        Some(17) => (0..size).flat_map(move |x| (0..(x / 2)).map(move |y| (x, y))).for_each(fun),
-        Some(18) => ((size / 2)..size)
-            .flat_map(move |x| ((size - 2 * (x - size / 2))..size).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(19) => (0..size).flat_map(move |x| (((x + size) / 2)..size).map(move |y| (x, y))).for_each(fun),
-        Some(20) => ((size / 2)..size)
-            .flat_map(move |x| (0..(2 * x - size)).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(21) => (0..size)
-            .flat_map(move |x| ((size - 1 - (x / 2))..size).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(22) => (0..(size / 2)).flat_map(move |x| ((2 * x)..size).map(move |y| (x, y))).for_each(fun),
-        Some(23) => (0..size).flat_map(move |x| (0..((size - x) / 2)).map(move |y| (x, y))).for_each(fun),
-
-        Some(24) => (size / 2..size).flat_map(move |x| (0..size).map(move |y| (x, y))).for_each(fun),
-        Some(25) => (0..size).flat_map(move |x| (size / 2..size).map(move |y| (x, y))).for_each(fun),
-        Some(26) => (0..size / 2).flat_map(move |x| (0..size).map(move |y| (x, y))).for_each(fun),
-        Some(27) => (0..size).flat_map(move |x| (0..size / 2).map(move |y| (x, y))).for_each(fun),
-
-        Some(28) => (0..size)
-            .flat_map(move |x| (0..(size / 2 + x).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(29) => (0..size)
-            .flat_map(move |x| ((size / 2).saturating_sub(x)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(30) => (0..size)
-            .flat_map(move |x| (x.saturating_sub(size / 2)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(31) => (0..size)
-            .flat_map(move |x| (0..(size + size / 2 - x).clamp(0, size)).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(32) | Some(45) => (0..size / 2).flat_map(move |x| ((size / 2 + x)..size).map(move |y| (x, y))).for_each(fun),
-        Some(33) | Some(46) => (0..size / 2).flat_map(move |x| (0..(size / 2 - x)).map(move |y| (x, y))).for_each(fun),
-        Some(34) | Some(47) => (size / 2..size).flat_map(move |x| (0..(x - size / 2)).map(move |y| (x, y))).for_each(fun),
-        Some(35) | Some(44) => (size / 2..size)
-            .flat_map(move |x| ((size + size / 2 - x)..size).map(move |y| (x, y)))
-            .for_each(fun),
-        Some(other) => unimplemented!("Shape {} is not implemented.", other),
-    };
+        None => pixels(size).for_each(fun),
+        Some(shape) => pixels(size).filter(|&pixel| !covers(shape, size, pixel)).for_each(fun),
+    }
 }
 
 #[cfg(test)]
 mod shape_tests {
-    use std::collections::HashSet;
+    use std::{collections::HashSet, fmt::Write};
 
     use super::*;
 
@@ -149,8 +73,8 @@ mod shape_tests {
     #[test]
     fn test_overlay_bounds() {
         let shapes = [
-            0, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 31, 32, 33, 34, 35, 36, 37, 38, 39,
-            40, 41, 42, 43, 44, 45, 46, 47,
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36,
+            37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
         ];
         let sizes = [2, 4, 8, 16, 32, 64];
         for size in &sizes {
@@ -168,8 +92,8 @@ mod shape_tests {
     #[test]
     fn test_underlay_bounds() {
         let shapes = [
-            0, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 31, 32, 33, 34, 35, 36, 37, 38, 39,
-            40, 41, 42, 43, 44, 45, 46, 47,
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36,
+            37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
         ];
         let sizes = [2, 4, 8, 16, 32, 64];
         for size in &sizes {
@@ -192,8 +116,8 @@ mod shape_tests {
     #[test]
     fn are_complement() {
         let shapes = [
-            0, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 31, 32, 33, 34, 35, 36, 37, 38, 39,
-            40, 41, 42, 43, 44, 45, 46, 47,
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36,
+            37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
         ];
         let sizes = [2, 4, 8, 16, 32, 64];
         for size in &sizes {
@@ -215,4 +139,31 @@ mod shape_tests {
             }
         }
     }
+    /// Draws `pixels` of a tile of `size`, with `#` where they are.
+    fn picture(size: u32, pixels: &[(u32, u32)]) -> Vec<String> {
+        let mut rows = vec![vec!['.'; size as usize]; size as usize];
+        for &(a, b) in pixels {
+            rows[b as usize][a as usize] = '#';
+        }
+        rows.into_iter().map(|row| row.into_iter().collect()).collect()
+    }
+
+    /// Every shape, with its overlay on the left and its underlay on the right, as in `tileshapes.txt`.
+    #[test]
+    fn golden() {
+        let size = 8;
+        let mut out = String::new();
+        for shape in 0..=47 {
+            let mut overlay = Vec::new();
+            draw_overlay(shape, size, |pixel| overlay.push(pixel));
+            let mut underlay = Vec::new();
+            draw_underlay(Some(shape), size, |pixel| underlay.push(pixel));
+
+            writeln!(out, "shape {shape}").unwrap();
+            for (overlay, underlay) in picture(size, &overlay).into_iter().zip(picture(size, &underlay)) {
+                writeln!(out, "{overlay} {underlay}").unwrap();
+            }
+        }
+        assert_eq!(out, include_str!("tileshapes.txt"));
+    }
 }
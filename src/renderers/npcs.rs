@@ -0,0 +1,345 @@
+//! Renders npcs as transparent pngs, for `--dump npc_renders`.
+//!
+//! Writes `npc_renders/<id>.png` of an npc's whole body and `npc_renders/<id>_chathead.png` of its chathead,
+//! and `npc_renders.json` with the id and name of every npc that was rendered, for looking them up by name.
+//!
+//! Models are drawn by a small software rasterizer, from the front and a little above, lit from the front left.
+//! Bodies stand in the first frame of their standing animation; chatheads are drawn as their models are made.
+//! The rasterizer is also what [scenery shots](crate::renderers::scenery) are drawn with.
+
+use image::{Rgba, RgbaImage};
+use rayon::iter::ParallelIterator;
+use rs3cache_backend::{
+    error::CacheResult,
+    index::{CacheIndex, Initial},
+};
+use rs3cache_utils::bar::Render;
+use serde::Serialize;
+
+use crate::{
+    cli::Config,
+    definitions::{frames::Frame, indextype::IndexType, models::Model, npc_configs::NpcConfig, sequences::Sequence},
+    output::mesh::{self, Triangle},
+    renderers::png,
+};
+
+/// The width and height of renders of whole npcs, in pixels.
+pub const BODY_SIZE: u32 = 256;
+
+/// The width and height of renders of chatheads, in pixels.
+pub const CHATHEAD_SIZE: u32 = 128;
+
+/// The space around a model, in pixels.
+const MARGIN: f32 = 4.0;
+
+/// How far above the horizon models are seen from, in radians.
+const PITCH: f32 = 0.2;
+
+/// Where the light comes from, as seen from the camera.
+const LIGHT: [f32; 3] = [-0.5, 0.5, 1.0];
+
+/// How bright faces that the light does not reach are.
+const AMBIENT: f32 = 0.5;
+
+/// An npc that was rendered, as listed in `npc_renders.json`.
+#[derive(Serialize, Clone, Debug)]
+struct Rendered {
+    id: u32,
+    name: Option<String>,
+    body: bool,
+    chathead: bool,
+}
+
+/// Combines `parts` into one model, recoloured by `replacements`, as npcs combine their models.
+pub fn merged(parts: &[Model], replacements: &[(u16, u16)]) -> Model {
+    let mut model = Model::default();
+    let (mut alphas, mut textures, mut skins) = (Vec::new(), Vec::new(), Vec::new());
+    for part in parts {
+        let offset = model.vertices.len() as u16;
+        model.vertices.extend_from_slice(&part.vertices);
+        model.faces.extend(part.faces.iter().map(|face| face.map(|vertex| vertex + offset)));
+        model.face_colours.extend(
+            part.face_colours
+                .iter()
+                .map(|&colour| replacements.iter().find(|(from, _)| *from == colour).map_or(colour, |(_, to)| *to)),
+        );
+        match &part.face_alphas {
+            Some(part_alphas) => alphas.extend_from_slice(part_alphas),
+            None => alphas.extend(std::iter::repeat(0).take(part.faces.len())),
+        }
+        match &part.face_textures {
+            Some(part_textures) => textures.extend_from_slice(part_textures),
+            None => textures.extend(std::iter::repeat(None).take(part.faces.len())),
+        }
+        match &part.vertex_skins {
+            Some(part_skins) => skins.extend_from_slice(part_skins),
+            None => skins.extend(std::iter::repeat(0).take(part.vertices.len())),
+        }
+    }
+    model.face_alphas = Some(alphas);
+    model.face_textures = Some(textures);
+    model.vertex_skins = Some(skins);
+    model
+}
+
+/// Scales `model` by `scale`, in 128ths horizontally and vertically, as npcs are scaled after they are posed.
+pub fn scaled(mut model: Model, (horizontal, vertical): (u16, u16)) -> Model {
+    let (horizontal, vertical) = (horizontal as i32, vertical as i32);
+    for [x, y, z] in &mut model.vertices {
+        (*x, *y, *z) = (*x * horizontal / 128, *y * vertical / 128, *z * horizontal / 128);
+    }
+    model
+}
+
+/// The signed area of the parallelogram of `a, b` and `p`, which is positive if `p` is left of `a` to `b`.
+fn edge(a: [f32; 3], b: [f32; 3], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// Draws `model` from the front and a little above, fitted into a transparent square image of `size` pixels.
+pub fn draw(model: &Model, size: u32) -> RgbaImage {
+    draw_triangles(mesh::triangles(model), size, 0.0, PITCH)
+}
+
+/// Draws `triangles` fitted into a transparent square image of `size` pixels,
+/// as seen from `yaw` to the left of their front, which faces `+z`, and `pitch` above the horizon, both in radians.
+pub fn draw_triangles(triangles: impl IntoIterator<Item = Triangle>, size: u32, yaw: f32, pitch: f32) -> RgbaImage {
+    let mut img = RgbaImage::new(size, size);
+    let (yaw_sin, yaw_cos) = yaw.sin_cos();
+    let (sin, cos) = pitch.sin_cos();
+    // This brings what is on the left towards the camera, which looks along -z.
+    let turn = |[x, y, z]: [f32; 3]| [x * yaw_cos + z * yaw_sin, y, z * yaw_cos - x * yaw_sin];
+    // The top of the model tilts towards the camera.
+    let view = |[x, y, z]: [f32; 3]| [x, y * cos - z * sin, y * sin + z * cos];
+    let mut triangles = triangles
+        .into_iter()
+        .map(|(corners, colour)| (corners.map(|corner| view(turn(corner))), colour))
+        .filter(|(_, [.., alpha])| *alpha != 0)
+        .collect::<Vec<_>>();
+    if triangles.is_empty() {
+        return img;
+    }
+    // Translucent faces are blended over whatever is behind them, so that has to be drawn first:
+    // the opaque faces, and then the translucent ones from back to front. The camera looks along -z.
+    let depth = |(corners, _): &Triangle| corners.iter().map(|corner| corner[2]).sum::<f32>();
+    triangles.sort_by(|a, b| {
+        let (a_translucent, b_translucent) = (a.1[3] != 255, b.1[3] != 255);
+        a_translucent.cmp(&b_translucent).then_with(|| match a_translucent {
+            true => depth(a).total_cmp(&depth(b)),
+            false => std::cmp::Ordering::Equal,
+        })
+    });
+
+    let corners = || triangles.iter().flat_map(|(corners, _)| corners.iter());
+    let bound = |axis: usize, f: fn(f32, f32) -> f32, start: f32| corners().map(|corner| corner[axis]).fold(start, f);
+    let (min_x, max_x) = (bound(0, f32::min, f32::MAX), bound(0, f32::max, f32::MIN));
+    let (min_y, max_y) = (bound(1, f32::min, f32::MAX), bound(1, f32::max, f32::MIN));
+    let scale = (size as f32 - 2.0 * MARGIN) / (max_x - min_x).max(max_y - min_y).max(f32::EPSILON);
+    let (center_x, center_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let half = size as f32 / 2.0;
+    let to_pixels = |[x, y, z]: [f32; 3]| [half + (x - center_x) * scale, half - (y - center_y) * scale, z];
+
+    let light_length = LIGHT.iter().map(|c| c * c).sum::<f32>().sqrt();
+    let mut depths = vec![f32::MIN; (size * size) as usize];
+    for ([a, b, c], [red, green, blue, alpha]) in triangles {
+        let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+        let normal = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let normal_length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+        if normal_length == 0.0 {
+            continue;
+        }
+        // Faces are lit on either side, as not every model winds them the same way.
+        let lit = normal.iter().zip(LIGHT).map(|(n, l)| n * l).sum::<f32>().abs() / normal_length / light_length;
+        let shade = AMBIENT + (1.0 - AMBIENT) * lit;
+        let colour = [red, green, blue].map(|channel| (channel as f32 * shade).round().min(255.0));
+
+        let [p0, p1, p2] = [a, b, c].map(to_pixels);
+        let area = edge(p0, p1, p2[..2].try_into().unwrap());
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+        let clamp = |value: f32| (value.max(0.0) as u32).min(size - 1);
+        let (left, right) = (clamp(p0[0].min(p1[0]).min(p2[0])), clamp(p0[0].max(p1[0]).max(p2[0]).ceil()));
+        let (top, bottom) = (clamp(p0[1].min(p1[1]).min(p2[1])), clamp(p0[1].max(p1[1]).max(p2[1]).ceil()));
+        for y in top..=bottom {
+            for x in left..=right {
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let weights = [edge(p1, p2, p) / area, edge(p2, p0, p) / area, edge(p0, p1, p) / area];
+                if weights.iter().any(|weight| *weight < 0.0) {
+                    continue;
+                }
+                let depth = weights[0] * p0[2] + weights[1] * p1[2] + weights[2] * p2[2];
+                let index = (y * size + x) as usize;
+                if depth <= depths[index] {
+                    continue;
+                }
+                let pixel = img.get_pixel_mut(x, y);
+                if alpha == 255 {
+                    depths[index] = depth;
+                    *pixel = Rgba([colour[0] as u8, colour[1] as u8, colour[2] as u8, 255]);
+                } else {
+                    let opacity = alpha as f32 / 255.0;
+                    let behind = pixel[3] as f32 / 255.0 * (1.0 - opacity);
+                    let total = opacity + behind;
+                    for (channel, value) in pixel.0[..3].iter_mut().zip(colour) {
+                        *channel = ((value * opacity + *channel as f32 * behind) / total).round() as u8;
+                    }
+                    pixel[3] = (total * 255.0).round() as u8;
+                }
+            }
+        }
+    }
+    img
+}
+
+/// The indices that npcs are rendered from. Every thread opens its own, as reading from an index moves its file cursor.
+struct Indices {
+    models: CacheIndex<Initial>,
+    frames: CacheIndex<Initial>,
+    skeletons: CacheIndex<Initial>,
+}
+
+impl Indices {
+    fn new(config: &Config) -> CacheResult<Self> {
+        Ok(Self {
+            models: CacheIndex::new(IndexType::MODELS, config.input.clone())?,
+            frames: CacheIndex::new(IndexType::FRAMES, config.input.clone())?,
+            skeletons: CacheIndex::new(IndexType::BASES, config.input.clone())?,
+        })
+    }
+}
+
+/// Renders every npc, or those in `--filter`, to `npc_renders`.
+///
+/// The models of every npc are read as it is rendered, rather than all up front.
+pub fn export(config: &Config) -> CacheResult<()> {
+    let mut npcs = NpcConfig::dump_all(config)?;
+    npcs.retain(|id, _| config.keeps(*id));
+    let sequences = Sequence::dump_all(config)?;
+
+    let body_models = |npc: &NpcConfig| {
+        npc.models
+            .as_ref()
+            .map_or_else(Vec::new, |models| models.models.iter().flatten().copied().collect())
+    };
+    let head_models = |npc: &NpcConfig| {
+        npc.head_models
+            .as_ref()
+            .map_or_else(Vec::new, |models| models.models.iter().flatten().map(|&id| id as u32).collect())
+    };
+    let stand_frame = |npc: &NpcConfig| {
+        let sequence = sequences.get(&(npc.standing_animation? as u32))?;
+        sequence.frame_ids.as_ref()?.first().copied()
+    };
+
+    let rendered = npcs
+        .into_values()
+        .render("npc renders")
+        .map_init(
+            || None,
+            |indices: &mut Option<Indices>, (npc, _)| {
+                let indices = match indices {
+                    Some(indices) => indices,
+                    None => indices.insert(Indices::new(config)?),
+                };
+                let replacements = npc
+                    .colour_replacements
+                    .as_ref()
+                    .map_or(&[][..], |replacements| &replacements.colour_replacements[..]);
+                let model = |ids: Vec<u32>| -> CacheResult<Option<Model>> {
+                    let parts = ids
+                        .into_iter()
+                        .map(|id| Model::get(&indices.models, id))
+                        .collect::<CacheResult<Vec<_>>>()?;
+                    Ok((!parts.is_empty()).then(|| merged(&parts, replacements)))
+                };
+
+                let body = match model(body_models(&npc))? {
+                    Some(mut body) => {
+                        if let Some(frame_id) = stand_frame(&npc) {
+                            Frame::get(&indices.frames, &indices.skeletons, frame_id)?.apply(&mut body);
+                        }
+                        let body = scaled(body, (npc.scale_xz.unwrap_or(128), npc.scale_y.unwrap_or(128)));
+                        let img = draw(&body, BODY_SIZE);
+                        png::put(config, format!("npc_renders/{}.png", npc.id), &img)?;
+                        true
+                    }
+                    None => false,
+                };
+                // Chatheads are drawn as they are, whatever the size of the npc.
+                let chathead = match model(head_models(&npc))? {
+                    Some(head) => {
+                        let img = draw(&head, CHATHEAD_SIZE);
+                        png::put(config, format!("npc_renders/{}_chathead.png", npc.id), &img)?;
+                        true
+                    }
+                    None => false,
+                };
+                Ok(Rendered {
+                    id: npc.id,
+                    name: npc.name.as_deref().map(str::to_string),
+                    body,
+                    chathead,
+                })
+            },
+        )
+        .collect::<CacheResult<Vec<_>>>()?;
+
+    let mut rendered = rendered.into_iter().filter(|npc| npc.body || npc.chathead).collect::<Vec<_>>();
+    rendered.sort_unstable_by_key(|npc| npc.id);
+    crate::output::export(config, "npc_renders", &rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(colour: u16) -> Model {
+        Model {
+            id: 0,
+            vertices: vec![[-128, 0, 0], [128, 0, 0], [0, -256, 0]],
+            faces: vec![[0, 1, 2]],
+            face_colours: vec![colour],
+            face_alphas: None,
+            face_textures: None,
+            vertex_skins: None,
+        }
+    }
+
+    #[test]
+    fn merging() {
+        let (first, second) = (triangle(1), triangle(2));
+        let model = merged(&[first, second], &[(2, 3)]);
+        assert_eq!(model.faces, vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(model.face_colours, vec![1, 3]);
+        assert_eq!(model.face_alphas.as_ref().map(Vec::len), Some(2));
+        assert_eq!(model.vertex_skins.as_ref().map(Vec::len), Some(6));
+
+        let model = scaled(model, (256, 128));
+        assert_eq!(model.vertices[1], [256, 0, 0]);
+        assert_eq!(model.vertices[2], [0, -256, 0]);
+    }
+
+    #[test]
+    fn drawing() {
+        // A light grey triangle, pointing up.
+        let img = draw(&triangle(100), 64);
+        assert_eq!(img.dimensions(), (64, 64));
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        assert_eq!(img.get_pixel(32, 48)[3], 255);
+        // Its top is narrower than its bottom.
+        assert_eq!(img.get_pixel(8, 8)[3], 0);
+        assert_eq!(img.get_pixel(8, 56)[3], 255);
+    }
+
+    #[test]
+    fn translucency() {
+        // A translucent red face in front of a translucent blue one, given front first.
+        let square = |z: f32, colour: [u8; 4]| ([[-1.0, -1.0, z], [3.0, -1.0, z], [-1.0, 3.0, z]], colour);
+        let triangles = [square(1.0, [255, 0, 0, 128]), square(0.0, [0, 0, 255, 128])];
+        let img = draw_triangles(triangles, 16, 0.0, 0.0);
+        // The one in front is drawn last, so it shows the most.
+        let pixel = img.get_pixel(4, 8);
+        assert!(pixel[0] > pixel[2], "{pixel:?}");
+    }
+}
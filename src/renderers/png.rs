@@ -0,0 +1,194 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use ::error::Context;
+use image::{
+    codecs::png::{self, CompressionType, FilterType},
+    ColorType, ImageEncoder, ImageError, ImageResult, RgbaImage,
+};
+use rs3cache_backend::error::{self, CacheResult};
+
+use crate::{
+    cli::{Config, PngEncoder, TileFormat},
+    renderers::{
+        progress::{self, Event},
+        quantize::{self, Indexed},
+    },
+};
+
+/// Saves `img` as a png, with the encoder settings selected by `--png-encoder`.
+pub fn save(img: &RgbaImage, path: impl AsRef<Path>, encoder: PngEncoder) -> ImageResult<()> {
+    let file = BufWriter::new(File::create(path)?);
+    encode(img, file, encoder)
+}
+
+/// Saves `img` as a png at `relative` in the output folder (or bucket).
+pub fn put(config: &Config, relative: impl AsRef<Path>, img: &RgbaImage) -> CacheResult<()> {
+    let relative = relative.as_ref();
+    let mut data = Vec::new();
+    encode(img, &mut data, config.png_encoder).map_err(to_io).context(error::Io {
+        path: relative.to_path_buf(),
+    })?;
+    config.sink()?.put(relative, &data)
+}
+
+/// Turns errors of the `image` crate into io errors, so that they can be reported like failed writes.
+fn to_io(e: ImageError) -> io::Error {
+    match e {
+        ImageError::IoError(e) => e,
+        other => io::Error::new(io::ErrorKind::Other, other),
+    }
+}
+
+/// Lossless copies of the tiles that were encoded lossily, so that zoom levels are made from them
+/// rather than from tiles that already lost detail. See [`get_tile`].
+#[cfg(all(feature = "webp", not(target_arch = "wasm32")))]
+static SOURCES: std::sync::LazyLock<std::path::PathBuf> = std::sync::LazyLock::new(|| crate::output::sink::temp_folder("lossless_tiles"));
+
+/// Saves the rendered tile `img` at `relative`, in the format selected by `--tile-format`.
+///
+/// The extension of `relative` should be that of the format.
+pub fn put_tile(config: &Config, relative: impl AsRef<Path>, img: &RgbaImage) -> CacheResult<()> {
+    let relative = relative.as_ref();
+    match config.tile_format {
+        TileFormat::Png => put(config, relative, img)?,
+        #[cfg(feature = "webp")]
+        TileFormat::Webp => {
+            use image::codecs::webp::{WebPEncoder, WebPQuality};
+
+            let quality = match config.tile_quality {
+                Some(quality) => WebPQuality::lossy(quality),
+                None => WebPQuality::lossless(),
+            };
+            let mut data = Vec::new();
+            WebPEncoder::new_with_quality(&mut data, quality)
+                .write_image(img.as_raw(), img.width(), img.height(), ColorType::Rgba8)
+                .map_err(to_io)
+                .context(error::Io {
+                    path: relative.to_path_buf(),
+                })?;
+            config.sink()?.put(relative, &data)?;
+
+            if config.tile_quality.is_some() {
+                let source = SOURCES.join(relative).with_extension("png");
+                if let Some(folder) = source.parent() {
+                    std::fs::create_dir_all(folder).with_context(|| error::Io { path: folder.to_path_buf() })?;
+                }
+                save(img, &source, PngEncoder::Fast).map_err(to_io).context(error::Io { path: source })?;
+            }
+        }
+    }
+    if progress::is_listened() {
+        progress::emit(Event::Tile {
+            path: relative.to_string_lossy().into_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// Reads back the tile at `relative` that was saved with [`put_tile`], if it exists.
+///
+/// Tiles that were encoded lossily are read from their lossless copy, if this run made it.
+/// Tiles of earlier runs, as when zoom levels are remade for a few mapsquares, only have their lossy encoding.
+pub fn get_tile(config: &Config, relative: impl AsRef<Path>) -> CacheResult<Option<RgbaImage>> {
+    let relative = relative.as_ref();
+
+    #[cfg(all(feature = "webp", not(target_arch = "wasm32")))]
+    if config.tile_format == TileFormat::Webp && config.tile_quality.is_some() {
+        let source = SOURCES.join(relative).with_extension("png");
+        if source.exists() {
+            let img = image::open(&source).map_err(to_io).context(error::Io { path: source })?;
+            return Ok(Some(img.into_rgba8()));
+        }
+    }
+
+    let Some(data) = config.sink()?.get(relative)? else { return Ok(None) };
+    let img = image::load_from_memory_with_format(&data, config.tile_format.image_format())
+        .map_err(to_io)
+        .context(error::Io {
+            path: relative.to_path_buf(),
+        })?;
+    Ok(Some(img.into_rgba8()))
+}
+
+/// Removes the lossless copies of the tiles under `relative`, once the zoom levels they are needed for are made.
+pub fn forget_sources(relative: impl AsRef<Path>) {
+    #[cfg(all(feature = "webp", not(target_arch = "wasm32")))]
+    let _ = std::fs::remove_dir_all(SOURCES.join(relative));
+    #[cfg(not(all(feature = "webp", not(target_arch = "wasm32"))))]
+    let _ = relative;
+}
+
+fn encode(img: &RgbaImage, writer: impl Write, encoder: PngEncoder) -> ImageResult<()> {
+    let (compression, filter) = match encoder {
+        PngEncoder::Fast => (CompressionType::Fast, FilterType::Sub),
+        PngEncoder::Small => (CompressionType::Default, FilterType::Adaptive),
+        PngEncoder::Indexed => return encode_indexed(img, writer).map_err(|e| ImageError::IoError(io::Error::from(e))),
+    };
+
+    png::PngEncoder::new_with_quality(writer, compression, filter).write_image(img.as_raw(), img.width(), img.height(), ColorType::Rgba8)
+}
+
+/// Writes `img` as an indexed png of at most 256 colours, with only the chunks needed to show it.
+fn encode_indexed(img: &RgbaImage, writer: impl Write) -> Result<(), ::png::EncodingError> {
+    let Indexed { palette, indices } = quantize::quantize(img, 256);
+
+    let mut encoder = ::png::Encoder::new(writer, img.width(), img.height());
+    encoder.set_color(::png::ColorType::Indexed);
+    encoder.set_depth(::png::BitDepth::Eight);
+    encoder.set_compression(::png::Compression::Best);
+    // Filters rarely help indices, which are not gradients.
+    encoder.set_filter(::png::FilterType::NoFilter);
+    encoder.set_palette(palette.iter().flat_map(|[r, g, b, _]| [*r, *g, *b]).collect::<Vec<_>>());
+    if palette.iter().any(|[.., a]| *a != 255) {
+        encoder.set_trns(palette.iter().map(|[.., a]| *a).collect::<Vec<_>>());
+    }
+    encoder.write_header()?.write_image_data(&indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn tiles() -> CacheResult<()> {
+        let config = Config {
+            output: crate::output::sink::temp_folder("png_tiles"),
+            ..Config::default()
+        };
+        let img = RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+        put_tile(&config, "map/-1/2/0_1_2.png", &img)?;
+
+        assert_eq!(get_tile(&config, "map/-1/2/0_1_2.png")?, Some(img));
+        assert_eq!(get_tile(&config, "map/-1/2/0_1_3.png")?, None);
+
+        config.sink()?.put("map/-1/2/0_1_4.png", b"not a png")?;
+        assert!(get_tile(&config, "map/-1/2/0_1_4.png").is_err());
+        Ok(())
+    }
+
+    #[cfg(all(feature = "webp", not(target_arch = "wasm32")))]
+    #[test]
+    fn lossy_sources() -> CacheResult<()> {
+        let config = Config {
+            output: crate::output::sink::temp_folder("png_lossy_sources"),
+            tile_format: TileFormat::Webp,
+            tile_quality: Some(10),
+            ..Config::default()
+        };
+        let img = RgbaImage::from_fn(16, 16, |x, y| Rgba([(x * 16) as u8, (y * 16) as u8, 0, 255]));
+        put_tile(&config, "lossy/-1/2/0_1_2.webp", &img)?;
+
+        // Read back from the lossless copy, not the lossy tile.
+        assert_eq!(get_tile(&config, "lossy/-1/2/0_1_2.webp")?, Some(img.clone()));
+
+        forget_sources("lossy/-1");
+        assert_ne!(get_tile(&config, "lossy/-1/2/0_1_2.webp")?, Some(img));
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Reports how far along the map renderer is, for GUIs and CI wrappers.
+//!
+//! Besides drawing its progress bar, the renderer emits an [`Event`] for every mapsquare it renders and every tile it writes.
+//! Embedding applications [`subscribe`] to them, or receive them from a [`channel`];
+//! `--progress json` writes each as a line of json to stderr, or to `--progress-file`.
+//! Stdout is left alone, as the dumpers print to it.
+//!
+//! ```no_run
+//! # use rs3cache::{cli::Config, renderers::{map, progress::{self, Event}}};
+//! let receiver = progress::channel();
+//! std::thread::spawn(move || {
+//!     for event in receiver {
+//!         if let Event::Square { done, total, .. } = event {
+//!             println!("{done}/{total}");
+//!         }
+//!     }
+//! });
+//! map::render(&Config::env())?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+
+/// Something that happened during a render. Serialized with an `event` tag, which is stable.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Rendering the mapsquares of `name` started.
+    Started { name: String, total: usize },
+    /// The mapsquare `i, j` was rendered, as the `done`th of `total`.
+    ///
+    /// `eta_secs` is how long the rest is expected to take, going by how long these took.
+    Square {
+        i: u8,
+        j: u8,
+        done: usize,
+        total: usize,
+        eta_secs: f64,
+    },
+    /// A tile was written to `path`, relative to the output.
+    Tile { path: String },
+    /// The tile at `path` could not be written, and was left out. The render goes on.
+    TileFailed { path: String, message: String },
+    /// The render failed.
+    Error { message: String },
+    /// Rendering the mapsquares of `name` ended, after rendering `done` of them.
+    Finished { name: String, done: usize, elapsed_secs: f64 },
+}
+
+impl Event {
+    /// The time the rest of the mapsquares are expected to take, if `done` of `total` took `elapsed`.
+    pub(crate) fn eta(elapsed: Duration, done: usize, total: usize) -> f64 {
+        if done == 0 {
+            return 0.0;
+        }
+        elapsed.as_secs_f64() / done as f64 * total.saturating_sub(done) as f64
+    }
+}
+
+/// Returns whether it wants more events.
+type Listener = Box<dyn Fn(&Event) -> bool + Send + Sync>;
+
+static LISTENERS: RwLock<Vec<(u64, Listener)>> = RwLock::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn add(listener: Listener) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    LISTENERS.write().unwrap().push((id, listener));
+    id
+}
+
+fn remove(ids: &[u64]) {
+    LISTENERS.write().unwrap().retain(|(id, _)| !ids.contains(id));
+}
+
+/// A listener added with [`subscribe`]. It stops being called when this is dropped.
+#[must_use = "the listener is removed when this is dropped"]
+#[derive(Debug)]
+pub struct Subscription {
+    id: u64,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        remove(&[self.id]);
+    }
+}
+
+/// Calls `listener` with every event from now on, from whichever thread emits it, until the returned [`Subscription`] is dropped.
+pub fn subscribe(listener: impl Fn(&Event) + Send + Sync + 'static) -> Subscription {
+    let id = add(Box::new(move |event| {
+        listener(event);
+        true
+    }));
+    Subscription { id }
+}
+
+/// Returns a receiver of every event from now on. Its sender is removed once it is dropped.
+pub fn channel() -> mpsc::Receiver<Event> {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    // Sending fails once the receiver is dropped, as there is no one left to tell.
+    add(Box::new(move |event| sender.lock().unwrap().send(event.clone()).is_ok()));
+    receiver
+}
+
+/// Writes every event as a line of json to `out`. This is `--progress json`.
+pub fn write_json(out: impl Write + Send + 'static) -> Subscription {
+    let out = Mutex::new(out);
+    subscribe(move |event| {
+        let mut out = out.lock().unwrap();
+        let _ = serde_json::to_writer(&mut *out, event);
+        let _ = writeln!(out);
+        let _ = out.flush();
+    })
+}
+
+/// Whether anyone is listening, so that events need not be made otherwise.
+pub(crate) fn is_listened() -> bool {
+    !LISTENERS.read().unwrap().is_empty()
+}
+
+/// Tells every listener about `event`, and removes those that want no more.
+pub(crate) fn emit(event: Event) {
+    let done = LISTENERS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, listener)| !listener(&event))
+        .map(|&(id, _)| id)
+        .collect::<Vec<_>>();
+    if !done.is_empty() {
+        remove(&done);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialization() {
+        let event = Event::Square {
+            i: 50,
+            j: 51,
+            done: 3,
+            total: 4,
+            eta_secs: Event::eta(Duration::from_secs(6), 3, 4),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"square","i":50,"j":51,"done":3,"total":4,"eta_secs":2.0}"#
+        );
+    }
+
+    #[test]
+    fn channels() {
+        let receiver = channel();
+        let event = Event::Tile {
+            path: "mapsquares/-1/2/0_50_50.png".to_string(),
+        };
+        emit(event.clone());
+        assert!(receiver.try_iter().any(|received| received == event));
+    }
+
+    #[test]
+    fn unsubscribing() {
+        let count = std::sync::Arc::new(AtomicU64::new(0));
+        let subscription = subscribe({
+            let count = count.clone();
+            // Other tests emit events at the same time.
+            move |event| {
+                if matches!(event, Event::Tile { path } if path == "unsubscribing") {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        let event = Event::Tile {
+            path: "unsubscribing".to_string(),
+        };
+        emit(event.clone());
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        drop(subscription);
+        emit(event.clone());
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        // Like the sender of a dropped channel.
+        let id = add(Box::new(|_| false));
+        emit(event);
+        assert!(LISTENERS.read().unwrap().iter().all(|&(other, _)| other != id));
+    }
+}
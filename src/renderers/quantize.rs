@@ -0,0 +1,139 @@
+//! Reduces images to a palette of at most 256 colours, for indexed pngs.
+//!
+//! Images that already have few enough colours keep them exactly. Others are reduced by median cut:
+//! the box of colours with the widest range is split at the median of that range until there are enough boxes,
+//! and every colour becomes the average of its box, weighted by how often it occurs.
+
+use std::collections::HashMap;
+
+use image::RgbaImage;
+
+/// A palette, and the index into it of every pixel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Indexed {
+    pub palette: Vec<[u8; 4]>,
+    pub indices: Vec<u8>,
+}
+
+/// Colours and how often they occur.
+type Bucket = Vec<([u8; 4], u32)>;
+
+/// The channel along which `bucket` is widest, and how wide it is there.
+fn widest(bucket: &Bucket) -> (usize, u8) {
+    (0..4)
+        .map(|channel| {
+            let (min, max) = bucket.iter().fold((u8::MAX, u8::MIN), |(min, max), (colour, _)| {
+                (min.min(colour[channel]), max.max(colour[channel]))
+            });
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .expect("there are four channels")
+}
+
+/// The average of the colours of `bucket`, weighted by their counts.
+fn average(bucket: &Bucket) -> [u8; 4] {
+    let total = bucket.iter().map(|(_, count)| *count as u64).sum::<u64>().max(1);
+    let mut sums = [0_u64; 4];
+    for (colour, count) in bucket {
+        for (sum, channel) in sums.iter_mut().zip(colour) {
+            *sum += *channel as u64 * *count as u64;
+        }
+    }
+    sums.map(|sum| ((sum + total / 2) / total) as u8)
+}
+
+/// Reduces `img` to at most `max` colours, which must be at least one.
+///
+/// Fully transparent pixels all become the same colour, as their colour does not show.
+pub fn quantize(img: &RgbaImage, max: usize) -> Indexed {
+    let normalize = |pixel: [u8; 4]| if pixel[3] == 0 { [0; 4] } else { pixel };
+
+    let mut counts = HashMap::<[u8; 4], u32>::new();
+    for pixel in img.pixels() {
+        *counts.entry(normalize(pixel.0)).or_default() += 1;
+    }
+
+    let mut buckets: Vec<Bucket> = if counts.len() <= max {
+        counts.into_iter().map(|entry| vec![entry]).collect()
+    } else {
+        vec![counts.into_iter().collect()]
+    };
+    while buckets.len() < max {
+        let Some((index, (channel, _))) = buckets
+            .iter()
+            .map(widest)
+            .enumerate()
+            .filter(|(_, (_, range))| *range > 0)
+            .max_by_key(|&(_, (_, range))| range)
+        else {
+            break;
+        };
+        let mut bucket = buckets.swap_remove(index);
+        bucket.sort_unstable_by_key(|(colour, _)| colour[channel]);
+
+        // Split where half of the pixels, rather than half of the colours, are on either side.
+        let half = bucket.iter().map(|(_, count)| *count as u64).sum::<u64>() / 2;
+        let mut seen = 0;
+        let median = bucket
+            .iter()
+            .position(|(_, count)| {
+                seen += *count as u64;
+                seen > half
+            })
+            .unwrap_or(0)
+            .clamp(1, bucket.len() - 1);
+        let upper = bucket.split_off(median);
+        buckets.push(bucket);
+        buckets.push(upper);
+    }
+
+    let palette = buckets.iter().map(average).collect::<Vec<_>>();
+    let lookup = buckets
+        .iter()
+        .enumerate()
+        .flat_map(|(index, bucket)| bucket.iter().map(move |(colour, _)| (*colour, index as u8)))
+        .collect::<HashMap<_, _>>();
+    let indices = img.pixels().map(|pixel| lookup[&normalize(pixel.0)]).collect();
+    Indexed { palette, indices }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn few_colours_are_kept() {
+        let img = RgbaImage::from_fn(16, 16, |x, y| match (x < 8, y < 8) {
+            (true, true) => Rgba([255, 0, 0, 255]),
+            (true, false) => Rgba([0, 255, 0, 128]),
+            (false, true) => Rgba([1, 2, 3, 0]),
+            (false, false) => Rgba([4, 5, 6, 0]),
+        });
+        let indexed = quantize(&img, 256);
+        assert_eq!(indexed.palette.len(), 3);
+        for (pixel, &index) in img.pixels().zip(&indexed.indices) {
+            let colour = indexed.palette[index as usize];
+            if pixel[3] == 0 {
+                assert_eq!(colour, [0; 4]);
+            } else {
+                assert_eq!(colour, pixel.0);
+            }
+        }
+    }
+
+    #[test]
+    fn many_colours_are_reduced() {
+        let img = RgbaImage::from_fn(64, 64, |x, y| Rgba([x as u8 * 4, y as u8 * 4, 128, 255]));
+        let indexed = quantize(&img, 16);
+        assert_eq!(indexed.palette.len(), 16);
+        for (pixel, &index) in img.pixels().zip(&indexed.indices) {
+            let colour = indexed.palette[index as usize];
+            for channel in 0..4 {
+                assert!(colour[channel].abs_diff(pixel[channel]) <= 64, "{colour:?} is far from {pixel:?}");
+            }
+        }
+    }
+}
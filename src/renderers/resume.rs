@@ -0,0 +1,241 @@
+//! Records which mapsquares were rendered, so that `--resume` can skip them when a render is restarted.
+//!
+//! The record is kept in `<layer>/<mapid>/rendered.json`, next to the zoom levels.
+//! Each rendered mapsquare is listed with a checksum of what it was rendered from:
+//! the crcs of the archives of it and its neighbours, and every option that changes its tiles.
+//! A mapsquare is only skipped if that checksum is still the same.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use itertools::iproduct;
+use path_macro::path;
+use rs3cache_backend::{error::CacheResult, hash};
+
+use crate::{cli::Config, definitions::mapsquares::MapSquares};
+
+/// The name of the file rendered mapsquares are recorded in.
+pub const FILE: &str = "rendered.json";
+
+/// How many mapsquares are rendered between saves of the record,
+/// so that little is lost if the renderer is killed rather than cancelled.
+pub const SAVE_EVERY: usize = 64;
+
+/// The mapsquares rendered so far, by `<i>_<j>`, with the checksums they were rendered at.
+pub struct Manifest {
+    path: PathBuf,
+    checksums: BTreeMap<(u8, u8), i32>,
+    previous: BTreeMap<String, i32>,
+    resume: bool,
+    rendered: Mutex<BTreeMap<String, i32>>,
+    unsaved: AtomicUsize,
+}
+
+impl Manifest {
+    /// Starts a record for rendering the layer `name` of map `map_id`.
+    ///
+    /// Mapsquares of an earlier record that are still up to date are kept, so rendering part of the map does not forget the rest.
+    /// With `--resume`, those can be skipped; see [`is_done`](Self::is_done).
+    pub fn new(config: &Config, name: &str, map_id: i32) -> CacheResult<Self> {
+        let path = path!(name / format!("{map_id}") / FILE);
+        let checksums = checksums(&MapSquares::new(config)?.crcs()?, &settings(config));
+
+        let previous: BTreeMap<String, i32> = config
+            .sink()?
+            .get(&path)?
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        let rendered = previous
+            .iter()
+            .filter(|(key, checksum)| parse_key(key).and_then(|coordinates| checksums.get(&coordinates)) == Some(checksum))
+            .map(|(key, checksum)| (key.clone(), *checksum))
+            .collect();
+
+        Ok(Self {
+            path,
+            checksums,
+            previous,
+            resume: config.resume,
+            rendered: Mutex::new(rendered),
+            unsaved: AtomicUsize::new(0),
+        })
+    }
+
+    /// Whether `--resume` was passed and the mapsquare at `coordinates` was rendered already,
+    /// from the same data and with the same settings.
+    pub fn is_done(&self, &(i, j): &(u8, u8)) -> bool {
+        if !self.resume {
+            return false;
+        }
+        match self.checksums.get(&(i, j)) {
+            Some(checksum) => self.previous.get(&key(i, j)) == Some(checksum),
+            None => false,
+        }
+    }
+
+    /// Records that the mapsquare at `i, j` was rendered, saving the record every [`SAVE_EVERY`] mapsquares.
+    pub fn record(&self, config: &Config, i: u8, j: u8) -> CacheResult<()> {
+        if let Some(checksum) = self.checksums.get(&(i, j)) {
+            self.rendered.lock().unwrap().insert(key(i, j), *checksum);
+        }
+        if self.unsaved.fetch_add(1, Ordering::Relaxed) + 1 >= SAVE_EVERY {
+            self.save(config)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the record to disk.
+    pub fn save(&self, config: &Config) -> CacheResult<()> {
+        let data = {
+            let rendered = self.rendered.lock().unwrap();
+            self.unsaved.store(0, Ordering::Relaxed);
+            serde_json::to_vec_pretty(&*rendered).unwrap()
+        };
+        config.sink()?.put(&self.path, &data)
+    }
+}
+
+fn key(i: u8, j: u8) -> String {
+    format!("{i}_{j}")
+}
+
+fn parse_key(key: &str) -> Option<(u8, u8)> {
+    let (i, j) = key.split_once('_')?;
+    Some((i.parse().ok()?, j.parse().ok()?))
+}
+
+/// Every option that changes what the tiles of a mapsquare look like, or how they are written.
+fn settings(config: &Config) -> String {
+    #[cfg(all(feature = "webp", not(target_arch = "wasm32")))]
+    let tile_quality = config.tile_quality;
+    #[cfg(not(all(feature = "webp", not(target_arch = "wasm32"))))]
+    let tile_quality = None::<u8>;
+
+    format!(
+        "{:?} {:?} {:?} {:?} {:?} {} {} {} {:?} {:?} {:?} {:?} {:?} {:?}",
+        config.render_config,
+        config.palette,
+        config.markers,
+        config.highlight_loc,
+        config.layers,
+        config.debug_grid,
+        config.debug_chunks,
+        config.trim_tiles,
+        config.tile_template,
+        config.tile_scheme,
+        config.png_encoder,
+        config.tile_format,
+        tile_quality,
+        config.output_format,
+    )
+}
+
+/// The checksum of every mapsquare: of the crcs of it and its neighbours, which it is drawn with, and of the `settings`.
+fn checksums(crcs: &BTreeMap<(u8, u8), Vec<i32>>, settings: &str) -> BTreeMap<(u8, u8), i32> {
+    crcs.keys()
+        .map(|&(i, j)| {
+            let mut data = settings.as_bytes().to_vec();
+            for (di, dj) in iproduct!(-1..=1, -1..=1) {
+                let neighbour = (i as i32 + di, j as i32 + dj);
+                let crcs = u8::try_from(neighbour.0)
+                    .ok()
+                    .zip(u8::try_from(neighbour.1).ok())
+                    .and_then(|neighbour| crcs.get(&neighbour));
+                // Missing neighbours are marked, so that adding one changes the checksum.
+                data.push(crcs.is_some() as u8);
+                for crc in crcs.into_iter().flatten() {
+                    data.extend(crc.to_be_bytes());
+                }
+            }
+            ((i, j), hash::crc32(data))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::PngEncoder,
+        output::naming::TileScheme,
+        renderers::map::{Layer, RenderConfig},
+    };
+
+    #[test]
+    fn neighbours_count() {
+        let crcs = BTreeMap::from([((50, 50), vec![1]), ((50, 51), vec![2]), ((60, 60), vec![3])]);
+        let config = Config::default();
+        let before = checksums(&crcs, &settings(&config));
+
+        let mut changed = crcs.clone();
+        changed.insert((50, 51), vec![4]);
+        let after = checksums(&changed, &settings(&config));
+        assert_ne!(before[&(50, 50)], after[&(50, 50)]);
+        assert_eq!(before[&(60, 60)], after[&(60, 60)]);
+
+        assert_eq!(parse_key(&key(50, 51)), Some((50, 51)));
+    }
+
+    #[test]
+    fn options_count() {
+        let crcs = BTreeMap::from([((50, 50), vec![1])]);
+        let before = checksums(&crcs, &settings(&Config::default()));
+        let changed = [
+            Config {
+                render_config: RenderConfig {
+                    tile_size: 32,
+                    ..RenderConfig::default()
+                },
+                ..Config::default()
+            },
+            Config {
+                highlight_loc: vec![1234],
+                ..Config::default()
+            },
+            Config {
+                layers: vec![Layer::Walls],
+                ..Config::default()
+            },
+            Config {
+                debug_grid: true,
+                ..Config::default()
+            },
+            Config {
+                png_encoder: PngEncoder::Indexed,
+                ..Config::default()
+            },
+            Config {
+                tile_scheme: TileScheme::Xyz,
+                ..Config::default()
+            },
+            Config {
+                debug_chunks: true,
+                ..Config::default()
+            },
+            Config {
+                trim_tiles: true,
+                ..Config::default()
+            },
+            #[cfg(feature = "webp")]
+            Config {
+                tile_quality: Some(50),
+                ..Config::default()
+            },
+            #[cfg(feature = "mbtiles")]
+            Config {
+                output_format: crate::cli::OutputFormat::Mbtiles,
+                ..Config::default()
+            },
+        ];
+        for config in changed {
+            assert_ne!(before, checksums(&crcs, &settings(&config)), "{}", settings(&config));
+        }
+    }
+}
@@ -1,15 +1,36 @@
 use image::{GenericImageView, Pixel, Rgba, RgbaImage};
 use itertools::{iproduct, izip};
 
-/// Resizes a 512x512 image to 256x256.
+use crate::cli::Config;
+#[cfg(feature = "gpu")]
+use crate::renderers::gpu;
+
+/// Resizes `img` to a half or a quarter of its size, on the gpu with `--gpu`.
+#[cfg_attr(not(feature = "gpu"), allow(unused_variables))]
+pub fn downscale(config: &Config, img: RgbaImage, factor: u32) -> RgbaImage {
+    #[cfg(feature = "gpu")]
+    if config.gpu {
+        if let Some(resized) = gpu::get().and_then(|gpu| gpu.resize(&img, factor)) {
+            return resized;
+        }
+    }
+    match factor {
+        2 => resize_half(img),
+        4 => resize_quarter(img),
+        _ => unimplemented!("only halving and quartering are supported"),
+    }
+}
+
+/// Resizes an image to half its size, as from 512x512 to 256x256.
 ///
 /// This function is used over the resize functions for two reasons:
 /// - Performance, because image's resize function is shit.
 /// - To avoid white lines around the edges.
 pub fn resize_half(input: impl GenericImageView<Pixel = Rgba<u8>>) -> RgbaImage {
-    assert_eq!(input.dimensions(), (512, 512));
+    let (width, height) = input.dimensions();
+    assert_eq!((width % 2, height % 2), (0, 0));
 
-    RgbaImage::from_fn(256, 256, |x, y| {
+    RgbaImage::from_fn(width / 2, height / 2, |x, y| {
         let mut accum: [u16; 4] = [0, 0, 0, 0];
 
         // Iterate over 2x2 chunks and assign the average to the new pixel
@@ -25,15 +46,16 @@ pub fn resize_half(input: impl GenericImageView<Pixel = Rgba<u8>>) -> RgbaImage
     })
 }
 
-/// Resizes a 1024x1024 image to 256x256.
+/// Resizes an image to a quarter of its size, as from 1024x1024 to 256x256.
 ///
 /// This function is used over the resize functions for two reasons:
 /// - Performance, because image's resize function is shit.
 /// - To avoid white lines around the edges.
 pub fn resize_quarter(input: impl GenericImageView<Pixel = Rgba<u8>>) -> RgbaImage {
-    assert_eq!(input.dimensions(), (1024, 1024));
+    let (width, height) = input.dimensions();
+    assert_eq!((width % 4, height % 4), (0, 0));
 
-    RgbaImage::from_fn(256, 256, |x, y| {
+    RgbaImage::from_fn(width / 4, height / 4, |x, y| {
         let mut accum: [u16; 4] = [0, 0, 0, 0];
 
         // Iterate over 4x4 chunks and assign the average to the new pixel
@@ -0,0 +1,131 @@
+//! Renders placed locations in their surroundings, for use in wiki infoboxes and the like.
+//!
+//! A shot is an isometric view from the south west of the ground and the locations within [`RADIUS`] tiles of a location,
+//! on its plane and the ones below. They are drawn from their models as [npcs are](crate::renderers::npcs),
+//! and only as far as the mapsquare of the location goes.
+
+use std::{collections::BTreeSet, f32::consts::FRAC_PI_4, fs};
+
+use ::error::Context;
+use console::style;
+use path_macro::path;
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use rs3cache_backend::error::{self, CacheResult};
+
+use crate::{
+    cli::Config,
+    definitions::{
+        location_configs::LocationConfig, locations::Location, mapsquares::GroupMapSquareIterator, models::Model, overlays::Overlay,
+        underlays::Underlay,
+    },
+    output::mesh::Triangle,
+    renderers::{npcs, png, terrain},
+};
+
+/// How many tiles to include around a location, in each direction.
+const RADIUS: u32 = 8;
+
+/// The width and height of a shot, in pixels.
+pub const SIZE: u32 = 512;
+
+/// How far above the horizon shots are seen from, in radians. This is `atan(1 / sqrt(2))`, as in isometric views.
+const PITCH: f32 = 0.615_479_7;
+
+/// The locations within [`RADIUS`] tiles of `centre`, on its plane or below.
+fn near<'a>(locations: &'a [Location], centre: &'a Location) -> impl Iterator<Item = &'a Location> + 'a {
+    locations.iter().filter(|loc| {
+        loc.plane.inner() <= centre.plane.inner() && loc.x.abs_diff(centre.x) as u32 <= RADIUS && loc.y.abs_diff(centre.y) as u32 <= RADIUS
+    })
+}
+
+/// Whether every corner of `triangle` is within [`RADIUS`] tiles of the tile at `x, y`.
+fn is_within((corners, _): &Triangle, x: u8, y: u8) -> bool {
+    let (x, y, radius) = (x as f32, y as f32, RADIUS as f32);
+    // North is towards -z.
+    corners
+        .iter()
+        .all(|&[east, _, south]| (x - radius..=x + radius + 1.0).contains(&east) && (y - radius..=y + radius + 1.0).contains(&-south))
+}
+
+/// Renders every placement of the location ids in `--ids`
+/// to `scenery_shots/<id>/<plane>_<x>_<y>.png`. Exposed as `--dump scenery_shots`.
+pub fn export(config: &Config) -> CacheResult<()> {
+    let Some(ids_file) = &config.ids else {
+        println!("    {} scenery_shots needs a file of location ids, passed with `--ids`", style("Warning").yellow());
+        return Ok(());
+    };
+    let ids = fs::read_to_string(ids_file).context(error::Io { path: ids_file.clone() })?;
+    let mut ids = parse_ids(&ids);
+    ids.retain(|id| config.keeps(*id));
+
+    let location_configs = LocationConfig::dump_all(config)?;
+    let underlays = Underlay::dump_all(config)?;
+    let overlays = Overlay::dump_all(config)?;
+    let model_ids = |loc: &Location| {
+        location_configs
+            .get(&loc.id)
+            .map(|location_config| terrain::model_ids(location_config, loc.r#type))
+            .unwrap_or_default()
+    };
+
+    GroupMapSquareIterator::new(-1_i32..=1_i32, -1_i32..=1_i32, config)?
+        .par_bridge()
+        .try_for_each(|gsq| {
+            let Some(locations) = gsq.core().and_then(|core| core.locations()) else { return Ok(()) };
+            let wanted = locations.iter().filter(|loc| ids.contains(&loc.id)).collect::<Vec<_>>();
+            if wanted.is_empty() {
+                return Ok(());
+            }
+
+            let needed = wanted
+                .iter()
+                .flat_map(|&centre| near(locations, centre))
+                .flat_map(model_ids)
+                .collect::<BTreeSet<_>>();
+            let models = Model::dump(config, needed)?;
+
+            for centre in wanted {
+                let plane = centre.plane.inner();
+                let mut triangles = terrain::ground(&gsq, &underlays, &overlays, 0..=plane as usize);
+                triangles.retain(|triangle| is_within(triangle, centre.x, centre.y));
+                for loc in near(locations, centre) {
+                    for model in model_ids(loc).iter().filter_map(|id| models.get(id)) {
+                        triangles.extend(terrain::place(&gsq, loc, model, location_configs.get(&loc.id)));
+                    }
+                }
+                let shot = npcs::draw_triangles(triangles, SIZE, FRAC_PI_4, PITCH);
+
+                let (global_x, global_y) = (centre.i as u32 * 64 + centre.x as u32, centre.j as u32 * 64 + centre.y as u32);
+                let filename = path!("scenery_shots" / format!("{}", centre.id) / format!("{plane}_{global_x}_{global_y}.png"));
+                png::put(config, filename, &shot)?;
+            }
+            Ok(())
+        })
+}
+
+/// Reads ids separated by whitespace or commas.
+pub(crate) fn parse_ids(text: &str) -> BTreeSet<u32> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|id| id.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids() {
+        assert_eq!(parse_ids("1, 2\n3\n\nfoo 4,"), BTreeSet::from([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn surroundings() {
+        let colour = [0, 0, 0, 255];
+        assert!(is_within(&([[2.0, 0.0, -2.0], [10.0, 5.0, -3.0], [3.0, 0.0, -19.0]], colour), 10, 10));
+        // Too far north.
+        assert!(!is_within(&([[2.0, 0.0, -2.0], [10.0, 5.0, -3.0], [3.0, 0.0, -20.0]], colour), 10, 10));
+        // Too far west.
+        assert!(!is_within(&([[1.0, 0.0, -2.0], [10.0, 5.0, -3.0], [3.0, 0.0, -19.0]], colour), 10, 10));
+    }
+}
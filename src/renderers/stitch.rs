@@ -0,0 +1,163 @@
+//! Assembles the tiles of one zoom level into a single image per plane, for `--stitch`.
+//!
+//! The image is written one row of tiles at a time, so only that row is ever in memory;
+//! even the whole world at zoom level 4 can be stitched, if slowly.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+use ::error::Context;
+use image::RgbaImage;
+use path_macro::path;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use regex::Regex;
+use rs3cache_backend::error::{self, CacheResult};
+
+use crate::{cli::Config, renderers::map::Region};
+
+/// The tiles that cover `region` at `zoom`, as ranges of tile coordinates.
+///
+/// A tile of zoom level 2 is a mapsquare of 64 by 64 game tiles, and every level up halves that.
+pub fn extent(zoom: i8, region: &Region) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
+    let shift = 8 - zoom as i32;
+    let tile = |c: u32| (c >> shift) as i32;
+    (
+        tile(region.x1.min(region.x2))..=tile(region.x1.max(region.x2)),
+        tile(region.y1.min(region.y2))..=tile(region.y1.max(region.y2)),
+    )
+}
+
+/// Appends row `y` of every image of `tiles` to `row`, with transparent pixels for the missing ones.
+fn append_row(row: &mut Vec<u8>, tiles: &[Option<RgbaImage>], size: u32, y: u32) {
+    for tile in tiles {
+        match tile {
+            Some(tile) if y < tile.height() => {
+                let width = tile.width().min(size) as usize * 4;
+                let start = (y * tile.width()) as usize * 4;
+                row.extend_from_slice(&tile.as_raw()[start..start + width]);
+                row.resize(row.len() + (size as usize * 4 - width), 0);
+            }
+            _ => row.resize(row.len() + size as usize * 4, 0),
+        }
+    }
+}
+
+/// Writes the tiles of `<name>/<mapid>/<zoom>` that are inside `region`, or all of them,
+/// as one png per plane to `<name>/<mapid>/stitched/<plane>.png`.
+///
+/// This reads the tiles before `--trim-tiles` crops them.
+pub fn export(config: &Config, name: &str, mapid: i32, zoom: i8, region: Option<&Region>) -> CacheResult<()> {
+    let sink = config.sink()?;
+    let extension = config.tile_format.extension();
+    let folder = path!(name / format!("{mapid}/{zoom}"));
+
+    let re = Regex::new(&format!(r"^(\d+)_(\d+)_(\d+)\.{extension}$")).expect("Regex is cursed.");
+    let tiles = sink
+        .list(&folder)?
+        .iter()
+        .filter_map(|filename| {
+            let caps = re.captures(filename)?;
+            let parse = |n: usize| caps[n].parse::<i32>().ok();
+            Some((parse(1)?, parse(2)?, parse(3)?))
+        })
+        .collect::<BTreeSet<_>>();
+
+    let (range_i, range_j) = match region {
+        Some(region) => extent(zoom, region),
+        None if tiles.is_empty() => return Ok(()),
+        None => {
+            let (is, js) = (tiles.iter().map(|&(_, i, _)| i), tiles.iter().map(|&(_, _, j)| j));
+            (
+                is.clone().min().unwrap()..=is.max().unwrap(),
+                js.clone().min().unwrap()..=js.max().unwrap(),
+            )
+        }
+    };
+    let planes = tiles
+        .iter()
+        .filter(|(_, i, j)| range_i.contains(i) && range_j.contains(j))
+        .map(|&(p, _, _)| p)
+        .collect::<BTreeSet<_>>();
+
+    let load = |p: i32, i: i32, j: i32| -> CacheResult<Option<RgbaImage>> {
+        if !tiles.contains(&(p, i, j)) {
+            return Ok(None);
+        }
+        let data = sink.get(path!(folder / format!("{p}_{i}_{j}.{extension}")))?;
+        Ok(data.map(|data| {
+            image::load_from_memory_with_format(&data, config.tile_format.image_format())
+                .unwrap()
+                .into_rgba8()
+        }))
+    };
+
+    for plane in planes {
+        let relative = path!(name / format!("{mapid}/stitched/{plane}.png"));
+
+        // Tiles are 256 pixels wide, unless the map was rendered with another `--tile-size`.
+        let size = tiles
+            .iter()
+            .find(|&&(p, _, _)| p == plane)
+            .map(|&(p, i, j)| load(p, i, j))
+            .transpose()?
+            .flatten()
+            .map_or(256, |tile| tile.height());
+        let width = range_i.clone().count() as u32 * size;
+        let height = range_j.clone().count() as u32 * size;
+
+        let mut out = sink.create(&relative)?;
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .and_then(|writer| writer.into_stream_writer())
+            .map_err(io::Error::from)
+            .context(error::Io { path: relative.clone() })?;
+
+        // Images start at the north, tiles at the south.
+        for j in range_j.clone().rev() {
+            let row_tiles = range_i
+                .clone()
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|i| load(plane, i, j))
+                .collect::<CacheResult<Vec<_>>>()?;
+
+            let mut rows = Vec::with_capacity(width as usize * size as usize * 4);
+            for y in 0..size {
+                append_row(&mut rows, &row_tiles, size, y);
+            }
+            writer.write_all(&rows).context(error::Io { path: relative.clone() })?;
+        }
+        writer.finish().map_err(io::Error::from).context(error::Io { path: relative.clone() })?;
+        out.finish()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn extents() {
+        let varrock = Region::new(3136, 3328, 3327, 3519);
+        assert_eq!(extent(2, &varrock), (49..=51, 52..=54));
+        assert_eq!(extent(4, &varrock), (196..=207, 208..=219));
+        assert_eq!(extent(-1, &varrock), (6..=6, 6..=6));
+    }
+
+    #[test]
+    fn rows() {
+        let tile = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 4]));
+        let mut row = Vec::new();
+        append_row(&mut row, &[Some(tile), None], 2, 1);
+        assert_eq!(row, [1, 2, 3, 4, 1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
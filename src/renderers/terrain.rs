@@ -0,0 +1,224 @@
+//! Exports mapsquares as 3d scenes, for 3d map viewers.
+//!
+//! Every mapsquare becomes `terrain3d/<i>_<j>.glb`: the ground of all planes as a heightfield,
+//! with each tile coloured by its overlay or underlay, and with `--terrain-locations` the models of the locations on it.
+//!
+//! Like [`mesh`], a tile is one unit wide and `y` points up; the south west corner of the mapsquare is at the origin
+//! and north is towards `-z`. Tile shapes are not cut out, so a tile with an overlay is coloured by it entirely.
+//! Tiles without a height get theirs from the game's terrain generator, which this does not replicate; those are flat.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
+
+use path_macro::path;
+use rayon::iter::ParallelIterator;
+use rs3cache_backend::error::CacheResult;
+use rs3cache_utils::bar::Render;
+
+use crate::{
+    cli::Config,
+    definitions::{
+        location_configs::LocationConfig,
+        locations::Location,
+        mapsquares::{GroupMapSquare, GroupMapSquareIterator},
+        models::Model,
+        overlays::Overlay,
+        tiles::Tile,
+        underlays::Underlay,
+    },
+    output::mesh::{self, Triangle, TEXTURED},
+    renderers::map::base::{height, HEIGHT_STEP},
+};
+
+/// How many height steps a plane is above the one below it, where its tiles have no height of their own.
+const PLANE_STEPS: i32 = 30;
+
+/// The height of the corner at `x, y` of `plane`, in tiles. As in the game, the heights of upper planes are relative to the plane below.
+fn elevation(squares: &GroupMapSquare, plane: usize, x: isize, y: isize) -> f32 {
+    let steps = (0..=plane)
+        .map(|p| height(squares, p, x, y).unwrap_or(if p == 0 { 0 } else { PLANE_STEPS }))
+        .sum::<i32>();
+    steps as f32 * HEIGHT_STEP
+}
+
+/// The colour of `tile`, or `None` if it has neither an underlay nor an overlay.
+fn colour(tile: &Tile, underlays: &BTreeMap<u32, Underlay>, overlays: &BTreeMap<u32, Overlay>) -> Option<[u8; 4]> {
+    let overlay = tile
+        .overlay_id
+        .and_then(|id| overlays.get(&(id.checked_sub(1)? as u32)))
+        .map(|overlay| match overlay.primary_colour {
+            // Magenta overlays are not drawn.
+            Some([255, 0, 255]) if overlay.texture.is_none() => None,
+            Some(colour) if overlay.texture.is_none() => Some(colour),
+            _ => Some(TEXTURED),
+        });
+    let underlay = || {
+        tile.underlay_id
+            .and_then(|id| underlays.get(&(id.checked_sub(1)? as u32)))
+            .and_then(|underlay| underlay.colour)
+    };
+    let [r, g, b] = overlay.flatten().or_else(underlay)?;
+    Some([r, g, b, 255])
+}
+
+/// The two faces of the tile at `x, y`, with the heights of its south west, south east, north east and north west corners.
+fn quad(x: f32, y: f32, [sw, se, ne, nw]: [f32; 4], colour: [u8; 4]) -> [Triangle; 2] {
+    let (sw, se, ne, nw) = ([x, sw, -y], [x + 1.0, se, -y], [x + 1.0, ne, -y - 1.0], [x, nw, -y - 1.0]);
+    [([sw, se, ne], colour), ([sw, ne, nw], colour)]
+}
+
+/// Turns a vertex of a model clockwise by `rotation` quarters, as the game does for placed locations.
+fn rotate([x, y, z]: [i32; 3], rotation: u8) -> [i32; 3] {
+    match rotation % 4 {
+        0 => [x, y, z],
+        1 => [z, y, -x],
+        2 => [-x, y, -z],
+        _ => [-z, y, x],
+    }
+}
+
+/// The ids of the models of `config` for locations of type `ty`.
+pub(crate) fn model_ids(config: &LocationConfig, ty: u8) -> Vec<u32> {
+    #[cfg(not(feature = "2008_3_shim"))]
+    if let Some(models_2) = &config.models_2 {
+        return models_2.models_2.iter().map(|&id| id as u32).collect();
+    }
+    #[cfg(feature = "2008_3_shim")]
+    if let Some(models_2) = &config.models_2 {
+        return models_2.models_2.iter().map(|&(_, id)| id).collect();
+    }
+    // Diagonal walls use the models of straight ones.
+    let ty = if ty == 11 { 10 } else { ty };
+    #[cfg(not(feature = "2008_3_shim"))]
+    let ids = config
+        .models
+        .iter()
+        .flat_map(|models| &models.models)
+        .filter(|&&(r#type, _)| r#type == ty)
+        .map(|&(_, id)| id as u32)
+        .collect();
+    #[cfg(feature = "2008_3_shim")]
+    let ids = config
+        .models
+        .iter()
+        .flat_map(|models| models.models.get(&(ty as i8)))
+        .flatten()
+        .flatten()
+        .copied()
+        .collect();
+    ids
+}
+
+/// The faces of `model` placed as `loc`, centred on the tiles it covers.
+pub(crate) fn place(squares: &GroupMapSquare, loc: &Location, model: &Model, config: Option<&LocationConfig>) -> Vec<Triangle> {
+    let (dim_x, dim_y) = (
+        config.and_then(|config| config.dim_x).unwrap_or(1) as f32,
+        config.and_then(|config| config.dim_y).unwrap_or(1) as f32,
+    );
+    // Locations that are turned a quarter have their sides swapped.
+    let (dim_x, dim_y) = if loc.rotation % 2 == 1 { (dim_y, dim_x) } else { (dim_x, dim_y) };
+    let scale = |scale: Option<u16>| scale.map_or(1.0, |scale| scale as f32 / 128.0);
+    let (scale_x, scale_y, scale_z) = config.map_or((1.0, 1.0, 1.0), |config| {
+        (scale(config.scale_x), scale(config.scale_y), scale(config.scale_z))
+    });
+
+    let mut placed = model.clone();
+    for vertex in &mut placed.vertices {
+        let [x, y, z] = rotate(*vertex, loc.rotation);
+        *vertex = [(x as f32 * scale_x) as i32, (y as f32 * scale_y) as i32, (z as f32 * scale_z) as i32];
+    }
+
+    let (x, y) = (loc.x as f32 + dim_x / 2.0, loc.y as f32 + dim_y / 2.0);
+    let ground = elevation(squares, loc.plane.inner() as usize, loc.x as isize, loc.y as isize);
+    mesh::triangles(&placed)
+        .map(|(corners, colour)| (corners.map(|[a, b, c]| [a + x, b + ground, c - y]), colour))
+        .collect()
+}
+
+/// The ground of `planes` of the core of `squares`.
+pub(crate) fn ground(
+    squares: &GroupMapSquare,
+    underlays: &BTreeMap<u32, Underlay>,
+    overlays: &BTreeMap<u32, Overlay>,
+    planes: RangeInclusive<usize>,
+) -> Vec<Triangle> {
+    let Some(tiles) = squares.core().and_then(|core| core.tiles()) else { return Vec::new() };
+    let mut triangles = Vec::new();
+    for plane in planes {
+        for x in 0..64 {
+            for y in 0..64 {
+                let Some(fill) = colour(&tiles[[plane, x, y]], underlays, overlays) else { continue };
+                let (x, y) = (x as isize, y as isize);
+                let corners = [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(dx, dy)| elevation(squares, plane, x + dx, y + dy));
+                triangles.extend(quad(x as f32, y as f32, corners, fill));
+            }
+        }
+    }
+    triangles
+}
+
+/// Saves every mapsquare as `terrain3d/<i>_<j>.glb`. Exposed as `--dump terrain3d`.
+pub fn export(config: &Config) -> CacheResult<()> {
+    let location_configs = LocationConfig::dump_all(config)?;
+    let underlays = Underlay::dump_all(config)?;
+    let overlays = Overlay::dump_all(config)?;
+    let sink = config.sink()?;
+
+    GroupMapSquareIterator::new(-1_i32..=1_i32, -1_i32..=1_i32, config)?
+        .render("terrain3d")
+        .try_for_each(|(squares, _)| {
+            let mut triangles = ground(&squares, &underlays, &overlays, 0..=3);
+
+            if config.terrain_locations {
+                let locations = squares.core().and_then(|core| core.locations()).unwrap_or_default();
+                let ids = locations
+                    .iter()
+                    .filter_map(|loc| Some(model_ids(location_configs.get(&loc.id)?, loc.r#type)))
+                    .flatten()
+                    .collect::<BTreeSet<_>>();
+                let models = Model::dump(config, ids)?;
+
+                for loc in locations {
+                    let location_config = location_configs.get(&loc.id);
+                    for id in location_config.map(|c| model_ids(c, loc.r#type)).unwrap_or_default() {
+                        if let Some(model) = models.get(&id) {
+                            triangles.extend(place(&squares, loc, model, location_config));
+                        }
+                    }
+                }
+            }
+
+            let (i, j) = (squares.core_i(), squares.core_j());
+            let Some(glb) = mesh::scene(&format!("mapsquare_{i}_{j}"), triangles) else { return Ok(()) };
+            sink.put(path!("terrain3d" / format!("{i}_{j}.glb")), &glb)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads() {
+        let [(first, _), (second, _)] = quad(2.0, 3.0, [0.0, 0.5, 1.0, 0.5], [1, 2, 3, 255]);
+        assert_eq!(first, [[2.0, 0.0, -3.0], [3.0, 0.5, -3.0], [3.0, 1.0, -4.0]]);
+        assert_eq!(second[2], [2.0, 0.5, -4.0]);
+
+        // Both faces point up.
+        for [a, b, c] in [first, second] {
+            let (u, v) = ([b[0] - a[0], b[2] - a[2]], [c[0] - a[0], c[2] - a[2]]);
+            assert!(u[1] * v[0] - u[0] * v[1] > 0.0);
+        }
+    }
+
+    #[test]
+    fn rotations() {
+        assert_eq!(rotate([1, 2, 3], 0), [1, 2, 3]);
+        assert_eq!(rotate([1, 2, 3], 1), [3, 2, -1]);
+        assert_eq!(rotate([1, 2, 3], 2), [-1, 2, -3]);
+        assert_eq!(rotate([1, 2, 3], 3), [-3, 2, 1]);
+        assert_eq!(rotate(rotate([1, 2, 3], 1), 3), [1, 2, 3]);
+    }
+}
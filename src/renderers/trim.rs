@@ -0,0 +1,108 @@
+//! Describes which part of each tile actually has content.
+//!
+//! Tiles of the upper planes are mostly transparent; with the bounding boxes in `manifest.json`
+//! a viewer can skip fetching tiles that have nothing to show in its viewport.
+
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use image::{GenericImageView, RgbaImage};
+use path_macro::path;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rs3cache_backend::error::CacheResult;
+use serde::Serialize;
+
+use crate::{cli::Config, renderers::png};
+
+/// The part of a tile that is not fully transparent, in pixels from the top left.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl BBox {
+    /// Returns the bounding box of all pixels of `img` that are not fully transparent,
+    /// or `None` if it is empty.
+    pub fn of(img: &RgbaImage) -> Option<Self> {
+        let mut pixels = img.enumerate_pixels().filter(|(_, _, pixel)| pixel[3] != 0);
+        let (x, y, _) = pixels.next()?;
+        let (min_x, min_y, max_x, max_y) = pixels.fold((x, y, x, y), |(min_x, min_y, max_x, max_y), (x, y, _)| {
+            (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+        });
+
+        Some(Self {
+            x: min_x,
+            y: min_y,
+            width: max_x - min_x + 1,
+            height: max_y - min_y + 1,
+        })
+    }
+}
+
+/// Writes the bounding box of every tile of `zoom_levels` to `<name>/<mapid>/manifest.json`,
+/// keyed as `<zoom>/<plane>_<x>_<y>`. Empty tiles are `null`.
+///
+/// With `--trim-tiles`, the tiles themselves are also cropped to their bounding box.
+pub fn export_manifest(config: &Config, name: &str, mapid: i32, zoom_levels: RangeInclusive<i8>) -> CacheResult<()> {
+    let sink = config.sink()?;
+    let extension = format!(".{}", config.tile_format.extension());
+
+    let mut manifest = BTreeMap::new();
+    for zoom in zoom_levels {
+        let folder = path!(name / format!("{mapid}/{zoom}"));
+        let boxes = sink
+            .list(&folder)?
+            .into_par_iter()
+            .filter(|filename| filename.ends_with(&extension))
+            .map(|filename| {
+                let path = path!(folder / &filename);
+                let Some(data) = sink.get(&path)? else { return Ok(None) };
+                let img = image::load_from_memory_with_format(&data, config.tile_format.image_format())
+                    .unwrap()
+                    .into_rgba8();
+
+                let bbox = BBox::of(&img);
+                match bbox {
+                    Some(BBox { x, y, width, height }) if config.trim_tiles && (width, height) != img.dimensions() => {
+                        png::put_tile(config, &path, &img.view(x, y, width, height).to_image())?
+                    }
+                    _ => {}
+                }
+
+                let key = format!("{zoom}/{}", filename.trim_end_matches(&extension));
+                Ok(Some((key, bbox)))
+            })
+            .collect::<CacheResult<Vec<_>>>()?;
+        manifest.extend(boxes.into_iter().flatten());
+    }
+
+    let data = serde_json::to_string(&manifest).unwrap();
+    sink.put(path!(name / format!("{mapid}/manifest.json")), data.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn bbox() {
+        let mut img = RgbaImage::new(256, 256);
+        assert_eq!(BBox::of(&img), None);
+
+        img.put_pixel(10, 20, Rgba([1, 2, 3, 255]));
+        img.put_pixel(30, 5, Rgba([1, 2, 3, 1]));
+        assert_eq!(
+            BBox::of(&img),
+            Some(BBox {
+                x: 10,
+                y: 5,
+                width: 21,
+                height: 16
+            })
+        );
+    }
+}
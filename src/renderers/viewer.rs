@@ -0,0 +1,60 @@
+//! A page to look at rendered maps with.
+//!
+//! It shows the tiles of one layer and map id, with a switcher between planes
+//! and the game coordinates of the mouse. It has no dependencies, so it works offline and when opened as a file.
+
+use rs3cache_backend::error::CacheResult;
+
+use crate::{
+    cli::Config,
+    output::naming::{TileNaming, TileScheme},
+    renderers::map::RenderConfig,
+};
+
+/// Fills in the viewer page for the tiles of the layer `name`, as rendered with `render` and named by `naming`.
+///
+/// Tiles are requested relative to the page, so it should be next to the layer's folder.
+pub fn page(name: &str, render: &RenderConfig, naming: &TileNaming) -> String {
+    [
+        ("{{title}}", format!("{name} {}", render.map_id)),
+        ("{{layer}}", name.to_string()),
+        ("{{map_id}}", render.map_id.to_string()),
+        ("{{extension}}", naming.extension.to_string()),
+        ("{{template}}", naming.template.to_string()),
+        ("{{xyz}}", (naming.scheme == TileScheme::Xyz).to_string()),
+        ("{{min_zoom}}", "-4".to_string()),
+        ("{{max_zoom}}", render.initial_zoom.to_string()),
+        // Lumbridge.
+        ("{{center_x}}", "3222".to_string()),
+        ("{{center_y}}", "3218".to_string()),
+    ]
+    .into_iter()
+    .fold(include_str!("viewer.html").to_string(), |page, (placeholder, value)| {
+        page.replace(placeholder, &value)
+    })
+}
+
+/// Writes the viewer page for the layer `name` to `index.html`, for `--emit-viewer`.
+pub fn export(config: &Config, name: &str) -> CacheResult<()> {
+    let page = page(name, &config.render_config, &config.tile_naming());
+    config.sink()?.put("index.html", page.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filled_in() {
+        let naming = TileNaming {
+            template: Default::default(),
+            scheme: TileScheme::Xyz,
+            extension: "png",
+            map_id: -1,
+        };
+        let page = page("mapsquares", &RenderConfig::default(), &naming);
+        assert!(!page.contains("{{"), "placeholders left in the page");
+        assert!(page.contains(r#"layer: "mapsquares""#));
+        assert!(!page.contains("https://"), "the page should be self-contained");
+    }
+}
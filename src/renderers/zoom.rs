@@ -1,46 +1,103 @@
-use std::{collections::HashSet, ffi::OsString, fs, io, ops::Range, path::Path, sync::LazyLock};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, Mutex,
+    },
+};
 
-use ::error::Context;
-use image::{imageops, ImageBuffer, ImageError, Rgba, RgbaImage};
-use itertools::Itertools;
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
 use path_macro::path;
 use rayon::iter::ParallelIterator;
 use regex::Regex;
-use rs3cache_backend::error::{self, CacheResult};
+use rs3cache_backend::error::CacheResult;
 use rs3cache_utils::bar::Render;
 
-use crate::{cli::Config, renderers::scale};
+use crate::{
+    cancel,
+    cli::Config,
+    renderers::{png, scale},
+};
 
-static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?P<p>\d+)(?:_)(?P<i>\d+)(?:_)(?P<j>\d+)(?:\.png)").expect("Regex is cursed."));
+static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?P<p>\d+)(?:_)(?P<i>\d+)(?:_)(?P<j>\d+)\.(?:png|webp)").expect("Regex is cursed."));
 
 /// Given a folder and a range of zoom levels, recursively creates tiles for all zoom levels.
 pub fn render_zoom_levels(config: &Config, name: &str, mapid: i32, range: Range<i8>, backfill: [u8; 4]) -> CacheResult<()> {
+    render_levels(config, name, mapid, range, backfill, |_, _, _| true)
+}
+
+/// Like [`render_zoom_levels`], but only remakes the tiles that cover one of `squares`,
+/// for when only those mapsquares were rendered again. Mapsquares are the tiles of zoom level 2.
+pub fn render_zoom_levels_of(
+    config: &Config,
+    name: &str,
+    mapid: i32,
+    range: Range<i8>,
+    backfill: [u8; 4],
+    squares: &BTreeSet<(u8, u8)>,
+) -> CacheResult<()> {
+    let covered = |zoom: i8, i: i32, j: i32| {
+        let shift = 2 - zoom;
+        squares.iter().any(|&(si, sj)| (si as i32) >> shift == i && (sj as i32) >> shift == j)
+    };
+    render_levels(config, name, mapid, range, backfill, covered)
+}
+
+/// How many MiB of a zoom level are kept in memory to make the next one from, unless `--memory-limit` is given.
+const RETAINED_MIB: u64 = 1024;
+
+/// The tiles of the zoom level that was just made, so that the next one does not have to read them back.
+///
+/// Tiles that do not fit in the budget are only on disk, and are read from there.
+type Retained = HashMap<(i32, i32, i32), RgbaImage>;
+
+fn render_levels(
+    config: &Config,
+    name: &str,
+    mapid: i32,
+    range: Range<i8>,
+    backfill: [u8; 4],
+    keep: impl Fn(i8, i32, i32) -> bool,
+) -> CacheResult<()> {
+    // Half of `--memory-limit`, as the tiles being made and encoded take memory too.
+    let budget = config.memory_limit.map_or(RETAINED_MIB, |limit| limit / 2) << 20;
+    let mut previous = Retained::new();
+
     let zoom_levels = range.rev();
     for zoom in zoom_levels {
-        let path = path!(config.output / name / format!("{mapid}/{zoom}"));
-        fs::create_dir_all(&path).context(error::Io { path })?;
-
-        let new_tile_coordinates = get_future_filenames(config, name, mapid, zoom + 1)?.into_iter();
+        // Each level is made from the one above it, so only whole levels are made.
+        if cancel::is_cancelled() {
+            break;
+        }
+        let new_tile_coordinates = get_future_filenames(config, name, mapid, zoom + 1)?
+            .into_iter()
+            .filter(|&(_, i, j)| keep(zoom, i, j))
+            .collect::<Vec<_>>()
+            .into_iter();
 
+        let retained = Mutex::new(Retained::new());
+        let used = AtomicU64::new(0);
         let func = |((p, i, j), _)| {
-            let img = make_tile(&config.output, name, mapid, zoom, p, i, j, backfill)?;
-            let path = path!(config.output / &name / format!("{mapid}/{zoom}/{p}_{i}_{j}.png"));
-
-            match img.save(&path) {
-                Ok(()) => {}
-                Err(ImageError::IoError(e)) => return Err(e).context(error::Io { path }),
-                Err(other) => panic!("{other}"),
-            };
+            let img = make_tile(config, name, mapid, zoom, p, i, j, backfill, &previous)?;
+            let extension = config.tile_format.extension();
+            png::put_tile(config, path!(name / format!("{mapid}/{zoom}/{p}_{i}_{j}.{extension}")), &img)?;
+
+            let size = img.as_raw().len() as u64;
+            if used.fetch_add(size, Ordering::Relaxed) + size <= budget {
+                retained.lock().unwrap().insert((p, i, j), img);
+            }
             Ok(())
         };
 
         new_tile_coordinates.render(format!("{name} zoom level {zoom}")).try_for_each(func)?;
+        previous = retained.into_inner().unwrap();
     }
     Ok(())
 }
 
-fn to_coordinates(text: OsString) -> (i32, i32, i32) {
-    let caps = RE.captures(text.to_str().unwrap()).unwrap();
+fn to_coordinates(text: &str) -> (i32, i32, i32) {
+    let caps = RE.captures(text).unwrap();
     let p = caps.name("p").unwrap().as_str().parse::<i32>().unwrap();
     let i = caps.name("i").unwrap().as_str().parse::<i32>().unwrap();
     let j = caps.name("j").unwrap().as_str().parse::<i32>().unwrap();
@@ -48,7 +105,7 @@ fn to_coordinates(text: OsString) -> (i32, i32, i32) {
 }
 
 fn make_tile(
-    folder: impl AsRef<Path>,
+    config: &Config,
     name: &str,
     mapid: i32,
     target_zoom: i8,
@@ -56,54 +113,48 @@ fn make_tile(
     target_i: i32,
     target_j: i32,
     backfill: [u8; 4],
+    previous: &Retained,
 ) -> CacheResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    let mut base = RgbaImage::from_fn(512, 512, |_, _| Rgba(backfill));
-
-    let files = get_files(folder, name, mapid, target_zoom, target_plane, target_i, target_j);
+    let children = [(0, 0), (0, 1), (1, 0), (1, 1)].map(|(di, dj)| ((di, dj), (target_plane, (target_i << 1) + di, (target_j << 1) + dj)));
 
-    for ((di, dj), img) in files {
-        match img {
-            Ok(f) => {
-                let img = f.into_rgba8();
-                imageops::overlay(&mut base, &img, (256 * di) as i64, 256 * (1 - dj) as i64);
-            }
-            // can be missing; if so, swallow
-            Err(ImageError::IoError(ref e)) if e.kind() == io::ErrorKind::NotFound => {}
-            Err(other_error) => panic!("{other_error}"),
+    let mut decoded = Vec::new();
+    for &((di, dj), child) in children.iter().filter(|(_, child)| !previous.contains_key(child)) {
+        // can be missing; if so, swallow
+        if let Some(img) = get_file(config, name, mapid, target_zoom + 1, child)? {
+            decoded.push((di, dj, img));
         }
     }
-    let scaled = scale::resize_half(base);
+    let tiles = children
+        .iter()
+        .filter_map(|((di, dj), child)| Some((*di, *dj, previous.get(child)?)))
+        .chain(decoded.iter().map(|(di, dj, img)| (*di, *dj, img)))
+        .collect::<Vec<_>>();
+
+    // Tiles are 256 pixels wide, unless the map was rendered with another `--tile-size`.
+    let size = tiles.first().map_or(256, |(_, _, img)| img.width());
+    let mut base = RgbaImage::from_fn(2 * size, 2 * size, |_, _| Rgba(backfill));
+    for (di, dj, img) in tiles {
+        imageops::overlay(&mut base, img, (size as i32 * di) as i64, size as i64 * (1 - dj) as i64);
+    }
+    let scaled = scale::downscale(config, base, 2);
     Ok(scaled)
 }
 
-fn get_files(
-    folder: impl AsRef<Path>,
-    name: &str,
-    mapid: i32,
-    target_zoom: i8,
-    target_plane: i32,
-    target_i: i32,
-    target_j: i32,
-) -> [((i32, i32), Result<image::DynamicImage, ImageError>); 4] {
-    [(0, 0), (0, 1), (1, 0), (1, 1)].map(|(di, dj)| {
-        let i = (target_i << 1) + di;
-        let j = (target_j << 1) + dj;
-        let zoom = target_zoom + 1;
-        let filename = path!(folder / name / format!("{mapid}/{zoom}/{target_plane}_{i}_{j}.png"));
-        ((di, dj), image::open(filename))
-    })
+/// Reads the tile `plane_i_j` of `zoom`, if it exists.
+fn get_file(config: &Config, name: &str, mapid: i32, zoom: i8, (plane, i, j): (i32, i32, i32)) -> CacheResult<Option<RgbaImage>> {
+    let filename = path!(name / format!("{mapid}/{zoom}/{plane}_{i}_{j}.{}", config.tile_format.extension()));
+    png::get_tile(config, filename)
 }
 
 fn get_future_filenames(config: &Config, name: &str, mapid: i32, zoom: i8) -> CacheResult<HashSet<(i32, i32, i32)>> {
-    let path = path!(config.output / name / format!("{mapid}/{zoom}"));
+    let path = path!(name / format!("{mapid}/{zoom}"));
 
-    fs::read_dir(&path)
-        .with_context(|| error::Io { path: path.clone() })?
-        .map_ok(|entry| {
-            let name = entry.file_name();
+    let filenames = config.sink()?.list(path)?;
+    Ok(filenames
+        .iter()
+        .map(|name| {
             let (p, i, j) = to_coordinates(name);
             (p, i >> 1, j >> 1)
         })
-        .collect::<Result<_, _>>()
-        .context(error::Io { path })
+        .collect())
 }
@@ -0,0 +1,67 @@
+//! Writes JSON Schemas for dumped definitions, as in `rs3 schema item npc`.
+//!
+//! Schemas are derived from the definition types, as they are serialized for dumps,
+//! so they can be used to validate dumps or to generate bindings for them. The cache is not read.
+//! Params are described by `additionalProperties`, as their keys are ids.
+
+use clap::{Args, ValueEnum};
+use rs3cache_backend::error::CacheResult;
+
+use crate::{cli::Config, show::Kind};
+
+/// Arguments of the `schema` subcommand.
+#[derive(Args, Clone, Debug)]
+pub struct Schema {
+    /// The kinds of definition to write schemas for; all of them by default.
+    #[clap(value_enum)]
+    pub kinds: Vec<Kind>,
+}
+
+/// Entry point for `schema`. Writes the schema of each kind as `schema/<kind>.json`.
+pub fn run(config: &Config, args: &Schema) -> CacheResult<()> {
+    let kinds = if args.kinds.is_empty() {
+        Kind::value_variants()
+    } else {
+        &args.kinds[..]
+    };
+    for kind in kinds {
+        let name = kind.to_possible_value().unwrap().get_name().to_string();
+        let mut schema = kind.schema();
+        schema.schema.metadata().title = Some(name.clone());
+        crate::output::put_json(config, format!("schema/{name}.json"), &schema)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn schema(kind: Kind) -> Value {
+        serde_json::to_value(kind.schema()).unwrap()
+    }
+
+    #[test]
+    fn required_and_params() {
+        let item = schema(Kind::Item);
+        assert_eq!(item["required"], json!(["id"]));
+        assert_eq!(item["properties"]["id"]["type"], "integer");
+        assert_eq!(item["properties"]["name"]["type"], json!(["string", "null"]));
+        assert_eq!(item["properties"]["_error"]["type"], json!(["string", "null"]));
+        assert_eq!(item["properties"]["params"]["additionalProperties"]["$ref"], "#/definitions/Param");
+        assert_eq!(item["definitions"]["Param"]["anyOf"][1]["type"], "string");
+
+        let inventory = schema(Kind::Inventory);
+        assert_eq!(inventory["definitions"]["Stock"]["required"], json!(["count", "item"]));
+    }
+
+    /// Every kind has a schema, whatever is in the cache.
+    #[test]
+    fn all_kinds() {
+        for kind in Kind::value_variants() {
+            assert_eq!(schema(*kind)["type"], "object", "{kind:?}");
+        }
+    }
+}
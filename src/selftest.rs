@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+};
+
+use console::style;
+use serde::Serialize;
+
+#[cfg(feature = "rs3")]
+use crate::definitions::{
+    achievements::Achievement,
+    quickchat::{QuickChatCategory, QuickChatPhrase},
+};
+#[cfg(feature = "osrs")]
+use crate::definitions::{clientscripts::ClientScript, interfaces::Interface, midi::MusicTrack, sound_effects::SoundEffect};
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::definitions::{
+    identkits::IdentityKit, inventories::Inventory, overlays::Overlay, params::ParamConfig, sequences::Sequence, spotanims::SpotAnim,
+    underlays::Underlay, var_configs::VarConfig,
+};
+use crate::{
+    cli::Config,
+    definitions::{
+        enums::Enum, item_configs::ItemConfig, location_configs::LocationConfig, mapsquares::GroupMapSquareIterator, npc_configs::NpcConfig,
+        structs::Struct, varbit_configs::VarbitConfig,
+    },
+    renderers::map::{self, Definitions},
+};
+
+/// How many definitions of each type are serialized after decoding.
+const SAMPLE_SIZE: usize = 16;
+
+/// How many mapsquares are rendered.
+const MAPSQUARES: usize = 2;
+
+type Check = fn(&Config) -> Result<String, String>;
+
+/// Decodes every supported definition type and renders a couple of mapsquares in memory,
+/// printing whether each subsystem works with the cache at hand. Exposed as `--selftest`.
+///
+/// Returns whether all checks passed.
+pub fn run(config: &Config) -> bool {
+    let checks: &[(&str, Check)] = &[
+        #[cfg(feature = "rs3")]
+        ("achievements", |config| sample(Achievement::dump_all(config))),
+        ("location_configs", |config| sample(LocationConfig::dump_all(config))),
+        ("npc_configs", |config| sample(NpcConfig::dump_all(config))),
+        ("item_configs", |config| sample(ItemConfig::dump_all(config))),
+        ("varbit_configs", |config| sample(VarbitConfig::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("identkits", |config| sample(IdentityKit::dump_all(config))),
+        #[cfg(feature = "osrs")]
+        ("clientscripts", |config| sample(ClientScript::dump_all(config))),
+        #[cfg(feature = "osrs")]
+        ("interfaces", |config| sample(Interface::dump_all(config))),
+        #[cfg(feature = "osrs")]
+        ("music", |config| {
+            sample(MusicTrack::dump_all(config).map(|all| all.into_iter().map(|((_, id), music)| (id, music)).collect()))
+        }),
+        #[cfg(feature = "osrs")]
+        ("sound effects", |config| sample(SoundEffect::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("inventories", |config| sample(Inventory::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("varps", |config| sample(VarConfig::dump_varps(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("varcs", |config| sample(VarConfig::dump_varcs(config))),
+        #[cfg(feature = "rs3")]
+        ("quickchat categories", |config| sample(QuickChatCategory::dump_all(config))),
+        #[cfg(feature = "rs3")]
+        ("quickchat phrases", |config| sample(QuickChatPhrase::dump_all(config))),
+        ("structs", |config| sample(Struct::dump_all(config))),
+        ("enums", |config| sample(Enum::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("params", |config| sample(ParamConfig::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("sequences", |config| sample(Sequence::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("spotanims", |config| sample(SpotAnim::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("underlays", |config| sample(Underlay::dump_all(config))),
+        #[cfg(any(feature = "rs3", feature = "osrs"))]
+        ("overlays", |config| sample(Overlay::dump_all(config))),
+        ("map renderer", render),
+    ];
+
+    let mut passed = 0;
+    for (name, check) in checks {
+        // Some decoders panic on unknown opcodes; that is a failure like any other.
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| check(config))).unwrap_or_else(|payload| {
+            Err(match payload.downcast::<String>() {
+                Ok(msg) => *msg,
+                Err(payload) => payload.downcast_ref::<&str>().copied().unwrap_or("panicked").to_string(),
+            })
+        });
+
+        match outcome {
+            Ok(msg) => {
+                passed += 1;
+                println!("    {} {name}: {msg}", style("Passed").green().bright());
+            }
+            Err(msg) => println!("    {} {name}: {msg}", style("Failed").red()),
+        }
+    }
+
+    println!("\n{passed}/{} checks passed.", checks.len());
+    passed == checks.len()
+}
+
+fn sample<T: Serialize>(defs: rs3cache_backend::error::CacheResult<BTreeMap<u32, T>>) -> Result<String, String> {
+    let defs = defs.map_err(|e| e.to_string())?;
+    if defs.is_empty() {
+        return Err("there are no definitions".to_string());
+    }
+
+    let step = (defs.len() / SAMPLE_SIZE).max(1);
+    for (id, def) in defs.iter().step_by(step) {
+        serde_json::to_string(def).map_err(|e| format!("unable to serialize {id}: {e}"))?;
+    }
+    Ok(format!("decoded {}", defs.len()))
+}
+
+fn render(config: &Config) -> Result<String, String> {
+    let definitions = Definitions::load(config).map_err(|e| e.to_string())?;
+    let squares = GroupMapSquareIterator::new(-1_i32..=1_i32, -1_i32..=1_i32, config).map_err(|e| e.to_string())?;
+
+    let mut rendered = Vec::new();
+    for gsq in squares.take(MAPSQUARES) {
+        let planes = map::render_planes(&config.render_config, &gsq, &definitions);
+        let empty = planes.iter().all(|img| img.pixels().all(|pixel| pixel[3] == 0));
+        rendered.push(((gsq.core_i(), gsq.core_j()), empty));
+    }
+
+    match rendered.as_slice() {
+        [] => Err("there are no mapsquares".to_string()),
+        squares if squares.iter().all(|(_, empty)| *empty) => Err(format!("{squares:?} all rendered as empty")),
+        squares => Ok(format!("rendered {} mapsquares", squares.len())),
+    }
+}
@@ -0,0 +1,283 @@
+//! Serves rendered tiles over HTTP, as in `rs3 serve`, with a [viewer](crate::renderers::viewer) at `/`.
+//!
+//! Files are read from the output folder (or bucket, or MBTiles files) as they are requested,
+//! so a render can be watched as it progresses. With `--lazy`, mapsquare tiles that do not exist yet
+//! are rendered from the cache when they are first requested. Only the zoom levels that are cut from single mapsquares
+//! are rendered this way; the lower zoom levels need a full `--render map`.
+//!
+//! This is meant for looking at renders locally, not for hosting them.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{IpAddr, Ipv4Addr, TcpListener, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    path::{Component, Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use ::error::Context;
+use clap::Args;
+use console::style;
+use path_macro::path;
+use rs3cache_backend::error::{self, CacheResult};
+
+use crate::{
+    cancel,
+    cli::Config,
+    definitions::mapsquares::GroupMapSquareIterator,
+    output::naming::{Tile, TileNaming},
+    renderers::{
+        map::{self, Definitions},
+        viewer,
+    },
+};
+
+/// The layer that the map renderer writes to.
+const LAYER: &str = "mapsquares";
+
+/// How many connections are answered at once. Further connections wait for one of them to finish.
+const WORKERS: usize = 8;
+
+/// How long to wait for a connection before checking whether ctrl-C was pressed.
+const POLL: Duration = Duration::from_millis(100);
+
+/// Arguments of the `serve` subcommand.
+#[derive(Args, Clone, Debug)]
+pub struct Serve {
+    /// The port to listen on.
+    #[clap(long, default_value_t = 8080)]
+    pub port: u16,
+
+    /// The address to listen on. Use `0.0.0.0` to make the server reachable from other machines.
+    #[clap(long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    pub bind: IpAddr,
+
+    /// Renders missing mapsquare tiles from the cache when they are requested.
+    #[clap(long)]
+    pub lazy: bool,
+}
+
+/// Renders mapsquares on demand, for `--lazy`.
+struct Lazy {
+    definitions: Definitions,
+    /// Each mapsquare that was requested, with whether it was attempted: rendered, or found to have nothing to render.
+    squares: Mutex<HashMap<(u8, u8), Arc<Mutex<bool>>>>,
+}
+
+/// Locks `mutex`, even if a thread panicked while holding it. Nothing it guards is left half updated.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Lazy {
+    /// Renders the mapsquare containing `tile`, if that is a mapsquare tile that was not attempted yet.
+    ///
+    /// Returns whether it rendered something.
+    fn render(&self, config: &Config, tile: &Tile) -> CacheResult<bool> {
+        let render = &config.render_config;
+        if tile.map_id != render.map_id || !(2..=render.initial_zoom).contains(&tile.zoom) {
+            return Ok(false);
+        }
+        let shift = tile.zoom - 2;
+        let (Ok(i), Ok(j)) = (u8::try_from(tile.x >> shift), u8::try_from(tile.y >> shift)) else { return Ok(false) };
+
+        // Only requests for tiles of the same mapsquare wait here, as they would render it again.
+        let square = Arc::clone(lock(&self.squares).entry((i, j)).or_default());
+        let mut attempted = lock(&square);
+        if *attempted {
+            return Ok(false);
+        }
+        // A render that fails or panics is not tried again.
+        *attempted = true;
+        let mut iter = GroupMapSquareIterator::new_only(-1_i32..=1_i32, -1_i32..=1_i32, vec![(i, j)], config)?;
+        let Some(group) = iter.next() else { return Ok(false) };
+        map::render_tile(config, render, LAYER, group, &self.definitions);
+        Ok(true)
+    }
+}
+
+/// Parses the path of a tile of the map renderer's layer, as named by `naming`.
+fn parse_tile(relative: &Path, naming: &TileNaming) -> Option<Tile> {
+    naming.parse(relative.strip_prefix(LAYER).ok()?)
+}
+
+/// The renderer's name of `tile`, which is what the sink takes.
+fn tile_path(tile: &Tile, extension: &str) -> PathBuf {
+    let Tile { map_id, zoom, plane, x, y } = tile;
+    path!(LAYER / format!("{map_id}/{zoom}/{plane}_{x}_{y}.{extension}"))
+}
+
+/// The path requested by `target`, relative to the output folder, or `None` if it tries to leave it.
+fn relative_path(target: &str) -> Option<&Path> {
+    let path = Path::new(target.split(['?', '#']).next()?.trim_start_matches('/'));
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then_some(path)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("json") => "application/json",
+        Some("html") => "text/html; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// Answers a single request.
+fn handle(config: &Config, lazy: Option<&Lazy>, mut stream: TcpStream) -> CacheResult<()> {
+    let peer_path = stream
+        .peer_addr()
+        .map_or_else(|_| PathBuf::from("<connection>"), |peer| PathBuf::from(peer.to_string()));
+    let peer = || error::Io { path: peer_path.clone() };
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut request_line).with_context(peer)?;
+    // The headers are not needed, but are read so that the client does not see the connection reset.
+    let mut header = String::new();
+    while reader.read_line(&mut header).with_context(peer)? > 2 {
+        header.clear();
+    }
+
+    let naming = config.tile_naming();
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/" | "/index.html")) => {
+            let page = viewer::page(LAYER, &config.render_config, &naming);
+            respond(&mut stream, "200 OK", content_type(Path::new("index.html")), page.as_bytes())
+        }
+        (Some("GET"), Some(target)) => match relative_path(target) {
+            Some(relative) => {
+                // Tiles are asked for by their names in the output, which may not be the renderer's.
+                let tile = parse_tile(relative, &naming);
+                let key = tile.map_or_else(|| relative.to_path_buf(), |tile| tile_path(&tile, naming.extension));
+                let sink = config.sink()?;
+                let mut data = sink.get(&key)?;
+                if let (None, Some(lazy), Some(tile)) = (&data, lazy, &tile) {
+                    if lazy.render(config, tile)? {
+                        data = sink.get(&key)?;
+                    }
+                }
+                match data {
+                    Some(data) => respond(&mut stream, "200 OK", content_type(relative), &data),
+                    None => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+                }
+            }
+            None => respond(&mut stream, "400 Bad Request", "text/plain", b"bad path"),
+        },
+        _ => respond(&mut stream, "405 Method Not Allowed", "text/plain", b"only GET is supported"),
+    };
+    response.with_context(peer)
+}
+
+/// Entry point for `serve`.
+pub fn run(config: &Config, serve: &Serve) -> CacheResult<()> {
+    let lazy = if serve.lazy {
+        Some(Lazy {
+            definitions: Definitions::load(config)?,
+            squares: Mutex::new(HashMap::new()),
+        })
+    } else {
+        None
+    };
+
+    let address = (serve.bind, serve.port);
+    let address_path = || error::Io {
+        path: format!("{}:{}", serve.bind, serve.port).into(),
+    };
+    let listener = TcpListener::bind(address).with_context(address_path)?;
+    // So that ctrl-C is noticed while no one is connecting.
+    listener.set_nonblocking(true).with_context(address_path)?;
+    println!(
+        "    {} on http://{}:{}/, press ctrl-C to stop",
+        style("Serving").green().bright(),
+        serve.bind,
+        serve.port
+    );
+
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(WORKERS);
+    let receiver = Mutex::new(receiver);
+    thread::scope(|s| {
+        for _ in 0..WORKERS {
+            s.spawn(|| loop {
+                let Ok(stream) = lock(&receiver).recv() else { break };
+                // A panicking render fails only its own request.
+                match panic::catch_unwind(AssertUnwindSafe(|| handle(config, lazy.as_ref(), stream))) {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => eprintln!("    {} {e}", style("Error").red()),
+                    Err(_) => eprintln!("    {} a request panicked", style("Error").red()),
+                }
+            });
+        }
+        // The first ctrl-C stops accepting connections, and the requests that were accepted are still answered.
+        while !cancel::is_cancelled() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(POLL);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            // Connections can inherit the listener's non-blocking mode.
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+            if sender.send(stream).is_err() {
+                break;
+            }
+        }
+        drop(sender);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            relative_path("/mapsquares/-1/2/0_50_50.png?v=1"),
+            Some(Path::new("mapsquares/-1/2/0_50_50.png"))
+        );
+        assert_eq!(relative_path("/../secrets"), None);
+        assert_eq!(relative_path("/mapsquares/../../secrets"), None);
+
+        let naming = Config::default().tile_naming();
+        let path = Path::new("mapsquares/-1/3/0_101_100.png");
+        let tile = parse_tile(path, &naming).unwrap();
+        assert_eq!((tile.zoom, tile.x, tile.y), (3, 101, 100));
+        assert_eq!(tile_path(&tile, "png"), path);
+        assert_eq!(parse_tile(Path::new("mapsquares/-1/manifest.json"), &naming), None);
+    }
+
+    #[test]
+    fn poisoned() {
+        let mutex = Mutex::new(1);
+        let panicked = thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = mutex.lock().unwrap();
+                panic!("while holding the lock");
+            })
+            .join()
+        });
+        assert!(panicked.is_err() && mutex.is_poisoned());
+        assert_eq!(*lock(&mutex), 1);
+    }
+}
@@ -0,0 +1,359 @@
+//! Prints a single definition, as in `rs3 show item 4151`.
+//!
+//! Params are typed (and named, if `--param-names` is passed) as in `--resolve-params`.
+//! With `--raw`, the undecoded file is printed as a hexdump instead; this also works for definitions that fail to decode.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write as _},
+    process::{Command, Stdio},
+};
+
+use ::error::Context;
+use bytes::Bytes;
+use clap::{Args, ValueEnum};
+use console::{style, Term};
+use rs3cache_backend::{
+    buf::ReadError,
+    error::{self, CacheResult},
+    index::{self, CacheIndex},
+};
+use schemars::{schema::RootSchema, schema_for};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    cli::Config,
+    definitions::{
+        enums::Enum,
+        indextype::{ConfigType, IndexType},
+        inventories::Inventory,
+        item_configs::ItemConfig,
+        location_configs::LocationConfig,
+        npc_configs::NpcConfig,
+        params::{ParamConfig, ParamResolver},
+        sequences::Sequence,
+        spotanims::SpotAnim,
+        structs::Struct,
+        var_configs::VarConfig,
+        varbit_configs::VarbitConfig,
+    },
+};
+
+/// Arguments of the `show` subcommand.
+#[derive(Args, Clone, Debug)]
+pub struct Show {
+    /// What kind of definition to show.
+    #[clap(value_enum)]
+    pub kind: Kind,
+
+    /// Its id.
+    pub id: u32,
+
+    /// Prints the file as a hexdump, without decoding it.
+    #[clap(long)]
+    pub raw: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Item,
+    Npc,
+    Loc,
+    Struct,
+    Enum,
+    Varbit,
+    Varp,
+    Varc,
+    Param,
+    Inventory,
+    Sequence,
+    Spotanim,
+}
+
+impl Kind {
+    /// The index, archive and file that the definition with this id is stored in.
+    fn location(self, id: u32) -> (u32, u32, u32) {
+        match self {
+            Kind::Item => (IndexType::OBJ_CONFIG, id >> 8, id & 0xFF),
+            Kind::Struct => (IndexType::STRUCT_CONFIG, id >> 5, id & 0x1F),
+            Kind::Varbit => (IndexType::CONFIG, ConfigType::VARBITS, id),
+            Kind::Varp => (IndexType::CONFIG, ConfigType::PLAYER, id),
+            Kind::Varc => (IndexType::CONFIG, ConfigType::CLIENT, id),
+            Kind::Param => (IndexType::CONFIG, ConfigType::PARAMS, id),
+            Kind::Inventory => (IndexType::CONFIG, ConfigType::INVENTORY, id),
+            #[cfg(feature = "rs3")]
+            Kind::Npc => (IndexType::NPC_CONFIG, id >> 7, id & 0x7F),
+            #[cfg(feature = "rs3")]
+            Kind::Loc => (IndexType::LOC_CONFIG, id >> 8, id & 0xFF),
+            #[cfg(feature = "rs3")]
+            Kind::Enum => (IndexType::ENUM_CONFIG, id >> 8, id & 0xFF),
+            #[cfg(feature = "rs3")]
+            Kind::Sequence => (IndexType::SEQ_CONFIG, id >> 7, id & 0x7F),
+            #[cfg(feature = "rs3")]
+            Kind::Spotanim => (IndexType::SPOT_CONFIG, id >> 8, id & 0xFF),
+            #[cfg(feature = "osrs")]
+            Kind::Npc => (IndexType::CONFIG, ConfigType::NPC_CONFIG, id),
+            #[cfg(feature = "osrs")]
+            Kind::Loc => (IndexType::CONFIG, ConfigType::LOC_CONFIG, id),
+            #[cfg(feature = "osrs")]
+            Kind::Enum => (IndexType::CONFIG, ConfigType::ENUM_CONFIG, id),
+            #[cfg(feature = "osrs")]
+            Kind::Sequence => (IndexType::CONFIG, ConfigType::SEQUENCE, id),
+            #[cfg(feature = "osrs")]
+            Kind::Spotanim => (IndexType::CONFIG, ConfigType::SPOTANIM, id),
+        }
+    }
+
+    /// What definitions of this kind are called in errors.
+    const fn what(self) -> &'static str {
+        match self {
+            Kind::Item => "item configs",
+            Kind::Npc => "npc configs",
+            Kind::Loc => "location configs",
+            Kind::Struct => "structs",
+            Kind::Enum => "enums",
+            Kind::Varbit => "varbits",
+            Kind::Varp => "varps",
+            Kind::Varc => "varcs",
+            Kind::Param => "params",
+            Kind::Inventory => "inventories",
+            Kind::Sequence => "sequences",
+            Kind::Spotanim => "spotanims",
+        }
+    }
+
+    /// The kind of the references of [`TypedParam::Reference`](crate::structures::paramtable::TypedParam::Reference)
+    /// that can be shown, if any.
+    fn of_reference(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "item" => Kind::Item,
+            "npc" => Kind::Npc,
+            "location" => Kind::Loc,
+            "struct" => Kind::Struct,
+            "enum" => Kind::Enum,
+            "inventory" => Kind::Inventory,
+            "sequence" => Kind::Sequence,
+            "spotanim" => Kind::Spotanim,
+            _ => return None,
+        })
+    }
+
+    /// Reads the file of the definition with this id, if there is one. Only its archive is read.
+    fn file(self, config: &Config, id: u32) -> CacheResult<Option<Bytes>> {
+        let (index_id, archive_id, file_id) = self.location(id);
+        let index = CacheIndex::new(index_id, config.input.clone())?;
+        if index.metadatas().get(&archive_id).is_none() {
+            return Ok(None);
+        }
+        Ok(index.archive(archive_id)?.file(&file_id))
+    }
+
+    /// Decodes the definition with this id, and nothing else.
+    fn decode(self, config: &Config, id: u32) -> CacheResult<Option<Value>> {
+        let Some(file) = self.file(config, id)? else { return Ok(None) };
+        let (revision, recovering) = (config.revision(), config.recover);
+        let value: Result<Value, ReadError> = try {
+            match self {
+                Kind::Item => json(ItemConfig::deserialize(id, file, recovering)?),
+                Kind::Npc => json(NpcConfig::deserialize(id, file, revision, recovering)?),
+                Kind::Loc => json(LocationConfig::deserialize(id, file, revision, recovering)?),
+                Kind::Struct => json(Struct::deserialize(id, file, recovering)?),
+                Kind::Enum => json(Enum::deserialize(id, file, recovering)?),
+                Kind::Varbit => json(VarbitConfig::deserialize(id, file, recovering)?),
+                Kind::Varp | Kind::Varc => json(VarConfig::deserialize(id, file, recovering)?),
+                Kind::Param => json(ParamConfig::deserialize(id, file, recovering)?),
+                Kind::Inventory => json(Inventory::deserialize(id, file, recovering)?),
+                Kind::Sequence => json(Sequence::deserialize(id, file, recovering)?),
+                Kind::Spotanim => json(SpotAnim::deserialize(id, file, recovering)?),
+            }
+        };
+        value.map(Some).context(error::Read { what: self.what() })
+    }
+
+    /// The JSON Schema of definitions of this kind, as they are serialized for dumps.
+    pub(crate) fn schema(self) -> RootSchema {
+        match self {
+            Kind::Item => schema_for!(ItemConfig),
+            Kind::Npc => schema_for!(NpcConfig),
+            Kind::Loc => schema_for!(LocationConfig),
+            Kind::Struct => schema_for!(Struct),
+            Kind::Enum => schema_for!(Enum),
+            Kind::Varbit => schema_for!(VarbitConfig),
+            Kind::Varp | Kind::Varc => schema_for!(VarConfig),
+            Kind::Param => schema_for!(ParamConfig),
+            Kind::Inventory => schema_for!(Inventory),
+            Kind::Sequence => schema_for!(Sequence),
+            Kind::Spotanim => schema_for!(SpotAnim),
+        }
+    }
+}
+
+fn json<T: Serialize>(def: T) -> Value {
+    serde_json::to_value(def).unwrap()
+}
+
+/// Entry point for `show`.
+pub fn run(config: &Config, show: &Show) -> CacheResult<()> {
+    let text = if show.raw {
+        let (index_id, archive_id, file_id) = show.kind.location(show.id);
+        let file = CacheIndex::new(index_id, config.input.clone())?
+            .archive(archive_id)?
+            .file(&file_id)
+            .context(index::FileMissing {
+                index_id,
+                archive_id,
+                file: file_id,
+            })
+            .context(error::Integrity)?;
+        hexdump(&file)
+    } else {
+        let Some(mut value) = show.kind.decode(config, show.id)? else {
+            println!("    {} there is no {:?} {}", style("Error").red(), show.kind, show.id);
+            return Ok(());
+        };
+        ParamResolver::load(config)?.resolve(&mut value);
+        name_references(config, &mut value)?;
+
+        let mut text = String::new();
+        colour(&mut text, &value, 0);
+        text
+    };
+
+    page(&text);
+    Ok(())
+}
+
+/// The definition that `value` refers to, if it is a reference as params are resolved to, like `{"item": 995}`.
+fn reference(value: &Value) -> Option<(Kind, u32)> {
+    let Value::Object(map) = value else { return None };
+    if map.len() != 1 {
+        return None;
+    }
+    let (kind, id) = map.iter().next()?;
+    Some((Kind::of_reference(kind)?, u32::try_from(id.as_i64()?).ok()?))
+}
+
+/// Adds the name of every definition that `value` refers to, as in `{"item": 995, "name": "Coins"}`.
+/// The references of those definitions are not followed.
+fn name_references(config: &Config, value: &mut Value) -> CacheResult<()> {
+    if let Some((kind, id)) = reference(value) {
+        if let Some(name) = kind.decode(config, id)?.and_then(|mut def| def.get_mut("name").map(Value::take)) {
+            value["name"] = name;
+        }
+        return Ok(());
+    }
+    match value {
+        Value::Array(items) => items.iter_mut().try_for_each(|item| name_references(config, item)),
+        Value::Object(map) => map.values_mut().try_for_each(|item| name_references(config, item)),
+        _ => Ok(()),
+    }
+}
+
+/// Pretty prints `value` like [`serde_json::to_string_pretty`], but with colours.
+pub(crate) fn colour(out: &mut String, value: &Value, depth: usize) {
+    let indent = "  ".repeat(depth + 1);
+    let outdent = "  ".repeat(depth);
+    match value {
+        Value::Null => write!(out, "{}", style("null").magenta()).unwrap(),
+        Value::Bool(b) => write!(out, "{}", style(b).magenta()).unwrap(),
+        Value::Number(n) => write!(out, "{}", style(n).yellow()).unwrap(),
+        Value::String(s) => write!(out, "{}", style(Value::String(s.clone())).green()).unwrap(),
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&indent);
+                colour(out, item, depth + 1);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            write!(out, "{outdent}]").unwrap();
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            out.push_str("{\n");
+            for (i, (key, item)) in map.iter().enumerate() {
+                write!(out, "{indent}{}: ", style(Value::String(key.clone())).cyan()).unwrap();
+                colour(out, item, depth + 1);
+                out.push_str(if i + 1 < map.len() { ",\n" } else { "\n" });
+            }
+            write!(out, "{outdent}}}").unwrap();
+        }
+    }
+}
+
+/// Formats `data` as lines of an offset, sixteen bytes and their ascii representation.
+fn hexdump(data: &Bytes) -> String {
+    data.chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex = chunk.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect::<String>();
+            format!("{:08x}  {hex:<47}  {ascii}", line * 16)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints `text`, through `$PAGER` (or `less`) if it does not fit on the terminal.
+pub(crate) fn page(text: &str) {
+    let term = Term::stdout();
+    let fits = match term.size_checked() {
+        Some((rows, _)) => text.lines().count() < rows as usize,
+        None => true,
+    };
+
+    if term.is_term() && !fits {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+        let mut args = pager.split_whitespace();
+        if let Some(program) = args.next() {
+            let child = Command::new(program).args(args).stdin(Stdio::piped()).spawn();
+            if let Ok(mut child) = child {
+                // The pager closing early is not an error.
+                let _ = child.stdin.take().unwrap().write_all(text.as_bytes());
+                let _ = child.wait();
+                return;
+            }
+        }
+    }
+
+    let _ = writeln!(io::stdout(), "{text}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hexdump_lines() {
+        let data = Bytes::from_static(b"\x01\x02abcdefghijklmnopq");
+        let dump = hexdump(&data);
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("00000000  01 02 61"));
+        assert!(lines[0].ends_with("..abcdefghijklmn"));
+        assert!(lines[1].starts_with("00000010  6f 70 71"));
+    }
+
+    #[test]
+    fn colour_is_json() {
+        console::set_colors_enabled(false);
+        let value = serde_json::json!({"id": 4151, "name": "Abyssal whip", "params": {"a": [1, null]}});
+        let mut text = String::new();
+        colour(&mut text, &value, 0);
+        assert_eq!(text, serde_json::to_string_pretty(&value).unwrap());
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(reference(&serde_json::json!({"item": 995})), Some((Kind::Item, 995)));
+        assert_eq!(reference(&serde_json::json!({"location": 1})), Some((Kind::Loc, 1)));
+        // Models have no definition to show, and objects with more keys are definitions themselves.
+        assert_eq!(reference(&serde_json::json!({"model": 5})), None);
+        assert_eq!(reference(&serde_json::json!({"item": 995, "name": "Coins"})), None);
+        assert_eq!(reference(&serde_json::json!(995)), None);
+    }
+}
@@ -1,17 +1,22 @@
 use std::{collections::BTreeMap, iter};
 
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 #[cfg(feature = "pyo3")]
 use pyo3::{exceptions::PyKeyError, prelude::*};
-use rs3cache_backend::buf::{BufExtra, JString};
-use serde::Serialize;
+use rs3cache_backend::buf::{BufExtra, BufMutExtra, JString};
+use schemars::JsonSchema;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+use crate::definitions::params::ParamConfig;
+use crate::types::coordinate::Coordinate;
 
 /// [`LocationConfig`](crate::definitions::location_configs::LocationConfig)s,
 /// items and
 /// [`NpcConfig`](crate::definitions::npc_configs::NpcConfig)s can have additional mapping of keys to properties.
 
 #[cfg_attr(feature = "pyo3", pyclass(frozen, get_all))]
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, JsonSchema, Debug, Clone)]
 pub struct ParamTable {
     /// Key:Value pairs of additional properties.
     pub params: BTreeMap<u32, Param>,
@@ -37,6 +42,42 @@ impl ParamTable {
         };
         (key, value)
     }
+
+    /// Writes `self` as [`deserialize`](ParamTable::deserialize) reads it.
+    pub fn encode(&self, buffer: &mut BytesMut) {
+        buffer.put_u8(self.params.len() as u8);
+        for (key, value) in &self.params {
+            match value {
+                Param::Integer(value) => {
+                    buffer.put_u8(0);
+                    buffer.put_uint(*key as u64, 3);
+                    buffer.put_i32(*value);
+                }
+                Param::String(value) => {
+                    buffer.put_u8(1);
+                    buffer.put_uint(*key as u64, 3);
+                    buffer.put_string(value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "rs3", feature = "osrs"))]
+impl ParamTable {
+    /// Interprets every value according to the [`ParamConfig`] of its key.
+    ///
+    /// Keys are named by `names` if present there, and by their id otherwise.
+    pub fn resolve(&self, configs: &BTreeMap<u32, ParamConfig>, names: &BTreeMap<u32, String>) -> BTreeMap<String, TypedParam> {
+        self.params
+            .iter()
+            .map(|(key, value)| {
+                let name = names.get(key).cloned().unwrap_or_else(|| key.to_string());
+                let type_char = configs.get(key).and_then(|config| config.type_char);
+                (name, TypedParam::new(value, type_char))
+            })
+            .collect()
+    }
 }
 
 #[cfg(feature = "pyo3")]
@@ -52,7 +93,7 @@ impl ParamTable {
 }
 
 /// An additional key:property mapping.
-#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+#[derive(Serialize, JsonSchema, Debug, Eq, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum Param {
     /// The integer variant.
@@ -80,3 +121,86 @@ impl IntoPy<PyObject> for &Param {
         }
     }
 }
+
+/// A [`Param`] interpreted according to the type of its key.
+#[derive(Debug, Clone)]
+pub enum TypedParam {
+    Integer(i32),
+    String(JString<Bytes>),
+    Boolean(bool),
+    Coordinate(Coordinate),
+    /// The id of another definition, like an item or an enum.
+    Reference {
+        kind: &'static str,
+        id: i32,
+    },
+}
+
+impl TypedParam {
+    /// Interprets `param` as the script type `type_char`. Values that do not fit their type are left as they are.
+    pub fn new(param: &Param, type_char: Option<char>) -> Self {
+        let value = match param {
+            Param::Integer(value) => *value,
+            Param::String(value) => return Self::String(value.clone()),
+        };
+
+        let kind = match type_char {
+            Some('1') if value == 0 || value == 1 => return Self::Boolean(value == 1),
+            Some('c') => match Coordinate::try_from(value as u32) {
+                Ok(coordinate) => return Self::Coordinate(coordinate),
+                Err(_) => return Self::Integer(value),
+            },
+            Some('A') => "sequence",
+            Some('d') => "graphic",
+            Some('g') => "enum",
+            Some('J') => "struct",
+            Some('l') => "location",
+            Some('m') => "model",
+            Some('n') => "npc",
+            Some('o' | 'O') => "item",
+            Some('t') => "spotanim",
+            Some('v') => "inventory",
+            Some('S') => "stat",
+            _ => return Self::Integer(value),
+        };
+        // -1 is used for "nothing", regardless of type
+        if value == -1 {
+            Self::Integer(value)
+        } else {
+            Self::Reference { kind, id: value }
+        }
+    }
+}
+
+impl Serialize for TypedParam {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Integer(value) => value.serialize(serializer),
+            Self::String(value) => value.serialize(serializer),
+            Self::Boolean(value) => value.serialize(serializer),
+            Self::Coordinate(value) => value.serialize(serializer),
+            Self::Reference { kind, id } => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(kind, id)?;
+                map.end()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed() {
+        let json = |param, ty| serde_json::to_string(&TypedParam::new(&Param::Integer(param), ty)).unwrap();
+
+        assert_eq!(json(995, Some('o')), r#"{"item":995}"#);
+        assert_eq!(json(-1, Some('o')), "-1");
+        assert_eq!(json(1, Some('1')), "true");
+        assert_eq!(json(5, Some('1')), "5");
+        assert_eq!(json(5, None), "5");
+        assert_eq!(json((3200 << 14) | 3200, Some('c')), r#"{"plane":0,"x":3200,"y":3200}"#);
+    }
+}
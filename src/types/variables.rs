@@ -1,10 +1,12 @@
+use bytes::BufMut;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
+use schemars::JsonSchema;
 use serde::Serialize;
 
 /// A bitmapping of a [`Varp`]
 #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-#[derive(Serialize, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Serialize, JsonSchema, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Varbit {
     ///The value of the `Varbit`. Cannot be `Some(u16::MAX)`.
     pub val: Option<u16>,
@@ -37,7 +39,7 @@ impl Varbit {
 
 /// A player variable
 #[cfg_attr(feature = "pyo3", pyclass(frozen))]
-#[derive(Serialize, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Serialize, JsonSchema, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Varp {
     ///The value of the `Varp`. Cannot be `Some(u16::MAX)`.
     pub val: Option<u16>,
@@ -69,7 +71,7 @@ impl Varp {
 }
 
 /// A variable containing either a Varp or Varbit.
-#[derive(Serialize, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Serialize, JsonSchema, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub enum VarpOrVarbit {
     /// See [`Varbit`].
     #[serde(rename = "varbit")]
@@ -91,6 +93,16 @@ impl VarpOrVarbit {
             other => panic!("Invalid variable pattern {other:?}."),
         }
     }
+
+    /// Writes `self` as a varbit followed by a varp, the inverse of [`new`](VarpOrVarbit::new).
+    pub fn encode(&self, buffer: &mut impl BufMut) {
+        let (varbit, varp) = match *self {
+            Self::Varbit(id) => (id, u16::MAX),
+            Self::Varp(id) => (u16::MAX, id),
+        };
+        buffer.put_u16(varbit);
+        buffer.put_u16(varp);
+    }
 }
 
 #[cfg(feature = "pyo3")]
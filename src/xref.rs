@@ -0,0 +1,208 @@
+//! Finds which definitions refer to which others, so that questions like "what uses struct 1234?"
+//! can be answered without joining dumps by hand. Exposed as `--dump xref`.
+//!
+//! Definitions are named as `<kind>:<id>`, with the kinds of [`TypedParam::Reference`].
+//! References come from params (typed by their [`ParamConfig`]), from enums whose values are references,
+//! and from the fields of items, locations, npcs and map labels that hold ids.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rs3cache_backend::error::CacheResult;
+use serde::Serialize;
+
+use crate::{
+    cli::Config,
+    definitions::{
+        enums::{Enum, Value},
+        item_configs::ItemConfig,
+        location_configs::LocationConfig,
+        maplabel_configs::{self, MapLabelConfig},
+        npc_configs::NpcConfig,
+        params::ParamConfig,
+        structs::Struct,
+    },
+    structures::paramtable::{ParamTable, TypedParam},
+};
+
+/// A definition, as `<kind>:<id>`.
+pub type Node = String;
+
+fn node(kind: &str, id: impl std::fmt::Display) -> Node {
+    format!("{kind}:{id}")
+}
+
+/// The references between definitions.
+#[derive(Clone, Debug, Default)]
+pub struct References {
+    uses: BTreeMap<Node, BTreeSet<Node>>,
+}
+
+/// What a definition refers to, and what refers to it.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub uses: BTreeSet<Node>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub used_by: BTreeSet<Node>,
+}
+
+impl References {
+    /// Records that `from` refers to `to`. Ids of -1 mean "nothing" and are left out.
+    pub fn add(&mut self, from: Node, kind: &str, id: i64) {
+        if id != -1 {
+            self.uses.entry(from).or_default().insert(node(kind, id));
+        }
+    }
+
+    fn add_params(&mut self, from: &Node, params: Option<&ParamTable>, configs: &BTreeMap<u32, ParamConfig>) {
+        for (key, value) in params.into_iter().flat_map(|table| &table.params) {
+            let type_char = configs.get(key).and_then(|config| config.type_char);
+            if let TypedParam::Reference { kind, id } = TypedParam::new(value, type_char) {
+                self.add(from.clone(), kind, id as i64);
+            }
+        }
+    }
+
+    /// Decodes every definition type that can refer to others, and collects their references.
+    pub fn collect(config: &Config) -> CacheResult<Self> {
+        let params = ParamConfig::dump_all(config)?;
+        let mut references = Self::default();
+
+        for (id, item) in ItemConfig::dump_all(config)? {
+            let from = node("item", id);
+            references.add_params(&from, item.params.as_ref(), &params);
+            let models = [item.base_model]
+                .into_iter()
+                .chain(item.male_models.into_iter().flatten())
+                .chain(item.female_models.into_iter().flatten())
+                .chain(item.male_head_models.into_iter().flatten())
+                .chain(item.female_head_models.into_iter().flatten())
+                .flatten();
+            for model in models {
+                references.add(from.clone(), "model", model as i64);
+            }
+            for template in [item.note_template, item.loan_template, item.bind_template].into_iter().flatten() {
+                references.add(from.clone(), "item", template as i64);
+            }
+        }
+
+        for (id, loc) in LocationConfig::dump_all(config)? {
+            let from = node("location", id);
+            references.add_params(&from, loc.params.as_ref(), &params);
+            for model in location_models(&loc) {
+                references.add(from.clone(), "model", model as i64);
+            }
+            if let Some(mapscene) = loc.mapscene {
+                references.add(from.clone(), "mapscene", mapscene as i64);
+            }
+            if let Some(label) = maplabel_configs::placed_by(&loc) {
+                references.add(from.clone(), "maplabel", label as i64);
+            }
+        }
+
+        for (id, npc) in NpcConfig::dump_all(config)? {
+            let from = node("npc", id);
+            references.add_params(&from, npc.params.as_ref(), &params);
+            for model in npc.models.iter().flat_map(|models| &models.models).flatten() {
+                references.add(from.clone(), "model", *model as i64);
+            }
+        }
+
+        for (id, r#struct) in Struct::dump_all(config)? {
+            references.add_params(&node("struct", id), r#struct.params.as_ref(), &params);
+        }
+
+        for (id, r#enum) in Enum::dump_all(config)? {
+            let Some(kind) = r#enum.value_kind() else { continue };
+            for value in r#enum.variants.values().chain(&r#enum.default) {
+                if let Value::Integer(value) = value {
+                    references.add(node("enum", id), kind, *value as i64);
+                }
+            }
+        }
+
+        for (id, label) in MapLabelConfig::dump_all(config)? {
+            for sprite in label.sprites() {
+                references.add(node("maplabel", id), "graphic", sprite as i64);
+            }
+        }
+
+        Ok(references)
+    }
+
+    /// Every definition that refers to or is referred to by another, with its references in both directions.
+    pub fn entries(&self) -> BTreeMap<&str, Entry> {
+        let mut entries: BTreeMap<&str, Entry> = BTreeMap::new();
+        for (from, uses) in &self.uses {
+            for to in uses {
+                entries.entry(to.as_str()).or_default().used_by.insert(from.clone());
+            }
+            entries.entry(from.as_str()).or_default().uses = uses.clone();
+        }
+        entries
+    }
+}
+
+/// The models of a location, of every shape.
+fn location_models(loc: &LocationConfig) -> Vec<u32> {
+    let mut models = Vec::new();
+    #[cfg(any(feature = "rs3", feature = "2008_3_shim"))]
+    models.extend(loc.models.iter().flat_map(|models| models.models.values()).flatten().flatten());
+    #[cfg(feature = "2008_3_shim")]
+    models.extend(loc.models_2.iter().flat_map(|models| &models.models_2).map(|&(_, model)| model));
+    #[cfg(all(feature = "osrs", not(feature = "2008_3_shim")))]
+    {
+        models.extend(loc.models.iter().flat_map(|models| &models.models).map(|(_, model)| *model as u32));
+        models.extend(loc.models_2.iter().flat_map(|models| &models.models_2).map(|model| *model as u32));
+    }
+    models
+}
+
+/// An [`Entry`] together with the definition it is of, as it is exported.
+#[derive(Serialize, Clone, Debug)]
+struct Row<'a> {
+    node: &'a str,
+    #[serde(flatten)]
+    entry: Entry,
+}
+
+/// Saves the references between all definitions as `xref.json`, in the order of their names. Exposed as `--dump xref`.
+pub fn export(config: &Config) -> CacheResult<()> {
+    let references = References::collect(config)?;
+    let rows = references
+        .entries()
+        .into_iter()
+        .map(|(node, entry)| Row { node, entry })
+        .collect::<Vec<_>>();
+    crate::output::export(config, "xref", &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_directions() {
+        let mut references = References::default();
+        references.add(node("item", 1), "struct", 1234);
+        references.add(node("enum", 5), "struct", 1234);
+        references.add(node("item", 1), "model", -1);
+
+        let entries = references.entries();
+        assert_eq!(entries["struct:1234"].used_by.iter().collect::<Vec<_>>(), ["enum:5", "item:1"]);
+        assert_eq!(entries["item:1"].uses.iter().collect::<Vec<_>>(), ["struct:1234"]);
+        assert!(!entries.contains_key("model:-1"));
+    }
+
+    #[test]
+    fn rows() {
+        let mut references = References::default();
+        references.add(node("item", 1), "struct", 1234);
+        let entries = references.entries();
+        let row = Row {
+            node: "item:1",
+            entry: entries["item:1"].clone(),
+        };
+        assert_eq!(serde_json::to_string(&row).unwrap(), r#"{"node":"item:1","uses":["struct:1234"]}"#);
+    }
+}